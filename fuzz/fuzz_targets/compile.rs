@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|src: &str| {
+    sage::fuzz::fuzz_compile(src);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sage::fuzz::ArbitraryExpr;
+
+fuzz_target!(|program: ArbitraryExpr| {
+    program.fuzz_compile();
+});
@@ -1043,6 +1043,8 @@ impl CoreOp {
                 env.push_matching(self, current_instruction);
                 // Start the function
                 result.begin_function();
+                // Name the function after its label, for the targets.
+                result.comment(&vm::label_comment(name));
                 // Push the frame pointer to the frame pointer stack
                 FP.push_to(&FP_STACK, result);
                 // Overwrite the old frame pointer with the stack pointer
@@ -0,0 +1,157 @@
+//! # Target Features
+//!
+//! Not every target can implement every standard instruction: a microcontroller
+//! might have no floating point unit, and an 8-bit computer no heap at all.
+//! Targets declare which groups of standard instructions they support with
+//! [`TargetFeatures`], and the compiler checks the instructions it generates
+//! against them with a [`TargetProgram`]. This way, a program using an
+//! unsupported instruction is rejected where the instruction is generated,
+//! instead of when the target builds the final output.
+use super::{AssemblyProgram, CoreOp, Error, StandardOp};
+use ::core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A group of standard instructions which a target may or may not support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Feature {
+    /// Floating point values and arithmetic.
+    Floats,
+    /// Allocating and freeing memory on the heap.
+    Alloc,
+    /// Atomic operations on memory.
+    Atomics,
+    /// Calling foreign functions.
+    FFI,
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Floats => write!(f, "floating point"),
+            Self::Alloc => write!(f, "heap allocation"),
+            Self::Atomics => write!(f, "atomic operations"),
+            Self::FFI => write!(f, "foreign function calls"),
+        }
+    }
+}
+
+impl StandardOp {
+    /// The feature a target must support to implement this instruction,
+    /// or `None` for the core instructions, which every target implements.
+    pub fn feature(&self) -> Option<Feature> {
+        match self {
+            Self::CoreOp(_) => None,
+            Self::Alloc(_)
+            | Self::Free(_)
+            | Self::Realloc { .. }
+            | Self::HeapUsed(_)
+            | Self::HeapFree(_) => Some(Feature::Alloc),
+            Self::AtomicLoad { .. }
+            | Self::AtomicStore { .. }
+            | Self::AtomicFetchAdd { .. }
+            | Self::AtomicCompareSwap { .. } => Some(Feature::Atomics),
+            Self::Call(_) => Some(Feature::FFI),
+            _ => Some(Feature::Floats),
+        }
+    }
+}
+
+/// The standard instructions a target supports.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TargetFeatures {
+    /// The name of the target, for reporting unsupported instructions.
+    pub name: String,
+    /// Does the target support floating point?
+    pub floats: bool,
+    /// Does the target support heap allocation?
+    pub alloc: bool,
+    /// Does the target support atomic operations?
+    pub atomics: bool,
+    /// Does the target support foreign function calls?
+    pub ffi: bool,
+}
+
+impl TargetFeatures {
+    /// A target which supports every standard instruction.
+    pub fn all(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            floats: true,
+            alloc: true,
+            atomics: true,
+            ffi: true,
+        }
+    }
+
+    /// A target which only supports the core instructions.
+    pub fn core_only(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            floats: false,
+            alloc: false,
+            atomics: false,
+            ffi: false,
+        }
+    }
+
+    /// Does the target support the given feature?
+    pub fn supports(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Floats => self.floats,
+            Feature::Alloc => self.alloc,
+            Feature::Atomics => self.atomics,
+            Feature::FFI => self.ffi,
+        }
+    }
+
+    /// Does the target support any standard instructions at all?
+    pub fn supports_std(&self) -> bool {
+        self.floats || self.alloc || self.atomics || self.ffi
+    }
+}
+
+/// An assembly program which only accepts the standard instructions supported
+/// by a target, and passes everything else on to the program it wraps.
+pub struct TargetProgram<'a> {
+    program: &'a mut dyn AssemblyProgram,
+    features: &'a TargetFeatures,
+}
+
+impl<'a> TargetProgram<'a> {
+    /// Check the instructions added to a program against the target's features.
+    pub fn new(program: &'a mut dyn AssemblyProgram, features: &'a TargetFeatures) -> Self {
+        Self { program, features }
+    }
+}
+
+impl AssemblyProgram for TargetProgram<'_> {
+    fn op(&mut self, op: CoreOp) {
+        self.program.op(op)
+    }
+
+    fn std_op(&mut self, op: StandardOp) -> Result<(), Error> {
+        match op.feature() {
+            Some(feature) if !self.features.supports(feature) => Err(Error::UnsupportedOnTarget(
+                op,
+                feature,
+                self.features.name.clone(),
+            )),
+            _ => self.program.std_op(op),
+        }
+    }
+
+    fn comment(&mut self, comment: String) {
+        self.program.comment(comment)
+    }
+
+    fn is_defined(&self, label: &str) -> bool {
+        self.program.is_defined(label)
+    }
+
+    fn current_instruction(&self) -> usize {
+        self.program.current_instruction()
+    }
+
+    fn get_op(&self, start: usize) -> Option<Result<CoreOp, StandardOp>> {
+        self.program.get_op(start)
+    }
+}
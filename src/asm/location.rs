@@ -1055,6 +1055,91 @@ impl Location {
         Ok(())
     }
 
+    /// Resize the memory at the address stored in this cell to the number of cells
+    /// stored in `size`, and store the address of the resized memory in this cell.
+    pub(crate) fn realloc(
+        &self,
+        size: &Self,
+        result: &mut dyn VirtualMachineProgram,
+    ) -> Result<(), Error> {
+        self.restore_from(result);
+        size.to(result);
+        result.std_op(vm::StandardOp::Realloc)?;
+        size.from(result);
+        self.save_to(result);
+        Ok(())
+    }
+
+    /// Store the number of cells currently allocated in this cell.
+    pub(crate) fn heap_used(&self, result: &mut dyn VirtualMachineProgram) -> Result<(), Error> {
+        result.std_op(vm::StandardOp::HeapUsed)?;
+        self.save_to(result);
+        Ok(())
+    }
+
+    /// Store the number of freed cells that the allocator can reuse in this cell.
+    pub(crate) fn heap_free(&self, result: &mut dyn VirtualMachineProgram) -> Result<(), Error> {
+        result.std_op(vm::StandardOp::HeapFree)?;
+        self.save_to(result);
+        Ok(())
+    }
+
+    /// Atomically load the cell at the address stored in this cell into `dst`.
+    pub(crate) fn atomic_load(
+        &self,
+        dst: &Self,
+        result: &mut dyn VirtualMachineProgram,
+    ) -> Result<(), Error> {
+        self.restore_from(result);
+        result.std_op(vm::StandardOp::AtomicLoad)?;
+        dst.save_to(result);
+        Ok(())
+    }
+
+    /// Atomically store `src` into the cell at the address stored in this cell.
+    pub(crate) fn atomic_store(
+        &self,
+        src: &Self,
+        result: &mut dyn VirtualMachineProgram,
+    ) -> Result<(), Error> {
+        self.restore_from(result);
+        src.to(result);
+        result.std_op(vm::StandardOp::AtomicStore)?;
+        src.from(result);
+        Ok(())
+    }
+
+    /// Atomically add `val` to the cell at the address stored in this cell,
+    /// and store the previous value of that cell in `val`.
+    pub(crate) fn atomic_fetch_add(
+        &self,
+        val: &Self,
+        result: &mut dyn VirtualMachineProgram,
+    ) -> Result<(), Error> {
+        self.restore_from(result);
+        val.to(result);
+        result.std_op(vm::StandardOp::AtomicAdd)?;
+        result.save();
+        val.from(result);
+        Ok(())
+    }
+
+    /// Atomically replace the cell at the address stored in this cell with the cell
+    /// after `expected`, if it equals `expected`. Store whether it was replaced in `dst`.
+    pub(crate) fn atomic_compare_swap(
+        &self,
+        expected: &Self,
+        dst: &Self,
+        result: &mut dyn VirtualMachineProgram,
+    ) -> Result<(), Error> {
+        self.restore_from(result);
+        expected.to(result);
+        result.std_op(vm::StandardOp::AtomicCompareSwap)?;
+        expected.from(result);
+        dst.save_to(result);
+        Ok(())
+    }
+
     pub(crate) fn sin(&self, result: &mut dyn VirtualMachineProgram) -> Result<(), Error> {
         self.std_op(vm::StandardOp::Sin(1), result)
     }
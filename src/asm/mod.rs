@@ -13,6 +13,7 @@
 //! 2. [Standard Assembly](./std)
 //! 3. [Assembly Memory Model](./location)
 //! 4. [Global Variable Management](./globals)
+//! 5. [Target Features](./features)
 //!
 //! ## The Core Variant
 //!
@@ -32,11 +33,13 @@ use ::std::collections::HashMap;
 use log::{debug, error, trace, warn};
 
 pub mod core;
+pub mod features;
 pub mod globals;
 pub mod location;
 pub mod std;
 
 pub use self::core::{CoreOp, CoreProgram};
+pub use features::{Feature, TargetFeatures, TargetProgram};
 pub use self::std::{StandardOp, StandardProgram};
 pub use globals::Globals;
 pub use location::{Location, A, B, C, D, E, F, FP, GP, REGISTERS, SP};
@@ -165,6 +168,8 @@ pub enum Error {
     VirtualMachineError(crate::vm::Error),
     /// Is this standard assembly operation supported by the target?
     UnsupportedInstruction(StandardOp),
+    /// This standard assembly operation needs a feature the named target doesn't support.
+    UnsupportedOnTarget(StandardOp, Feature, String),
     /// The given label was not defined.
     UndefinedLabel(String, usize),
     /// The given global was not defined.
@@ -187,6 +192,10 @@ impl Display for Error {
         match self {
             Self::VirtualMachineError(e) => write!(f, "{}", e),
             Self::UnsupportedInstruction(op) => write!(f, "Unsupported instruction: {}", op),
+            Self::UnsupportedOnTarget(op, feature, target) => write!(
+                f,
+                "Unsupported instruction on target {target}, which has no {feature} support: {op}"
+            ),
             Self::UndefinedLabel(name, i) => {
                 write!(f, "Undefined label {} at instruction #{}", name, i)
             }
@@ -382,6 +382,52 @@ pub enum StandardOp {
     Alloc(Location),
     /// Free the memory allocated at the address stored in the operand cell.
     Free(Location),
+    /// Resize the memory allocated at the address stored in `ptr` to `size` cells.
+    /// The address of the resized memory is stored in `ptr`.
+    Realloc {
+        /// The cell holding the address of the allocated memory.
+        ptr: Location,
+        /// The cell holding the new number of cells.
+        size: Location,
+    },
+    /// Store the number of cells currently allocated in the operand cell.
+    HeapUsed(Location),
+    /// Store the number of freed cells that the allocator can reuse in the operand cell.
+    HeapFree(Location),
+
+    /// Atomically load the cell at the address stored in `ptr` into `dst`.
+    AtomicLoad {
+        /// The cell holding the address of the atomic cell.
+        ptr: Location,
+        /// The destination cell.
+        dst: Location,
+    },
+    /// Atomically store `src` into the cell at the address stored in `ptr`.
+    AtomicStore {
+        /// The cell holding the address of the atomic cell.
+        ptr: Location,
+        /// The value to store.
+        src: Location,
+    },
+    /// Atomically add `val` to the cell at the address stored in `ptr`.
+    /// The previous value of the atomic cell is stored in `val`.
+    AtomicFetchAdd {
+        /// The cell holding the address of the atomic cell.
+        ptr: Location,
+        /// The value to add, which is replaced with the previous value.
+        val: Location,
+    },
+    /// Atomically replace the cell at the address stored in `ptr` with the cell after
+    /// `expected`, if it is equal to `expected`. `dst` is set to whether the swap happened,
+    /// and if it didn't, `expected` is set to the current value of the atomic cell.
+    AtomicCompareSwap {
+        /// The cell holding the address of the atomic cell.
+        ptr: Location,
+        /// The expected value, followed by the new value.
+        expected: Location,
+        /// The destination cell for whether the swap happened.
+        dst: Location,
+    },
 
     /// Call a foreign function.
     Call(FFIBinding),
@@ -678,6 +724,42 @@ impl StandardOp {
                     unsupported(self.clone())?
                 }
             }
+            Self::Realloc { ptr, size } => {
+                if ptr.realloc(size, result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+            Self::HeapUsed(loc) => {
+                if loc.heap_used(result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+            Self::HeapFree(loc) => {
+                if loc.heap_free(result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+
+            Self::AtomicLoad { ptr, dst } => {
+                if ptr.atomic_load(dst, result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+            Self::AtomicStore { ptr, src } => {
+                if ptr.atomic_store(src, result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+            Self::AtomicFetchAdd { ptr, val } => {
+                if ptr.atomic_fetch_add(val, result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
+            Self::AtomicCompareSwap { ptr, expected, dst } => {
+                if ptr.atomic_compare_swap(expected, dst, result).is_err() {
+                    unsupported(self.clone())?
+                }
+            }
 
             Self::Call(binding) => {
                 let input_cells = binding.input_cells;
@@ -810,6 +892,16 @@ impl fmt::Display for StandardOp {
 
             Self::Alloc(loc) => write!(f, "alloc {loc}"),
             Self::Free(loc) => write!(f, "free {loc}"),
+            Self::Realloc { ptr, size } => write!(f, "realloc {ptr}, {size}"),
+            Self::HeapUsed(loc) => write!(f, "heap-used {loc}"),
+            Self::HeapFree(loc) => write!(f, "heap-free {loc}"),
+
+            Self::AtomicLoad { ptr, dst } => write!(f, "atomic-load {ptr}, {dst}"),
+            Self::AtomicStore { ptr, src } => write!(f, "atomic-store {ptr}, {src}"),
+            Self::AtomicFetchAdd { ptr, val } => write!(f, "atomic-add {ptr}, {val}"),
+            Self::AtomicCompareSwap { ptr, expected, dst } => {
+                write!(f, "atomic-cas {ptr}, {expected}, {dst}")
+            }
 
             // Self::Peek(loc) => write!(f, "peek {loc}"),
             // Self::Poke(loc) => write!(f, "poke {loc}"),
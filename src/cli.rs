@@ -12,8 +12,11 @@ use sage::{
     LOGO_WITH_COLOR, *,
 };
 use std::{
+    collections::BTreeMap,
     fmt,
     fs::{read_to_string, write},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use log::error;
@@ -56,10 +59,44 @@ enum TargetType {
     // SageOS,
     /// Compile to C source code (GCC only).
     C,
-    // /// Compile to x86 assembly code.
-    // X86,
+    /// Compile to freestanding C source code, which doesn't use libc (GCC only).
+    FreestandingC,
+    /// Compile to x86-64 assembly code for Linux (GNU assembler).
+    X86_64,
+    /// Compile to 64-bit RISC-V assembly code for Linux (GNU assembler).
+    #[value(name = "riscv64")]
+    RiscV64,
+    /// Compile to Thumb assembly code for bare-metal ARM Cortex-M microcontrollers (GNU assembler).
+    CortexM,
+    /// Compile to 6502 assembly code for 8-bit computers (ca65 assembler).
+    #[value(name = "6502")]
+    Mos6502,
+    /// Compile to a JavaScript module, for Node.js and web browsers.
+    #[value(name = "js")]
+    JavaScript,
+    /// Compile to a Python module, which runs as a script.
+    Python,
     /// Compile using the Sage-Lisp backend provided by the user.
-    SageLisp
+    SageLisp,
+    /// Print the LIR of the program in the text format.
+    LirText,
+}
+
+impl TargetType {
+    /// The name of the backend in the target registry which builds this target, if any.
+    fn registered_name(&self) -> Option<&'static str> {
+        match self {
+            Self::C => Some("c"),
+            Self::FreestandingC => Some("freestanding-c"),
+            Self::X86_64 => Some("x86-64"),
+            Self::RiscV64 => Some("riscv64"),
+            Self::CortexM => Some("cortex-m"),
+            Self::Mos6502 => Some("6502"),
+            Self::JavaScript => Some("js"),
+            Self::Python => Some("python"),
+            _ => None,
+        }
+    }
 }
 
 /// The source language options to compile.
@@ -69,6 +106,8 @@ enum SourceType {
     Sage,
     /// Compile LIR code.
     LowIR,
+    /// Compile LIR code written in the text format.
+    LirText,
     /// Compile core variant assembly code.
     CoreASM,
     /// Compile standard variant assembly code.
@@ -79,10 +118,255 @@ enum SourceType {
     StdVM,
 }
 
+/// The variant of the virtual machine to compile a program to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Variant {
+    /// Compile to the core variant, falling back on the standard
+    /// variant if the program uses standard instructions.
+    Core,
+    /// Compile to the core variant, and report an error where the
+    /// program uses standard instructions.
+    CoreOnly,
+    /// Compile to the standard variant.
+    Std,
+}
+
+/// What a program can use without declaring it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Prelude {
+    /// The builtins and the standard library.
+    Std,
+    /// Only the builtins, like `alloc` and `free`.
+    Builtins,
+    /// Nothing, for programs which declare everything they use.
+    None,
+}
+
+impl From<Prelude> for sage::frontend::Prelude {
+    fn from(prelude: Prelude) -> Self {
+        match prelude {
+            Prelude::Std => Self::Std,
+            Prelude::Builtins => Self::Builtins,
+            Prelude::None => Self::None,
+        }
+    }
+}
+
+/// The intermediate representations of a program which can be written out with `--emit`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Emit {
+    /// The syntax tree of the parsed program, written to `<output>.ast`.
+    Ast,
+    /// The LIR of the program in the text format, written to `<output>.lir`.
+    Lir,
+    /// The core variant of the assembly code, written to `<output>.core.asm.sg`.
+    CoreAsm,
+    /// The standard variant of the assembly code, written to `<output>.std.asm.sg`.
+    StdAsm,
+    /// The virtual machine code, written to `<output>.vm.sg`.
+    Vm,
+    /// The C source code, written to `<output>.c`.
+    C,
+}
+
+/// The formats the times of the compiler's passes can be reported in with `--time-passes`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum TimePassesFormat {
+    /// A table for people to read.
+    Human,
+    /// JSON for tools to read.
+    Json,
+}
+
+/// The formats errors and warnings can be reported in with `--error-format`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ErrorFormat {
+    /// Diagnostics with the source code they refer to, for people to read.
+    Human,
+    /// A JSON object for each diagnostic on its own line, for editors and other tools to read.
+    Json,
+}
+
 /// The argument parser for the CLI.
 #[derive(Parser, Debug)]
-#[clap(author, version, about = Some(LOGO_WITH_COLOR), long_about = Some(LOGO_WITH_COLOR), max_term_width=90)]
-struct Args {
+#[clap(author, version, about = Some(LOGO_WITH_COLOR), long_about = Some(LOGO_WITH_COLOR), max_term_width=90, arg_required_else_help = true)]
+struct Cli {
+    /// Print the extended description of an error code, like `E0028`, and exit.
+    #[clap(long, value_name = "CODE")]
+    explain: Option<String>,
+    /// What to do with the input file.
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// The subcommands of the CLI.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compile the input file to a target (C by default), and write the output files.
+    Build(Options),
+    /// Compile the input file and execute it in the interpreter.
+    Run(Options),
+    /// Check the input file for errors when compiled for a target, without writing any output.
+    Check(Options),
+    /// Compile the input file to assembly code, and write it to the output file.
+    Asm(Options),
+    /// Evaluate code interactively, printing the value and type of each expression.
+    Repl(ReplOptions),
+    /// Rewrite source files in the canonical style.
+    Fmt(FmtOptions),
+    /// Write the documentation of the input file's modules, types, and procedures.
+    Doc(DocOptions),
+    /// Run the procedures in the input file marked with the `test` attribute.
+    Test(TestOptions),
+    /// Run the procedures in the input file marked with the `bench` attribute, and
+    /// report the instructions they execute.
+    Bench(BenchOptions),
+    /// Rebuild the input file whenever its source files change, and run it or its tests.
+    Watch(WatchOptions),
+}
+
+impl Command {
+    /// The options shared by every subcommand which compiles an input file.
+    fn options(&self) -> Option<&Options> {
+        match self {
+            Self::Build(options)
+            | Self::Run(options)
+            | Self::Check(options)
+            | Self::Asm(options) => Some(options),
+            Self::Repl(_)
+            | Self::Fmt(_)
+            | Self::Doc(_)
+            | Self::Test(_)
+            | Self::Bench(_)
+            | Self::Watch(_) => None,
+        }
+    }
+}
+
+/// The options of the formatter.
+#[derive(clap::Args, Debug)]
+struct FmtOptions {
+    /// The source files to format.
+    #[clap(value_parser, required = true)]
+    files: Vec<String>,
+
+    /// Don't write the files, just fail if any of them aren't formatted.
+    #[clap(long, value_parser)]
+    check: bool,
+}
+
+/// The options of the documentation generator.
+#[derive(clap::Args, Debug)]
+struct DocOptions {
+    /// The source file to document.
+    #[clap(value_parser)]
+    file: String,
+
+    /// The file to write the documentation to. By default, this is
+    /// the name of the source file with the format's extension.
+    #[clap(short, long, value_parser)]
+    output: Option<String>,
+
+    /// The format to write the documentation in.
+    #[clap(short, long, value_parser, default_value = "markdown")]
+    format: DocFormatType,
+}
+
+/// The formats the documentation generator can write.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum DocFormatType {
+    Markdown,
+    Html,
+}
+
+/// The options of the test runner.
+#[derive(clap::Args, Debug)]
+struct TestOptions {
+    /// The source file to run the tests of.
+    #[clap(value_parser)]
+    file: String,
+
+    /// Only run the tests whose names contain this string.
+    #[clap(value_parser)]
+    filter: Option<String>,
+
+    /// The number of cells allocated for the call stack.
+    #[clap(short, long, value_parser, default_value = "65536")]
+    call_stack_size: usize,
+
+    /// Write an lcov report of the source lines the tests ran to this file.
+    #[clap(long, value_parser, value_name = "FILE")]
+    coverage: Option<String>,
+}
+
+/// The options of the benchmark runner.
+#[derive(clap::Args, Debug)]
+struct BenchOptions {
+    /// The source file to run the benchmarks of.
+    #[clap(value_parser)]
+    file: String,
+
+    /// Only run the benchmarks whose names contain this string.
+    #[clap(value_parser)]
+    filter: Option<String>,
+
+    /// The number of times to call each benchmark.
+    #[clap(short = 'n', long, value_parser, default_value = "10")]
+    iterations: usize,
+
+    /// Compare the results against a baseline saved with `--save-baseline`.
+    #[clap(short, long, value_parser)]
+    baseline: Option<String>,
+
+    /// Save the results to a file, to compare against later.
+    #[clap(long, value_parser)]
+    save_baseline: Option<String>,
+
+    /// The number of cells allocated for the call stack.
+    #[clap(short, long, value_parser, default_value = "65536")]
+    call_stack_size: usize,
+}
+
+/// The options of the file watcher.
+#[derive(clap::Args, Debug)]
+struct WatchOptions {
+    /// The source file to rebuild. Every source file in its directory is watched.
+    #[clap(value_parser)]
+    file: String,
+
+    /// Run the tests in the file instead of the program.
+    #[clap(long, value_parser)]
+    test: bool,
+
+    /// Only run the tests whose names contain this string.
+    #[clap(long, value_parser)]
+    filter: Option<String>,
+
+    /// The directory to cache compiled monomorphized procedures in between rebuilds.
+    /// By default, this is a directory in the system's temporary directory.
+    #[clap(long, value_parser)]
+    cache_dir: Option<String>,
+
+    /// The number of milliseconds to wait between checking the files for changes.
+    #[clap(long, value_parser, default_value = "500")]
+    interval: u64,
+
+    /// The number of cells allocated for the call stack.
+    #[clap(short, long, value_parser, default_value = "65536")]
+    call_stack_size: usize,
+}
+
+/// The options of the REPL.
+#[derive(clap::Args, Debug)]
+struct ReplOptions {
+    /// The number of cells allocated for the call stack.
+    #[clap(short, long, value_parser, default_value = "65536")]
+    call_stack_size: usize,
+}
+
+/// The options shared by every subcommand.
+#[derive(clap::Args, Debug)]
+struct Options {
     /// The input file to compiler.
     #[clap(value_parser)]
     input: String,
@@ -95,9 +379,36 @@ struct Args {
     #[clap(short, value_parser, default_value = "sage")]
     source_type: SourceType,
 
-    /// The target language to compile to.
-    #[clap(short, value_parser, default_value = "run")]
-    target_type: TargetType,
+    /// The target language to compile to. The program is compiled with the cell width
+    /// and standard instructions of the target, even when it's only checked.
+    #[clap(short, long, value_parser)]
+    target: Option<TargetType>,
+
+    /// The optimization level: `0` keeps the program as written, `1` removes
+    /// assertions, and `2` also packs the fields of bitfields into fewer cells.
+    /// This overrides the level of the build profile.
+    #[clap(short = 'O', long, value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: Option<u8>,
+
+    /// The build profile, which chooses the optimization level, bounds checks, assertions,
+    /// and debug info together: `debug`, `release`, or a profile from the `sage.toml` manifest.
+    #[clap(long, value_parser)]
+    profile: Option<String>,
+
+    /// The variant to compile to. By default, programs are compiled to the core variant for
+    /// targets which only support the core instructions, and to the standard variant otherwise.
+    #[clap(long, value_parser)]
+    variant: Option<Variant>,
+
+    /// What the program can use without declaring it: the builtins and the standard
+    /// library, only the builtins, or nothing.
+    #[clap(long, value_parser, default_value = "std")]
+    prelude: Prelude,
+
+    /// The intermediate representations of the program to write next to the output
+    /// (such as `--emit=lir,std-asm`), for debugging the compiler.
+    #[clap(long, value_parser, value_delimiter = ',')]
+    emit: Vec<Emit>,
 
     /// The number of cells allocated for the call stack.
     #[clap(short, long, value_parser, default_value = "12582912")]
@@ -111,6 +422,132 @@ struct Args {
     /// also enable debug logging.
     #[clap(short, long, value_parser)]
     debug: Option<String>,
+
+    /// The directory to cache compiled monomorphized procedures in (if any).
+    /// Monomorphs cached by previous runs are reused instead of recompiled.
+    #[clap(long, value_parser)]
+    cache_dir: Option<String>,
+
+    /// The warnings to ignore (such as `unused-variable`).
+    #[clap(long, value_parser)]
+    allow: Vec<WarningKind>,
+
+    /// The warnings to report as errors (such as `unused-variable`).
+    #[clap(long, value_parser)]
+    deny: Vec<WarningKind>,
+
+    /// The built-in lints to run over the program (such as `null-deref`).
+    #[clap(long, value_parser)]
+    lint: Vec<String>,
+
+    /// Check array indices at runtime, and halt with the location
+    /// of any index which is out of bounds (for debug builds).
+    #[clap(long, value_parser)]
+    bounds_checks: bool,
+
+    /// Keep track of the procedures being called, and print
+    /// a backtrace when the program panics (for debug builds).
+    #[clap(long, value_parser)]
+    backtraces: bool,
+
+    /// Compile for release, with the `release` build profile.
+    #[clap(long, value_parser, conflicts_with = "profile")]
+    release: bool,
+
+    /// Let the compiler reorder the fields of bitfields to pack them
+    /// into fewer cells. Structs keep their layouts.
+    #[clap(long, value_parser)]
+    reorder_fields: bool,
+
+    /// The C headers declaring the foreign functions, for the C target. With headers or
+    /// libraries, foreign functions are called directly instead of through `ffi.h`.
+    #[clap(long, value_parser)]
+    c_header: Vec<String>,
+
+    /// The C libraries defining the foreign functions, for the C target (like `m` for `-lm`).
+    #[clap(long, value_parser)]
+    c_lib: Vec<String>,
+
+    /// The number of cells on the tape of a compiled program. Each target has its own default,
+    /// and the tape must be larger than the call stack.
+    #[clap(long, value_parser)]
+    tape_cells: Option<usize>,
+
+    /// Check that a compiled program's tape pointer stays on the tape,
+    /// and exit with an error when it doesn't.
+    #[clap(long, value_parser)]
+    tape_bounds_check: bool,
+
+    /// The function a compiled program writes characters to the standard output with.
+    #[clap(long, value_parser)]
+    putchar_hook: Option<String>,
+
+    /// The function a compiled program writes characters to the standard error with.
+    #[clap(long, value_parser)]
+    eputchar_hook: Option<String>,
+
+    /// The function a compiled program reads characters with.
+    #[clap(long, value_parser)]
+    getchar_hook: Option<String>,
+
+    /// Stop the program in the interpreter after it executes this many instructions.
+    #[clap(long, value_parser)]
+    max_steps: Option<u64>,
+
+    /// The number of cells on the interpreter's tape: stop the program if it
+    /// moves its pointer past them. This must be larger than the call stack.
+    #[clap(long, value_parser)]
+    max_tape: Option<usize>,
+
+    /// Stop the program in the interpreter after it runs for this many seconds.
+    #[clap(long, value_parser)]
+    timeout: Option<f64>,
+
+    /// Stop the program in the interpreter after it reads and writes this many values.
+    #[clap(long, value_parser)]
+    io_quota: Option<u64>,
+
+    /// Check the program's memory in the interpreter, and stop it with a backtrace when
+    /// it uses an uninitialized value or freed memory. Only the standard variant has a heap.
+    #[clap(long, value_parser)]
+    sanitize: bool,
+
+    /// Report the allocations the program never freed when it exits in the interpreter,
+    /// with where they were allocated.
+    #[clap(long, value_parser)]
+    leak_check: bool,
+
+    /// Show the framebuffer the program draws on channel 0 in a window when it's run,
+    /// with a size like `--window=640x480`.
+    #[cfg(feature = "framebuffer")]
+    #[clap(long, value_parser, num_args = 0..=1, require_equals = true, default_missing_value = "320x240")]
+    window: Option<String>,
+
+    /// Put the terminal in raw mode when the program polls keys, so it can read
+    /// each key as it's pressed without waiting for a line.
+    #[cfg(feature = "terminal")]
+    #[clap(long, value_parser)]
+    raw_keys: bool,
+
+    /// Set compile parameters for `cfg` checks, as `name` or `name=value`.
+    /// The `target` parameter is set to the target type automatically.
+    #[clap(long, value_parser)]
+    cfg: Vec<String>,
+
+    /// Report the time and peak memory of each pass of the compiler on the standard error,
+    /// as a table, or as JSON with `--time-passes=json`.
+    #[clap(long, value_parser, num_args = 0..=1, require_equals = true, default_missing_value = "human")]
+    time_passes: Option<TimePassesFormat>,
+
+    /// The format to report errors and warnings in on the standard error:
+    /// as text for people, or as a JSON object for each diagnostic.
+    #[clap(long, value_parser, default_value = "human")]
+    error_format: ErrorFormat,
+
+    /// The arguments to pass to the program's `main` procedure, after `--`.
+    /// The name of the input file is passed before them.
+    #[clap(last = true, value_parser)]
+    program_args: Vec<String>,
 }
 
 /// The types of errors returned by the CLI.
@@ -130,11 +567,13 @@ enum Error {
     /// Error generated when assembling input code.
     AsmError(asm::Error),
     /// Error generated by the interpreter executing input code.
-    InterpreterError(String),
+    InterpreterError(RuntimeError),
     /// Error when building the virtual machine code for a given target.
     BuildError(String),
     /// Invalid source code (expected core but got standard).
     InvalidSource(String),
+    /// Many errors found in the source code.
+    Many(Vec<Self>),
 }
 
 impl Error {
@@ -151,79 +590,176 @@ impl Error {
                     self
                 }
             }
+            Self::LirError(lir::Error::Many(errors)) => Self::Many(
+                errors
+                    .into_iter()
+                    .map(|err| Self::LirError(err).annotate_with_source(code))
+                    .collect(),
+            ),
             _ => self,
         }
     }
 }
 
+impl Error {
+    /// The diagnostics describing this error, for reporting it as JSON.
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Error::LirError(e) => e.diagnostics(),
+            Error::WithSourceCode { loc, err, .. } => err
+                .diagnostics()
+                .into_iter()
+                .map(|diagnostic| diagnostic.with_location(loc.clone()))
+                .collect(),
+            Error::Many(errors) => errors.iter().flat_map(Error::diagnostics).collect(),
+            Error::Parse(e) => vec![Diagnostic::parse_error(e)],
+            err => vec![Diagnostic::error(format!("{err:?}"))],
+        }
+    }
+}
+
 impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::IO(e) => write!(f, "IO error: {:?}", e),
             Error::Parse(e) => write!(f, "Parse error: {}", e),
             Error::AsmError(e) => write!(f, "Assembly error: {:?}", e),
-            Error::LirError(e) => write!(f, "LIR error: {}", e),
+            Error::LirError(e) => match e.code() {
+                Some(code) => write!(f, "LIR error[{code}]: {}", e),
+                None => write!(f, "LIR error: {}", e),
+            },
             Error::WithSourceCode {
                 loc,
                 source_code,
                 err,
             } => {
-                // use codespan_reporting::files::SimpleFiles;
-                use codespan_reporting::diagnostic::{Diagnostic, Label};
-                use codespan_reporting::files::SimpleFiles;
-                use codespan_reporting::term::{
-                    emit,
-                    termcolor::{ColorChoice, StandardStream},
-                };
-                use no_comment::{languages, IntoWithoutComments};
-
-                let SourceCodeLocation {
-                    line,
-                    column,
-                    filename,
-                    offset,
-                    length,
-                } = loc;
-
-                let mut files = SimpleFiles::new();
-
-                let source_code = source_code
-                    .to_string()
-                    .chars()
-                    .without_comments(languages::rust())
-                    .collect::<String>();
-
-                let filename = filename.clone().unwrap_or("unknown".to_string());
+                // Comments are stripped by the parser, so the locations refer to the code without them.
+                let source_code = frontend::without_comments(source_code);
 
-                let file_id = files.add(filename.clone(), source_code);
-
-                let loc = format!("{}:{}:{}:{}", filename, line, column, offset);
-
-                // let code = format!("{}\n{}^", code, " ".repeat(*column - 1));
-                // write!(f, "Error at {}:\n{}\n{:?}", loc, code, err)?
-
-                let diagnostic = Diagnostic::error()
-                    .with_message(format!("Error at {}", loc))
-                    .with_labels(vec![Label::primary(
-                        file_id,
-                        *offset..*offset + length.unwrap_or(0),
-                    )
-                    .with_message(format!("{err:?}"))]);
-
-                let writer = StandardStream::stderr(ColorChoice::Always);
-                let config = codespan_reporting::term::Config::default();
-
-                emit(&mut writer.lock(), &config, &files, &diagnostic).unwrap();
-
-                Ok(())
+                let diagnostic = match err.as_ref() {
+                    Error::LirError(e) => e.diagnostic(),
+                    err => Diagnostic::error(format!("{err:?}")),
+                }
+                .with_location(loc.clone());
+                write!(f, "{}", diagnostic.render(&source_code))
             }
             Error::InterpreterError(e) => write!(f, "Interpreter error: {}", e),
             Error::BuildError(e) => write!(f, "Build error: {}", e),
             Error::InvalidSource(e) => write!(f, "Invalid source: {}", e),
+            Error::Many(errors) => {
+                for err in errors {
+                    writeln!(f, "{err:?}")?;
+                }
+                write!(f, "{} errors found", errors.len())
+            }
         }
     }
 }
 
+/// The changes to a target's runtime configuration given on the command line.
+#[derive(Default)]
+struct RuntimeOptions {
+    /// The number of cells on the tape, if not the target's default.
+    tape_cells: Option<usize>,
+    /// Whether to check the tape pointer stays on the tape.
+    bounds_check: bool,
+    /// The function to write characters to the standard output with, if not the default.
+    put_char: Option<String>,
+    /// The function to write characters to the standard error with, if not the default.
+    eput_char: Option<String>,
+    /// The function to read characters with, if not the default.
+    get_char: Option<String>,
+    /// The limits on programs run in the interpreter.
+    limits: Limits,
+    /// Whether to check the memory of programs run in the interpreter.
+    sanitize: bool,
+    /// Whether to report the allocations programs run in the interpreter never free.
+    leak_check: bool,
+    /// The width and height of the window to show the framebuffer in, if any.
+    #[cfg(feature = "framebuffer")]
+    window: Option<(usize, usize)>,
+    /// Whether to poll keys from the terminal in raw mode.
+    #[cfg(feature = "terminal")]
+    raw_keys: bool,
+}
+
+impl RuntimeOptions {
+    /// Configure a target with these options, on top of its own defaults.
+    fn configure(
+        &self,
+        target: &mut dyn targets::Target,
+        call_stack_size: usize,
+    ) -> Result<(), Error> {
+        let mut config = target.config().clone();
+        if let Some(tape_cells) = self.tape_cells {
+            if tape_cells <= call_stack_size {
+                return Err(Error::BuildError(format!(
+                    "the tape ({tape_cells} cells) must be larger than the call stack ({call_stack_size} cells)"
+                )));
+            }
+            config.tape_cells = tape_cells;
+        }
+        config.bounds_check = self.bounds_check;
+        if let Some(name) = &self.put_char {
+            config.hooks.put_char = name.clone();
+        }
+        if let Some(name) = &self.eput_char {
+            config.hooks.eput_char = name.clone();
+        }
+        if let Some(name) = &self.get_char {
+            config.hooks.get_char = name.clone();
+        }
+        target.configure(config).map_err(Error::BuildError)
+    }
+
+    /// The device to run programs in the interpreter with.
+    fn device(&self) -> Box<dyn Device> {
+        #[allow(unused_mut)]
+        let mut device: Box<dyn Device> = Box::new(StandardDevice::default());
+        #[cfg(feature = "terminal")]
+        if self.raw_keys {
+            device = Box::new(TerminalDevice::new(device));
+        }
+        #[cfg(feature = "framebuffer")]
+        if let Some((width, height)) = self.window {
+            device = Box::new(WindowDevice::new(device, width, height));
+        }
+        device
+    }
+}
+
+/// How to build a program, as chosen on the command line.
+struct BuildOptions<'a> {
+    /// The name of the file the source code was read from, if any.
+    filename: Option<&'a str>,
+    /// The language of the source code.
+    src_type: SourceType,
+    /// The name of the output files, without their extensions.
+    output: &'a str,
+    /// The number of cells in the call stack.
+    call_stack_size: usize,
+    /// Compile the program to the core variant when possible?
+    core: bool,
+    /// Write the output in the readable, annotated format?
+    debug: bool,
+    /// The C headers and libraries to call foreign functions from, if any.
+    c_linkage: Option<targets::CLinkage>,
+    /// The changes to the target's runtime configuration.
+    runtime: RuntimeOptions,
+}
+
+/// Parse the size of a window, like `640x480`.
+#[cfg(feature = "framebuffer")]
+fn parse_window_size(size: &str) -> Result<(usize, usize), String> {
+    let (width, height) = size
+        .split_once('x')
+        .ok_or_else(|| format!("expected a size like 640x480, got {size:?}"))?;
+    match (width.trim().parse(), height.trim().parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!("expected a size like 640x480, got {size:?}")),
+    }
+}
+
 /// Compile a given source language to virtual machine code.
 fn compile_source_to_vm(
     filename: Option<&str>,
@@ -231,15 +767,20 @@ fn compile_source_to_vm(
     src_type: SourceType,
     call_stack_size: usize,
     default_to_core: bool,
+    env: &Env,
 ) -> Result<Result<sage::vm::CoreProgram, sage::vm::StandardProgram>, Error> {
     match src_type {
         SourceType::StdVM => {
             // Simply parse the virtual machine code
-            parse_vm(src).map_err(Error::Parse)
+            env.time_pass("parse", || parse_vm(src))
+                .map_err(Error::Parse)
         }
         SourceType::CoreVM => {
             // Parse the virtual machine code
-            match parse_vm(src).map_err(Error::Parse)? {
+            match env
+                .time_pass("parse", || parse_vm(src))
+                .map_err(Error::Parse)?
+            {
                 // If we got a core program back, return it.
                 Ok(prog) => Ok(Ok(prog)),
                 // Otherwise, our core program was actually a standard program. Throw an error.
@@ -248,94 +789,72 @@ fn compile_source_to_vm(
                 )),
             }
         }
-        SourceType::StdASM => {
-            // Parse the assembly code.
-            // Then, assembly the program with the given recursion depth,
-            // and return the virtual machine output.
-            match parse_asm(src).map_err(Error::Parse)? {
-                Ok(prog) => Ok(Ok(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(prog) => Ok(Err(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-            }
-        }
-        SourceType::CoreASM => {
-            // Parse the assembly code.
-            match parse_asm(src).map_err(Error::Parse)? {
-                // If we got back a core program, assembly it and return the virtual machine code.
-                Ok(prog) => Ok(Ok(prog
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                // Otherwise, our core program was actually a standard program. Throw an error.
-                Err(_) => Err(Error::InvalidSource(
-                    "expected core assembly program, got standard assembly program".to_string(),
-                )),
-            }
-        }
-        SourceType::LowIR => {
-            // Parse the lower intermediate representation code.
-            match parse_lir(src)
-                .map_err(Error::Parse)?
-                .compile(true)
-                .map_err(Error::LirError)?
-            {
-                // If we got back a valid program, assemble it and return the result.
-                Ok(asm_code) => Ok(Ok(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(asm_code) => Ok(Err(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-            }
-        }
-        SourceType::Sage => {
-            match parse_frontend(&src, filename)
-                .map_err(Error::Parse)?
-                .compile(default_to_core)
-                .map_err(Error::LirError)
-                .map_err(|e| e.annotate_with_source(&src))?
-            {
-                // If we got back a valid program, assemble it and return the result.
-                Ok(asm_code) => Ok(Ok(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-                Err(asm_code) => Ok(Err(asm_code
-                    .assemble(call_stack_size)
-                    .map_err(Error::AsmError)?)),
-            }
-        }
+        // LIR is always compiled to the core variant when possible.
+        SourceType::LowIR | SourceType::LirText => assemble(
+            compile_source_to_asm(filename, src, src_type, true, env)?,
+            call_stack_size,
+            env,
+        ),
+        // Compile the code to assembly code, and assemble it with the given recursion depth.
+        SourceType::CoreASM | SourceType::StdASM | SourceType::Sage => assemble(
+            compile_source_to_asm(filename, src, src_type, default_to_core, env)?,
+            call_stack_size,
+            env,
+        ),
     }
 }
 
+/// Assemble an assembly program into virtual machine code.
+fn assemble(
+    asm_code: Result<sage::asm::CoreProgram, sage::asm::StandardProgram>,
+    call_stack_size: usize,
+    env: &Env,
+) -> Result<Result<sage::vm::CoreProgram, sage::vm::StandardProgram>, Error> {
+    env.time_pass("asm-to-vm", || match asm_code {
+        Ok(asm_code) => Ok(Ok(asm_code
+            .assemble(call_stack_size)
+            .map_err(Error::AsmError)?)),
+        Err(asm_code) => Ok(Err(asm_code
+            .assemble(call_stack_size)
+            .map_err(Error::AsmError)?)),
+    })
+}
+
 /// Compile code in a given source language to assembly code.
+/// If `core` is set, the program is compiled to the core variant when possible.
 fn compile_source_to_asm(
     filename: Option<&str>,
     src: String,
     src_type: SourceType,
+    core: bool,
+    env: &Env,
 ) -> Result<Result<sage::asm::CoreProgram, sage::asm::StandardProgram>, Error> {
     match src_type {
         // If the source language is standard assembly, then parse it and return it.
-        SourceType::StdASM => parse_asm(src).map_err(Error::Parse),
+        SourceType::StdASM => env
+            .time_pass("parse", || parse_asm(src))
+            .map_err(Error::Parse),
         // If the source language is core assembly, then parse it and return it if it's actually a core variant program.
         // Otherwise, throw an error.
-        SourceType::CoreASM => match parse_asm(src).map_err(Error::Parse)? {
+        SourceType::CoreASM => match env
+            .time_pass("parse", || parse_asm(src))
+            .map_err(Error::Parse)?
+        {
             Ok(prog) => Ok(Ok(prog)),
             Err(_) => Err(Error::InvalidSource(
                 "expected core assembly program, got standard assembly program".to_string(),
             )),
         },
-        // If the source language is LIR, parse it and compile it to assembly code.
-        SourceType::LowIR => parse_lir(src)
-            .map_err(Error::Parse)?
-            .compile(true)
-            .map_err(Error::LirError),
+        // If the source language is LIR or the LIR text format, parse it and compile it to assembly code.
+        SourceType::LowIR | SourceType::LirText => {
+            parse_source_to_lir(filename, src, src_type, env)?
+                .compile_with_env(env.clone(), core)
+                .map_err(Error::LirError)
+        }
 
         // If the source language is Sage, parse it and compile it to assembly code.
-        SourceType::Sage => parse_frontend(&src, filename)
-            .map_err(Error::Parse)?
-            .compile(true)
+        SourceType::Sage => parse_source_to_lir(filename, src.clone(), src_type, env)?
+            .compile_with_env(env.clone(), core)
             .map_err(Error::LirError)
             .map_err(|e| e.annotate_with_source(&src)),
         // If the source language is a virtual machine program,
@@ -346,49 +865,84 @@ fn compile_source_to_asm(
     }
 }
 
-/// Compile code in a given source language to a given target language.
-fn compile(
+/// Parse code in a given source language to LIR.
+fn parse_source_to_lir(
     filename: Option<&str>,
     src: String,
     src_type: SourceType,
+    env: &Env,
+) -> Result<Expr, Error> {
+    match src_type {
+        SourceType::LowIR => env.time_pass("parse", || parse_lir(src)),
+        SourceType::LirText => env.time_pass("parse", || parse_lir_text(src)),
+        SourceType::Sage => env.time_pass("parse", || {
+            parse_frontend_with_prelude(&src, filename, env.get_prelude())
+        }),
+        // Assembly and virtual machine programs have no LIR.
+        SourceType::CoreASM | SourceType::StdASM | SourceType::CoreVM | SourceType::StdVM => {
+            return Err(Error::InvalidSource(
+                "cannot get the LIR of an assembly or VM program".to_string(),
+            ))
+        }
+    }
+    .map_err(Error::Parse)
+}
+
+/// Compile code in a given source language to a given target language.
+/// If the options' `core` is set, the program is compiled to the core variant when possible.
+fn compile(
+    src: String,
     target: TargetType,
-    output: String,
-    call_stack_size: usize,
-    debug: bool,
+    options: &BuildOptions,
+    env: &Env,
 ) -> Result<(), Error> {
+    let BuildOptions {
+        filename,
+        src_type,
+        output,
+        call_stack_size,
+        core,
+        debug,
+        ref runtime,
+        ..
+    } = *options;
     match target {
         // If the target is `Run`, then compile the code and execute it with the interpreter.
-        TargetType::Run => match compile_source_to_vm(filename, src, src_type, call_stack_size, false)? {
+        TargetType::Run => match compile_source_to_vm(filename, src, src_type, call_stack_size, core, env)? {
             // If the code is core variant virtual machine code
             Ok(vm_code) => {
-                CoreInterpreter::new(StandardDevice::default())
+                CoreInterpreter::new(runtime.device())
+                    .with_limits(runtime.limits)
                     .run(&vm_code)
                     .map_err(Error::InterpreterError)?;
             }
             // If the code is standard variant virtual machine code
             Err(vm_code) => {
-                StandardInterpreter::new(StandardDevice::default())
-                    .run(&vm_code)
-                    .map_err(Error::InterpreterError)?;
+                let profile = StandardInterpreter::new(runtime.device())
+                    .with_limits(runtime.limits)
+                    .with_sanitizer(runtime.sanitize)
+                    .with_leak_check(runtime.leak_check)
+                    .profile(&vm_code);
+                if !profile.leaks.is_empty() {
+                    let cells: usize = profile.leaks.iter().map(|leak| leak.size).sum();
+                    let count = profile.leaks.len();
+                    let plural = if count == 1 { "" } else { "s" };
+                    eprintln!(
+                        "leak check: {count} allocation{plural} ({cells} cells) never freed"
+                    );
+                    for leak in &profile.leaks {
+                        eprintln!("  {leak}");
+                    }
+                }
+                profile.result.map_err(Error::InterpreterError)?;
             }
         },
 
-        // If the target is C source code, then compile the code to virtual machine code,
-        // and then use the C target implementation to build the output source code.
-        TargetType::C => write_file(
-            format!("{output}.c"),
-            match compile_source_to_vm(filename, src, src_type, call_stack_size, false)? {
-                Ok(vm_code) => targets::C.build_core(&vm_code.flatten()),
-                Err(vm_code) => targets::C.build_std(&vm_code.flatten()),
-            }
-            .map_err(Error::BuildError)?,
-        )?,
-
         // If the target is C source code, then compile the code to virtual machine code,
         // and then use the C target implementation to build the output source code.
         TargetType::SageLisp => {
 
-            write_file(format!("output.txt"), match compile_source_to_vm(filename, src, src_type, call_stack_size, false)? {
+            write_file(format!("output.txt"), match compile_source_to_vm(filename, src, src_type, call_stack_size, core, env)? {
                 Ok(vm_code) => targets::SageLisp::new(sage::frontend::get_lisp_env()).build_core(&vm_code.flatten()),
                 Err(vm_code) => targets::SageLisp::new(sage::frontend::get_lisp_env()).build_std(&vm_code.flatten()),
             }.map_err(Error::BuildError)?)?
@@ -398,9 +952,15 @@ fn compile(
             // )?
         }
 
+        // If the target is the LIR text format, then parse the source and print its LIR.
+        TargetType::LirText => write_file(
+            format!("{output}.lir"),
+            parse_source_to_lir(filename, src, src_type, env)?.to_text(),
+        )?,
+
         // If the target is core virtual machine code, then try to compile the source to the core variant.
         // If not possible, throw an error.
-        TargetType::CoreVM => match compile_source_to_vm(filename, src, src_type, call_stack_size, true)?
+        TargetType::CoreVM => match compile_source_to_vm(filename, src, src_type, call_stack_size, true, env)?
         {
             Ok(vm_code) if debug => write_file(
                 format!("{output}.vm.sg"),
@@ -415,7 +975,7 @@ fn compile(
         // If the result is core variant, we don't care. Just return the generated code.
         TargetType::StdVM => write_file(
             format!("{output}.vm.sg"),
-            match compile_source_to_vm(filename, src, src_type, call_stack_size, false)? {
+            match compile_source_to_vm(filename, src, src_type, call_stack_size, core, env)? {
                 Ok(vm_code) if debug => format!("{:#}", vm_code.flatten()),
                 Err(vm_code) if debug => format!("{:#}", vm_code.flatten()),
                 Ok(vm_code) => vm_code.flatten().to_string(),
@@ -424,7 +984,7 @@ fn compile(
         )?,
         // If the target is core assembly code, then try to compile the source to the core variant.
         // If not possible, throw an error.
-        TargetType::CoreASM => match compile_source_to_asm(filename, src, src_type)? {
+        TargetType::CoreASM => match compile_source_to_asm(filename, src, src_type, core, env)? {
             Ok(asm_code) if debug => {
                 write_file(format!("{output}.asm.sg"), format!("{:#}", asm_code))
             }
@@ -437,17 +997,169 @@ fn compile(
         // If the result is core variant, we don't care. Just return the generated code.
         TargetType::StdASM => write_file(
             format!("{output}.asm.sg"),
-            match compile_source_to_asm(filename, src, src_type)? {
+            match compile_source_to_asm(filename, src, src_type, core, env)? {
                 Ok(core_asm_code) if debug => format!("{:#}", core_asm_code),
                 Err(std_asm_code) if debug => format!("{:#}", std_asm_code),
                 Ok(core_asm_code) => core_asm_code.to_string(),
                 Err(std_asm_code) => std_asm_code.to_string(),
             },
         )?,
+
+        // Every other target is built by the backend registered under its name,
+        // which emits the output files itself.
+        _ => {
+            let mut backend = get_backend(target, options)?;
+            let vm_code =
+                match compile_source_to_vm(filename, src, src_type, call_stack_size, core, env)? {
+                    Ok(vm_code) => Ok(vm_code.flatten()),
+                    Err(vm_code) => Err(vm_code.flatten()),
+                };
+            let code = env
+                .time_pass("vm-to-target", || backend.build(&vm_code))
+                .map_err(Error::BuildError)?;
+            backend.emit(output, &code).map_err(Error::BuildError)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check code in a given source language for errors when compiled for a given target,
+/// without writing any output. Targets built by a backend also build the program,
+/// so that the errors the backend finds are reported too.
+fn check(src: String, target: TargetType, options: &BuildOptions, env: &Env) -> Result<(), Error> {
+    let BuildOptions {
+        filename,
+        src_type,
+        call_stack_size,
+        core,
+        ..
+    } = *options;
+    let vm_code = compile_source_to_vm(filename, src, src_type, call_stack_size, core, env)?;
+    if target.registered_name().is_some() {
+        let mut backend = get_backend(target, options)?;
+        let vm_code = match vm_code {
+            Ok(vm_code) => Ok(vm_code.flatten()),
+            Err(vm_code) => Err(vm_code.flatten()),
+        };
+        env.time_pass("vm-to-target", || backend.build(&vm_code))
+            .map_err(Error::BuildError)?;
+    }
+    Ok(())
+}
+
+/// Write each of the requested intermediate representations of a program
+/// to a file named after the output and the representation.
+fn emit(src: &str, emit: &[Emit], options: &BuildOptions, env: &Env) -> Result<(), Error> {
+    let BuildOptions {
+        filename,
+        src_type,
+        output,
+        call_stack_size,
+        core,
+        debug,
+        ..
+    } = *options;
+    if emit.contains(&Emit::Ast) || emit.contains(&Emit::Lir) {
+        let lir_code = parse_source_to_lir(filename, src.to_string(), src_type, env)?;
+        if emit.contains(&Emit::Ast) {
+            write_file(format!("{output}.ast"), format!("{lir_code:#?}"))?;
+        }
+        if emit.contains(&Emit::Lir) {
+            write_file(format!("{output}.lir"), lir_code.to_text())?;
+        }
+    }
+
+    if emit.contains(&Emit::CoreAsm) || emit.contains(&Emit::StdAsm) {
+        // Core assembly can only be written if the program is compiled to the core variant.
+        let core = core || emit.contains(&Emit::CoreAsm);
+        let asm_code = compile_source_to_asm(filename, src.to_string(), src_type, core, env)?;
+        if emit.contains(&Emit::CoreAsm) {
+            match &asm_code {
+                Ok(core_asm_code) if debug => write_file(
+                    format!("{output}.core.asm.sg"),
+                    format!("{core_asm_code:#}"),
+                )?,
+                Ok(core_asm_code) => {
+                    write_file(format!("{output}.core.asm.sg"), core_asm_code.to_string())?
+                }
+                Err(_) => {
+                    return Err(Error::InvalidSource(
+                        "cannot emit core assembly for a program which uses standard instructions"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+        if emit.contains(&Emit::StdAsm) {
+            // Core programs are also standard programs.
+            let std_asm_code = match asm_code {
+                Ok(core_asm_code) => sage::asm::StandardProgram::from(core_asm_code),
+                Err(std_asm_code) => std_asm_code,
+            };
+            write_file(
+                format!("{output}.std.asm.sg"),
+                if debug {
+                    format!("{std_asm_code:#}")
+                } else {
+                    std_asm_code.to_string()
+                },
+            )?;
+        }
+    }
+
+    if emit.contains(&Emit::Vm) || emit.contains(&Emit::C) {
+        let vm_code = match compile_source_to_vm(
+            filename,
+            src.to_string(),
+            src_type,
+            call_stack_size,
+            core,
+            env,
+        )? {
+            Ok(vm_code) => Ok(vm_code.flatten()),
+            Err(vm_code) => Err(vm_code.flatten()),
+        };
+        if emit.contains(&Emit::Vm) {
+            write_file(
+                format!("{output}.vm.sg"),
+                match &vm_code {
+                    Ok(vm_code) if debug => format!("{vm_code:#}"),
+                    Err(vm_code) if debug => format!("{vm_code:#}"),
+                    Ok(vm_code) => vm_code.to_string(),
+                    Err(vm_code) => vm_code.to_string(),
+                },
+            )?;
+        }
+        if emit.contains(&Emit::C) {
+            let mut backend = get_backend(TargetType::C, options)?;
+            let code = env
+                .time_pass("vm-to-target", || backend.build(&vm_code))
+                .map_err(Error::BuildError)?;
+            backend.emit(output, &code).map_err(Error::BuildError)?;
+        }
     }
     Ok(())
 }
 
+/// Get the backend registered for a target, configured with the given runtime options.
+fn get_backend(
+    target: TargetType,
+    options: &BuildOptions,
+) -> Result<Box<dyn targets::Target>, Error> {
+    let mut backend: Box<dyn targets::Target> = match (target, &options.c_linkage) {
+        // C linked with libraries calls the foreign functions directly.
+        (TargetType::C, Some(linkage)) => Box::new(targets::C::with_linkage(linkage.clone())),
+        _ => target
+            .registered_name()
+            .and_then(targets::get_target)
+            .ok_or_else(|| Error::BuildError(format!("no backend registered for {target:?}")))?,
+    };
+    options
+        .runtime
+        .configure(backend.as_mut(), options.call_stack_size)?;
+    Ok(backend)
+}
+
 /// Write some contents to a file.
 fn write_file(file: String, contents: String) -> Result<(), Error> {
     write(file, contents).map_err(Error::IO)
@@ -458,10 +1170,381 @@ fn read_file(name: &str) -> Result<String, Error> {
     read_to_string(name).map_err(Error::IO)
 }
 
+/// Print the warnings reported while compiling the given source code.
+fn print_warnings(env: &Env, source_code: &str, format: ErrorFormat) {
+    let source_code = frontend::without_comments(source_code);
+    for warning in env.get_warnings() {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", warning.diagnostic().render(&source_code)),
+            ErrorFormat::Json => eprintln!("{}", warning.diagnostic().to_json()),
+        }
+    }
+}
+
+/// Format source files in place, or check that they're already formatted.
+fn fmt(options: &FmtOptions) {
+    let mut failed = false;
+    for file in &options.files {
+        let result = read_file(file).and_then(|src| {
+            let formatted = frontend::format_source(&src).map_err(Error::Parse)?;
+            if options.check {
+                Ok(formatted == src)
+            } else {
+                write_file(file.clone(), formatted).map(|_| true)
+            }
+        });
+        match result {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("{file} is not formatted");
+                failed = true;
+            }
+            Err(e) => {
+                eprintln!("Could not format {file}: {e:?}");
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1)
+    }
+}
+
+/// Print the extended description of an error code.
+fn explain(code: &str) {
+    match ErrorCode::find(code) {
+        Some(error) => println!("{}: {}\n\n{}", error.code, error.title, error.explanation),
+        None => {
+            eprintln!("{code} is not an error code");
+            std::process::exit(1)
+        }
+    }
+}
+
+/// Write the documentation of a source file.
+fn doc(options: &DocOptions) {
+    let format = match options.format {
+        DocFormatType::Markdown => DocFormat::Markdown,
+        DocFormatType::Html => DocFormat::Html,
+    };
+    let name = std::path::Path::new(&options.file)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| options.file.clone());
+
+    let result = read_file(&options.file).and_then(|src| {
+        let program = parse_frontend_minimal(&src, Some(&options.file)).map_err(Error::Parse)?;
+        // The program isn't documented with the prelude, but its types may use the prelude's.
+        let prelude = frontend::with_prelude(Expr::NONE, true, true).map_err(Error::Parse)?;
+        let docs = ModuleDocs::from_program(&name, &program, &prelude, &Env::default());
+        let output = options
+            .output
+            .clone()
+            .unwrap_or_else(|| format!("{name}.{}", format.extension()));
+        write_file(output, docs.render(format))
+    });
+    if let Err(e) = result {
+        eprintln!("Could not document {}: {e:?}", options.file);
+        std::process::exit(1)
+    }
+}
+
+/// Rebuild a source file whenever the source files in its directory change,
+/// and run the program or its tests.
+fn watch(options: &WatchOptions) {
+    let dir = Path::new(&options.file)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
+    let cache_dir = options
+        .cache_dir
+        .clone()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("sage-cache"));
+
+    let mut last_changes = None;
+    loop {
+        let changes = source_file_changes(&dir);
+        if last_changes.as_ref() != Some(&changes) {
+            last_changes = Some(changes);
+            println!("[watch] rebuilding {}", options.file);
+            let mut env = interpreter_env();
+            env.set_monomorph_cache_dir(&cache_dir);
+            let start = std::time::Instant::now();
+            let passed = rebuild(options, &env);
+            println!(
+                "[watch] {} in {:.2}s, waiting for changes",
+                if passed { "finished" } else { "failed" },
+                start.elapsed().as_secs_f64()
+            );
+        }
+        std::thread::sleep(std::time::Duration::from_millis(options.interval));
+    }
+}
+
+/// Rebuild the watched file, and run it or its tests. This returns whether it succeeded.
+fn rebuild(options: &WatchOptions, env: &Env) -> bool {
+    let src = match read_file(&options.file) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("Could not read {}: {e:?}", options.file);
+            return false;
+        }
+    };
+    let result = if options.test {
+        frontend::TestRunner::new(env.clone(), options.call_stack_size)
+            .run(&src, Some(&options.file), options.filter.as_deref())
+            .map(|results| print_test_results(&results))
+            .map_err(Error::Parse)
+    } else {
+        let build = BuildOptions {
+            filename: Some(&options.file),
+            src_type: SourceType::Sage,
+            output: "",
+            call_stack_size: options.call_stack_size,
+            core: false,
+            debug: false,
+            c_linkage: None,
+            runtime: RuntimeOptions::default(),
+        };
+        compile(src.clone(), TargetType::Run, &build, env).map(|_| true)
+    };
+    print_warnings(env, &src, ErrorFormat::Human);
+    match result {
+        Ok(passed) => passed,
+        Err(Error::InterpreterError(RuntimeError::Exit(status))) => {
+            eprintln!("The program exited with status {status}");
+            false
+        }
+        Err(e) => {
+            eprintln!("{e:?}");
+            false
+        }
+    }
+}
+
+/// The times the source files in a directory (and the directories in it) were last changed.
+fn source_file_changes(dir: &Path) -> BTreeMap<PathBuf, SystemTime> {
+    let mut changes = BTreeMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Skip hidden directories, like `.git`.
+            let is_hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if path.is_dir() && !is_hidden {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "sg") {
+                if let Ok(modified) = entry.metadata().and_then(|meta| meta.modified()) {
+                    changes.insert(path, modified);
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// The environment to compile programs which are run in the interpreter under.
+fn interpreter_env() -> Env {
+    let mut env = Env::default();
+    env.set_cfg("target", Some("run"));
+    env.set_cfg("cell_width", Some(64));
+    env
+}
+
+/// Run the tests in a source file, and exit with an error if any of them fail.
+fn test(options: &TestOptions) {
+    let env = interpreter_env();
+    let runner = frontend::TestRunner::new(env, options.call_stack_size);
+
+    let results = read_file(&options.file).and_then(|src| {
+        runner
+            .run(&src, Some(&options.file), options.filter.as_deref())
+            .map_err(Error::Parse)
+    });
+    let results = match results {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Could not test {}: {e:?}", options.file);
+            std::process::exit(1)
+        }
+    };
+
+    if let Some(path) = &options.coverage {
+        let mut coverage = testing::Coverage::new();
+        for result in &results {
+            coverage.merge(&result.coverage);
+        }
+        if let Err(e) = write_file(path.clone(), coverage.to_lcov()) {
+            eprintln!("Could not write coverage to {path}: {e:?}");
+            std::process::exit(1)
+        }
+    }
+
+    if !print_test_results(&results) {
+        std::process::exit(1)
+    }
+}
+
+/// Print which tests passed, and the output of the tests which failed.
+/// This returns whether all of the tests passed.
+fn print_test_results(results: &[frontend::TestResult]) -> bool {
+    println!("running {} tests", results.len());
+    for result in results {
+        let status = if result.passed() { "ok" } else { "FAILED" };
+        println!("test {} ... {status}", result.name);
+    }
+    let failed = results
+        .iter()
+        .filter(|result| !result.passed())
+        .collect::<Vec<_>>();
+    for result in &failed {
+        println!("\n---- {} ----", result.name);
+        print!("{}", result.output);
+        if !result.output.is_empty() && !result.output.ends_with('\n') {
+            println!();
+        }
+        if let Some(failure) = &result.failure {
+            println!("{failure}");
+        }
+    }
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if failed.is_empty() { "ok" } else { "FAILED" },
+        results.len() - failed.len(),
+        failed.len()
+    );
+    failed.is_empty()
+}
+
+/// Run the benchmarks in a source file, and compare them against a baseline.
+fn bench(options: &BenchOptions) {
+    let env = interpreter_env();
+    let runner = frontend::BenchRunner::new(env, options.call_stack_size, options.iterations);
+
+    let result = read_file(&options.file).and_then(|src| {
+        let baseline: Vec<frontend::BenchResult> = match &options.baseline {
+            Some(file) => serde_json::from_str(&read_file(file)?)
+                .map_err(|e| Error::Parse(format!("invalid baseline {file}: {e}")))?,
+            None => vec![],
+        };
+        let results = runner
+            .run(&src, Some(&options.file), options.filter.as_deref())
+            .map_err(Error::Parse)?;
+
+        println!("running {} benchmarks", results.len());
+        for result in &results {
+            print!(
+                "bench {} ... {} instructions/iter, {:?}/iter",
+                result.name, result.instructions, result.time
+            );
+            match baseline.iter().find(|old| old.name == result.name) {
+                Some(old) => println!(" ({:+.2}% since baseline)", result.change_since(old)),
+                None => println!(),
+            }
+        }
+        if let Some(file) = &options.save_baseline {
+            let json = serde_json::to_string_pretty(&results)
+                .map_err(|e| Error::BuildError(e.to_string()))?;
+            write_file(file.clone(), json)?;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        eprintln!("Could not benchmark {}: {e:?}", options.file);
+        std::process::exit(1)
+    }
+}
+
+/// Run the REPL, evaluating each input read from the standard input until it's closed.
+fn repl(options: &ReplOptions) {
+    use std::io::{stdin, stdout, BufRead, Write};
+
+    let env = interpreter_env();
+    let mut repl = match frontend::Repl::new(env, options.call_stack_size) {
+        Ok(repl) => repl,
+        Err(e) => {
+            eprintln!("Could not start the REPL: {e}");
+            std::process::exit(1)
+        }
+    };
+
+    let mut input = String::new();
+    loop {
+        // Prompt for more lines while the input is unfinished.
+        print!("{}", if input.is_empty() { ">>> " } else { "... " });
+        let _ = stdout().flush();
+        match stdin().lock().read_line(&mut input) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if input.trim().is_empty() {
+            input.clear();
+            continue;
+        }
+        if frontend::Repl::is_incomplete(&input) {
+            continue;
+        }
+
+        match repl.eval(&input) {
+            Ok(printed) => {
+                print!("{}", printed.stdout);
+                eprint!("{}", printed.stderr);
+            }
+            Err(e) => eprintln!("{e}"),
+        }
+        input.clear();
+    }
+    println!();
+}
+
+/// The build profile chosen by the options, as configured by the manifest next to the input.
+/// Without a profile, the program is built with the default options.
+fn build_profile(args: &Options) -> Result<BuildProfile, String> {
+    let name = match (&args.profile, args.release) {
+        (Some(name), _) => name.as_str(),
+        (None, true) => "release",
+        (None, false) => return Ok(BuildProfile::default()),
+    };
+    match Manifest::find(&args.input) {
+        Some(path) => Manifest::load(path)?.profile(name),
+        None => Manifest::default().profile(name),
+    }
+}
+
 /// Run the CLI.
 fn cli() {
     // Parse the arguments to the CLI.
-    let args = Args::parse();
+    let command = match Cli::parse() {
+        Cli {
+            explain: Some(code),
+            ..
+        } => return explain(&code),
+        Cli {
+            command: Some(command),
+            ..
+        } => command,
+        _ => {
+            let _ = Cli::command().print_help();
+            return;
+        }
+    };
+    let args = match (&command, command.options()) {
+        (_, Some(args)) => args,
+        (Command::Repl(options), None) => return repl(options),
+        (Command::Fmt(options), None) => return fmt(options),
+        (Command::Doc(options), None) => return doc(options),
+        (Command::Test(options), None) => return test(options),
+        (Command::Bench(options), None) => return bench(options),
+        (Command::Watch(options), None) => return watch(options),
+        (_, None) => unreachable!(),
+    };
     let mut builder = env_logger::Builder::from_default_env();
     builder.format_timestamp(None);
 
@@ -482,26 +1565,192 @@ fn cli() {
 
     builder.init();
 
+    // Create the environment to compile LIR code under.
+    let mut env = Env::default();
+    if let Some(dir) = &args.cache_dir {
+        env.set_monomorph_cache_dir(dir);
+    }
+    let mut profile = match build_profile(args) {
+        Ok(profile) => profile,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if let Some(level) = args.opt_level {
+        profile = profile.with_opt_level(level);
+    }
+    // The flags turn on checks on top of the profile.
+    profile.bounds_checks |= args.bounds_checks;
+    profile.configure(&mut env);
+    env.set_backtraces(args.backtraces);
+    env.set_core_only(args.variant == Some(Variant::CoreOnly));
+    env.set_prelude(args.prelude.into());
+    if args.reorder_fields {
+        env.set_field_reordering(true);
+    }
+    if args.time_passes.is_some() {
+        env.enable_pass_timings();
+    }
+
+    // Building defaults to C, and everything else to the interpreter.
+    let target = match (&command, args.target) {
+        (Command::Run(_), Some(target)) if !matches!(target, TargetType::Run) => {
+            error!("`run` executes the program in the interpreter, use `build` to compile for {target:?}");
+            std::process::exit(1);
+        }
+        (_, Some(target)) => target,
+        (Command::Build(_), None) => TargetType::C,
+        (_, None) => TargetType::Run,
+    };
+    if let Some(target) = target.to_possible_value() {
+        env.set_cfg("target", Some(target.get_name()));
+    }
+    // Compile for the cell width and standard instructions of the target's backend.
+    let backend = target.registered_name().and_then(targets::get_target);
+    // Programs for targets without the standard instructions are compiled to the core variant.
+    // Assembly is also compiled to the core variant when possible, since it's easier to read.
+    let core = match args.variant {
+        Some(variant) => variant != Variant::Std,
+        None => {
+            matches!(command, Command::Asm(_))
+                || backend
+                    .as_ref()
+                    .is_some_and(|backend| !backend.supports_std())
+        }
+    };
+    match backend {
+        Some(backend) => {
+            env.set_cfg("cell_width", Some(backend.cell_width()));
+            env.set_target_features(backend.features());
+        }
+        None => env.set_cfg("cell_width", Some(64)),
+    }
+    for param in &args.cfg {
+        match param.split_once('=') {
+            Some((name, value)) => env.set_cfg(name.trim(), Some(value.trim())),
+            None => env.set_cfg(param.trim(), None::<&str>),
+        }
+    }
+    env.set_program_args(std::iter::once(&args.input).chain(&args.program_args));
+    for kind in &args.allow {
+        env.set_warning_level(*kind, WarningLevel::Allow);
+    }
+    for kind in &args.deny {
+        env.set_warning_level(*kind, WarningLevel::Deny);
+    }
+    for name in &args.lint {
+        match builtin_lint(name) {
+            Some(lint) => env.add_lint(lint),
+            None => {
+                error!("Unknown lint {name}");
+                return;
+            }
+        }
+    }
+
+    // Call foreign functions from the given C headers and libraries, if there are any.
+    let c_linkage =
+        (!args.c_header.is_empty() || !args.c_lib.is_empty()).then(|| targets::CLinkage {
+            headers: args.c_header.clone(),
+            libraries: args.c_lib.clone(),
+        });
+    let timeout = match args
+        .timeout
+        .map(std::time::Duration::try_from_secs_f64)
+        .transpose()
+    {
+        Ok(timeout) => timeout,
+        Err(e) => {
+            eprintln!("Invalid timeout: {e}");
+            std::process::exit(1)
+        }
+    };
+    let runtime = RuntimeOptions {
+        tape_cells: args.tape_cells,
+        bounds_check: args.tape_bounds_check,
+        put_char: args.putchar_hook.clone(),
+        eput_char: args.eputchar_hook.clone(),
+        get_char: args.getchar_hook.clone(),
+        limits: Limits {
+            max_steps: args.max_steps,
+            max_tape: args.max_tape,
+            timeout,
+            io_quota: args.io_quota,
+        },
+        sanitize: args.sanitize,
+        leak_check: args.leak_check,
+        #[cfg(feature = "framebuffer")]
+        window: match args.window.as_deref().map(parse_window_size).transpose() {
+            Ok(window) => window,
+            Err(e) => {
+                eprintln!("Invalid window size: {e}");
+                std::process::exit(1)
+            }
+        },
+        #[cfg(feature = "terminal")]
+        raw_keys: args.raw_keys,
+    };
+
+    let options = BuildOptions {
+        filename: Some(&args.input),
+        src_type: args.source_type,
+        output: &args.output,
+        call_stack_size: args.call_stack_size,
+        core,
+        debug: args.debug.is_some(),
+        c_linkage,
+        runtime,
+    };
+
     // Set the directory of the current executable to be that of the file
     match read_file(&args.input) {
         Ok(file_contents) => {
-            match compile(
-                Some(&args.input),
-                file_contents,
-                args.source_type,
-                args.target_type,
-                args.output,
-                args.call_stack_size,
-                args.debug.is_some(),
-            ) {
+            // Write the intermediate representations first, so they're there even if the program fails.
+            let result =
+                emit(&file_contents, &args.emit, &options, &env).and_then(|_| match &command {
+                    Command::Check(_) => check(file_contents.clone(), target, &options, &env),
+                    _ => compile(
+                        file_contents.clone(),
+                        match (&command, args.variant) {
+                            (Command::Asm(_), Some(Variant::Core | Variant::CoreOnly)) => {
+                                TargetType::CoreASM
+                            }
+                            (Command::Asm(_), _) => TargetType::StdASM,
+                            _ => target,
+                        },
+                        &options,
+                        &env,
+                    ),
+                });
+            print_warnings(&env, &file_contents, args.error_format);
+            if let Some(timings) = env.get_pass_timings() {
+                match args.time_passes {
+                    Some(TimePassesFormat::Json) => eprintln!("{}", timings.to_json()),
+                    _ => eprint!("{}", timings.report()),
+                }
+            }
+            match result {
                 Ok(_) => {}
+                // The program already reported why it halted, so just pass on its status.
+                Err(Error::InterpreterError(RuntimeError::Exit(status))) => {
+                    std::process::exit(status as i32)
+                }
+                Err(e) if args.error_format == ErrorFormat::Json => {
+                    for diagnostic in e.diagnostics() {
+                        eprintln!("{}", diagnostic.to_json());
+                    }
+                    std::process::exit(1)
+                }
                 Err(e) => {
                     error!("{e:#?}");
+                    std::process::exit(1)
                 }
             }
         }
         Err(e) => {
             error!("Error reading file: {e:?}");
+            std::process::exit(1)
         }
     }
 }
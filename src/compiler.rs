@@ -0,0 +1,321 @@
+//! # The Compiler
+//!
+//! The [`Compiler`] builder compiles frontend source code in one call, for
+//! applications which embed Sage. It parses the source, compiles it to LIR,
+//! assembly, and virtual machine code, and builds it for a target, so the
+//! application doesn't need to set up an [`Env`] or assemble the program itself.
+//!
+//! ```rust
+//! use sage::Compiler;
+//!
+//! let compilation = Compiler::new("println(1 + 2);").target("c").compile();
+//! assert!(compilation.succeeded());
+//! assert!(compilation.code().unwrap().contains("int main"));
+//! ```
+//!
+//! Every stage of the program which was compiled is kept on the [`Compilation`],
+//! along with the errors and warnings reported while compiling it, so a program
+//! which fails to compile can still be inspected up to the stage which failed.
+use crate::{
+    asm,
+    frontend::{without_comments, Prelude},
+    lir::{Compile, Diagnostic, Env, Expr, Severity},
+    manifest::BuildProfile,
+    parse::parse_frontend_with_prelude,
+    targets::{self, Target},
+    vm,
+};
+
+/// The variant of the virtual machine to compile a program to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// The core variant, which every target supports. Programs which use
+    /// standard instructions are compiled to the standard variant instead.
+    Core,
+    /// Only the core variant. Programs which use standard instructions are an
+    /// error, pointing to where they're used.
+    CoreOnly,
+    /// The standard variant.
+    Standard,
+}
+
+/// A builder for compiling frontend source code.
+#[derive(Clone, Debug)]
+pub struct Compiler {
+    /// The source code to compile.
+    src: String,
+    /// The name of the file the source code is from, for diagnostics and module files.
+    filename: Option<String>,
+    /// The name of the target to build the program for, if any.
+    target: Option<String>,
+    /// The variant to compile to, if not the target's default.
+    variant: Option<Variant>,
+    /// What the program can use without declaring it, if not the environment's.
+    prelude: Option<Prelude>,
+    /// The build profile, if one was chosen.
+    profile: Option<BuildProfile>,
+    /// The optimization level, from `0` to `2`, if not the profile's.
+    opt_level: Option<u8>,
+    /// The width of a cell in bits, if not the target's.
+    cell_width: Option<usize>,
+    /// The number of cells allocated for the call stack.
+    call_stack_size: usize,
+    /// The compile parameters for `cfg` checks.
+    cfg: Vec<(String, Option<String>)>,
+    /// The environment to compile under.
+    env: Env,
+}
+
+impl Compiler {
+    /// Compile some frontend source code. By default, the program is compiled
+    /// for the interpreter, without building it for a target.
+    pub fn new(src: impl ToString) -> Self {
+        Self {
+            src: src.to_string(),
+            filename: None,
+            target: None,
+            variant: None,
+            prelude: None,
+            profile: None,
+            opt_level: None,
+            cell_width: None,
+            call_stack_size: 65536,
+            cfg: vec![],
+            env: Env::default(),
+        }
+    }
+
+    /// The name of the file the source code is from. Diagnostics point into this
+    /// file, and the modules it declares are loaded from the files next to it.
+    pub fn filename(mut self, filename: impl ToString) -> Self {
+        self.filename = Some(filename.to_string());
+        self
+    }
+
+    /// Build the program for a registered target, like `c` or `x86-64`.
+    /// The program is compiled with the target's cell width and standard instructions.
+    pub fn target(mut self, name: impl ToString) -> Self {
+        self.target = Some(name.to_string());
+        self
+    }
+
+    /// The variant to compile to. By default, programs are compiled to the core variant for
+    /// targets which only support the core instructions, and to the standard variant otherwise.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = Some(variant);
+        self
+    }
+
+    /// What the program can use without declaring it. By default, this is the builtins
+    /// and the standard library, and a program's own declarations override them.
+    pub fn prelude(mut self, prelude: Prelude) -> Self {
+        self.prelude = Some(prelude);
+        self
+    }
+
+    /// The build profile, which chooses the optimization level, bounds checks,
+    /// assertions, and debug info together, like [`BuildProfile::release`].
+    pub fn profile(mut self, profile: BuildProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// The optimization level: `0` keeps the program as written, `1` removes
+    /// assertions, and `2` also packs the fields of bitfields into fewer cells.
+    /// This overrides the level of the profile.
+    pub fn opt_level(mut self, level: u8) -> Self {
+        self.opt_level = Some(level.min(2));
+        self
+    }
+
+    /// The width of a cell in bits. This defaults to the target's cell width, or
+    /// 64 bits without a target. A target can only build programs for its own width.
+    pub fn cell_width(mut self, bits: usize) -> Self {
+        self.cell_width = Some(bits);
+        self
+    }
+
+    /// The number of cells allocated for the call stack.
+    pub fn call_stack_size(mut self, cells: usize) -> Self {
+        self.call_stack_size = cells;
+        self
+    }
+
+    /// Set a compile parameter for `cfg` checks, with or without a value.
+    pub fn cfg(mut self, name: impl ToString, value: Option<impl ToString>) -> Self {
+        self.cfg
+            .push((name.to_string(), value.map(|value| value.to_string())));
+        self
+    }
+
+    /// The environment to compile under, like one with builtins or foreign
+    /// functions registered on it. The builder's options are set on top of it.
+    pub fn env(mut self, env: Env) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Compile the program, as far as it can be compiled.
+    pub fn compile(&self) -> Compilation {
+        let mut compilation = Compilation {
+            source: without_comments(&self.src),
+            lir: None,
+            asm: None,
+            vm: None,
+            code: None,
+            diagnostics: vec![],
+        };
+        let mut env = self.env.clone();
+        if let Err(diagnostics) = self.compile_into(&mut env, &mut compilation) {
+            compilation.diagnostics.extend(diagnostics);
+        }
+        // The warnings are reported before the error which stopped the compiler.
+        let mut diagnostics = env
+            .get_warnings()
+            .iter()
+            .map(|warning| warning.diagnostic())
+            .collect::<Vec<_>>();
+        diagnostics.append(&mut compilation.diagnostics);
+        compilation.diagnostics = diagnostics;
+        compilation
+    }
+
+    /// Compile the program under an environment, saving each stage on the compilation.
+    fn compile_into(
+        &self,
+        env: &mut Env,
+        compilation: &mut Compilation,
+    ) -> Result<(), Vec<Diagnostic>> {
+        let mut target = match &self.target {
+            Some(name) => Some(
+                targets::get_target(name)
+                    .ok_or_else(|| vec![Diagnostic::error(format!("unknown target `{name}`"))])?,
+            ),
+            None => None,
+        };
+        let cell_width = self
+            .cell_width
+            .unwrap_or_else(|| target.as_ref().map_or(64, |target| target.cell_width()));
+
+        let profile = self.profile.unwrap_or(BuildProfile {
+            // Without a profile, the environment's checks and debug info are kept.
+            bounds_checks: env.has_bounds_checks(),
+            debug_info: env.has_debug_info(),
+            ..BuildProfile::default()
+        });
+        match self.opt_level {
+            Some(level) => profile.with_opt_level(level).configure(env),
+            None => profile.configure(env),
+        }
+        env.set_cfg("target", Some(self.target.as_deref().unwrap_or("run")));
+        env.set_cfg("cell_width", Some(cell_width));
+        if let Some(target) = &target {
+            env.set_target_features(target.features());
+        }
+        for (name, value) in &self.cfg {
+            env.set_cfg(name, value.as_ref());
+        }
+        let core = match self.variant {
+            Some(variant) => variant != Variant::Standard,
+            None => target.as_ref().is_some_and(|target| !target.supports_std()),
+        };
+        if self.variant == Some(Variant::CoreOnly) {
+            env.set_core_only(true);
+        }
+        if let Some(prelude) = self.prelude {
+            env.set_prelude(prelude);
+        }
+
+        let lir =
+            parse_frontend_with_prelude(&self.src, self.filename.as_deref(), env.get_prelude())
+                .map_err(|e| vec![Diagnostic::parse_error(&e)])?;
+        compilation.lir = Some(lir.clone());
+
+        let asm = lir
+            .compile_with_env(env.clone(), core)
+            .map_err(|e| e.diagnostics())?;
+        compilation.asm = Some(asm.clone());
+
+        let vm = match asm {
+            Ok(asm) => asm.assemble(self.call_stack_size).map(Ok),
+            Err(asm) => asm.assemble(self.call_stack_size).map(Err),
+        }
+        .map_err(|e| vec![Diagnostic::error(e)])?;
+        compilation.vm = Some(vm.clone());
+
+        if let Some(target) = &mut target {
+            let mut config = target.config().clone();
+            config.cell_width = cell_width;
+            target
+                .configure(config)
+                .map_err(|e| vec![Diagnostic::error(e)])?;
+            let code = target.build(&vm).map_err(|e| vec![Diagnostic::error(e)])?;
+            compilation.code = Some(code);
+        }
+        Ok(())
+    }
+}
+
+/// The stages of a program compiled by a [`Compiler`], and the diagnostics reported
+/// while compiling it. The stages after an error are missing.
+#[derive(Clone)]
+pub struct Compilation {
+    /// The source code, without its comments, which the diagnostics' locations refer to.
+    source: String,
+    /// The parsed program.
+    lir: Option<Expr>,
+    /// The assembly code, of the core variant or the standard variant.
+    asm: Option<Result<asm::CoreProgram, asm::StandardProgram>>,
+    /// The virtual machine code, of the core variant or the standard variant.
+    vm: Option<Result<vm::CoreProgram, vm::StandardProgram>>,
+    /// The code built for the target, if there is a target.
+    code: Option<String>,
+    /// The errors and warnings reported while compiling.
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Compilation {
+    /// Did the program compile without errors?
+    pub fn succeeded(&self) -> bool {
+        !self
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// The program parsed to LIR, if it parsed.
+    pub fn lir(&self) -> Option<&Expr> {
+        self.lir.as_ref()
+    }
+
+    /// The assembly code of the program, if it compiled. This is `Ok` for
+    /// a core variant program, and `Err` for a standard variant program.
+    pub fn asm(&self) -> Option<&Result<asm::CoreProgram, asm::StandardProgram>> {
+        self.asm.as_ref()
+    }
+
+    /// The virtual machine code of the program, if it assembled. This is `Ok` for
+    /// a core variant program, and `Err` for a standard variant program.
+    pub fn vm(&self) -> Option<&Result<vm::CoreProgram, vm::StandardProgram>> {
+        self.vm.as_ref()
+    }
+
+    /// The code built for the target, like C source code, if it built.
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// The errors and warnings reported while compiling, warnings first.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Render the diagnostics as plain text, with the source lines they refer to.
+    pub fn render_diagnostics(&self) -> String {
+        self.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.render(&self.source))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
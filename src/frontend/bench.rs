@@ -0,0 +1,122 @@
+//! # The Benchmark Runner
+//!
+//! Benchmarks are procedures marked with the `bench` attribute, which take no arguments:
+//!
+//! ```text
+//! #[bench]
+//! fun bench_fib() {
+//!     fib(20);
+//! }
+//! ```
+//!
+//! Like tests, each benchmark is compiled into its own program with the declarations
+//! at the top level of the source code. The program calls the benchmark some number
+//! of times in a row, and it's profiled in the interpreter. To leave out the work
+//! the program does before the benchmark is called (like initializing the prelude),
+//! the same program is also profiled without calling the benchmark at all, and the
+//! difference is split between the iterations.
+//!
+//! The number of instructions executed is exactly the same every time a benchmark
+//! is run, so it's the measurement to compare between versions of the compiler.
+//! The wall time of the interpreter is reported too, but it varies between runs.
+use super::test_runner::{declarations, find_annotated, harness, profile};
+use crate::lir::{Attribute, Declaration, Env};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The measurements of a benchmark.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BenchResult {
+    /// The path of the benchmark procedure, like `math.bench_gcd`.
+    pub name: String,
+    /// The number of times the benchmark was called.
+    pub iterations: usize,
+    /// The number of instructions executed per iteration.
+    pub instructions: u64,
+    /// The time the interpreter took per iteration.
+    pub time: Duration,
+}
+
+impl BenchResult {
+    /// The change in the number of instructions executed per iteration
+    /// since a baseline, as a percentage of the baseline.
+    pub fn change_since(&self, baseline: &Self) -> f64 {
+        if baseline.instructions == 0 {
+            return 0.0;
+        }
+        (self.instructions as f64 - baseline.instructions as f64) * 100.0
+            / baseline.instructions as f64
+    }
+}
+
+/// Runs the benchmarks declared in frontend source code.
+pub struct BenchRunner {
+    /// The environment every benchmark is compiled under.
+    env: Env,
+    /// The number of cells allocated for the call stack.
+    call_stack_size: usize,
+    /// The number of times each benchmark is called.
+    iterations: usize,
+}
+
+impl BenchRunner {
+    /// Compile the benchmarks under the given environment, and call each of them
+    /// the given number of times.
+    pub fn new(env: Env, call_stack_size: usize, iterations: usize) -> Self {
+        Self {
+            env,
+            call_stack_size,
+            iterations: iterations.max(1),
+        }
+    }
+
+    /// Run the benchmarks declared in source code whose names contain the filter,
+    /// in the order they're declared. If a benchmark fails, this returns an error.
+    pub fn run(
+        &self,
+        src: &str,
+        filename: Option<&str>,
+        filter: Option<&str>,
+    ) -> Result<Vec<BenchResult>, String> {
+        let decls = declarations(src, filename)?;
+        find_annotated(&decls, &Attribute::Bench, filter)
+            .into_iter()
+            .map(|path| {
+                self.run_bench(&decls, &path)
+                    .map_err(|e| format!("benchmark `{}` failed: {e}", path.join(".")))
+            })
+            .collect()
+    }
+
+    /// Profile the benchmark at the given path, with and without calling it.
+    fn run_bench(&self, decls: &[Declaration], path: &[String]) -> Result<BenchResult, String> {
+        let (before, before_time) = self.measure(decls, path, 0)?;
+        let (after, after_time) = self.measure(decls, path, self.iterations)?;
+        Ok(BenchResult {
+            name: path.join("."),
+            iterations: self.iterations,
+            instructions: after.saturating_sub(before) / self.iterations as u64,
+            time: after_time.saturating_sub(before_time) / self.iterations as u32,
+        })
+    }
+
+    /// Profile a program which calls the benchmark a number of times, and return
+    /// the number of instructions it executed and the time it took.
+    fn measure(
+        &self,
+        decls: &[Declaration],
+        path: &[String],
+        calls: usize,
+    ) -> Result<(u64, Duration), String> {
+        let (profile, time, _) =
+            profile(&self.env, self.call_stack_size, harness(decls, path, calls))?;
+        match profile.result {
+            Ok(()) => Ok((profile.instructions, time)),
+            // A panic explains itself on the standard error stream.
+            Err(e) => match profile.device.error_str().trim() {
+                "" => Err(e.to_string()),
+                error => Err(error.to_string()),
+            },
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! # The Formatter
+//!
+//! The formatter rewrites frontend source code in a canonical style, so that
+//! code written by hand and generated code can be compared line by line.
+//!
+//! The frontend parses straight to LIR, which doesn't keep the comments or the
+//! layout of the source code. So, the formatter works on the tokens of the source
+//! instead, and keeps the lines as they are written: it indents every line by the
+//! brackets around it, spaces out commas, the colons of type annotations, and binary
+//! operators, removes trailing whitespace, and collapses runs of blank lines. Comments
+//! are kept where they are.
+//!
+//! The source is parsed before and after it's formatted, and the formatter
+//! returns an error instead of changing what the program means.
+use super::{parse_source, without_comments};
+
+/// The string a line is indented with for every bracket around it.
+const INDENT: &str = "    ";
+
+/// The operators made of more than one character, which are lexed as one token.
+const OPERATORS: &[&str] = &[
+    "::", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "=>", "->", "..",
+];
+
+/// The binary operators which are written with a space on either side.
+/// `<` and `>` are left as they are written, since they also surround type arguments.
+const SPACED_OPERATORS: &[&str] = &[
+    "=", "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "=>", "+", "-", "*",
+    "/", "%", "&", "|", "^",
+];
+
+/// The keywords which can come right before an expression, so an operator after them is unary.
+const KEYWORDS: &[&str] = &["as", "else", "if", "in", "match", "return", "while"];
+
+/// The kinds of tokens the formatter lays out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    /// An identifier, keyword, number, or string or character literal.
+    Word,
+    /// An operator or other punctuation.
+    Punct,
+    /// An opening bracket.
+    Open,
+    /// A closing bracket.
+    Close,
+    /// A line or block comment.
+    Comment,
+    /// The end of a line.
+    Newline,
+}
+
+/// A token of the source code.
+#[derive(Clone, Copy, Debug)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    /// Was the token written with whitespace before it?
+    space_before: bool,
+}
+
+impl Token<'_> {
+    /// Is this token the given punctuation?
+    fn is(&self, text: &str) -> bool {
+        self.kind == Kind::Punct && self.text == text
+    }
+
+    /// Is this token a binary operator written with spaces?
+    fn is_spaced_operator(&self) -> bool {
+        self.kind == Kind::Punct && SPACED_OPERATORS.contains(&self.text)
+    }
+}
+
+/// Format frontend source code in the canonical style.
+pub fn format_source(input: &str) -> Result<String, String> {
+    let before = parse_source(&without_comments(input), None)?.to_string();
+    let output = layout(&lex(input)?);
+    let after = parse_source(&without_comments(&output), None)
+        .map_err(|e| format!("formatting the code broke it, please report this:\n{e}"))?
+        .to_string();
+    if before != after {
+        return Err("formatting the code changed its meaning, please report this".to_string());
+    }
+    Ok(output)
+}
+
+/// Split source code into tokens.
+fn lex(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = input;
+    let mut space_before = false;
+    while let Some(ch) = rest.chars().next() {
+        let (kind, len) = if ch == '\n' {
+            (Kind::Newline, 1)
+        } else if ch.is_whitespace() {
+            space_before = true;
+            rest = &rest[ch.len_utf8()..];
+            continue;
+        } else if rest.starts_with("//") {
+            (Kind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            let end = rest.find("*/").ok_or("unterminated block comment")?;
+            (Kind::Comment, end + 2)
+        } else if ch == '"' {
+            let len = quoted_len(rest).ok_or("unterminated string literal")?;
+            (Kind::Word, len)
+        } else if ch == '\'' {
+            // A quote which doesn't start a character literal is just punctuation.
+            match quoted_len(rest) {
+                Some(len) if !rest[..len].contains('\n') => (Kind::Word, len),
+                _ => (Kind::Punct, 1),
+            }
+        } else if ch.is_alphanumeric() || ch == '_' {
+            let mut len = word_len(rest);
+            // Take the fractional part of a float, but not a range like `0..10`.
+            if ch.is_ascii_digit()
+                && rest[len..].starts_with('.')
+                && rest[len + 1..].starts_with(|c: char| c.is_ascii_digit())
+            {
+                len += 1 + word_len(&rest[len + 1..]);
+            }
+            (Kind::Word, len)
+        } else if matches!(ch, '(' | '[' | '{') {
+            (Kind::Open, 1)
+        } else if matches!(ch, ')' | ']' | '}') {
+            (Kind::Close, 1)
+        } else {
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => (Kind::Punct, op.len()),
+                None => (Kind::Punct, ch.len_utf8()),
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &rest[..len],
+            space_before,
+        });
+        space_before = false;
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+/// The length of the identifier or number at the start of the input.
+fn word_len(input: &str) -> usize {
+    input
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(input.len())
+}
+
+/// The length of the string or character literal at the start of the input, including its quotes.
+fn quoted_len(input: &str) -> Option<usize> {
+    let quote = input.chars().next()?;
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, ch)) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if ch == quote {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Write out the tokens, one line of the source at a time.
+fn layout(tokens: &[Token]) -> String {
+    let mut output = String::new();
+    // For every bracket which is still open, the indentation of the lines inside it.
+    let mut open: Vec<usize> = vec![];
+    // The number of brackets open around the attribute being written, if any.
+    // Attributes are written in Sage-Lisp, so their spacing is left alone.
+    let mut attribute = None;
+    let mut blank_lines = 0;
+
+    for line in tokens.split(|token| token.kind == Kind::Newline) {
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        // Collapse runs of blank lines, and drop the ones at the start of the file.
+        if blank_lines > 0 && !output.is_empty() {
+            output.push('\n');
+        }
+        blank_lines = 0;
+
+        // Closing brackets at the start of a line are indented along with the line which opened them.
+        let closing = line
+            .iter()
+            .take_while(|token| token.kind == Kind::Close)
+            .count()
+            .min(open.len());
+        let indent = match open.last() {
+            _ if closing > 0 => open[open.len() - closing],
+            Some(indent) => indent + 1,
+            None => 0,
+        };
+        output.push_str(&INDENT.repeat(indent));
+        // A bracket opened after another bracket is closed on the same line, like the
+        // body of a function whose parameters span several lines, is indented like the
+        // line which opened the closed bracket.
+        let mut base = indent;
+
+        let mut before_prev: Option<&Token> = None;
+        let mut prev: Option<&Token> = None;
+        for token in line {
+            if let Some(prev) = prev {
+                let space = match attribute {
+                    Some(_) => token.space_before,
+                    None => space_between(before_prev, prev, token),
+                };
+                if space {
+                    output.push(' ');
+                }
+            }
+            output.push_str(token.text);
+
+            match token.kind {
+                Kind::Open => {
+                    if attribute.is_none() && token.text == "[" && prev.is_some_and(|p| p.is("#")) {
+                        attribute = Some(open.len());
+                    }
+                    open.push(base);
+                }
+                Kind::Close => {
+                    if let Some(indent) = open.pop() {
+                        base = indent;
+                    }
+                    if attribute == Some(open.len()) {
+                        attribute = None;
+                    }
+                }
+                _ => {}
+            }
+            before_prev = prev;
+            prev = Some(token);
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Is there a space between two tokens on the same line, after the given token before them?
+fn space_between(before_prev: Option<&Token>, prev: &Token, next: &Token) -> bool {
+    // An operator is only binary after an operand: `&&x` is a reference to a reference,
+    // and `return -x` negates `x`.
+    let is_operand = |token: &Token| match token.kind {
+        Kind::Word => !KEYWORDS.contains(&token.text),
+        Kind::Close => true,
+        _ => false,
+    };
+    match (prev.kind, next.kind) {
+        (_, Kind::Comment) => true,
+        _ if next.is(",") || next.is(";") => false,
+        (_, Kind::Close) if prev.is(",") => false,
+        _ if prev.is(",") => true,
+        // Type annotations are written `name: Type`.
+        _ if next.is(":") => false,
+        _ if prev.is(":") => true,
+        (Kind::Open, _) if prev.text != "{" => false,
+        (_, Kind::Close) if next.text != "}" => false,
+        _ if next.is_spaced_operator() && is_operand(prev) => true,
+        _ if prev.is_spaced_operator() && before_prev.is_some_and(is_operand) => true,
+        _ => next.space_before,
+    }
+}
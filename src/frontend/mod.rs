@@ -1,27 +1,115 @@
 mod parse;
 use crate::lir::Expr;
-pub use parse::{parse_module, parse_source, get_lisp_env};
+pub use parse::{parse_module, parse_source, parse_repl_input, get_lisp_env};
+mod repl;
+pub use repl::{Printed, Repl};
+mod fmt;
+pub use fmt::format_source;
+mod test_runner;
+pub use test_runner::{TestResult, TestRunner};
+mod bench;
+pub use bench::{BenchResult, BenchRunner};
 
-fn without_comments(code: impl ToString) -> String {
+/// Remove the comments from source code. Documentation comments (lines starting
+/// with `///`) are kept as `#doc "..."` lines, which the parser attaches to the
+/// declarations after them. Source code locations in the frontend refer to
+/// the code returned by this function.
+pub fn without_comments(code: impl ToString) -> String {
     use no_comment::{languages, IntoWithoutComments};
     code.to_string()
+        .split('\n')
+        .map(|line| {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            match line.trim_start().strip_prefix("///") {
+                Some(doc) if !doc.starts_with('/') => {
+                    let doc = doc.strip_prefix(' ').unwrap_or(doc).trim_end();
+                    // Escape slashes too, so the text can't start a comment.
+                    let doc = doc
+                        .replace('\\', "\\\\")
+                        .replace('"', "\\\"")
+                        .replace('/', "\\/");
+                    format!("{indent}{}\"{doc}\"", parse::DOC_MARKER)
+                }
+                _ => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
         .chars()
         .without_comments(languages::rust())
         .collect::<String>()
 }
 
+/// What a program can use without declaring or importing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Prelude {
+    /// The builtins, and the standard library as the `std` module.
+    #[default]
+    Std,
+    /// Only the builtins, like `alloc`, `free`, and `get_sp`, without the standard library.
+    Builtins,
+    /// Nothing: the program declares everything it uses.
+    None,
+}
+
+impl Prelude {
+    /// Are the builtins declared for the program?
+    pub fn has_builtins(&self) -> bool {
+        *self != Self::None
+    }
+
+    /// Is the standard library declared for the program?
+    pub fn has_std(&self) -> bool {
+        *self == Self::Std
+    }
+}
+
 pub fn parse(
     input: impl ToString,
     filename: Option<&str>,
     include_builtins: bool,
     include_std: bool,
 ) -> Result<Expr, String> {
-    let mut expr = parse_source(&without_comments(input), filename.map(|x| x.to_owned()))?;
+    let expr = parse_source(&without_comments(input), filename.map(|x| x.to_owned()))?;
+    with_prelude(expr, include_builtins, include_std)
+}
+
+/// The modules of the standard library, by name, in the order they're declared.
+/// Each is kept in its own file, and can import the modules declared before it.
+const STD_MODULES: &[(&str, &str)] = &[
+    ("math", include_str!("std/math.sg")),
+    ("mem", include_str!("std/mem.sg")),
+    ("env", include_str!("std/env.sg")),
+    ("io", include_str!("std/io.sg")),
+    ("fallible", include_str!("std/fallible.sg")),
+    ("int", include_str!("std/int.sg")),
+    ("collections", include_str!("std/collections.sg")),
+    ("time", include_str!("std/time.sg")),
+    ("term", include_str!("std/term.sg")),
+    ("physics", include_str!("std/physics.sg")),
+];
+
+/// The source code of the standard library, with each of its modules.
+fn std_lib_source() -> String {
+    let mut source = String::new();
+    for (name, module) in STD_MODULES {
+        source += &format!("mod {name} {{\n{module}}}\n\n");
+    }
+    source
+}
+
+/// Add the builtins and the standard library to a parsed program. They're declared
+/// around the program, so its own declarations with the same names override them.
+pub fn with_prelude(
+    mut expr: Expr,
+    include_builtins: bool,
+    include_std: bool,
+) -> Result<Expr, String> {
     use crate::side_effects::Output;
     if include_std {
         // Only check the stdlib when we're in debug mode
         let in_debug_mode = cfg!(debug_assertions);
-        let std_lib = parse_module("std", &without_comments(include_str!("std_lib.sg")), in_debug_mode)?;
+        let std_lib = parse_module("std", &without_comments(std_lib_source()), in_debug_mode)?;
         expr = expr.with(std_lib)
     }
     if include_builtins {
@@ -49,6 +137,48 @@ pub fn parse(
                 crate::asm::StandardOp::CoreOp(crate::asm::CoreOp::Pop(None, 1)),
             ],
         });
+        let realloc = crate::lir::ConstExpr::StandardBuiltin(crate::lir::StandardBuiltin {
+            name: "realloc".to_string(),
+            args: vec![
+                (
+                    "ptr".to_string(),
+                    crate::lir::Type::Pointer(
+                        crate::lir::Mutability::Any,
+                        Box::new(crate::lir::Type::Any),
+                    ),
+                ),
+                ("size".to_string(), crate::lir::Type::Int),
+            ],
+            ret: crate::lir::Type::Pointer(
+                crate::lir::Mutability::Mutable,
+                Box::new(crate::lir::Type::Any),
+            ),
+            body: vec![
+                crate::asm::StandardOp::CoreOp(crate::asm::CoreOp::Pop(Some(crate::asm::A), 1)),
+                crate::asm::StandardOp::Realloc {
+                    ptr: crate::asm::SP.deref(),
+                    size: crate::asm::A,
+                },
+            ],
+        });
+        let heap_used = crate::lir::ConstExpr::StandardBuiltin(crate::lir::StandardBuiltin {
+            name: "heap_used".to_string(),
+            args: vec![],
+            ret: crate::lir::Type::Int,
+            body: vec![
+                crate::asm::StandardOp::CoreOp(crate::asm::CoreOp::Next(crate::asm::SP, None)),
+                crate::asm::StandardOp::HeapUsed(crate::asm::SP.deref()),
+            ],
+        });
+        let heap_free = crate::lir::ConstExpr::StandardBuiltin(crate::lir::StandardBuiltin {
+            name: "heap_free".to_string(),
+            args: vec![],
+            ret: crate::lir::Type::Int,
+            body: vec![
+                crate::asm::StandardOp::CoreOp(crate::asm::CoreOp::Next(crate::asm::SP, None)),
+                crate::asm::StandardOp::HeapFree(crate::asm::SP.deref()),
+            ],
+        });
         use crate::asm::CoreOp::*;
 
         use crate::asm::*;
@@ -154,6 +284,189 @@ pub fn parse(
             body: vec![crate::asm::CoreOp::Push(crate::asm::STACK_START, 1)],
         });
 
+        // An arena is a single block of cells, which starts with a header of its capacity
+        // and the number of cells used so far. Allocating from an arena just bumps the
+        // number of cells used, and resetting it frees everything allocated at once.
+        //
+        // `arena_new` allocates the block on the heap, so it needs the standard variant.
+        // For the core variant, `arena_init` makes an arena out of a region of the tape
+        // reserved by the program (like an array), whose first two cells hold the header.
+        // Allocating from and resetting an arena only use core instructions.
+        let arena_type = crate::lir::Type::Pointer(
+            crate::lir::Mutability::Mutable,
+            Box::new(crate::lir::Type::Any),
+        );
+        let arena_new = crate::lir::ConstExpr::StandardBuiltin(crate::lir::StandardBuiltin {
+            name: "arena_new".to_string(),
+            args: vec![("size".to_string(), crate::lir::Type::Int)],
+            ret: arena_type.clone(),
+            body: vec![
+                // Allocate the cells of the arena, along with its header.
+                StandardOp::CoreOp(Move {
+                    src: SP.deref(),
+                    dst: B,
+                }),
+                StandardOp::CoreOp(Set(A, 2)),
+                StandardOp::CoreOp(Add { src: B, dst: A }),
+                StandardOp::Alloc(A),
+                // Store the capacity, and mark every cell as unused.
+                StandardOp::CoreOp(Move {
+                    src: B,
+                    dst: A.deref(),
+                }),
+                StandardOp::CoreOp(Set(A.deref().offset(1), 0)),
+                StandardOp::CoreOp(Move {
+                    src: A,
+                    dst: SP.deref(),
+                }),
+            ],
+        });
+        let arena_init = crate::lir::ConstExpr::CoreBuiltin(crate::lir::CoreBuiltin {
+            name: "arena_init".to_string(),
+            args: vec![
+                ("region".to_string(), arena_type.clone()),
+                ("size".to_string(), crate::lir::Type::Int),
+            ],
+            ret: arena_type.clone(),
+            body: vec![
+                Pop(Some(B), 1),
+                Move {
+                    src: SP.deref(),
+                    dst: A,
+                },
+                // The header takes two of the region's cells, and the rest can be allocated.
+                Move {
+                    src: B,
+                    dst: A.deref(),
+                },
+                Prev(A.deref(), Some(2)),
+                Set(A.deref().offset(1), 0),
+            ],
+        });
+        let arena_alloc = crate::lir::ConstExpr::CoreBuiltin(crate::lir::CoreBuiltin {
+            name: "arena_alloc".to_string(),
+            args: vec![
+                ("arena".to_string(), arena_type.clone()),
+                ("size".to_string(), crate::lir::Type::Int),
+            ],
+            ret: arena_type.clone(),
+            body: vec![
+                Pop(Some(B), 1),
+                Move {
+                    src: SP.deref(),
+                    dst: A,
+                },
+                // Check whether the arena has room for the allocation.
+                Move {
+                    src: A.deref().offset(1),
+                    dst: C,
+                },
+                Add { src: B, dst: C },
+                IsGreater {
+                    a: C,
+                    b: A.deref(),
+                    dst: D,
+                },
+                If(D),
+                // If it doesn't, return a null pointer.
+                Set(SP.deref(), crate::NULL),
+                Else,
+                // Otherwise, return the first unused cell, and mark the allocation as used.
+                Move {
+                    src: A.deref().offset(1),
+                    dst: B,
+                },
+                Move {
+                    src: C,
+                    dst: A.deref().offset(1),
+                },
+                Index {
+                    src: A,
+                    offset: B,
+                    dst: C,
+                },
+                Next(C, Some(2)),
+                Move {
+                    src: C,
+                    dst: SP.deref(),
+                },
+                End,
+            ],
+        });
+        let arena_reset = crate::lir::ConstExpr::CoreBuiltin(crate::lir::CoreBuiltin {
+            name: "arena_reset".to_string(),
+            args: vec![("arena".to_string(), arena_type)],
+            ret: crate::lir::Type::None,
+            body: vec![Pop(Some(A), 1), Set(A.deref().offset(1), 0)],
+        });
+
+        // The number conversions are procedures which use the LIR builtins, instead of
+        // being parsed as builtins, so a program's own procedures with the same names
+        // (like a parser's `parse_float`) override them, like the rest of the prelude.
+        let number_string = crate::lir::Type::Array(
+            Box::new(crate::lir::Type::Char),
+            Box::new(crate::lir::ConstExpr::Int(
+                crate::lir::NUMBER_STRING_LEN as i64,
+            )),
+        );
+        let string_arg = || {
+            vec![(
+                "ptr".to_string(),
+                crate::lir::Mutability::Immutable,
+                crate::lir::Type::Pointer(
+                    crate::lir::Mutability::Any,
+                    Box::new(crate::lir::Type::Char),
+                ),
+            )]
+        };
+        let number_proc = |name: &str, args, ret, body: Expr| {
+            crate::lir::ConstExpr::Proc(crate::lir::Procedure::new(
+                Some(name.to_string()),
+                args,
+                ret,
+                body,
+            ))
+        };
+        let int_to_string = number_proc(
+            "int_to_string",
+            vec![(
+                "n".to_string(),
+                crate::lir::Mutability::Immutable,
+                crate::lir::Type::Int,
+            )],
+            number_string.clone(),
+            Expr::var("n").unop(crate::lir::IntToString),
+        );
+        let float_to_string = number_proc(
+            "float_to_string",
+            vec![
+                (
+                    "f".to_string(),
+                    crate::lir::Mutability::Immutable,
+                    crate::lir::Type::Float,
+                ),
+                (
+                    "precision".to_string(),
+                    crate::lir::Mutability::Immutable,
+                    crate::lir::Type::Int,
+                ),
+            ],
+            number_string,
+            Expr::var("f").binop(crate::lir::FloatToString, Expr::var("precision")),
+        );
+        let parse_int = number_proc(
+            "parse_int",
+            string_arg(),
+            crate::lir::Type::Int,
+            Expr::var("ptr").unop(crate::lir::ParseNumber::Int),
+        );
+        let parse_float = number_proc(
+            "parse_float",
+            string_arg(),
+            crate::lir::Type::Float,
+            Expr::var("ptr").unop(crate::lir::ParseNumber::Float),
+        );
+
         let mut debug_body = vec![];
         for ch in "Debug\n".to_string().chars() {
             debug_body.push(crate::asm::CoreOp::Set(crate::asm::TMP, ch as i64));
@@ -190,6 +503,17 @@ pub fn parse(
             vec![
                 ("free", free),
                 ("alloc", alloc),
+                ("realloc", realloc),
+                ("heap_used", heap_used),
+                ("heap_free", heap_free),
+                ("arena_new", arena_new),
+                ("arena_init", arena_init),
+                ("arena_alloc", arena_alloc),
+                ("arena_reset", arena_reset),
+                ("int_to_string", int_to_string),
+                ("float_to_string", float_to_string),
+                ("parse_int", parse_int),
+                ("parse_float", parse_float),
                 // ("realloc_fp_stack", realloc_fp_stack),
                 // ("realloc_stack", realloc_stack),
                 ("debug", debug),
@@ -1,27 +1,28 @@
 use log::{error, trace};
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag, take_while, take_while1, take_while_m_n},
-    character::complete::{char, digit1, hex_digit1, multispace1, oct_digit1},
-    combinator::{all_consuming, cut, map, map_opt, opt, recognize, verify},
+    bytes::complete::{tag, take_while1},
+    character::complete::{anychar, char, digit1, hex_digit1, multispace1, oct_digit1, satisfy},
+    combinator::{all_consuming, cut, map, map_opt, opt, recognize},
     error::{context, ContextError, ParseError},
-    multi::{fold_many0, many0, many0_count, many1},
+    multi::{many0, many0_count, many1},
     sequence::{delimited, pair, preceded, terminated},
     IResult, Parser,
 };
 use std::{
-    collections::BTreeMap, sync::{Arc, RwLock}
+    collections::{BTreeMap, HashMap}, path::PathBuf, sync::{Arc, RwLock}
 };
 
-use crate::{lir::*, parse::SourceCodeLocation};
+use crate::{lir::*, parse::SourceCodeLocation, side_effects::LogLevel};
 use nom::{
     character::complete::{alpha1, alphanumeric1},
     combinator::value,
-    error::{convert_error, ErrorKind, FromExternalError, VerboseError},
+    error::{convert_error, ErrorKind, VerboseError},
 };
 const KEYWORDS: &[&str] = &[
     "def", "fun", "struct", "enum", "mut", "let", "if", "else", "while", "for", "return", "match",
-    "True", "False", "Null", "None", "sizeof", "Int", "Float", "Char", "Bool", "Cell", "Never",
+    "True", "False", "Null", "None", "sizeof", "alignof", "offsetof", "typename", "fieldnames",
+    "fieldoffsets", "variantnames", "Int", "Float", "Char", "Bool", "Cell", "Never",
     "!",
 ];
 
@@ -961,8 +962,8 @@ fn make_env() -> sage_lisp::Env {
                 .unwrap();
 
                 let c_code = match vm_code {
-                    Ok(vm_code) => crate::targets::C.build_core(&vm_code.flatten()).unwrap(),
-                    Err(vm_code) => crate::targets::C.build_std(&vm_code.flatten()).unwrap(),
+                    Ok(vm_code) => crate::targets::C::default().build_core(&vm_code.flatten()).unwrap(),
+                    Err(vm_code) => crate::targets::C::default().build_std(&vm_code.flatten()).unwrap(),
                 };
 
                 Expr::String(c_code)
@@ -1177,6 +1178,15 @@ lazy_static! {
     static ref LISP_ENV: RwLock<sage_lisp::Env> = RwLock::new(make_env());
     static ref FILE_SAVES: RwLock<Vec<(Vec<usize>, Arc<String>, Option<String>)>> =
         RwLock::new(vec![]);
+    /// The directories of the module files being parsed, which the modules they
+    /// declare are loaded from. The project root, the directory of the file being
+    /// compiled, is the current directory while it's parsed.
+    static ref MODULE_DIRS: RwLock<Vec<PathBuf>> = RwLock::new(vec![]);
+    /// The modules parsed from files so far, so that each file is only parsed once.
+    static ref MODULE_FILES: RwLock<HashMap<PathBuf, Declaration>> = RwLock::new(HashMap::new());
+    /// The error from loading a module file, which is reported instead of the
+    /// error about the `mod` statement which declared it.
+    static ref MODULE_ERROR: RwLock<Option<String>> = RwLock::new(None);
 }
 
 pub fn get_lisp_env() -> sage_lisp::Env {
@@ -1217,6 +1227,9 @@ fn obliterate_save() {
     *program = Arc::new(String::new());
     FILE_SAVES.write().unwrap().clear();
     *LISP_ENV.write().unwrap() = make_env();
+    MODULE_DIRS.write().unwrap().clear();
+    MODULE_FILES.write().unwrap().clear();
+    *MODULE_ERROR.write().unwrap() = None;
 }
 
 fn setup_source_code_locations(program: &str, filename: Option<String>) {
@@ -1335,10 +1348,64 @@ fn get_max_precedence() -> u8 {
     BIN_OPS.read().unwrap().values().map(|x| x.0).max().unwrap()
 }
 
+/// The start of a line holding a documentation comment. Comments are removed before
+/// the source is parsed, so `///` comments are rewritten to `#doc "..."` lines first.
+pub(super) const DOC_MARKER: &str = "#doc ";
+
 fn whitespace<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, &'a str, E> {
-    take_while(|c: char| c.is_whitespace())(input)
+    // Documentation comments are skipped like whitespace, and attached to
+    // declarations afterwards with `doc_comment_before`.
+    let mut rest = input.trim_start();
+    while let Some((after, _)) = parse_doc_comment(rest) {
+        rest = after.trim_start();
+    }
+    Ok((rest, &input[..input.len() - rest.len()]))
+}
+
+/// Parse a documentation comment line, returning the rest of the input and its text.
+fn parse_doc_comment(input: &str) -> Option<(&str, String)> {
+    let mut chars = input
+        .strip_prefix(DOC_MARKER)?
+        .strip_prefix('"')?
+        .char_indices();
+    let mut text = String::new();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '\\' => text.push(chars.next()?.1),
+            '"' => return Some((&input[DOC_MARKER.len() + 1 + i + 1..], text)),
+            ch => text.push(ch),
+        }
+    }
+    None
+}
+
+/// Get the documentation comments on the lines before a declaration, if it has any.
+fn doc_comment_before(declaration: &str) -> Option<Attribute> {
+    let program = PROGRAM.read().unwrap();
+    let offset = get_current_offset_in_program(declaration);
+    let mut lines = vec![];
+    for line in program.get(..offset)?.lines().rev() {
+        match parse_doc_comment(line.trim_start()) {
+            Some((_, text)) => lines.push(text),
+            None if line.trim().is_empty() => continue,
+            None => break,
+        }
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    lines.reverse();
+    Some(Attribute::Doc(lines.join("\n")))
+}
+
+/// Attach the documentation comments before a declaration to it.
+fn with_doc_comment(declaration: Declaration, input: &str) -> Declaration {
+    match doc_comment_before(input) {
+        Some(doc) => declaration.with_attributes([doc]),
+        None => declaration,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -1404,6 +1471,39 @@ fn stmts_to_expr(stmts: Vec<Statement>, end_of_program: bool) -> Expr {
     }
 }
 
+/// Parse an input entered into a REPL.
+///
+/// If the input is an expression without a semicolon, the program prints its value
+/// with `debug` followed by its type. Otherwise, the input is parsed as statements.
+/// The declarations at the top level of the program are the ones the input adds to the REPL.
+pub fn parse_repl_input(input: &str) -> Result<Expr, String> {
+    fn parse_helper<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+        input: &'a str,
+    ) -> IResult<&'a str, Expr, E> {
+        let (input, mut stmts) = many0(parse_stmt)(input)?;
+        let (input, _) = whitespace(input)?;
+        let (input, expr) = parse_expr(input)?;
+        let (input, _) = whitespace(input)?;
+        // The type is only computed, so the expression is still evaluated once.
+        let ty = Expr::ConstExpr(ConstExpr::TypeOf(Box::new(expr.clone())));
+        stmts.push(Statement::Expr(Expr::Many(vec![
+            expr.unop(Put::Debug),
+            Expr::ConstExpr(ConstExpr::Char(':')).print(),
+            Expr::ConstExpr(ConstExpr::Char(' ')).print(),
+            ty.print(),
+            Expr::ConstExpr(ConstExpr::Char('\n')).print(),
+        ])));
+        Ok((input, stmts_to_expr(stmts, true)))
+    }
+
+    obliterate_save();
+    setup_source_code_locations(input, None);
+    if let Ok((_, expr)) = all_consuming(parse_helper::<VerboseError<&str>>)(input) {
+        return Ok(expr);
+    }
+    parse_source(input, None)
+}
+
 pub fn parse_source(input: &str, filename: Option<String>) -> Result<Expr, String> {
     obliterate_save();
     let old_dir = match &filename {
@@ -1431,14 +1531,17 @@ pub fn parse_source(input: &str, filename: Option<String>) -> Result<Expr, Strin
     }
     let result = all_consuming(parse_helper::<VerboseError<&str>>)(input);
     let _ = std::env::set_current_dir(old_dir);
+    if let Some(err) = MODULE_ERROR.write().unwrap().take() {
+        return Err(err);
+    }
     match result {
         Err(nom::Err::Error(e)) => {
             trace!("Error: {e}");
-            Err(convert_error(input, e).to_string())
+            Err(syntax_error(input, e))
         }
         Err(nom::Err::Failure(e)) => {
             trace!("Failure: {e}");
-            Err(convert_error(input, e).to_string())
+            Err(syntax_error(input, e))
         }
         Err(nom::Err::Incomplete(_e)) => {
             unreachable!()
@@ -1447,17 +1550,22 @@ pub fn parse_source(input: &str, filename: Option<String>) -> Result<Expr, Strin
     }
 }
 
+/// Describe a syntax error in some source code, with its error code.
+fn syntax_error(input: &str, e: VerboseError<&str>) -> String {
+    format!("[E0100] {}", convert_error(input, e))
+}
+
 pub fn parse_module(name: &str, input: &str, checked: bool) -> Result<Declaration, String> {
     setup_source_code_locations(input, Some(name.to_owned()));
 
     match parse_module_contents::<VerboseError<&str>>(name, input, checked) {
         Err(nom::Err::Error(e)) => {
             trace!("Error: {e}");
-            Err(convert_error(input, e).to_string())
+            Err(syntax_error(input, e))
         }
         Err(nom::Err::Failure(e)) => {
             trace!("Failure: {e}");
-            Err(convert_error(input, e).to_string())
+            Err(syntax_error(input, e))
         }
         Err(nom::Err::Incomplete(_e)) => {
             unreachable!()
@@ -1465,7 +1573,7 @@ pub fn parse_module(name: &str, input: &str, checked: bool) -> Result<Declaratio
         Ok(("", expr)) => Ok(expr),
         Ok((new_input, _expr)) => {
             let e = VerboseError::<&str>::from_error_kind(new_input, ErrorKind::Verify);
-            Err(convert_error(input, e).to_string())
+            Err(syntax_error(input, e))
         }
     }
 }
@@ -1503,6 +1611,83 @@ fn parse_attribute<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     Ok((input, env.eval(expr)))
 }
 
+/// Parse an attribute on a declaration, like `#[inline]`, `#[deprecated("use `bar`")]`,
+/// `#[derive(eq, hash)]`, or `#[specialize(Float)]`.
+fn parse_declaration_attribute<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Attribute, E> {
+    let (input, _) = tag("#")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("[")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, attribute) = alt((
+        value(Attribute::Inline, tag("inline")),
+        value(Attribute::NoMangle, tag("no_mangle")),
+        value(Attribute::Cold, tag("cold")),
+        value(Attribute::Pure, tag("pure")),
+        value(Attribute::Test, tag("test")),
+        value(Attribute::Bench, tag("bench")),
+        map(
+            preceded(
+                tag("deprecated"),
+                opt(delimited(
+                    pair(whitespace, tag("(")),
+                    preceded(whitespace, parse_string_literal),
+                    pair(whitespace, tag(")")),
+                )),
+            ),
+            Attribute::Deprecated,
+        ),
+        map(
+            delimited(
+                pair(tag("derive"), pair(whitespace, tag("("))),
+                many1(delimited(
+                    whitespace,
+                    map_opt(
+                        take_while1(|c: char| c.is_alphanumeric() || c == '_'),
+                        Derive::from_name,
+                    ),
+                    opt(pair(whitespace, tag(","))),
+                )),
+                pair(whitespace, tag(")")),
+            ),
+            Attribute::Derive,
+        ),
+        map(
+            delimited(
+                pair(tag("specialize"), pair(whitespace, tag("("))),
+                many1(delimited(
+                    whitespace,
+                    parse_type,
+                    opt(pair(whitespace, tag(","))),
+                )),
+                pair(whitespace, tag(")")),
+            ),
+            Attribute::Specialize,
+        ),
+    ))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("]")(input)?;
+    Ok((input, attribute))
+}
+
+/// Parse a declaration statement preceded by one or more attributes.
+fn parse_attributed_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    mut parse_decl_stmt: impl FnMut(&'a str) -> IResult<&'a str, Statement, E>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Statement, E> {
+    move |input| {
+        let (input, attributes) =
+            many1(terminated(parse_declaration_attribute, whitespace))(input)?;
+        match parse_decl_stmt(input)? {
+            (input, Statement::Declaration(decl, loc)) => Ok((
+                input,
+                Statement::Declaration(decl.with_attributes(attributes), loc),
+            )),
+            _ => Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Verify))),
+        }
+    }
+}
+
 fn parse_impl_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Statement, E> {
@@ -1514,9 +1699,16 @@ fn parse_impl_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = cut(tag("{"))(input)?;
     let (mut input, _) = whitespace(input)?;
     let mut impl_items = vec![];
-    while let Ok((i, item)) = parse_impl_item::<E>(input, &ty) {
+    while let Ok((i, (name, item))) = parse_impl_item::<E>(input, &ty) {
         trace!("Parsed impl item: {item:?}");
-        impl_items.push(item);
+        let item = match (item, doc_comment_before(input)) {
+            (ConstExpr::Proc(proc), Some(doc)) => ConstExpr::Proc(proc.with_attributes([doc])),
+            (ConstExpr::PolyProc(proc), Some(doc)) => {
+                ConstExpr::PolyProc(proc.with_attributes([doc]))
+            }
+            (item, _) => item,
+        };
+        impl_items.push((name, item));
         let (i, _) = whitespace(i)?;
         input = i;
     }
@@ -1896,6 +2088,7 @@ fn parse_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Statement, E> {
     let (input, _) = whitespace(input)?;
+    let start = input;
     start_source_code_tracking(input);
     let (input, stmt) = alt((
         context(
@@ -1909,7 +2102,7 @@ fn parse_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 
     let stmt = match stmt {
         Statement::Declaration(decl, _) => {
-            Statement::Declaration(decl, Some(source_code_loc.clone()))
+            Statement::Declaration(with_doc_comment(decl, start), Some(source_code_loc.clone()))
         }
         Statement::Expr(expr) => Statement::Expr(expr.annotate(source_code_loc.clone())),
     };
@@ -1923,10 +2116,20 @@ fn parse_long_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = whitespace(input)?;
 
     let (input, stmt) = alt((
+        context(
+            "attributes",
+            parse_attributed_stmt(alt((
+                parse_quick_fun_stmt,
+                parse_fun_stmt,
+                parse_enum_stmt,
+                parse_struct_stmt,
+            ))),
+        ),
         context("if let", parse_if_let_stmt),
         context("if", parse_if_stmt),
         context("when", parse_when_stmt),
         context("match", parse_match_stmt),
+        context("while let", parse_while_let_stmt),
         context("while", parse_while_stmt),
         context("for", parse_for_stmt),
         context("function", parse_quick_fun_stmt),
@@ -2058,48 +2261,117 @@ fn parse_module_file_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = tag(";")(input)?;
     let (input, _) = whitespace(input)?;
     trace!("Parsed module file stmt for {name}");
-    // Open the file
-    if let Ok(contents) = std::fs::read_to_string(format!("{}.sg", name)) {
-        save_source_code_setup();
-        setup_source_code_locations(&contents.clone(), Some(name.to_string()));
-        if let Ok((new_input, module)) =
-            parse_module_contents::<VerboseError<&str>>(name, &contents, true)
-        {
-            if !new_input.is_empty() {
-                return Err(nom::Err::Error(E::from_error_kind(
-                    input,
-                    ErrorKind::Verify,
-                )));
-            }
-            restore_source_code_setup();
-            return Ok((input, Statement::Declaration(module, None)));
-        } else {
-            restore_source_code_setup();
-            return Err(nom::Err::Error(E::from_error_kind(
+    match load_module_file(name) {
+        Ok(module) => Ok((input, Statement::Declaration(module, None))),
+        Err(err) => {
+            // The module can't be parsed any other way, so stop parsing and report why.
+            *MODULE_ERROR.write().unwrap() = Some(err);
+            Err(nom::Err::Failure(E::from_error_kind(
                 input,
                 ErrorKind::Verify,
-            )));
+            )))
         }
     }
+}
 
+/// Load the module declared with `mod name;` from its file. The file is found in the
+/// directory of the module declaring it, as either `name.sg` or `name/mod.sg`, and the
+/// modules it declares are found in the `name` directory. So, the files of a project
+/// are laid out like its modules, starting from the file being compiled.
+fn load_module_file(name: &str) -> Result<Declaration, String> {
+    let dir = MODULE_DIRS
+        .read()
+        .unwrap()
+        .last()
+        .cloned()
+        .unwrap_or_default();
+    let candidates = [
+        dir.join(format!("{name}.sg")),
+        dir.join(name).join("mod.sg"),
+    ];
+    let path = candidates
+        .iter()
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            format!(
+                "[E0101] could not find the file for module `{name}`, expected `{}` or `{}`",
+                candidates[0].display(),
+                candidates[1].display()
+            )
+        })?;
+
+    // Parsing can backtrack over a `mod` statement, so the modules are only parsed once.
+    let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if let Some(module) = MODULE_FILES.read().unwrap().get(&key) {
+        return Ok(module.clone());
+    }
+
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        format!(
+            "[E0102] could not read module file `{}`: {e}",
+            path.display()
+        )
+    })?;
+    let contents = super::without_comments(contents);
+    save_source_code_setup();
+    setup_source_code_locations(&contents, Some(path.display().to_string()));
+    MODULE_DIRS.write().unwrap().push(dir.join(name));
+    let result = parse_module_contents::<VerboseError<&str>>(name, &contents, true);
+    MODULE_DIRS.write().unwrap().pop();
     restore_source_code_setup();
-    Err(nom::Err::Error(E::from_error_kind(
-        input,
-        ErrorKind::Verify,
-    )))
+
+    let module = match result {
+        Ok((rest, module)) if rest.trim().is_empty() => module,
+        // A module declared inside this one failed to load.
+        _ if MODULE_ERROR.read().unwrap().is_some() => {
+            return Err(MODULE_ERROR.write().unwrap().take().unwrap())
+        }
+        Ok((rest, _)) => {
+            let e = VerboseError::from_error_kind(rest, ErrorKind::Verify);
+            return Err(format!(
+                "[E0103] in module file `{}`:\n{}",
+                path.display(),
+                convert_error(contents.as_str(), e)
+            ));
+        }
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            return Err(format!(
+                "[E0103] in module file `{}`:\n{}",
+                path.display(),
+                convert_error(contents.as_str(), e)
+            ))
+        }
+        Err(nom::Err::Incomplete(_)) => unreachable!(),
+    };
+    MODULE_FILES.write().unwrap().insert(key, module.clone());
+    Ok(module)
 }
 
 fn parse_decl<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Declaration, E> {
     let (input, _) = whitespace(input)?;
+    let start = input;
     let (input, decl) = alt((
+        context(
+            "attributes",
+            parse_attributed_stmt(alt((
+                parse_fun_stmt,
+                parse_type_stmt,
+                parse_enum_stmt,
+                parse_struct_stmt,
+            ))),
+        ),
         context("function", parse_fun_stmt),
         context("type", parse_type_stmt),
         context("enum", parse_enum_stmt),
         context("struct", parse_struct_stmt),
         context("extern", terminated(parse_extern_stmt, tag(";"))),
         context("const", terminated(parse_const_stmt, tag(";"))),
+        context(
+            "static_assert",
+            terminated(parse_static_assert_stmt, tag(";")),
+        ),
         context("impl", parse_impl_stmt),
         context("import", parse_import_stmt),
         context("module", parse_module_stmt),
@@ -2107,7 +2379,7 @@ fn parse_decl<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     ))(input)?;
 
     match decl {
-        Statement::Declaration(decl, _) => Ok((input, decl)),
+        Statement::Declaration(decl, _) => Ok((input, with_doc_comment(decl, start))),
         _ => unreachable!(),
     }
 }
@@ -2196,6 +2468,26 @@ fn parse_if_let_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     Ok((input, Statement::Expr(expr)))
 }
 
+fn parse_while_let_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Statement, E> {
+    let (input, _) = tag("while")(input)?;
+    let (input, _) = whitespace(input)?;
+    // Require a space after `let`, so `while letter < 10` is still a `while` loop.
+    let (input, _) = terminated(tag("let"), multispace1)(input)?;
+    let (input, pattern) = cut(parse_pattern)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("=")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, value) = cut(parse_expr)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, body) = cut(parse_block)(input)?;
+    Ok((
+        input,
+        Statement::Expr(Expr::WhileLet(pattern, value.into(), Box::new(body))),
+    ))
+}
+
 fn parse_when_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Statement, E> {
@@ -2574,6 +2866,27 @@ fn parse_const_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     ))
 }
 
+fn parse_static_assert_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Statement, E> {
+    // "static_assert" "(" <cond: ConstExpr> "," <message: String> ")"
+    let (input, _) = tag("static_assert")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, cond) = cut(parse_const)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag(","))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, message) = cut(parse_string_literal)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag(")"))(input)?;
+    Ok((
+        input,
+        Statement::Declaration(Declaration::StaticAssert(cond, message), None),
+    ))
+}
+
 fn parse_pattern_var_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Statement, E> {
@@ -2689,7 +3002,7 @@ fn parse_type_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 
     Ok((
         input,
-        Statement::Declaration(Declaration::Type(name.to_owned(), ty), None),
+        Statement::Declaration(Declaration::Type(name.to_owned(), ty, vec![]), None),
     ))
 }
 
@@ -2746,6 +3059,7 @@ fn parse_struct_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
                         params,
                         Type::Struct(fields).into(),
                     ),
+                    vec![],
                 ),
                 None,
             ),
@@ -2754,7 +3068,7 @@ fn parse_struct_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
         Ok((
             input,
             Statement::Declaration(
-                Declaration::Type(name.to_owned(), Type::Struct(fields)),
+                Declaration::Type(name.to_owned(), Type::Struct(fields), vec![]),
                 None,
             ),
         ))
@@ -2771,27 +3085,27 @@ fn parse_enum_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     // Get the template params
     let (input, template_params) = opt(parse_type_params)(input)?;
 
+    let (input, _) = whitespace(input)?;
+    // Get the number of cells in the tag
+    let (input, tag_cells) = opt(parse_enum_tag_cells)(input)?;
+
     let (input, _) = whitespace(input)?;
     let (input, _) = tag("{")(input)?;
 
     let (input, _) = whitespace(input)?;
-    // Parse a comma separated list of symbols, optionally followed by a type
-    let (input, mut fields) = many0(terminated(
-        preceded(whitespace, pair(parse_symbol, opt(parse_type))),
+    // Parse a comma separated list of symbols, optionally followed by a type and a tag value
+    let (input, mut variants) = many0(terminated(
+        preceded(whitespace, parse_enum_variant),
         terminated(tag(","), whitespace),
     ))(input)?;
 
-    let (input, last) = opt(preceded(whitespace, pair(parse_symbol, opt(parse_type))))(input)?;
+    let (input, last) = opt(preceded(whitespace, parse_enum_variant))(input)?;
 
-    if let Some((k, v)) = last {
-        fields.push((k, v));
+    if let Some(variant) = last {
+        variants.push(variant);
     }
 
-    // For all the fields that don't have a type, assign them the "None" type
-    let fields = fields
-        .into_iter()
-        .map(|(k, v)| (k.to_owned(), v.unwrap_or(Type::None)))
-        .collect();
+    let ty = make_enum_type(variants, tag_cells);
     // trace!("Fields: {fields}");
     // trace!("Template params: {template_params}");
     let (input, _) = whitespace(input)?;
@@ -2805,8 +3119,9 @@ fn parse_enum_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
                     name.to_owned(),
                     Type::Poly(
                         params,
-                        Type::EnumUnion(fields).into(),
+                        ty.into(),
                     ),
+                    vec![],
                 ),
                 None,
             ),
@@ -2815,13 +3130,53 @@ fn parse_enum_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
         Ok((
             input,
             Statement::Declaration(
-                Declaration::Type(name.to_owned(), Type::EnumUnion(fields)),
+                Declaration::Type(name.to_owned(), ty, vec![]),
                 None,
             ),
         ))
     }
 }
 
+/// Parse a variant of an enum, with an optional type and an optional tag value.
+/// For example, `Some(Int) = 1`.
+fn parse_enum_variant<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, (&'a str, Option<Type>, Option<i64>), E> {
+    let (input, name) = parse_symbol(input)?;
+    let (input, ty) = opt(parse_type)(input)?;
+    let (input, tag_value) = opt(preceded(pair(whitespace, tag("=")), parse_int_literal))(input)?;
+    Ok((input, (name, ty, tag_value)))
+}
+
+/// Parse the number of cells taken up by the tag of an enum, like `: 2` in `enum Op: 2 { ... }`.
+fn parse_enum_tag_cells<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, i64, E> {
+    preceded(pair(tag(":"), whitespace), parse_int_literal)(input)
+}
+
+/// Build an enum type from its parsed variants, and the number of cells in its tag.
+fn make_enum_type(
+    variants: Vec<(&str, Option<Type>, Option<i64>)>,
+    tag_cells: Option<i64>,
+) -> Type {
+    let tags = variants
+        .iter()
+        .filter_map(|(name, _, tag_value)| Some((name.to_string(), (*tag_value)?)))
+        .collect::<Vec<_>>();
+    // A negative width is left as zero, so that it's reported when the type is checked.
+    let tag_cells = tag_cells.map_or(1, |n| usize::try_from(n).unwrap_or(0));
+
+    // For all the variants that don't have a type, assign them the "None" type
+    Type::EnumUnion(
+        variants
+            .into_iter()
+            .map(|(name, ty, _)| (name.to_owned(), ty.unwrap_or(Type::None)))
+            .collect(),
+        EnumRepr::new(tags, tag_cells),
+    )
+}
+
 fn parse_return_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Statement, E> {
@@ -2939,8 +3294,10 @@ fn parse_short_stmt<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     start_source_code_tracking(input);
 
     let (input, stmt) = alt((
+        context("attributes", parse_attributed_stmt(parse_type_stmt)),
         context("extern", parse_extern_stmt),
         context("const", parse_const_stmt),
+        context("static_assert", parse_static_assert_stmt),
         context("let", parse_var_stmt),
         context("let", parse_pattern_var_stmt),
         context("let static", parse_static_var_stmt),
@@ -2985,8 +3342,19 @@ fn parse_type_array<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = whitespace(input)?;
     let (input, size) = parse_const(input)?;
     let (input, _) = whitespace(input)?;
+    // The dimensions of a multi-dimensional array, like `[Int * 3, 4]`,
+    // which is an array of 3 arrays of 4 integers.
+    let (input, sizes) = many0(delimited(
+        pair(tag(","), whitespace),
+        parse_const,
+        whitespace,
+    ))(input)?;
     let (input, _) = tag("]")(input)?;
-    Ok((input, Type::Array(Box::new(ty), Box::new(size))))
+    let ty = std::iter::once(size)
+        .chain(sizes)
+        .rev()
+        .fold(ty, |ty, size| Type::Array(Box::new(ty), Box::new(size)));
+    Ok((input, ty))
 }
 
 fn parse_type_tuple<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
@@ -3048,29 +3416,25 @@ fn parse_type_enum<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     // Optionally check for "enum" keyword
     let (input, _) = opt(tag("enum"))(input)?;
     let (input, _) = whitespace(input)?;
+    // Get the number of cells in the tag
+    let (input, tag_cells) = opt(parse_enum_tag_cells)(input)?;
+    let (input, _) = whitespace(input)?;
     let (input, _) = tag("{")(input)?;
 
     let (input, _) = whitespace(input)?;
-    // Parse a comma separated list of symbols, optionally followed by a type
-    let (input, mut fields) =
-        many0(terminated(pair(parse_symbol, opt(parse_type)), tag(",")))(input)?;
+    // Parse a comma separated list of symbols, optionally followed by a type and a tag value
+    let (input, mut variants) = many0(terminated(parse_enum_variant, tag(",")))(input)?;
 
-    let (input, last) = opt(pair(parse_symbol, opt(parse_type)))(input)?;
+    let (input, last) = opt(parse_enum_variant)(input)?;
 
-    if let Some((k, v)) = last {
-        fields.push((k, v));
+    if let Some(variant) = last {
+        variants.push(variant);
     }
 
-    // For all the fields that don't have a type, assign them the "None" type
-    let fields = fields
-        .into_iter()
-        .map(|(k, v)| (k.to_owned(), v.unwrap_or(Type::None)))
-        .collect();
-
     let (input, _) = whitespace(input)?;
     let (input, _) = tag("}")(input)?;
 
-    Ok((input, Type::EnumUnion(fields)))
+    Ok((input, make_enum_type(variants, tag_cells)))
 }
 
 fn parse_type_params<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
@@ -3424,9 +3788,20 @@ fn parse_expr_index<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = whitespace(input)?;
     let (input, index) = parse_expr(input)?;
     let (input, _) = whitespace(input)?;
+    // Indexing a multi-dimensional array, like `a[i, j]`, is the same as `a[i][j]`.
+    let (input, indices) = many0(delimited(
+        pair(tag(","), whitespace),
+        parse_expr,
+        whitespace,
+    ))(input)?;
     let (input, _) = cut(tag("]"))(input)?;
 
-    Ok((input, expr.clone().idx(index)))
+    Ok((
+        input,
+        indices
+            .into_iter()
+            .fold(expr.clone().idx(index), |expr, index| expr.idx(index)),
+    ))
 }
 
 fn parse_expr_cast<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
@@ -3459,6 +3834,17 @@ fn parse_expr_call<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
         args.push(last_arg);
     }
 
+    // Deserializing takes the type to deserialize as a type argument.
+    if let Expr::ConstExpr(ConstExpr::Monomorphize(template, ty_args)) = expr {
+        if matches!(&**template, ConstExpr::Symbol(name) if name == "deserialize")
+            && ty_args.len() == 1
+            && args.len() == 1
+        {
+            let ty = Expr::ConstExpr(ConstExpr::Type(ty_args[0].clone()));
+            return Ok((input, args.remove(0).binop(FromCells, ty)));
+        }
+    }
+
     if let Expr::ConstExpr(ConstExpr::Symbol(name)) = expr {
         // Ok((input, Expr::var(name).app(args)))
         match name.as_str() {
@@ -3476,6 +3862,114 @@ fn parse_expr_call<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
                     Expr::Many(args.into_iter().map(|x| x.print()).collect()),
                 ));
             }
+            "panic" if args.len() == 1 => {
+                return Ok((input, args.remove(0).unop(Panic)));
+            }
+            "assert" if args.len() == 2 => {
+                let msg = args.remove(1);
+                return Ok((input, args.remove(0).binop(Assert, msg)));
+            }
+            "atomic_load" if args.len() == 1 => {
+                return Ok((input, args.remove(0).unop(AtomicLoad)));
+            }
+            "atomic_store" if args.len() == 2 => {
+                let val = args.remove(1);
+                return Ok((input, args.remove(0).binop(Atomic::Store, val)));
+            }
+            "atomic_fetch_add" if args.len() == 2 => {
+                let val = args.remove(1);
+                return Ok((input, args.remove(0).binop(Atomic::FetchAdd, val)));
+            }
+            "derived_eq" | "derived_cmp" if args.len() == 2 => {
+                let derive = if name == "derived_eq" {
+                    Derive::Eq
+                } else {
+                    Derive::Cmp
+                };
+                let rhs = args.remove(1);
+                return Ok((input, args.remove(0).binop(Derived(derive), rhs)));
+            }
+            "derived_hash" if args.len() == 1 => {
+                return Ok((input, args.remove(0).unop(DerivedHash)));
+            }
+            "serialize" if args.len() == 1 => {
+                return Ok((input, args.remove(0).unop(ToCells)));
+            }
+            "alloca" if args.len() == 1 => {
+                return Ok((input, args.remove(0).unop(StackAlloc)));
+            }
+            "atomic_compare_swap" if args.len() == 3 => {
+                let new = args.remove(2);
+                let expected = args.remove(1);
+                return Ok((
+                    input,
+                    Expr::TernaryOp(
+                        AtomicCompareSwap.to_string(),
+                        Box::new(args.remove(0)),
+                        Box::new(expected),
+                        Box::new(new),
+                    ),
+                ));
+            }
+            op if args.len() == 2
+                && OverflowArithmetic::all()
+                    .iter()
+                    .any(|overflow_op| overflow_op.to_string() == op) =>
+            {
+                let rhs = args.remove(1);
+                return Ok((input, args.remove(0).binop(op, rhs)));
+            }
+            "eprint" | "eprintln" => {
+                if name == "eprintln" {
+                    args.push(Expr::ConstExpr(ConstExpr::Char('\n')));
+                }
+                return Ok((
+                    input,
+                    Expr::Many(args.into_iter().map(|x| x.unop(Put::DisplayErr)).collect()),
+                ));
+            }
+            name if !args.is_empty() && LogLevel::from_builtin(name).is_some() => {
+                let level = LogLevel::from_builtin(name).unwrap();
+                return Ok((input, Expr::log(level, args)));
+            }
+            "printf" if !args.is_empty() => {
+                let fmt = args.remove(0);
+                return Ok((input, fmt.binop(Format, Expr::Tuple(args))));
+            }
+            "print_to" if args.len() == 2 => {
+                let val = args.remove(1);
+                return Ok((input, args.remove(0).binop(PutTo(Put::Display), val)));
+            }
+            "input_from" if args.len() == 2 => {
+                let ptr = args.remove(1);
+                return Ok((input, args.remove(0).binop(GetFrom, ptr)));
+            }
+            "poll_key" if args.is_empty() => {
+                return Ok((input, Expr::poll_key(0)));
+            }
+            "terminal_width" if args.is_empty() => {
+                return Ok((input, Expr::terminal_size(0).0));
+            }
+            "terminal_height" if args.is_empty() => {
+                return Ok((input, Expr::terminal_size(0).1));
+            }
+            "set_pixel" if args.len() == 3 => {
+                let color = args.remove(2);
+                let y = args.remove(1);
+                return Ok((input, Expr::set_pixel(0, args.remove(0), y, color)));
+            }
+            "clear_display" if args.len() == 1 => {
+                return Ok((input, Expr::clear_display(0, args.remove(0))));
+            }
+            "present" if args.is_empty() => {
+                return Ok((input, Expr::present(0)));
+            }
+            "play_sample" if args.len() == 1 => {
+                return Ok((input, Expr::play_sample(0, args.remove(0))));
+            }
+            "set_sample_rate" if args.len() == 1 => {
+                return Ok((input, Expr::set_sample_rate(0, args.remove(0))));
+            }
             "println" => {
                 // return Ok((input, args.println()))
                 return Ok((
@@ -3527,18 +4021,29 @@ fn parse_expr_atom<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
         map(parse_const_atom, Expr::ConstExpr),
         parse_expr_tuple,
         parse_expr_group,
+        parse_expr_array_repeat,
         parse_expr_array,
         parse_expr_struct,
         parse_block,
         parse_if_let_expr,
         parse_if_expr,
         parse_match_expr,
+        parse_expr_hole,
         map(parse_type_atom, |t| ConstExpr::Type(t).into()),
     ))(input)?;
 
     Ok((input, expr))
 }
 
+fn parse_expr_hole<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Expr, E> {
+    // A typed hole: `?` followed by an optional name.
+    let (input, _) = tag("?")(input)?;
+    let (input, name) = opt(parse_symbol)(input)?;
+    Ok((input, Expr::hole(name)))
+}
+
 fn parse_expr_group<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Expr, E> {
@@ -3569,6 +4074,23 @@ fn parse_expr_tuple<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     Ok((input, Expr::Tuple(exprs)))
 }
 
+fn parse_expr_array_repeat<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, Expr, E> {
+    // An array of copies of an element: `[elem; len]`.
+    let (input, _) = tag("[")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, elem) = parse_expr(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(";")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, len) = parse_const(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("]")(input)?;
+
+    Ok((input, Expr::ArrayRepeat(elem.into(), len.into())))
+}
+
 fn parse_expr_array<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, Expr, E> {
@@ -3595,6 +4117,13 @@ fn parse_expr_struct<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = whitespace(input)?;
     let (input, _) = tag("{")(input)?;
 
+    let (input, _) = whitespace(input)?;
+    // Check for a base struct to copy the remaining fields from.
+    let (input, base) = opt(terminated(
+        preceded(tag(".."), parse_expr),
+        opt(preceded(whitespace, tag(","))),
+    ))(input)?;
+
     let (input, _) = whitespace(input)?;
     let (input, mut fields) = many0(terminated(
         alt((
@@ -3623,6 +4152,16 @@ fn parse_expr_struct<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     let (input, _) = whitespace(input)?;
     let (input, _) = tag("}")(input)?;
 
+    if let Some(base) = base {
+        return Ok((
+            input,
+            Expr::struct_update(
+                base,
+                fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect(),
+            ),
+        ));
+    }
+
     Ok((
         input,
         Expr::Struct(fields.into_iter().map(|(k, v)| (k.to_owned(), v)).collect()),
@@ -3745,23 +4284,20 @@ fn parse_const_atom<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     alt((
         parse_const_sizeof_expr,
         parse_const_sizeof_type,
+        parse_const_alignof_type,
+        parse_const_offsetof,
+        parse_const_reflect,
+        parse_const_cfg,
         parse_const_tuple,
         parse_const_group,
         parse_const_bool,
         parse_const_null,
         parse_const_none,
-        map(parse_char_literal, ConstExpr::Char),
+        parse_char_literal,
         map(parse_float_literal, ConstExpr::Float),
         map(parse_int_literal, ConstExpr::Int),
-        map(parse_string_literal, |s| {
-            ConstExpr::Array(
-                s.to_string()
-                    .chars()
-                    .map(ConstExpr::Char)
-                    .chain(std::iter::once(ConstExpr::Char('\0')))
-                    .collect(),
-            )
-        }),
+        parse_const_string,
+        parse_const_table,
         parse_const_array,
         parse_const_struct,
         map(parse_symbol, |x| ConstExpr::Symbol(x.to_string())),
@@ -3826,6 +4362,10 @@ fn parse_float_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
 
     // Use builtin nom double
     let (input, result) = nom::number::complete::recognize_float(input)?;
+    // An integer followed by `..` is the start of a range, not a float.
+    if result.ends_with('.') && input.starts_with('.') {
+        return Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Float)));
+    }
     // Try to parse as an integer first
     let result: f64 = if let Ok(_i) = result.parse::<i64>() {
         // Fail
@@ -3841,241 +4381,65 @@ fn parse_float_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     }
 }
 
-/// Parse a unicode sequence, of the form u{XXXX}, where XXXX is 1 to 6
-/// hexadecimal numerals. We will combine this later with parse_escaped_char
-/// to parse sequences like \u{00AC}.
-fn parse_unicode<'a, E>(input: &'a str) -> IResult<&'a str, char, E>
-where
-    E: ParseError<&'a str>,
-{
-    // `take_while_m_n` parses between `m` and `n` bytes (inclusive) that match
-    // a predicate. `parse_hex` here parses between 1 and 6 hexadecimal numerals.
-    let parse_hex = take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit());
-
-    // `preceded` takes a prefix parser, and if it succeeds, returns the result
-    // of the body parser. In this case, it parses u{XXXX}.
-    let parse_delimited_hex = preceded(
-        char('u'),
-        // `delimited` is like `preceded`, but it parses both a prefix and a suffix.
-        // It returns the result of the middle parser. In this case, it parses
-        // {XXXX}, where XXXX is 1 to 6 hex numerals, and returns XXXX
-        delimited(char('{'), parse_hex, char('}')),
-    );
-
-    // `map_res` takes the result of a parser and applies a function that returns
-    // a Result. In this case we take the hex bytes from parse_hex and attempt to
-    // convert them to a u32.
-    let parse_u32 = map(parse_delimited_hex, move |hex| {
-        u32::from_str_radix(hex, 16).unwrap()
-    });
-
-    // map_opt is like map_res, but it takes an Option instead of a Result. If
-    // the function returns None, map_opt returns an error. In this case, because
-    // not all u32 values are valid unicode code points, we have to fallibly
-    // convert to char with from_u32.
-    map_opt(parse_u32, std::char::from_u32).parse(input)
-}
-
-/// Parse an escaped character: \n, \t, \r, \u{00AC}, etc.
-fn parse_escaped_char<'a, E>(input: &'a str) -> IResult<&'a str, char, E>
-where
-    E: ParseError<&'a str>,
-{
-    preceded(
-        char('\\'),
-        // `alt` tries each parser in sequence, returning the result of
-        // the first successful match
-        alt((
-            parse_unicode,
-            // The `value` parser returns a fixed value (the first argument) if its
-            // parser (the second argument) succeeds. In these cases, it looks for
-            // the marker characters (n, r, t, etc) and returns the matching
-            // character (\n, \r, \t, etc).
-            value('\0', char('0')),
-            value('\n', char('n')),
-            value('\r', char('r')),
-            value('\t', char('t')),
-            value('\u{08}', char('b')),
-            value('\u{0C}', char('f')),
-            value('\\', char('\\')),
-            value('/', char('/')),
-            value('"', char('"')),
-            value('\'', char('\'')),
-            // Parse an \x followed by two hex digits, and convert it to a char
-            map(
-                preceded(char('x'), take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit())),
-                |hex| u8::from_str_radix(hex, 16).unwrap() as char,
-            ),
-            // Parse an \u followed by four hex digits, and convert it to a char
-            // map(
-            //     preceded(char('u'), take_while_m_n(4, 4, |c: char| c.is_ascii_hexdigit())),
-            //     |hex| char::from_u32(u32::from_str_radix(hex, 16).unwrap()).unwrap(),
-            // ),
-        )),
-    )
-    .parse(input)
-}
-
-/// Parse a backslash, followed by any amount of whitespace. This is used later
-/// to discard any escaped whitespace.
-fn parse_escaped_whitespace<'a, E: ParseError<&'a str>>(
-    input: &'a str,
-) -> IResult<&'a str, &'a str, E> {
-    preceded(char('\\'), multispace1).parse(input)
-}
-
-/// Parse a non-empty block of text that doesn't include \ or "
-fn parse_literal_intermediate<'a, E: ParseError<&'a str>>(
-    input: &'a str,
-) -> IResult<&'a str, &'a str, E> {
-    // `is_not` parses a string of 0 or more characters that aren't one of the
-    // given characters.
-    let not_quote_slash = is_not("\"\\");
-
-    // `verify` runs a parser, then runs a verification function on the output of
-    // the parser. The verification function accepts out output only if it
-    // returns true. In this case, we want to ensure that the output of is_not
-    // is non-empty.
-    verify(not_quote_slash, |s: &str| !s.is_empty()).parse(input)
+/// Parse the text between the quotes of a character or string literal,
+/// leaving its escape sequences as they are.
+fn parse_raw_literal<'a, E: ParseError<&'a str>>(
+    quote: char,
+) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str, E> {
+    move |input| {
+        delimited(
+            char(quote),
+            recognize(many0_count(alt((
+                preceded(char('\\'), anychar),
+                satisfy(move |c| c != quote && c != '\\'),
+            )))),
+            cut(char(quote)),
+        )
+        .parse(input)
+    }
 }
 
-/// Parse a non-empty block of text that doesn't include \ or "
-fn parse_literal_intermediate_char<'a, E: ParseError<&'a str>>(
+/// Report an invalid literal, pointing at the start of the literal.
+fn invalid_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
-) -> IResult<&'a str, &'a str, E> {
-    // `is_not` parses a string of 0 or more characters that aren't one of the
-    // given characters.
-    let not_quote_slash = is_not("\'\\");
-
-    // `verify` runs a parser, then runs a verification function on the output of
-    // the parser. The verification function accepts out output only if it
-    // returns true. In this case, we want to ensure that the output of is_not
-    // is non-empty.
-    verify(not_quote_slash, |s: &str| !s.is_empty()).parse(input)
-}
-
-/// A string fragment contains a fragment of a string being parsed: either
-/// a non-empty Literal (a series of non-escaped characters), a single
-/// parsed escaped character, or a block of escaped whitespace.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum StringFragment<'a> {
-    Literal(&'a str),
-    EscapedChar(char),
-    EscapedWS,
-}
-
-/// Combine parse_literal, parse_escaped_whitespace, and parse_escaped_char
-/// into a StringFragment.
-fn parse_fragment_str<'a, E>(input: &'a str) -> IResult<&'a str, StringFragment<'a>, E>
-where
-    E: ParseError<&'a str>,
-{
-    alt((
-        // The `map` combinator runs a parser, then applies a function to the output
-        // of that parser.
-        map(parse_literal_intermediate, StringFragment::Literal),
-        map(parse_escaped_char, StringFragment::EscapedChar),
-        value(StringFragment::EscapedWS, parse_escaped_whitespace),
+    err: Error,
+) -> nom::Err<E> {
+    error!("{err}");
+    nom::Err::Failure(E::add_context(
+        input,
+        "invalid literal",
+        E::from_error_kind(input, ErrorKind::Char),
     ))
-    .parse(input)
 }
 
-fn parse_fragment_char<'a, E>(input: &'a str) -> IResult<&'a str, StringFragment, E>
-where
-    E: ParseError<&'a str>,
-{
-    alt((
-        // The `map` combinator runs a parser, then applies a function to the output
-        // of that parser.
-        map(parse_literal_intermediate_char, StringFragment::Literal),
-        map(parse_escaped_char, StringFragment::EscapedChar),
-        value(StringFragment::EscapedWS, parse_escaped_whitespace),
-    ))
-    .parse(input)
-}
-
-/// Parse a string. Use a loop of parse_fragment and push all of the fragments
-/// into an output string.
-fn parse_string<'a, E>(input: &'a str) -> IResult<&'a str, String, E>
-where
-    E: ParseError<&'a str> + FromExternalError<&'a str, std::num::ParseIntError>,
-{
-    // fold is the equivalent of iterator::fold. It runs a parser in a loop,
-    // and for each output value, calls a folding function on each output value.
-    let build_string = fold_many0(
-        // Our parser function – parses a single string fragment
-        parse_fragment_str,
-        // Our init value, an empty string
-        String::new,
-        // Our folding function. For each fragment, append the fragment to the
-        // string.
-        |mut string, fragment| {
-            match fragment {
-                StringFragment::Literal(s) => string.push_str(s),
-                StringFragment::EscapedChar(c) => string.push(c),
-                StringFragment::EscapedWS => {}
-            }
-            string
-        },
-    );
-
-    // Finally, parse the string. Note that, if `build_string` could accept a raw
-    // " character, the closing delimiter " would never match. When using
-    // `delimited` with a looping parser (like fold), be sure that the
-    // loop won't accidentally match your closing delimiter!
-    delimited(char('"'), build_string, char('"')).parse(input)
+fn parse_char_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    let (input, _) = whitespace(input)?;
+    let (rest, literal) = parse_raw_literal('\'')(input)?;
+    match ConstExpr::char_literal(literal) {
+        Ok(result) => Ok((rest, result)),
+        Err(err) => Err(invalid_literal(input, err)),
+    }
 }
 
-fn parse_char_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+fn parse_const_string<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
-) -> IResult<&'a str, char, E> {
-    let (input, _) = whitespace(input)?;
-    // fold is the equivalent of iterator::fold. It runs a parser in a loop,
-    // and for each output value, calls a folding function on each output value.
-    let build_string = fold_many0(
-        // Our parser function – parses a single string fragment
-        parse_fragment_char,
-        // Our init value, an empty string
-        String::new,
-        // Our folding function. For each fragment, append the fragment to the
-        // string.
-        |mut string, fragment| {
-            match fragment {
-                StringFragment::Literal(s) => string.push_str(s),
-                StringFragment::EscapedChar(c) => string.push(c),
-                StringFragment::EscapedWS => {}
-            }
-            string
-        },
-    );
-
-    // Finally, parse the string. Note that, if `build_string` could accept a raw
-    // " character, the closing delimiter " would never match. When using
-    // `delimited` with a looping parser (like fold), be sure that the
-    // loop won't accidentally match your closing delimiter!
-    let (input, result) = delimited(char('\''), cut(build_string), cut(char('\''))).parse(input)?;
-    if result.len() != 1 {
-        error!("Invalid char literal: {result}");
-        return Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Digit)));
+) -> IResult<&'a str, ConstExpr, E> {
+    let (rest, literal) = parse_raw_literal('"')(input)?;
+    match ConstExpr::string_literal(literal) {
+        Ok(result) => Ok((rest, result)),
+        Err(err) => Err(invalid_literal(input, err)),
     }
-    Ok((input, result.chars().next().unwrap()))
 }
 
 fn parse_string_literal<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, String, E> {
-    // map(context(
-    //   "string",
-    //   alt((
-    //         // preceded(char('\''), cut(terminated(parse_inner_str_single, char('\'')))),
-    //         preceded(char('"'), cut(terminated(parse_inner_str_double, char('"')))),
-    //   )),
-    // ), |s| s.to_string())(input)
-
-    if let Ok((input, s)) = parse_string::<VerboseError<&str>>(input) {
-        Ok((input, s))
-    } else {
-        Err(nom::Err::Error(E::from_error_kind(input, ErrorKind::Digit)))
+    let (rest, literal) = parse_raw_literal('"')(input)?;
+    match unescape(literal) {
+        Ok(result) => Ok((rest, result)),
+        Err(err) => Err(invalid_literal(input, err)),
     }
 }
 
@@ -4162,6 +4526,92 @@ fn parse_const_sizeof_type<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     Ok((input, ConstExpr::SizeOfType(ty)))
 }
 
+fn parse_const_alignof_type<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    let (input, _) = tag("alignof")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("<")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, ty) = parse_type(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(">")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(")")(input)?;
+
+    Ok((input, ConstExpr::AlignOfType(ty)))
+}
+
+fn parse_const_offsetof<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    let (input, _) = tag("offsetof")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("<")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, ty) = parse_type(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(">")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, member) = alt((
+        map(parse_int_literal, ConstExpr::Int),
+        map(parse_symbol, |x| ConstExpr::Symbol(x.to_string())),
+    ))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(")")(input)?;
+
+    Ok((input, ConstExpr::OffsetOf(ty, Box::new(member))))
+}
+
+fn parse_const_reflect<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    // ("typename" | "fieldnames" | "fieldoffsets" | "variantnames") "<" <ty: Type> ">" "(" ")"
+    let (input, name) = alt((
+        tag("typename"),
+        tag("fieldnames"),
+        tag("fieldoffsets"),
+        tag("variantnames"),
+    ))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("<")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, ty) = parse_type(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(">")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag(")")(input)?;
+
+    let reflection = Reflection::from_name(name).unwrap();
+    Ok((input, ConstExpr::Reflect(ty, reflection)))
+}
+
+fn parse_const_cfg<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    // "cfg" "(" <name: Symbol> ("=" <value: String>)? ")"
+    let (input, _) = tag("cfg")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("(")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, name) = cut(parse_symbol)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, value) = opt(preceded(
+        tag("="),
+        preceded(whitespace, cut(parse_string_literal)),
+    ))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag(")"))(input)?;
+
+    Ok((input, ConstExpr::Cfg(name.to_owned(), value)))
+}
+
 fn parse_const_tuple<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     input: &'a str,
 ) -> IResult<&'a str, ConstExpr, E> {
@@ -4198,6 +4648,41 @@ fn parse_const_array<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
     Ok((input, ConstExpr::Array(exprs)))
 }
 
+fn parse_const_table<'a, E: ParseError<&'a str> + ContextError<&'a str>>(
+    input: &'a str,
+) -> IResult<&'a str, ConstExpr, E> {
+    // "[" "for" <name: Symbol> "in" <start: ConstExpr> ".." <end: ConstExpr> "=>" <elem: Expr> "]"
+    let (input, _) = tag("[")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = tag("for")(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, name) = cut(parse_symbol)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag("in"))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, start) = cut(parse_const)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag(".."))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, end) = cut(parse_const)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag("=>"))(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, elem) = cut(parse_expr)(input)?;
+    let (input, _) = whitespace(input)?;
+    let (input, _) = cut(tag("]"))(input)?;
+
+    Ok((
+        input,
+        ConstExpr::Table(
+            name.to_owned(),
+            Box::new(start),
+            Box::new(end),
+            Box::new(elem),
+        ),
+    ))
+}
+
 fn is_symbol_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
 }
@@ -4447,6 +4932,12 @@ mod tests {
 
         assert_parse_const("1.0", Some(ConstExpr::Float(1.0)));
         assert_parse_const("'-'", Some(ConstExpr::Char('-')));
+        assert_parse_const("'\\''", Some(ConstExpr::Char('\'')));
+        assert_parse_const("'\\u{e9}'", Some(ConstExpr::Char('é')));
+        assert_parse_const("'é'", Some(ConstExpr::Char('é')));
+        unassert_parse_const("'ab'");
+        unassert_parse_const("'\\q'");
+        unassert_parse_const("'\\u{110000}'");
 
         assert_parse_const(
             "\"hello\"",
@@ -4471,6 +4962,16 @@ mod tests {
                 ConstExpr::Char('\0'),
             ])),
         );
+        assert_parse_const(
+            "\"\\x41\\u{1F600}\\\"\"",
+            Some(ConstExpr::Array(vec![
+                ConstExpr::Char('A'),
+                ConstExpr::Char('\u{1F600}'),
+                ConstExpr::Char('"'),
+                ConstExpr::Char('\0'),
+            ])),
+        );
+        unassert_parse_const("\"\\xff\"");
 
         assert_parse_const("a", Some(ConstExpr::Symbol("a".to_string())));
         assert_parse_const("_a", Some(ConstExpr::Symbol("_a".to_string())));
@@ -4507,6 +5008,15 @@ mod tests {
             ConstExpr::Int(3),
         ]);
         assert_parse_const("[1, 2, 3]", Some(a.clone()));
+        assert_parse_const(
+            "[for i in 0..N => i * i]",
+            Some(ConstExpr::Table(
+                "i".to_string(),
+                Box::new(ConstExpr::Int(0)),
+                Box::new(ConstExpr::Symbol("N".to_string())),
+                Box::new(Expr::var("i").mul(Expr::var("i"))),
+            )),
+        );
 
         assert_parse_const(
             "std.Option<Int>",
@@ -4522,6 +5032,21 @@ mod tests {
         );
         assert_parse_const("sizeof<Int>()", Some(ConstExpr::SizeOfType(Type::Int)));
         unassert_parse_const("sizeof<Int>(5)");
+        assert_parse_const("alignof<Int>()", Some(ConstExpr::AlignOfType(Type::Int)));
+        assert_parse_const(
+            "offsetof<Point>(y)",
+            Some(ConstExpr::OffsetOf(
+                Type::Symbol("Point".to_string()),
+                Box::new(ConstExpr::Symbol("y".to_string())),
+            )),
+        );
+        assert_parse_const(
+            "fieldnames<Point>()",
+            Some(ConstExpr::Reflect(
+                Type::Symbol("Point".to_string()),
+                Reflection::FieldNames,
+            )),
+        );
         assert_parse_const(
             "Result<Int, String> of Ok(5)",
             Some(ConstExpr::EnumUnion(
@@ -4570,6 +5095,16 @@ mod tests {
                 Box::new(ConstExpr::Int(5)),
             )),
         );
+        assert_parse_type(
+            "[Int * 2, 3]",
+            Some(Type::Array(
+                Box::new(Type::Array(
+                    Box::new(Type::Int),
+                    Box::new(ConstExpr::Int(3)),
+                )),
+                Box::new(ConstExpr::Int(2)),
+            )),
+        );
 
         assert_parse_type(
             "enum {Ok Int, Err &Char}",
@@ -4583,6 +5118,7 @@ mod tests {
                 ]
                 .into_iter()
                 .collect(),
+                EnumRepr::default(),
             )),
         );
 
@@ -4614,6 +5150,7 @@ mod tests {
                 ]
                 .into_iter()
                 .collect(),
+                EnumRepr::default(),
             )),
         );
 
@@ -4997,6 +5534,18 @@ p<Int>(5);
         // Now try calls
         assert_parse_expr("a()", Some(Expr::var("a").app(vec![])));
         assert_parse_expr("a(b)", Some(Expr::var("a").app(vec![Expr::var("b")])));
+        assert_parse_expr(
+            "a[i, j]",
+            Some(Expr::var("a").idx(Expr::var("i")).idx(Expr::var("j"))),
+        );
+        assert_parse_expr(
+            "deserialize<Point>(buf)",
+            Some(Expr::var("buf").binop(
+                FromCells,
+                Expr::ConstExpr(ConstExpr::Type(Type::Symbol("Point".to_string()))),
+            )),
+        );
+        assert_parse_expr("alloca(n)", Some(Expr::var("n").unop(StackAlloc)));
         assert_parse_expr(
             "a.x(b)",
             Some(
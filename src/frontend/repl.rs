@@ -0,0 +1,174 @@
+//! # The REPL
+//!
+//! The REPL evaluates frontend code one input at a time. An input is either more
+//! statements, whose declarations are kept for the inputs after them, or an expression,
+//! whose value is printed with `debug` along with its type.
+//!
+//! The session keeps one environment, one assembly program, and one interpreter for all
+//! of its inputs. Each input is compiled under the environment left by the inputs before
+//! it, and its code is added to the end of the program. Then, the interpreter runs only
+//! the new code, on the tape left behind by the inputs before it. So, the variables the
+//! inputs declare stay on the stack, and the side effects of an input only happen once.
+//! If an input fails to compile or run, the session goes back to how it was before it.
+//!
+//! The program is assembled again for each input, since the new procedures it defines
+//! are moved before the code outside of them. The static variables of the prelude are
+//! allocated when the session starts, and later inputs have `STATIC_ROOM` cells left
+//! over for theirs. The programs run with an empty input buffer, since the REPL reads
+//! the terminal.
+use super::{parse_repl_input, with_prelude, without_comments};
+use crate::{
+    asm::{AssemblyProgram, CoreOp, StandardOp, StandardProgram, FP, SP},
+    lir::{Env, Expr},
+    vm::{StandardInterpreter, TestingDevice},
+};
+use std::collections::BTreeSet;
+
+/// The number of cells reserved for the static variables declared after the prelude.
+const STATIC_ROOM: usize = 1024;
+
+/// What an input printed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Printed {
+    /// What the input wrote to the standard output, including the value of an expression.
+    pub stdout: String,
+    /// What the input wrote to the standard error.
+    pub stderr: String,
+}
+
+/// An interactive session evaluating frontend code.
+pub struct Repl {
+    /// The environment with the declarations of the inputs so far.
+    env: Env,
+    /// The assembly code of the inputs so far.
+    asm: StandardProgram,
+    /// The interpreter which ran the inputs so far, with the state they left behind.
+    interpreter: StandardInterpreter<TestingDevice>,
+    /// The number of instructions outside of any procedure in the last program run.
+    /// The code of the next input comes after them.
+    main_len: usize,
+    /// The names of the static variables declared so far.
+    statics: BTreeSet<String>,
+    /// The number of cells reserved for static variables which haven't been used yet.
+    static_room: usize,
+    /// The number of cells allocated for the call stack.
+    call_stack_size: usize,
+}
+
+impl Repl {
+    /// Start a session, compiling the inputs under the given environment.
+    /// This compiles and runs the prelude, which every input can use.
+    pub fn new(env: Env, call_stack_size: usize) -> Result<Self, String> {
+        let mut asm = StandardProgram::default();
+        // Leave room after the prelude's static variables for those of later inputs.
+        // The inputs' variables are on the stack after the room.
+        asm.op(CoreOp::Next(SP, Some(STATIC_ROOM as isize)));
+        asm.op(CoreOp::Move { src: SP, dst: FP });
+        let mut repl = Self {
+            env,
+            asm,
+            interpreter: StandardInterpreter::new(TestingDevice::new("")),
+            main_len: 0,
+            statics: BTreeSet::new(),
+            static_room: STATIC_ROOM,
+            call_stack_size,
+        };
+        repl.run(with_prelude(Expr::NONE, true, true)?)?;
+        Ok(repl)
+    }
+
+    /// Evaluate an input, returning what it printed. If the input is an expression,
+    /// this includes its value and type. Inputs with errors are discarded, and the
+    /// error includes what they printed before they failed.
+    pub fn eval(&mut self, input: &str) -> Result<Printed, String> {
+        let program = parse_repl_input(&without_comments(input))?;
+        self.run(program)
+    }
+
+    /// Is the input unfinished? An input isn't finished until all of
+    /// its brackets and quotes are closed, so it can span several lines.
+    pub fn is_incomplete(input: &str) -> bool {
+        let mut depth = 0;
+        let mut quote = None;
+        let input = without_comments(input);
+        let mut chars = input.chars();
+        while let Some(ch) = chars.next() {
+            match (quote, ch) {
+                (Some(_), '\\') => {
+                    chars.next();
+                }
+                (Some(open), ch) if ch == open => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(ch),
+                (None, '(' | '[' | '{') => depth += 1,
+                (None, ')' | ']' | '}') => depth -= 1,
+                (None, _) => {}
+            }
+        }
+        depth > 0 || quote.is_some()
+    }
+
+    /// Compile a program after the inputs so far, and run its code on their tape.
+    /// The session is only changed if the program runs successfully.
+    fn run(&mut self, program: Expr) -> Result<Printed, String> {
+        let mut env = self.env.clone();
+        let mut asm = self.asm.clone();
+        let start = asm.code.len();
+        program
+            .compile_top_level(&mut env, &mut asm)
+            .map_err(|e| e.to_string())?;
+        let (statics, static_room) = self.allocate_statics(&asm.code[start..])?;
+        let vm_code = asm
+            .assemble(self.call_stack_size)
+            .map_err(|e| e.to_string())?;
+        // The new code outside of any procedure is at the end of the program.
+        let main_len = vm_code.get_main().len();
+        let resume_at = vm_code.0.len() - (main_len - self.main_len);
+
+        let saved = self.interpreter.clone();
+        let result = self.interpreter.resume(&vm_code, resume_at);
+        let device = self.interpreter.device_mut();
+        let printed = Printed {
+            stdout: device.output_str(),
+            stderr: device.error_str(),
+        };
+        device.output.clear();
+        device.error.clear();
+        match result {
+            Ok(()) => {
+                self.env = env;
+                self.asm = asm;
+                self.main_len = main_len;
+                self.statics = statics;
+                self.static_room = static_room;
+                Ok(printed)
+            }
+            Err(e) => {
+                self.interpreter = saved;
+                Err(format!("{}{}{e}", printed.stdout, printed.stderr))
+            }
+        }
+    }
+
+    /// Find the static variables declared by some new code, and allocate them in the room
+    /// left for them. This returns the static variables declared so far, and the room left.
+    /// The prelude's static variables are allocated with the program, so they don't use any room.
+    fn allocate_statics(&self, code: &[StandardOp]) -> Result<(BTreeSet<String>, usize), String> {
+        let mut statics = self.statics.clone();
+        let mut room = self.static_room;
+        let started = self.main_len > 0;
+        for op in code {
+            if let StandardOp::CoreOp(CoreOp::Global { name, size }) = op {
+                if statics.insert(name.clone()) && started {
+                    room = room.checked_sub(*size).ok_or_else(|| {
+                        format!(
+                            "the REPL has no room left for the static variable `{name}`, \
+                             only {STATIC_ROOM} cells are reserved for static variables"
+                        )
+                    })?;
+                }
+            }
+        }
+        Ok((statics, room))
+    }
+}
@@ -0,0 +1,198 @@
+//! # The Test Runner
+//!
+//! Tests are procedures marked with the `test` attribute, which take no arguments:
+//!
+//! ```text
+//! fun add(a: Int, b: Int): Int {
+//!     return a + b;
+//! }
+//!
+//! #[test]
+//! fun test_add() {
+//!     assert(add(1, 2) == 3, "1 + 2 should be 3");
+//! }
+//! ```
+//!
+//! Each test is compiled into its own program, with the declarations at the top
+//! level of the source code (but none of its statements), which calls the test and
+//! halts. Tests can be declared inside modules too. The programs are run in the
+//! interpreter with a `TestingDevice`, so everything they print is captured.
+//! A test passes if it returns without panicking, or otherwise halting with a
+//! failing exit status.
+//!
+//! The runner also records which lines of the source code each test ran, which
+//! `sage test --coverage` writes as an lcov report.
+use super::{parse_source, with_prelude, without_comments};
+use crate::{
+    lir::{Attribute, Compile, ConstExpr, Declaration, Env, Exit, Expr},
+    testing::Coverage,
+    vm::{CoreInterpreter, Profile, StandardInterpreter, TestingDevice},
+};
+use std::time::{Duration, Instant};
+
+/// The result of running a test.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    /// The path of the test procedure, like `math.test_gcd`.
+    pub name: String,
+    /// Why the test failed, or `None` if it passed.
+    pub failure: Option<String>,
+    /// Everything the test printed to the standard output.
+    pub output: String,
+    /// The lines of the source code the test ran.
+    pub coverage: Coverage,
+}
+
+impl TestResult {
+    /// Did the test pass?
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Runs the tests declared in frontend source code.
+pub struct TestRunner {
+    /// The environment every test is compiled under.
+    env: Env,
+    /// The number of cells allocated for the call stack.
+    call_stack_size: usize,
+}
+
+impl TestRunner {
+    /// Compile the tests under the given environment.
+    pub fn new(env: Env, call_stack_size: usize) -> Self {
+        Self {
+            env,
+            call_stack_size,
+        }
+    }
+
+    /// Run the tests declared in source code whose names contain the filter,
+    /// in the order they're declared.
+    pub fn run(
+        &self,
+        src: &str,
+        filename: Option<&str>,
+        filter: Option<&str>,
+    ) -> Result<Vec<TestResult>, String> {
+        let decls = declarations(src, filename)?;
+        Ok(find_annotated(&decls, &Attribute::Test, filter)
+            .into_iter()
+            .map(|path| self.run_test(&decls, &path))
+            .collect())
+    }
+
+    /// Compile a program which calls the test at the given path, and interpret it.
+    fn run_test(&self, decls: &[Declaration], path: &[String]) -> TestResult {
+        let program = harness(decls, path, 1);
+        // A test which doesn't compile fails without printing anything.
+        let (output, failure, coverage) = match profile(&self.env, self.call_stack_size, program) {
+            Ok((profile, _, coverage)) => {
+                let device = profile.device;
+                // A panic explains itself on the standard error stream.
+                let failure = profile
+                    .result
+                    .err()
+                    .map(|e| match device.error_str().trim() {
+                        "" => e.to_string(),
+                        error => error.to_string(),
+                    });
+                (device.output_str(), failure, coverage)
+            }
+            Err(error) => (String::new(), Some(error), Coverage::new()),
+        };
+        TestResult {
+            name: path.join("."),
+            failure,
+            output,
+            coverage,
+        }
+    }
+}
+
+/// The declarations at the top level of some source code. The statements
+/// at the top level aren't run, so the tests only see the declarations.
+pub(super) fn declarations(src: &str, filename: Option<&str>) -> Result<Vec<Declaration>, String> {
+    let program = parse_source(&without_comments(src), filename.map(String::from))?;
+    Ok(program
+        .top_level_declarations()
+        .into_iter()
+        .filter(Declaration::is_compile_time_declaration)
+        .collect())
+}
+
+/// Find the paths of the procedures with an attribute whose names contain the filter,
+/// in some declarations and in the modules they declare.
+pub(super) fn find_annotated(
+    decls: &[Declaration],
+    attribute: &Attribute,
+    filter: Option<&str>,
+) -> Vec<Vec<String>> {
+    let mut paths = vec![];
+    find_procs(decls, attribute, &[], &mut paths);
+    paths.retain(|path| filter.map_or(true, |filter| path.join(".").contains(filter)));
+    paths
+}
+
+/// Add the paths of the procedures with an attribute, under the given path, to a list.
+fn find_procs(
+    decls: &[Declaration],
+    attribute: &Attribute,
+    path: &[String],
+    paths: &mut Vec<Vec<String>>,
+) {
+    for decl in Declaration::many(decls.to_vec()).flatten() {
+        match decl {
+            Declaration::Proc(name, proc) if proc.has_attribute(attribute) => {
+                paths.push([path, &[name]].concat())
+            }
+            Declaration::Module(name, decls, ..) => {
+                find_procs(&decls, attribute, &[path, &[name]].concat(), paths)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A program with some declarations, which calls the procedure at the given path
+/// a number of times, and then halts, so that a `main` procedure isn't called.
+pub(super) fn harness(decls: &[Declaration], path: &[String], calls: usize) -> Expr {
+    let mut proc = ConstExpr::var(&path[0]);
+    for name in &path[1..] {
+        proc = proc.field(ConstExpr::var(name));
+    }
+    let mut body = vec![proc.app(vec![]); calls];
+    body.push(Expr::ConstExpr(ConstExpr::Int(0)).unop(Exit));
+    Expr::Declare(
+        Box::new(Declaration::many(decls.to_vec())),
+        Box::new(Expr::Many(body)),
+    )
+}
+
+/// Compile a program with the prelude, and profile it in the interpreter with a `TestingDevice`.
+/// This returns the profile along with the time the interpreter took, not counting the compiler,
+/// and the lines of the source code the program ran.
+pub(super) fn profile(
+    env: &Env,
+    call_stack_size: usize,
+    program: Expr,
+) -> Result<(Profile<TestingDevice>, Duration, Coverage), String> {
+    let program = with_prelude(program, true, true)?;
+    let vm_code = match program
+        .compile_with_env(env.clone(), false)
+        .map_err(|e| e.to_string())?
+    {
+        Ok(asm_code) => asm_code.assemble(call_stack_size).map(Ok),
+        Err(asm_code) => asm_code.assemble(call_stack_size).map(Err),
+    }
+    .map_err(|e| e.to_string())?;
+
+    let start = Instant::now();
+    let profile = match &vm_code {
+        Ok(vm_code) => CoreInterpreter::new(TestingDevice::new("")).profile(vm_code),
+        Err(vm_code) => StandardInterpreter::new(TestingDevice::new("")).profile(vm_code),
+    };
+    let time = start.elapsed();
+    let coverage = Coverage::of(&vm_code, &profile);
+    Ok((profile, time, coverage))
+}
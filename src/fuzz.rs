@@ -0,0 +1,178 @@
+//! # Fuzzing
+//!
+//! Entry points for fuzzing the parser and the compiler with `cargo fuzz`
+//! (see the harnesses in the `fuzz` directory).
+//!
+//! Each entry point takes any input, and only panics if it finds a bug: errors in
+//! the program are expected, and are ignored. The parser and the compiler recurse
+//! over the syntax tree, so inputs which are nested too deeply are skipped instead
+//! of overflowing the stack, and programs are compiled with low limits on how far
+//! types and constant expressions are evaluated, so every input finishes quickly.
+//!
+//! With the `fuzzing` feature, [`ArbitraryExpr`] generates well formed LIR programs
+//! from the fuzzer's input, which reach deeper into the compiler than random text.
+use crate::{
+    frontend,
+    lir::{Compile, Env},
+};
+
+#[cfg(feature = "fuzzing")]
+use crate::lir::{ConstExpr, Expr, Mutability, Type};
+
+/// The longest input which is fuzzed, in bytes.
+pub const MAX_INPUT_LEN: usize = 4096;
+
+/// The most brackets an input can be nested in before it's skipped.
+pub const MAX_NESTING: usize = 48;
+
+/// How deep types are simplified while fuzzing, instead of the usual limit.
+const TYPE_RECURSION_LIMIT: usize = 64;
+
+/// How deep constant expressions are evaluated while fuzzing, instead of the usual limit.
+const CONST_RECURSION_LIMIT: usize = 64;
+
+/// Parse any bytes as frontend source code. Bytes which aren't UTF-8 are skipped.
+pub fn fuzz_parse(data: &[u8]) {
+    let Ok(src) = std::str::from_utf8(data) else {
+        return;
+    };
+    if is_fuzzable(src) {
+        let _ = frontend::parse(src, None, false, false);
+    }
+}
+
+/// Parse and compile frontend source code, without the prelude.
+pub fn fuzz_compile(src: &str) {
+    if !is_fuzzable(src) {
+        return;
+    }
+    if let Ok(program) = frontend::parse(src, None, false, false) {
+        let _ = program.compile_with_env(fuzzing_env(), false);
+    }
+}
+
+/// The environment programs are compiled under while fuzzing.
+pub fn fuzzing_env() -> Env {
+    let mut env = Env::default();
+    env.set_type_recursion_limit(TYPE_RECURSION_LIMIT);
+    env.set_const_recursion_limit(CONST_RECURSION_LIMIT);
+    env
+}
+
+/// Is the input small enough, and nested shallowly enough, to fuzz?
+fn is_fuzzable(src: &str) -> bool {
+    if src.len() > MAX_INPUT_LEN {
+        return false;
+    }
+    let mut depth = 0usize;
+    for ch in src.chars() {
+        match ch {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth > MAX_NESTING {
+            return false;
+        }
+    }
+    true
+}
+
+/// A well formed LIR program generated from a fuzzer's input. The programs use a
+/// handful of variables and operators, so most of them type check and compile.
+#[cfg(feature = "fuzzing")]
+#[derive(Clone, Debug)]
+pub struct ArbitraryExpr(pub Expr);
+
+#[cfg(feature = "fuzzing")]
+impl ArbitraryExpr {
+    /// The most expressions a generated expression is nested in.
+    const MAX_DEPTH: usize = 12;
+    /// The variables the generated programs can define and use.
+    const VARS: &'static [&'static str] = &["a", "b", "c", "d"];
+
+    /// Generate an expression, nested at most `depth` more times.
+    fn expr(u: &mut arbitrary::Unstructured, depth: usize) -> arbitrary::Result<Expr> {
+        if depth == 0 || u.is_empty() {
+            return Self::leaf(u);
+        }
+        let depth = depth - 1;
+        Ok(match u.int_in_range(0..=9)? {
+            0 | 1 => Self::leaf(u)?,
+            2 => {
+                let var = u.choose(Self::VARS)?;
+                let ty = match u.arbitrary::<bool>()? {
+                    true => Some(Self::ty(u)?),
+                    false => None,
+                };
+                Expr::let_var(
+                    var,
+                    Mutability::Mutable,
+                    ty,
+                    Self::expr(u, depth)?,
+                    Self::expr(u, depth)?,
+                )
+            }
+            3 => {
+                let lhs = Self::expr(u, depth)?;
+                let rhs = Self::expr(u, depth)?;
+                match u.int_in_range(0..=9)? {
+                    0 => lhs.add(rhs),
+                    1 => lhs.sub(rhs),
+                    2 => lhs.mul(rhs),
+                    3 => lhs.div(rhs),
+                    4 => lhs.rem(rhs),
+                    5 => lhs.lt(rhs),
+                    6 => lhs.eq(rhs),
+                    7 => lhs.and(rhs),
+                    8 => lhs.or(rhs),
+                    _ => lhs.bitxor(rhs),
+                }
+            }
+            4 => match u.int_in_range(0..=2)? {
+                0 => Self::expr(u, depth)?.neg(),
+                1 => Self::expr(u, depth)?.not(),
+                _ => Self::expr(u, depth)?.unop("put"),
+            },
+            5 => Self::expr(u, depth)?.if_then(Self::expr(u, depth)?, Self::expr(u, depth)?),
+            6 => Self::expr(u, depth)?.while_loop(Self::expr(u, depth)?),
+            7 => Expr::var(u.choose(Self::VARS)?).assign("+=", Self::expr(u, depth)?),
+            8 => Expr::Tuple(vec![Self::expr(u, depth)?, Self::expr(u, depth)?]),
+            _ => Expr::Many(vec![Self::expr(u, depth)?, Self::expr(u, depth)?]),
+        })
+    }
+
+    /// Generate an expression which doesn't contain any others.
+    fn leaf(u: &mut arbitrary::Unstructured) -> arbitrary::Result<Expr> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => ConstExpr::Int(u.arbitrary()?).into(),
+            1 => ConstExpr::Float(u.arbitrary()?).into(),
+            2 => ConstExpr::Char(u.arbitrary()?).into(),
+            3 => ConstExpr::Bool(u.arbitrary()?).into(),
+            _ => Expr::var(u.choose(Self::VARS)?),
+        })
+    }
+
+    /// Generate a simple type.
+    fn ty(u: &mut arbitrary::Unstructured) -> arbitrary::Result<Type> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Type::Int,
+            1 => Type::Float,
+            2 => Type::Char,
+            3 => Type::Bool,
+            _ => Type::Tuple(vec![Self::ty(u)?, Self::ty(u)?]),
+        })
+    }
+
+    /// Compile the generated program.
+    pub fn fuzz_compile(self) {
+        let _ = self.0.compile_with_env(fuzzing_env(), false);
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for ArbitraryExpr {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Self::expr(u, Self::MAX_DEPTH).map(Self)
+    }
+}
@@ -68,6 +68,7 @@
 //! 2. [The Assembly Language](./asm/index.html)
 //! 3. [The Virtual Machine](./vm/index.html)
 //! 4. [Target Backends](./targets/index.html)
+//! 5. [Embedding the Compiler](./compiler/index.html)
 //!
 //! ## Stages of IR
 //!
@@ -107,13 +108,20 @@
 //! | Arithmetic             | `IsNonNegative?` | `Add`     | `Subtract`      | `Multiply` | `Divide`    | `Remainder`  |
 //! | Worldly                | `GetChar`        | `PutChar` | `GetInt`        | `PutInt`   | `GetFloat`  | `PutFloat`   |
 pub mod asm;
+pub mod compiler;
 pub mod frontend;
+pub mod fuzz;
 pub mod lir;
+pub mod manifest;
 pub mod parse;
 pub mod side_effects;
 pub mod targets;
+pub mod testing;
 pub mod vm;
 
+pub use compiler::{Compilation, Compiler};
+pub use manifest::{BuildProfile, Manifest};
+
 /// The value of the NULL pointer constant.
 ///
 /// I've chosen to use the smallest value that can be expressed by an 8-bit signed integer.
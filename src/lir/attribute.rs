@@ -0,0 +1,140 @@
+//! # Attributes
+//!
+//! Attributes are markers attached to procedure and type declarations which change
+//! how the compiler treats them, without changing what they mean:
+//!
+//! - `inline`: calls to the procedure are replaced with its body, where it's safe to do so.
+//! - `no_mangle`: the procedure's label is its own name, instead of a generated unique name.
+//! - `cold`: the procedure is rarely called, so it's never inlined.
+//! - `deprecated`: uses of the procedure or type are reported with a warning.
+//! - `derive`: the type gets associated procedures generated from its layout,
+//!   like `eq`, `cmp`, and `hash`.
+//! - `specialize`: the procedure is used for the monomorph of the polymorphic
+//!   procedure with the same name for the given type arguments, instead of its
+//!   generic body.
+//! - `pure`: the procedure has no side effects, which is checked when it's type checked.
+//!   See the `effects` module for what counts as a side effect.
+//! - `doc`: the documentation of the procedure or type, which the documentation
+//!   generator renders. In the frontend, this is written as `///` comments.
+//! - `test`: the procedure is a test, which `sage test` runs. A test takes no
+//!   arguments, and it passes if it returns without panicking.
+//! - `bench`: the procedure is a benchmark, which `sage bench` runs. Like a test,
+//!   a benchmark takes no arguments.
+//!
+//! In the frontend, attributes are written before a declaration:
+//!
+//! ```text
+//! #[inline]
+//! fun square(x: Int): Int = x * x;
+//!
+//! #[deprecated("use `Point3` instead")]
+//! struct Point { x: Int, y: Int }
+//!
+//! #[derive(eq, hash)]
+//! struct Point3 { x: Int, y: Int, z: Int }
+//!
+//! fun is_zero<T>(x: T): Bool = x == (0 as T);
+//!
+//! #[specialize(Float)]
+//! fun is_zero(x: Float): Bool = x * x < 0.000001;
+//! ```
+use super::{Derive, Type};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use serde_derive::{Deserialize, Serialize};
+
+/// An attribute on a procedure or type declaration.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Attribute {
+    /// Replace calls to the procedure with its body.
+    Inline,
+    /// Use the name of the procedure as its label, instead of a mangled name.
+    NoMangle,
+    /// The procedure is rarely called, so it should never be inlined.
+    Cold,
+    /// Warn about every use of the declaration, with an optional note.
+    Deprecated(Option<String>),
+    /// Generate associated procedures for the type from its layout.
+    Derive(Vec<Derive>),
+    /// Override the monomorph of the polymorphic procedure with the same name
+    /// for these type arguments.
+    Specialize(Vec<Type>),
+    /// The procedure doesn't perform I/O, FFI calls, or heap allocation.
+    Pure,
+    /// The documentation of the declaration.
+    Doc(String),
+    /// The procedure is a test.
+    Test,
+    /// The procedure is a benchmark.
+    Bench,
+}
+
+impl Attribute {
+    /// The name of the attribute, as written in the source code.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Inline => "inline",
+            Self::NoMangle => "no_mangle",
+            Self::Cold => "cold",
+            Self::Deprecated(_) => "deprecated",
+            Self::Derive(_) => "derive",
+            Self::Specialize(_) => "specialize",
+            Self::Pure => "pure",
+            Self::Doc(_) => "doc",
+            Self::Test => "test",
+            Self::Bench => "bench",
+        }
+    }
+
+    /// Can this attribute be used on a type declaration?
+    /// Only `deprecated`, `derive`, and `doc` make sense for a type, and `derive`
+    /// has no effect on a procedure.
+    pub fn applies_to_types(&self) -> bool {
+        matches!(self, Self::Deprecated(_) | Self::Derive(_) | Self::Doc(_))
+    }
+
+    /// Find the deprecation attribute in a list of attributes, and return its note.
+    pub fn find_deprecation(attributes: &[Self]) -> Option<Option<&str>> {
+        attributes.iter().find_map(|attribute| match attribute {
+            Self::Deprecated(note) => Some(note.as_deref()),
+            _ => None,
+        })
+    }
+
+    /// Join the documentation attributes in a list of attributes, if there are any.
+    pub fn find_doc(attributes: &[Self]) -> Option<String> {
+        let docs = attributes
+            .iter()
+            .filter_map(|attribute| match attribute {
+                Self::Doc(doc) => Some(doc.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (!docs.is_empty()).then(|| docs.join("\n"))
+    }
+
+    /// Find the specialization attribute in a list of attributes, and return its type arguments.
+    pub fn find_specialization(attributes: &[Self]) -> Option<&[Type]> {
+        attributes.iter().find_map(|attribute| match attribute {
+            Self::Specialize(ty_args) => Some(ty_args.as_slice()),
+            _ => None,
+        })
+    }
+}
+
+impl Display for Attribute {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Deprecated(Some(note)) => write!(f, "#[deprecated({note:?})]"),
+            Self::Doc(doc) => write!(f, "#[doc({doc:?})]"),
+            Self::Derive(derives) => {
+                let derives = derives.iter().map(Derive::name).collect::<Vec<_>>();
+                write!(f, "#[derive({})]", derives.join(", "))
+            }
+            Self::Specialize(ty_args) => {
+                let ty_args = ty_args.iter().map(Type::to_string).collect::<Vec<_>>();
+                write!(f, "#[specialize({})]", ty_args.join(", "))
+            }
+            _ => write!(f, "#[{}]", self.name()),
+        }
+    }
+}
@@ -6,14 +6,18 @@
 //!
 //! How does the compiler compile an LIR expression into an assembly program?
 //!
-//! 1. First, type check the expression.
-//! 2. Then, attempt to compile the expression into a core assembly program.
-//! 3. If the expression cannot be compiled into a core assembly program, then compile it into a standard assembly program.
+//! 1. First, if the program declares a `main` procedure, add the call to it.
+//! 2. Then, type check the expression.
+//! 3. Then, attempt to compile the expression into a core assembly program.
+//! 4. If the expression cannot be compiled into a core assembly program, then compile it into a standard assembly program.
+//!    If the environment has the features of the target being compiled to, only the standard instructions
+//!    the target supports are allowed, and any others are reported at the code which uses them.
 use super::*;
 use crate::asm::{
-    AssemblyProgram, CoreOp, CoreProgram, StandardOp, StandardProgram, A, B, C, FP, SP,
+    AssemblyProgram, CoreOp, CoreProgram, StandardOp, StandardProgram, TargetProgram, A, B, C, D,
+    FP, SP,
 };
-use crate::NULL;
+use crate::{parse::SourceCodeLocation, vm, NULL};
 use log::*;
 use rayon::prelude::*;
 use std::sync::Mutex;
@@ -30,13 +34,35 @@ pub trait Compile: TypeCheck + std::fmt::Debug + std::fmt::Display {
     ///
     /// On an error, this will return an Err value containing the error.
     fn compile(self, core: bool) -> Result<Result<CoreProgram, StandardProgram>, Error>
+    where
+        Self: Sized + Clone,
+    {
+        self.compile_with_env(Env::default(), core)
+    }
+
+    /// Compile the expression into an assembly program, starting from the given environment.
+    /// This allows the compiler's settings on the environment to be configured.
+    ///
+    /// The `core` or `std` compile parameter is set for the variant being compiled to.
+    fn compile_with_env(
+        self,
+        mut env: Env,
+        core: bool,
+    ) -> Result<Result<CoreProgram, StandardProgram>, Error>
     where
         Self: Sized + Clone,
     {
         // eprintln!("Compiling LIR expression {self}");
+        env.set_variant_cfg(core);
+        // Call the program's `main` procedure, if it has one.
+        let program = self.add_entry_point(&env)?;
         info!("Type checking...");
-        // First, type check the expression.
-        self.type_check(&Env::default())?;
+        env.time_pass("typecheck", || -> Result<(), Error> {
+            // First, type check the expression.
+            program.type_check(&env)?;
+            // Run the lints registered on the environment.
+            program.lint(&env)
+        })?;
         // Then, attempt to compile the expression into a core assembly program.
         let mut core_asm = CoreProgram::default();
 
@@ -44,15 +70,25 @@ pub trait Compile: TypeCheck + std::fmt::Debug + std::fmt::Display {
         if core {
             // If the expression cannot be compiled into a core assembly program,
             // then compile it into a standard assembly program.
-            if let Err(err) = self
-                .clone()
-                // Compile the expression into the core assembly program.
-                .compile_expr(&mut Env::default(), &mut core_asm)
-            {
+            if let Err(err) = env.time_pass("lir-to-asm", || {
+                program
+                    .clone()
+                    // Compile the expression into the core assembly program.
+                    .compile_expr(&mut env.clone(), &mut core_asm)
+            }) {
+                // Programs which must be compiled to the core variant can't fall back on the standard variant.
+                if env.has_core_only() {
+                    return Err(err);
+                }
                 warn!("Failed to compile into core assembly program: {err}, falling back on standard assembly");
+                // Check the code for the standard variant, which may be different.
+                env.set_variant_cfg(false);
+                env.time_pass("typecheck", || program.type_check(&env))?;
                 let mut std_asm = StandardProgram::default();
                 // Compile the expression into the standard assembly program.
-                self.compile_expr(&mut Env::default(), &mut std_asm)?;
+                env.time_pass("lir-to-asm", || {
+                    compile_for_target(&program, &env, &mut std_asm)
+                })?;
                 info!("Compiled to standard assembly successfully");
                 // Return the fallback standard assembly program.
                 Ok(Err(std_asm))
@@ -64,18 +100,43 @@ pub trait Compile: TypeCheck + std::fmt::Debug + std::fmt::Display {
         } else {
             let mut std_asm = StandardProgram::default();
             // Compile the expression into the standard assembly program.
-            self.compile_expr(&mut Env::default(), &mut std_asm)?;
+            env.time_pass("lir-to-asm", || {
+                compile_for_target(&program, &env, &mut std_asm)
+            })?;
             info!("Compiled to standard assembly successfully");
             // Return the fallback standard assembly program.
             Ok(Err(std_asm))
         }
     }
+    /// Add the call to the program's entry point, if it declares one.
+    /// This is done before type checking, so the call is checked with the rest of the program.
+    fn add_entry_point(self, _env: &Env) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        Ok(self)
+    }
+
+    /// Run the lints registered on the environment over the expression.
+    /// The problems the lints find are reported as warnings on the environment.
+    fn lint(&self, _env: &Env) -> Result<(), Error> {
+        Ok(())
+    }
+
     // Compile a specific expression into an assembly program.
     fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error>;
 }
 
 /// Compile an LIR expression into several core assembly instructions.
 impl Compile for Expr {
+    fn add_entry_point(self, env: &Env) -> Result<Self, Error> {
+        self.with_entry(env)
+    }
+
+    fn lint(&self, env: &Env) -> Result<(), Error> {
+        super::lint::lint_expr(self, env)
+    }
+
     fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error> {
         let is_const = matches!(self, Self::ConstExpr(_));
         trace!("Compiling expression {self} (is_const={is_const}) {self:?} in environment {env}");
@@ -90,9 +151,30 @@ impl Compile for Expr {
         // Compile the expression.
         match self {
             Self::Annotated(expr, metadata) => {
+                // Remember where the expression is, so runtime checks inside it can report it.
+                let outer = metadata
+                    .location()
+                    .map(|loc| env.set_location(Some(loc.clone())));
+                // Mark the source line the instructions come from, when it changes.
+                let marked = match (&outer, env.get_location()) {
+                    (Some(outer), Some(loc))
+                        if env.has_debug_info() && !same_line(outer.as_ref(), loc) =>
+                    {
+                        output.comment(vm::line_comment(loc.line, loc.filename.as_deref()));
+                        true
+                    }
+                    _ => false,
+                };
                 // Compile the expression.
-                expr.compile_expr(env, output)
-                    .map_err(|e| e.annotate(metadata))?;
+                let result = expr.compile_expr(env, output);
+                if let Some(outer) = outer {
+                    // Go back to the line of the enclosing expression.
+                    if let (true, Some(loc)) = (marked, &outer) {
+                        output.comment(vm::line_comment(loc.line, loc.filename.as_deref()));
+                    }
+                    env.set_location(outer);
+                }
+                result.map_err(|e| e.annotate(metadata))?;
             }
 
             Self::Match(expr, branches) => {
@@ -112,6 +194,13 @@ impl Compile for Expr {
                     .compile_expr(env, output)?;
             }
 
+            Self::WhileLet(pat, expr, body) => {
+                // Generate the loop and its pattern matching code.
+                pat.while_let_pattern(&expr, &body, env)?
+                    // Compile the pattern matching code.
+                    .compile_expr(env, output)?;
+            }
+
             Self::UnaryOp(unop, expr) => {
                 let unop = env
                     .get_unop(&unop)
@@ -193,9 +282,16 @@ impl Compile for Expr {
             Self::ConstExpr(expr) => expr.compile_expr(env, output)?,
             // Compile a block of expressions.
             Self::Many(exprs) => {
-                for expr in exprs {
+                let count = exprs.len();
+                for (i, expr) in exprs.into_iter().enumerate() {
+                    // Only the value of the last expression is kept,
+                    // so discard the (unused) results of the others.
+                    let unused_size = if i + 1 < count { expr.get_size(env)? } else { 0 };
                     // Compile the expression in the block.
                     env.compile_args([expr], output)?;
+                    if unused_size > 0 {
+                        output.op(CoreOp::Pop(None, unused_size));
+                    }
                 }
             }
 
@@ -203,18 +299,79 @@ impl Compile for Expr {
             Self::As(ref expr, ref t) => {
                 // Compile the expression.
                 expr.clone().compile_expr(env, output)?;
+                // Casting between a struct and a struct with a guaranteed layout
+                // rearranges the members into the new layout.
+                let from = expr.get_type(env)?.simplify_until_concrete(env, false)?;
+                let to = t.simplify_until_concrete(env, false)?;
+                if matches!(
+                    (&from, &to),
+                    (Type::Struct(_), Type::Repr(..))
+                        | (Type::Repr(..), Type::Struct(_))
+                        | (Type::Repr(..), Type::Repr(..))
+                ) && from != to
+                {
+                    return compile_rearrange_members(&from, &to, env, output);
+                }
                 // Cast the expression to the specified type.
-                match (expr.get_type(env)?, t.clone()) {
+                //
+                // | From \ To | Int      | Float    | Char     | Bool     | Cell |
+                // |-----------|----------|----------|----------|----------|------|
+                // | Int       | -        | convert  | same     | `!= 0`   | same |
+                // | Float     | truncate | -        | truncate | `!= 0.0` | bits |
+                // | Char      | same     | convert  | -        | `!= 0`   | same |
+                // | Bool      | 0 or 1   | convert  | 0 or 1   | -        | same |
+                // | Cell      | same     | bits     | same     | same     | -    |
+                //
+                // Float to integer casts truncate toward zero. Casts to and from
+                // a cell reinterpret the bits of the value without converting it.
+                match (from.clone().discard_type_wrapper(), to.clone().discard_type_wrapper()) {
                     // If the cast is an integer to a float,
                     // then convert the integer to a float.
-                    (Type::Int, Type::Float) => {
+                    (Type::Int | Type::Char | Type::Bool, Type::Float) => {
                         output.std_op(StandardOp::ToFloat(SP.deref()))?;
                     }
                     // If the cast is a float to an integer,
                     // then convert the float to an integer.
-                    (Type::Float, Type::Int) => {
+                    (Type::Float, Type::Int | Type::Char) => {
                         output.std_op(StandardOp::ToInt(SP.deref()))?;
                     }
+                    // If the cast is a float to a boolean,
+                    // then check if the float is nonzero.
+                    (Type::Float, Type::Bool) => {
+                        // The float comparisons are inclusive, so the float is zero
+                        // exactly when it is both at least and at most zero.
+                        output.std_op(StandardOp::Set(B, 0.0))?;
+                        output.std_op(StandardOp::IsGreater {
+                            a: SP.deref(),
+                            b: B,
+                            dst: C,
+                        })?;
+                        output.std_op(StandardOp::IsGreater {
+                            a: B,
+                            b: SP.deref(),
+                            dst: D,
+                        })?;
+                        output.op(CoreOp::And { src: D, dst: C });
+                        output.op(CoreOp::Not(C));
+                        output.op(CoreOp::Move {
+                            src: C,
+                            dst: SP.deref(),
+                        });
+                    }
+                    // If the cast is an integer to a boolean,
+                    // then check if the integer is nonzero.
+                    (Type::Int | Type::Char, Type::Bool) => {
+                        output.op(CoreOp::Set(B, 0));
+                        output.op(CoreOp::IsNotEqual {
+                            a: SP.deref(),
+                            b: B,
+                            dst: C,
+                        });
+                        output.op(CoreOp::Move {
+                            src: C,
+                            dst: SP.deref(),
+                        });
+                    }
                     // If the cast is to a type of the same size,
                     // we will trust the user.
                     (a, b) if a.get_size(env)? == b.get_size(env)? => {}
@@ -271,6 +428,23 @@ impl Compile for Expr {
                     }
                     // If the procedure is a symbol, get the procedure from the environment.
                     Expr::ConstExpr(ConstExpr::Symbol(name)) => {
+                        // Replace calls to `inline` procedures with their bodies, unless
+                        // the name refers to a variable holding a procedure instead.
+                        if let (Some(ConstExpr::Proc(proc)), false, None) = (
+                            env.get_const(&name),
+                            env.has_local_var(&name),
+                            env.get_static_var(&name),
+                        ) {
+                            if let Some(inlined) = super::inline::inline_call(proc, &args, env) {
+                                debug!("Inlining call to {name}: {inlined}");
+                                let depth = env.get_inline_depth();
+                                env.set_inline_depth(depth + 1);
+                                let result = inlined.compile_expr(env, output);
+                                env.set_inline_depth(depth);
+                                return result;
+                            }
+                        }
+
                         // Push the arguments to the procedure on the stack.
                         env.compile_args(args, output)?;
 
@@ -385,15 +559,10 @@ impl Compile for Expr {
                 // Get the size of the arguments and return value.
                 let args_size = env.get_args_size();
                 let ret_size = e.get_size(env)?;
-                // Execute the body to leave the return value
-                e.compile_expr(env, output)?;
-
-                // Overwrite the arguments with the return value
-                output.op(CoreOp::Copy {
-                    dst: FP.deref().offset(1 - args_size as isize),
-                    src: SP.deref().offset(1 - ret_size as isize),
-                    size: ret_size,
-                });
+                // Write the return value over the arguments
+                compile_return_value(*e, ret_size, env, output)?;
+                // Restore the procedure's frame pointer from before any stack allocations.
+                env.compile_restore_frame_pointer(env.get_stack_frame_count(), output);
 
                 // Because we could be terminating the function at an
                 // arbitrary point on the stack, we have to make the stack
@@ -409,6 +578,9 @@ impl Compile for Expr {
                     SP,
                     Some(args_size as isize - ret_size as isize),
                 ));
+                if env.has_backtraces() {
+                    compile_backtrace_pop(output);
+                }
                 output.op(CoreOp::Return);
             }
 
@@ -472,6 +644,37 @@ impl Compile for Expr {
 
             // Compile an assignment operation to a pointer.
             Self::DerefMut(ptr, val) => {
+                // If we're storing to a member of a bitfield, then
+                // only overwrite the member's bits in its cell.
+                if let Some((addr, shift, bits)) = ptr.get_bitfield_member_reference(env)? {
+                    let mask = bitfield_mask(bits);
+                    // Push the value and the address of the cell to the stack.
+                    val.compile_expr(env, output)?;
+                    addr.compile_expr(env, output)?;
+                    // Pop the address into A, and the value into B.
+                    output.op(CoreOp::Pop(Some(A), 1));
+                    output.op(CoreOp::Pop(Some(B), 1));
+                    // Truncate the value to the width of the member,
+                    // and shift it into the member's position.
+                    output.op(CoreOp::Set(C, mask));
+                    output.op(CoreOp::BitwiseAnd { src: C, dst: B });
+                    output.op(CoreOp::Set(C, shift as i64));
+                    output.op(CoreOp::LeftShift { src: C, dst: B });
+                    // Clear the member's bits in the cell, and then set them to the new value.
+                    output.op(CoreOp::Set(C, !(mask << shift)));
+                    output.op(CoreOp::Move {
+                        src: A.deref(),
+                        dst: D,
+                    });
+                    output.op(CoreOp::BitwiseAnd { src: C, dst: D });
+                    output.op(CoreOp::BitwiseOr { src: B, dst: D });
+                    output.op(CoreOp::Move {
+                        src: D,
+                        dst: A.deref(),
+                    });
+                    return Ok(());
+                }
+
                 // Push the value to the stack
                 let val_type = val.get_type(env)?;
                 val.compile_expr(env, output)?;
@@ -499,6 +702,31 @@ impl Compile for Expr {
                 env.compile_args(elems, output)?
             }
 
+            // Compile an array of copies of an element.
+            Self::ArrayRepeat(elem, len) => {
+                let len = len.as_int(env)?;
+                let elem_size = elem.get_size(env)?;
+                // Compile the first copy of the element.
+                elem.compile_expr(env, output)?;
+                if len <= 0 {
+                    // An empty array still evaluates its element, but doesn't keep it.
+                    output.op(CoreOp::Pop(None, elem_size));
+                } else if len > 1 && elem_size > 0 {
+                    // Push a copy of the first element for every other element of the array.
+                    output.op(CoreOp::Many(vec![
+                        CoreOp::GetAddress {
+                            addr: SP.deref().offset(1 - elem_size as isize),
+                            dst: A,
+                        },
+                        CoreOp::Set(B, len - 1),
+                        CoreOp::While(B),
+                        CoreOp::Push(A.deref(), elem_size),
+                        CoreOp::Dec(B),
+                        CoreOp::End,
+                    ]));
+                }
+            }
+
             // Compile a tuple literal.
             Self::Tuple(items) => {
                 // Compile the items
@@ -532,11 +760,11 @@ impl Compile for Expr {
                 // Get the size of the tagged union.
                 let result_size = t.get_size(env)?;
                 let t = t.simplify_until_concrete(env, false)?;
-                if let Type::EnumUnion(fields) = t {
+                if let Type::EnumUnion(fields, repr) = t {
                     // Get the list of possible variant names.
                     let variants = fields.clone().into_keys().collect::<Vec<_>>();
-                    // Get the value of the tag associated with this variant.
-                    if let Some(tag_value) = Type::variant_index(&variants, &variant) {
+                    // Get the cells of the tag associated with this variant.
+                    if let Some(tag) = repr.tag_cells_of(&variants, &variant) {
                         // Get the size of the value we are storing in the union.
                         let val_size = val.get_size(env)?;
 
@@ -547,21 +775,33 @@ impl Compile for Expr {
                         output.op(CoreOp::Next(
                             SP,
                             // This size *includes* the tag: it allocates space for it so we
-                            // can immediately set the value under the stack poiner as the tag.
+                            // can immediately set the cells under the stack poiner as the tag.
                             Some(result_size as isize - val_size as isize),
                         ));
 
-                        output.op(CoreOp::Set(SP.deref(), tag_value as i64));
+                        compile_set_tag(&tag, output);
                         return Ok(());
                     } else {
                         // If we could not find the variant return an error.
-                        return Err(Error::VariantNotFound(Type::EnumUnion(fields), variant));
+                        return Err(Error::VariantNotFound(
+                            Type::EnumUnion(fields, repr),
+                            variant,
+                        ));
                     }
                 } else {
                     return Err(Error::VariantNotFound(t.clone(), variant));
                 }
             }
 
+            // A hole can't be compiled; report what could fill it instead.
+            Self::Hole(name) => {
+                return Err(Error::Hole {
+                    name,
+                    expected: None,
+                    fits: env.get_bindings_fitting(None),
+                });
+            }
+
             // Compile an indexing operation.
             Self::Index(val, idx) => {
                 // TODO: optimize this by using `Refer` when possible
@@ -573,23 +813,28 @@ impl Compile for Expr {
                 let size = t.get_size(env)?;
                 // Get the type of the value being indexed
                 let val_type = val.get_type(env)?.simplify_until_concrete(env, false)?;
-                // Get the size of the value being indexed.
-                let val_size = val_type.get_size(env)?;
                 // Figure out what to do based on the value's type.
                 match val_type {
                     // If the value being indexed is an array:
-                    Type::Array(ref elem, _) => {
+                    Type::Array(ref elem, ref len) => {
                         // First, lets try to compile the same index expression using pointer
                         // arithmetic. This will be faster than pushing the entire array
                         // onto the stack and indexing it.
-                        let optimized_idx = val
-                            .clone()
-                            // Reference the current
-                            .refer(Mutability::Immutable)
-                            // Make the type a pointer to the inner element type
-                            .as_type(Type::Pointer(Mutability::Immutable, elem.clone()))
-                            // Index the new pointer
-                            .idx(*idx.clone());
+                        let optimized_idx = if env.has_bounds_checks() {
+                            // Indexing a pointer forgets the length of the array,
+                            // so take the address of the element instead, which checks the index.
+                            Self::Index(val.clone(), idx.clone())
+                                .refer(Mutability::Immutable)
+                                .deref()
+                        } else {
+                            val.clone()
+                                // Reference the current
+                                .refer(Mutability::Immutable)
+                                // Make the type a pointer to the inner element type
+                                .as_type(Type::Pointer(Mutability::Immutable, elem.clone()))
+                                // Index the new pointer
+                                .idx(*idx.clone())
+                        };
                         // The optimized index *may not be possible* if the array is
                         // not able to be referenced (like an array literal). Type checking
                         // a reference operation will confirm that the array is able to be
@@ -605,30 +850,60 @@ impl Compile for Expr {
                             return optimized_idx.compile_expr(env, output);
                         }
 
-                        // Get the size of the element we will return.
-                        let elem_size = elem.get_size(env)?;
-                        // Push the array onto the stack.
-                        // Then, push the index onto the stack.
-                        env.compile_args([*val, *idx], output)?;
-
-                        // Calculate the offset of the element we want to return
-                        // (the index times the size of the element), and store it in `B`.
-                        output.op(CoreOp::Pop(Some(B), 1));
-                        if elem_size > 1 {
-                            output.op(CoreOp::Set(A, elem_size as i64));
-                            output.op(CoreOp::Mul { dst: B, src: A });
+                        // Fold a chain of indices into nested arrays, like `a[i][j]`, into a
+                        // single row-major offset. This way, only the innermost element is
+                        // copied out of the array, instead of every intermediate row.
+                        let mut indices = vec![(*idx.clone(), elem.get_size(env)?, len.clone())];
+                        let mut base = *val.clone();
+                        while let Self::Index(inner, inner_idx) = &base {
+                            match inner.get_type(env)?.simplify_until_concrete(env, false)? {
+                                Type::Array(elem, len) => {
+                                    indices.push((*inner_idx.clone(), elem.get_size(env)?, len));
+                                }
+                                _ => break,
+                            }
+                            base = *inner.clone();
+                        }
+                        indices.reverse();
+                        let base_size = base.get_type(env)?.get_size(env)?;
+
+                        // Push the outermost array onto the stack.
+                        // Then, push each of the indices onto the stack.
+                        env.compile_args(
+                            std::iter::once(base)
+                                .chain(indices.iter().map(|(idx, ..)| idx.clone())),
+                            output,
+                        )?;
+
+                        // Calculate the offset of the element we want to return, starting
+                        // from the innermost dimension, and store it in `A`.
+                        for (i, (_, elem_size, len)) in indices.into_iter().rev().enumerate() {
+                            // The offset in this dimension is the index times the size of the element.
+                            output.op(CoreOp::Pop(Some(B), 1));
+                            if env.has_bounds_checks() {
+                                compile_bounds_check(len.as_int(env)?, env, output);
+                            }
+                            if elem_size > 1 {
+                                output.op(CoreOp::Set(C, elem_size as i64));
+                                output.op(CoreOp::Mul { dst: B, src: C });
+                            }
+                            if i == 0 {
+                                output.op(CoreOp::Move { src: B, dst: A });
+                            } else {
+                                output.op(CoreOp::Add { dst: A, src: B });
+                            }
                         }
 
-                        // Get the address of the array's first element, and store it in `A`.
+                        // Get the address of the array's first element, and store it in `B`.
                         output.op(CoreOp::GetAddress {
-                            addr: SP.deref().offset(1 - val_size as isize),
-                            dst: A,
+                            addr: SP.deref().offset(1 - base_size as isize),
+                            dst: B,
                         });
-                        // Index the address stored in `A` with the offset stored in `B`,
+                        // Index the address stored in `B` with the offset stored in `A`,
                         // and store the address of that index in `C`.
                         output.op(CoreOp::Index {
-                            src: A,
-                            offset: B,
+                            src: B,
+                            offset: A,
                             dst: C,
                         });
 
@@ -636,11 +911,11 @@ impl Compile for Expr {
                         // array's first element on the stack.
                         output.op(CoreOp::Copy {
                             src: C.deref(),
-                            dst: SP.deref().offset(1 - val_size as isize),
+                            dst: SP.deref().offset(1 - base_size as isize),
                             size,
                         });
                         // Pop the remaining elements off the stack, so the element we indexed remains.
-                        output.op(CoreOp::Pop(None, val_size - size));
+                        output.op(CoreOp::Pop(None, base_size - size));
                     }
                     // If the value being indexed is a pointer:
                     Type::Pointer(_, elem) => {
@@ -714,6 +989,26 @@ impl Compile for Expr {
                         }
                     }
                     val_type => {
+                        // If the value is a bitfield, then extract the member's bits from its cell.
+                        if let Some((offset, shift, bits)) = val_type.get_bitfield_member(member, env)? {
+                            let val_size = val_type.get_size(env)?;
+                            // Evaluate the bitfield and push it onto the stack.
+                            val.clone().compile_expr(env, output)?;
+                            // Move the cell containing the member into A, and pop the bitfield.
+                            output.op(CoreOp::Move {
+                                src: SP.deref().offset(1 - val_size as isize + offset as isize),
+                                dst: A,
+                            });
+                            output.op(CoreOp::Pop(None, val_size));
+                            // Shift the member's bits down, and mask off the other members.
+                            output.op(CoreOp::Set(B, shift as i64));
+                            output.op(CoreOp::LogicalRightShift { src: B, dst: A });
+                            output.op(CoreOp::Set(B, bitfield_mask(bits)));
+                            output.op(CoreOp::BitwiseAnd { src: B, dst: A });
+                            output.op(CoreOp::Push(A, 1));
+                            return Ok(());
+                        }
+
                         // Get the size of the field we want to retrieve.
                         let size = self.get_size(env)?;
                         // Get the size of the value we want to get a field from.
@@ -790,7 +1085,7 @@ impl Compile for Expr {
                         output.op(
                             // Calculate the address of the variable from the offset
                             CoreOp::GetAddress {
-                                addr: FP.deref().offset(*offset),
+                                addr: env.get_frame_location(*offset),
                                 dst: SP.deref(),
                             },
                         )
@@ -823,11 +1118,18 @@ impl Compile for Expr {
                     // Get the type of the value we want to get a field from.
                     let val_type = val.get_type(env)?;
                     // val_type.add_monomorphized_associated_consts(env)?;
+                    // Bitfield members don't have their own addresses.
+                    if val_type.get_bitfield_member(&name, env)?.is_some() {
+                        return Err(Error::InvalidRefer(Expr::Member(
+                            Expr::from(*val.clone()).into(),
+                            *name,
+                        )));
+                    }
 
                     // Push the address of the struct, tuple, or union onto the stack.
                     match val_type.simplify_until_has_members(env, false)? {
                         // If the value is a struct, tuple, or union:
-                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) => {
+                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) | Type::Repr(_, _) => {
                             // Compile a reference to the inner value with the expected mutability.
                             Self::Refer(expected_mutability, Expr::from(*val.clone()).into())
                                 .compile_expr(env, output)?;
@@ -915,11 +1217,15 @@ impl Compile for Expr {
                     // Get the type of the value we want to get a field from.
                     let val_type = val.get_type(env)?;
                     // val_type.add_monomorphized_associated_consts(env)?;
+                    // Bitfield members don't have their own addresses.
+                    if val_type.get_bitfield_member(&name, env)?.is_some() {
+                        return Err(Error::InvalidRefer(Expr::Member(val, name)));
+                    }
 
                     // Push the address of the struct, tuple, or union onto the stack.
                     match val_type.simplify_until_has_members(env, false)? {
                         // If the value is a struct, tuple, or union:
-                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) => {
+                        Type::Struct(_) | Type::Tuple(_) | Type::Union(_) | Type::Repr(_, _) => {
                             // Compile a reference to the inner value with the expected mutability.
                             Self::Refer(expected_mutability, val.clone())
                                 .compile_expr(env, output)?;
@@ -966,7 +1272,7 @@ impl Compile for Expr {
                     let val_type = val.get_type(env)?.simplify_until_concrete(env, false)?;
                     match val_type {
                         // If the value is an array:
-                        Type::Array(ref elem, _) => {
+                        Type::Array(ref elem, ref len) => {
                             // Push the address of the array onto the stack.
                             Self::Refer(expected_mutability, val.clone())
                                 .compile_expr(env, output)?;
@@ -979,6 +1285,9 @@ impl Compile for Expr {
                             output.op(CoreOp::Pop(Some(B), 1));
                             // Store the address of the array in `A`.
                             output.op(CoreOp::Pop(Some(A), 1));
+                            if env.has_bounds_checks() {
+                                compile_bounds_check(len.clone().as_int(env)?, env, output);
+                            }
                             if elem_size > 1 {
                                 // Store the size of the element in `C`.
                                 output.op(CoreOp::Set(C, elem_size as i64));
@@ -1054,6 +1363,10 @@ impl Compile for Expr {
 
 /// Compile a constant expression.
 impl Compile for ConstExpr {
+    fn lint(&self, env: &Env) -> Result<(), Error> {
+        super::lint::lint_const(self, env)
+    }
+
     fn compile_expr(self, env: &mut Env, output: &mut dyn AssemblyProgram) -> Result<(), Error> {
         trace!("Compiling constant expression {self} in environment {env}");
         let mut debug_str = format!("{self}");
@@ -1221,6 +1534,11 @@ impl Compile for ConstExpr {
             Self::Bool(x) => {
                 output.op(CoreOp::PushConst(vec![x as i64]));
             }
+            // Compile whether a compile parameter is set.
+            Self::Cfg(name, value) => {
+                let is_set = env.has_cfg(&name, value.as_deref());
+                output.op(CoreOp::PushConst(vec![is_set as i64]));
+            }
             // Compile a cell value.
             Self::Cell(n) => {
                 output.op(CoreOp::PushConst(vec![n]));
@@ -1241,6 +1559,22 @@ impl Compile for ConstExpr {
             Self::SizeOfExpr(e) => {
                 output.op(CoreOp::PushConst(vec![e.get_size(env)? as i64]));
             }
+            // Calculate the alignment of a type.
+            Self::AlignOfType(t) => {
+                output.op(CoreOp::PushConst(vec![t.get_alignment(env)? as i64]));
+            }
+            // Calculate the offset of a member of a type.
+            Self::OffsetOf(t, member) => {
+                output.op(CoreOp::PushConst(vec![t.get_offset_of(&member, env)? as i64]));
+            }
+            // Describe a type as a constant array.
+            Self::Reflect(t, reflection) => {
+                reflection.reflect(&t, env)?.compile_expr(env, output)?;
+            }
+            // Compile a table as the array it evaluates to.
+            table @ Self::Table(..) => {
+                table.eval(env)?.compile_expr(env, output)?;
+            }
             // Compile a tuple constant.
             Self::Tuple(items) => {
                 // Compile the items
@@ -1385,11 +1719,11 @@ impl Compile for ConstExpr {
                 let t = t.simplify_until_has_variants(env, false)?;
 
                 // Get the inner list of variants and compile the expression using this information.
-                if let Type::EnumUnion(variants) = t.clone().simplify(env)? {
+                if let Type::EnumUnion(variants, repr) = t.clone().simplify(env)? {
                     // Get the list of possible variant names.
                     let variants = variants.into_keys().collect::<Vec<_>>();
-                    // Get the value of the tag associated with this variant.
-                    if let Some(tag_value) = Type::variant_index(&variants, &variant) {
+                    // Get the cells of the tag associated with this variant.
+                    if let Some(tag) = repr.tag_cells_of(&variants, &variant) {
                         // Get the size of the value we are storing in the union.
                         let val_size = val.get_size(env)?;
 
@@ -1400,11 +1734,11 @@ impl Compile for ConstExpr {
                         output.op(CoreOp::Next(
                             SP,
                             // This size *includes* the tag: it allocates space for it so we
-                            // can immediately set the value under the stack pointer as the tag.
+                            // can immediately set the cells under the stack pointer as the tag.
                             Some(result_size as isize - val_size as isize),
                         ));
 
-                        output.op(CoreOp::Set(SP.deref(), tag_value as i64));
+                        compile_set_tag(&tag, output);
                     } else {
                         // If we could not find the variant return an error.
                         return Err(Error::VariantNotFound(t, variant));
@@ -1422,9 +1756,10 @@ impl Compile for ConstExpr {
             Self::StandardBuiltin(builtin) => {
                 builtin.compile_expr(env, output)?;
             }
-            // Compile a foreign call.
+            // A foreign function used as a value is wrapped in a procedure which calls it.
+            // Calls to foreign functions are compiled directly, when the arguments are applied.
             Self::FFIProcedure(ffi_proc) => {
-                ffi_proc.compile_expr(env, output)?;
+                Self::Proc(ffi_proc.as_procedure()).compile_expr(env, output)?;
             }
             // Compile a procedure.
             Self::Proc(proc) => {
@@ -1458,13 +1793,13 @@ impl Compile for ConstExpr {
                 // This is to prevent infinite loops and to keep recursion under control.
                 match enum_type.simplify_until_has_variants(env, false)? {
                     // If the type is an enum, we can continue.
-                    Type::Enum(variants) => {
-                        // Get the index of the variant.
-                        if let Some(index) = Type::variant_index(&variants, &variant) {
-                            // Push the index of the variant onto the stack.
+                    Type::Enum(variants, repr) => {
+                        // Get the tag of the variant.
+                        if let Some(tag) = repr.tag_cells_of(&variants, &variant) {
+                            // Push the tag of the variant onto the stack.
                             // output.op(CoreOp::Set(A, index as i64));
                             // output.op(CoreOp::Push(A, 1));
-                            output.op(CoreOp::PushConst(vec![index as i64]));
+                            output.op(CoreOp::PushConst(tag));
                             return Ok(());
                         } else {
                             // If the variant is not found, return an error.
@@ -1472,18 +1807,20 @@ impl Compile for ConstExpr {
                         }
                     }
                     // If the type is an enum union, we can continue.
-                    Type::EnumUnion(variants) if variants.get(&variant) == Some(&Type::None) => {
-                        // Get the index of the variant.
-                        if let Some(index) = Type::variant_index(
+                    Type::EnumUnion(variants, repr)
+                        if variants.get(&variant) == Some(&Type::None) =>
+                    {
+                        // Get the tag of the variant.
+                        if let Some(tag) = repr.tag_cells_of(
                             variants.into_keys().collect::<Vec<_>>().as_slice(),
                             &variant,
                         ) {
-                            // Push the index of the variant onto the stack.
+                            // Push the tag of the variant onto the stack.
                             // Allocate the size of the structure on the stack by
                             // incrementing the stack pointer by the size of the structure.
-                            // Then, set the value under the stack pointer to the index of the variant.
+                            // Then, set the cells under the stack pointer to the tag of the variant.
                             output.op(CoreOp::Next(SP, Some(enum_type.get_size(env)? as isize)));
-                            output.op(CoreOp::Set(SP.deref(), index as i64));
+                            compile_set_tag(&tag, output);
                             return Ok(());
                         } else {
                             // If the variant is not found, return an error.
@@ -1502,7 +1839,10 @@ impl Compile for ConstExpr {
                 // Compile a symbol.
                 if let Some((_, t, offset)) = env.get_var(&name) {
                     // If the symbol is a variable, push it onto the stack.
-                    output.op(CoreOp::Push(FP.deref().offset(*offset), t.get_size(env)?))
+                    output.op(CoreOp::Push(
+                        env.get_frame_location(*offset),
+                        t.get_size(env)?,
+                    ))
                 } else if let Some((_, t, location)) = env.get_static_var(&name) {
                     // If the symbol is a static variable, push it onto the stack.
                     output.op(CoreOp::Push(location.clone(), t.get_size(env)?))
@@ -1522,3 +1862,251 @@ impl Compile for ConstExpr {
         Ok(())
     }
 }
+
+/// Compile an expression in the return position of a procedure, writing its value
+/// directly into the return slot over the procedure's arguments.
+///
+/// The value of a block or declaration is constructed in place, instead of being copied
+/// down over the block's local variables and then copied again over the arguments.
+/// A returned local variable is copied straight into the return slot, without pushing it.
+/// This saves a full copy of large aggregate return values on every call.
+///
+/// The local variables are left on the stack, so afterwards the caller must reset
+/// the stack pointer relative to the frame pointer.
+pub(crate) fn compile_return_value(
+    expr: Expr,
+    ret_size: usize,
+    env: &mut Env,
+    output: &mut dyn AssemblyProgram,
+) -> Result<(), Error> {
+    // The return slot starts where the arguments start.
+    let slot = env.get_frame_location(1 - env.get_args_size() as isize);
+
+    // Copy a local variable straight into the return slot.
+    let var_offset = match &expr {
+        Expr::ConstExpr(ConstExpr::Symbol(name)) => match env.get_var(name) {
+            Some((_, t, offset)) => (t.get_size(env)? == ret_size).then_some(*offset),
+            None => None,
+        },
+        _ => None,
+    };
+    if let Some(offset) = var_offset {
+        output.op(CoreOp::Copy {
+            src: env.get_frame_location(offset),
+            dst: slot,
+            size: ret_size,
+        });
+        return Ok(());
+    }
+
+    match expr {
+        Expr::Annotated(expr, metadata) => {
+            // Remember where the expression is, so runtime checks inside it can report it.
+            let outer = metadata
+                .location()
+                .map(|loc| env.set_location(Some(loc.clone())));
+            let result = compile_return_value(*expr, ret_size, env, output);
+            if let Some(outer) = outer {
+                env.set_location(outer);
+            }
+            result.map_err(|e| e.annotate(metadata))?;
+        }
+        // Compile the statements of the block like usual,
+        // and construct the result in the return slot.
+        Expr::Many(mut exprs) if !exprs.is_empty() => {
+            let last = exprs.pop().unwrap();
+            for expr in exprs {
+                let unused_size = expr.get_size(env)?;
+                env.compile_args([expr], output)?;
+                if unused_size > 0 {
+                    output.op(CoreOp::Pop(None, unused_size));
+                }
+            }
+            compile_return_value(last, ret_size, env, output)?;
+        }
+        Expr::Declare(declaration, body) => {
+            declaration.compile_return_value(*body, ret_size, env, output)?;
+        }
+        // Otherwise, push the value and copy it over the arguments.
+        expr => {
+            expr.compile_expr(env, output)?;
+            output.op(CoreOp::Copy {
+                dst: slot,
+                src: SP.deref().offset(1 - ret_size as isize),
+                size: ret_size,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Set the tag of a tagged union, whose last cell is on the top of the stack.
+/// The tag is stored in the last cells of the value.
+fn compile_set_tag(tag: &[i64], output: &mut dyn AssemblyProgram) {
+    for (i, cell) in tag.iter().rev().enumerate() {
+        output.op(CoreOp::Set(SP.deref().offset(-(i as isize)), *cell));
+    }
+}
+
+/// Check that the index in `B` is in bounds for an array of the given length.
+/// If it isn't, the program panics. `A` and `B` are preserved.
+fn compile_bounds_check(len: i64, env: &Env, output: &mut dyn AssemblyProgram) {
+    // Store whether the index is in bounds in `D`.
+    output.op(CoreOp::Set(C, len));
+    output.op(CoreOp::IsLess { a: B, b: C, dst: D });
+    output.op(CoreOp::Set(C, 0));
+    output.op(CoreOp::IsGreaterEqual { a: B, b: C, dst: C });
+    output.op(CoreOp::And { src: C, dst: D });
+    output.op(CoreOp::Not(D));
+    output.op(CoreOp::If(D));
+    compile_trap(
+        &format!("index out of bounds for array of length {len}"),
+        env,
+        output,
+    );
+    output.op(CoreOp::End);
+}
+
+/// Get the mask for the bits of a bitfield member with the given width.
+pub(crate) fn bitfield_mask(bits: usize) -> i64 {
+    if bits >= BITS_PER_CELL {
+        -1
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Rearrange the members of a struct on the top of the stack into the layout
+/// of another struct type with the same members. The members' cells are copied
+/// without being converted, so the members must have the same types in both.
+fn compile_rearrange_members(
+    from: &Type,
+    to: &Type,
+    env: &Env,
+    output: &mut dyn AssemblyProgram,
+) -> Result<(), Error> {
+    let from_size = from.get_size(env)? as isize;
+    let to_size = to.get_size(env)? as isize;
+    let members: Vec<(String, Type)> = match to {
+        Type::Struct(fields) => fields.clone().into_iter().collect(),
+        Type::Repr(fields, _) => fields.clone(),
+        _ => return Err(Error::UnsupportedOperation(Expr::ConstExpr(ConstExpr::Type(to.clone())))),
+    };
+
+    // Push the rearranged struct on top of the original, with its padding zeroed.
+    output.op(CoreOp::PushConst(vec![0; to_size as usize]));
+    for (name, ty) in members {
+        let member = ConstExpr::Symbol(name);
+        let src = from.get_offset_of(&member, env)? as isize;
+        let dst = to.get_offset_of(&member, env)? as isize;
+        output.op(CoreOp::Copy {
+            src: SP.deref().offset(1 - to_size - from_size + src),
+            dst: SP.deref().offset(1 - to_size + dst),
+            size: ty.get_size(env)?,
+        });
+    }
+    // Copy the rearranged struct over the original, and pop the rest.
+    output.op(CoreOp::Copy {
+        src: SP.deref().offset(1 - to_size),
+        dst: SP.deref().offset(1 - to_size - from_size),
+        size: to_size as usize,
+    });
+    output.op(CoreOp::Pop(None, from_size as usize));
+    Ok(())
+}
+
+impl Expr {
+    /// Compile a program after the code of the programs compiled before it with the same
+    /// environment and output, like the inputs of a REPL. The variables declared by the
+    /// statements at the top level of the program are left on the stack, and their declarations
+    /// are kept in the environment, so that the programs compiled after it can use them.
+    pub fn compile_top_level(
+        self,
+        env: &mut Env,
+        output: &mut StandardProgram,
+    ) -> Result<(), Error> {
+        env.set_variant_cfg(false);
+        self.type_check(env)?;
+        self.lint(env)?;
+        match env.get_target_features().cloned() {
+            Some(features) => {
+                self.compile_statements(env, &mut TargetProgram::new(output, &features))
+            }
+            None => self.compile_statements(env, output),
+        }
+    }
+
+    /// Compile the statements of a program, keeping the declarations along its spine in scope.
+    /// The frontend nests the statements after a declaration in its body, and ends a block
+    /// of statements with the declaration after them, so these are the top level declarations.
+    fn compile_statements(
+        self,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Annotated(expr, metadata) => {
+                let outer = metadata
+                    .location()
+                    .map(|loc| env.set_location(Some(loc.clone())));
+                let result = expr.compile_statements(env, output);
+                if let Some(outer) = outer {
+                    env.set_location(outer);
+                }
+                result.map_err(|e| e.annotate(metadata))
+            }
+            Self::Declare(decl, body) => {
+                decl.compile_open(env, output)?;
+                body.compile_statements(env, output)
+            }
+            Self::Many(mut exprs) => {
+                let last = exprs.pop();
+                // Only the last expression can continue the spine. The others are
+                // expression statements, and their blocks are closed like usual.
+                for expr in exprs {
+                    expr.compile_statement(env, output)?;
+                }
+                match last {
+                    Some(last) => last.compile_statements(env, output),
+                    None => Ok(()),
+                }
+            }
+            expr => expr.compile_statement(env, output),
+        }
+    }
+
+    /// Compile an expression statement, discarding its value.
+    fn compile_statement(
+        self,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let unused_size = self.get_size(env)?;
+        env.compile_args([self], output)?;
+        if unused_size > 0 {
+            output.op(CoreOp::Pop(None, unused_size));
+        }
+        Ok(())
+    }
+}
+
+/// Is a source code location on the same line of the same file as another?
+fn same_line(a: Option<&SourceCodeLocation>, b: &SourceCodeLocation) -> bool {
+    a.is_some_and(|a| a.line == b.line && a.filename == b.filename)
+}
+
+/// Compile a program into a standard assembly program, only allowing the standard
+/// instructions supported by the target in the environment (if it has one).
+fn compile_for_target(
+    program: &(impl Compile + Clone),
+    env: &Env,
+    output: &mut StandardProgram,
+) -> Result<(), Error> {
+    let program = program.clone();
+    match env.get_target_features() {
+        Some(features) => {
+            program.compile_expr(&mut env.clone(), &mut TargetProgram::new(output, features))
+        }
+        None => program.compile_expr(&mut env.clone(), output),
+    }
+}
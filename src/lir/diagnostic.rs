@@ -0,0 +1,326 @@
+//! # Diagnostics
+//!
+//! A diagnostic is a message about the source code being compiled, like an error,
+//! along with the locations in the source code it refers to. Diagnostics are
+//! rendered to plain text with the offending source lines and carets underneath
+//! them, so they don't depend on any particular terminal library.
+//!
+//! ```text
+//! error[E0028]: mismatched types: expected Int, found Char in 'a'
+//!  --> main.sg:3:14
+//!   |
+//! 3 | let x: Int = 'a';
+//!   |              ^^^
+//!   = note: expected type Int, found type Char
+//! ```
+use super::{Annotation, Error, ErrorCode};
+use crate::parse::SourceCodeLocation;
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use serde_json::{json, Value};
+
+/// How severe a diagnostic is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Some extra information about the code.
+    Note,
+    /// The code compiles, but is likely wrong.
+    Warning,
+    /// The code does not compile.
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Note => write!(f, "note"),
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A note attached to a diagnostic, optionally pointing to another location in the source code.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Note {
+    /// The message of the note.
+    pub message: String,
+    /// The location the note refers to, if any.
+    pub location: Option<SourceCodeLocation>,
+}
+
+/// A message about the source code, with the locations it refers to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// How severe the diagnostic is.
+    pub severity: Severity,
+    /// The code of the kind of error the diagnostic is about, if it has one.
+    pub code: Option<&'static str>,
+    /// The message of the diagnostic.
+    pub message: String,
+    /// The location of the code the diagnostic is about, if it is known.
+    pub location: Option<SourceCodeLocation>,
+    /// The related notes.
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    /// Create a new diagnostic with the given severity and message.
+    pub fn new(severity: Severity, message: impl ToString) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.to_string(),
+            location: None,
+            notes: vec![],
+        }
+    }
+
+    /// Create a new error diagnostic.
+    pub fn error(message: impl ToString) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    /// Create a new warning diagnostic.
+    pub fn warning(message: impl ToString) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Create an error diagnostic for an error from parsing frontend code.
+    /// These errors start with their code, like `[E0100]`, which is moved to the diagnostic.
+    pub fn parse_error(error: &str) -> Self {
+        let coded = error
+            .strip_prefix('[')
+            .and_then(|error| error.split_once("] "))
+            .and_then(|(code, message)| Some((ErrorCode::find(code)?, message)));
+        match coded {
+            Some((code, message)) => Self::error(message).with_code(code.code),
+            None => Self::error(error),
+        }
+    }
+
+    /// Give the diagnostic the code of the kind of error it's about.
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Point the diagnostic at a location in the source code.
+    pub fn with_location(mut self, location: SourceCodeLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    /// Point the diagnostic at the location in an annotation, if it has one.
+    pub fn with_annotation(mut self, annotation: &Annotation) -> Self {
+        if let Some(location) = annotation.location() {
+            self.location = Some(location.clone());
+        }
+        self
+    }
+
+    /// Add a note to the diagnostic.
+    pub fn with_note(mut self, message: impl ToString) -> Self {
+        self.notes.push(Note {
+            message: message.to_string(),
+            location: None,
+        });
+        self
+    }
+
+    /// Add a note to the diagnostic which points at another location in the source code.
+    pub fn with_note_at(mut self, message: impl ToString, location: SourceCodeLocation) -> Self {
+        self.notes.push(Note {
+            message: message.to_string(),
+            location: Some(location),
+        });
+        self
+    }
+
+    /// Render the diagnostic as plain text, using the given source code
+    /// to display the lines the diagnostic refers to.
+    pub fn render(&self, source: &str) -> String {
+        let mut result = format!("{}: {}\n", self.header(), self.message);
+        // The width of the gutter containing the line numbers.
+        let gutter = self
+            .location
+            .iter()
+            .chain(self.notes.iter().filter_map(|note| note.location.as_ref()))
+            .map(|loc| loc.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+
+        if let Some(loc) = &self.location {
+            result += &render_snippet(loc, source, gutter, '^', None);
+        }
+
+        for note in &self.notes {
+            match &note.location {
+                Some(loc) => {
+                    result += &render_snippet(loc, source, gutter, '-', Some(&note.message));
+                }
+                None => {
+                    result += &format!("{:gutter$} = note: {}\n", "", note.message);
+                }
+            }
+        }
+        result
+    }
+
+    /// The diagnostic as JSON, for editors and other tools to read. The notes
+    /// are its children, and the locations are its spans, with lines and
+    /// columns counted from one.
+    ///
+    /// ```json
+    /// {
+    ///   "severity": "error",
+    ///   "code": "E0028",
+    ///   "message": "mismatched types: expected Int, found Char in 'a'",
+    ///   "spans": [{ "file": "main.sg", "line": 3, "column": 14, "offset": 31, "length": 3 }],
+    ///   "children": [{ "severity": "note", "message": "expected type Int, found type Char", "spans": [] }]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> Value {
+        let children = self
+            .notes
+            .iter()
+            .map(|note| {
+                json!({
+                    "severity": Severity::Note.to_string(),
+                    "message": note.message,
+                    "spans": note.location.iter().map(span_json).collect::<Vec<_>>(),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "severity": self.severity.to_string(),
+            "code": self.code,
+            "message": self.message,
+            "spans": self.location.iter().map(span_json).collect::<Vec<_>>(),
+            "children": children,
+        })
+    }
+
+    /// The severity of the diagnostic, followed by its code if it has one, like `error[E0028]`.
+    fn header(&self) -> String {
+        match self.code {
+            Some(code) => format!("{}[{code}]", self.severity),
+            None => self.severity.to_string(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match &self.location {
+            Some(loc) => write!(f, "{loc}: {}: {}", self.header(), self.message)?,
+            None => write!(f, "{}: {}", self.header(), self.message)?,
+        }
+        for note in &self.notes {
+            match &note.location {
+                Some(loc) => write!(f, "\n{loc}: note: {}", note.message)?,
+                None => write!(f, "\nnote: {}", note.message)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A location in the source code as JSON.
+fn span_json(loc: &SourceCodeLocation) -> Value {
+    json!({
+        "file": loc.filename,
+        "line": loc.line,
+        "column": loc.column,
+        "offset": loc.offset,
+        "length": loc.length,
+    })
+}
+
+/// Render the line of source code at a location, with a marker underneath the code at the location.
+fn render_snippet(
+    loc: &SourceCodeLocation,
+    source: &str,
+    gutter: usize,
+    marker: char,
+    label: Option<&str>,
+) -> String {
+    let mut result = format!("{:gutter$}--> {loc}\n", "");
+    let Some(line) = source.lines().nth(loc.line.saturating_sub(1)) else {
+        return result;
+    };
+    // Tabs are expanded so that the marker lines up with the code.
+    let line = line.replace('\t', "    ");
+    let column = loc.column.saturating_sub(1).min(line.chars().count());
+    let length = loc
+        .length
+        .unwrap_or(1)
+        .clamp(1, (line.chars().count() - column).max(1));
+
+    result += &format!("{:gutter$} |\n", "");
+    result += &format!("{:>gutter$} | {line}\n", loc.line);
+    result += &format!(
+        "{:gutter$} | {}{}",
+        "",
+        " ".repeat(column),
+        marker.to_string().repeat(length)
+    );
+    if let Some(label) = label {
+        result += &format!(" {label}");
+    }
+    result.push('\n');
+    result
+}
+
+impl Error {
+    /// Get the location of the source code which caused this error, if it is known.
+    pub fn location(&self) -> Option<&SourceCodeLocation> {
+        match self {
+            Self::Annotated(err, annotation) => annotation.location().or_else(|| err.location()),
+            _ => None,
+        }
+    }
+
+    /// Get the error without any of the annotations attached to it.
+    pub fn unannotated(&self) -> &Self {
+        match self {
+            Self::Annotated(err, _) => err.unannotated(),
+            _ => self,
+        }
+    }
+
+    /// Create a diagnostic for each of the errors that make up this error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.errors().into_iter().map(|err| err.diagnostic()).collect()
+    }
+
+    /// Create a diagnostic describing this error, which can be rendered with the source code.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let err = self.unannotated();
+        let mut diagnostic = Diagnostic::error(err);
+        if let Some(code) = err.code() {
+            diagnostic = diagnostic.with_code(code);
+        }
+        if let Some(loc) = self.location() {
+            diagnostic = diagnostic.with_location(loc.clone());
+        }
+
+        match err {
+            Self::MismatchedTypes {
+                expected, found, ..
+            } => diagnostic.with_note(format!("expected type {expected}, found type {found}")),
+            Self::MismatchedMutability {
+                expected, found, ..
+            } => diagnostic.with_note(format!("expected {expected} access, found {found} access")),
+            Self::Hole { fits, .. } if fits.is_empty() => {
+                diagnostic.with_note("no bindings in scope fit this hole")
+            }
+            Self::Hole { fits, .. } => fits.iter().fold(
+                diagnostic.with_note("bindings in scope that fit this hole:"),
+                |diagnostic, (name, ty)| diagnostic.with_note(format!("  {name}: {ty}")),
+            ),
+            Self::AssemblyError(_) => diagnostic
+                .with_note("this is likely a bug in the compiler or in a handwritten builtin"),
+            _ => diagnostic,
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! # Documentation
+//!
+//! This module collects the documentation of a program from its declarations:
+//! the `doc` attributes on its types and procedures (written as `///` comments in
+//! the frontend), the signatures of its procedures, and the layouts of its types.
+//! The documentation is rendered as Markdown or HTML by the `sage doc` command.
+//!
+//! Only the declarations at the top level of the program and of the modules it
+//! declares are documented. The procedures declared in an `impl` block for a type
+//! are documented along with the type.
+use super::{Attribute, ConstExpr, Declaration, Env, Expr, GetSize, Mutability, Type};
+use std::fmt::Write;
+
+/// The formats documentation can be rendered in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl DocFormat {
+    /// The extension of a file in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// The documentation of a module, or of the top level of a program.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleDocs {
+    /// The path of the module, like `geometry.shapes`.
+    pub name: String,
+    /// The types declared in the module.
+    pub types: Vec<TypeDocs>,
+    /// The procedures declared in the module.
+    pub procs: Vec<ProcDocs>,
+    /// The modules declared in the module.
+    pub modules: Vec<ModuleDocs>,
+}
+
+/// The documentation of a type declaration.
+#[derive(Clone, Debug)]
+pub struct TypeDocs {
+    pub name: String,
+    pub doc: Option<String>,
+    pub ty: Type,
+    /// The size of the type in cells. Polymorphic types don't have a size.
+    pub size: Option<usize>,
+    /// The members of the type, with their types and offsets in cells, in layout order.
+    pub fields: Vec<(String, Type, usize)>,
+    /// The variants of the type, if it's an enum.
+    pub variants: Vec<String>,
+    /// The procedures declared for the type in `impl` blocks.
+    pub methods: Vec<ProcDocs>,
+}
+
+/// The documentation of a procedure declaration.
+#[derive(Clone, Debug)]
+pub struct ProcDocs {
+    pub name: String,
+    pub doc: Option<String>,
+    /// The signature of the procedure, like `fun add(a: Int, b: Int): Int`.
+    pub signature: String,
+}
+
+impl ModuleDocs {
+    /// Collect the documentation of a program. The prelude is the code the program
+    /// is compiled with, like the builtins and the standard library: it isn't
+    /// documented, but the layouts of the program's types can depend on it.
+    pub fn from_program(name: impl ToString, program: &Expr, prelude: &Expr, env: &Env) -> Self {
+        let mut env = env.clone();
+        define(
+            &mut env,
+            &Declaration::many(prelude.top_level_declarations()).flatten(),
+        );
+        Self::new(name, program.top_level_declarations(), &env)
+    }
+
+    /// Collect the documentation of a list of declarations.
+    pub fn new(name: impl ToString, decls: Vec<Declaration>, env: &Env) -> Self {
+        let name = name.to_string();
+        let decls = Declaration::many(decls).flatten();
+        let mut env = env.clone();
+        define(&mut env, &decls);
+
+        let mut docs = Self {
+            name: name.clone(),
+            ..Self::default()
+        };
+        for decl in &decls {
+            match decl {
+                Declaration::Type(name, ty, attributes) => {
+                    docs.types.push(TypeDocs::new(name, ty, attributes, &env))
+                }
+                Declaration::Proc(name, proc) => docs.procs.push(ProcDocs::new(
+                    name,
+                    &[],
+                    proc.get_args(),
+                    proc.get_ret(),
+                    proc.get_attributes(),
+                )),
+                Declaration::PolyProc(name, proc) => docs.procs.push(ProcDocs::new(
+                    name,
+                    proc.get_type_params(),
+                    proc.get_args(),
+                    proc.get_ret(),
+                    proc.get_attributes(),
+                )),
+                Declaration::Module(module, decls, ..) => {
+                    docs.modules
+                        .push(Self::new(format!("{name}.{module}"), decls.to_vec(), &env))
+                }
+                _ => {}
+            }
+        }
+
+        // Document the procedures in `impl` blocks with the types they're declared for.
+        for decl in &decls {
+            let Declaration::Impl(ty, items) = decl else {
+                continue;
+            };
+            let ty_name = match ty {
+                Type::Symbol(name) => name,
+                Type::Apply(ty, _) => match &**ty {
+                    Type::Symbol(name) => name,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            let Some(ty_docs) = docs.types.iter_mut().find(|ty| &ty.name == ty_name) else {
+                continue;
+            };
+            for (name, item) in items {
+                match item {
+                    ConstExpr::Proc(proc) => ty_docs.methods.push(ProcDocs::new(
+                        name,
+                        &[],
+                        proc.get_args(),
+                        proc.get_ret(),
+                        proc.get_attributes(),
+                    )),
+                    ConstExpr::PolyProc(proc) => ty_docs.methods.push(ProcDocs::new(
+                        name,
+                        proc.get_type_params(),
+                        proc.get_args(),
+                        proc.get_ret(),
+                        proc.get_attributes(),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+        docs
+    }
+
+    /// Render the documentation of this module and the modules inside it.
+    pub fn render(&self, format: DocFormat) -> String {
+        let mut output = String::new();
+        match format {
+            DocFormat::Markdown => self.render_markdown(&mut output),
+            DocFormat::Html => {
+                output += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+                let _ = writeln!(output, "<title>{}</title>", escape(&self.name));
+                output += "</head>\n<body>\n";
+                self.render_html(&mut output);
+                output += "</body>\n</html>\n";
+            }
+        }
+        output
+    }
+
+    fn render_markdown(&self, output: &mut String) {
+        let _ = writeln!(output, "# Module `{}`\n", self.name);
+        if !self.modules.is_empty() {
+            output.push_str("## Modules\n\n");
+            for module in &self.modules {
+                let _ = writeln!(output, "- `{}`", module.name);
+            }
+            output.push('\n');
+        }
+        if !self.types.is_empty() {
+            output.push_str("## Types\n\n");
+            for ty in &self.types {
+                let _ = writeln!(output, "### `{}`\n", ty.name);
+                if let Some(doc) = &ty.doc {
+                    let _ = writeln!(output, "{doc}\n");
+                }
+                let _ = writeln!(output, "```\ntype {} = {}\n```\n", ty.name, ty.ty);
+                if let Some(size) = ty.size {
+                    let _ = writeln!(output, "Size: {size} cells\n");
+                }
+                if !ty.fields.is_empty() {
+                    output.push_str("|Field|Type|Offset|\n|---|---|---|\n");
+                    for (name, field_ty, offset) in &ty.fields {
+                        let _ = writeln!(output, "|`{name}`|`{field_ty}`|{offset}|");
+                    }
+                    output.push('\n');
+                }
+                if !ty.variants.is_empty() {
+                    let variants = ty.variants.iter().map(|v| format!("`{v}`"));
+                    let _ = writeln!(
+                        output,
+                        "Variants: {}\n",
+                        variants.collect::<Vec<_>>().join(", ")
+                    );
+                }
+                for method in &ty.methods {
+                    let _ = writeln!(output, "#### `{}`\n", method.signature);
+                    if let Some(doc) = &method.doc {
+                        let _ = writeln!(output, "{doc}\n");
+                    }
+                }
+            }
+        }
+        if !self.procs.is_empty() {
+            output.push_str("## Procedures\n\n");
+            for proc in &self.procs {
+                let _ = writeln!(output, "### `{}`\n", proc.signature);
+                if let Some(doc) = &proc.doc {
+                    let _ = writeln!(output, "{doc}\n");
+                }
+            }
+        }
+        for module in &self.modules {
+            module.render_markdown(output);
+        }
+    }
+
+    fn render_html(&self, output: &mut String) {
+        let _ = writeln!(
+            output,
+            "<h1 id=\"{0}\">Module <code>{0}</code></h1>",
+            escape(&self.name)
+        );
+        if !self.modules.is_empty() {
+            output.push_str("<h2>Modules</h2>\n<ul>\n");
+            for module in &self.modules {
+                let _ = writeln!(
+                    output,
+                    "<li><a href=\"#{0}\"><code>{0}</code></a></li>",
+                    escape(&module.name)
+                );
+            }
+            output.push_str("</ul>\n");
+        }
+        if !self.types.is_empty() {
+            output.push_str("<h2>Types</h2>\n");
+            for ty in &self.types {
+                let _ = writeln!(output, "<h3><code>{}</code></h3>", escape(&ty.name));
+                render_html_doc(&ty.doc, output);
+                let _ = writeln!(
+                    output,
+                    "<pre><code>type {} = {}</code></pre>",
+                    escape(&ty.name),
+                    escape(&ty.ty.to_string())
+                );
+                if let Some(size) = ty.size {
+                    let _ = writeln!(output, "<p>Size: {size} cells</p>");
+                }
+                if !ty.fields.is_empty() {
+                    output
+                        .push_str("<table>\n<tr><th>Field</th><th>Type</th><th>Offset</th></tr>\n");
+                    for (name, field_ty, offset) in &ty.fields {
+                        let _ = writeln!(
+                            output,
+                            "<tr><td><code>{}</code></td><td><code>{}</code></td><td>{offset}</td></tr>",
+                            escape(name),
+                            escape(&field_ty.to_string())
+                        );
+                    }
+                    output.push_str("</table>\n");
+                }
+                if !ty.variants.is_empty() {
+                    let variants = ty
+                        .variants
+                        .iter()
+                        .map(|v| format!("<code>{}</code>", escape(v)));
+                    let _ = writeln!(
+                        output,
+                        "<p>Variants: {}</p>",
+                        variants.collect::<Vec<_>>().join(", ")
+                    );
+                }
+                for method in &ty.methods {
+                    let _ = writeln!(
+                        output,
+                        "<h4><code>{}</code></h4>",
+                        escape(&method.signature)
+                    );
+                    render_html_doc(&method.doc, output);
+                }
+            }
+        }
+        if !self.procs.is_empty() {
+            output.push_str("<h2>Procedures</h2>\n");
+            for proc in &self.procs {
+                let _ = writeln!(output, "<h3><code>{}</code></h3>", escape(&proc.signature));
+                render_html_doc(&proc.doc, output);
+            }
+        }
+        for module in &self.modules {
+            module.render_html(output);
+        }
+    }
+}
+
+impl TypeDocs {
+    fn new(name: &str, ty: &Type, attributes: &[Attribute], env: &Env) -> Self {
+        let mut fields = vec![];
+        let mut variants = vec![];
+        // A polymorphic type only has a layout once it's applied to type arguments.
+        if !matches!(ty, Type::Poly(..)) {
+            let members: Vec<(String, Type)> = match ty.simplify_until_has_members(env, false) {
+                Ok(Type::Struct(fields)) | Ok(Type::Union(fields)) => fields.into_iter().collect(),
+                Ok(Type::Repr(fields, _)) => fields,
+                Ok(Type::Tuple(items)) => items
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, item)| (i.to_string(), item))
+                    .collect(),
+                _ => vec![],
+            };
+            for (member, member_ty) in members {
+                let access = match member.parse::<i64>() {
+                    Ok(i) => ConstExpr::Int(i),
+                    Err(_) => ConstExpr::Symbol(member.clone()),
+                };
+                if let Ok(offset) = ty.get_offset_of(&access, env) {
+                    fields.push((member, member_ty, offset));
+                }
+            }
+            fields.sort_by_key(|(_, _, offset)| *offset);
+
+            variants = match ty.simplify_until_has_variants(env, false) {
+                Ok(Type::Enum(variants, _)) => variants,
+                Ok(Type::EnumUnion(variants, _)) => variants.into_keys().collect(),
+                _ => vec![],
+            };
+        }
+
+        Self {
+            name: name.to_string(),
+            doc: Attribute::find_doc(attributes),
+            ty: ty.clone(),
+            size: match ty {
+                Type::Poly(..) => None,
+                ty => ty.get_size(env).ok(),
+            },
+            fields,
+            variants,
+            methods: vec![],
+        }
+    }
+}
+
+impl ProcDocs {
+    fn new(
+        name: &str,
+        ty_params: &[(String, Option<Type>)],
+        args: &[(String, Mutability, Type)],
+        ret: &Type,
+        attributes: &[Attribute],
+    ) -> Self {
+        let mut signature = format!("fun {name}");
+        if !ty_params.is_empty() {
+            let ty_params = ty_params.iter().map(|(param, ty)| match ty {
+                Some(ty) => format!("{param}: {ty}"),
+                None => param.clone(),
+            });
+            let _ = write!(signature, "<{}>", ty_params.collect::<Vec<_>>().join(", "));
+        }
+        let args = args.iter().map(|(arg, mutability, ty)| match mutability {
+            Mutability::Mutable => format!("mut {arg}: {ty}"),
+            _ => format!("{arg}: {ty}"),
+        });
+        let _ = write!(signature, "({})", args.collect::<Vec<_>>().join(", "));
+        if *ret != Type::None {
+            let _ = write!(signature, ": {ret}");
+        }
+
+        Self {
+            name: name.to_string(),
+            doc: Attribute::find_doc(attributes),
+            signature,
+        }
+    }
+}
+
+/// Define the types and procedures of some declarations in an environment.
+/// Types which can't be defined are documented without their layouts.
+fn define(env: &mut Env, decls: &[Declaration]) {
+    for decl in decls {
+        let _ = env.add_compile_time_declaration(decl, false);
+    }
+}
+
+/// Write documentation text as HTML paragraphs.
+fn render_html_doc(doc: &Option<String>, output: &mut String) {
+    if let Some(doc) = doc {
+        for paragraph in doc.split("\n\n") {
+            let _ = writeln!(output, "<p>{}</p>", escape(paragraph));
+        }
+    }
+}
+
+/// Escape text for HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,295 @@
+//! # Effects
+//!
+//! This module finds the side effects that running some LIR code can have,
+//! other than reading and writing memory:
+//!
+//! - I/O: reading input, writing output, or panicking with a message.
+//! - FFI: calling a foreign function, which can do anything.
+//! - Heap allocation: allocating, freeing, or inspecting the heap.
+//!
+//! Effects are inferred bottom-up: an operation has the effects of its builtin
+//! operator, a builtin procedure has the effects of the instructions in its body,
+//! and a call to a procedure has the effects of the procedure's body. A call to a
+//! procedure which isn't known at compile time (like a procedure passed as an argument)
+//! is assumed to have every effect.
+//!
+//! Code without any of these effects is *pure*: running it twice gives the same
+//! result, so only pure code is evaluated at compile time, and transformations which
+//! remove, merge, or reorder calls must check that the calls are pure first.
+//! A procedure can be marked with the `pure` attribute, which is checked:
+//!
+//! ```text
+//! #[pure]
+//! fun square(x: Int): Int = x * x;
+//!
+//! #[pure]
+//! fun greet(): Int {
+//!     println("hello!"); // error: `greet` is marked `pure`, but it performs I/O
+//!     return 0;
+//! }
+//! ```
+use super::{
+    walk_declaration, walk_expr, ConstExpr, Declaration, Env, Expr, PolyProcedure, Procedure, Visit,
+};
+use crate::asm::{CoreOp, StandardOp};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::ops::{BitOr, BitOrAssign};
+use std::collections::{HashMap, HashSet};
+
+/// The side effects that some code can have.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Effects {
+    /// Does the code read input, write output, or panic?
+    pub io: bool,
+    /// Does the code call a foreign function?
+    pub ffi: bool,
+    /// Does the code allocate, free, or inspect heap memory?
+    pub alloc: bool,
+}
+
+impl Effects {
+    /// No side effects.
+    pub const PURE: Self = Self {
+        io: false,
+        ffi: false,
+        alloc: false,
+    };
+    /// Only I/O.
+    pub const IO: Self = Self {
+        io: true,
+        ..Self::PURE
+    };
+    /// Only calls to foreign functions.
+    pub const FFI: Self = Self {
+        ffi: true,
+        ..Self::PURE
+    };
+    /// Only heap allocation.
+    pub const ALLOC: Self = Self {
+        alloc: true,
+        ..Self::PURE
+    };
+    /// Every side effect, for code we can't see into.
+    pub const ALL: Self = Self {
+        io: true,
+        ffi: true,
+        alloc: true,
+    };
+
+    /// Does this have no side effects?
+    pub fn is_pure(&self) -> bool {
+        *self == Self::PURE
+    }
+
+    /// The effects of some core assembly instructions.
+    pub fn of_core_ops(ops: &[CoreOp]) -> Self {
+        ops.iter().fold(Self::PURE, |effects, op| match op {
+            CoreOp::Get(..) | CoreOp::Put(..) => effects | Self::IO,
+            _ => effects,
+        })
+    }
+
+    /// The effects of some standard assembly instructions.
+    pub fn of_standard_ops(ops: &[StandardOp]) -> Self {
+        ops.iter().fold(Self::PURE, |effects, op| match op {
+            StandardOp::CoreOp(op) => effects | Self::of_core_ops(std::slice::from_ref(op)),
+            StandardOp::Call(_) => effects | Self::FFI,
+            StandardOp::Alloc(_)
+            | StandardOp::Free(_)
+            | StandardOp::Realloc { .. }
+            | StandardOp::HeapUsed(_)
+            | StandardOp::HeapFree(_) => effects | Self::ALLOC,
+            _ => effects,
+        })
+    }
+}
+
+impl BitOr for Effects {
+    type Output = Self;
+
+    /// Combine the effects of two pieces of code.
+    fn bitor(self, other: Self) -> Self {
+        Self {
+            io: self.io || other.io,
+            ffi: self.ffi || other.ffi,
+            alloc: self.alloc || other.alloc,
+        }
+    }
+}
+
+impl BitOrAssign for Effects {
+    fn bitor_assign(&mut self, other: Self) {
+        *self = *self | other;
+    }
+}
+
+impl Display for Effects {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let mut names = vec![];
+        if self.io {
+            names.push("I/O");
+        }
+        if self.ffi {
+            names.push("FFI calls");
+        }
+        if self.alloc {
+            names.push("heap allocation");
+        }
+        if names.is_empty() {
+            write!(f, "no side effects")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+impl Expr {
+    /// Find the side effects that evaluating this expression can have.
+    pub fn get_effects(&self, env: &Env) -> Effects {
+        let mut pass = EffectsPass::new(env);
+        pass.visit_expr(self);
+        pass.effects
+    }
+}
+
+impl Procedure {
+    /// Find the side effects that calling this procedure can have.
+    pub fn get_effects(&self, env: &Env) -> Effects {
+        let mut pass = EffectsPass::new(env);
+        pass.call_procedure(self);
+        pass.effects
+    }
+}
+
+/// Walks over code, collecting the effects of the operations it performs
+/// and the procedures it calls.
+struct EffectsPass<'e> {
+    env: &'e Env,
+    effects: Effects,
+    /// The procedures which have already been walked, so recursion terminates.
+    called: HashSet<String>,
+    /// The constants declared inside the code, which hide the environment's.
+    consts: HashMap<String, ConstExpr>,
+    /// The variables and parameters declared inside the code.
+    /// A call through one of these could be to any procedure.
+    locals: HashSet<String>,
+}
+
+impl<'e> EffectsPass<'e> {
+    fn new(env: &'e Env) -> Self {
+        Self {
+            env,
+            effects: Effects::PURE,
+            called: HashSet::new(),
+            consts: HashMap::new(),
+            locals: HashSet::new(),
+        }
+    }
+
+    /// Add the effects of running a procedure's body.
+    fn call_procedure(&mut self, proc: &Procedure) {
+        if self.called.insert(proc.get_mangled_name().to_string()) {
+            for (name, ..) in proc.get_args() {
+                self.locals.insert(name.clone());
+            }
+            self.visit_expr(proc.get_body());
+        }
+    }
+
+    /// Add the effects of calling an expression as a procedure.
+    fn call(&mut self, f: &Expr) {
+        match f {
+            Expr::Annotated(f, _) => self.call(f),
+            Expr::ConstExpr(f) => self.call_const(f),
+            // Methods and procedure values could be anything.
+            _ => self.effects = Effects::ALL,
+        }
+    }
+
+    /// Add the effects of calling a constant as a procedure.
+    fn call_const(&mut self, f: &ConstExpr) {
+        match f {
+            ConstExpr::Annotated(f, _) => self.call_const(f),
+            ConstExpr::Proc(proc) => self.call_procedure(proc),
+            ConstExpr::PolyProc(proc) => {
+                if self.called.insert(proc.get_name().to_string()) {
+                    self.visit_expr(proc.get_body());
+                }
+            }
+            ConstExpr::Monomorphize(f, _) => self.call_const(f),
+            ConstExpr::CoreBuiltin(builtin) => self.effects |= Effects::of_core_ops(&builtin.body),
+            ConstExpr::StandardBuiltin(builtin) => {
+                self.effects |= Effects::of_standard_ops(&builtin.body)
+            }
+            ConstExpr::FFIProcedure(_) => self.effects |= Effects::FFI,
+            ConstExpr::Symbol(name) if self.locals.contains(name) => self.effects = Effects::ALL,
+            ConstExpr::Symbol(name) => {
+                match self.consts.get(name).or_else(|| self.env.get_const(name)) {
+                    Some(f) => {
+                        let f = f.clone();
+                        self.call_const(&f)
+                    }
+                    None => self.effects = Effects::ALL,
+                }
+            }
+            // Look up associated procedures and module members.
+            ConstExpr::Member(..) => match f.clone().eval(self.env) {
+                Ok(ConstExpr::Member(..)) | Err(_) => self.effects = Effects::ALL,
+                Ok(f) => self.call_const(&f),
+            },
+            _ => self.effects = Effects::ALL,
+        }
+    }
+}
+
+impl<'a> Visit<'a> for EffectsPass<'_> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::UnaryOp(op, _) => match self.env.get_unop(op) {
+                Some(op) => self.effects |= op.effects(),
+                None => self.effects = Effects::ALL,
+            },
+            Expr::BinaryOp(op, ..) => match self.env.get_binop(op) {
+                Some(op) => self.effects |= op.effects(),
+                None => self.effects = Effects::ALL,
+            },
+            Expr::TernaryOp(op, ..) => match self.env.get_ternop(op) {
+                Some(op) => self.effects |= op.effects(),
+                None => self.effects = Effects::ALL,
+            },
+            Expr::Apply(f, _) => self.call(f),
+            _ => {}
+        }
+        walk_expr(self, expr)
+    }
+
+    fn visit_declaration(&mut self, decl: &'a Declaration) {
+        match decl {
+            Declaration::Var(name, ..) | Declaration::StaticVar(name, ..) => {
+                self.locals.insert(name.clone());
+            }
+            Declaration::Const(name, cexpr) => {
+                self.consts.insert(name.clone(), cexpr.clone());
+            }
+            Declaration::Proc(name, proc) => {
+                self.consts
+                    .insert(name.clone(), ConstExpr::Proc(proc.clone()));
+            }
+            Declaration::PolyProc(name, proc) => {
+                self.consts
+                    .insert(name.clone(), ConstExpr::PolyProc(proc.clone()));
+            }
+            Declaration::ExternProc(name, proc) => {
+                self.consts
+                    .insert(name.clone(), ConstExpr::FFIProcedure(proc.clone()));
+            }
+            _ => {}
+        }
+        walk_declaration(self, decl)
+    }
+
+    // Defining a procedure doesn't run it: its effects are added where it's called.
+    fn visit_procedure(&mut self, _proc: &'a Procedure) {}
+
+    fn visit_poly_procedure(&mut self, _proc: &'a PolyProcedure) {}
+}
@@ -0,0 +1,176 @@
+//! # Program Entry
+//!
+//! A program is an expression, which runs from top to bottom. A program can also
+//! declare a `main` procedure at its top level, which the compiler calls after the
+//! rest of the top level runs:
+//!
+//! ```text
+//! fun main(argc: Int, argv: &&Char): Int {
+//!     if argc < 2 {
+//!         println("expected an argument");
+//!         return 1;
+//!     }
+//!     println("got ", argc - 1, " arguments");
+//!     return 0;
+//! }
+//! ```
+//!
+//! `main` takes either no arguments, or the number of arguments and a pointer to an
+//! array of pointers to them as null-terminated strings, followed by a null pointer
+//! (like `argc` and `argv` in C). The arguments are set on the environment, and the
+//! first is the name of the program by convention.
+//!
+//! `main` returns either `None`, or an `Int` exit status, which the program halts with.
+//! A status of `0` means success; the interpreters report any other status as a
+//! `RuntimeError::Exit`, and the C target passes it to `exit`.
+//!
+//! Programs written before this convention declare `main` as an ordinary procedure,
+//! and call it themselves. So `main` is only called automatically if it takes the
+//! arguments or returns a status, and the top level of the program doesn't refer to it.
+use super::{
+    walk_const, ConstExpr, Declaration, Env, Error, Exit, Expr, Mutability, Procedure, Type, Visit,
+};
+
+/// The name of the procedure that a program starts at.
+pub const ENTRY_POINT: &str = "main";
+/// The name of the array of pointers to the program's arguments.
+const ARGV: &str = "__argv";
+
+impl Expr {
+    /// Call the program's `main` procedure after its top level runs, passing it the
+    /// environment's program arguments, and halt with the exit status it returns.
+    ///
+    /// Programs without a `main` procedure which takes the arguments or returns a status,
+    /// or which call it themselves, are unchanged.
+    pub fn with_entry(mut self, env: &Env) -> Result<Self, Error> {
+        if let Some((main, body)) = find_main(&mut self) {
+            if refers_to_main(body) {
+                return Ok(self);
+            }
+            if let Some(call) = call_main(&main, env)? {
+                let top_level = std::mem::replace(body, Expr::NONE);
+                *body = Expr::Many(vec![top_level, call]);
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Find the `main` procedure declared by the outermost scopes of a program,
+/// and the body of the scope which declares it.
+fn find_main(expr: &mut Expr) -> Option<(Procedure, &mut Expr)> {
+    match expr {
+        Expr::Annotated(expr, _) => find_main(expr),
+        // The program's own declarations are in the innermost of the outer scopes,
+        // inside the scopes of the builtins and the standard library.
+        Expr::Declare(_, body) if has_main(body) => find_main(body),
+        Expr::Declare(decls, body) => declared_main(decls).map(|main| (main, &mut **body)),
+        _ => None,
+    }
+}
+
+/// Do the outermost scopes of an expression declare a `main` procedure?
+fn has_main(expr: &Expr) -> bool {
+    match expr {
+        Expr::Annotated(expr, _) => has_main(expr),
+        Expr::Declare(decls, body) => declared_main(decls).is_some() || has_main(body),
+        _ => false,
+    }
+}
+
+/// Get the `main` procedure declared by a declaration, if it declares one.
+fn declared_main(decl: &Declaration) -> Option<Procedure> {
+    match decl {
+        Declaration::Proc(name, proc) if name == ENTRY_POINT => Some(proc.clone()),
+        // A later declaration shadows an earlier one.
+        Declaration::Many(decls) => decls.iter().rev().find_map(declared_main),
+        _ => None,
+    }
+}
+
+/// Does an expression refer to the `main` procedure?
+fn refers_to_main(expr: &Expr) -> bool {
+    struct Refers(bool);
+
+    impl<'a> Visit<'a> for Refers {
+        fn visit_const(&mut self, expr: &'a ConstExpr) {
+            if matches!(expr, ConstExpr::Symbol(name) if name == ENTRY_POINT) {
+                self.0 = true;
+            }
+            walk_const(self, expr)
+        }
+    }
+
+    let mut refers = Refers(false);
+    refers.visit_expr(expr);
+    refers.0
+}
+
+/// Call the `main` procedure with the environment's program arguments,
+/// and halt with its exit status if it returns one.
+///
+/// A `main` which takes no arguments and returns nothing is an ordinary procedure,
+/// so it isn't called.
+fn call_main(main: &Procedure, env: &Env) -> Result<Option<Expr>, Error> {
+    let returns_status = !matches!(main.get_ret(), Type::None | Type::Never);
+    let call = match main.get_args().len() {
+        0 if !returns_status => return Ok(None),
+        0 => Expr::var(ENTRY_POINT).app(vec![]),
+        2 => {
+            let (decls, argc, argv) = program_args(env);
+            Expr::var(ENTRY_POINT).app(vec![argc, argv]).with(decls)
+        }
+        _ if !returns_status => return Ok(None),
+        _ => {
+            return Err(Error::InvalidEntryPoint(Type::Proc(
+                main.get_args()
+                    .iter()
+                    .map(|(_, _, ty)| ty.clone())
+                    .collect(),
+                Box::new(main.get_ret().clone()),
+            )))
+        }
+    };
+    match returns_status {
+        true => Ok(Some(call.unop(Exit))),
+        false => Ok(Some(call)),
+    }
+}
+
+/// Declare the environment's program arguments as null-terminated strings,
+/// and get the `argc` and `argv` to pass to `main`.
+fn program_args(env: &Env) -> (Declaration, Expr, Expr) {
+    let args = env.get_program_args();
+    let mut decls = vec![];
+    let mut argv = vec![];
+    for (i, arg) in args.iter().enumerate() {
+        let name = format!("{ARGV}_{i}");
+        let chars = arg.chars().chain(std::iter::once('\0'));
+        decls.push(Declaration::Var(
+            name.clone(),
+            Mutability::Immutable,
+            None,
+            Expr::ConstExpr(ConstExpr::Array(chars.map(ConstExpr::Char).collect())),
+        ));
+        argv.push(
+            Expr::var(name)
+                .idx(ConstExpr::Int(0))
+                .refer(Mutability::Immutable),
+        );
+    }
+    // The array of pointers ends with a null pointer, like in C.
+    let char_ptr = Type::Pointer(Mutability::Immutable, Box::new(Type::Char));
+    argv.push(Expr::ConstExpr(ConstExpr::Null).as_type(char_ptr));
+    decls.push(Declaration::Var(
+        ARGV.to_string(),
+        Mutability::Immutable,
+        None,
+        Expr::Array(argv),
+    ));
+
+    let argc = Expr::ConstExpr(ConstExpr::Int(args.len() as i64));
+    let argv = Expr::var(ARGV)
+        .idx(ConstExpr::Int(0))
+        .refer(Mutability::Immutable);
+    (Declaration::many(decls), argc, argv)
+}
@@ -5,14 +5,17 @@
 //! with respect to the frame pointer.
 
 use super::{
-    AssignOp, BinaryOp, Compile, ConstExpr, Declaration, Error, Expr, FFIProcedure, GetSize,
-    GetType, Mutability, PolyProcedure, Procedure, TernaryOp, Type, UnaryOp,
+    AssignOp, Attribute, BinaryOp, BITS_PER_CELL, Compile, ConstExpr, CoreBuiltin, Declaration, Error, Expr, FFIProcedure, GetSize,
+    GetType, InternedType, Lint, MonomorphCache, Mutability, PassTimings, PolyProcedure, Procedure, StandardBuiltin,
+    TernaryOp, Type, TypeCache, TypeInterner, UnaryOp, Warning, WarningKind, WarningLevel,
 };
-use crate::asm::{AssemblyProgram, Globals, Location};
+use crate::asm::{AssemblyProgram, CoreOp, Globals, Location, TargetFeatures, FP, FP_STACK};
+use crate::frontend::Prelude;
+use crate::parse::SourceCodeLocation;
 use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 use std::{
-    collections::{HashMap, HashSet}, sync::{Arc, RwLock}
+    collections::{BTreeMap, HashMap, HashSet}, sync::{Arc, RwLock}
 };
 
 use log::*;
@@ -31,7 +34,11 @@ pub struct Env {
     assignops: Arc<HashMap<String, Box<dyn AssignOp>>>,
 
     /// The types (and also their sizes) defined under the environment.
-    types: Arc<HashMap<String, Type>>,
+    types: Arc<HashMap<String, InternedType>>,
+    /// The interned types shared by every scope of the environment.
+    interner: Arc<RwLock<TypeInterner>>,
+    /// The attributes of the types defined under the environment, if they have any.
+    type_attributes: Arc<HashMap<String, Vec<Attribute>>>,
     /// The constants defined under the environment.
     consts: Arc<HashMap<String, ConstExpr>>,
     /// The procedures defined under the environment.
@@ -55,6 +62,10 @@ pub struct Env {
     // The current offset caused by temporarily pushing arguments onto the stack.
     sp_offset: isize,
     saved_sp_offsets: Vec<isize>,
+    /// The offsets from the frame pointer where each stack allocation in the
+    /// current procedure started a new stack frame. Variables with offsets
+    /// before a stack frame are addressed from the frame pointer it saved.
+    stack_frames: Vec<isize>,
     /// The size of the arguments supplied to the function, in cells.
     /// This is incremented by the size of each argument defined (for a procedure).
     /// This is unaffected by defining *variables* in the scope of the function.
@@ -62,9 +73,64 @@ pub struct Env {
     /// Expected return type of the current function.
     /// This is `None` if we are not currently compiling a function.
     expected_ret: Option<Type>,
+    /// How many inlined procedure bodies are being compiled in this scope.
+    /// This stops recursive `inline` procedures from being expanded forever.
+    inline_depth: usize,
 
     /// Memoized type sizes.
     type_sizes: Arc<HashMap<Type, usize>>,
+    /// Memoized results of type equality, sizes, and simplification under the
+    /// current definitions. This is replaced whenever something is defined.
+    type_cache: Arc<RwLock<TypeCache>>,
+
+    /// The maximum recursion depth for simplifying, sizing, and comparing types.
+    type_recursion_limit: usize,
+    /// The maximum recursion depth for evaluating constant expressions.
+    const_recursion_limit: usize,
+
+    /// The on-disk cache of compiled monomorphs, if one is used.
+    monomorph_cache: Option<Arc<MonomorphCache>>,
+    /// The times of the compiler's passes, if they're being measured.
+    pass_timings: Option<Arc<PassTimings>>,
+
+    /// Check that array indices are in bounds at runtime?
+    bounds_checks: bool,
+    /// Keep a shadow stack of the procedures being called, so panics can print a backtrace?
+    backtraces: bool,
+    /// Compile assertions? If not, they're removed from the program.
+    assertions: bool,
+    /// Reorder the fields of types without a guaranteed layout to make them smaller?
+    reorder_fields: bool,
+    /// Mark the source line each instruction comes from with a comment?
+    debug_info: bool,
+    /// Reject programs which need the standard variant, instead of compiling them to it?
+    core_only: bool,
+    /// What frontend programs can use without declaring it.
+    prelude: Prelude,
+    /// The compile parameters which `cfg` constants are checked against, like the target
+    /// being compiled to. Each is either a flag, or a name with a value.
+    cfg: Arc<BTreeMap<String, Option<String>>>,
+    /// The standard instructions the target being compiled to supports, if it is known.
+    /// Code using other standard instructions is rejected where it is compiled.
+    target_features: Option<Arc<TargetFeatures>>,
+    /// The arguments passed to the program's `main` procedure, starting with the program's name.
+    program_args: Arc<Vec<String>>,
+    /// The source code location of the expression being compiled, if it is known.
+    /// This is reported by runtime checks which fail.
+    location: Option<SourceCodeLocation>,
+
+    /// The warnings reported while type checking, shared by every scope.
+    warnings: Arc<RwLock<Vec<Warning>>>,
+    /// The levels of the kinds of warnings which aren't at their default level.
+    warning_levels: Arc<HashMap<WarningKind, WarningLevel>>,
+    /// The names of the variables used under each enclosing scope
+    /// which is checked for unused variables.
+    used_vars: Vec<Arc<RwLock<HashSet<String>>>>,
+
+    /// The lints to run over the program after it type checks.
+    lints: Arc<Vec<Arc<dyn Lint>>>,
+    /// The levels of the lints which aren't at the level of the `lint` warning.
+    lint_levels: Arc<HashMap<String, WarningLevel>>,
 }
 
 impl Default for Env {
@@ -78,10 +144,29 @@ impl Default for Env {
                 map.insert("get".to_owned(), Box::new(crate::lir::Get));
                 map.insert("put".to_owned(), Box::new(crate::lir::Put::Display));
                 map.insert("debug".to_owned(), Box::new(crate::lir::Put::Debug));
+                map.insert("eput".to_owned(), Box::new(crate::lir::Put::DisplayErr));
+                map.insert("edebug".to_owned(), Box::new(crate::lir::Put::DebugErr));
+                for sense in [
+                    crate::lir::Sense::Key,
+                    crate::lir::Sense::TerminalWidth,
+                    crate::lir::Sense::TerminalHeight,
+                ] {
+                    let read_from = crate::lir::ReadFrom(sense);
+                    map.insert(read_from.to_string(), Box::new(read_from));
+                }
                 map.insert("new".to_owned(), Box::new(crate::lir::New));
                 map.insert("del".to_owned(), Box::new(crate::lir::Delete));
+                map.insert("alloca".to_owned(), Box::new(crate::lir::StackAlloc));
                 map.insert("tag".to_owned(), Box::new(crate::lir::Tag));
                 map.insert("data".to_owned(), Box::new(crate::lir::Data));
+                map.insert("panic".to_owned(), Box::new(crate::lir::Panic));
+                map.insert("exit".to_owned(), Box::new(crate::lir::Exit));
+                map.insert("int_to_string".to_owned(), Box::new(crate::lir::IntToString));
+                map.insert("parse_int".to_owned(), Box::new(crate::lir::ParseNumber::Int));
+                map.insert("parse_float".to_owned(), Box::new(crate::lir::ParseNumber::Float));
+                map.insert("atomic_load".to_owned(), Box::new(crate::lir::AtomicLoad));
+                map.insert("derived_hash".to_owned(), Box::new(crate::lir::DerivedHash));
+                map.insert("serialize".to_owned(), Box::new(crate::lir::ToCells));
 
                 map
             }),
@@ -113,10 +198,56 @@ impl Default for Env {
                 map.insert("&".to_owned(), Box::new(crate::lir::BitwiseAnd));
                 map.insert("|".to_owned(), Box::new(crate::lir::BitwiseOr));
                 map.insert("^".to_owned(), Box::new(crate::lir::BitwiseXor));
+                map.insert("format".to_owned(), Box::new(crate::lir::Format));
+                map.insert("get_from".to_owned(), Box::new(crate::lir::GetFrom));
+                map.insert("is_variant".to_owned(), Box::new(crate::lir::IsVariant));
+                map.insert("expect_variant".to_owned(), Box::new(crate::lir::ExpectVariant));
+                map.insert("assert".to_owned(), Box::new(crate::lir::Assert));
+                map.insert("float_to_string".to_owned(), Box::new(crate::lir::FloatToString));
+                map.insert("atomic_store".to_owned(), Box::new(crate::lir::Atomic::Store));
+                map.insert("atomic_fetch_add".to_owned(), Box::new(crate::lir::Atomic::FetchAdd));
+                map.insert("deserialize".to_owned(), Box::new(crate::lir::FromCells));
+                for derive in [crate::lir::Derive::Eq, crate::lir::Derive::Cmp] {
+                    let op = crate::lir::Derived(derive);
+                    map.insert(op.to_string(), Box::new(op));
+                }
+                for op in crate::lir::OverflowArithmetic::all() {
+                    map.insert(op.to_string(), Box::new(op));
+                }
+                for put in [
+                    crate::lir::Put::Display,
+                    crate::lir::Put::Debug,
+                    crate::lir::Put::DisplayErr,
+                    crate::lir::Put::DebugErr,
+                ] {
+                    let put_to = crate::lir::PutTo(put);
+                    map.insert(put_to.to_string(), Box::new(put_to));
+                }
+                for draw in [
+                    crate::lir::Draw::Column,
+                    crate::lir::Draw::Row,
+                    crate::lir::Draw::Pixel,
+                    crate::lir::Draw::Clear,
+                    crate::lir::Draw::Present,
+                ] {
+                    let draw_to = crate::lir::DrawTo(draw);
+                    map.insert(draw_to.to_string(), Box::new(draw_to));
+                }
+                for play in [crate::lir::Play::Sample, crate::lir::Play::SampleRate] {
+                    let play_to = crate::lir::PlayTo(play);
+                    map.insert(play_to.to_string(), Box::new(play_to));
+                }
                 map
             }),
 
-            ternops: Arc::new(HashMap::new()),
+            ternops: Arc::new({
+                let mut map: HashMap<String, Box<dyn TernaryOp>> = HashMap::new();
+                map.insert(
+                    "atomic_compare_swap".to_owned(),
+                    Box::new(crate::lir::AtomicCompareSwap),
+                );
+                map
+            }),
 
             assignops: Arc::new({
                 let mut map: HashMap<String, Box<dyn AssignOp>> = HashMap::new();
@@ -158,7 +289,10 @@ impl Default for Env {
             // It is important that we use reference counting for the tables because the environment
             // will be copied many times during the compilation process to create new scopes.
             types: Arc::new(HashMap::new()),
+            interner: Arc::new(RwLock::new(TypeInterner::new())),
             type_sizes: Arc::new(HashMap::new()),
+            type_cache: Arc::new(RwLock::new(TypeCache::new())),
+            type_attributes: Arc::new(HashMap::new()),
             consts: Arc::new(HashMap::new()),
             procs: Arc::new(HashMap::new()),
             vars: Arc::new(HashMap::new()),
@@ -173,8 +307,41 @@ impl Default for Env {
             // The last argument is stored at `[FP]`, so our first variable must be at `[FP + 1]`.
             fp_offset: 1,
             sp_offset: 0,
+            stack_frames: Vec::new(),
             args_size: 0,
             expected_ret: None,
+            inline_depth: 0,
+
+            type_recursion_limit: Type::SIMPLIFY_RECURSION_LIMIT,
+            const_recursion_limit: ConstExpr::EVAL_RECURSION_LIMIT,
+            monomorph_cache: None,
+            pass_timings: None,
+
+            bounds_checks: false,
+            backtraces: false,
+            assertions: true,
+            reorder_fields: false,
+            debug_info: true,
+            core_only: false,
+            prelude: Prelude::default(),
+            cfg: Arc::new(
+                [
+                    ("std".to_owned(), None),
+                    ("cell_width".to_owned(), Some("64".to_owned())),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+            target_features: None,
+            program_args: Arc::new(Vec::new()),
+            location: None,
+
+            warnings: Arc::new(RwLock::new(Vec::new())),
+            warning_levels: Arc::new(HashMap::new()),
+            used_vars: Vec::new(),
+
+            lints: Arc::new(Vec::new()),
+            lint_levels: Arc::new(HashMap::new()),
         }
     }
 }
@@ -201,6 +368,8 @@ impl Env {
         Self {
             // Only keep the types, constants, and procedures defined.
             types: self.types.clone(),
+            interner: self.interner.clone(),
+            type_attributes: self.type_attributes.clone(),
             consts: self.consts.clone(),
             procs: self.procs.clone(),
             static_vars: self.static_vars.clone(),
@@ -211,6 +380,7 @@ impl Env {
                 // Arc::new(type_sizes)
                 self.type_sizes.clone()
             },
+            type_cache: self.type_cache.clone(),
             globals: self.globals.clone(),
             processed_monomorphizations: self.processed_monomorphizations.clone(),
             // associated_constants: self.associated_constants.clone(),
@@ -224,12 +394,396 @@ impl Env {
                 // Arc::new(RwLock::new(type_checked_consts))
                 self.type_checked_consts.clone()
             },
+            type_recursion_limit: self.type_recursion_limit,
+            const_recursion_limit: self.const_recursion_limit,
+            monomorph_cache: self.monomorph_cache.clone(),
+            pass_timings: self.pass_timings.clone(),
+            bounds_checks: self.bounds_checks,
+            backtraces: self.backtraces,
+            assertions: self.assertions,
+            reorder_fields: self.reorder_fields,
+            debug_info: self.debug_info,
+            core_only: self.core_only,
+            prelude: self.prelude,
+            cfg: self.cfg.clone(),
+            target_features: self.target_features.clone(),
+            program_args: self.program_args.clone(),
+            warnings: self.warnings.clone(),
+            warning_levels: self.warning_levels.clone(),
+            lints: self.lints.clone(),
+            lint_levels: self.lint_levels.clone(),
 
             // The rest are the same as a new environment.
             ..Env::default()
         }
     }
 
+    /// Get the maximum recursion depth for simplifying, sizing, and comparing types.
+    pub fn get_type_recursion_limit(&self) -> usize {
+        self.type_recursion_limit
+    }
+
+    /// Set the maximum recursion depth for simplifying, sizing, and comparing types.
+    /// Deeply nested recursive types may need a higher limit than the default.
+    pub fn set_type_recursion_limit(&mut self, limit: usize) {
+        self.type_recursion_limit = limit;
+    }
+
+    /// Get the maximum recursion depth for evaluating constant expressions.
+    pub fn get_const_recursion_limit(&self) -> usize {
+        self.const_recursion_limit
+    }
+
+    /// Set the maximum recursion depth for evaluating constant expressions.
+    pub fn set_const_recursion_limit(&mut self, limit: usize) {
+        self.const_recursion_limit = limit;
+    }
+
+    /// Store compiled monomorphs in the given directory, and reuse the ones
+    /// stored there by previous runs of the compiler.
+    pub fn set_monomorph_cache_dir(&mut self, dir: impl Into<std::path::PathBuf>) {
+        self.monomorph_cache = Some(Arc::new(MonomorphCache::new(dir)));
+    }
+
+    /// Get the on-disk cache of compiled monomorphs, if one is used.
+    pub fn get_monomorph_cache(&self) -> Option<Arc<MonomorphCache>> {
+        self.monomorph_cache.clone()
+    }
+
+    /// Measure the time and memory of the compiler's passes.
+    pub fn enable_pass_timings(&mut self) {
+        self.pass_timings = Some(Arc::new(PassTimings::new()));
+    }
+
+    /// Get the times of the compiler's passes, if they're being measured.
+    pub fn get_pass_timings(&self) -> Option<Arc<PassTimings>> {
+        self.pass_timings.clone()
+    }
+
+    /// Run a pass of the compiler, timing it if pass times are being measured.
+    pub fn time_pass<T>(&self, name: &str, pass: impl FnOnce() -> T) -> T {
+        match &self.pass_timings {
+            Some(timings) => timings.time(name, pass),
+            None => pass(),
+        }
+    }
+
+    /// Are array indices checked against the length of the array at runtime?
+    pub fn has_bounds_checks(&self) -> bool {
+        self.bounds_checks
+    }
+
+    /// Check array indices against the length of the array at runtime, and halt
+    /// with the location of the index if it is out of bounds. This is meant for
+    /// debug builds: the checks make every index into an array slower.
+    pub fn set_bounds_checks(&mut self, enabled: bool) {
+        self.bounds_checks = enabled;
+    }
+
+    /// Do procedures keep a shadow stack of their names, so panics can print a backtrace?
+    pub fn has_backtraces(&self) -> bool {
+        self.backtraces
+    }
+
+    /// Have every procedure push its name onto a shadow stack when it's called, and pop it
+    /// when it returns, so that panics can print the procedures they were called from.
+    /// Like bounds checks, this is meant for debug builds: it makes every call slower.
+    pub fn set_backtraces(&mut self, enabled: bool) {
+        self.backtraces = enabled;
+    }
+
+    /// Are assertions compiled into the program?
+    pub fn has_assertions(&self) -> bool {
+        self.assertions
+    }
+
+    /// Compile assertions into the program, or remove them for release builds.
+    /// Assertions which are false at compile time are reported either way.
+    pub fn set_assertions(&mut self, enabled: bool) {
+        self.assertions = enabled;
+    }
+
+    /// Are the fields of types without a guaranteed layout reordered to make them smaller?
+    pub fn has_field_reordering(&self) -> bool {
+        self.reorder_fields
+    }
+
+    /// Let the compiler choose the order of the fields in a bitfield, packing them into
+    /// as few cells as possible instead of in the order they are declared. Every member
+    /// of a struct takes whole cells, so structs have no padding to remove: plain structs
+    /// are unaffected, and structs with a guaranteed layout always keep their declared order.
+    ///
+    /// This must be done before any types are sized. The chosen layout is reported by
+    /// the offsets of the members.
+    pub fn set_field_reordering(&mut self, enabled: bool) {
+        self.reorder_fields = enabled;
+    }
+
+    /// Are the source lines of the instructions marked in the compiled program?
+    pub fn has_debug_info(&self) -> bool {
+        self.debug_info
+    }
+
+    /// Mark the source line each instruction comes from with a comment, which targets
+    /// like C write as `#line` directives, and which test coverage is counted from.
+    /// Release builds can leave them out to make the generated code smaller.
+    pub fn set_debug_info(&mut self, enabled: bool) {
+        self.debug_info = enabled;
+    }
+
+    /// Must programs compiled to the core variant only use core instructions?
+    pub fn has_core_only(&self) -> bool {
+        self.core_only
+    }
+
+    /// Make a program which uses standard instructions an error where they're compiled,
+    /// when it's compiled to the core variant, instead of compiling it to the standard
+    /// variant. This is for targets which can only run core variant programs.
+    pub fn set_core_only(&mut self, enabled: bool) {
+        self.core_only = enabled;
+    }
+
+    /// What frontend programs can use without declaring it.
+    pub fn get_prelude(&self) -> Prelude {
+        self.prelude
+    }
+
+    /// Choose what frontend programs can use without declaring it: the builtins and the
+    /// standard library, only the builtins, or nothing.
+    pub fn set_prelude(&mut self, prelude: Prelude) {
+        self.prelude = prelude;
+    }
+
+    /// Is a compile parameter set? Without a value, this checks whether the parameter
+    /// is set at all, and with one, whether it's set to that value.
+    pub fn has_cfg(&self, name: &str, value: Option<&str>) -> bool {
+        match (self.cfg.get(name), value) {
+            (Some(_), None) => true,
+            (Some(Some(found)), Some(value)) => found == value,
+            _ => false,
+        }
+    }
+
+    /// The number of bits in a cell on the target, from the `cell_width` compile parameter.
+    /// This is the width of an `Int`, and the widest that a bitfield member can be.
+    pub fn get_cell_width(&self) -> usize {
+        self.cfg
+            .get("cell_width")
+            .and_then(|width| width.as_deref()?.parse().ok())
+            .filter(|width| (1..=BITS_PER_CELL).contains(width))
+            .unwrap_or(BITS_PER_CELL)
+    }
+
+    /// Set a compile parameter, either as a flag or to a value.
+    /// This must be done before type checking, so that `when` expressions
+    /// on the parameter check the code for the right target.
+    pub fn set_cfg(&mut self, name: impl ToString, value: Option<impl ToString>) {
+        Arc::make_mut(&mut self.cfg).insert(name.to_string(), value.map(|v| v.to_string()));
+    }
+
+    /// Unset a compile parameter.
+    pub fn unset_cfg(&mut self, name: &str) {
+        Arc::make_mut(&mut self.cfg).remove(name);
+    }
+
+    /// The standard instructions the target being compiled to supports, if it is known.
+    pub fn get_target_features(&self) -> Option<&TargetFeatures> {
+        self.target_features.as_deref()
+    }
+
+    /// Set the standard instructions the target being compiled to supports.
+    /// Code using any other standard instructions fails to compile, with an error
+    /// at the code which uses them.
+    pub fn set_target_features(&mut self, features: TargetFeatures) {
+        self.target_features = Some(Arc::new(features));
+    }
+
+    /// The arguments passed to the program's `main` procedure.
+    pub fn get_program_args(&self) -> &[String] {
+        &self.program_args
+    }
+
+    /// Set the arguments passed to the program's `main` procedure, as `argc` and `argv`.
+    /// By convention, the first argument is the name of the program.
+    pub fn set_program_args(&mut self, args: impl IntoIterator<Item = impl ToString>) {
+        self.program_args = Arc::new(args.into_iter().map(|arg| arg.to_string()).collect());
+    }
+
+    /// Set the `core` or `std` flag, for the variant of the assembly language being compiled to.
+    pub(crate) fn set_variant_cfg(&mut self, core: bool) {
+        let (set, unset) = if core {
+            ("core", "std")
+        } else {
+            ("std", "core")
+        };
+        self.unset_cfg(unset);
+        self.set_cfg(set, None::<String>);
+    }
+
+    /// Get the source code location of the expression being compiled, if it is known.
+    pub(super) fn get_location(&self) -> Option<&SourceCodeLocation> {
+        self.location.as_ref()
+    }
+
+    /// Set the source code location of the expression being compiled,
+    /// and return the previous location so it can be restored.
+    pub(super) fn set_location(
+        &mut self,
+        location: Option<SourceCodeLocation>,
+    ) -> Option<SourceCodeLocation> {
+        std::mem::replace(&mut self.location, location)
+    }
+
+    /// Get the level of a kind of warning.
+    pub fn get_warning_level(&self, kind: WarningKind) -> WarningLevel {
+        self.warning_levels
+            .get(&kind)
+            .copied()
+            .unwrap_or_else(|| kind.default_level())
+    }
+
+    /// Set the level of a kind of warning, to allow, report, or deny it.
+    pub fn set_warning_level(&mut self, kind: WarningKind, level: WarningLevel) {
+        Arc::make_mut(&mut self.warning_levels).insert(kind, level);
+    }
+
+    /// Register a lint to run over the program after it type checks.
+    pub fn add_lint(&mut self, lint: Arc<dyn Lint>) {
+        Arc::make_mut(&mut self.lints).push(lint);
+    }
+
+    /// Register a builtin procedure implemented in core assembly, which programs
+    /// compiled under the environment can call by the builtin's name.
+    pub fn add_core_builtin(&mut self, builtin: CoreBuiltin) {
+        self.define_const(builtin.name.clone(), ConstExpr::CoreBuiltin(builtin));
+    }
+
+    /// Register a builtin procedure implemented in standard assembly, which programs
+    /// compiled under the environment can call by the builtin's name.
+    pub fn add_standard_builtin(&mut self, builtin: StandardBuiltin) {
+        self.define_const(builtin.name.clone(), ConstExpr::StandardBuiltin(builtin));
+    }
+
+    /// Register a foreign function, which programs compiled under the
+    /// environment can call by the name of the function.
+    pub fn add_foreign_function(&mut self, ffi: FFIProcedure) {
+        self.define_const(ffi.get_name().to_string(), ConstExpr::FFIProcedure(ffi));
+    }
+
+    /// Get the lints registered on the environment.
+    pub fn get_lints(&self) -> Arc<Vec<Arc<dyn Lint>>> {
+        self.lints.clone()
+    }
+
+    /// Get the level of a lint. Unless it is set, this is the level of the `lint` warning.
+    pub fn get_lint_level(&self, name: &str) -> WarningLevel {
+        self.lint_levels
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| self.get_warning_level(WarningKind::Lint))
+    }
+
+    /// Set the level of a lint, to allow, report, or deny it.
+    pub fn set_lint_level(&mut self, name: impl ToString, level: WarningLevel) {
+        Arc::make_mut(&mut self.lint_levels).insert(name.to_string(), level);
+    }
+
+    /// Report a warning. If this kind of warning is denied, it is returned as an error.
+    pub fn warn(&self, warning: Warning) -> Result<(), Error> {
+        let level = match warning.unannotated() {
+            Warning::Lint(name, _) => self.get_lint_level(name),
+            _ => self.get_warning_level(warning.kind()),
+        };
+        match level {
+            WarningLevel::Allow => Ok(()),
+            WarningLevel::Warn => {
+                let mut warnings = self.warnings.write().unwrap();
+                // The same code may be type checked more than once.
+                let message = warning.to_string();
+                if !warnings.iter().any(|w| w.to_string() == message) {
+                    warn!("{warning}");
+                    warnings.push(warning);
+                }
+                Ok(())
+            }
+            WarningLevel::Deny => Err(warning.into_error()),
+        }
+    }
+
+    /// Get the warnings reported so far.
+    pub fn get_warnings(&self) -> Vec<Warning> {
+        self.warnings.read().unwrap().clone()
+    }
+
+    /// Start recording which variables are used under this scope.
+    pub(super) fn track_used_vars(&mut self) {
+        self.used_vars.push(Arc::new(RwLock::new(HashSet::new())));
+    }
+
+    /// Was the variable used under this scope since `track_used_vars` was called?
+    pub(super) fn is_var_used(&self, var: &str) -> bool {
+        self.used_vars
+            .last()
+            .map(|used| used.read().unwrap().contains(var))
+            .unwrap_or(true)
+    }
+
+    /// A hash of the constants, types, static variables, and compile options
+    /// of this environment. Code compiled under environments with the same
+    /// fingerprint refers to the same definitions, and is lowered the same way.
+    pub fn fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        let mut consts = self.consts.iter().collect::<Vec<_>>();
+        consts.sort_by_key(|(name, _)| *name);
+        for (name, cexpr) in consts {
+            name.hash(&mut hasher);
+            cexpr.to_string().hash(&mut hasher);
+        }
+        let mut types = self.types.iter().collect::<Vec<_>>();
+        types.sort_by_key(|(name, _)| *name);
+        for (name, ty) in types {
+            name.hash(&mut hasher);
+            ty.to_string().hash(&mut hasher);
+        }
+        // The associated constants are keyed by type, so sort them by how the type is written.
+        let mut associated_constants = self
+            .associated_constants
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(ty, consts)| {
+                let mut consts = consts
+                    .iter()
+                    .map(|(name, (cexpr, ty))| (name.clone(), cexpr.to_string(), ty.to_string()))
+                    .collect::<Vec<_>>();
+                consts.sort();
+                (ty.to_string(), consts)
+            })
+            .collect::<Vec<_>>();
+        associated_constants.sort();
+        associated_constants.hash(&mut hasher);
+        let mut static_vars = self.static_vars.iter().collect::<Vec<_>>();
+        static_vars.sort_by_key(|(name, _)| *name);
+        for (name, (mutability, ty, _)) in static_vars {
+            name.hash(&mut hasher);
+            mutability.hash(&mut hasher);
+            ty.to_string().hash(&mut hasher);
+        }
+        // Code compiled with bounds checks differs from code compiled without them.
+        self.bounds_checks.hash(&mut hasher);
+        self.backtraces.hash(&mut hasher);
+        self.assertions.hash(&mut hasher);
+        self.reorder_fields.hash(&mut hasher);
+        self.debug_info.hash(&mut hasher);
+        self.cfg.hash(&mut hasher);
+        self.target_features.hash(&mut hasher);
+        self.core_only.hash(&mut hasher);
+        self.prelude.hash(&mut hasher);
+        self.program_args.hash(&mut hasher);
+        hasher.finish()
+    }
+
     pub(crate) fn has_type_checked_const(&self, const_expr: &ConstExpr) -> bool {
         self.type_checked_consts
             .read()
@@ -691,6 +1245,29 @@ impl Env {
         Ok(())
     }
 
+    /// Add the declarations to this environment after they failed to type check.
+    /// Variables whose types can't be determined are defined with the `Any` type,
+    /// so that the code using them can still be checked for more errors.
+    pub(super) fn add_declaration_with_recovery(&mut self, declaration: &Declaration) {
+        for decl in declaration.clone().flatten() {
+            if self.add_compile_time_declaration(&decl, false).is_err() {
+                debug!("Failed to add compile-time declaration {decl} while recovering");
+            }
+            if self.add_local_variable_declaration(&decl, false).is_ok() {
+                continue;
+            }
+            let bindings = match decl {
+                Declaration::Var(name, mutability, _, _) => vec![(mutability, name)],
+                Declaration::VarPat(pat, _) => pat.get_binding_names(),
+                _ => vec![],
+            };
+            for (mutability, name) in bindings {
+                // Defining a variable of type `Any` can't fail when not compiling.
+                let _ = self.define_var(name, mutability, Type::Any, false);
+            }
+        }
+    }
+
     /// Add all the compile-time declarations to this environment. These are declarations
     /// for types, constants, and procedures that are defined at compile-time. Variables
     /// are not included because they are defined at runtime.
@@ -719,12 +1296,13 @@ impl Env {
                 } else {
                     // If the module is not defined, we need to define it
                     Arc::make_mut(&mut self.modules).insert(module_name.clone(), *defined_id);
+                    self.invalidate_type_cache();
                 }
 
                 let mut exports = vec![];
                 for decl in Declaration::Many(decls.clone()).flatten().iter() {
                     match decl {
-                        Declaration::Type(name, _) => {
+                        Declaration::Type(name, ..) => {
                             exports.push(name.clone());
                         }
                         Declaration::Module(name, ..) => {
@@ -781,8 +1359,13 @@ impl Env {
                 let result = exports.with(Declaration::Many(decls.clone())).eval(self)?;
                 self.define_const(module_name, result)
             }
-            Declaration::Type(name, ty) => {
+            Declaration::Type(name, ty, attributes) => {
                 self.define_type(name, ty.clone());
+                self.set_type_attributes(name, attributes.clone());
+                // Add the procedures derived for the type as associated constants.
+                if let Some(derived) = Declaration::derived_impl(name, ty, attributes) {
+                    self.add_compile_time_declaration(&derived, compiling)?;
+                }
             }
             Declaration::Const(name, e) => {
                 self.define_const(name, e.clone());
@@ -816,7 +1399,23 @@ impl Env {
                 }
             }
             Declaration::Proc(name, proc) => {
-                self.define_proc(name, proc.clone());
+                if let Some(ty_args) = Attribute::find_specialization(proc.get_attributes()) {
+                    // A specialization overrides a monomorph of the polymorphic procedure
+                    // with the same name, instead of defining a new procedure.
+                    match self.get_const(name) {
+                        Some(ConstExpr::PolyProc(poly)) => {
+                            poly.specialize(ty_args.to_vec(), proc.clone(), self)?
+                        }
+                        _ => {
+                            return Err(Error::InvalidSpecialization(
+                                name.clone(),
+                                "there is no polymorphic procedure with this name".to_string(),
+                            ))
+                        }
+                    }
+                } else {
+                    self.define_proc(name, proc.clone());
+                }
             }
             Declaration::PolyProc(name, proc) => {
                 self.define_poly_proc(name, proc.clone());
@@ -889,13 +1488,44 @@ impl Env {
             }
             Declaration::Var(..) => {}
             Declaration::VarPat(..) => {}
+            Declaration::StaticAssert(..) => {}
             Declaration::Many(decls) => {
+                // First, collect the signatures of every procedure, type, and constant
+                // in the block. These are only looked up by name, so defining them early
+                // lets a declaration use one that comes after it: procedures can call
+                // procedures defined later, or be mutually recursive.
+                for decl in Declaration::Many(decls.clone()).flatten() {
+                    match &decl {
+                        // Specializations are added in order, once the procedures
+                        // they specialize are defined.
+                        Declaration::Proc(_, proc)
+                            if Attribute::find_specialization(proc.get_attributes()).is_some() => {}
+                        Declaration::Proc(..)
+                        | Declaration::PolyProc(..)
+                        | Declaration::ExternProc(..)
+                        | Declaration::Type(..)
+                        | Declaration::Const(..) => {
+                            self.add_compile_time_declaration(&decl, compiling)?
+                        }
+                        _ => {}
+                    }
+                }
+
+                // Then add every declaration in order, so that the modules, imports,
+                // and impls which are evaluated right away can see the whole block.
+                // The procedures, types, and constants are defined a second time here
+                // on purpose. Redefining one replaces it with the same definition, but:
+                // - an import or module evaluated in this pass can define a name which
+                //   a later declaration shadows, so the later one must be added again
+                //   after it, as if there were no first pass;
+                // - a type defined before the types it refers to can only be simplified
+                //   and sized once they're all defined, which they are by now.
                 for decl in decls.iter() {
                     self.add_compile_time_declaration(decl, compiling)?;
                 }
 
                 for decl in decls.iter() {
-                    if let Declaration::Type(name, ty) = decl {
+                    if let Declaration::Type(name, ty, _) = decl {
                         if let Ok(size) = ty.get_size(self) {
                             self.set_precalculated_size(ty.clone(), size);
                         } else {
@@ -918,7 +1548,7 @@ impl Env {
     ) -> Result<(), Error> {
         trace!("Adding local declaration {declaration}");
         match declaration {
-            Declaration::Type(_, _) => {
+            Declaration::Type(..) => {
                 // Types are not defined at runtime.
             }
             Declaration::Const(_, _) => {
@@ -948,6 +1578,9 @@ impl Env {
             Declaration::FromImportAll(..) => {
                 // From imports are not defined at runtime.
             }
+            Declaration::StaticAssert(..) => {
+                // Static assertions are only checked at compile time.
+            }
             Declaration::Var(name, mutability, ty, expr) => {
                 let ty = match ty {
                     Some(ty) => ty.clone(),
@@ -985,6 +1618,8 @@ impl Env {
         trace!("Defining static variable {name} of type {ty} at {location}");
         Arc::make_mut(&mut self.static_vars)
             .insert(name.clone(), (mutability, ty, Location::Global(name)));
+        drop(globals);
+        self.invalidate_type_cache();
         Ok(location)
     }
 
@@ -1013,7 +1648,9 @@ impl Env {
             }
             _ => {
                 Arc::make_mut(&mut self.consts).insert(name.clone(), ConstExpr::Type(ty.clone()));
-                Arc::make_mut(&mut self.types).insert(name.clone(), ty.clone());
+                self.invalidate_type_cache();
+                let interned = self.intern_type(ty.clone());
+                Arc::make_mut(&mut self.types).insert(name.clone(), interned);
 
                 if let Ok(simplified) = ty.simplify_until_concrete(self, false) {
                     if let Ok(size) = simplified.get_size(self) {
@@ -1043,9 +1680,40 @@ impl Env {
 
     /// Get a type definition from this environment.
     pub(super) fn get_type(&self, name: &str) -> Option<&Type> {
+        self.types.get(name).map(|ty| &**ty)
+    }
+
+    /// Set the attributes of a type defined under this environment.
+    pub(super) fn set_type_attributes(&mut self, name: impl ToString, attributes: Vec<Attribute>) {
+        let name = name.to_string();
+        if attributes.is_empty() {
+            if self.type_attributes.contains_key(&name) {
+                Arc::make_mut(&mut self.type_attributes).remove(&name);
+            }
+        } else {
+            Arc::make_mut(&mut self.type_attributes).insert(name, attributes);
+        }
+    }
+
+    /// Get the attributes of a type defined under this environment.
+    pub(super) fn get_type_attributes(&self, name: &str) -> &[Attribute] {
+        self.type_attributes
+            .get(name)
+            .map(|attributes| attributes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Get the interned handle of a type definition from this environment.
+    pub(super) fn get_interned_type(&self, name: &str) -> Option<&InternedType> {
         self.types.get(name)
     }
 
+    /// Get the shared handle for a type. Structurally identical types
+    /// interned under the same environment share the same handle.
+    pub fn intern_type(&self, ty: Type) -> InternedType {
+        self.interner.write().unwrap().intern(ty)
+    }
+
     /// Define a constant with a given name under this environment.
     pub(super) fn define_const(&mut self, name: impl ToString, e: ConstExpr) {
         let name = name.to_string();
@@ -1068,6 +1736,7 @@ impl Env {
         */
 
         Arc::make_mut(&mut self.consts).insert(name, e);
+        self.invalidate_type_cache();
     }
 
     /// Get a constant definition from this environment.
@@ -1081,6 +1750,7 @@ impl Env {
         trace!("Defining procedure {name} as {proc}");
         Arc::make_mut(&mut self.procs).insert(name.clone(), proc.clone());
         Arc::make_mut(&mut self.consts).insert(name, ConstExpr::Proc(proc));
+        self.invalidate_type_cache();
     }
 
     /// Define a polymorphic procedure with a given name under this environment.
@@ -1088,6 +1758,7 @@ impl Env {
         let name = name.to_string();
         trace!("Defining polymorphic procedure {name} as {proc}");
         Arc::make_mut(&mut self.consts).insert(name, ConstExpr::PolyProc(proc));
+        self.invalidate_type_cache();
     }
 
     /// Define an FFI procedure with a given name under this environment.
@@ -1095,6 +1766,7 @@ impl Env {
         let name = name.to_string();
         trace!("Defining FFI procedure {name} as {proc}");
         Arc::make_mut(&mut self.consts).insert(name, ConstExpr::FFIProcedure(proc));
+        self.invalidate_type_cache();
     }
 
     /// Get a procedure definition from this environment.
@@ -1134,6 +1806,60 @@ impl Env {
         }
     }
 
+    /// Get the offset from the frame pointer of the next variable to be defined.
+    pub(super) fn get_frame_offset(&self) -> isize {
+        self.fp_offset + self.sp_offset
+    }
+
+    /// Get the location of a variable at an offset from the frame pointer.
+    pub(super) fn get_frame_location(&self, offset: isize) -> Location {
+        // The number of stack frames started after the variable was defined.
+        let frames = self
+            .stack_frames
+            .iter()
+            .filter(|start| offset < **start)
+            .count() as isize;
+        if frames == 0 {
+            FP.deref().offset(offset)
+        } else {
+            FP_STACK.deref().offset(1 - frames).deref().offset(offset)
+        }
+    }
+
+    /// Start a new stack frame after a stack allocation. The variables defined
+    /// from here on are addressed from the new frame pointer.
+    pub(super) fn push_stack_frame(&mut self) {
+        self.stack_frames.push(self.get_frame_offset());
+    }
+
+    /// Get the number of stack frames started by stack allocations in the current procedure.
+    pub(super) fn get_stack_frame_count(&self) -> usize {
+        self.stack_frames.len()
+    }
+
+    /// Restore the frame pointer from before the last `count` stack frames were started.
+    /// This doesn't end the stack frames in the environment, so that it can be used
+    /// to return early from a procedure.
+    pub(super) fn compile_restore_frame_pointer(
+        &self,
+        count: usize,
+        output: &mut dyn AssemblyProgram,
+    ) {
+        if count > 0 {
+            output.op(CoreOp::Move {
+                src: FP_STACK.deref().offset(1 - count as isize),
+                dst: FP,
+            });
+            output.op(CoreOp::Prev(FP_STACK, Some(count as isize)));
+        }
+    }
+
+    /// End the last `count` stack frames, after restoring the frame pointer.
+    pub(super) fn pop_stack_frames(&mut self, count: usize) {
+        let len = self.stack_frames.len().saturating_sub(count);
+        self.stack_frames.truncate(len);
+    }
+
     /// Get a variable's size, in cells.
     pub(super) fn get_args_size(&self) -> usize {
         self.args_size
@@ -1141,7 +1867,59 @@ impl Env {
 
     /// Get a variable's type and its offset from the frame pointer in the current scope.
     pub(super) fn get_var(&self, var: &str) -> Option<&(Mutability, Type, isize)> {
-        self.vars.get(var)
+        let result = self.vars.get(var);
+        if result.is_some() {
+            for used in &self.used_vars {
+                used.write().unwrap().insert(var.to_owned());
+            }
+        }
+        result
+    }
+
+    /// Is a variable defined in the current scope? Unlike `get_var`, this
+    /// doesn't count as a use of the variable.
+    pub(super) fn has_local_var(&self, var: &str) -> bool {
+        self.vars.contains_key(var)
+    }
+
+    /// Get the number of inlined procedure bodies being compiled in this scope.
+    pub(super) fn get_inline_depth(&self) -> usize {
+        self.inline_depth
+    }
+
+    /// Set the number of inlined procedure bodies being compiled in this scope.
+    pub(super) fn set_inline_depth(&mut self, depth: usize) {
+        self.inline_depth = depth;
+    }
+
+    /// Get the variables and procedures in scope which can be used where a value
+    /// of the expected type is needed (or all of them, if no type is expected).
+    /// This is used to suggest what could fill a typed hole.
+    pub fn get_bindings_fitting(&self, expected: Option<&Type>) -> Vec<(String, Type)> {
+        let vars = self.vars.iter().map(|(name, (_, ty, _))| (name, ty.clone()));
+        let static_vars = self
+            .static_vars
+            .iter()
+            .map(|(name, (_, ty, _))| (name, ty.clone()));
+        let procs = self
+            .procs
+            .iter()
+            .filter_map(|(name, proc)| Some((name, proc.get_type(self).ok()?)));
+
+        let mut fits = vars
+            .chain(static_vars)
+            .chain(procs)
+            // Skip the bindings generated by the compiler.
+            .filter(|(name, _)| !name.starts_with("__"))
+            .filter(|(_, ty)| match expected {
+                Some(expected) => ty.can_decay_to(expected, self).unwrap_or(false),
+                None => true,
+            })
+            .map(|(name, ty)| (name.clone(), ty))
+            .collect::<Vec<_>>();
+        fits.sort_by(|(a, _), (b, _)| a.cmp(b));
+        fits.dedup_by(|(a, _), (b, _)| a == b);
+        fits
     }
 
     /// Is the variable defined in scope as mutable?
@@ -1192,6 +1970,7 @@ impl Env {
             );
             Arc::make_mut(&mut self.vars).insert(name, (mutability, ty, self.fp_offset));
         }
+        self.invalidate_type_cache();
         // Set the frame pointer offset to `1` so that the first variable defined under the scope is at `[FP + 1]`.
         self.fp_offset = 1;
 
@@ -1239,6 +2018,7 @@ impl Env {
         
         // Store the variable's type and offset in the environment.
         Arc::make_mut(&mut self.vars).insert(var, (mutability, ty, offset));
+        self.invalidate_type_cache();
         // Return the offset of the variable from the frame pointer.
         Ok(offset)
     }
@@ -1324,6 +2104,47 @@ impl Env {
         }
         Arc::make_mut(&mut self.type_sizes).insert(ty, size);
     }
+
+    /// Start a new, empty type cache for this environment.
+    /// This must be called whenever something is defined, since the memoized
+    /// results depend on the definitions in scope. Other scopes sharing the
+    /// old cache are unaffected.
+    fn invalidate_type_cache(&mut self) {
+        self.type_cache = Arc::new(RwLock::new(TypeCache::new()));
+    }
+
+    /// Get the memoized result of comparing two types under this environment.
+    pub(super) fn get_cached_equality(&self, a: &Type, b: &Type) -> Option<bool> {
+        self.type_cache.read().unwrap().get_equality(a, b)
+    }
+
+    /// Memoize the result of comparing two types under this environment.
+    pub(super) fn cache_equality(&self, a: Type, b: Type, equal: bool) {
+        self.type_cache.write().unwrap().set_equality(a, b, equal);
+    }
+
+    /// Get the memoized size of a type under this environment.
+    pub(super) fn get_cached_size(&self, ty: &Type) -> Option<usize> {
+        self.type_cache.read().unwrap().get_size(ty)
+    }
+
+    /// Memoize the size of a type under this environment.
+    pub(super) fn cache_size(&self, ty: Type, size: usize) {
+        self.type_cache.write().unwrap().set_size(ty, size);
+    }
+
+    /// Get the memoized concrete simplification of a type under this environment.
+    pub(super) fn get_cached_concrete(&self, ty: &Type, checked: bool) -> Option<Type> {
+        self.type_cache.read().unwrap().get_concrete(ty, checked)
+    }
+
+    /// Memoize the concrete simplification of a type under this environment.
+    pub(super) fn cache_concrete(&self, ty: Type, checked: bool, concrete: Type) {
+        self.type_cache
+            .write()
+            .unwrap()
+            .set_concrete(ty, checked, concrete);
+    }
 }
 
 impl Display for Env {
@@ -1,5 +1,6 @@
 use super::{
-    Annotation, AssignOp, BinaryOp, ConstExpr, Expr, Mutability, Pattern, PolyProcedure, TernaryOp, Type, UnaryOp
+    Annotation, AssignOp, Attribute, BinaryOp, ConstExpr, Effects, Expr, Mutability, Pattern, PolyProcedure, TernaryOp, Type, UnaryOp,
+    Warning,
 };
 use core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
@@ -50,6 +51,16 @@ pub enum Error {
     InvalidIndex(Expr),
     /// Invalid `Refer` expression. The compiler was not able to calculate the address of the expression.
     InvalidRefer(Expr),
+    /// Invalid bitfield type. A member was declared twice, or its width
+    /// was zero or wider than a cell.
+    InvalidBitfield(Type, String),
+    /// Invalid struct type with a guaranteed layout. A member was declared
+    /// twice, or the alignment was zero.
+    InvalidRepr(Type, String),
+    /// The tag values or tag width of an enum are invalid, like two variants sharing a tag.
+    InvalidEnumRepr(Type, String),
+    /// An attribute was used on a declaration it doesn't apply to, like `inline` on a type.
+    InvalidAttribute(Attribute, String),
     /// Invalid unary operation (negate, not) expression (incorrect types).
     InvalidUnaryOp(Box<dyn UnaryOp>, Expr),
     /// Invalid unary op types (incorrect types).
@@ -107,6 +118,15 @@ pub enum Error {
     /// Invalid constant expression.
     InvalidConstExpr(ConstExpr),
 
+    /// Tried to evaluate an expression at compile time which can only be evaluated at runtime.
+    NonConstExpr(Expr),
+
+    /// A procedure marked `pure` has side effects.
+    ImpureProcedure(String, Effects),
+
+    /// The program's `main` procedure has the wrong type for an entry point.
+    InvalidEntryPoint(Type),
+
     /// Expression uses an operation unsupported by the target.
     UnsupportedOperation(Expr),
 
@@ -122,6 +142,13 @@ pub enum Error {
     /// Invalid number of template arguments to a type.
     InvalidTemplateArgs(Type),
 
+    /// Applied a template type to the wrong number of arguments.
+    MismatchedTemplateArgs {
+        ty: Type,
+        expected: usize,
+        found: usize,
+    },
+
     /// Tried to apply a non-template type to some arguments.
     ApplyNonTemplate(Type),
 
@@ -134,8 +161,42 @@ pub enum Error {
     /// Cannot monomorphize a constant expression.
     InvalidMonomorphize(ConstExpr),
 
+    /// A procedure couldn't specialize a polymorphic procedure (the name of the procedure and the reason).
+    InvalidSpecialization(String, String),
+
     /// Duplicate implementations of a member for a type
     DuplicateMember(Type, String),
+
+    /// A format string was malformed, or did not match its arguments.
+    InvalidFormatString(Expr, String),
+    /// A character or string literal was invalid (the text of the literal and the reason).
+    InvalidLiteral(String, String),
+
+    /// An I/O channel was not a non-negative constant integer.
+    InvalidChannel(Expr),
+    /// A stack allocation was used somewhere other than the initial value of a variable.
+    InvalidStackAlloc(Expr),
+
+    /// A warning was found, and that kind of warning is denied.
+    DeniedWarning(Box<Warning>),
+
+    /// An assertion's condition was false at compile time (the condition and the message).
+    AssertionFailed(Expr, Expr),
+    /// A static assertion's condition was false (the condition and the message).
+    StaticAssertionFailed(ConstExpr, String),
+
+    /// A typed hole was found. This reports the type expected in its place (if known),
+    /// and the bindings in scope which fit it.
+    Hole {
+        name: Option<String>,
+        expected: Option<Type>,
+        fits: Vec<(String, Type)>,
+    },
+
+    /// Many errors found in independent parts of the program.
+    ///
+    /// Do NOT instantiate this directly: use `Error::collect`.
+    Many(Vec<Self>),
 }
 
 impl Error {
@@ -149,9 +210,49 @@ impl Error {
                 *previous_annotation = result;
                 self
             }
+            // Annotate each of the errors, keeping their own more precise locations.
+            Self::Many(errors) => Self::Many(
+                errors
+                    .drain(..)
+                    .map(|err| err.annotate(annotation.clone()))
+                    .collect(),
+            ),
             _ => Self::Annotated(Box::new(self), annotation),
         }
     }
+
+    /// Combine the results of checking independent parts of the program,
+    /// so that every error is reported instead of just the first.
+    /// This returns `Ok(())` if none of them failed.
+    pub fn collect(results: impl IntoIterator<Item = Result<(), Self>>) -> Result<(), Self> {
+        let mut errors: Vec<Self> = vec![];
+        for result in results {
+            let Err(err) = result else { continue };
+            for err in match err {
+                Self::Many(errs) => errs,
+                err => vec![err],
+            } {
+                // The same error may be found by checking a declaration and its uses.
+                let message = err.to_string();
+                if !errors.iter().any(|found| found.to_string() == message) {
+                    errors.push(err);
+                }
+            }
+        }
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(Self::Many(errors)),
+        }
+    }
+
+    /// Get the individual errors that make up this error.
+    pub fn errors(&self) -> Vec<&Self> {
+        match self {
+            Self::Many(errors) => errors.iter().flat_map(|err| err.errors()).collect(),
+            _ => vec![self],
+        }
+    }
 }
 
 /// Create an IR error from an assembly error.
@@ -167,9 +268,10 @@ impl Display for Error {
             Self::UnexpectedConstParam { found, expr } => {
                 write!(f, "unexpected constant parameter {found} in expression {expr}")
             }
-            Self::Annotated(err, _) => {
-                write!(f, "{err}")
-            }
+            Self::Annotated(..) => match self.location() {
+                Some(loc) => write!(f, "{loc}: {}", self.unannotated()),
+                None => write!(f, "{}", self.unannotated()),
+            },
             Self::UnimplementedOperator(op) => {
                 write!(f, "unimplemented operator {}", op)
             }
@@ -178,6 +280,47 @@ impl Display for Error {
                 write!(f, "duplicate member {member} of type {ty}")
             }
 
+            Self::InvalidFormatString(expr, reason) => {
+                write!(f, "invalid format string {expr}: {reason}")
+            }
+            Self::InvalidLiteral(literal, reason) => {
+                write!(f, "invalid literal {literal:?}: {reason}")
+            }
+            Self::InvalidChannel(expr) => {
+                write!(f, "invalid I/O channel {expr}, expected a non-negative constant integer")
+            }
+            Self::InvalidStackAlloc(expr) => {
+                write!(
+                    f,
+                    "stack allocation {expr} must be the initial value of a variable"
+                )
+            }
+            Self::Hole {
+                name, expected, ..
+            } => {
+                write!(f, "found hole ?{}", name.as_deref().unwrap_or(""))?;
+                match expected {
+                    Some(expected) => write!(f, " of type {expected}"),
+                    None => write!(f, " of unknown type"),
+                }
+            }
+            Self::Many(errors) => {
+                write!(f, "{} errors:", errors.len())?;
+                for err in errors {
+                    write!(f, "\n{err}")?;
+                }
+                Ok(())
+            }
+            Self::DeniedWarning(warning) => {
+                write!(f, "{warning} (the `{}` warning is denied)", warning.kind())
+            }
+            Self::AssertionFailed(cond, msg) => {
+                write!(f, "assertion {cond} is always false: {msg}")
+            }
+            Self::StaticAssertionFailed(cond, msg) => {
+                write!(f, "static assertion {cond} failed: {msg}")
+            }
+
             Self::MismatchedTypes {
                 expected,
                 found,
@@ -244,6 +387,18 @@ impl Display for Error {
             Self::InvalidRefer(expr) => {
                 write!(f, "invalid refer expression {}", expr)
             }
+            Self::InvalidBitfield(ty, member) => {
+                write!(f, "invalid member {member} of bitfield type {ty}")
+            }
+            Self::InvalidRepr(ty, member) => {
+                write!(f, "invalid member {member} of struct type {ty}")
+            }
+            Self::InvalidEnumRepr(ty, reason) => {
+                write!(f, "invalid representation of enum type {ty}: {reason}")
+            }
+            Self::InvalidAttribute(attribute, name) => {
+                write!(f, "attribute {attribute} can't be used on the type {name}")
+            }
             Self::InvalidUnaryOp(op, expr) => {
                 write!(f, "invalid unary operation {} {}", op, expr)
             }
@@ -319,6 +474,19 @@ impl Display for Error {
             Self::InvalidConstExpr(expr) => {
                 write!(f, "invalid constant expression {}", expr)
             }
+            Self::NonConstExpr(expr) => {
+                write!(f, "expression {} can't be evaluated at compile time", expr)
+            }
+            Self::ImpureProcedure(name, effects) => {
+                write!(f, "procedure {name} is marked `pure`, but it performs {effects}")
+            }
+            Self::InvalidEntryPoint(ty) => {
+                write!(
+                    f,
+                    "entry point `main` has type {ty}, but it must take no arguments or \
+                    `(argc: Int, argv: &&Char)`"
+                )
+            }
             Self::UnsupportedOperation(expr) => {
                 write!(f, "unsupported operation {}", expr)
             }
@@ -334,6 +502,16 @@ impl Display for Error {
             Self::InvalidTemplateArgs(ty) => {
                 write!(f, "invalid template arguments for type {}", ty)
             }
+            Self::MismatchedTemplateArgs {
+                ty,
+                expected,
+                found,
+            } => {
+                write!(
+                    f,
+                    "expected {expected} template argument(s) for type {ty}, but found {found}"
+                )
+            }
             Self::ApplyNonTemplate(ty) => {
                 write!(f, "tried to apply non-template type {}", ty)
             }
@@ -353,6 +531,9 @@ impl Display for Error {
                     expr
                 )
             }
+            Self::InvalidSpecialization(name, reason) => {
+                write!(f, "invalid specialization of {name}: {reason}")
+            }
         }
     }
 }
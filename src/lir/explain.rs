@@ -0,0 +1,673 @@
+//! # Error Codes
+//!
+//! Every kind of error the compiler reports has a stable code, like `E0041`, which
+//! is printed with its diagnostics. Tests and tools can match on the code instead
+//! of the error's message, which may be reworded. Codes are never reused: if a
+//! kind of error is removed, its code is retired with it.
+//!
+//! The LIR errors are numbered from `E0001`, and the errors from parsing frontend
+//! code are numbered from `E0100`. Each code has an explanation, with an example
+//! of code which causes it, which is printed by `sage --explain <CODE>`.
+use super::Error;
+
+/// The explanation of an error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorCode {
+    /// The code, like `E0041`.
+    pub code: &'static str,
+    /// A short description of the error.
+    pub title: &'static str,
+    /// A longer description of the error, with examples.
+    pub explanation: &'static str,
+}
+
+impl ErrorCode {
+    /// Find the explanation of a code. The code's case doesn't matter, and its `E`
+    /// and leading zeros are optional, so `E0028`, `e28`, and `28` are the same code.
+    pub fn find(code: &str) -> Option<&'static Self> {
+        let code = code.trim();
+        let number = code
+            .strip_prefix(['E', 'e'])
+            .unwrap_or(code)
+            .parse::<u32>()
+            .ok()?;
+        let code = format!("E{number:04}");
+        ERROR_CODES.iter().find(|error| error.code == code)
+    }
+}
+
+impl Error {
+    /// The stable code of this kind of error, like `E0041`. Annotated errors have the code
+    /// of the error they annotate, and a collection of many errors doesn't have a code.
+    pub fn code(&self) -> Option<&'static str> {
+        Some(match self.unannotated() {
+            Self::Annotated(..) | Self::Many(_) => return None,
+            Self::UnimplementedOperator(_) => "E0001",
+            Self::UnexpectedConstParam { .. } => "E0002",
+            Self::AssemblyError(_) => "E0003",
+            Self::VariantNotFound(..) => "E0004",
+            Self::MemberNotFound(..) => "E0005",
+            Self::RecursionDepthConst(_) => "E0006",
+            Self::CouldntSimplify(..) => "E0007",
+            Self::RecursionDepthTypeEquality(..) => "E0008",
+            Self::NonIntegralConst(_) => "E0009",
+            Self::UnsizedType(_) => "E0010",
+            Self::DerefNonPointer(_) => "E0011",
+            Self::ApplyNonProc(_) => "E0012",
+            Self::NonSymbol(_) => "E0013",
+            Self::InvalidIndex(_) => "E0014",
+            Self::InvalidRefer(_) => "E0015",
+            Self::InvalidBitfield(..) => "E0016",
+            Self::InvalidRepr(..) => "E0017",
+            Self::InvalidEnumRepr(..) => "E0018",
+            Self::InvalidAttribute(..) => "E0019",
+            Self::InvalidUnaryOp(..) => "E0020",
+            Self::InvalidUnaryOpTypes(..) => "E0021",
+            Self::InvalidBinaryOp(..) => "E0022",
+            Self::InvalidBinaryOpTypes(..) => "E0023",
+            Self::InvalidTernaryOp(..) => "E0024",
+            Self::InvalidTernaryOpTypes(..) => "E0025",
+            Self::InvalidAssignOp(..) => "E0026",
+            Self::InvalidAssignOpTypes(..) => "E0027",
+            Self::MismatchedTypes { .. } => "E0028",
+            Self::MismatchedMutability { .. } => "E0029",
+            Self::SymbolNotDefined(_) => "E0030",
+            Self::TypeNotDefined(_) => "E0031",
+            Self::NegativeArrayLength(_) => "E0032",
+            Self::InvalidPatternForType(..) => "E0033",
+            Self::InvalidPatternForExpr(..) => "E0034",
+            Self::InvalidMatchExpr(_) => "E0035",
+            Self::NonExhaustivePatterns { .. } => "E0036",
+            Self::InvalidAs(..) => "E0037",
+            Self::InvalidConstExpr(_) => "E0038",
+            Self::NonConstExpr(_) => "E0039",
+            Self::ImpureProcedure(..) => "E0040",
+            Self::InvalidEntryPoint(_) => "E0041",
+            Self::UnsupportedOperation(_) => "E0042",
+            Self::TypeRedefined(_) => "E0043",
+            Self::ModuleRedefined(_) => "E0044",
+            Self::UnusedExpr(..) => "E0045",
+            Self::InvalidTemplateArgs(_) => "E0046",
+            Self::MismatchedTemplateArgs { .. } => "E0047",
+            Self::ApplyNonTemplate(_) => "E0048",
+            Self::SizeOfTemplate(_) => "E0049",
+            Self::CompilePolyProc(_) => "E0050",
+            Self::InvalidMonomorphize(_) => "E0051",
+            Self::InvalidSpecialization(..) => "E0052",
+            Self::DuplicateMember(..) => "E0053",
+            Self::InvalidFormatString(..) => "E0054",
+            Self::InvalidLiteral(..) => "E0055",
+            Self::InvalidChannel(_) => "E0056",
+            Self::InvalidStackAlloc(_) => "E0057",
+            Self::DeniedWarning(_) => "E0058",
+            Self::AssertionFailed(..) => "E0059",
+            Self::StaticAssertionFailed(..) => "E0060",
+            Self::Hole { .. } => "E0061",
+        })
+    }
+}
+
+/// The explanations of every error code, in order.
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "unimplemented operator",
+        explanation: "An operator was used which has no implementation in the compiler's environment.
+
+This usually means a builtin operator was removed from the environment, or code
+written for a newer version of the compiler used an operator this version lacks.",
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "unexpected constant parameter",
+        explanation: "A constant was given as an argument to a template where a type was expected.
+
+    struct Box<T> {
+        value: T
+    }
+
+    let b: Box<5> = ...; // `5` is a constant, not a type
+
+Only the parameters of a template declared as constants can be given constants.",
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "invalid generated assembly",
+        explanation: "The compiler generated assembly code which couldn't be assembled.
+
+This is almost always a bug in the compiler, or in a builtin written by hand in
+assembly. Please report it, along with the code which caused it.",
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "variant not found",
+        explanation: "An enum was used with a variant it doesn't declare.
+
+    enum Direction { North, South }
+
+    let d = Direction of East; // `Direction` has no variant `East`
+
+Check the spelling of the variant, and that the enum is the one you meant.",
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "member not found",
+        explanation: "A member was accessed which a value doesn't have. This is either a field
+of a struct, tuple, or union, or an associated constant or method of its type.
+
+    struct Point { x: Int, y: Int }
+
+    let p = {x=1, y=2};
+    println(p.z); // `Point` has no member `z`",
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "constant recursion limit reached",
+        explanation: "Evaluating a constant expression recursed too deeply.
+
+This usually means a constant is defined in terms of itself:
+
+    const A = B + 1;
+    const B = A + 1;",
+    },
+    ErrorCode {
+        code: "E0007",
+        title: "couldn't simplify type",
+        explanation: "A type couldn't be simplified to a concrete type.
+
+This happens when a recursive type never bottoms out, or when a template is applied
+to arguments it can't be simplified with.",
+    },
+    ErrorCode {
+        code: "E0008",
+        title: "type comparison recursion limit reached",
+        explanation: "Comparing two types recursed too deeply to decide whether they're equal.
+
+This usually involves recursive types which expand forever when compared, like a
+template which is applied to a larger version of itself in its own definition.",
+    },
+    ErrorCode {
+        code: "E0009",
+        title: "non-integral constant",
+        explanation: "A constant was used where an integer, boolean, or character is required,
+like the length of an array.
+
+    let a: [Int * 2.5] = ...; // array lengths must be integers",
+    },
+    ErrorCode {
+        code: "E0010",
+        title: "unsized type",
+        explanation: "A value was created with a type whose size can't be known.
+
+The stack is managed at compile time, so every value must have a known size.
+A type which contains itself directly has no size:
+
+    struct List { head: Int, tail: List }
+
+Use a pointer to the type instead, like `tail: &List`.",
+    },
+    ErrorCode {
+        code: "E0011",
+        title: "dereferenced a non-pointer",
+        explanation: "A value which isn't a pointer was dereferenced.
+
+    let x = 5;
+    println(*x); // `x` is an `Int`, not a pointer
+
+Only pointers, like `&Int` or `&mut Int`, can be dereferenced.",
+    },
+    ErrorCode {
+        code: "E0012",
+        title: "called a non-procedure",
+        explanation: "A value which isn't a procedure was called with arguments.
+
+    let x = 5;
+    x(1, 2); // `x` is an `Int`, not a procedure",
+    },
+    ErrorCode {
+        code: "E0013",
+        title: "expected a symbol",
+        explanation: "A name was expected, but a different kind of constant was found.
+
+This is usually caused by a malformed member access, or by LIR which was
+generated or written by hand.",
+    },
+    ErrorCode {
+        code: "E0014",
+        title: "invalid index",
+        explanation: "A value was indexed which isn't an array or a pointer, or the index isn't an integer.
+
+    let x = 5;
+    println(x[0]); // `Int` can't be indexed
+
+    let a = [1, 2, 3];
+    println(a['a']); // indices must be integers",
+    },
+    ErrorCode {
+        code: "E0015",
+        title: "invalid reference",
+        explanation: "A reference was taken to a value which has no address, like a constant
+or the result of a procedure call.
+
+    fun five(): Int { return 5; }
+
+    let p = &five(); // the result of `five()` is a temporary
+
+Store the value in a variable first, and take a reference to the variable.",
+    },
+    ErrorCode {
+        code: "E0016",
+        title: "invalid bitfield",
+        explanation: "A bitfield type was declared with a member declared twice, or with
+a member whose width is zero or wider than a cell.",
+    },
+    ErrorCode {
+        code: "E0017",
+        title: "invalid layout",
+        explanation: "A struct with a guaranteed layout declared a member twice, or declared an alignment of zero.",
+    },
+    ErrorCode {
+        code: "E0018",
+        title: "invalid enum representation",
+        explanation: "The tags of an enum are invalid. Either two variants share a tag,
+or a tag doesn't fit in the width declared for the tags.",
+    },
+    ErrorCode {
+        code: "E0019",
+        title: "invalid attribute",
+        explanation: "An attribute was used on a declaration it doesn't apply to.
+
+    #[inline]
+    struct Point { x: Int, y: Int } // only procedures can be inlined
+
+Some attributes, like `test` and `specialize`, also can't be used on polymorphic procedures.",
+    },
+    ErrorCode {
+        code: "E0020",
+        title: "invalid unary operation",
+        explanation: "A unary operator was applied to a value of a type it doesn't support.
+
+    let s = \"hello\";
+    println(-s); // strings can't be negated",
+    },
+    ErrorCode {
+        code: "E0021",
+        title: "invalid unary operation types",
+        explanation: "A unary operator was applied to a type it doesn't support.
+This is the same as E0020, reported where only the type of the operand is known.",
+    },
+    ErrorCode {
+        code: "E0022",
+        title: "invalid binary operation",
+        explanation: "A binary operator was applied to values of types it doesn't support.
+
+    println(1 + 'a'); // an `Int` can't be added to a `Char`
+
+Convert one of the values with `as` first, like `1 + ('a' as Int)`.",
+    },
+    ErrorCode {
+        code: "E0023",
+        title: "invalid binary operation types",
+        explanation: "A binary operator was applied to types it doesn't support.
+This is the same as E0022, reported where only the types of the operands are known.",
+    },
+    ErrorCode {
+        code: "E0024",
+        title: "invalid ternary operation",
+        explanation: "A ternary operator was applied to values of types it doesn't support.",
+    },
+    ErrorCode {
+        code: "E0025",
+        title: "invalid ternary operation types",
+        explanation: "A ternary operator was applied to types it doesn't support.
+This is the same as E0024, reported where only the types of the operands are known.",
+    },
+    ErrorCode {
+        code: "E0026",
+        title: "invalid assignment",
+        explanation: "An assignment operator was applied to values of types it doesn't support.
+
+    let mut x = 5;
+    x += 2.5; // a `Float` can't be added to an `Int` variable",
+    },
+    ErrorCode {
+        code: "E0027",
+        title: "invalid assignment types",
+        explanation: "An assignment operator was applied to types it doesn't support.
+This is the same as E0026, reported where only the types of the operands are known.",
+    },
+    ErrorCode {
+        code: "E0028",
+        title: "mismatched types",
+        explanation: "A value was used where a value of a different type was expected.
+
+    let x: Int = 'a'; // expected `Int`, found `Char`
+
+    fun double(n: Int): Int { return n * 2; }
+    double(True); // expected `Int`, found `Bool`
+
+Convert the value with `as`, or change the type which is expected.",
+    },
+    ErrorCode {
+        code: "E0029",
+        title: "mismatched mutability",
+        explanation: "An immutable value was used where a mutable one was expected.
+
+    let x = 5;
+    x = 6; // `x` isn't mutable
+
+    let y = 5;
+    let p: &mut Int = &y; // `&y` is an immutable reference
+
+Declare the variable with `let mut`, and take mutable references with `&mut`.",
+    },
+    ErrorCode {
+        code: "E0030",
+        title: "symbol not defined",
+        explanation: "A name was used which isn't defined in the scope it's used in.
+
+    println(count); // `count` isn't defined
+
+Check the spelling of the name, and that it's declared before it's used
+(or imported, if it's declared in a module).",
+    },
+    ErrorCode {
+        code: "E0031",
+        title: "type not defined",
+        explanation: "A type was used which isn't defined in the scope it's used in.
+
+    let p: Pointt = ...; // `Pointt` isn't defined
+
+Check the spelling of the type, and that it's imported if it's declared in a module.",
+    },
+    ErrorCode {
+        code: "E0032",
+        title: "negative array length",
+        explanation: "An array was declared with a negative length.
+
+    let a: [Int * -1] = ...;",
+    },
+    ErrorCode {
+        code: "E0033",
+        title: "invalid pattern for type",
+        explanation: "A pattern was matched against a value of a type it can never match.
+
+    enum Shape { Circle(Float), Square(Float) }
+
+    match 5 {
+        of Circle(r) => ..., // an `Int` can't be a `Shape`
+    }",
+    },
+    ErrorCode {
+        code: "E0034",
+        title: "invalid pattern for expression",
+        explanation: "A pattern was matched against an expression it can never match.
+This is the same as E0033, reported where the expression is known.",
+    },
+    ErrorCode {
+        code: "E0035",
+        title: "invalid match expression",
+        explanation: "A value was matched over which can't be matched, like a procedure.",
+    },
+    ErrorCode {
+        code: "E0036",
+        title: "non-exhaustive patterns",
+        explanation: "A match doesn't handle every possible value.
+
+    enum Direction { North, South, East, West }
+
+    match d {
+        of North => 1,
+        of South => 2,
+    } // `East` and `West` aren't handled
+
+Add the missing cases, or a `_` pattern which handles everything else.",
+    },
+    ErrorCode {
+        code: "E0037",
+        title: "invalid cast",
+        explanation: "A value was cast with `as` to a type it can't be converted to.
+
+    struct Point { x: Int, y: Int }
+
+    let p = {x=1, y=2};
+    let n = p as Int; // a `Point` can't be cast to an `Int`",
+    },
+    ErrorCode {
+        code: "E0038",
+        title: "invalid constant expression",
+        explanation: "A constant expression couldn't be evaluated, like a constant
+which applies an operator to values it doesn't support.",
+    },
+    ErrorCode {
+        code: "E0039",
+        title: "expression isn't constant",
+        explanation: "An expression which can only be evaluated at runtime was used where
+a constant was expected.
+
+    let n = 5;
+    const SIZE = n; // `n` is a variable, not a constant",
+    },
+    ErrorCode {
+        code: "E0040",
+        title: "impure procedure",
+        explanation: "A procedure marked `pure` has side effects, like printing or writing through a pointer.
+
+    #[pure]
+    fun add(a: Int, b: Int): Int {
+        println(a); // printing is a side effect
+        return a + b;
+    }
+
+Remove the side effects, or remove the `pure` attribute.",
+    },
+    ErrorCode {
+        code: "E0041",
+        title: "invalid entry point",
+        explanation: "The program's `main` procedure has a type which can't be called as an entry point.
+
+`main` takes either no arguments, or the number of arguments and a pointer to them
+(like `argc` and `argv` in C), and returns either nothing or an `Int` exit status.
+
+    fun main(argc: Int, argv: &&Char): Int { ... }",
+    },
+    ErrorCode {
+        code: "E0042",
+        title: "unsupported operation",
+        explanation: "An operation was used which the target being compiled for doesn't support.
+
+For example, targets which only support the core instructions can't use floats.
+Compile for a target which supports the operation, or avoid using it.",
+    },
+    ErrorCode {
+        code: "E0043",
+        title: "type redefined",
+        explanation: "A type was declared twice in the same scope.
+
+    struct Point { x: Int, y: Int }
+    struct Point { x: Float, y: Float }
+
+Rename one of the types.",
+    },
+    ErrorCode {
+        code: "E0044",
+        title: "module redefined",
+        explanation: "A module was declared twice in the same scope.
+
+    mod math { ... }
+    mod math { ... }
+
+Rename one of the modules, or combine them.",
+    },
+    ErrorCode {
+        code: "E0045",
+        title: "unused expression",
+        explanation: "An expression's value was thrown away, but it wasn't `None`.
+
+This is an error for LIR which discards values without a type annotation.
+In the frontend, the values of statements are discarded automatically.",
+    },
+    ErrorCode {
+        code: "E0046",
+        title: "invalid template arguments",
+        explanation: "A template was given arguments which aren't valid for its parameters.",
+    },
+    ErrorCode {
+        code: "E0047",
+        title: "wrong number of template arguments",
+        explanation: "A template was given the wrong number of arguments.
+
+    struct Pair<A, B> { first: A, second: B }
+
+    let p: Pair<Int> = ...; // `Pair` takes 2 arguments, not 1",
+    },
+    ErrorCode {
+        code: "E0048",
+        title: "applied a non-template",
+        explanation: "A type which isn't a template was given arguments.
+
+    struct Point { x: Int, y: Int }
+
+    let p: Point<Int> = ...; // `Point` has no parameters",
+    },
+    ErrorCode {
+        code: "E0049",
+        title: "size of a template",
+        explanation: "The size of a template was needed, before it was given its arguments.
+
+    struct Box<T> { value: T }
+
+    println(sizeof<Box>()); // use `sizeof<Box<Int>>()` instead",
+    },
+    ErrorCode {
+        code: "E0050",
+        title: "compiled a polymorphic procedure",
+        explanation: "A polymorphic procedure was used without giving it type arguments,
+so it couldn't be compiled.
+
+    fun id<T>(x: T): T { return x; }
+
+    let f = id; // use `id<Int>` instead",
+    },
+    ErrorCode {
+        code: "E0051",
+        title: "invalid monomorphization",
+        explanation: "Type arguments were given to a constant which isn't polymorphic.
+
+    fun double(n: Int): Int { return n * 2; }
+
+    double<Int>(5); // `double` has no type parameters",
+    },
+    ErrorCode {
+        code: "E0052",
+        title: "invalid specialization",
+        explanation: "A procedure marked with `specialize` couldn't specialize the polymorphic
+procedure of the same name. Its signature must match the polymorphic procedure's,
+with the type parameters replaced by the types it specializes.",
+    },
+    ErrorCode {
+        code: "E0053",
+        title: "duplicate member",
+        explanation: "A member was implemented twice for the same type.
+
+    impl Point {
+        fun origin(): Point { ... }
+        fun origin(): Point { ... }
+    }",
+    },
+    ErrorCode {
+        code: "E0054",
+        title: "invalid format string",
+        explanation: "A format string given to the `format` operator was malformed, like a `{` which
+isn't closed, or it didn't have as many placeholders as it had arguments.
+Use `{{` and `}}` to print literal braces.",
+    },
+    ErrorCode {
+        code: "E0055",
+        title: "invalid literal",
+        explanation: "A character or string literal was invalid, like a character literal
+with more than one character, or an unknown escape sequence.
+
+    let c = 'ab';",
+    },
+    ErrorCode {
+        code: "E0056",
+        title: "invalid I/O channel",
+        explanation: "An I/O channel wasn't a constant, non-negative integer.
+
+The channel of an I/O operation must be known at compile time.",
+    },
+    ErrorCode {
+        code: "E0057",
+        title: "invalid stack allocation",
+        explanation: "A stack allocation was used somewhere other than the initial value of a variable.
+
+Stack allocations live as long as the variable they initialize, so they can only
+be used to initialize a variable.",
+    },
+    ErrorCode {
+        code: "E0058",
+        title: "denied warning",
+        explanation: "A warning was found, and that kind of warning is denied, like with `--deny unused-variable`.
+
+Fix the warning, or stop denying that kind of warning.",
+    },
+    ErrorCode {
+        code: "E0059",
+        title: "assertion failed",
+        explanation: "An assertion's condition is false, and it was evaluated at compile time.
+
+    assert(1 + 1 == 3, \"math is broken\");",
+    },
+    ErrorCode {
+        code: "E0060",
+        title: "static assertion failed",
+        explanation: "A static assertion's condition is false.
+
+    static_assert(sizeof<Int>() == 2, \"Int must be two cells\");",
+    },
+    ErrorCode {
+        code: "E0061",
+        title: "typed hole",
+        explanation: "A typed hole was left in the program, written `?` or `?name`.
+
+    fun double(n: Int): Int {
+        return ?todo;
+    }
+
+The error reports the type expected in place of the hole, and the bindings in
+scope which have that type. Replace the hole with an expression to compile the program.",
+    },
+    ErrorCode {
+        code: "E0100",
+        title: "syntax error",
+        explanation: "The source code couldn't be parsed.
+
+    let x = ;
+
+The error shows where the parser stopped, and what it was trying to parse there.",
+    },
+    ErrorCode {
+        code: "E0101",
+        title: "module file not found",
+        explanation: "A module was declared with `mod name;`, but no file was found for it.
+
+The module is read from `name.sg`, or from `name/mod.sg`, in the directory of
+the file which declares it (or in the module's own directory, for modules
+declared inside other module files).",
+    },
+    ErrorCode {
+        code: "E0102",
+        title: "couldn't read module file",
+        explanation: "The file of a module declared with `mod name;` was found, but it couldn't be read.
+
+Check the file's permissions, and that it's valid UTF-8.",
+    },
+    ErrorCode {
+        code: "E0103",
+        title: "syntax error in module file",
+        explanation: "The file of a module declared with `mod name;` couldn't be parsed.
+
+The error shows where the parser stopped in the module's file.",
+    },
+];
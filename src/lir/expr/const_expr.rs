@@ -10,8 +10,8 @@
 //! - Enum variants
 
 use crate::lir::{
-    Annotation, CoreBuiltin, Declaration, Env, Error, Expr, FFIProcedure, GetSize, GetType,
-    Mutability, PolyProcedure, Procedure, Simplify, StandardBuiltin, Type,
+    Annotation, CoreBuiltin, Declaration, Env, Error, Expr, FFIProcedure, Fold, GetSize, GetType,
+    Mutability, PolyProcedure, Procedure, Reflection, Simplify, StandardBuiltin, Substitute, Type,
 };
 use log::*;
 
@@ -61,6 +61,18 @@ pub enum ConstExpr {
     /// Get the size of an expression's type (in cells) as a constant int.
     /// This will not evaluate the inner expression.
     SizeOfExpr(Box<Expr>),
+    /// Get the alignment of a type (in cells) as a constant int.
+    AlignOfType(Type),
+    /// Get the offset of a member from the start of a struct, tuple,
+    /// or union type (in cells) as a constant int.
+    OffsetOf(Type, Box<Self>),
+    /// Is a compile parameter set, like the target or the variant of the assembly language?
+    /// With a value, this checks if the parameter is set to that value. This is a constant bool,
+    /// so `when` expressions on it pick the code for each target before type checking.
+    Cfg(String, Option<String>),
+    /// Describe a property of a type, like its name or the names of its members,
+    /// as a constant array.
+    Reflect(Type, Reflection),
 
     /// A type as a constant expression.
     Type(Type),
@@ -68,6 +80,10 @@ pub enum ConstExpr {
     Tuple(Vec<Self>),
     /// An array of constant values.
     Array(Vec<Self>),
+    /// An array with an element for each integer from the start of a range up to
+    /// (but not including) its end. Each element is the expression evaluated at
+    /// compile time, with the integer bound to the named constant.
+    Table(String, Box<Self>, Box<Self>, Box<Expr>),
     /// A structure of constant values.
     Struct(BTreeMap<String, Self>),
     /// A union of constant values.
@@ -101,6 +117,10 @@ unsafe impl Send for ConstExpr {}
 unsafe impl Sync for ConstExpr {}
 
 impl ConstExpr {
+    /// This is the default maximum number of times a constant will be evaluated recursively.
+    /// The limit used during compilation is configured on the `Env`.
+    pub const EVAL_RECURSION_LIMIT: usize = 100;
+
     pub fn monomorphize(self, ty_args: Vec<Type>) -> Self {
         Self::Monomorphize(Box::new(self), ty_args)
     }
@@ -218,12 +238,24 @@ impl ConstExpr {
         Self::Member(self.into(), field.into())
     }
 
+    /// Convert an integer constant to a character constant, as a cast would.
+    ///
+    /// Integers which are not valid code points are left as integers,
+    /// since the cast does not change the value in the cell.
+    fn int_to_char(n: i64) -> Self {
+        u32::try_from(n)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Self::Char)
+            .unwrap_or(Self::Int(n))
+    }
+
     /// Evaluate this constant with stack overflow prevention.
     ///
     /// The `i` is a counter for the number of recursions caused by an `eval` call.
     fn eval_checked(self, env: &Env, i: usize) -> Result<Self, Error> {
         let i: usize = i + 1;
-        if i > 100 {
+        if i > env.get_const_recursion_limit() {
             error!("Recursion depth exceeded while evaluating: {self}");
             Err(Error::RecursionDepthConst(self))
         } else {
@@ -479,11 +511,33 @@ impl ConstExpr {
                         ));
                     }
 
-                    expr.eval_checked(env, i)
+                    // Convert numeric constants with the same semantics as a runtime cast.
+                    let val = expr.eval_checked(env, i)?;
+                    Ok(
+                        match (val, cast_ty.simplify_until_concrete(env, false)?.discard_type_wrapper()) {
+                            (Self::Int(n), Type::Float) => Self::Float(n as f64),
+                            (Self::Char(c), Type::Float) => Self::Float(c as u32 as f64),
+                            (Self::Bool(b), Type::Float) => Self::Float(b as i64 as f64),
+                            (Self::Float(n), Type::Int) => Self::Int(n as i64),
+                            (Self::Float(n), Type::Char) => Self::int_to_char(n as i64),
+                            (Self::Int(n), Type::Char) => Self::int_to_char(n),
+                            (Self::Char(c), Type::Int) => Self::Int(c as u32 as i64),
+                            (Self::Bool(b), Type::Int) => Self::Int(b as i64),
+                            (Self::Bool(b), Type::Char) => Self::Char(b as u8 as char),
+                            (Self::Int(n), Type::Bool) => Self::Bool(n != 0),
+                            (Self::Char(c), Type::Bool) => Self::Bool(c != '\0'),
+                            (Self::Float(n), Type::Bool) => Self::Bool(n != 0.0),
+                            (val, _) => val,
+                        },
+                    )
                 }
 
                 Self::SizeOfType(t) => Ok(Self::Int(t.get_size(env)? as i64)),
                 Self::SizeOfExpr(e) => Ok(Self::Int(e.get_size(env)? as i64)),
+                Self::AlignOfType(t) => Ok(Self::Int(t.get_alignment(env)? as i64)),
+                Self::OffsetOf(t, member) => Ok(Self::Int(t.get_offset_of(&member, env)? as i64)),
+                Self::Cfg(name, value) => Ok(Self::Bool(env.has_cfg(&name, value.as_deref()))),
+                Self::Reflect(t, reflection) => reflection.reflect(&t, env),
 
                 Self::Symbol(name) => {
                     if let Some(c) = env.get_const(&name) {
@@ -507,6 +561,19 @@ impl ConstExpr {
                         .map(|c| c.eval_checked(env, i))
                         .collect::<Result<Vec<Self>, Error>>()?,
                 )),
+                Self::Table(name, start, end, elem) => {
+                    let start = start.as_int(env)?;
+                    let end = end.as_int(env)?;
+                    let mut new_env = env.clone();
+                    Ok(Self::Array(
+                        (start..end)
+                            .map(|n| {
+                                new_env.define_const(&name, Self::Int(n));
+                                elem.eval_const(&new_env)
+                            })
+                            .collect::<Result<Vec<Self>, Error>>()?,
+                    ))
+                }
                 Self::Struct(fields) => Ok(Self::Struct(
                     fields
                         .into_iter()
@@ -851,11 +918,18 @@ impl GetType for ConstExpr {
             }
             Self::Null => Type::Pointer(Mutability::Any, Box::new(Type::Any)),
             Self::None => Type::None,
-            Self::SizeOfType(_) | Self::SizeOfExpr(_) | Self::Int(_) => Type::Int,
+            Self::SizeOfType(_)
+            | Self::SizeOfExpr(_)
+            | Self::AlignOfType(_)
+            | Self::OffsetOf(..)
+            | Self::Int(_) => Type::Int,
             Self::Float(_) => Type::Float,
             Self::Char(_) => Type::Char,
             Self::Cell(_) => Type::Cell,
-            Self::Bool(_) => Type::Bool,
+            Self::Bool(_) | Self::Cfg(..) => Type::Bool,
+            Self::Reflect(t, reflection) => {
+                reflection.reflect(&t, env)?.get_type_checked(env, i)?
+            }
             Self::Of(enum_type, _) => enum_type,
             Self::Tuple(items) => Type::Tuple(
                 items
@@ -863,6 +937,7 @@ impl GetType for ConstExpr {
                     .map(|c| c.get_type_checked(env, i))
                     .collect::<Result<Vec<Type>, Error>>()?,
             ),
+            Self::Table(..) => self.clone().eval(env)?.get_type_checked(env, i)?,
             Self::Array(items) => Type::Array(
                 Box::new(if !items.is_empty() {
                     items[0].get_type_checked(env, i)?
@@ -923,101 +998,7 @@ impl GetType for ConstExpr {
     }
 
     fn substitute(&mut self, name: &str, substitution: &Type) {
-        match self {
-            Self::Type(t) => {
-                *t = t.substitute(name, substitution);
-            }
-
-            Self::Template(params, ret) => {
-                if params.iter().map(|x| x.0.clone()).collect::<Vec<_>>().contains(&name.to_string()) {
-                    return;
-                }
-                ret.substitute(name, substitution);
-            }
-            Self::Member(container, member) => {
-                container.substitute(name, substitution);
-                member.substitute(name, substitution);
-            }
-            Self::Annotated(expr, _) => {
-                expr.substitute(name, substitution);
-            }
-            Self::As(expr, cast_ty) => {
-                expr.substitute(name, substitution);
-                *cast_ty = cast_ty.substitute(name, substitution);
-            }
-            Self::Declare(bindings, expr) => {
-                bindings.substitute(name, substitution);
-                expr.substitute(name, substitution);
-            }
-            Self::Monomorphize(expr, ty_args) => {
-                expr.substitute(name, substitution);
-                for ty_arg in ty_args {
-                    *ty_arg = ty_arg.substitute(name, substitution);
-                }
-            }
-            Self::TypeOf(expr) => {
-                expr.substitute(name, substitution);
-            }
-            Self::Null => {}
-            Self::None => {}
-            Self::SizeOfType(inner_ty) => {
-                *inner_ty = inner_ty.substitute(name, substitution);
-            }
-            Self::SizeOfExpr(expr) => {
-                expr.substitute(name, substitution);
-            }
-            Self::Cell(_) => {}
-            Self::Int(_) => {}
-            Self::Float(_) => {}
-            Self::Char(_) => {}
-            Self::Bool(_) => {}
-            Self::Of(enum_type, _) => {
-                *enum_type = enum_type.substitute(name, substitution);
-            }
-            Self::Tuple(items) => {
-                for item in items {
-                    item.substitute(name, substitution);
-                }
-            }
-            Self::Array(items) => {
-                for item in items {
-                    item.substitute(name, substitution);
-                }
-            }
-            Self::Struct(fields) => {
-                for item in fields.values_mut() {
-                    item.substitute(name, substitution);
-                }
-            }
-            Self::Union(inner, _, expr) => {
-                *inner = inner.substitute(name, substitution);
-                expr.substitute(name, substitution);
-            }
-            Self::EnumUnion(inner, _, expr) => {
-                *inner = inner.substitute(name, substitution);
-                expr.substitute(name, substitution);
-            }
-            Self::PolyProc(proc) => {
-                proc.substitute(name, substitution);
-            }
-            Self::Proc(proc) => {
-                proc.substitute(name, substitution);
-            }
-            Self::CoreBuiltin(builtin) => {
-                builtin.substitute(name, substitution);
-            }
-            Self::StandardBuiltin(builtin) => {
-                builtin.substitute(name, substitution);
-            }
-            Self::FFIProcedure(ffi_proc) => {
-                ffi_proc.substitute(name, substitution);
-            }
-            Self::Symbol(symbol_name) if symbol_name == name => {
-                // A constant symbol cannot be substituted for a type variable.
-                *self = ConstExpr::Type(substitution.clone());
-            }
-            _ => {}
-        }
+        *self = Substitute::new(name, substitution).fold_const(std::mem::replace(self, Self::None));
     }
 }
 
@@ -1118,6 +1099,9 @@ impl fmt::Display for ConstExpr {
                 }
                 write!(f, "]")
             }
+            Self::Table(name, start, end, elem) => {
+                write!(f, "[{elem} for {name} in {start}..{end}]")
+            }
             Self::Bool(x) => write!(f, "{}", if *x { "true" } else { "false" }),
             Self::Char(ch) => write!(f, "{ch:?}"),
             Self::Cell(n) => write!(f, "{n:x}"),
@@ -1130,6 +1114,11 @@ impl fmt::Display for ConstExpr {
             Self::Of(t, name) => write!(f, "{t} of {name}"),
             Self::SizeOfExpr(expr) => write!(f, "sizeof({expr}"),
             Self::SizeOfType(ty) => write!(f, "sizeof<{ty}>()"),
+            Self::AlignOfType(ty) => write!(f, "alignof<{ty}>()"),
+            Self::OffsetOf(ty, member) => write!(f, "offsetof<{ty}>({member})"),
+            Self::Cfg(name, None) => write!(f, "cfg({name})"),
+            Self::Cfg(name, Some(value)) => write!(f, "cfg({name} = {value:?})"),
+            Self::Reflect(ty, reflection) => write!(f, "{reflection}<{ty}>()"),
         }
     }
 }
@@ -1264,6 +1253,32 @@ impl Hash for ConstExpr {
                 ty.hash(state);
             }
             Self::Any => state.write_u8(29),
+            Self::AlignOfType(ty) => {
+                state.write_u8(30);
+                ty.hash(state);
+            }
+            Self::OffsetOf(ty, member) => {
+                state.write_u8(31);
+                ty.hash(state);
+                member.hash(state);
+            }
+            Self::Cfg(name, value) => {
+                state.write_u8(32);
+                name.hash(state);
+                value.hash(state);
+            }
+            Self::Reflect(ty, reflection) => {
+                state.write_u8(33);
+                ty.hash(state);
+                reflection.hash(state);
+            }
+            Self::Table(name, start, end, elem) => {
+                state.write_u8(34);
+                name.hash(state);
+                start.hash(state);
+                end.hash(state);
+                elem.hash(state);
+            }
         }
     }
 }
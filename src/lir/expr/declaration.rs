@@ -2,8 +2,8 @@ use super::{PolyProcedure, Procedure};
 use crate::{
     asm::{AssemblyProgram, CoreOp, Location, SP},
     lir::{
-        Compile, ConstExpr, Env, Error, Expr, FFIProcedure, GetSize, GetType, Mutability, Pattern,
-        Type, TypeCheck,
+        compile_return_value, Attribute, Compile, ConstExpr, Env, Error, Expr, FFIProcedure, Fold,
+        GetSize, GetType, Mutability, Pattern, StackAlloc, Substitute, Type, TypeCheck, Warning,
     },
 };
 use core::{
@@ -28,8 +28,8 @@ pub enum Declaration {
     Proc(String, Procedure),
     /// A polymorphic procedure declaration.
     PolyProc(String, PolyProcedure),
-    /// A type declaration.
-    Type(String, Type),
+    /// A type declaration, with its attributes.
+    Type(String, Type, Vec<Attribute>),
     /// A constant expression.
     Const(String, ConstExpr),
     /// A variable declaration with a pattern.
@@ -39,6 +39,9 @@ pub enum Declaration {
     /// Declare associated constants and procedures for a type.
     Impl(Type, Vec<(String, ConstExpr)>),
     /// Many declarations.
+    ///
+    /// The procedures, types, and constants in the block are defined before any
+    /// of them are checked, so they can refer to each other in any order.
     Many(Arc<Vec<Declaration>>),
     /// Declare a module
     ///
@@ -53,10 +56,15 @@ pub enum Declaration {
         names: Vec<(String, Option<String>)>,
     },
     FromImportAll(ConstExpr),
+    /// Assert that a constant condition is true, failing compilation with the message if it isn't.
+    StaticAssert(ConstExpr, String),
 }
 
 impl Declaration {
-    pub(crate) fn static_var(
+    /// Create a static variable declaration. The variable is allocated in the
+    /// global data region, so it can be accessed and mutated from any procedure
+    /// without being passed as an argument.
+    pub fn static_var(
         name: impl Into<String>,
         mutability: Mutability,
         ty: Type,
@@ -65,11 +73,74 @@ impl Declaration {
         Self::StaticVar(name.into(), mutability, ty, expr.into())
     }
 
+    /// Create a static assertion, which fails compilation with the message
+    /// if the condition is false.
+    pub fn static_assert(cond: impl Into<ConstExpr>, message: impl ToString) -> Self {
+        Self::StaticAssert(cond.into(), message.to_string())
+    }
+
     /// Create a collection of declarations
     pub fn many(decls: impl Into<Vec<Self>>) -> Self {
         Self::Many(Arc::new(decls.into()))
     }
 
+    /// Add attributes to the procedures and types declared by this declaration.
+    /// Other kinds of declarations don't have attributes, so they're unchanged.
+    pub fn with_attributes(self, attributes: impl IntoIterator<Item = Attribute>) -> Self {
+        let attributes = attributes.into_iter().collect::<Vec<_>>();
+        match self {
+            Self::Proc(name, proc) => Self::Proc(name, proc.with_attributes(attributes)),
+            Self::PolyProc(name, proc) => Self::PolyProc(name, proc.with_attributes(attributes)),
+            Self::Type(name, ty, mut existing) => {
+                existing.extend(attributes);
+                Self::Type(name, ty, existing)
+            }
+            Self::Many(decls) => Self::many(
+                Arc::unwrap_or_clone(decls)
+                    .into_iter()
+                    .map(|decl| decl.with_attributes(attributes.clone()))
+                    .collect::<Vec<_>>(),
+            ),
+            other => other,
+        }
+    }
+
+    /// The implementation of the procedures derived for a type declaration
+    /// by its `derive` attributes, if it has any.
+    ///
+    /// The procedures of a polymorphic type take the type applied to its own
+    /// parameters, so they're monomorphized along with the type.
+    pub fn derived_impl(name: &str, ty: &Type, attributes: &[Attribute]) -> Option<Self> {
+        let derives = attributes
+            .iter()
+            .flat_map(|attribute| match attribute {
+                Attribute::Derive(derives) => derives.clone(),
+                _ => vec![],
+            })
+            .collect::<Vec<_>>();
+        if derives.is_empty() {
+            return None;
+        }
+
+        let ty = match ty {
+            Type::Poly(params, _) => Type::Apply(
+                Box::new(Type::Symbol(name.to_string())),
+                params
+                    .iter()
+                    .map(|(param, _)| Type::Symbol(param.clone()))
+                    .collect(),
+            ),
+            _ => Type::Symbol(name.to_string()),
+        };
+        Some(Self::Impl(
+            ty.clone(),
+            derives
+                .iter()
+                .map(|derive| (derive.name().to_string(), derive.procedure(ty.clone())))
+                .collect(),
+        ))
+    }
+
     /// Create a module with a given name and a list of declarations, and whether or not it is checked.
     pub fn module(name: impl ToString, decls: impl Into<Vec<Self>>, checked: bool) -> Self {
         lazy_static::lazy_static! {
@@ -213,6 +284,7 @@ impl Declaration {
             Self::FromImport { .. } => true,
             Self::FromImportAll(..) => true,
             Self::StaticVar(..) => true,
+            Self::StaticAssert(..) => true,
             Self::Many(decls) => decls
                 .par_iter()
                 .all(|decl| decl.is_compile_time_declaration()),
@@ -232,6 +304,35 @@ impl Declaration {
             .map(|_| ())
     }
 
+    /// Compile a declaration without a body. Its variables are left on the stack, and it's
+    /// added to the environment, so that the code compiled after it can use it.
+    pub(crate) fn compile_open(
+        &self,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        self.compile_helper(None, env, output).map(|_| ())
+    }
+
+    /// Compile a declaration in the return position of a procedure. The variables
+    /// are left on the stack, and the value of the body is written directly into
+    /// the procedure's return slot.
+    pub(crate) fn compile_return_value(
+        &self,
+        body: Expr,
+        ret_size: usize,
+        env: &Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let mut env = env.clone();
+        let frames = env.get_stack_frame_count();
+        self.compile_helper(None, &mut env, output)?;
+        compile_return_value(body, ret_size, &mut env, output)?;
+        // Restore the frame pointer from before any stack allocations in the declaration.
+        env.compile_restore_frame_pointer(env.get_stack_frame_count() - frames, output);
+        Ok(())
+    }
+
     /// Compile a declaration, and return how many cells were allocated for variables.
     /// This will modify the environment to add the declaration. If there is a body,
     /// it will be compiled under a new scope, and will be popped off the stack when
@@ -248,6 +349,10 @@ impl Declaration {
         // The size of the variables declared.
         // This is used to pop the stack when we're done.
         let mut var_size = 0;
+        // Where the variables start, and how many stack frames were started before them.
+        // Stack allocations start new stack frames, which are ended with the scope.
+        let var_offset = env.get_frame_offset();
+        let frames = env.get_stack_frame_count();
         match self {
             Declaration::Var(name, _mutability, specifier, expr) => {
                 // Get the current instruction (for logging)
@@ -267,11 +372,20 @@ impl Declaration {
                 // println!("Var {name} has type {var_ty:?} is {var_size}");
                 let cur = output.current_instruction();
                 // Compile the expression to leave the value on the stack.
-                expr.clone().compile_expr(env, output)?;
+                // A stack allocation leaves the number of cells to reserve.
+                let stack_alloc = expr.as_stack_alloc();
+                stack_alloc
+                    .unwrap_or(expr)
+                    .clone()
+                    .compile_expr(env, output)?;
                 output.log_instructions_after("var", &log_message, cur);
 
                 // Add the variable to the environment, so that it can be used in the body.
                 env.add_local_variable_declaration(self, true)?;
+                if stack_alloc.is_some() {
+                    // Reserve the cells after the variable, and point the variable to them.
+                    StackAlloc.compile_reserve(env, output)?;
+                }
                 // Log the instructions for the declaration.
                 output.log_instructions_after(name, &log_message, current_instruction);
             }
@@ -322,6 +436,13 @@ impl Declaration {
                     var_size += decl.compile_helper(None, env, output)?;
                 }
             }
+            Declaration::StaticAssert(cond, message) => {
+                // The type parameters of any procedure around the assertion
+                // are known by now, so the condition must be evaluated.
+                if cond.clone().eval(env)? != ConstExpr::Bool(true) {
+                    return Err(Error::StaticAssertionFailed(cond.clone(), message.clone()));
+                }
+            }
             _ => {}
         }
 
@@ -336,7 +457,25 @@ impl Declaration {
 
             // Compile the body under the new scope
             body.compile_expr(env, output)?;
-            if var_size != 0 {
+            let new_frames = env.get_stack_frame_count() - frames;
+            if new_frames != 0 {
+                // The stack allocations make the size of the variables only known at runtime,
+                // so restore the frame pointer and copy the return value over the variables
+                // at their offset from it.
+                env.compile_restore_frame_pointer(new_frames, output);
+                env.pop_stack_frames(new_frames);
+                let vars = env.get_frame_location(var_offset);
+                output.op(CoreOp::Copy {
+                    src: SP.deref().offset(1 - result_size as isize),
+                    dst: vars.clone(),
+                    size: result_size,
+                });
+                // Point the stack pointer to the end of the return value.
+                output.op(CoreOp::GetAddress {
+                    addr: vars.offset(result_size as isize - 1),
+                    dst: SP,
+                });
+            } else if var_size != 0 {
                 // Copy the return value over where the arguments were stored,
                 // so that when we pop the stack, it's as if we popped the variables
                 // and arguments, and pushed our return value.
@@ -408,61 +547,8 @@ impl Declaration {
 
     /// Substitute a type symbol for a type.
     pub(crate) fn substitute(&mut self, substitution_name: &str, substitution_ty: &Type) {
-        match self {
-            Self::StaticVar(_name, _mutability, expected_ty, expr) => {
-                expr.substitute(substitution_name, substitution_ty);
-                expected_ty.substitute(substitution_name, substitution_ty);
-            }
-            Self::Var(_name, _mutability, expected_ty, expr) => {
-                expr.substitute(substitution_name, substitution_ty);
-                if let Some(expected_ty) = expected_ty {
-                    *expected_ty = expected_ty.substitute(substitution_name, substitution_ty);
-                }
-            }
-            Self::Proc(_name, proc) => {
-                proc.substitute(substitution_name, substitution_ty);
-            }
-            Self::PolyProc(_name, proc) => {
-                proc.substitute(substitution_name, substitution_ty);
-            }
-            Self::Type(_name , ty) => {
-                *ty = ty.substitute(substitution_name, substitution_ty);
-            }
-            Self::Const(_name, expr) => {
-                expr.substitute(substitution_name, substitution_ty);
-            }
-            Self::VarPat(_pat, expr) => {
-                expr.substitute(substitution_name, substitution_ty);
-            }
-            Self::ExternProc(_name, proc) => {
-                proc.substitute(substitution_name, substitution_ty);
-            }
-            Self::Impl(_name, impls) => {
-                // for (_name, expr) in impls {
-                //     expr.substitute(substitution_name, substitution_ty);
-                // }
-                impls.par_iter_mut().for_each(|(_name, expr)| {
-                    expr.substitute(substitution_name, substitution_ty);
-                });
-            }
-            Self::Many(decls) => {
-                // for decl in decls {
-                //     decl.substitute(substitution_name, substitution_ty);
-                // }
-                Arc::make_mut(decls).par_iter_mut().for_each(|decl| {
-                    decl.substitute(substitution_name, substitution_ty);
-                });
-            }
-            Self::Module(_name, decls, ..) => {
-                Arc::make_mut(decls).par_iter_mut().for_each(|decl| {
-                    decl.substitute(substitution_name, substitution_ty);
-                });
-            }
-            Self::FromImport { module, .. } => {
-                module.substitute(substitution_name, substitution_ty);
-            }
-            Self::FromImportAll(module) => module.substitute(substitution_name, substitution_ty),
-        }
+        *self = Substitute::new(substitution_name, substitution_ty)
+            .fold_declaration(std::mem::replace(self, Self::many(vec![])));
     }
 }
 
@@ -490,28 +576,52 @@ impl TypeCheck for Declaration {
                             expr: Expr::NONE.with(self.clone()),
                         });
                     }
+                    expr.type_check_against(expected_ty, env)?;
+                } else {
+                    expr.type_check(env)?;
                 }
-
-                expr.type_check(env)?;
             }
             // Typecheck a procedure declaration.
             Self::Proc(name, proc) => {
                 let mut new_env = env.clone();
-                new_env.define_proc(name, proc.clone());
+                // A specialization doesn't hide the polymorphic procedure it specializes.
+                if Attribute::find_specialization(proc.get_attributes()).is_none() {
+                    new_env.define_proc(name, proc.clone());
+                }
                 proc.type_check(&new_env)?;
             }
             // Typecheck a polymorphic procedure declaration.
             Self::PolyProc(name, proc) => {
+                // Only monomorphic procedures can be specializations, tests, or benchmarks.
+                if let Some(attribute) = proc.get_attributes().iter().find(|a| {
+                    matches!(
+                        a,
+                        Attribute::Specialize(_) | Attribute::Test | Attribute::Bench
+                    )
+                }) {
+                    return Err(Error::InvalidAttribute(attribute.clone(), name.clone()));
+                }
                 let mut new_env = env.clone();
                 new_env.define_poly_proc(name, proc.clone());
                 proc.type_check(&new_env)?;
             }
             // Typecheck a type declaration.
-            Self::Type(name, ty) => {
+            Self::Type(name, ty, attributes) => {
+                // Only some attributes make sense on a type.
+                if let Some(attribute) = attributes.iter().find(|a| !a.applies_to_types()) {
+                    return Err(Error::InvalidAttribute(attribute.clone(), name.clone()));
+                }
+                // Warn if this hides a different type with the same name.
+                if matches!(env.get_type(name), Some(found) if found != ty) {
+                    env.warn(Warning::ShadowedType(name.clone()))?;
+                }
                 let mut new_env = env.clone();
                 new_env.define_type(name, ty.clone());
                 // ty.add_monomorphized_associated_consts(env)?;
                 ty.type_check(&new_env)?;
+                if let Some(derived) = Self::derived_impl(name, ty, attributes) {
+                    derived.type_check(&new_env)?;
+                }
             }
             // Typecheck a constant expression.
             Self::Const(name, expr) => {
@@ -528,7 +638,7 @@ impl TypeCheck for Declaration {
                 // Typecheck the specified type of the variable.
                 expected_ty.type_check(&new_env)?;
                 // Typecheck the expression assigned to the variable.
-                expr.type_check(&new_env)?;
+                expr.type_check_against(expected_ty, &new_env)?;
                 let found_ty = expr.get_type(&new_env)?;
 
                 // Make sure the type of the expression matches the type of the variable.
@@ -655,8 +765,13 @@ impl TypeCheck for Declaration {
                 let mut new_env = env.clone();
 
                 // Add all the compile-time declarations to the environment.
+                // The signatures are all collected before any body is checked,
+                // so the declarations can be mutually recursive.
                 self.detect_duplicate_modules(&mut HashSet::new())?;
-                new_env.add_declaration(&self.clone(), false)?;
+                let add_result = new_env.add_declaration(&self.clone(), false);
+                if add_result.is_err() {
+                    new_env.add_declaration_with_recovery(self);
+                }
 
                 // Get all the compile time declarations so we can type check them in parallel.
                 // let (comp_time_decls, run_time_decls): (Vec<_>, Vec<_>) = self.clone()
@@ -667,22 +782,25 @@ impl TypeCheck for Declaration {
                     .iter()
                     .partition(|decl| decl.is_compile_time_declaration());
 
-                if !comp_time_decls.is_empty() {
-                    // Type check all the compile time declarations in parallel.
-                    comp_time_decls.par_iter().try_for_each(|decl| {
-                        debug!("Typechecking decl: {decl}");
-                        decl.type_check(&new_env)
-                        // Ok::<(), Error>(())
-                    })?;
+                // Type check all the compile time declarations in parallel,
+                // collecting all of their errors.
+                let mut results = vec![add_result];
+                results.par_extend(comp_time_decls.par_iter().map(|decl| {
+                    debug!("Typechecking decl: {decl}");
+                    decl.type_check(&new_env)
+                }));
+
+                for decl in run_time_decls {
+                    match decl.type_check(&new_env) {
+                        Ok(()) => results.push(new_env.add_declaration(decl, false)),
+                        Err(err) => {
+                            // Define what we can so the later declarations can still be checked.
+                            results.push(Err(err));
+                            new_env.add_declaration_with_recovery(decl);
+                        }
+                    }
                 }
-
-                run_time_decls
-                    .iter()
-                    // .map(|decl| decl.type_check(&new_env))
-                    .try_for_each(|decl| {
-                        decl.type_check(&new_env)?;
-                        new_env.add_declaration(decl, false)
-                    })?;
+                Error::collect(results)?;
             }
 
             Self::Module(name, decls, checked, ..) => {
@@ -718,6 +836,23 @@ impl TypeCheck for Declaration {
             Self::FromImportAll(module) => {
                 module.type_check(env)?;
             },
+            Self::StaticAssert(cond, message) => {
+                cond.type_check(env)?;
+                let found = cond.get_type(env)?;
+                if !found.equals(&Type::Bool, env)? {
+                    return Err(Error::MismatchedTypes {
+                        expected: Type::Bool,
+                        found,
+                        expr: Expr::NONE.with(self.clone()),
+                    });
+                }
+                // The condition may depend on the type parameters of a procedure,
+                // which aren't known until it's monomorphized. Then it's checked
+                // again when it's compiled.
+                if let Ok(ConstExpr::Bool(false)) = cond.clone().eval(env) {
+                    return Err(Error::StaticAssertionFailed(cond.clone(), message.clone()));
+                }
+            }
         }
         Ok(())
     }
@@ -743,7 +878,10 @@ impl Display for Declaration {
                 write!(f, "{}", proc)?;
                 write!(f, " {}", name)?;
             }
-            Self::Type(name, ty) => {
+            Self::Type(name, ty, attributes) => {
+                for attribute in attributes {
+                    write!(f, "{} ", attribute)?;
+                }
                 write!(f, "type {}", name)?;
                 write!(f, " = {}", ty)?;
             }
@@ -783,6 +921,7 @@ impl Display for Declaration {
                 }
             }
             Self::FromImportAll(module) => write!(f, "from {module} import *")?,
+            Self::StaticAssert(cond, message) => write!(f, "static_assert({cond}, {message:?})")?,
         }
         Ok(())
     }
@@ -790,13 +929,13 @@ impl Display for Declaration {
 
 impl From<(String, Type)> for Declaration {
     fn from((name, ty): (String, Type)) -> Self {
-        Self::Type(name, ty)
+        Self::Type(name, ty, vec![])
     }
 }
 
 impl From<(&str, Type)> for Declaration {
     fn from((name, expr): (&str, Type)) -> Self {
-        Self::Type(name.to_string(), expr)
+        Self::Type(name.to_string(), expr, vec![])
     }
 }
 
@@ -984,10 +1123,11 @@ impl Hash for Declaration {
                 name.hash(state);
                 proc.hash(state);
             }
-            Self::Type(name, ty) => {
+            Self::Type(name, ty, attributes) => {
                 state.write_u8(4);
                 name.hash(state);
                 ty.hash(state);
+                attributes.hash(state);
             }
             Self::Const(name, expr) => {
                 state.write_u8(5);
@@ -1028,6 +1168,11 @@ impl Hash for Declaration {
                 state.write_u8(12);
                 module.hash(state);
             }
+            Self::StaticAssert(cond, message) => {
+                state.write_u8(13);
+                cond.hash(state);
+                message.hash(state);
+            }
         }
     }
 }
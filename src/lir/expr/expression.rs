@@ -60,6 +60,12 @@ pub enum Expr {
     /// the matched variables in scope.
     /// If the match fails, the else expression is evaluated.
     IfLet(Pattern, Box<Self>, Box<Self>, Box<Self>),
+    /// A while-let loop.
+    ///
+    /// This repeatedly matches an expression against a pattern,
+    /// and evaluates the body expression with the matched variables
+    /// in scope, until the match fails.
+    WhileLet(Pattern, Box<Self>, Box<Self>),
 
     /// Perform a unary operation on two expressions.
     UnaryOp(String, Box<Self>),
@@ -85,6 +91,12 @@ pub enum Expr {
 
     /// An array of expressions.
     Array(Vec<Self>),
+    /// An array of a constant number of copies of an element.
+    ///
+    /// The element is evaluated once, and then copied into the rest
+    /// of the array by a loop, so the size of the code doesn't grow
+    /// with the length of the array.
+    ArrayRepeat(Box<Self>, Box<ConstExpr>),
     /// A tuple of expressions.
     Tuple(Vec<Self>),
     /// A union: a collection of named fields.
@@ -112,6 +124,11 @@ pub enum Expr {
     Member(Box<Self>, ConstExpr),
     /// Index an array or pointer with an expression that evaluates to an `Int` at runtime.
     Index(Box<Self>, Box<Self>),
+
+    /// A typed hole, with an optional name: a placeholder for an expression that
+    /// hasn't been written yet. A hole type checks as any type, but reports the type
+    /// expected in its place and the bindings in scope that fit it as an error.
+    Hole(Option<String>),
 }
 
 impl From<ConstExpr> for Expr {
@@ -137,6 +154,70 @@ impl Expr {
         ])
     }
 
+    /// Log a message made of some values at a level, on the log channel.
+    pub fn log(level: crate::side_effects::LogLevel, args: Vec<Self>) -> Self {
+        let channel = Self::ConstExpr(ConstExpr::Int(crate::side_effects::LOG_CHANNEL as i64));
+        let prefix = ConstExpr::string_literal(level.prefix())
+            .expect("log level prefixes have no escape sequences");
+        Self::Many(
+            std::iter::once(prefix.into())
+                .chain(args)
+                .chain(std::iter::once(ConstExpr::Char('\n').into()))
+                .map(|arg| channel.clone().binop(PutTo(Put::DisplayErr), arg))
+                .collect(),
+        )
+    }
+
+    /// The next key pressed on a channel, or `NO_KEY` if there isn't one.
+    pub fn poll_key(channel: usize) -> Self {
+        Self::ConstExpr(ConstExpr::Int(channel as i64)).unop(ReadFrom(Sense::Key))
+    }
+
+    /// The width and height of the terminal on a channel, in columns and rows.
+    pub fn terminal_size(channel: usize) -> (Self, Self) {
+        let channel = Self::ConstExpr(ConstExpr::Int(channel as i64));
+        (
+            channel.clone().unop(ReadFrom(Sense::TerminalWidth)),
+            channel.unop(ReadFrom(Sense::TerminalHeight)),
+        )
+    }
+
+    /// Set the pixel at `(x, y)` of the framebuffer on a channel to a 0xRRGGBB color.
+    pub fn set_pixel(
+        channel: usize,
+        x: impl Into<Self>,
+        y: impl Into<Self>,
+        color: impl Into<Self>,
+    ) -> Self {
+        let channel = Self::ConstExpr(ConstExpr::Int(channel as i64));
+        Self::Many(vec![
+            channel.clone().binop(DrawTo(Draw::Column), x),
+            channel.clone().binop(DrawTo(Draw::Row), y),
+            channel.binop(DrawTo(Draw::Pixel), color),
+        ])
+    }
+
+    /// Fill the framebuffer on a channel with a 0xRRGGBB color.
+    pub fn clear_display(channel: usize, color: impl Into<Self>) -> Self {
+        Self::ConstExpr(ConstExpr::Int(channel as i64)).binop(DrawTo(Draw::Clear), color)
+    }
+
+    /// Present the frame drawn on the framebuffer on a channel.
+    pub fn present(channel: usize) -> Self {
+        Self::ConstExpr(ConstExpr::Int(channel as i64))
+            .binop(DrawTo(Draw::Present), ConstExpr::Int(0))
+    }
+
+    /// Play a PCM sample, from -32768 to 32767, on the speaker on a channel.
+    pub fn play_sample(channel: usize, sample: impl Into<Self>) -> Self {
+        Self::ConstExpr(ConstExpr::Int(channel as i64)).binop(PlayTo(Play::Sample), sample)
+    }
+
+    /// Set the number of samples played per second on the speaker on a channel.
+    pub fn set_sample_rate(channel: usize, rate: impl Into<Self>) -> Self {
+        Self::ConstExpr(ConstExpr::Int(channel as i64)).binop(PlayTo(Play::SampleRate), rate)
+    }
+
     pub fn is_method_call(&self, env: &Env) -> Result<bool, Error> {
         let result = match self {
             Self::Annotated(inner, annotation) => {
@@ -454,6 +535,111 @@ impl Expr {
         }
     }
 
+    /// If this expression is a reference to a member of a bitfield, get the address
+    /// of the cell containing the member, along with the member's bit offset within
+    /// that cell and its width in bits.
+    ///
+    /// Bitfield members don't have addresses of their own, so they are written by
+    /// updating the bits of the cell that contains them.
+    pub fn get_bitfield_member_reference(
+        &self,
+        env: &Env,
+    ) -> Result<Option<(Self, usize, usize)>, Error> {
+        let (mutability, inner) = match self {
+            Self::Annotated(inner, _) => return inner.get_bitfield_member_reference(env),
+            Self::Refer(mutability, inner) => (*mutability, inner.as_ref().clone()),
+            _ => return Ok(None),
+        };
+
+        let (val, member) = match inner {
+            Self::Annotated(inner, _) => {
+                return Self::Refer(mutability, inner).get_bitfield_member_reference(env)
+            }
+            Self::Member(val, member) => (*val, member),
+            Self::ConstExpr(ConstExpr::Member(val, member)) => (Self::ConstExpr(*val), *member),
+            _ => return Ok(None),
+        };
+
+        let val_type = val.get_type(env)?;
+        if let Some((offset, shift, bits)) = val_type.get_bitfield_member(&member, env)? {
+            // Get the address of the bitfield itself.
+            let addr = if let Type::Pointer(..) = val_type.simplify_until_has_members(env, false)? {
+                val
+            } else {
+                val.refer(mutability)
+            };
+            // Offset the address to the cell containing the member.
+            let addr = addr
+                .as_type(Type::Pointer(mutability, Box::new(Type::Int)))
+                .idx(ConstExpr::Int(offset as i64))
+                .refer(mutability);
+            Ok(Some((addr, shift, bits)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Create a typed hole, with an optional name.
+    pub fn hole(name: Option<impl ToString>) -> Self {
+        Self::Hole(name.map(|name| name.to_string()))
+    }
+
+    /// Is this expression a procedure application (ignoring annotations)?
+    pub fn is_apply(&self) -> bool {
+        match self {
+            Self::Annotated(expr, _) => expr.is_apply(),
+            Self::Apply(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Get the number of cells to reserve, if this is a stack allocation (ignoring annotations).
+    pub fn as_stack_alloc(&self) -> Option<&Self> {
+        match self {
+            Self::Annotated(expr, _) => expr.as_stack_alloc(),
+            Self::UnaryOp(op, size) if *op == StackAlloc.to_string() => Some(size),
+            _ => None,
+        }
+    }
+
+    /// Evaluate this expression at compile time. This only works for constants,
+    /// operations on constants which can be folded, casts, blocks, and conditionals.
+    /// Operations with side effects are never evaluated, even if their operands are constant.
+    pub fn eval_const(&self, env: &Env) -> Result<ConstExpr, Error> {
+        let non_const = || Error::NonConstExpr(self.clone());
+        match self {
+            Self::Annotated(expr, _) => expr.eval_const(env),
+            Self::ConstExpr(expr) => expr.clone().eval(env),
+            Self::UnaryOp(op, expr) => {
+                let expr = expr.eval_const(env)?;
+                let op = env.get_unop(op).ok_or_else(non_const)?;
+                if !op.effects().is_pure() {
+                    return Err(non_const());
+                }
+                op.eval(&expr, &mut env.clone())
+            }
+            Self::BinaryOp(op, lhs, rhs) => {
+                let lhs = lhs.eval_const(env)?;
+                let rhs = rhs.eval_const(env)?;
+                let op = env.get_binop(op).ok_or_else(non_const)?;
+                if !op.effects().is_pure() {
+                    return Err(non_const());
+                }
+                op.eval(&lhs, &rhs, &mut env.clone())
+            }
+            Self::As(expr, ty) => expr.eval_const(env)?.as_type(ty.clone()).eval(env),
+            Self::Many(exprs) => exprs
+                .iter()
+                .try_fold(ConstExpr::None, |_, expr| expr.eval_const(env)),
+            Self::If(cond, then, otherwise) => match cond.eval_const(env)? {
+                ConstExpr::Bool(true) => then.eval_const(env),
+                ConstExpr::Bool(false) => otherwise.eval_const(env),
+                _ => Err(non_const()),
+            },
+            _ => Err(non_const()),
+        }
+    }
+
     /// An annotated expression with some metadata.
     pub fn annotate(&self, annotation: impl Into<Annotation>) -> Self {
         match self {
@@ -497,6 +683,23 @@ impl Expr {
         Self::Declare(Box::new(older_decls.into()), self.clone().into())
     }
 
+    /// The declarations at the top level of a program, outside of any procedure.
+    pub fn top_level_declarations(&self) -> Vec<Declaration> {
+        match self {
+            Self::Declare(decl, body) => {
+                let mut decls = vec![decl.as_ref().clone()];
+                decls.extend(body.top_level_declarations());
+                decls
+            }
+            Self::Annotated(inner, _) => inner.top_level_declarations(),
+            Self::Many(exprs) => exprs
+                .iter()
+                .flat_map(Self::top_level_declarations)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     /// Get the size of an expression.
     pub fn size_of(self) -> Self {
         Self::ConstExpr(ConstExpr::SizeOfExpr(Box::new(self)))
@@ -722,6 +925,26 @@ impl Expr {
         ret.into().with(procs)
     }
 
+    /// Build a struct from an existing value, with some of its fields replaced.
+    ///
+    /// The base value is copied into a new variable, and then each of the
+    /// replaced fields is assigned to its new value. This typechecks that
+    /// every replaced field is a member of the base value with a matching type.
+    pub fn struct_update(base: impl Into<Self>, fields: Vec<(String, Self)>) -> Self {
+        let var = "__struct_update__";
+        let mut body = vec![];
+        for (name, val) in fields {
+            body.push(
+                Self::var(var)
+                    .field(ConstExpr::Symbol(name))
+                    .refer(Mutability::Mutable)
+                    .deref_mut(val),
+            );
+        }
+        body.push(Self::var(var));
+        Self::let_var(var, Mutability::Mutable, None, base, Self::Many(body))
+    }
+
     /// Create a structure of fields to expressions.
     pub fn structure(vars: BTreeMap<&str, Self>) -> Self {
         let mut result = BTreeMap::new();
@@ -751,6 +974,16 @@ impl Expr {
         Expr::While(Box::new(self), Box::new(body.into()))
     }
 
+    /// Create an if-let statement, which matches this expression against a pattern.
+    pub fn if_let(self, pat: Pattern, t: impl Into<Self>, e: impl Into<Self>) -> Self {
+        Expr::IfLet(pat, Box::new(self), Box::new(t.into()), Box::new(e.into()))
+    }
+
+    /// Create a while-let loop, which runs while this expression matches a pattern.
+    pub fn while_let(self, pat: Pattern, body: impl Into<Self>) -> Self {
+        Expr::WhileLet(pat, Box::new(self), Box::new(body.into()))
+    }
+
     /// Reference this expression (i.e. get a pointer to it).
     pub fn refer(self, mutability: impl Into<Mutability>) -> Self {
         Expr::Refer(mutability.into(), Box::new(self))
@@ -796,6 +1029,7 @@ impl fmt::Display for Expr {
                 }
                 write!(f, " }}")
             }
+            Self::ArrayRepeat(elem, len) => write!(f, "[{elem}; {len}]"),
             Self::Array(exprs) => {
                 write!(f, "[")?;
                 for (i, item) in exprs.iter().enumerate() {
@@ -836,6 +1070,9 @@ impl fmt::Display for Expr {
             Self::IfLet(pat, expr, t, e) => {
                 write!(f, "if let {pat} = {expr} {t} else {e}")
             }
+            Self::WhileLet(pat, expr, body) => {
+                write!(f, "while let {pat} = {expr} {body}")
+            }
             Self::When(cond, t, e) => {
                 write!(f, "when ({cond}) {t} else {e}")
             }
@@ -865,6 +1102,8 @@ impl fmt::Display for Expr {
 
             Self::Member(val, field) => write!(f, "({val}).{field}"),
             Self::Index(val, idx) => write!(f, "{val}[{idx}]"),
+            Self::Hole(Some(name)) => write!(f, "?{name}"),
+            Self::Hole(None) => write!(f, "?"),
 
             Self::Return(val) => write!(f, "return {val}"),
             Self::Refer(mutability, val) => {
@@ -904,6 +1143,10 @@ impl PartialEq for Expr {
 
             // Create a while loop: while the first expression evaluates to true, evaluate the second expression.
             (While(cond1, body1), While(cond2, body2)) => cond1 == cond2 && body1 == body2,
+            // A while-let loop: while the expression matches the pattern, evaluate the body.
+            (WhileLet(pat1, expr1, body1), WhileLet(pat2, expr2, body2)) => {
+                pat1 == pat2 && expr1 == expr2 && body1 == body2
+            }
 
             // An if-then-else expression.
             //
@@ -951,6 +1194,8 @@ impl PartialEq for Expr {
 
             // An array of expressions.
             (Array(vals1), Array(vals2)) => vals1 == vals2,
+            // An array of copies of an element.
+            (ArrayRepeat(elem1, len1), ArrayRepeat(elem2, len2)) => elem1 == elem2 && len1 == len2,
             // A tuple of expressions.
             (Tuple(vals1), Tuple(vals2)) => vals1 == vals2,
             // A union: a collection of named fields.
@@ -983,6 +1228,8 @@ impl PartialEq for Expr {
 
             (Declare(decl1, expr1), Declare(decl2, expr2)) => expr1 == expr2 && decl1 == decl2,
 
+            (Hole(name1), Hole(name2)) => name1 == name2,
+
             _ => false,
         }
     }
@@ -1146,6 +1393,24 @@ impl Hash for Expr {
                 decl.hash(state);
                 expr.hash(state);
             }
+
+            Hole(name) => {
+                state.write_u8(23);
+                name.hash(state);
+            }
+
+            WhileLet(pat, expr, body) => {
+                state.write_u8(24);
+                pat.hash(state);
+                expr.hash(state);
+                body.hash(state);
+            }
+
+            ArrayRepeat(elem, len) => {
+                state.write_u8(25);
+                elem.hash(state);
+                len.hash(state);
+            }
         }
     }
 }
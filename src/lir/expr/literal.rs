@@ -0,0 +1,120 @@
+//! # Literals
+//!
+//! This module builds character and string constants from the source text of
+//! literals, so that every frontend handles escape sequences the same way.
+//!
+//! |Escape|Character|
+//! |---|---|
+//! |`\0`|Null|
+//! |`\n`|Newline|
+//! |`\r`|Carriage return|
+//! |`\t`|Tab|
+//! |`\b`|Backspace|
+//! |`\f`|Form feed|
+//! |`\\`, `\/`, `\'`, `\"`|The escaped character|
+//! |`\xHH`|The ASCII character with the hexadecimal code `HH`|
+//! |`\u{H...}`|The unicode code point with 1 to 6 hexadecimal digits|
+//!
+//! A backslash followed by whitespace skips the whitespace, so long strings can
+//! be split over several lines.
+
+use super::ConstExpr;
+use crate::lir::Error;
+use std::ops::RangeInclusive;
+
+/// Unescape the text between the quotes of a character or string literal.
+pub fn unescape(literal: &str) -> Result<String, Error> {
+    let invalid = |reason: String| Error::InvalidLiteral(literal.to_string(), reason);
+
+    let mut result = String::new();
+    let mut chars = literal.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        result.push(match chars.next() {
+            Some('0') => '\0',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('b') => '\u{08}',
+            Some('f') => '\u{0C}',
+            Some(c @ ('\\' | '/' | '\'' | '"')) => c,
+            Some('x') => {
+                let code: String = (0..2).filter_map(|_| chars.next()).collect();
+                match u8::from_str_radix(&code, 16) {
+                    Ok(byte) if is_hex(&code, 2..=2) && byte.is_ascii() => byte as char,
+                    _ => return Err(invalid(format!("invalid escape sequence \\x{code}"))),
+                }
+            }
+            Some('u') => {
+                if chars.next() != Some('{') {
+                    return Err(invalid("expected { after \\u".to_string()));
+                }
+                let mut code = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => code.push(c),
+                        None => return Err(invalid("unclosed unicode escape".to_string())),
+                    }
+                }
+                if !is_hex(&code, 1..=6) {
+                    return Err(invalid(format!(
+                        "unicode escape \\u{{{code}}} must have 1 to 6 hex digits"
+                    )));
+                }
+                u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| invalid(format!("invalid unicode escape \\u{{{code}}}")))?
+            }
+            Some(c) if c.is_whitespace() => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                continue;
+            }
+            Some(c) => return Err(invalid(format!("unknown escape sequence \\{c}"))),
+            None => return Err(invalid("unfinished escape sequence".to_string())),
+        });
+    }
+    Ok(result)
+}
+
+/// Is the code of an escape sequence made of an allowed number of hexadecimal digits?
+fn is_hex(code: &str, digits: RangeInclusive<usize>) -> bool {
+    digits.contains(&code.len()) && code.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Unescape the text between the quotes of a character literal, which must
+/// unescape to exactly one character.
+pub fn unescape_char(literal: &str) -> Result<char, Error> {
+    let invalid = |reason: &str| Error::InvalidLiteral(literal.to_string(), reason.to_string());
+    let unescaped = unescape(literal)?;
+    let mut chars = unescaped.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        (None, _) => Err(invalid("empty character literal")),
+        (Some(_), Some(_)) => Err(invalid("character literal has more than one character")),
+    }
+}
+
+impl ConstExpr {
+    /// A character constant from the text between the quotes of a character literal.
+    pub fn char_literal(literal: &str) -> Result<Self, Error> {
+        unescape_char(literal).map(Self::Char)
+    }
+
+    /// A null-terminated array of characters from the text between the quotes
+    /// of a string literal.
+    pub fn string_literal(literal: &str) -> Result<Self, Error> {
+        Ok(Self::Array(
+            unescape(literal)?
+                .chars()
+                .chain(std::iter::once('\0'))
+                .map(Self::Char)
+                .collect(),
+        ))
+    }
+}
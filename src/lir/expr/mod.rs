@@ -4,6 +4,7 @@
 mod const_expr;
 mod declaration;
 mod expression;
+mod literal;
 mod ops;
 mod pattern;
 mod procedure;
@@ -11,6 +12,7 @@ mod procedure;
 pub use const_expr::*;
 pub use declaration::*;
 pub use expression::*;
+pub use literal::*;
 pub use ops::*;
 pub use pattern::*;
 pub use procedure::*;
@@ -7,6 +7,7 @@
 //! - `Divide`
 //! - `Remainder`
 //! - `Power`
+//! - The checked, wrapping, and saturating integer operations, like `add_checked`
 
 use crate::{
     asm::{AssemblyProgram, CoreOp, StandardOp, A, B, SP},
@@ -16,9 +17,11 @@ use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use log::*;
 mod addition;
 mod negate;
+mod overflow;
 
 pub use addition::*;
 pub use negate::*;
+pub use overflow::*;
 
 /// An arithmetic operation.
 #[derive(Clone, Copy)]
@@ -0,0 +1,242 @@
+//! # Overflow Arithmetic
+//!
+//! This module implements integer arithmetic with a chosen behavior on overflow:
+//! - `add_checked`, `sub_checked`, `mul_checked`: the wrapped result, and whether it overflowed.
+//! - `add_wrapping`, `sub_wrapping`, `mul_wrapping`: the result, wrapped around the range of `Int`.
+//! - `add_saturating`, `sub_saturating`, `mul_saturating`: the result, clamped to the range of `Int`.
+//!
+//! An `Int` is a two's complement integer as wide as a cell on the target: 64 bits on the
+//! virtual machines, but narrower on targets like the 6502 and Cortex-M (see the `cell_width`
+//! compile parameter). The virtual machines have no overflow flags, so overflow is detected
+//! by comparing the operands against the bounds of `Int` *before* the operation is performed,
+//! like `a > MAX - b` for addition. None of the comparisons overflow themselves.
+use super::*;
+
+/// The smallest and largest `Int` on the target being compiled to.
+fn int_bounds(env: &Env) -> (i64, i64) {
+    let shift = BITS_PER_CELL - env.get_cell_width();
+    (i64::MIN >> shift, i64::MAX >> shift)
+}
+
+/// The name of the tuple which holds the operands, while the overflow is checked.
+const OVERFLOW_ARGS: &str = "__overflow_args";
+
+/// What to do when an integer operation overflows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Overflow {
+    /// Return the wrapped result, along with whether the operation overflowed.
+    Checked,
+    /// Return the result wrapped around the range of `Int`.
+    Wrapping,
+    /// Return the largest or smallest `Int`, whichever is closest to the true result.
+    Saturating,
+}
+
+/// An integer operation with a chosen behavior on overflow.
+/// Only addition, subtraction, and multiplication are supported.
+#[derive(Clone, Copy)]
+pub struct OverflowArithmetic(pub Arithmetic, pub Overflow);
+
+impl OverflowArithmetic {
+    /// Every supported operation, for registering them in the environment.
+    pub fn all() -> Vec<Self> {
+        let mut result = vec![];
+        for op in [Arithmetic::Add, Arithmetic::Subtract, Arithmetic::Multiply] {
+            for overflow in [Overflow::Checked, Overflow::Wrapping, Overflow::Saturating] {
+                result.push(Self(op, overflow));
+            }
+        }
+        result
+    }
+
+    /// Perform the operation on two integers, returning the exact result,
+    /// the result wrapped around the range of `Int` on the target, and whether it overflowed.
+    fn overflowing(&self, a: i64, b: i64, env: &Env) -> Result<(i128, i64, bool), Error> {
+        let (a, b) = (a as i128, b as i128);
+        let exact = match self.0 {
+            Arithmetic::Add => a + b,
+            Arithmetic::Subtract => a - b,
+            Arithmetic::Multiply => a * b,
+            _ => return Err(Error::UnimplementedOperator(self.to_string())),
+        };
+        let (min, max) = int_bounds(env);
+        // Keep the low bits of the result, and sign extend them to the width of a cell.
+        let shift = BITS_PER_CELL - env.get_cell_width();
+        let wrapped = ((exact as i64) << shift) >> shift;
+        Ok((exact, wrapped, exact < min as i128 || exact > max as i128))
+    }
+
+    /// An expression which is true if the operation on `a` and `b` overflows.
+    fn overflows(&self, a: Expr, b: Expr, env: &Env) -> Result<Expr, Error> {
+        let zero = ConstExpr::Int(0);
+        let (min, max) = int_bounds(env);
+        let (int_min, int_max) = (ConstExpr::Int(min), ConstExpr::Int(max));
+        Ok(match self.0 {
+            // `a + b > MAX` when `b > 0`, and `a + b < MIN` otherwise.
+            Arithmetic::Add => b.clone().gt(zero).if_then(
+                a.clone().gt(Expr::from(int_max).sub(b.clone())),
+                a.lt(Expr::from(int_min).sub(b)),
+            ),
+            // `a - b > MAX` when `b < 0`, and `a - b < MIN` otherwise.
+            Arithmetic::Subtract => b.clone().lt(zero).if_then(
+                a.clone().gt(Expr::from(int_max).add(b.clone())),
+                a.lt(Expr::from(int_min).add(b)),
+            ),
+            // Compare each operand against the bound divided by the other,
+            // depending on the signs of the operands.
+            Arithmetic::Multiply => a.clone().gt(zero.clone()).if_then(
+                b.clone().gt(zero.clone()).if_then(
+                    a.clone().gt(Expr::from(int_max.clone()).div(b.clone())),
+                    b.clone().lt(Expr::from(int_min.clone()).div(a.clone())),
+                ),
+                b.clone().gt(zero.clone()).if_then(
+                    a.clone().lt(Expr::from(int_min).div(b.clone())),
+                    a.clone()
+                        .neq(zero)
+                        .if_then(b.lt(Expr::from(int_max).div(a)), ConstExpr::Bool(false)),
+                ),
+            ),
+            _ => return Err(Error::UnimplementedOperator(self.to_string())),
+        })
+    }
+
+    /// The bound which an overflowing operation on `a` and `b` saturates to.
+    fn saturated(&self, a: Expr, b: Expr, env: &Env) -> Result<Expr, Error> {
+        let zero = ConstExpr::Int(0);
+        let (min, max) = int_bounds(env);
+        let (int_min, int_max) = (ConstExpr::Int(min), ConstExpr::Int(max));
+        Ok(match self.0 {
+            Arithmetic::Add => b.gt(zero).if_then(int_max, int_min),
+            Arithmetic::Subtract => b.lt(zero).if_then(int_max, int_min),
+            // The result is positive when the operands have the same sign.
+            Arithmetic::Multiply => a.gt(zero.clone()).eq(b.gt(zero)).if_then(int_max, int_min),
+            _ => return Err(Error::UnimplementedOperator(self.to_string())),
+        })
+    }
+}
+
+impl BinaryOp for OverflowArithmetic {
+    /// Compile the operation, by checking for overflow with comparisons on the operands.
+    fn compile(
+        &self,
+        lhs: &Expr,
+        rhs: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        // The wrapping operations are the plain operations:
+        // integer arithmetic wraps on every target.
+        if self.1 == Overflow::Wrapping {
+            return self.0.compile(lhs, rhs, env, output);
+        }
+
+        // Evaluate the operands once, and check them for overflow.
+        let args = Expr::var(OVERFLOW_ARGS);
+        let a = args.clone().field(ConstExpr::Int(0));
+        let b = args.field(ConstExpr::Int(1));
+        let wrapped = a.clone().binop(self.0, b.clone());
+        let overflows = self.overflows(a.clone(), b.clone(), env)?;
+        let result = match self.1 {
+            Overflow::Checked => Expr::Tuple(vec![wrapped, overflows]),
+            _ => overflows.if_then(self.saturated(a, b, env)?, wrapped),
+        };
+
+        Expr::let_var(
+            OVERFLOW_ARGS,
+            Mutability::Immutable,
+            Some(Type::Tuple(vec![Type::Int, Type::Int])),
+            Expr::Tuple(vec![lhs.clone(), rhs.clone()]),
+            result,
+        )
+        .compile_expr(env, output)
+    }
+
+    /// Both operands must be integers.
+    fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(lhs.equals(&Type::Int, env)? && rhs.equals(&Type::Int, env)?)
+    }
+
+    /// The checked operations return the result and whether it overflowed,
+    /// and the others return just the result.
+    fn return_type(&self, lhs: &Expr, rhs: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply_exprs(lhs, rhs, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                lhs.clone(),
+                rhs.clone(),
+            ));
+        }
+        Ok(match self.1 {
+            Overflow::Checked => Type::Tuple(vec![Type::Int, Type::Bool]),
+            _ => Type::Int,
+        })
+    }
+
+    /// Evaluate the operation on constant integers.
+    fn eval(&self, lhs: &ConstExpr, rhs: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        let (a, b) = match (lhs.clone().eval(env)?, rhs.clone().eval(env)?) {
+            (ConstExpr::Int(a), ConstExpr::Int(b)) => (a, b),
+            _ => {
+                return Err(Error::InvalidBinaryOp(
+                    self.clone_box(),
+                    Expr::ConstExpr(lhs.clone()),
+                    Expr::ConstExpr(rhs.clone()),
+                ))
+            }
+        };
+        let (exact, wrapped, overflowed) = self.overflowing(a, b, env)?;
+        let (min, max) = int_bounds(env);
+        Ok(match self.1 {
+            Overflow::Checked => {
+                ConstExpr::Tuple(vec![ConstExpr::Int(wrapped), ConstExpr::Bool(overflowed)])
+            }
+            Overflow::Wrapping => ConstExpr::Int(wrapped),
+            Overflow::Saturating => ConstExpr::Int(exact.clamp(min as i128, max as i128) as i64),
+        })
+    }
+
+    /// The operation is compiled by `compile`, so this is never used.
+    fn compile_types(
+        &self,
+        _lhs: &Type,
+        _rhs: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, lhs: &Expr, rhs: &Expr) -> String {
+        format!("{self}({lhs}, {rhs})")
+    }
+}
+
+impl Debug for OverflowArithmetic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for OverflowArithmetic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let op = match self.0 {
+            Arithmetic::Add => "add",
+            Arithmetic::Subtract => "sub",
+            Arithmetic::Multiply => "mul",
+            Arithmetic::Divide => "div",
+            Arithmetic::Remainder => "rem",
+            Arithmetic::Power => "pow",
+        };
+        let overflow = match self.1 {
+            Overflow::Checked => "checked",
+            Overflow::Wrapping => "wrapping",
+            Overflow::Saturating => "saturating",
+        };
+        write!(f, "{op}_{overflow}")
+    }
+}
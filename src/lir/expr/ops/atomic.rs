@@ -0,0 +1,262 @@
+//! # Atomic Operations
+//!
+//! This module implements atomic operations on a cell behind a pointer:
+//! - `atomic_load(ptr)`: the value of the cell.
+//! - `atomic_store(ptr, val)`: write `val` to the cell.
+//! - `atomic_fetch_add(ptr, val)`: add `val` to the cell, and return its previous value.
+//! - `atomic_compare_swap(ptr, expected, new)`: write `new` to the cell if it equals
+//!   `expected`, and return whether it was written.
+//!
+//! The interpreters are single-threaded, so they implement these like the plain
+//! operations, but the C target maps them to real atomics. They all require the
+//! standard instructions.
+use super::*;
+use crate::asm::{CoreOp, StandardOp, SP};
+use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// The type of the cell which the atomic operations act on.
+fn atomic_cell(mutability: Mutability) -> Type {
+    Type::Pointer(mutability, Box::new(Type::Int))
+}
+
+/// The error for an atomic operation on a target without the standard instructions.
+fn unsupported(name: String) -> Error {
+    Error::UnsupportedOperation(Expr::UnaryOp(
+        name,
+        Box::new(Expr::ConstExpr(ConstExpr::None)),
+    ))
+}
+
+/// Atomically load the integer behind a pointer.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct AtomicLoad;
+
+impl UnaryOp for AtomicLoad {
+    /// The operand must be a pointer to an integer.
+    fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        ty.equals(&atomic_cell(Mutability::Any), env)
+    }
+
+    /// The result is the integer behind the pointer.
+    fn return_type(&self, expr: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply(&expr.get_type(env)?, env)? {
+            return Err(Error::InvalidUnaryOp(self.clone_box(), expr.clone()));
+        }
+        Ok(Type::Int)
+    }
+
+    /// Atomic operations can't be evaluated at compile time.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Replace the pointer on the stack with the value it points to.
+    fn compile_types(
+        &self,
+        _ty: &Type,
+        _env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        output
+            .std_op(StandardOp::AtomicLoad {
+                ptr: SP.deref(),
+                dst: SP.deref(),
+            })
+            .map_err(|_| unsupported(self.name()))
+    }
+
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for AtomicLoad {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for AtomicLoad {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "atomic_load")
+    }
+}
+
+/// An atomic operation which writes an integer to the cell behind a pointer.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Atomic {
+    /// Store the integer in the cell.
+    Store,
+    /// Add the integer to the cell, and return the cell's previous value.
+    FetchAdd,
+}
+
+impl BinaryOp for Atomic {
+    /// The first operand must be a mutable pointer to an integer,
+    /// and the second operand must be an integer.
+    fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(lhs.equals(&atomic_cell(Mutability::Mutable), env)? && rhs.equals(&Type::Int, env)?)
+    }
+
+    /// A store returns nothing, and a fetch-add returns the previous value.
+    fn return_type(&self, lhs: &Expr, rhs: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply_exprs(lhs, rhs, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                lhs.clone(),
+                rhs.clone(),
+            ));
+        }
+        Ok(match self {
+            Self::Store => Type::None,
+            Self::FetchAdd => Type::Int,
+        })
+    }
+
+    /// Atomic operations can't be evaluated at compile time.
+    fn eval(&self, lhs: &ConstExpr, _rhs: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(lhs.clone()))
+    }
+
+    /// Perform the operation on the pointer and the integer on the stack.
+    fn compile_types(
+        &self,
+        _lhs: &Type,
+        _rhs: &Type,
+        _env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let ptr = SP.deref().offset(-1);
+        let val = SP.deref();
+        match self {
+            Self::Store => {
+                output
+                    .std_op(StandardOp::AtomicStore { ptr, src: val })
+                    .map_err(|_| unsupported(self.name()))?;
+                output.op(CoreOp::Pop(None, 2));
+            }
+            Self::FetchAdd => {
+                output
+                    .std_op(StandardOp::AtomicFetchAdd {
+                        ptr: ptr.clone(),
+                        val: val.clone(),
+                    })
+                    .map_err(|_| unsupported(self.name()))?;
+                // Replace the pointer with the previous value.
+                output.op(CoreOp::Move { src: val, dst: ptr });
+                output.op(CoreOp::Pop(None, 1));
+            }
+        }
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, lhs: &Expr, rhs: &Expr) -> String {
+        format!("{self}({lhs}, {rhs})")
+    }
+}
+
+impl Debug for Atomic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for Atomic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Store => write!(f, "atomic_store"),
+            Self::FetchAdd => write!(f, "atomic_fetch_add"),
+        }
+    }
+}
+
+/// Atomically replace the integer behind a pointer, if it equals an expected value.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct AtomicCompareSwap;
+
+impl TernaryOp for AtomicCompareSwap {
+    /// The first operand must be a mutable pointer to an integer,
+    /// and the expected and new values must be integers.
+    fn can_apply(&self, a: &Type, b: &Type, c: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(a.equals(&atomic_cell(Mutability::Mutable), env)?
+            && b.equals(&Type::Int, env)?
+            && c.equals(&Type::Int, env)?)
+    }
+
+    /// The result is whether the value was replaced.
+    fn return_type(&self, a: &Expr, b: &Expr, c: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply_exprs(a, b, c, env)? {
+            return Err(Error::InvalidTernaryOp(
+                self.clone_box(),
+                a.clone(),
+                b.clone(),
+                c.clone(),
+            ));
+        }
+        Ok(Type::Bool)
+    }
+
+    /// Atomic operations can't be evaluated at compile time.
+    fn eval(
+        &self,
+        a: &ConstExpr,
+        _b: &ConstExpr,
+        _c: &ConstExpr,
+        _env: &mut Env,
+    ) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(a.clone()))
+    }
+
+    /// Compare and swap with the pointer, expected value, and new value on the stack.
+    /// The expected value is directly followed by the new value, as the standard
+    /// instruction requires.
+    fn compile_types(
+        &self,
+        _a: &Type,
+        _b: &Type,
+        _c: &Type,
+        _env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        output
+            .std_op(StandardOp::AtomicCompareSwap {
+                ptr: SP.deref().offset(-2),
+                expected: SP.deref().offset(-1),
+                dst: SP.deref().offset(-2),
+            })
+            .map_err(|_| unsupported(self.name()))?;
+        output.op(CoreOp::Pop(None, 2));
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn TernaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, a: &Expr, b: &Expr, c: &Expr) -> String {
+        format!("{self}({a}, {b}, {c})")
+    }
+}
+
+impl Debug for AtomicCompareSwap {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for AtomicCompareSwap {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "atomic_compare_swap")
+    }
+}
@@ -56,6 +56,9 @@ impl BinaryOp for Comparison {
 
                 self.can_apply(a_type, b_type, env)
             }
+            // Enums with wide tags are compared by the values of their tags.
+            (a @ Type::Enum(..), Self::Equal, b @ Type::Enum(..))
+            | (a @ Type::Enum(..), Self::NotEqual, b @ Type::Enum(..)) => a.can_decay_to(b, env),
             (a, Self::Equal, b) | (a, Self::NotEqual, b) => {
                 Ok(a.can_decay_to(b, env)? && a.get_size(env)? == 1)
             }
@@ -234,6 +237,22 @@ impl BinaryOp for Comparison {
                 return self.compile_types(a_type, b_type, env, output);
             }
 
+            // The value of an enum's tag is stored in its last cell.
+            (Type::Enum(_, repr), Self::Equal | Self::NotEqual, b)
+                if repr.tag_cells > 1 && lhs.can_decay_to(b, env)? =>
+            {
+                let tag_cells = repr.tag_cells as isize;
+                let a = SP.deref().offset(-tag_cells);
+                let b = SP.deref();
+                let dst = SP.deref().offset(1 - 2 * tag_cells);
+                output.op(match self {
+                    Self::Equal => CoreOp::IsEqual { a, b, dst },
+                    _ => CoreOp::IsNotEqual { a, b, dst },
+                });
+                output.op(CoreOp::Pop(None, 2 * tag_cells as usize - 1));
+                return Ok(());
+            }
+
             (a, Self::Equal, b) if a.can_decay_to(b, env)? => {
                 output.op(CoreOp::Move { src: dst, dst: tmp });
                 output.op(core_op);
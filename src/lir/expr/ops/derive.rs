@@ -0,0 +1,543 @@
+//! # Derived Operations
+//!
+//! This module implements the operations which the compiler derives from the
+//! layout of a type, instead of the user writing them member by member:
+//! - `derived_eq(a, b)`: whether two values are equal.
+//! - `derived_cmp(a, b)`: compare two values, giving `-1`, `0`, or `1`.
+//! - `derived_hash(a)`: hash a value to an integer.
+//!
+//! Structs, tuples, and arrays are compared member by member in layout order.
+//! Tagged unions are compared by their variants first, and then by their data.
+//! Pointers are compared by their addresses, not by the values they point to.
+//!
+//! Each operation is expanded into an expression for the concrete type of its
+//! operands when it's compiled, so a polymorphic procedure gets a separate
+//! comparison for each of its monomorphs.
+//!
+//! A type declared with the `derive` attribute gets associated procedures which
+//! apply these operations, so `#[derive(eq, cmp, hash)]` on `Point` defines
+//! `Point::eq`, `Point::cmp`, and `Point::hash`.
+use super::*;
+use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use serde_derive::{Deserialize, Serialize};
+
+/// An operation which can be derived from the layout of a type.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Derive {
+    /// Whether two values are equal.
+    Eq,
+    /// Compare two values, giving `-1`, `0`, or `1`.
+    Cmp,
+    /// Hash a value to an integer.
+    Hash,
+}
+
+impl Derive {
+    /// Every operation which can be derived.
+    pub fn all() -> [Self; 3] {
+        [Self::Eq, Self::Cmp, Self::Hash]
+    }
+
+    /// The name of the associated procedure derived for a type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Cmp => "cmp",
+            Self::Hash => "hash",
+        }
+    }
+
+    /// Get the derivable operation with the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|derive| derive.name() == name)
+    }
+
+    /// The type of the result of the operation.
+    fn result_type(&self) -> Type {
+        match self {
+            Self::Eq => Type::Bool,
+            Self::Cmp | Self::Hash => Type::Int,
+        }
+    }
+
+    /// The procedure derived for a type, which applies the operation to its arguments.
+    pub fn procedure(&self, ty: Type) -> ConstExpr {
+        let arg = |name: &str| (name.to_string(), Mutability::Immutable, ty.clone());
+        let (a, b) = (Expr::var("a"), Expr::var("b"));
+        match self {
+            Self::Eq | Self::Cmp => ConstExpr::proc(
+                None,
+                vec![arg("a"), arg("b")],
+                self.result_type(),
+                a.binop(Derived(*self), b),
+            ),
+            Self::Hash => ConstExpr::proc(None, vec![arg("a")], Type::Int, a.unop(DerivedHash)),
+        }
+    }
+
+    /// Apply the operation to its operands, by binding them to variables
+    /// and expanding the operation for their type. Hashing only has one operand.
+    fn apply(&self, a: &Expr, b: Option<&Expr>, env: &Env) -> Result<Option<Expr>, Error> {
+        let ty = a.get_type(env)?;
+        let (a_var, b_var) = (temporary("a", 0), temporary("b", 0));
+        let Some(mut body) = self.expand(Expr::var(&a_var), Expr::var(&b_var), &ty, 1, env)? else {
+            return Ok(None);
+        };
+        if let Some(b) = b {
+            body = Expr::let_var(
+                &b_var,
+                Mutability::Immutable,
+                Some(ty.clone()),
+                b.clone(),
+                body,
+            );
+        }
+        Ok(Some(Expr::let_var(
+            a_var,
+            Mutability::Immutable,
+            Some(ty),
+            a.clone(),
+            body,
+        )))
+    }
+
+    /// Can the operation be derived for the given type?
+    fn can_derive(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(self
+            .expand(Expr::var("a"), Expr::var("b"), ty, 1, env)?
+            .is_some())
+    }
+
+    /// Expand the operation on two values of a type into an expression.
+    /// The values must be expressions which can be evaluated more than once,
+    /// like variables and their members. Hashing only uses the first value.
+    ///
+    /// The temporary variables of the expansion are numbered by its `depth`,
+    /// so that they don't collide with those of the expansions it contains.
+    /// If the operation can't be derived for the type, this returns `None`.
+    fn expand(
+        &self,
+        a: Expr,
+        b: Expr,
+        ty: &Type,
+        depth: usize,
+        env: &Env,
+    ) -> Result<Option<Expr>, Error> {
+        let ty = ty.simplify_until_concrete(env, false)?;
+        let member = |name: &String| ConstExpr::Symbol(name.clone());
+        Ok(match &ty {
+            Type::None => Some(self.identity()),
+            Type::Unit(_, inner) => self.expand(
+                a.as_type(*inner.clone()),
+                b.as_type(*inner.clone()),
+                inner,
+                depth,
+                env,
+            )?,
+            Type::Int | Type::Float | Type::Cell | Type::Char | Type::Bool => {
+                Some(self.scalar(a, b, &ty))
+            }
+            Type::Pointer(..) => {
+                Some(self.scalar(a.as_type(Type::Cell), b.as_type(Type::Cell), &Type::Cell))
+            }
+            Type::Enum(variants, _) => Some(match self {
+                Self::Eq => a.eq(b),
+                _ => self.scalar(
+                    variant_index(a, &ty, variants),
+                    variant_index(b, &ty, variants),
+                    &Type::Int,
+                ),
+            }),
+            Type::Struct(fields) => self.members(
+                a,
+                b,
+                fields.iter().map(|(name, ty)| (member(name), ty.clone())),
+                depth,
+                env,
+            )?,
+            Type::Repr(fields, _) => self.members(
+                a,
+                b,
+                fields.iter().map(|(name, ty)| (member(name), ty.clone())),
+                depth,
+                env,
+            )?,
+            Type::Bitfield(fields) => self.members(
+                a,
+                b,
+                fields.iter().map(|(name, _)| (member(name), Type::Int)),
+                depth,
+                env,
+            )?,
+            Type::Tuple(items) => self.members(
+                a,
+                b,
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| (ConstExpr::Int(i as i64), ty.clone())),
+                depth,
+                env,
+            )?,
+            Type::Array(elem, len) => self.array(a, b, elem, len, depth, env)?,
+            Type::EnumUnion(variants, repr) => {
+                // Compare the tags first, and then the data of the variant.
+                let tag_ty = Type::Enum(variants.keys().cloned().collect(), repr.clone());
+                let Some(tags) = self.expand(
+                    a.clone().unop(Tag),
+                    b.clone().unop(Tag),
+                    &tag_ty,
+                    depth + 1,
+                    env,
+                )?
+                else {
+                    return Ok(None);
+                };
+                let (a_var, b_var) = (temporary("a", depth), temporary("b", depth));
+                let mut data = self.identity();
+                for (variant, variant_ty) in variants.iter().rev() {
+                    let Some(expanded) = self.expand(
+                        Expr::var(&a_var),
+                        Expr::var(&b_var),
+                        variant_ty,
+                        depth + 1,
+                        env,
+                    )?
+                    else {
+                        return Ok(None);
+                    };
+                    let data_of = |value: &Expr| value.clone().unop(Data).field(member(variant));
+                    let bind = |var: &String, value: &Expr, body: Expr| {
+                        let ty = Some(variant_ty.clone());
+                        Expr::let_var(var, Mutability::Immutable, ty, data_of(value), body)
+                    };
+                    // A hash only has the first value.
+                    let mut expanded = expanded;
+                    if *self != Self::Hash {
+                        expanded = bind(&b_var, &b, expanded);
+                    }
+                    let expanded = bind(&a_var, &a, expanded);
+                    data = a
+                        .clone()
+                        .unop(Tag)
+                        .eq(ConstExpr::Of(tag_ty.clone(), variant.clone()))
+                        .if_then(expanded, data);
+                }
+                Some(self.combine(vec![tags, data], depth))
+            }
+            _ => None,
+        })
+    }
+
+    /// The result of the operation on values without any members.
+    fn identity(&self) -> Expr {
+        match self {
+            Self::Eq => Expr::ConstExpr(ConstExpr::Bool(true)),
+            Self::Cmp | Self::Hash => int(0),
+        }
+    }
+
+    /// The operation on values that fit in a single cell.
+    fn scalar(&self, a: Expr, b: Expr, ty: &Type) -> Expr {
+        // Characters and booleans are ordered and hashed by their integer values.
+        let (a, b) = match ty {
+            Type::Char | Type::Bool => (a.as_type(Type::Int), b.as_type(Type::Int)),
+            _ => (a, b),
+        };
+        match self {
+            Self::Eq => a.eq(b),
+            Self::Cmp => a
+                .clone()
+                .lt(b.clone())
+                .if_then(int(-1), a.gt(b).if_then(int(1), int(0))),
+            Self::Hash if *ty == Type::Int => a,
+            Self::Hash => a.as_type(Type::Int),
+        }
+    }
+
+    /// The operation on values with the given members, in layout order.
+    fn members(
+        &self,
+        a: Expr,
+        b: Expr,
+        members: impl Iterator<Item = (ConstExpr, Type)>,
+        depth: usize,
+        env: &Env,
+    ) -> Result<Option<Expr>, Error> {
+        let mut parts = vec![];
+        for (member, ty) in members {
+            let a = a.clone().field(member.clone());
+            let b = b.clone().field(member);
+            match self.expand(a, b, &ty, depth + 1, env)? {
+                Some(part) => parts.push(part),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(self.combine(parts, depth)))
+    }
+
+    /// The operation on arrays, which loops over their elements.
+    fn array(
+        &self,
+        a: Expr,
+        b: Expr,
+        elem: &Type,
+        len: &ConstExpr,
+        depth: usize,
+        env: &Env,
+    ) -> Result<Option<Expr>, Error> {
+        let (i, result) = (temporary("i", depth), temporary("result", depth));
+        let Some(elem) = self.expand(
+            a.idx(Expr::var(&i)),
+            b.idx(Expr::var(&i)),
+            elem,
+            depth + 1,
+            env,
+        )?
+        else {
+            return Ok(None);
+        };
+        let in_bounds = Expr::var(&i).lt(Expr::ConstExpr(len.clone()));
+        let (condition, step) = match self {
+            Self::Eq => (Expr::var(&result).and(in_bounds), elem),
+            Self::Cmp => (Expr::var(&result).eq(int(0)).and(in_bounds), elem),
+            Self::Hash => (in_bounds, hash_step(Expr::var(&result), elem)),
+        };
+        let body = Expr::Many(vec![
+            condition.while_loop(Expr::Many(vec![
+                assign(&result, step),
+                assign(&i, Expr::var(&i).add(int(1))),
+            ])),
+            Expr::var(&result),
+        ]);
+        Ok(Some(Expr::let_var(
+            &i,
+            Mutability::Mutable,
+            Some(Type::Int),
+            int(0),
+            Expr::let_var(
+                &result,
+                Mutability::Mutable,
+                Some(self.result_type()),
+                self.identity(),
+                body,
+            ),
+        )))
+    }
+
+    /// Combine the results of the operation on each member of a value, in order.
+    fn combine(&self, parts: Vec<Expr>, depth: usize) -> Expr {
+        match self {
+            // The values are equal if all of their members are equal.
+            Self::Eq => parts
+                .into_iter()
+                .rev()
+                .reduce(|rest, part| part.and(rest))
+                .unwrap_or_else(|| self.identity()),
+            // The values are ordered by their first member which differs.
+            Self::Cmp => {
+                let order = temporary("order", depth);
+                parts.into_iter().rev().fold(self.identity(), |rest, part| {
+                    Expr::let_var(
+                        &order,
+                        Mutability::Immutable,
+                        Some(Type::Int),
+                        part,
+                        Expr::var(&order)
+                            .neq(int(0))
+                            .if_then(Expr::var(&order), rest),
+                    )
+                })
+            }
+            // The hashes of the members are mixed together.
+            Self::Hash => parts.into_iter().fold(self.identity(), hash_step),
+        }
+    }
+}
+
+/// The name of a temporary variable used by an expansion.
+fn temporary(name: &str, depth: usize) -> String {
+    format!("__derived_{name}{depth}")
+}
+
+/// An integer literal.
+fn int(n: i64) -> Expr {
+    Expr::ConstExpr(ConstExpr::Int(n))
+}
+
+/// Assign a value to a mutable variable.
+fn assign(var: &str, value: Expr) -> Expr {
+    Expr::var(var).refer(Mutability::Mutable).deref_mut(value)
+}
+
+/// Mix the hash of another member into a hash.
+fn hash_step(hash: Expr, part: Expr) -> Expr {
+    hash.mul(int(31)).add(part)
+}
+
+/// The index of an enum's variant in its list of variants.
+fn variant_index(value: Expr, ty: &Type, variants: &[String]) -> Expr {
+    variants
+        .iter()
+        .enumerate()
+        .rev()
+        .fold(int(0), |rest, (i, variant)| {
+            value
+                .clone()
+                .eq(ConstExpr::Of(ty.clone(), variant.clone()))
+                .if_then(int(i as i64), rest)
+        })
+}
+
+impl Debug for Derive {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for Derive {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Derived equality or ordering of two values of the same type.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Derived(pub Derive);
+
+impl BinaryOp for Derived {
+    /// The operands must have the same type, and the operation must be derivable for it.
+    fn can_apply(&self, lhs: &Type, rhs: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(self.0 != Derive::Hash && lhs.equals(rhs, env)? && self.0.can_derive(lhs, env)?)
+    }
+
+    /// Equality gives a `Bool`, and ordering gives an `Int`.
+    fn return_type(&self, lhs: &Expr, rhs: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply_exprs(lhs, rhs, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                lhs.clone(),
+                rhs.clone(),
+            ));
+        }
+        Ok(self.0.result_type())
+    }
+
+    /// Derived operations are only expanded when they're compiled.
+    fn eval(&self, lhs: &ConstExpr, _rhs: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(lhs.clone()))
+    }
+
+    /// Expand the operation for the type of the operands, and compile the expansion.
+    fn compile(
+        &self,
+        lhs: &Expr,
+        rhs: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let expanded = self
+            .0
+            .apply(lhs, Some(rhs), env)?
+            .ok_or_else(|| Error::InvalidBinaryOp(self.clone_box(), lhs.clone(), rhs.clone()))?;
+        expanded.compile_expr(env, output)
+    }
+
+    fn compile_types(
+        &self,
+        _lhs: &Type,
+        _rhs: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        unimplemented!("Don't use this function, use compile instead")
+    }
+
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, lhs: &Expr, rhs: &Expr) -> String {
+        format!("{self}({lhs}, {rhs})")
+    }
+}
+
+impl Debug for Derived {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for Derived {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "derived_{}", self.0)
+    }
+}
+
+/// A derived hash of a value.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct DerivedHash;
+
+impl UnaryOp for DerivedHash {
+    /// The operation must be derivable for the type of the operand.
+    fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        Derive::Hash.can_derive(ty, env)
+    }
+
+    /// The hash is an `Int`.
+    fn return_type(&self, expr: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply(&expr.get_type(env)?, env)? {
+            return Err(Error::InvalidUnaryOp(self.clone_box(), expr.clone()));
+        }
+        Ok(Type::Int)
+    }
+
+    /// Derived operations are only expanded when they're compiled.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Expand the hash for the type of the operand, and compile the expansion.
+    fn compile(
+        &self,
+        expr: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let expanded = Derive::Hash
+            .apply(expr, None, env)?
+            .ok_or_else(|| Error::InvalidUnaryOp(self.clone_box(), expr.clone()))?;
+        expanded.compile_expr(env, output)
+    }
+
+    fn compile_types(
+        &self,
+        _ty: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        unimplemented!("Don't use this function, use compile instead")
+    }
+
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for DerivedHash {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for DerivedHash {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "derived_hash")
+    }
+}
@@ -8,9 +8,95 @@ use crate::{
 use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 
+/// The stream that a `put` or `debug` operation writes to.
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Stream {
+    /// Standard output on the given channel.
+    Stdout(usize),
+    /// Standard error on the given channel.
+    Stderr(usize),
+}
+
+impl Stream {
+    /// The output for writing a character to this stream.
+    pub fn char(&self) -> Output {
+        match self {
+            Self::Stdout(channel) => Output::new(OutputMode::StdoutChar, *channel),
+            Self::Stderr(channel) => Output::new(OutputMode::StderrChar, *channel),
+        }
+    }
+
+    /// The output for writing an integer to this stream.
+    pub fn int(&self) -> Output {
+        match self {
+            Self::Stdout(channel) => Output::new(OutputMode::StdoutInt, *channel),
+            Self::Stderr(channel) => Output::new(OutputMode::StderrInt, *channel),
+        }
+    }
+
+    /// The output for writing a float to this stream.
+    pub fn float(&self) -> Output {
+        match self {
+            Self::Stdout(channel) => Output::new(OutputMode::StdoutFloat, *channel),
+            Self::Stderr(channel) => Output::new(OutputMode::StderrFloat, *channel),
+        }
+    }
+}
+
+/// Get the I/O channel number from a constant expression.
+fn get_channel(expr: &Expr, env: &Env) -> Result<usize, Error> {
+    match expr {
+        Expr::Annotated(expr, metadata) => {
+            get_channel(expr, env).map_err(|err| err.annotate(metadata.clone()))
+        }
+        Expr::ConstExpr(cexpr) => match cexpr.clone().as_int(env)? {
+            n if n >= 0 => Ok(n as usize),
+            _ => Err(Error::InvalidChannel(expr.clone())),
+        },
+        _ => Err(Error::InvalidChannel(expr.clone())),
+    }
+}
+
+/// Read a value from standard input.
 #[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct Get;
 
+impl Get {
+    /// Read a value from an input channel into the pointer on the top of the stack.
+    /// The pointer is popped off the stack afterwards.
+    pub fn get_from(
+        channel: usize,
+        ty: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        if ty.equals(
+            &Type::Pointer(Mutability::Mutable, Box::new(Type::Char)),
+            env,
+        )? {
+            output.op(CoreOp::Get(SP.deref().deref(), Input::new(InputMode::StdinChar, channel)));
+        } else if ty.equals(
+            &Type::Pointer(Mutability::Mutable, Box::new(Type::Int)),
+            env,
+        )? {
+            output.op(CoreOp::Get(SP.deref().deref(), Input::new(InputMode::StdinInt, channel)));
+        } else if ty.equals(
+            &Type::Pointer(Mutability::Mutable, Box::new(Type::Float)),
+            env,
+        )? {
+            output.op(CoreOp::Get(SP.deref().deref(), Input::new(InputMode::StdinFloat, channel)));
+        } else {
+            return Err(Error::UnsupportedOperation(Expr::UnaryOp(
+                Self.name(),
+                Box::new(Expr::ConstExpr(ConstExpr::None)),
+            )));
+        }
+
+        output.op(CoreOp::Pop(None, 1));
+        Ok(())
+    }
+}
+
 impl UnaryOp for Get {
     /// Can this unary operation be applied to the given type?
     fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
@@ -43,30 +129,12 @@ impl UnaryOp for Get {
         env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        if ty.equals(
-            &Type::Pointer(Mutability::Mutable, Box::new(Type::Char)),
-            env,
-        )? {
-            output.op(CoreOp::Get(SP.deref().deref(), Input::stdin_char()));
-        } else if ty.equals(
-            &Type::Pointer(Mutability::Mutable, Box::new(Type::Int)),
-            env,
-        )? {
-            output.op(CoreOp::Get(SP.deref().deref(), Input::stdin_int()));
-        } else if ty.equals(
-            &Type::Pointer(Mutability::Mutable, Box::new(Type::Float)),
-            env,
-        )? {
-            output.op(CoreOp::Get(SP.deref().deref(), Input::stdin_float()));
-        } else {
-            return Err(Error::UnsupportedOperation(Expr::UnaryOp(
-                self.name(),
-                Box::new(Expr::ConstExpr(ConstExpr::None)),
-            )));
-        }
+        Self::get_from(0, ty, env, output)
+    }
 
-        output.op(CoreOp::Pop(None, 1));
-        Ok(())
+    /// Reading input is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
     }
 
     /// Clone this operation into a box.
@@ -90,30 +158,87 @@ impl Display for Get {
 /// Print a value to a given output.
 #[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub enum Put {
+    /// Print the debug representation of a value to standard output.
     Debug,
+    /// Print the display representation of a value to standard output.
     Display,
+    /// Print the debug representation of a value to standard error.
+    DebugErr,
+    /// Print the display representation of a value to standard error.
+    DisplayErr,
 }
 
 impl Put {
+    /// Get the stream this operation prints to on a given channel.
+    pub fn stream(&self, channel: usize) -> Stream {
+        match self {
+            Self::Debug | Self::Display => Stream::Stdout(channel),
+            Self::DebugErr | Self::DisplayErr => Stream::Stderr(channel),
+        }
+    }
+
+    /// Does this operation print the debug representation of a value?
+    pub fn is_debug(&self) -> bool {
+        matches!(self, Self::Debug | Self::DebugErr)
+    }
+
+    /// Print the value at the given address with this operation.
+    fn print(
+        &self,
+        stream: Stream,
+        addr: Location,
+        t: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        if self.is_debug() {
+            Self::debug_to(stream, addr, t, env, output)
+        } else {
+            Self::display_to(stream, addr, t, env, output)
+        }
+    }
+
+    /// Print a value at the given address in its debug representation.
     pub fn debug(
         addr: Location,
         t: &Type,
         env: &mut Env,
         output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Self::debug_to(Stream::Stdout(0), addr, t, env, output)
+    }
+
+    /// Print a value at the given address in its display representation.
+    pub fn display(
+        addr: Location,
+        t: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Self::display_to(Stream::Stdout(0), addr, t, env, output)
+    }
+
+    /// Print a value at the given address to a stream in its debug representation.
+    pub fn debug_to(
+        stream: Stream,
+        addr: Location,
+        t: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
         let t = &t.simplify_until_concrete(env, false)?;
         match t {
             Type::Type(t) => {
                 for c in format!("{}", t).chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
 
                 // Print associated constants
                 for (name, constant) in env.get_all_associated_consts(t) {
                     for c in format!(" const {name} = {constant};").chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                 }
             }
@@ -125,79 +250,81 @@ impl Put {
                 };
                 for ch in prefix.chars() {
                     output.op(CoreOp::Set(A, ch as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
-                output.op(CoreOp::Put(addr, Output::stdout_int()));
+                output.op(CoreOp::Put(addr, stream.int()));
                 output.op(CoreOp::Set(A, b')' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
             }
             Type::Bool => {
                 output.op(CoreOp::If(addr));
                 for c in "true".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
                 output.op(CoreOp::Else);
                 for c in "false".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
                 output.op(CoreOp::End);
             }
             Type::None => {
                 for c in "None".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
             Type::Any => {
                 for c in "Any".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
             Type::Cell => {
-                output.op(CoreOp::Put(addr, Output::stdout_int()));
+                output.op(CoreOp::Put(addr, stream.int()));
                 for ch in " (Cell)".to_string().chars() {
                     output.op(CoreOp::Set(A, ch as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
             Type::Int => {
-                output.op(CoreOp::Put(addr, Output::stdout_int()));
+                output.op(CoreOp::Put(addr, stream.int()));
             }
             Type::Float => {
-                output.op(CoreOp::Put(addr, Output::stdout_float()));
+                output.op(CoreOp::Put(addr, stream.float()));
             }
             Type::Char => {
                 output.op(CoreOp::Set(A, b'\'' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
-                output.op(CoreOp::Put(addr, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
+                output.op(CoreOp::Put(addr, stream.char()));
                 output.op(CoreOp::Set(A, b'\'' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
             }
             Type::Never => {
                 for c in "Never".to_string().chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
 
-            Type::Enum(variants) => {
+            Type::Enum(variants, repr) => {
+                // The value of the tag is stored in its last cell.
+                let tag_address = addr.offset(repr.tag_cells as isize - 1);
                 for variant in variants.iter() {
-                    let variant_id = Type::variant_index(variants, variant).unwrap();
+                    let variant_id = repr.tag_of(variants, variant).unwrap();
 
                     output.op(CoreOp::Move {
-                        src: addr.clone(),
+                        src: tag_address.clone(),
                         dst: A,
                     });
-                    output.op(CoreOp::Set(B, variant_id as i64));
+                    output.op(CoreOp::Set(B, variant_id));
                     // Check if the value is the same as the variant ID
                     output.op(CoreOp::IsEqual { a: A, b: B, dst: C });
                     output.op(CoreOp::If(C));
                     for c in format!("{t} of {variant}").chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     output.op(CoreOp::End);
                 }
@@ -209,140 +336,164 @@ impl Put {
                 if ty.equals(&Type::Int, env)? {
                     output.op(Many(vec![
                         Set(C, b'[' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         GetAddress { addr, dst: A },
                         Set(B, array_len),
                         While(B),
-                        Put(A.deref(), Output::stdout_int()),
+                        Put(A.deref(), stream.int()),
                         Next(A, None),
                         Dec(B),
                         If(B),
                         Set(C, b',' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         Set(C, b' ' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         End,
                         End,
                         Set(C, b']' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                     ]))
                 } else if ty.equals(&Type::Float, env)? {
                     output.op(Many(vec![
                         Set(C, b'[' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         GetAddress { addr, dst: A },
                         Set(B, array_len),
                         While(B),
-                        Put(A.deref(), Output::stdout_float()),
+                        Put(A.deref(), stream.float()),
                         Next(A, None),
                         Dec(B),
                         If(B),
                         Set(C, b',' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         Set(C, b' ' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         End,
                         End,
                         Set(C, b']' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                     ]))
                 } else {
                     let ty_size = ty.get_size(env)? as isize;
 
                     output.op(Set(A, b'[' as i64));
-                    output.op(Put(A, Output::stdout_char()));
+                    output.op(Put(A, stream.char()));
                     for i in 0..array_len as isize {
-                        Self::debug(addr.offset(i * ty_size), ty, env, output)?;
+                        Self::debug_to(stream, addr.offset(i * ty_size), ty, env, output)?;
                         if i < array_len as isize - 1 {
                             output.op(Set(A, b',' as i64));
-                            output.op(Put(A, Output::stdout_char()));
+                            output.op(Put(A, stream.char()));
                             output.op(Set(A, b' ' as i64));
-                            output.op(Put(A, Output::stdout_char()));
+                            output.op(Put(A, stream.char()));
                         }
                     }
                     output.op(Set(A, b']' as i64));
-                    output.op(Put(A, Output::stdout_char()));
+                    output.op(Put(A, stream.char()));
                 }
             }
 
             Type::Struct(fields) => {
                 for c in "{".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
                 let mut offset = 0;
                 for (i, (field_name, field_type)) in fields.iter().enumerate() {
                     for c in field_name.chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     output.op(CoreOp::Set(A, b'=' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
-                    Self::debug(addr.offset(offset), field_type, env, output)?;
+                    output.op(CoreOp::Put(A, stream.char()));
+                    Self::debug_to(stream, addr.offset(offset), field_type, env, output)?;
                     if i < fields.len() - 1 {
                         output.op(CoreOp::Set(A, b',' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         output.op(CoreOp::Set(A, b' ' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         offset += field_type.get_size(env)? as isize;
                     }
                 }
                 output.op(CoreOp::Set(A, b'}' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
+            }
+
+            Type::Repr(fields, _) => {
+                output.op(CoreOp::Set(A, b'{' as i64));
+                output.op(CoreOp::Put(A, stream.char()));
+                for (i, (field_name, field_type)) in fields.iter().enumerate() {
+                    for c in field_name.chars() {
+                        output.op(CoreOp::Set(A, c as u8 as i64));
+                        output.op(CoreOp::Put(A, stream.char()));
+                    }
+                    output.op(CoreOp::Set(A, b'=' as i64));
+                    output.op(CoreOp::Put(A, stream.char()));
+                    // The fields may be padded, so use the offsets from the layout.
+                    let offset = t.get_offset_of(&ConstExpr::Symbol(field_name.clone()), env)?;
+                    Self::debug_to(stream, addr.offset(offset as isize), field_type, env, output)?;
+                    if i < fields.len() - 1 {
+                        output.op(CoreOp::Set(A, b',' as i64));
+                        output.op(CoreOp::Put(A, stream.char()));
+                        output.op(CoreOp::Set(A, b' ' as i64));
+                        output.op(CoreOp::Put(A, stream.char()));
+                    }
+                }
+                output.op(CoreOp::Set(A, b'}' as i64));
+                output.op(CoreOp::Put(A, stream.char()));
             }
 
             Type::Tuple(types) => {
                 output.op(CoreOp::Set(A, b'(' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
                 let mut offset = 0;
                 for (i, ty) in types.iter().enumerate() {
-                    Self::debug(addr.offset(offset), ty, env, output)?;
+                    Self::debug_to(stream, addr.offset(offset), ty, env, output)?;
                     if i < types.len() - 1 {
                         output.op(CoreOp::Set(A, b',' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         output.op(CoreOp::Set(A, b' ' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         offset += ty.get_size(env)? as isize;
                     }
                 }
                 output.op(CoreOp::Set(A, b')' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
             }
 
             Type::Proc(args, ret) => {
                 if args.len() != 1 {
                     for c in "(".chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                 }
                 for (i, ty) in args.iter().enumerate() {
                     for ch in ty.to_string().chars() {
                         output.op(CoreOp::Set(A, ch as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     if i < args.len() - 1 {
                         output.op(CoreOp::Set(A, b',' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         output.op(CoreOp::Set(A, b' ' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                 }
                 if args.len() != 1 {
                     output.op(CoreOp::Set(A, b')' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
                 for ch in format!(" -> {ret}").chars() {
                     output.op(CoreOp::Set(A, ch as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
 
             Type::Unit(_name, ty) => {
-                Self::debug(addr, ty, env, output)?;
+                Self::debug_to(stream, addr, ty, env, output)?;
                 // for ch in format!(" ({})", name).chars() {
                 //     output.op(CoreOp::Set(A, ch as u8 as i64));
-                //     output.op(CoreOp::Put(A, Output::stdout_char()));
+                //     output.op(CoreOp::Put(A, stream.char()));
                 // }
             }
 
@@ -350,11 +501,11 @@ impl Put {
                 t.type_check(env)?;
                 for ch in name.chars() {
                     output.op(CoreOp::Set(A, ch as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
 
-            Type::EnumUnion(fields) => {
+            Type::EnumUnion(fields, repr) => {
                 // Calculate the address of the tag and the data
                 let tag_address = addr.offset(t.get_size(env)? as isize - 1);
                 let data_address = addr;
@@ -363,9 +514,9 @@ impl Put {
                 let variants: Vec<String> = fields.clone().into_keys().collect();
                 // Iterate through all of the possible tags and check if the value is one of them
                 for (name, variant_t) in fields.iter() {
-                    if let Some(tag_value) = Type::variant_index(&variants, name) {
+                    if let Some(tag_value) = repr.tag_of(&variants, name) {
                         // Check if the value's tag is equal to tag for the name
-                        output.op(CoreOp::Set(A, tag_value as i64));
+                        output.op(CoreOp::Set(A, tag_value));
                         output.op(CoreOp::IsEqual {
                             a: tag_address.clone(),
                             b: A,
@@ -374,9 +525,9 @@ impl Put {
                         output.op(CoreOp::If(B));
                         for c in format!("{t} of {name} ").chars() {
                             output.op(CoreOp::Set(A, c as u8 as i64));
-                            output.op(CoreOp::Put(A, Output::stdout_char()));
+                            output.op(CoreOp::Put(A, stream.char()));
                         }
-                        Self::debug(data_address.clone(), variant_t, env, output)?;
+                        Self::debug_to(stream, data_address.clone(), variant_t, env, output)?;
                         output.op(CoreOp::End);
                     } else {
                         return Err(Error::VariantNotFound(t.clone(), name.clone()));
@@ -387,37 +538,37 @@ impl Put {
             Type::Union(fields) => {
                 for c in "union {".chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
                 for (i, (field_name, field_type)) in fields.iter().enumerate() {
                     for c in field_name.chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     output.op(CoreOp::Set(A, b':' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                     output.op(CoreOp::Set(A, b' ' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                     for ch in field_type.to_string().chars() {
                         output.op(CoreOp::Set(A, ch as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     output.op(CoreOp::Set(A, b' ' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                     output.op(CoreOp::Set(A, b'=' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                     output.op(CoreOp::Set(A, b' ' as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
-                    Self::debug(addr.clone(), field_type, env, output)?;
+                    output.op(CoreOp::Put(A, stream.char()));
+                    Self::debug_to(stream, addr.clone(), field_type, env, output)?;
                     if i < fields.len() - 1 {
                         output.op(CoreOp::Set(A, b',' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                         output.op(CoreOp::Set(A, b' ' as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                 }
                 output.op(CoreOp::Set(A, b'}' as i64));
-                output.op(CoreOp::Put(A, Output::stdout_char()));
+                output.op(CoreOp::Put(A, stream.char()));
             }
 
             _ => return Err(Error::InvalidUnaryOpTypes(Box::new(Self::Debug), t.clone())),
@@ -425,7 +576,9 @@ impl Put {
         Ok(())
     }
 
-    pub fn display(
+    /// Print a value at the given address to a stream in its display representation.
+    pub fn display_to(
+        stream: Stream,
         addr: Location,
         t: &Type,
         env: &mut Env,
@@ -434,15 +587,15 @@ impl Put {
         let t = &t.simplify_until_concrete(env, false)?;
         match t {
             Type::Cell => {
-                output.op(CoreOp::Put(addr, Output::stdout_int()));
+                output.op(CoreOp::Put(addr, stream.int()));
             }
             Type::Char => {
-                output.op(CoreOp::Put(addr, Output::stdout_char()));
+                output.op(CoreOp::Put(addr, stream.char()));
             }
             Type::Type(t) => {
                 for ch in t.to_string().chars() {
                     output.op(CoreOp::Set(A, ch as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
 
@@ -455,36 +608,38 @@ impl Put {
                         dst: A,
                     });
                     output.op(CoreOp::While(A.deref()));
-                    output.op(CoreOp::Put(A.deref(), Output::stdout_char()));
+                    output.op(CoreOp::Put(A.deref(), stream.char()));
                     output.op(CoreOp::Next(A, None));
                     output.op(CoreOp::End);
                 } else {
-                    Self::debug(addr, t, env, output)?;
+                    Self::debug_to(stream, addr, t, env, output)?;
                 }
             }
 
-            Type::Enum(variants) => {
+            Type::Enum(variants, repr) => {
+                // The value of the tag is stored in its last cell.
+                let tag_address = addr.offset(repr.tag_cells as isize - 1);
                 for variant in variants.iter() {
-                    let variant_id = Type::variant_index(variants, variant).unwrap();
+                    let variant_id = repr.tag_of(variants, variant).unwrap();
 
                     output.op(CoreOp::Move {
-                        src: addr.clone(),
+                        src: tag_address.clone(),
                         dst: A,
                     });
-                    output.op(CoreOp::Set(B, variant_id as i64));
+                    output.op(CoreOp::Set(B, variant_id));
                     // Check if the value is the same as the variant ID
                     output.op(CoreOp::IsEqual { a: A, b: B, dst: C });
                     output.op(CoreOp::If(C));
                     for c in variant.chars() {
                         output.op(CoreOp::Set(A, c as u8 as i64));
-                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                        output.op(CoreOp::Put(A, stream.char()));
                     }
                     output.op(CoreOp::End);
                 }
 
                 for c in format!(" of {t}").chars() {
                     output.op(CoreOp::Set(A, c as u8 as i64));
-                    output.op(CoreOp::Put(A, Output::stdout_char()));
+                    output.op(CoreOp::Put(A, stream.char()));
                 }
             }
 
@@ -499,7 +654,7 @@ impl Put {
                         Set(B, array_len),
                         While(B),
                         If(A.deref()),
-                        Put(A.deref(), Output::stdout_char()),
+                        Put(A.deref(), stream.char()),
                         Next(A, None),
                         Dec(B),
                         Else,
@@ -510,62 +665,62 @@ impl Put {
                 } else if ty.equals(&Type::Int, env)? {
                     output.op(Many(vec![
                         Set(C, b'[' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         GetAddress { addr, dst: A },
                         Set(B, array_len),
                         While(B),
-                        Put(A.deref(), Output::stdout_int()),
+                        Put(A.deref(), stream.int()),
                         Next(A, None),
                         Dec(B),
                         If(B),
                         Set(C, b',' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         Set(C, b' ' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         End,
                         End,
                         Set(C, b']' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                     ]))
                 } else if ty.equals(&Type::Float, env)? {
                     output.op(Many(vec![
                         Set(C, b'[' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         GetAddress { addr, dst: A },
                         Set(B, array_len),
                         While(B),
-                        Put(A.deref(), Output::stdout_float()),
+                        Put(A.deref(), stream.float()),
                         Next(A, None),
                         Dec(B),
                         If(B),
                         Set(C, b',' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         Set(C, b' ' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                         End,
                         End,
                         Set(C, b']' as i64),
-                        Put(C, Output::stdout_char()),
+                        Put(C, stream.char()),
                     ]))
                 } else {
                     output.op(Set(A, b'[' as i64));
-                    output.op(Put(A, Output::stdout_char()));
+                    output.op(Put(A, stream.char()));
                     for i in 0..array_len as isize {
-                        Self::debug(addr.offset(i * ty_size), ty, env, output)?;
+                        Self::debug_to(stream, addr.offset(i * ty_size), ty, env, output)?;
                         if i < array_len as isize - 1 {
                             output.op(CoreOp::Set(A, b',' as i64));
-                            output.op(CoreOp::Put(A, Output::stdout_char()));
+                            output.op(CoreOp::Put(A, stream.char()));
                             output.op(CoreOp::Set(A, b' ' as i64));
-                            output.op(CoreOp::Put(A, Output::stdout_char()));
+                            output.op(CoreOp::Put(A, stream.char()));
                         }
                     }
                     output.op(Set(A, b']' as i64));
-                    output.op(Put(A, Output::stdout_char()));
+                    output.op(Put(A, stream.char()));
                 }
             }
 
             _ => {
-                Self::debug(addr, t, env, output)?;
+                Self::debug_to(stream, addr, t, env, output)?;
             }
         }
         Ok(())
@@ -602,15 +757,17 @@ impl UnaryOp for Put {
 
         // Calculate the address of the expression on the stack.
         let addr = SP.deref().offset(-size + 1);
-        match self {
-            Self::Debug => Self::debug(addr, ty, env, output)?,
-            Self::Display => Self::display(addr, ty, env, output)?,
-        }
+        self.print(self.stream(0), addr, ty, env, output)?;
 
         output.op(CoreOp::Pop(None, size as usize));
         Ok(())
     }
 
+    /// Writing output is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
     /// Clone this operation into a box.
     fn clone_box(&self) -> Box<dyn UnaryOp> {
         Box::new(*self)
@@ -625,6 +782,8 @@ impl Debug for Put {
             match self {
                 Self::Debug => "debug",
                 Self::Display => "put",
+                Self::DebugErr => "edebug",
+                Self::DisplayErr => "eput",
             }
         )
     }
@@ -638,7 +797,873 @@ impl Display for Put {
             match self {
                 Self::Debug => "debug",
                 Self::Display => "put",
+                Self::DebugErr => "edebug",
+                Self::DisplayErr => "eput",
             }
         )
     }
 }
+
+/// Print a value to a numbered output channel.
+///
+/// The left hand side is the constant channel number,
+/// and the right hand side is the value to print.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct PutTo(pub Put);
+
+impl BinaryOp for PutTo {
+    /// Typechecks the channel and the value to print.
+    fn type_check(&self, channel: &Expr, val: &Expr, env: &Env) -> Result<(), Error> {
+        get_channel(channel, env)?;
+        val.type_check(env)
+    }
+
+    /// Printing a value always returns `None`.
+    fn return_type(&self, _channel: &Expr, _val: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The channel must be an integer, and the value can be anything.
+    fn can_apply(&self, channel: &Type, _val: &Type, env: &Env) -> Result<bool, Error> {
+        channel.equals(&Type::Int, env)
+    }
+
+    /// Printing is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, channel: &ConstExpr, _val: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(channel.clone()))
+    }
+
+    /// Compile the operation. Only the value is pushed onto the stack:
+    /// the channel is known at compile time.
+    fn compile(
+        &self,
+        channel: &Expr,
+        val: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let channel = get_channel(channel, env)?;
+        let ty = val.get_type(env)?;
+        // Get the size of the value.
+        let size = ty.get_size(env)?;
+        // Push the value onto the stack.
+        val.clone().compile_expr(env, output)?;
+        // Print the value on the stack to the channel.
+        let addr = SP.deref().offset(1 - size as isize);
+        self.0.print(self.0.stream(channel), addr, &ty, env, output)?;
+        // Pop the value off the stack.
+        output.op(CoreOp::Pop(None, size));
+        Ok(())
+    }
+
+    /// The channel is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _channel: &Type,
+        _val: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Writing output is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, channel: &Expr, val: &Expr) -> String {
+        format!("{} #{channel} {val}", self.0)
+    }
+}
+
+impl Debug for PutTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+impl Display for PutTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+/// Read a value from a numbered input channel.
+///
+/// The left hand side is the constant channel number,
+/// and the right hand side is a mutable pointer to store the value in.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct GetFrom;
+
+impl BinaryOp for GetFrom {
+    /// Typechecks the channel and the pointer to read into.
+    fn type_check(&self, channel: &Expr, ptr: &Expr, env: &Env) -> Result<(), Error> {
+        get_channel(channel, env)?;
+        if !Get.can_apply(&ptr.get_type(env)?, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                channel.clone(),
+                ptr.clone(),
+            ));
+        }
+        ptr.type_check(env)
+    }
+
+    /// Reading a value always returns `None`.
+    fn return_type(&self, _channel: &Expr, _ptr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The channel must be an integer, and the destination must be
+    /// a mutable pointer to a `Char`, `Int`, or `Float`.
+    fn can_apply(&self, channel: &Type, ptr: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(channel.equals(&Type::Int, env)? && Get.can_apply(ptr, env)?)
+    }
+
+    /// Reading is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, channel: &ConstExpr, _ptr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(channel.clone()))
+    }
+
+    /// Compile the operation. Only the pointer is pushed onto the stack:
+    /// the channel is known at compile time.
+    fn compile(
+        &self,
+        channel: &Expr,
+        ptr: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let channel = get_channel(channel, env)?;
+        // Push the pointer onto the stack.
+        ptr.clone().compile_expr(env, output)?;
+        // Read the value into the pointer.
+        Get::get_from(channel, &ptr.get_type(env)?, env, output)
+    }
+
+    /// The channel is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _channel: &Type,
+        _ptr: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Reading input is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, channel: &Expr, ptr: &Expr) -> String {
+        format!("{self} #{channel} {ptr}")
+    }
+}
+
+impl Debug for GetFrom {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "get_from")
+    }
+}
+
+impl Display for GetFrom {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "get_from")
+    }
+}
+
+/// An integer a program can read from a device without waiting for the user.
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Sense {
+    /// The next key pressed, or [`NO_KEY`] if there isn't one.
+    Key,
+    /// The width of the terminal in columns.
+    TerminalWidth,
+    /// The height of the terminal in rows.
+    TerminalHeight,
+}
+
+impl Sense {
+    /// The input for this value on a channel.
+    pub fn input(&self, channel: usize) -> Input {
+        let mode = match self {
+            Self::Key => InputMode::KeyPoll,
+            Self::TerminalWidth => InputMode::TerminalWidth,
+            Self::TerminalHeight => InputMode::TerminalHeight,
+        };
+        Input::new(mode, channel)
+    }
+}
+
+impl Display for Sense {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Key => write!(f, "poll_key"),
+            Self::TerminalWidth => write!(f, "terminal_width"),
+            Self::TerminalHeight => write!(f, "terminal_height"),
+        }
+    }
+}
+
+/// Read an integer from a numbered input channel, and return it.
+///
+/// The operand is the constant channel number.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct ReadFrom(pub Sense);
+
+impl UnaryOp for ReadFrom {
+    /// The channel must be a constant integer.
+    fn type_check(&self, channel: &Expr, env: &Env) -> Result<(), Error> {
+        get_channel(channel, env)?;
+        Ok(())
+    }
+
+    /// Reading always returns an integer.
+    fn return_type(&self, _channel: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::Int)
+    }
+
+    /// The channel must be an integer.
+    fn can_apply(&self, channel: &Type, env: &Env) -> Result<bool, Error> {
+        channel.equals(&Type::Int, env)
+    }
+
+    /// Reading is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, channel: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(channel.clone()))
+    }
+
+    /// Compile the operation. Nothing is pushed for the channel:
+    /// it's known at compile time.
+    fn compile(
+        &self,
+        channel: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let channel = get_channel(channel, env)?;
+        // Read the value, and push it onto the stack.
+        output.op(CoreOp::Get(A, self.0.input(channel)));
+        output.op(CoreOp::Push(A, 1));
+        Ok(())
+    }
+
+    /// The channel is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _channel: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::UnaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Reading input is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, channel: &Expr) -> String {
+        format!("{} #{channel}", self.0)
+    }
+}
+
+impl Debug for ReadFrom {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_from", self.0)
+    }
+}
+
+impl Display for ReadFrom {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_from", self.0)
+    }
+}
+
+/// A drawing operation on a pixel framebuffer.
+/// See [`Framebuffer`] for the convention devices follow.
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Draw {
+    /// Move the cursor to a column.
+    Column,
+    /// Move the cursor to a row.
+    Row,
+    /// Set the pixel under the cursor to a 0xRRGGBB color, and move the cursor right.
+    Pixel,
+    /// Fill the framebuffer with a 0xRRGGBB color.
+    Clear,
+    /// Present the frame drawn so far. The value is ignored.
+    Present,
+}
+
+impl Draw {
+    /// The output for this operation on a channel.
+    pub fn output(&self, channel: usize) -> Output {
+        let mode = match self {
+            Self::Column => OutputMode::SetCursorColumn,
+            Self::Row => OutputMode::SetCursorRow,
+            Self::Pixel => OutputMode::Pixel,
+            Self::Clear => OutputMode::ClearDisplay,
+            Self::Present => OutputMode::UpdateDisplay,
+        };
+        Output::new(mode, channel)
+    }
+}
+
+impl Display for Draw {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Column => write!(f, "draw_column"),
+            Self::Row => write!(f, "draw_row"),
+            Self::Pixel => write!(f, "draw_pixel"),
+            Self::Clear => write!(f, "clear_display"),
+            Self::Present => write!(f, "present"),
+        }
+    }
+}
+
+/// Draw to the framebuffer on a numbered output channel.
+///
+/// The left hand side is the constant channel number,
+/// and the right hand side is the integer to draw with.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct DrawTo(pub Draw);
+
+impl BinaryOp for DrawTo {
+    /// Typechecks the channel and the value to draw with.
+    fn type_check(&self, channel: &Expr, val: &Expr, env: &Env) -> Result<(), Error> {
+        get_channel(channel, env)?;
+        if !val.get_type(env)?.equals(&Type::Int, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                channel.clone(),
+                val.clone(),
+            ));
+        }
+        val.type_check(env)
+    }
+
+    /// Drawing always returns `None`.
+    fn return_type(&self, _channel: &Expr, _val: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The channel and the value must both be integers.
+    fn can_apply(&self, channel: &Type, val: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(channel.equals(&Type::Int, env)? && val.equals(&Type::Int, env)?)
+    }
+
+    /// Drawing is a side effect, so it cannot be evaluated at compile time.
+    fn eval(
+        &self,
+        channel: &ConstExpr,
+        _val: &ConstExpr,
+        _env: &mut Env,
+    ) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(channel.clone()))
+    }
+
+    /// Compile the operation. Only the value is pushed onto the stack:
+    /// the channel is known at compile time.
+    fn compile(
+        &self,
+        channel: &Expr,
+        val: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let channel = get_channel(channel, env)?;
+        // Push the value onto the stack, and draw with it.
+        val.clone().compile_expr(env, output)?;
+        output.op(CoreOp::Put(SP.deref(), self.0.output(channel)));
+        // Pop the value off the stack.
+        output.op(CoreOp::Pop(None, 1));
+        Ok(())
+    }
+
+    /// The channel is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _channel: &Type,
+        _val: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Drawing is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, channel: &Expr, val: &Expr) -> String {
+        format!("{} #{channel} {val}", self.0)
+    }
+}
+
+impl Debug for DrawTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+impl Display for DrawTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+/// A sound operation on a speaker.
+/// See [`AudioBuffer`] for the convention devices follow.
+#[derive(Clone, Copy, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum Play {
+    /// Play a PCM sample, from -32768 to 32767.
+    Sample,
+    /// Set the number of samples played per second.
+    SampleRate,
+}
+
+impl Play {
+    /// The output for this operation on a channel.
+    pub fn output(&self, channel: usize) -> Output {
+        let mode = match self {
+            Self::Sample => OutputMode::Sample,
+            Self::SampleRate => OutputMode::SampleRate,
+        };
+        Output::new(mode, channel)
+    }
+}
+
+impl Display for Play {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Sample => write!(f, "play_sample"),
+            Self::SampleRate => write!(f, "set_sample_rate"),
+        }
+    }
+}
+
+/// Play sound on the speaker on a numbered output channel.
+///
+/// The left hand side is the constant channel number,
+/// and the right hand side is the integer to play.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct PlayTo(pub Play);
+
+impl BinaryOp for PlayTo {
+    /// Typechecks the channel and the value to play.
+    fn type_check(&self, channel: &Expr, val: &Expr, env: &Env) -> Result<(), Error> {
+        get_channel(channel, env)?;
+        if !val.get_type(env)?.equals(&Type::Int, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                channel.clone(),
+                val.clone(),
+            ));
+        }
+        val.type_check(env)
+    }
+
+    /// Playing sound always returns `None`.
+    fn return_type(&self, _channel: &Expr, _val: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The channel and the value must both be integers.
+    fn can_apply(&self, channel: &Type, val: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(channel.equals(&Type::Int, env)? && val.equals(&Type::Int, env)?)
+    }
+
+    /// Playing sound is a side effect, so it cannot be evaluated at compile time.
+    fn eval(
+        &self,
+        channel: &ConstExpr,
+        _val: &ConstExpr,
+        _env: &mut Env,
+    ) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(channel.clone()))
+    }
+
+    /// Compile the operation. Only the value is pushed onto the stack:
+    /// the channel is known at compile time.
+    fn compile(
+        &self,
+        channel: &Expr,
+        val: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let channel = get_channel(channel, env)?;
+        // Push the value onto the stack, and play it.
+        val.clone().compile_expr(env, output)?;
+        output.op(CoreOp::Put(SP.deref(), self.0.output(channel)));
+        // Pop the value off the stack.
+        output.op(CoreOp::Pop(None, 1));
+        Ok(())
+    }
+
+    /// The channel is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _channel: &Type,
+        _val: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Playing sound is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, channel: &Expr, val: &Expr) -> String {
+        format!("{} #{channel} {val}", self.0)
+    }
+}
+
+impl Debug for PlayTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+impl Display for PlayTo {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}_to", self.0)
+    }
+}
+
+/// A piece of a parsed format string.
+#[derive(Clone, Debug, PartialEq)]
+enum FormatPiece {
+    /// Literal text to print as-is.
+    Text(String),
+    /// A placeholder for the next argument, with an optional required type.
+    /// The boolean is whether the argument should be printed with `debug`.
+    Placeholder(Option<Type>, bool),
+}
+
+/// Print a compile-time format string, interleaved with a tuple of arguments.
+///
+/// The left hand side is the format string, and the right hand side is the
+/// tuple of arguments. The arguments are always written as a tuple, so `(x)`
+/// formats one argument, and `((x, y))` formats one tuple argument.
+/// The following placeholders are supported:
+/// - `{}` prints the next argument like `put`.
+/// - `{:?}` prints the next argument like `debug`.
+/// - `{:d}`, `{:f}`, and `{:c}` print the next argument, which must be an
+///   `Int`, `Float`, or `Char` respectively.
+///
+/// Use `{{` and `}}` to print literal braces.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Format;
+
+impl Format {
+    /// Get the format string from the left hand side of the operation.
+    fn get_format_string(fmt: &Expr, env: &Env) -> Result<String, Error> {
+        match fmt {
+            Expr::Annotated(fmt, _) => Self::get_format_string(fmt, env),
+            Expr::ConstExpr(cexpr) => match cexpr.clone().eval(env)? {
+                ConstExpr::Array(chars) => chars
+                    .into_iter()
+                    .map(|ch| ch.as_char(env))
+                    .collect::<Result<String, _>>(),
+                _ => Err(Error::InvalidFormatString(
+                    fmt.clone(),
+                    "the format string must be a constant string".to_string(),
+                )),
+            },
+            _ => Err(Error::InvalidFormatString(
+                fmt.clone(),
+                "the format string must be a constant string".to_string(),
+            )),
+        }
+    }
+
+    /// Split a format string into its literal text and placeholders.
+    fn parse(fmt: &Expr, env: &Env) -> Result<Vec<FormatPiece>, Error> {
+        let text = Self::get_format_string(fmt, env)?;
+        let invalid = |reason: &str| Error::InvalidFormatString(fmt.clone(), reason.to_string());
+
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    // Read the specifier up to the closing brace.
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => spec.push(ch),
+                            None => return Err(invalid("unterminated placeholder")),
+                        }
+                    }
+                    let placeholder = match spec.as_str() {
+                        "" => FormatPiece::Placeholder(None, false),
+                        ":?" => FormatPiece::Placeholder(None, true),
+                        ":d" => FormatPiece::Placeholder(Some(Type::Int), false),
+                        ":f" => FormatPiece::Placeholder(Some(Type::Float), false),
+                        ":c" => FormatPiece::Placeholder(Some(Type::Char), false),
+                        _ => return Err(invalid(&format!("unknown placeholder {{{spec}}}"))),
+                    };
+                    if !literal.is_empty() {
+                        pieces.push(FormatPiece::Text(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(placeholder);
+                }
+                '}' => return Err(invalid("unmatched `}`")),
+                ch => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(FormatPiece::Text(literal));
+        }
+        Ok(pieces)
+    }
+
+    /// Get the arguments to format. They must be written as a tuple, even when there
+    /// are none or only one, so that a single tuple argument isn't mistaken for several.
+    fn get_args(fmt: &Expr, args: &Expr) -> Result<Vec<Expr>, Error> {
+        match args {
+            Expr::Annotated(args, _) => Self::get_args(fmt, args),
+            Expr::Tuple(items) => Ok(items.clone()),
+            Expr::ConstExpr(ConstExpr::Tuple(items)) => {
+                Ok(items.iter().cloned().map(Expr::ConstExpr).collect())
+            }
+            _ => Err(Error::InvalidFormatString(
+                fmt.clone(),
+                "the arguments must be written as a tuple".to_string(),
+            )),
+        }
+    }
+
+    /// Get the types of the arguments to format.
+    fn get_arg_types(fmt: &Expr, args: &Expr, env: &Env) -> Result<Vec<Type>, Error> {
+        Self::get_args(fmt, args)?
+            .iter()
+            .map(|arg| arg.get_type(env))
+            .collect()
+    }
+}
+
+impl BinaryOp for Format {
+    /// Typechecks the format string against the types of the arguments.
+    fn type_check(&self, fmt: &Expr, args: &Expr, env: &Env) -> Result<(), Error> {
+        if let Expr::Annotated(args, metadata) = args {
+            return self
+                .type_check(fmt, args, env)
+                .map_err(|err| err.annotate(metadata.clone()));
+        }
+
+        args.type_check(env)?;
+        let pieces = Self::parse(fmt, env)?;
+        let arg_types = Self::get_arg_types(fmt, args, env)?;
+
+        let mut arg_types = arg_types.into_iter();
+        for piece in &pieces {
+            if let FormatPiece::Placeholder(expected, _) = piece {
+                let found = arg_types.next().ok_or_else(|| {
+                    Error::InvalidFormatString(
+                        fmt.clone(),
+                        "not enough arguments for the format string".to_string(),
+                    )
+                })?;
+                if let Some(expected) = expected {
+                    if !found.equals(expected, env)? {
+                        return Err(Error::MismatchedTypes {
+                            expected: expected.clone(),
+                            found,
+                            expr: args.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        if arg_types.next().is_some() {
+            return Err(Error::InvalidFormatString(
+                fmt.clone(),
+                "too many arguments for the format string".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Formatting a string always returns `None`.
+    fn return_type(&self, _fmt: &Expr, _args: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The format string must be a string, and the arguments can be anything.
+    fn can_apply(&self, fmt: &Type, _args: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(matches!(
+            fmt.simplify_until_concrete(env, false)?,
+            Type::Array(elem, _) if elem.equals(&Type::Char, env)?
+        ))
+    }
+
+    /// Formatting is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, fmt: &ConstExpr, _args: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(fmt.clone()))
+    }
+
+    /// Compile the format operation.
+    ///
+    /// Only the arguments are pushed onto the stack: the format string is
+    /// expanded at compile time into the output operations for each piece.
+    fn compile(
+        &self,
+        fmt: &Expr,
+        args: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        if let Expr::Annotated(args, metadata) = args {
+            return self
+                .compile(fmt, args, env, output)
+                .map_err(|err| err.annotate(metadata.clone()));
+        }
+
+        let pieces = Self::parse(fmt, env)?;
+        let arg_types = Self::get_arg_types(fmt, args, env)?;
+        // Get the size of the arguments.
+        let size = args.get_size(env)?;
+        // Push the arguments onto the stack.
+        args.clone().compile_expr(env, output)?;
+
+        // Calculate the address of the arguments on the stack.
+        let addr = SP.deref().offset(1 - size as isize);
+        let mut offset = 0;
+        let mut arg_types = arg_types.iter();
+        for piece in pieces {
+            match piece {
+                FormatPiece::Text(text) => {
+                    for ch in text.chars() {
+                        output.op(CoreOp::Set(A, ch as i64));
+                        output.op(CoreOp::Put(A, Output::stdout_char()));
+                    }
+                }
+                FormatPiece::Placeholder(_, is_debug) => {
+                    let ty = arg_types.next().ok_or_else(|| {
+                        Error::InvalidFormatString(
+                            fmt.clone(),
+                            "not enough arguments for the format string".to_string(),
+                        )
+                    })?;
+                    if is_debug {
+                        Put::debug(addr.offset(offset), ty, env, output)?;
+                    } else {
+                        Put::display(addr.offset(offset), ty, env, output)?;
+                    }
+                    offset += ty.get_size(env)? as isize;
+                }
+            }
+        }
+
+        // Pop the arguments off the stack.
+        output.op(CoreOp::Pop(None, size));
+        Ok(())
+    }
+
+    /// The format string is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _fmt: &Type,
+        _args: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, fmt: &Expr, args: &Expr) -> String {
+        format!("{self} {fmt} {args}")
+    }
+}
+
+impl Debug for Format {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "format")
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "format")
+    }
+}
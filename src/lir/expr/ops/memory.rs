@@ -1,5 +1,5 @@
 use super::*;
-use crate::asm::{CoreOp, StandardOp, A, SP};
+use crate::asm::{CoreOp, StandardOp, A, B, FP, FP_STACK, SP};
 use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 #[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
@@ -51,6 +51,11 @@ impl UnaryOp for New {
         Ok(())
     }
 
+    /// Allocating on the heap.
+    fn effects(&self) -> Effects {
+        Effects::ALLOC
+    }
+
     /// Clone this operation into a box.
     fn clone_box(&self) -> Box<dyn UnaryOp> {
         Box::new(*self)
@@ -105,6 +110,11 @@ impl UnaryOp for Delete {
         Ok(())
     }
 
+    /// Freeing heap memory.
+    fn effects(&self) -> Effects {
+        Effects::ALLOC
+    }
+
     /// Clone this operation into a box.
     fn clone_box(&self) -> Box<dyn UnaryOp> {
         Box::new(*self)
@@ -122,3 +132,123 @@ impl Display for Delete {
         write!(f, "del")
     }
 }
+
+/// Reserve a number of cells on the stack, which is only known at runtime.
+///
+/// A stack allocation must be the initial value of a variable. The cells are
+/// reserved directly after the variable, and live until the end of the
+/// variable's scope. The cells are not initialized.
+///
+/// Variables are addressed at static offsets from the frame pointer, so the
+/// frame pointer is saved and moved past the cells. Variables declared before
+/// the allocation are then addressed from the saved frame pointer.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct StackAlloc;
+
+impl StackAlloc {
+    /// Reserve the cells, given the variable on the top of the stack which
+    /// holds the number of cells to reserve. The variable is overwritten with
+    /// a pointer to the cells, and a new stack frame is started after them.
+    pub(crate) fn compile_reserve(
+        &self,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        output.op(CoreOp::Move {
+            src: SP.deref(),
+            dst: A,
+        });
+        output.op(CoreOp::GetAddress {
+            addr: SP.deref().offset(1),
+            dst: B,
+        });
+        output.op(CoreOp::Move {
+            src: B,
+            dst: SP.deref(),
+        });
+        output.op(CoreOp::Index {
+            src: SP,
+            offset: A,
+            dst: SP,
+        });
+
+        // Save the frame pointer, and move it past the cells.
+        output.op(CoreOp::PushTo {
+            src: FP,
+            sp: FP_STACK,
+            size: 1,
+        });
+        output.op(CoreOp::Index {
+            src: FP,
+            offset: A,
+            dst: FP,
+        });
+        env.push_stack_frame();
+        Ok(())
+    }
+}
+
+impl UnaryOp for StackAlloc {
+    /// The number of cells to reserve must be an integer.
+    fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        ty.equals(&Type::Int, env)
+    }
+
+    /// The result is a pointer to the reserved cells.
+    fn return_type(&self, _expr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::Pointer(Mutability::Mutable, Box::new(Type::Any)))
+    }
+
+    /// Stack allocation is only done at runtime.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Stack allocations are compiled by the declarations they initialize,
+    /// so anywhere else is an error.
+    fn compile(
+        &self,
+        expr: &Expr,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::InvalidStackAlloc(Expr::UnaryOp(
+            self.name(),
+            Box::new(expr.clone()),
+        )))
+    }
+
+    fn compile_types(
+        &self,
+        _ty: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::InvalidStackAlloc(Expr::UnaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for StackAlloc {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "alloca")
+    }
+}
+
+impl Display for StackAlloc {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "alloca")
+    }
+}
@@ -4,20 +4,30 @@
 //! tertiary, binary, and unary operations on LIR expressions.
 mod arithmetic;
 mod assign;
+mod atomic;
 mod bitwise;
 mod comparison;
+mod derive;
 mod io;
 mod logic;
 mod memory;
+mod number;
+mod panic;
+mod serialize;
 mod tagged_union;
 
 pub use arithmetic::*;
 pub use assign::*;
+pub use atomic::*;
 pub use bitwise::*;
 pub use comparison::*;
+pub use derive::*;
 pub use io::*;
 pub use logic::*;
 pub use memory::*;
+pub use number::*;
+pub use panic::*;
+pub use serialize::*;
 pub use tagged_union::*;
 
 use crate::{asm::AssemblyProgram, lir::*};
@@ -185,6 +195,10 @@ pub trait UnaryOp: std::fmt::Debug + std::fmt::Display + Send + Sync {
     }
     /// Evaluates the operation on the given constant expression.
     fn eval(&self, expr: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error>;
+    /// The side effects of the operation itself, not counting its operands.
+    fn effects(&self) -> Effects {
+        Effects::PURE
+    }
     /// Compiles the operation on the given expression.
     fn compile(
         &self,
@@ -287,6 +301,10 @@ pub trait BinaryOp: std::fmt::Debug + std::fmt::Display + Send + Sync {
     }
     /// Evaluates the operation on the given constant expressions.
     fn eval(&self, lhs: &ConstExpr, rhs: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error>;
+    /// The side effects of the operation itself, not counting its operands.
+    fn effects(&self) -> Effects {
+        Effects::PURE
+    }
     /// Compiles the operation on the given expressions.
     fn compile(
         &self,
@@ -405,6 +423,10 @@ pub trait TernaryOp: std::fmt::Debug + std::fmt::Display + Send + Sync {
     fn can_apply_exprs(&self, a: &Expr, b: &Expr, c: &Expr, env: &Env) -> Result<bool, Error> {
         self.can_apply(&a.get_type(env)?, &b.get_type(env)?, &c.get_type(env)?, env)
     }
+    /// The side effects of the operation itself, not counting its operands.
+    fn effects(&self) -> Effects {
+        Effects::PURE
+    }
     /// Evaluates the operation on the given constant expressions.
     fn eval(
         &self,
@@ -0,0 +1,697 @@
+//! # Number Conversions
+//!
+//! This module implements the builtins which convert between numbers and strings:
+//! - `int_to_string(n)` and `float_to_string(f, precision)` return the number
+//!   written in decimal, as a null-terminated string of `NUMBER_STRING_LEN` characters.
+//! - `parse_int(ptr)` and `parse_float(ptr)` read a number from the start of a string.
+//!
+//! The output devices and targets each print numbers their own way, so these
+//! are implemented in generated LIR code instead. Each builtin calls a helper
+//! procedure which is compiled once per program, so every target gives the same result.
+//!
+//! Floats are written with exactly `precision` digits after the decimal point (at most
+//! `MAX_FLOAT_PRECISION`), rounded to the nearest digit. Floats too large for an `Int`
+//! are written with a single digit before the decimal point and an exponent, like `1.50e+20`,
+//! and `NaN` and `inf` are written as such.
+use super::*;
+
+/// The number of characters in the strings returned by `int_to_string` and `float_to_string`.
+/// The strings are null-terminated, and padded with null characters.
+pub const NUMBER_STRING_LEN: usize = 48;
+/// The most digits that `float_to_string` writes after the decimal point.
+pub const MAX_FLOAT_PRECISION: i64 = 16;
+
+/// The type of the strings returned by `int_to_string` and `float_to_string`.
+fn number_string_type() -> Type {
+    Type::Array(
+        Box::new(Type::Char),
+        Box::new(ConstExpr::Int(NUMBER_STRING_LEN as i64)),
+    )
+}
+
+/// Is the type a pointer to characters?
+fn is_char_pointer(ty: &Type, env: &Env) -> Result<bool, Error> {
+    Ok(match ty.simplify_until_concrete(env, false)? {
+        Type::Pointer(_, elem) => elem.equals(&Type::Char, env)?,
+        _ => false,
+    })
+}
+
+/// Compile a call to one of the helper procedures with some arguments.
+fn compile_helper(
+    proc: Procedure,
+    args: Vec<Expr>,
+    env: &mut Env,
+    output: &mut dyn AssemblyProgram,
+) -> Result<(), Error> {
+    let proc = proc.with_attributes([Attribute::NoMangle]);
+    Expr::from(ConstExpr::Proc(proc))
+        .app(args)
+        .compile_expr(env, output)
+}
+
+/// An integer constant.
+fn int(n: i64) -> ConstExpr {
+    ConstExpr::Int(n)
+}
+
+/// A float constant.
+fn float(f: f64) -> ConstExpr {
+    ConstExpr::Float(f)
+}
+
+/// Assign a value to a mutable variable.
+fn set(name: &str, val: impl Into<Expr>) -> Expr {
+    Expr::var(name).refer(Mutability::Mutable).deref_mut(val)
+}
+
+/// Do something only if a condition is true.
+fn when(cond: Expr, then: impl Into<Expr>) -> Expr {
+    cond.if_then(then, ConstExpr::None)
+}
+
+/// Declare the `buf` string and its length `len`, evaluate some expressions, and return `buf`.
+fn with_buffer(body: Vec<Expr>) -> Expr {
+    let mut body = body;
+    body.push(Expr::var("buf"));
+    Expr::let_vars(
+        vec![
+            (
+                "buf",
+                Mutability::Mutable,
+                Some(number_string_type()),
+                Expr::ArrayRepeat(
+                    Box::new(ConstExpr::Char('\0').into()),
+                    Box::new(int(NUMBER_STRING_LEN as i64)),
+                ),
+            ),
+            ("len", Mutability::Mutable, Some(Type::Int), int(0).into()),
+        ],
+        Expr::Many(body),
+    )
+}
+
+/// Write a character at the end of `buf`.
+fn push_char(ch: impl Into<Expr>) -> Expr {
+    Expr::Many(vec![
+        Expr::var("buf")
+            .idx(Expr::var("len"))
+            .refer(Mutability::Mutable)
+            .deref_mut(ch),
+        set("len", Expr::var("len").add(int(1))),
+    ])
+}
+
+/// Write some text at the end of `buf`.
+fn push_str(text: &str) -> Expr {
+    Expr::Many(
+        text.chars()
+            .map(|ch| push_char(ConstExpr::Char(ch)))
+            .collect(),
+    )
+}
+
+/// Write the digits of the integer variable `n` at the end of `buf`, leaving `n` as zero.
+/// The variable must not be positive: negative numbers have room for the smallest `Int`.
+fn push_digits(n: &str) -> Expr {
+    Expr::let_vars(
+        vec![
+            (
+                "digits",
+                Mutability::Mutable,
+                Some(Type::Int),
+                int(1).into(),
+            ),
+            (
+                "rest",
+                Mutability::Mutable,
+                Some(Type::Int),
+                Expr::var(n).div(int(10)),
+            ),
+        ],
+        Expr::Many(vec![
+            // Count the digits.
+            Expr::var("rest").neq(int(0)).while_loop(Expr::Many(vec![
+                set("digits", Expr::var("digits").add(int(1))),
+                set("rest", Expr::var("rest").div(int(10))),
+            ])),
+            // Write the digits from the last to the first.
+            Expr::let_var(
+                "i",
+                Mutability::Mutable,
+                Some(Type::Int),
+                Expr::var("len").add(Expr::var("digits")),
+                Expr::Many(vec![
+                    Expr::var("i")
+                        .gt(Expr::var("len"))
+                        .while_loop(Expr::Many(vec![
+                            set("i", Expr::var("i").sub(int(1))),
+                            Expr::var("buf")
+                                .idx(Expr::var("i"))
+                                .refer(Mutability::Mutable)
+                                .deref_mut(
+                                    Expr::from(int('0' as i64))
+                                        .sub(Expr::var(n).rem(int(10)))
+                                        .as_type(Type::Char),
+                                ),
+                            set(n, Expr::var(n).div(int(10))),
+                        ])),
+                    set("len", Expr::var("len").add(Expr::var("digits"))),
+                ]),
+            ),
+        ]),
+    )
+}
+
+/// The value of the digit at `ptr[i]`, or a value outside `0..=9` if it isn't a digit.
+fn digit_at(i: &str) -> Expr {
+    Expr::var("ptr")
+        .idx(Expr::var(i))
+        .as_type(Type::Int)
+        .sub(int('0' as i64))
+}
+
+/// Is the digit variable `d` between 0 and 9?
+fn is_digit(d: &str) -> Expr {
+    Expr::var(d).ge(int(0)).and(Expr::var(d).le(int(9)))
+}
+
+/// Is the character at `ptr[i]` the given character?
+fn char_at_is(i: &str, ch: char) -> Expr {
+    Expr::var("ptr")
+        .idx(Expr::var(i))
+        .as_type(Type::Int)
+        .eq(int(ch as i64))
+}
+
+/// Skip a sign at `ptr[i]`, and set `neg` if it's a minus sign.
+fn skip_sign() -> Expr {
+    char_at_is("i", '-').if_then(
+        Expr::Many(vec![
+            set("neg", ConstExpr::Bool(true)),
+            set("i", Expr::var("i").add(int(1))),
+        ]),
+        when(char_at_is("i", '+'), set("i", Expr::var("i").add(int(1)))),
+    )
+}
+
+/// The helper procedure for `int_to_string`.
+fn int_to_string_proc() -> Procedure {
+    Procedure::new(
+        Some("__int_to_string".to_string()),
+        vec![("n".to_string(), Mutability::Mutable, Type::Int)],
+        number_string_type(),
+        with_buffer(vec![
+            // Work with the negative of the number, so the smallest `Int` can be written.
+            Expr::var("n").lt(int(0)).if_then(
+                push_char(ConstExpr::Char('-')),
+                set("n", Expr::var("n").neg()),
+            ),
+            push_digits("n"),
+        ]),
+    )
+}
+
+/// The helper procedure for `float_to_string`.
+fn float_to_string_proc() -> Procedure {
+    let f = || Expr::var("f");
+    let precision = || Expr::var("precision");
+    let i = || Expr::var("i");
+
+    // Write a float which is a positive, finite number.
+    let scale_down = || {
+        Expr::Many(vec![
+            set("f", f().div(float(10.0))),
+            set("exp", Expr::var("exp").add(int(1))),
+        ])
+    };
+    let write_finite = Expr::Many(vec![
+        // Scale floats too large for an `Int` down to a single digit before the decimal
+        // point, and write the exponent after them.
+        when(
+            f().lt(float(1e18)).not(),
+            f().lt(float(10.0)).not().while_loop(scale_down()),
+        ),
+        // Round to the nearest digit at the precision.
+        i().lt(precision()).while_loop(Expr::Many(vec![
+            set("round", Expr::var("round").div(float(10.0))),
+            set("i", i().add(int(1))),
+        ])),
+        set("f", f().add(Expr::var("round"))),
+        // Rounding can carry into another digit before the decimal point, like `9.99e+20`
+        // rounding to `10.0e+20`, so scale it down again.
+        when(
+            Expr::var("exp").gt(int(0)).and(f().lt(float(10.0)).not()),
+            scale_down(),
+        ),
+        // Write the whole part.
+        set("whole", f().as_type(Type::Int)),
+        set("frac", f().sub(Expr::var("whole").as_type(Type::Float))),
+        set("whole", Expr::var("whole").neg()),
+        push_digits("whole"),
+        // Write the digits of the fractional part.
+        when(
+            precision().gt(int(0)),
+            Expr::Many(vec![
+                push_char(ConstExpr::Char('.')),
+                set("i", int(0)),
+                i().lt(precision()).while_loop(Expr::Many(vec![
+                    set("frac", Expr::var("frac").mul(float(10.0))),
+                    set("whole", Expr::var("frac").as_type(Type::Int)),
+                    when(Expr::var("whole").gt(int(9)), set("whole", int(9))),
+                    set(
+                        "frac",
+                        Expr::var("frac").sub(Expr::var("whole").as_type(Type::Float)),
+                    ),
+                    push_char(Expr::var("whole").add(int('0' as i64)).as_type(Type::Char)),
+                    set("i", i().add(int(1))),
+                ])),
+            ]),
+        ),
+        when(
+            Expr::var("exp").gt(int(0)),
+            Expr::Many(vec![
+                push_str("e+"),
+                set("exp", Expr::var("exp").neg()),
+                push_digits("exp"),
+            ]),
+        ),
+    ]);
+
+    Procedure::new(
+        Some("__float_to_string".to_string()),
+        vec![
+            ("f".to_string(), Mutability::Mutable, Type::Float),
+            ("precision".to_string(), Mutability::Mutable, Type::Int),
+        ],
+        number_string_type(),
+        Expr::let_vars(
+            vec![
+                ("whole", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                (
+                    "frac",
+                    Mutability::Mutable,
+                    Some(Type::Float),
+                    float(0.0).into(),
+                ),
+                ("exp", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                (
+                    "round",
+                    Mutability::Mutable,
+                    Some(Type::Float),
+                    float(0.5).into(),
+                ),
+                ("i", Mutability::Mutable, Some(Type::Int), int(0).into()),
+            ],
+            with_buffer(vec![
+                when(precision().lt(int(0)), set("precision", int(0))),
+                when(
+                    precision().gt(int(MAX_FLOAT_PRECISION)),
+                    set("precision", int(MAX_FLOAT_PRECISION)),
+                ),
+                // Every comparison with `NaN` is false.
+                f().lt(float(1.0)).or(f().gt(float(0.0))).not().if_then(
+                    push_str("NaN"),
+                    Expr::Many(vec![
+                        when(
+                            f().lt(float(0.0)),
+                            Expr::Many(vec![push_char(ConstExpr::Char('-')), set("f", f().neg())]),
+                        ),
+                        f().gt(float(f64::MAX))
+                            .if_then(push_str("inf"), write_finite),
+                    ]),
+                ),
+            ]),
+        ),
+    )
+}
+
+/// The helper procedure for `parse_int`.
+fn parse_int_proc() -> Procedure {
+    Procedure::new(
+        Some("__parse_int".to_string()),
+        vec![(
+            "ptr".to_string(),
+            Mutability::Immutable,
+            Type::Pointer(Mutability::Any, Box::new(Type::Char)),
+        )],
+        Type::Int,
+        Expr::let_vars(
+            vec![
+                ("i", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                ("n", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                (
+                    "neg",
+                    Mutability::Mutable,
+                    Some(Type::Bool),
+                    ConstExpr::Bool(false).into(),
+                ),
+                ("d", Mutability::Mutable, Some(Type::Int), int(0).into()),
+            ],
+            Expr::Many(vec![
+                skip_sign(),
+                set("d", digit_at("i")),
+                // Accumulate the negative of the number, so the smallest `Int` can be read.
+                is_digit("d").while_loop(Expr::Many(vec![
+                    set("n", Expr::var("n").mul(int(10)).sub(Expr::var("d"))),
+                    set("i", Expr::var("i").add(int(1))),
+                    set("d", digit_at("i")),
+                ])),
+                Expr::var("neg").if_then(Expr::var("n"), Expr::var("n").neg()),
+            ]),
+        ),
+    )
+}
+
+/// The helper procedure for `parse_float`.
+fn parse_float_proc() -> Procedure {
+    let f = || Expr::var("f");
+    let i = || Expr::var("i");
+    Procedure::new(
+        Some("__parse_float".to_string()),
+        vec![(
+            "ptr".to_string(),
+            Mutability::Immutable,
+            Type::Pointer(Mutability::Any, Box::new(Type::Char)),
+        )],
+        Type::Float,
+        Expr::let_vars(
+            vec![
+                ("i", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                (
+                    "f",
+                    Mutability::Mutable,
+                    Some(Type::Float),
+                    float(0.0).into(),
+                ),
+                (
+                    "scale",
+                    Mutability::Mutable,
+                    Some(Type::Float),
+                    float(0.1).into(),
+                ),
+                (
+                    "neg",
+                    Mutability::Mutable,
+                    Some(Type::Bool),
+                    ConstExpr::Bool(false).into(),
+                ),
+                ("d", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                ("exp", Mutability::Mutable, Some(Type::Int), int(0).into()),
+                (
+                    "exp_neg",
+                    Mutability::Mutable,
+                    Some(Type::Bool),
+                    ConstExpr::Bool(false).into(),
+                ),
+            ],
+            Expr::Many(vec![
+                skip_sign(),
+                // Read the whole part.
+                set("d", digit_at("i")),
+                is_digit("d").while_loop(Expr::Many(vec![
+                    set(
+                        "f",
+                        f().mul(float(10.0))
+                            .add(Expr::var("d").as_type(Type::Float)),
+                    ),
+                    set("i", i().add(int(1))),
+                    set("d", digit_at("i")),
+                ])),
+                // Read the fractional part.
+                when(
+                    char_at_is("i", '.'),
+                    Expr::Many(vec![
+                        set("i", i().add(int(1))),
+                        set("d", digit_at("i")),
+                        is_digit("d").while_loop(Expr::Many(vec![
+                            set(
+                                "f",
+                                f().add(
+                                    Expr::var("scale").mul(Expr::var("d").as_type(Type::Float)),
+                                ),
+                            ),
+                            set("scale", Expr::var("scale").div(float(10.0))),
+                            set("i", i().add(int(1))),
+                            set("d", digit_at("i")),
+                        ])),
+                    ]),
+                ),
+                // Read the exponent, and scale the number by it.
+                when(
+                    char_at_is("i", 'e').or(char_at_is("i", 'E')),
+                    Expr::Many(vec![
+                        set("i", i().add(int(1))),
+                        char_at_is("i", '-').if_then(
+                            Expr::Many(vec![
+                                set("exp_neg", ConstExpr::Bool(true)),
+                                set("i", i().add(int(1))),
+                            ]),
+                            when(char_at_is("i", '+'), set("i", i().add(int(1)))),
+                        ),
+                        set("d", digit_at("i")),
+                        is_digit("d").while_loop(Expr::Many(vec![
+                            set("exp", Expr::var("exp").mul(int(10)).add(Expr::var("d"))),
+                            set("i", i().add(int(1))),
+                            set("d", digit_at("i")),
+                        ])),
+                        Expr::var("exp").gt(int(0)).while_loop(Expr::Many(vec![
+                            Expr::var("exp_neg").if_then(
+                                set("f", f().div(float(10.0))),
+                                set("f", f().mul(float(10.0))),
+                            ),
+                            set("exp", Expr::var("exp").sub(int(1))),
+                        ])),
+                    ]),
+                ),
+                Expr::var("neg").if_then(f().neg(), f()),
+            ]),
+        ),
+    )
+}
+
+/// Write an integer as a decimal string.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct IntToString;
+
+impl UnaryOp for IntToString {
+    /// The number must be an `Int`.
+    fn can_apply(&self, expr: &Type, env: &Env) -> Result<bool, Error> {
+        expr.equals(&Type::Int, env)
+    }
+
+    /// The result is a null-terminated string.
+    fn return_type(&self, _expr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(number_string_type())
+    }
+
+    /// Write a constant integer as a string.
+    fn eval(&self, expr: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        let n = expr.clone().as_int(env)?;
+        let mut chars = n
+            .to_string()
+            .chars()
+            .map(ConstExpr::Char)
+            .collect::<Vec<_>>();
+        chars.resize(NUMBER_STRING_LEN, ConstExpr::Char('\0'));
+        Ok(ConstExpr::Array(chars))
+    }
+
+    /// Compile a call to the helper procedure.
+    fn compile(
+        &self,
+        expr: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        compile_helper(int_to_string_proc(), vec![expr.clone()], env, output)
+    }
+
+    /// The operation is compiled by `compile`, so this is never used.
+    fn compile_types(
+        &self,
+        _expr: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for IntToString {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "int_to_string")
+    }
+}
+
+impl Display for IntToString {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "int_to_string")
+    }
+}
+
+/// Write a float as a decimal string, with a given number of digits after the decimal point.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct FloatToString;
+
+impl BinaryOp for FloatToString {
+    /// The number must be a `Float`, and the precision must be an `Int`.
+    fn can_apply(&self, f: &Type, precision: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(f.equals(&Type::Float, env)? && precision.equals(&Type::Int, env)?)
+    }
+
+    /// The result is a null-terminated string.
+    fn return_type(&self, _f: &Expr, _precision: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(number_string_type())
+    }
+
+    /// The rounding of the generated code isn't reproduced at compile time.
+    fn eval(
+        &self,
+        f: &ConstExpr,
+        _precision: &ConstExpr,
+        _env: &mut Env,
+    ) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(f.clone()))
+    }
+
+    /// Compile a call to the helper procedure.
+    fn compile(
+        &self,
+        f: &Expr,
+        precision: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        compile_helper(
+            float_to_string_proc(),
+            vec![f.clone(), precision.clone()],
+            env,
+            output,
+        )
+    }
+
+    /// The operation is compiled by `compile`, so this is never used.
+    fn compile_types(
+        &self,
+        _f: &Type,
+        _precision: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, f: &Expr, precision: &Expr) -> String {
+        format!("{self}({f}, {precision})")
+    }
+}
+
+impl Debug for FloatToString {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "float_to_string")
+    }
+}
+
+impl Display for FloatToString {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "float_to_string")
+    }
+}
+
+/// Read a number from the start of a string.
+///
+/// The number can start with a sign. Reading stops at the first character which
+/// isn't part of the number, and a string which doesn't start with a number reads as zero.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub enum ParseNumber {
+    /// Read an `Int`, like `-42`.
+    Int,
+    /// Read a `Float`, like `-4.2`, `.5`, or `1e-3`.
+    Float,
+}
+
+impl UnaryOp for ParseNumber {
+    /// The string must be a pointer to characters.
+    fn can_apply(&self, expr: &Type, env: &Env) -> Result<bool, Error> {
+        is_char_pointer(expr, env)
+    }
+
+    /// The result is the number which was read.
+    fn return_type(&self, _expr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(match self {
+            Self::Int => Type::Int,
+            Self::Float => Type::Float,
+        })
+    }
+
+    /// Pointers can't be read at compile time.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Compile a call to the helper procedure.
+    fn compile(
+        &self,
+        expr: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let proc = match self {
+            Self::Int => parse_int_proc(),
+            Self::Float => parse_float_proc(),
+        };
+        compile_helper(proc, vec![expr.clone()], env, output)
+    }
+
+    /// The operation is compiled by `compile`, so this is never used.
+    fn compile_types(
+        &self,
+        _expr: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for ParseNumber {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for ParseNumber {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Int => write!(f, "parse_int"),
+            Self::Float => write!(f, "parse_float"),
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! # Panics
+//!
+//! A panic halts the program with a failing exit status, after writing a message
+//! to the standard error stream. Every panic uses the same trap sequence:
+//!
+//! 1. Write `panicked at <location>: ` (or `panicked: ` if the location
+//!    isn't known) to the standard error stream.
+//! 2. Write the message, followed by a newline.
+//! 3. Halt with the exit status `1`, by writing it to the `exit` output.
+//!
+//! The interpreters report the halt as a `RuntimeError::Exit`, and the C
+//! target calls `exit` with the status.
+//!
+//! ## Backtraces
+//!
+//! When the environment has backtraces enabled, every procedure pushes a pointer to
+//! its name onto a shadow stack in static memory when it's called, and pops it when it
+//! returns. A panic then writes the names on the shadow stack after its message,
+//! from the innermost call outwards. Only the innermost `BACKTRACE_DEPTH` calls are kept.
+//!
+//! ## Assertions
+//!
+//! An assertion panics with its message if its condition is false. Assertions are
+//! only compiled when the environment has assertions enabled (the default), and
+//! are removed entirely otherwise. An assertion whose condition can be evaluated
+//! at compile time is checked while type checking instead, and never compiled.
+//!
+//! ## Exiting
+//!
+//! `exit(status)` halts the program normally with an integer exit status, by
+//! writing it to the `exit` output without a message. This is how the status
+//! returned by a program's `main` procedure is passed on.
+use super::*;
+use crate::{
+    asm::{CoreOp, Location, A, B, C, D, SP},
+    side_effects::Output,
+};
+use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// The exit status of a program which panics.
+pub const PANIC_EXIT_STATUS: i64 = 1;
+
+/// The number of calls kept on the shadow stack for backtraces.
+pub const BACKTRACE_DEPTH: usize = 256;
+/// The global holding the number of calls on the shadow stack.
+const BACKTRACE_LEN: &str = "__backtrace_len";
+/// The global holding the pointers to the names of the procedures on the shadow stack.
+const BACKTRACE: &str = "__backtrace";
+
+/// Declare the globals which hold the shadow stack.
+fn declare_backtrace(output: &mut dyn AssemblyProgram) {
+    output.op(CoreOp::Global {
+        name: BACKTRACE_LEN.to_string(),
+        size: 1,
+    });
+    output.op(CoreOp::Global {
+        name: BACKTRACE.to_string(),
+        size: BACKTRACE_DEPTH,
+    });
+}
+
+/// Push the name of a procedure onto the shadow stack, when the procedure is called.
+/// This overwrites the `A`, `B`, and `C` registers.
+pub(crate) fn compile_backtrace_push(label: &str, name: &str, output: &mut dyn AssemblyProgram) {
+    declare_backtrace(output);
+    // Store the name of the procedure as a null-terminated string in its own global.
+    let name_global = format!("__backtrace_name_{label}");
+    let mut chars = name.chars().map(|ch| ch as i64).collect::<Vec<_>>();
+    chars.push(0);
+    output.op(CoreOp::Global {
+        name: name_global.clone(),
+        size: chars.len(),
+    });
+    output.op(CoreOp::Const {
+        dst: Location::Global(name_global.clone()),
+        vals: chars,
+    });
+
+    // If there's room on the shadow stack, store a pointer to the name at the top.
+    let len = Location::Global(BACKTRACE_LEN.to_string());
+    output.op(CoreOp::Set(B, BACKTRACE_DEPTH as i64));
+    output.op(CoreOp::IsLess {
+        a: len.clone(),
+        b: B,
+        dst: C,
+    });
+    output.op(CoreOp::If(C));
+    output.op(CoreOp::GetAddress {
+        addr: Location::Global(BACKTRACE.to_string()),
+        dst: B,
+    });
+    output.op(CoreOp::Index {
+        src: B,
+        offset: len.clone(),
+        dst: C,
+    });
+    output.op(CoreOp::GetAddress {
+        addr: Location::Global(name_global),
+        dst: A,
+    });
+    output.op(CoreOp::Move {
+        src: A,
+        dst: C.deref(),
+    });
+    output.op(CoreOp::End);
+    output.op(CoreOp::Inc(len));
+}
+
+/// Pop the name of a procedure off the shadow stack, when the procedure returns.
+pub(crate) fn compile_backtrace_pop(output: &mut dyn AssemblyProgram) {
+    declare_backtrace(output);
+    output.op(CoreOp::Dec(Location::Global(BACKTRACE_LEN.to_string())));
+}
+
+/// Write the names of the procedures on the shadow stack to the standard error stream.
+/// This overwrites the `A`, `B`, `C`, and `D` registers.
+fn compile_print_backtrace(output: &mut dyn AssemblyProgram) {
+    declare_backtrace(output);
+    compile_eprint("backtrace:\n", output);
+    // Store the number of names kept on the shadow stack in `B`.
+    output.op(CoreOp::Move {
+        src: Location::Global(BACKTRACE_LEN.to_string()),
+        dst: B,
+    });
+    output.op(CoreOp::Set(C, BACKTRACE_DEPTH as i64));
+    output.op(CoreOp::IsLess { a: C, b: B, dst: D });
+    output.op(CoreOp::If(D));
+    output.op(CoreOp::Move { src: C, dst: B });
+    output.op(CoreOp::End);
+
+    // Write the names from the top of the shadow stack down.
+    output.op(CoreOp::While(B));
+    output.op(CoreOp::Dec(B));
+    compile_eprint("  in ", output);
+    output.op(CoreOp::GetAddress {
+        addr: Location::Global(BACKTRACE.to_string()),
+        dst: A,
+    });
+    output.op(CoreOp::Index {
+        src: A,
+        offset: B,
+        dst: C,
+    });
+    output.op(CoreOp::Move {
+        src: C.deref(),
+        dst: A,
+    });
+    // Write the null-terminated name that `A` points to.
+    output.op(CoreOp::Move {
+        src: A.deref(),
+        dst: C,
+    });
+    output.op(CoreOp::While(C));
+    output.op(CoreOp::Put(C, Stream::Stderr(0).char()));
+    output.op(CoreOp::Next(A, None));
+    output.op(CoreOp::Move {
+        src: A.deref(),
+        dst: C,
+    });
+    output.op(CoreOp::End);
+    compile_eprint("\n", output);
+    output.op(CoreOp::End);
+}
+
+/// Write the start of a panic message, with the location of the expression being compiled.
+fn compile_panic_prefix(env: &Env, output: &mut dyn AssemblyProgram) {
+    match env.get_location() {
+        Some(loc) => compile_eprint(&format!("panicked at {loc}: "), output),
+        None => compile_eprint("panicked: ", output),
+    }
+}
+
+/// Write a string known at compile time to the standard error stream.
+/// This overwrites the `C` register.
+fn compile_eprint(text: &str, output: &mut dyn AssemblyProgram) {
+    for ch in text.chars() {
+        output.op(CoreOp::Set(C, ch as i64));
+        output.op(CoreOp::Put(C, Stream::Stderr(0).char()));
+    }
+}
+
+/// Halt the program with the given exit status, after writing
+/// the backtrace if the environment has backtraces enabled.
+fn compile_exit(status: i64, env: &Env, output: &mut dyn AssemblyProgram) {
+    if env.has_backtraces() {
+        compile_print_backtrace(output);
+    }
+    output.op(CoreOp::Set(C, status));
+    output.op(CoreOp::Put(C, Output::exit()));
+}
+
+/// Compile a panic with a message known at compile time. This is used for the
+/// runtime checks inserted by the compiler, like checking the variant of a tagged union.
+/// The registers are overwritten, so this should only run when the check fails.
+pub(crate) fn compile_trap(message: &str, env: &Env, output: &mut dyn AssemblyProgram) {
+    compile_panic_prefix(env, output);
+    compile_eprint(message, output);
+    compile_eprint("\n", output);
+    compile_exit(PANIC_EXIT_STATUS, env, output);
+}
+
+/// Compile a panic with the message on the top of the stack, written after the given note.
+fn compile_panic_with(
+    note: &str,
+    ty: &Type,
+    env: &mut Env,
+    output: &mut dyn AssemblyProgram,
+) -> Result<(), Error> {
+    let size = ty.get_size(env)?;
+
+    compile_panic_prefix(env, output);
+    compile_eprint(note, output);
+    Put::display_to(
+        Stream::Stderr(0),
+        SP.deref().offset(1 - size as isize),
+        ty,
+        env,
+        output,
+    )?;
+    compile_eprint("\n", output);
+    compile_exit(PANIC_EXIT_STATUS, env, output);
+
+    // The program has halted, but the code after this still
+    // expects the message to be popped off the stack.
+    output.op(CoreOp::Pop(None, size));
+    Ok(())
+}
+
+/// Halt the program with a failing exit status, after writing a message to the standard error stream.
+///
+/// This is written `panic(msg)`. The message can be any value which can be printed with `eput`.
+/// The result is `Never`, so a panic can be used wherever a value of any type is expected.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Panic;
+
+impl UnaryOp for Panic {
+    /// Any value can be used as the message.
+    fn can_apply(&self, _expr: &Type, _env: &Env) -> Result<bool, Error> {
+        Ok(true)
+    }
+
+    /// A panic never returns.
+    fn return_type(&self, _expr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::Never)
+    }
+
+    /// Panicking is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Compile the panic, with the message on the top of the stack.
+    fn compile_types(
+        &self,
+        ty: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        compile_panic_with("", ty, env, output)
+    }
+
+    /// Panicking writes a message and exits, which is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+}
+
+impl Debug for Panic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "panic")
+    }
+}
+
+impl Display for Panic {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "panic")
+    }
+}
+
+/// Panic with a message if a condition is false.
+///
+/// This is written `assert(cond, msg)`. The message is only evaluated if the assertion fails.
+/// If the environment has assertions disabled, neither the condition nor the message is evaluated.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Assert;
+
+impl BinaryOp for Assert {
+    /// Typecheck the assertion, and check its condition if it's known at compile time.
+    fn type_check(&self, cond: &Expr, msg: &Expr, env: &Env) -> Result<(), Error> {
+        if !cond.get_type(env)?.equals(&Type::Bool, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                cond.clone(),
+                msg.clone(),
+            ));
+        }
+        cond.type_check(env)?;
+        msg.type_check(env)?;
+
+        match cond.eval_const(env) {
+            Ok(ConstExpr::Bool(false)) => Err(Error::AssertionFailed(cond.clone(), msg.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    /// An assertion has no result.
+    fn return_type(&self, _cond: &Expr, _msg: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::None)
+    }
+
+    /// The condition must be a `Bool`, and the message can be any value.
+    fn can_apply(&self, cond: &Type, _msg: &Type, env: &Env) -> Result<bool, Error> {
+        cond.equals(&Type::Bool, env)
+    }
+
+    /// Check a constant assertion.
+    fn eval(&self, cond: &ConstExpr, msg: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        match cond.clone().eval(env)? {
+            ConstExpr::Bool(true) => Ok(ConstExpr::None),
+            ConstExpr::Bool(false) => Err(Error::AssertionFailed(
+                Expr::ConstExpr(cond.clone()),
+                Expr::ConstExpr(msg.clone()),
+            )),
+            found => Err(Error::InvalidConstExpr(found)),
+        }
+    }
+
+    /// Compile the assertion. The message is only compiled into the branch which panics.
+    fn compile(
+        &self,
+        cond: &Expr,
+        msg: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        // Assertions which are disabled, or which always hold, compile to nothing.
+        if !env.has_assertions() || matches!(cond.eval_const(env), Ok(ConstExpr::Bool(true))) {
+            return Ok(());
+        }
+
+        cond.clone().compile_expr(env, output)?;
+        output.op(CoreOp::Pop(Some(A), 1));
+        output.op(CoreOp::Not(A));
+        output.op(CoreOp::If(A));
+        let ty = msg.get_type(env)?;
+        msg.clone().compile_expr(env, output)?;
+        compile_panic_with("assertion failed: ", &ty, env, output)?;
+        output.op(CoreOp::End);
+        Ok(())
+    }
+
+    /// The message is compiled by `compile`, so this is never used.
+    fn compile_types(
+        &self,
+        _cond: &Type,
+        _msg: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// A failed assertion panics, which is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the assertion like a call.
+    fn display(&self, cond: &Expr, msg: &Expr) -> String {
+        format!("assert({cond}, {msg})")
+    }
+}
+
+impl Debug for Assert {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "assert")
+    }
+}
+
+impl Display for Assert {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "assert")
+    }
+}
+
+/// Halt the program with an exit status.
+///
+/// This is written `exit(status)`, where the status is an `Int`. Unlike a panic,
+/// nothing is written to the standard error stream.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct Exit;
+
+impl UnaryOp for Exit {
+    /// The exit status must be an integer.
+    fn can_apply(&self, expr: &Type, env: &Env) -> Result<bool, Error> {
+        expr.can_decay_to(&Type::Int, env)
+    }
+
+    /// Exiting never returns.
+    fn return_type(&self, _expr: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::Never)
+    }
+
+    /// Exiting is a side effect, so it cannot be evaluated at compile time.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Halt with the status on the top of the stack.
+    fn compile_types(
+        &self,
+        _ty: &Type,
+        _env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        output.op(CoreOp::Pop(Some(C), 1));
+        output.op(CoreOp::Put(C, Output::exit()));
+        Ok(())
+    }
+
+    /// Exiting halts the program, which is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("exit({expr})")
+    }
+}
+
+impl Debug for Exit {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "exit")
+    }
+}
+
+impl Display for Exit {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "exit")
+    }
+}
@@ -0,0 +1,270 @@
+//! # Serialization Operations
+//!
+//! This module implements the builtins which convert values to and from
+//! buffers of cells, using the flattened layouts of their types:
+//! - `serialize(value)`: the cells of a value, as a `[Cell; N]`.
+//! - `deserialize<T>(buffer)`: the value of type `T` stored in a `[Cell; N]`.
+//!
+//! `N` is the serialized size of the type. The serialized form doesn't include
+//! padding, and unpacks bitfields into a cell for each field, so it's the same
+//! regardless of how the compiler lays out the type. This makes it suitable
+//! for passing values through FFI, files, or shared memory.
+use super::*;
+use crate::asm::{CoreOp, A, B, SP};
+use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
+
+/// The type of the buffer that a value of a type is serialized to.
+fn buffer_type(ty: &Type, env: &Env) -> Result<Type, Error> {
+    Ok(Type::Array(
+        Box::new(Type::Cell),
+        Box::new(ConstExpr::Int(ty.get_serialized_size(env)? as i64)),
+    ))
+}
+
+/// Serialize a value of a type to a buffer of cells.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct ToCells;
+
+impl UnaryOp for ToCells {
+    /// Any type with a layout can be serialized.
+    fn can_apply(&self, ty: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(ty.get_segments(env).is_ok())
+    }
+
+    /// The result is an array with a cell for each cell of the serialized value.
+    fn return_type(&self, expr: &Expr, env: &Env) -> Result<Type, Error> {
+        buffer_type(&expr.get_type(env)?, env)
+    }
+
+    /// Serialization is only done at runtime.
+    fn eval(&self, expr: &ConstExpr, _env: &mut Env) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(expr.clone()))
+    }
+
+    /// Replace the value on the stack with its serialized cells.
+    fn compile_types(
+        &self,
+        ty: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let size = ty.get_size(env)?;
+        let len = ty.get_serialized_size(env)?;
+        // Build the serialized cells above the value.
+        output.op(CoreOp::Next(SP, Some(len as isize)));
+        let value = SP.deref().offset(1 - (size + len) as isize);
+        let buffer = SP.deref().offset(1 - len as isize);
+
+        let mut i = 0;
+        for segment in ty.get_segments(env)? {
+            let dst = buffer.offset(i as isize);
+            match segment {
+                Segment::Cells { offset, size } => output.op(CoreOp::Copy {
+                    src: value.offset(offset as isize),
+                    dst,
+                    size,
+                }),
+                Segment::Bits {
+                    offset,
+                    shift,
+                    bits,
+                } => {
+                    // Shift the field's bits down, and mask off the other fields.
+                    output.op(CoreOp::Move {
+                        src: value.offset(offset as isize),
+                        dst: A,
+                    });
+                    output.op(CoreOp::Set(B, shift as i64));
+                    output.op(CoreOp::LogicalRightShift { src: B, dst: A });
+                    output.op(CoreOp::Set(B, bitfield_mask(bits)));
+                    output.op(CoreOp::BitwiseAnd { src: B, dst: A });
+                    output.op(CoreOp::Move { src: A, dst });
+                }
+            }
+            i += segment.serialized_size();
+        }
+
+        // Move the serialized cells down over the value.
+        if len > 0 {
+            output.op(CoreOp::Copy {
+                src: buffer,
+                dst: value,
+                size: len,
+            });
+        }
+        output.op(CoreOp::Pop(None, size));
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn UnaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, expr: &Expr) -> String {
+        format!("{self}({expr})")
+    }
+}
+
+impl Debug for ToCells {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for ToCells {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "serialize")
+    }
+}
+
+/// Deserialize a value of a type from a buffer of cells.
+///
+/// The second operand is the type to deserialize, as a constant type expression.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct FromCells;
+
+impl FromCells {
+    /// Get the type to deserialize from the type of the second operand.
+    fn target(ty: &Type, env: &Env) -> Option<Type> {
+        match ty.clone().simplify(env).ok()? {
+            Type::Type(ty) => Some(*ty),
+            _ => None,
+        }
+    }
+}
+
+impl BinaryOp for FromCells {
+    /// The buffer must have exactly as many cells as the serialized type.
+    fn can_apply(&self, buffer: &Type, ty: &Type, env: &Env) -> Result<bool, Error> {
+        match Self::target(ty, env) {
+            Some(ty) => buffer.equals(&buffer_type(&ty, env)?, env),
+            None => Ok(false),
+        }
+    }
+
+    /// The result is a value of the deserialized type.
+    fn return_type(&self, buffer: &Expr, ty: &Expr, env: &Env) -> Result<Type, Error> {
+        if !self.can_apply_exprs(buffer, ty, env)? {
+            return Err(Error::InvalidBinaryOp(
+                self.clone_box(),
+                buffer.clone(),
+                ty.clone(),
+            ));
+        }
+        Self::target(&ty.get_type(env)?, env)
+            .ok_or_else(|| Error::InvalidBinaryOp(self.clone_box(), buffer.clone(), ty.clone()))
+    }
+
+    /// Deserialization is only done at runtime.
+    fn eval(
+        &self,
+        buffer: &ConstExpr,
+        _ty: &ConstExpr,
+        _env: &mut Env,
+    ) -> Result<ConstExpr, Error> {
+        Err(Error::InvalidConstExpr(buffer.clone()))
+    }
+
+    /// Only the buffer is pushed onto the stack: the type is known at compile time.
+    fn compile(
+        &self,
+        buffer: &Expr,
+        ty: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        buffer.clone().compile_expr(env, output)?;
+        self.compile_types(&buffer.get_type(env)?, &ty.get_type(env)?, env, output)
+    }
+
+    /// Replace the buffer on the stack with the value it holds.
+    fn compile_types(
+        &self,
+        _buffer: &Type,
+        ty: &Type,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let ty = Self::target(ty, env)
+            .ok_or_else(|| Error::InvalidConstExpr(ConstExpr::Type(ty.clone())))?;
+        let size = ty.get_size(env)?;
+        let len = ty.get_serialized_size(env)?;
+        let segments = ty.get_segments(env)?;
+        // Build the value above the buffer.
+        output.op(CoreOp::Next(SP, Some(size as isize)));
+        let buffer = SP.deref().offset(1 - (len + size) as isize);
+        let value = SP.deref().offset(1 - size as isize);
+
+        // The fields of a bitfield are combined into their cells, so clear them first.
+        for segment in &segments {
+            if let Segment::Bits { offset, .. } = segment {
+                output.op(CoreOp::Set(value.offset(*offset as isize), 0));
+            }
+        }
+
+        let mut i = 0;
+        for segment in segments {
+            let src = buffer.offset(i as isize);
+            match segment {
+                Segment::Cells { offset, size } => output.op(CoreOp::Copy {
+                    src,
+                    dst: value.offset(offset as isize),
+                    size,
+                }),
+                Segment::Bits {
+                    offset,
+                    shift,
+                    bits,
+                } => {
+                    // Truncate the field to its width, and shift it into position.
+                    output.op(CoreOp::Move { src, dst: A });
+                    output.op(CoreOp::Set(B, bitfield_mask(bits)));
+                    output.op(CoreOp::BitwiseAnd { src: B, dst: A });
+                    output.op(CoreOp::Set(B, shift as i64));
+                    output.op(CoreOp::LeftShift { src: B, dst: A });
+                    output.op(CoreOp::BitwiseOr {
+                        src: A,
+                        dst: value.offset(offset as isize),
+                    });
+                }
+            }
+            i += segment.serialized_size();
+        }
+
+        // Move the value down over the buffer.
+        if size > 0 {
+            output.op(CoreOp::Copy {
+                src: value,
+                dst: buffer,
+                size,
+            });
+        }
+        output.op(CoreOp::Pop(None, len));
+        Ok(())
+    }
+
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    /// Format the operation like a call.
+    fn display(&self, buffer: &Expr, ty: &Expr) -> String {
+        match ty {
+            Expr::ConstExpr(ConstExpr::Type(ty)) => format!("{self}<{ty}>({buffer})"),
+            _ => format!("{self}({buffer}, {ty})"),
+        }
+    }
+}
+
+impl Debug for FromCells {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for FromCells {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "deserialize")
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 
 use super::*;
-use crate::asm::{CoreOp, SP};
+use crate::asm::{CoreOp, A, B, C, SP};
 use ::core::fmt::{Debug, Display, Formatter, Result as FmtResult};
 
 /// Get the Enum value of the tag associated with a tagged union (EnumUnion).
@@ -20,9 +20,9 @@ impl UnaryOp for Tag {
         let ty = expr.get_type(env)?.simplify_until_has_variants(env, false)?;
 
         match ty {
-            Type::EnumUnion(variants) => Ok(Type::Enum(variants.into_keys().collect())),
+            Type::EnumUnion(variants, repr) => Ok(Type::Enum(variants.into_keys().collect(), repr)),
             found => Err(Error::MismatchedTypes {
-                expected: Type::EnumUnion(BTreeMap::new()),
+                expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                 found,
                 expr: expr.clone(),
             }),
@@ -34,21 +34,21 @@ impl UnaryOp for Tag {
         let expr = expr.clone().eval(env)?;
         match expr.clone() {
             ConstExpr::EnumUnion(t, variant, _) => {
-                if let Type::EnumUnion(variants) = t.clone().simplify(env)? {
+                if let Type::EnumUnion(variants, repr) = t.clone().simplify(env)? {
                     Ok(ConstExpr::Of(
-                        Type::Enum(variants.into_keys().collect()),
+                        Type::Enum(variants.into_keys().collect(), repr),
                         variant,
                     ))
                 } else {
                     Err(Error::MismatchedTypes {
-                        expected: Type::EnumUnion(BTreeMap::new()),
+                        expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                         found: t,
                         expr: Expr::ConstExpr(expr),
                     })
                 }
             }
             found => Err(Error::MismatchedTypes {
-                expected: Type::EnumUnion(BTreeMap::new()),
+                expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                 found: found.get_type(env)?,
                 expr: Expr::ConstExpr(expr),
             }),
@@ -62,17 +62,20 @@ impl UnaryOp for Tag {
         env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
-        // Get the size of the type.
+        // Get the size of the type, and the size of its tag.
         let size = ty.get_size(env)?;
+        let tag_cells = get_tag_cells(ty, env)?;
 
-        if size > 1 {
-            // Copy the tag to a temp register
+        if size > tag_cells {
+            // Move the tag, at the end of the value, to the start of the value.
             let cur = output.current_instruction();
-            output.op(CoreOp::Move {
-                src: SP.deref(),
-                dst: SP.deref().offset(1 - size as isize),
-            });
-            output.op(CoreOp::Pop(None, size - 1));
+            for i in 0..tag_cells {
+                output.op(CoreOp::Move {
+                    src: SP.deref().offset(i as isize + 1 - tag_cells as isize),
+                    dst: SP.deref().offset(i as isize + 1 - size as isize),
+                });
+            }
+            output.op(CoreOp::Pop(None, size - tag_cells));
             output.log_instructions_after("tag", &format!("for {ty}"), cur);
         }
 
@@ -113,9 +116,9 @@ impl UnaryOp for Data {
         let ty = expr.get_type(env)?.simplify_until_has_variants(env, false)?;
 
         match ty {
-            Type::EnumUnion(variants) => Ok(Type::Union(variants)),
+            Type::EnumUnion(variants, _) => Ok(Type::Union(variants)),
             found => Err(Error::MismatchedTypes {
-                expected: Type::EnumUnion(BTreeMap::new()),
+                expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                 found,
                 expr: expr.clone(),
             }),
@@ -127,18 +130,18 @@ impl UnaryOp for Data {
         let expr = expr.clone().eval(env)?;
         match expr.clone() {
             ConstExpr::EnumUnion(t, variant, val) => {
-                if let Type::EnumUnion(variants) = t {
+                if let Type::EnumUnion(variants, _) = t {
                     ConstExpr::Union(Type::Union(variants), variant, val).eval(env)
                 } else {
                     Err(Error::MismatchedTypes {
-                        expected: Type::EnumUnion(BTreeMap::new()),
+                        expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                         found: t.clone(),
                         expr: Expr::ConstExpr(expr),
                     })
                 }
             }
             found => Err(Error::MismatchedTypes {
-                expected: Type::EnumUnion(BTreeMap::new()),
+                expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
                 found: found.get_type(env)?,
                 expr: Expr::ConstExpr(expr),
             }),
@@ -148,12 +151,12 @@ impl UnaryOp for Data {
     /// Compile the unary operation.
     fn compile_types(
         &self,
-        _ty: &Type,
-        _env: &mut Env,
+        ty: &Type,
+        env: &mut Env,
         output: &mut dyn AssemblyProgram,
     ) -> Result<(), Error> {
         // Remove the tag.
-        output.op(CoreOp::Pop(None, 1));
+        output.op(CoreOp::Pop(None, get_tag_cells(ty, env)?));
 
         Ok(())
     }
@@ -175,3 +178,280 @@ impl Display for Data {
         write!(f, "data")
     }
 }
+
+/// Get the number of cells taken up by the tag of a tagged union.
+fn get_tag_cells(ty: &Type, env: &Env) -> Result<usize, Error> {
+    match ty.simplify_until_has_variants(env, false)? {
+        Type::EnumUnion(_, repr) => Ok(repr.tag_cells),
+        found => Err(Error::MismatchedTypes {
+            expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
+            found,
+            expr: Expr::ConstExpr(ConstExpr::None),
+        }),
+    }
+}
+
+/// Get the name of the variant from the right hand side of a variant check.
+fn get_variant_name(name: &Expr, env: &Env) -> Result<String, Error> {
+    match name {
+        Expr::Annotated(name, metadata) => {
+            get_variant_name(name, env).map_err(|err| err.annotate(metadata.clone()))
+        }
+        Expr::ConstExpr(cexpr) => match cexpr.clone().eval(env)? {
+            ConstExpr::Array(chars) => chars
+                .into_iter()
+                .map(|ch| ch.as_char(env))
+                .collect::<Result<String, _>>(),
+            _ => Err(Error::UnsupportedOperation(name.clone())),
+        },
+        _ => Err(Error::UnsupportedOperation(name.clone())),
+    }
+}
+
+/// Get the variants of a tagged union, along with the tag and type of the named variant.
+fn get_variant(
+    expr: &Expr,
+    name: &Expr,
+    env: &Env,
+) -> Result<(BTreeMap<String, Type>, i64, Type), Error> {
+    let ty = expr.get_type(env)?;
+    let name = get_variant_name(name, env)?;
+    match ty.simplify_until_has_variants(env, false)? {
+        Type::EnumUnion(variants, repr) => {
+            let names = variants.keys().cloned().collect::<Vec<_>>();
+            match (repr.tag_of(&names, &name), variants.get(&name)) {
+                (Some(tag), Some(variant_ty)) => {
+                    let variant_ty = variant_ty.clone();
+                    Ok((variants, tag, variant_ty))
+                }
+                _ => Err(Error::VariantNotFound(ty, name)),
+            }
+        }
+        found => Err(Error::MismatchedTypes {
+            expected: Type::EnumUnion(BTreeMap::new(), EnumRepr::default()),
+            found,
+            expr: expr.clone(),
+        }),
+    }
+}
+
+/// Push whether the tag of a tagged union on the top of the stack is the given tag,
+/// popping the tagged union.
+fn compile_tag_check(size: usize, tag: i64, output: &mut dyn AssemblyProgram) {
+    // The tag is stored in the last cell of the value.
+    output.op(CoreOp::Move {
+        src: SP.deref(),
+        dst: A,
+    });
+    output.op(CoreOp::Pop(None, size));
+    output.op(CoreOp::Set(B, tag));
+    output.op(CoreOp::IsEqual { a: A, b: B, dst: C });
+    output.op(CoreOp::Push(C, 1));
+}
+
+/// Check whether a tagged union (EnumUnion) holds the given variant.
+///
+/// This is written `is_variant(expr, "Name")`, and compiles to a comparison
+/// of the tagged union's tag against the variant's tag.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct IsVariant;
+
+impl BinaryOp for IsVariant {
+    /// Typechecks the tagged union, and confirms that it has the variant.
+    fn type_check(&self, expr: &Expr, name: &Expr, env: &Env) -> Result<(), Error> {
+        expr.type_check(env)?;
+        get_variant(expr, name, env)?;
+        Ok(())
+    }
+
+    /// Checking a variant always returns a `Bool`.
+    fn return_type(&self, _expr: &Expr, _name: &Expr, _env: &Env) -> Result<Type, Error> {
+        Ok(Type::Bool)
+    }
+
+    /// The left hand side must be a tagged union.
+    fn can_apply(&self, expr: &Type, _name: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(matches!(
+            expr.simplify_until_has_variants(env, false),
+            Ok(Type::EnumUnion(..))
+        ))
+    }
+
+    /// Evaluate the variant check on a constant tagged union.
+    fn eval(&self, expr: &ConstExpr, name: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        let name = get_variant_name(&Expr::ConstExpr(name.clone()), env)?;
+        match expr.clone().eval(env)? {
+            ConstExpr::EnumUnion(_, variant, _) => Ok(ConstExpr::Bool(variant == name)),
+            found => Err(Error::InvalidConstExpr(found)),
+        }
+    }
+
+    /// Compile the variant check.
+    ///
+    /// Only the tagged union is pushed onto the stack: the variant's
+    /// tag is known at compile time.
+    fn compile(
+        &self,
+        expr: &Expr,
+        name: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let (_, tag, _) = get_variant(expr, name, env)?;
+        let size = expr.get_size(env)?;
+        expr.clone().compile_expr(env, output)?;
+        compile_tag_check(size, tag, output);
+        Ok(())
+    }
+
+    /// The variant is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _expr: &Type,
+        _name: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, expr: &Expr, name: &Expr) -> String {
+        format!("{self}({expr}, {name})")
+    }
+}
+
+impl Debug for IsVariant {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "is_variant")
+    }
+}
+
+impl Display for IsVariant {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "is_variant")
+    }
+}
+
+/// Get the value of the given variant from a tagged union (EnumUnion).
+///
+/// This is written `expect_variant(expr, "Name")`. If the tagged union holds
+/// a different variant at runtime, the program panics.
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+pub struct ExpectVariant;
+
+impl BinaryOp for ExpectVariant {
+    /// Typechecks the tagged union, and confirms that it has the variant.
+    fn type_check(&self, expr: &Expr, name: &Expr, env: &Env) -> Result<(), Error> {
+        expr.type_check(env)?;
+        get_variant(expr, name, env)?;
+        Ok(())
+    }
+
+    /// The result is the value stored in the variant.
+    fn return_type(&self, expr: &Expr, name: &Expr, env: &Env) -> Result<Type, Error> {
+        let (_, _, ty) = get_variant(expr, name, env)?;
+        Ok(ty)
+    }
+
+    /// The left hand side must be a tagged union.
+    fn can_apply(&self, expr: &Type, _name: &Type, env: &Env) -> Result<bool, Error> {
+        Ok(matches!(
+            expr.simplify_until_has_variants(env, false),
+            Ok(Type::EnumUnion(..))
+        ))
+    }
+
+    /// Evaluate the variant access on a constant tagged union.
+    fn eval(&self, expr: &ConstExpr, name: &ConstExpr, env: &mut Env) -> Result<ConstExpr, Error> {
+        let name = get_variant_name(&Expr::ConstExpr(name.clone()), env)?;
+        match expr.clone().eval(env)? {
+            ConstExpr::EnumUnion(_, variant, val) if variant == name => Ok(*val),
+            ConstExpr::EnumUnion(t, _, _) => Err(Error::VariantNotFound(t, name)),
+            found => Err(Error::InvalidConstExpr(found)),
+        }
+    }
+
+    /// Compile the variant access.
+    fn compile(
+        &self,
+        expr: &Expr,
+        name: &Expr,
+        env: &mut Env,
+        output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        let (_, tag, ty) = get_variant(expr, name, env)?;
+        let size = expr.get_size(env)?;
+        let variant_size = ty.get_size(env)?;
+        let variant_name = get_variant_name(name, env)?;
+        expr.clone().compile_expr(env, output)?;
+
+        // Check the tag against the variant's tag.
+        output.op(CoreOp::Set(B, tag));
+        output.op(CoreOp::IsNotEqual {
+            a: SP.deref(),
+            b: B,
+            dst: A,
+        });
+        output.op(CoreOp::If(A));
+        // If the tag doesn't match, panic.
+        let message = format!("expected variant {variant_name} of {}", expr.get_type(env)?);
+        compile_trap(&message, env, output);
+        output.op(CoreOp::End);
+
+        // Pop the tag and the unused space in the union, leaving the variant's value.
+        output.op(CoreOp::Pop(None, size - variant_size));
+        Ok(())
+    }
+
+    /// The variant is not known from its type alone, so this
+    /// operation must be compiled with `compile` instead.
+    fn compile_types(
+        &self,
+        _expr: &Type,
+        _name: &Type,
+        _env: &mut Env,
+        _output: &mut dyn AssemblyProgram,
+    ) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation(Expr::BinaryOp(
+            self.name(),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+            Box::new(Expr::ConstExpr(ConstExpr::None)),
+        )))
+    }
+
+    /// Expecting the wrong variant panics, which is I/O.
+    fn effects(&self) -> Effects {
+        Effects::IO
+    }
+
+    /// Clone this operation into a box.
+    fn clone_box(&self) -> Box<dyn BinaryOp> {
+        Box::new(*self)
+    }
+
+    fn display(&self, expr: &Expr, name: &Expr) -> String {
+        format!("{self}({expr}, {name})")
+    }
+}
+
+impl Debug for ExpectVariant {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "expect_variant")
+    }
+}
+
+impl Display for ExpectVariant {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "expect_variant")
+    }
+}
@@ -136,7 +136,7 @@ impl Pattern {
             }
 
             // Confirm all the variants of a tagged enum are matched.
-            Type::EnumUnion(variants) => {
+            Type::EnumUnion(variants, _) => {
                 let mut found = vec![false; variants.len()];
                 for pattern in patterns {
                     match pattern {
@@ -186,7 +186,7 @@ impl Pattern {
             }
 
             // Confirm all the variants of an enum are matched.
-            Type::Enum(items) => {
+            Type::Enum(items, _) => {
                 // Create a vector of booleans, one for each variant.
                 let mut found = vec![false; items.len()];
                 // Iterate over the patterns.
@@ -491,6 +491,31 @@ impl Pattern {
         ))
     }
 
+    /// Generate a `while let` loop, which evaluates `body` with the pattern's bindings
+    /// each time `expr` matches the pattern, and stops the first time it doesn't.
+    pub fn while_let_pattern(&self, expr: &Expr, body: &Expr, env: &Env) -> Result<Expr, Error> {
+        // A flag which is cleared when the expression stops matching the pattern.
+        let flag_name = expr.to_string() + "__WHILE_LET";
+        let flag = Expr::var(&flag_name);
+        // Each iteration is an `if let`, which either runs the body or clears the flag.
+        let iteration = self.if_let_pattern(
+            expr,
+            &Expr::Many(vec![body.clone(), Expr::NONE]),
+            &flag
+                .clone()
+                .refer(Mutability::Mutable)
+                .deref_mut(ConstExpr::Bool(false)),
+            env,
+        )?;
+        Ok(Expr::let_var(
+            flag_name,
+            Mutability::Mutable,
+            Some(Type::Bool),
+            ConstExpr::Bool(true),
+            flag.while_loop(iteration),
+        ))
+    }
+
     /// Generate an expression which evaluates a `match` expression, which matches
     /// a given `expr` against a set of patterns and branches.
     pub fn match_pattern(expr: &Expr, branches: &[(Self, Expr)], env: &Env) -> Result<Expr, Error> {
@@ -565,6 +590,23 @@ impl Pattern {
             .collect())
     }
 
+    /// Get the names of the variables bound by this pattern, without checking their types.
+    pub fn get_binding_names(&self) -> Vec<(Mutability, String)> {
+        match self {
+            Self::Tuple(patterns) | Self::Alt(patterns) => patterns
+                .iter()
+                .flat_map(|pat| pat.get_binding_names())
+                .collect(),
+            Self::Struct(fields) => fields
+                .values()
+                .flat_map(|pat| pat.get_binding_names())
+                .collect(),
+            Self::Variant(_, Some(pat)) | Self::Pointer(pat) => pat.get_binding_names(),
+            Self::Symbol(mutability, name) => vec![(*mutability, name.clone())],
+            Self::Variant(_, None) | Self::ConstExpr(_) | Self::Wildcard => vec![],
+        }
+    }
+
     /// Get the map of new variables, their types which are bound by this pattern, and their offsets in the expression.
     fn get_bindings_with_offset(
         &self,
@@ -601,7 +643,7 @@ impl Pattern {
 
             // If the pattern is a variant, and the type is a EnumUnion,
             // get the bindings for the variant.
-            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants)) => {
+            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants, _)) => {
                 // If the variant is not found in the type, throw an error
                 if !variants.contains_key(name) {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
@@ -661,8 +703,8 @@ impl Pattern {
             }
 
             // If the pattern is a wildcard, then return an empty map (no bindings).
-            (Self::Variant(_, None), Type::Enum(_))
-            | (Self::Variant(_, None), Type::EnumUnion(_))
+            (Self::Variant(_, None), Type::Enum(..))
+            | (Self::Variant(_, None), Type::EnumUnion(..))
             | (Self::Wildcard, _)
             | (Self::ConstExpr(_), _) => HashMap::new(),
 
@@ -726,7 +768,7 @@ impl Pattern {
         Ok(match (self, ty) {
             // If the pattern is a variant, and the type is a EnumUnion,
             // check if the variant matches the pattern.
-            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants)) => {
+            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants, repr)) => {
                 // If the variant is not found in the type, throw an error
                 if !variants.contains_key(name) {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
@@ -736,7 +778,7 @@ impl Pattern {
                 expr.clone()
                     .unop(super::ops::Tag)
                     .eq(ConstExpr::Of(
-                        Type::Enum(variants.clone().into_keys().collect()),
+                        Type::Enum(variants.clone().into_keys().collect(), repr.clone()),
                         name.clone(),
                     ))
                     .and(
@@ -751,7 +793,7 @@ impl Pattern {
                     )
             }
 
-            (Self::Variant(name, None), Type::EnumUnion(variants)) => {
+            (Self::Variant(name, None), Type::EnumUnion(variants, repr)) => {
                 // If the variant is not found in the type, throw an error
                 if !variants.contains_key(name) {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
@@ -759,12 +801,12 @@ impl Pattern {
                 // If no error was thrown, the variant is an option which can be matched.
                 // Now, check if the tag matches the variant.
                 expr.clone().unop(super::ops::Tag).eq(ConstExpr::Of(
-                    Type::Enum(variants.clone().into_keys().collect()),
+                    Type::Enum(variants.clone().into_keys().collect(), repr.clone()),
                     name.clone(),
                 ))
             }
 
-            (Self::Variant(name, None), Type::Enum(items)) => {
+            (Self::Variant(name, None), Type::Enum(items, _)) => {
                 // If the variant is not found in the type, throw an error
                 if !items.contains(name) {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
@@ -912,7 +954,7 @@ impl Pattern {
         Ok(match (self, ty) {
             // If the pattern is a variant, and the type is a tagged union,
             // bind the pattern to the corresponding variant type in the tagged union.
-            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants)) => {
+            (Self::Variant(name, Some(pattern)), Type::EnumUnion(variants, _)) => {
                 // Get the inner variant type from the tagged union
                 if let Some(variant_ty) = variants.get(name) {
                     // Bind the inner expression of the tagged union
@@ -932,7 +974,7 @@ impl Pattern {
 
             // If the pattern is a variant, and the type is a tagged union,
             // but there is no pattern to bind, simply error check.
-            (Self::Variant(name, None), Type::EnumUnion(variants)) => {
+            (Self::Variant(name, None), Type::EnumUnion(variants, _)) => {
                 // Get the inner variant type from the tagged union
                 if variants.get(name).is_none() {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
@@ -942,7 +984,7 @@ impl Pattern {
 
             // If the pattern is a variant, and the type is an enum,
             // simply error check (there is no pattern to bind).
-            (Self::Variant(name, None), Type::Enum(items)) => {
+            (Self::Variant(name, None), Type::Enum(items, _)) => {
                 // Get the inner variant type from the tagged union
                 if !items.contains(name) {
                     return Err(Error::VariantNotFound(ty.clone(), name.clone()));
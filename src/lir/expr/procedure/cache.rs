@@ -0,0 +1,214 @@
+//! # Monomorph Cache
+//!
+//! An on-disk cache of the assembly code compiled for monomorphized procedures,
+//! so that unchanged monomorphs aren't recompiled across runs of the compiler.
+//!
+//! Each entry is keyed by a hash of the monomorph's mangled name (which includes
+//! its type arguments), its body, and a fingerprint of the environment it's
+//! compiled under: the constants, types, and associated constants defined in it,
+//! and the options (like the prelude and the program's arguments) it's compiled with.
+//!
+//! Only code that is safe to replay in a later run is cached:
+//! - It must only use core instructions.
+//! - It must not declare any global variables.
+//! - Every procedure it calls, but does not define itself, must be a monomorph,
+//!   since only those have the same label in every run. These must already be
+//!   defined when the cached code is replayed.
+//!
+//! The anonymous procedures defined inside of the cached code are given fresh
+//! labels when the code is replayed.
+
+use super::fresh_lambda_name;
+use crate::asm::{AssemblyProgram, CoreOp, StandardOp};
+use crate::lir::{Env, Expr};
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+
+/// The prefix of the labels of monomorphized procedures.
+/// These labels are the same in every run of the compiler.
+pub(super) const MONOMORPH_PREFIX: &str = "__MONOMORPHIZED_";
+
+/// A cached, compiled monomorph.
+#[derive(Serialize, Deserialize)]
+struct CachedMonomorph {
+    /// The labels of the procedures defined by the code.
+    defines: Vec<String>,
+    /// The labels used by the code which it does not define.
+    references: Vec<String>,
+    /// The compiled code.
+    code: Vec<CoreOp>,
+}
+
+/// An on-disk cache of compiled monomorphized procedures.
+#[derive(Clone, Debug)]
+pub struct MonomorphCache {
+    /// The directory the cache entries are stored in.
+    dir: PathBuf,
+}
+
+impl MonomorphCache {
+    /// Create a cache which stores its entries in the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Get the directory the cache entries are stored in.
+    pub fn get_dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Get the key for a monomorph compiled under the given environment.
+    pub(super) fn key(&self, mangled_name: &str, body: &Expr, env: &Env) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        mangled_name.hash(&mut hasher);
+        body.to_string().hash(&mut hasher);
+        env.fingerprint().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}.json"))
+    }
+
+    /// Replay the cached code for a monomorph into the output, if there is a
+    /// usable entry for the key. This returns whether the code was replayed.
+    pub(super) fn load(&self, key: u64, output: &mut dyn AssemblyProgram) -> bool {
+        let Ok(contents) = std::fs::read_to_string(self.path(key)) else {
+            return false;
+        };
+        let cached: CachedMonomorph = match serde_json::from_str(&contents) {
+            Ok(cached) => cached,
+            Err(e) => {
+                warn!("Ignoring corrupt monomorph cache entry {key:016x}: {e}");
+                return false;
+            }
+        };
+
+        // The procedures used by the code must already be compiled,
+        // and the monomorphs it defines must not be.
+        if !cached.references.iter().all(|label| output.is_defined(label))
+            || cached
+                .defines
+                .iter()
+                .any(|label| label.starts_with(MONOMORPH_PREFIX) && output.is_defined(label))
+        {
+            debug!(target: "mono", "Cached monomorph {key:016x} can't be used here");
+            return false;
+        }
+
+        // Give the anonymous procedures defined by the code fresh labels.
+        let renames = cached
+            .defines
+            .iter()
+            .filter(|label| !label.starts_with(MONOMORPH_PREFIX))
+            .map(|label| (label.clone(), fresh_lambda_name()))
+            .collect::<HashMap<_, _>>();
+
+        debug!(target: "mono", "Replaying cached monomorph {key:016x}");
+        for mut op in cached.code {
+            rename_labels(&mut op, &renames);
+            output.op(op);
+        }
+        true
+    }
+
+    /// Save the code compiled for a monomorph, starting at the given instruction,
+    /// if it is safe to replay in a later run.
+    pub(super) fn store(&self, key: u64, output: &dyn AssemblyProgram, start: usize) {
+        let mut code = vec![];
+        let mut i = start;
+        while let Some(op) = output.get_op(i) {
+            match op {
+                Ok(op) | Err(StandardOp::CoreOp(op)) => code.push(op),
+                Err(_) => {
+                    debug!(target: "mono", "Not caching monomorph {key:016x}: it uses standard instructions");
+                    return;
+                }
+            }
+            i += 1;
+        }
+
+        let mut defines = BTreeSet::new();
+        let mut references = BTreeSet::new();
+        for op in &code {
+            if !collect_labels(op, &mut defines, &mut references) {
+                debug!(target: "mono", "Not caching monomorph {key:016x}: it declares globals");
+                return;
+            }
+        }
+        let references = references
+            .difference(&defines)
+            .cloned()
+            .collect::<Vec<_>>();
+        if !references
+            .iter()
+            .all(|label| label.starts_with(MONOMORPH_PREFIX))
+        {
+            debug!(target: "mono", "Not caching monomorph {key:016x}: it calls procedures defined elsewhere");
+            return;
+        }
+
+        let cached = CachedMonomorph {
+            defines: defines.into_iter().collect(),
+            references,
+            code,
+        };
+        let result = std::fs::create_dir_all(&self.dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string(&cached).map_err(|e| e.to_string()))
+            .and_then(|json| std::fs::write(self.path(key), json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Couldn't write monomorph cache entry {key:016x}: {e}");
+        }
+    }
+}
+
+/// Collect the labels defined and used by an instruction.
+/// This returns false if the instruction can't be replayed in a later run.
+fn collect_labels(
+    op: &CoreOp,
+    defines: &mut BTreeSet<String>,
+    references: &mut BTreeSet<String>,
+) -> bool {
+    match op {
+        CoreOp::Fn(label) => {
+            defines.insert(label.clone());
+        }
+        CoreOp::SetLabel(_, label) | CoreOp::CallLabel(label) => {
+            references.insert(label.clone());
+        }
+        CoreOp::Many(ops) => {
+            return ops
+                .iter()
+                .all(|op| collect_labels(op, defines, references));
+        }
+        CoreOp::Global { .. } => return false,
+        _ => {}
+    }
+    true
+}
+
+/// Rename the labels used by an instruction.
+fn rename_labels(op: &mut CoreOp, renames: &HashMap<String, String>) {
+    match op {
+        CoreOp::Fn(label) | CoreOp::SetLabel(_, label) | CoreOp::CallLabel(label) => {
+            if let Some(new_label) = renames.get(label) {
+                *label = new_label.clone();
+            }
+        }
+        CoreOp::Many(ops) => {
+            for op in ops {
+                rename_labels(op, renames);
+            }
+        }
+        _ => {}
+    }
+}
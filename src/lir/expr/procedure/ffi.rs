@@ -4,9 +4,15 @@
 //! This is compiled down to a standard assembly `Call` instruction.
 //! The label is the name of the foreign function. The types determine the
 //! size of the cells for the arguments and return value.
+//!
+//! When a foreign function is used as a value instead of being called directly,
+//! it's wrapped in a procedure which calls it, so it can be passed around like any other procedure.
+use super::Procedure;
 use crate::asm::{AssemblyProgram, StandardOp};
-use crate::lir::{Compile, Env, Error, GetSize, GetType, Type, TypeCheck};
-use crate::side_effects::FFIBinding;
+use crate::lir::{
+    Compile, ConstExpr, Env, Error, Expr, GetSize, GetType, Mutability, Type, TypeCheck,
+};
+use crate::side_effects::{CSignature, CType, FFIBinding};
 use core::fmt::{Display, Formatter, Result as FmtResult};
 use serde_derive::{Deserialize, Serialize};
 
@@ -31,6 +37,35 @@ impl FFIProcedure {
     pub fn new(name: String, args: Vec<Type>, ret: Type) -> Self {
         Self { name, args, ret }
     }
+
+    /// Get the name of the foreign function.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the types of the arguments.
+    pub fn get_args(&self) -> &[Type] {
+        &self.args
+    }
+
+    /// Get the return type of the foreign function.
+    pub fn get_ret(&self) -> &Type {
+        &self.ret
+    }
+
+    /// Wrap the foreign function in a procedure which calls it with its arguments.
+    /// This lets the foreign function be stored in a variable or passed to another procedure.
+    pub fn as_procedure(&self) -> Procedure {
+        let args = self
+            .args
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (format!("arg{i}"), Mutability::Immutable, ty.clone()))
+            .collect::<Vec<_>>();
+        let body = Expr::ConstExpr(ConstExpr::FFIProcedure(self.clone()))
+            .app(args.iter().map(|(name, ..)| Expr::var(name)).collect());
+        Procedure::new(Some(self.name.clone()), args, self.ret.clone(), body)
+    }
 }
 
 impl TypeCheck for FFIProcedure {
@@ -64,14 +99,54 @@ impl Compile for FFIProcedure {
         }
         let ret_size = self.ret.get_size(env)?;
 
-        output.std_op(StandardOp::Call(FFIBinding::new(
-            self.name, args_size, ret_size,
-        )))?;
+        let mut binding = FFIBinding::new(self.name.clone(), args_size, ret_size);
+        if let Some(signature) = self.c_signature(env)? {
+            binding = binding.with_signature(signature);
+        }
+        output.std_op(StandardOp::Call(binding))?;
 
         Ok(())
     }
 }
 
+impl FFIProcedure {
+    /// The C signature of the foreign function, if every argument and the return value
+    /// have a C equivalent. Targets which link with C libraries call the function directly
+    /// with this signature.
+    fn c_signature(&self, env: &Env) -> Result<Option<CSignature>, Error> {
+        let mut args = vec![];
+        for arg in &self.args {
+            match c_type(arg, env)? {
+                Some(CType::Void) | None => return Ok(None),
+                Some(ty) => args.push(ty),
+            }
+        }
+        // C strings can't be returned, since they aren't strings of cells.
+        Ok(match c_type(&self.ret, env)? {
+            Some(CType::String) | None => None,
+            Some(ret) => Some(CSignature { args, ret }),
+        })
+    }
+}
+
+/// The C equivalent of an LIR type, if it has one.
+fn c_type(ty: &Type, env: &Env) -> Result<Option<CType>, Error> {
+    Ok(match ty.simplify_until_concrete(env, false)? {
+        Type::None => Some(CType::Void),
+        Type::Int | Type::Cell => Some(CType::Int),
+        Type::Float => Some(CType::Float),
+        Type::Char => Some(CType::Char),
+        Type::Bool => Some(CType::Bool),
+        Type::Pointer(Mutability::Immutable, inner)
+            if matches!(inner.simplify_until_concrete(env, false)?, Type::Char) =>
+        {
+            Some(CType::String)
+        }
+        Type::Pointer(..) => Some(CType::Pointer),
+        _ => None,
+    })
+}
+
 impl Display for FFIProcedure {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         write!(f, "{}(", self.name)?;
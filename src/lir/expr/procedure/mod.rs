@@ -16,11 +16,13 @@
 //! Polymorphic procedures take a list of type arguments, and produce a monomorphized
 //! version of the procedure. This can then be compiled directly to assembly.
 mod builtin;
+mod cache;
 mod ffi;
 mod mono;
 mod poly;
 
 pub use builtin::*;
+pub use cache::*;
 pub use ffi::*;
 pub use mono::*;
 pub use poly::*;
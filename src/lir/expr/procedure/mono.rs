@@ -8,10 +8,11 @@
 //! they are used.
 //!
 //! Procedures are created by the `proc` keyword.
+use super::MONOMORPH_PREFIX;
 use crate::asm::{AssemblyProgram, CoreOp, A, FP, SP};
 use crate::lir::{
-    Compile, ConstExpr, Declaration, Env, Error, Expr, GetSize, GetType, Mutability, Type,
-    TypeCheck,
+    compile_backtrace_pop, compile_backtrace_push, compile_return_value, Attribute, Compile,
+    ConstExpr, Declaration, Env, Error, Expr, GetSize, GetType, Mutability, Type, TypeCheck,
 };
 use core::fmt;
 use std::hash::Hash;
@@ -27,6 +28,13 @@ lazy_static! {
     static ref LAMBDA_COUNT: Mutex<usize> = Mutex::new(0);
 }
 
+/// Generate a new unique mangled name for a procedure.
+pub(super) fn fresh_lambda_name() -> String {
+    let mut lambda_count = LAMBDA_COUNT.lock().unwrap();
+    *lambda_count += 1;
+    format!("__LAMBDA_{lambda_count}")
+}
+
 /// A monomorphic procedure of LIR code which can be applied to a list of arguments.
 /// A procedure is compiled down to a label in the assembly code.
 /// The label is called with the `Call` instruction.
@@ -42,6 +50,9 @@ pub struct Procedure {
     ret: Type,
     /// The procedure's body expression
     body: Box<Expr>,
+    /// The attributes of the procedure, like `inline` or `no_mangle`.
+    #[serde(default)]
+    attributes: Vec<Attribute>,
 
     #[serde(skip)]
     has_type_checked: Arc<RwLock<bool>>,
@@ -54,6 +65,7 @@ impl PartialEq for Procedure {
             && self.args == other.args
             && self.ret == other.ret
             && self.body == other.body
+            && self.attributes == other.attributes
     }
 }
 
@@ -66,31 +78,67 @@ impl Procedure {
         ret: Type,
         body: impl Into<Expr>,
     ) -> Self {
-        let mut lambda_count = LAMBDA_COUNT.lock().unwrap();
-        *lambda_count += 1;
         Self {
             common_name,
-            mangled_name: format!("__LAMBDA_{lambda_count}"),
+            mangled_name: fresh_lambda_name(),
             args,
             ret,
             body: Box::new(body.into()),
+            attributes: vec![],
             has_type_checked: Arc::new(RwLock::new(false)),
         }
     }
 
     pub fn with(&self, decls: impl Into<Declaration>) -> Self {
-        let mut lambda_count = LAMBDA_COUNT.lock().unwrap();
-        *lambda_count += 1;
         Self {
             common_name: self.common_name.clone(),
-            mangled_name: format!("__LAMBDA_{lambda_count}"),
+            mangled_name: if self.has_attribute(&Attribute::NoMangle) {
+                self.mangled_name.clone()
+            } else {
+                fresh_lambda_name()
+            },
             args: self.args.clone(),
             ret: self.ret.clone(),
             body: Box::new(self.body.with(decls)),
+            attributes: self.attributes.clone(),
             has_type_checked: Arc::new(RwLock::new(false)),
         }
     }
 
+    /// Give a copy of the procedure its own mangled name, so that it is compiled
+    /// separately from the original. A `no_mangle` procedure keeps its name.
+    pub(crate) fn with_fresh_name(mut self) -> Self {
+        if !self.has_attribute(&Attribute::NoMangle) {
+            self.mangled_name = fresh_lambda_name();
+        }
+        self.has_type_checked = Arc::new(RwLock::new(false));
+        self
+    }
+
+    /// Add attributes to the procedure.
+    ///
+    /// A `no_mangle` procedure uses its common name as its label, so
+    /// it must be unique among all the procedures in the program.
+    pub fn with_attributes(mut self, attributes: impl IntoIterator<Item = Attribute>) -> Self {
+        self.attributes.extend(attributes);
+        if self.has_attribute(&Attribute::NoMangle) {
+            if let Some(name) = &self.common_name {
+                self.mangled_name = name.clone();
+            }
+        }
+        self
+    }
+
+    /// Get the attributes of the procedure.
+    pub fn get_attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    /// Does the procedure have a given attribute?
+    pub fn has_attribute(&self, attribute: &Attribute) -> bool {
+        self.attributes.contains(attribute)
+    }
+
     /// Get the arguments of the procedure.
     pub fn get_args(&self) -> &[(String, Mutability, Type)] {
         &self.args
@@ -106,6 +154,12 @@ impl Procedure {
         &self.body
     }
 
+    /// Get mutable references to the arguments, return type, and body of the procedure,
+    /// for passes which rewrite the procedure in place.
+    pub(crate) fn parts_mut(&mut self) -> (&mut Vec<(String, Mutability, Type)>, &mut Type, &mut Expr) {
+        (&mut self.args, &mut self.ret, &mut self.body)
+    }
+
     /// Get the mangled name of the procedure.
     /// The procedure's mangled name is used to store the procedure in the environment.
     pub fn get_mangled_name(&self) -> &str {
@@ -183,7 +237,17 @@ impl TypeCheck for Procedure {
                 "Typechecking body of procedure {} ({:?})",
                 self.mangled_name, self.common_name
             );
-            self.body.type_check(&new_env)
+            self.body.type_check_against(&self.ret, &new_env)?;
+
+            // Confirm that a procedure marked `pure` has no side effects.
+            if self.has_attribute(&Attribute::Pure) {
+                let effects = self.get_effects(&new_env);
+                if !effects.is_pure() {
+                    let name = self.common_name.as_ref().unwrap_or(&self.mangled_name);
+                    return Err(Error::ImpureProcedure(name.clone(), effects));
+                }
+            }
+            Ok(())
         }
     }
 }
@@ -226,25 +290,47 @@ impl Compile for Procedure {
             return Ok(());
         }
 
+        // If this is a monomorph which was compiled in a previous run,
+        // then reuse the cached code.
+        let cache = env
+            .get_monomorph_cache()
+            .filter(|_| self.mangled_name.starts_with(MONOMORPH_PREFIX));
+        let cache_key = cache.as_ref().map(|cache| cache.key(&self.mangled_name, &self.body, env));
+        if let (Some(cache), Some(key)) = (&cache, cache_key) {
+            if cache.load(key, output) {
+                return Ok(());
+            }
+        }
+        let start = output.current_instruction();
+
         // Declare the function body
         output.op(CoreOp::Fn(self.mangled_name.clone()));
         if let Some(common_name) = &self.common_name {
             output.comment(format!("{}({})", common_name, args_size));
         }
         let current_instruction = output.current_instruction();
+        // Record the call on the shadow stack, for backtraces.
+        if env.has_backtraces() {
+            let name = self.common_name.as_deref().unwrap_or(&self.mangled_name);
+            compile_backtrace_push(&self.mangled_name, name, output);
+        }
 
-        // Execute the body to leave the return value
-        self.body.compile_expr(&mut new_env, output)?;
+        // Execute the body to construct the return value over the arguments
+        compile_return_value(self.body, ret_size, &mut new_env, output)?;
 
-        // Overwrite the arguments with the return value
-        output.op(CoreOp::Copy {
-            dst: FP.deref().offset(1 - args_size as isize),
-            src: SP.deref().offset(1 - ret_size as isize),
-            size: ret_size,
+        // Make the stack pointer point to the end of the return value, popping
+        // any local variables along with the arguments.
+        output.op(CoreOp::GetAddress {
+            addr: FP.deref(),
+            dst: SP,
         });
-        // Decrement the stack pointer by the difference between the size of the
-        // arguments and return value, to leave the return value on the stack.
-        output.op(CoreOp::Pop(None, args_size));
+        output.op(CoreOp::Prev(
+            SP,
+            Some(args_size as isize - ret_size as isize),
+        ));
+        if env.has_backtraces() {
+            compile_backtrace_pop(output);
+        }
         // End the function body
         output.op(CoreOp::End);
 
@@ -263,6 +349,11 @@ impl Compile for Procedure {
         );
         output.log_instructions_after(name, &message, current_instruction);
 
+        // Save the compiled monomorph for later runs.
+        if let (Some(cache), Some(key)) = (&cache, cache_key) {
+            cache.store(key, output, start);
+        }
+
         Ok(())
     }
 }
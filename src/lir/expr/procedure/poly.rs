@@ -3,8 +3,14 @@
 //! A polymorphic procedure of LIR code which can be applied to a list of arguments with type arguments.
 //! This is mono-morphed into a `Procedure` when it is called with a list of type arguments.
 //! A procedure is compiled down to a label in the assembly code.
-use super::Procedure;
-use crate::lir::{ConstExpr, Declaration, Env, Error, Expr, GetType, Mutability, Type, TypeCheck};
+//!
+//! Procedures defined in the body of a polymorphic procedure can use its type parameters.
+//! They are copied into each monomorph, so every monomorph has its own version of them.
+use super::{Procedure, MONOMORPH_PREFIX};
+use crate::lir::{
+    fold_poly_procedure_children, fold_procedure_children, Attribute, ConstExpr, Declaration, Env,
+    Error, Expr, Fold, GetType, Mutability, Type, TypeCheck,
+};
 use std::{
     collections::HashMap,
     fmt,
@@ -30,9 +36,17 @@ pub struct PolyProcedure {
     ret: Type,
     /// The body of the procedure.
     body: Box<Expr>,
+    /// The attributes of the procedure, which are given to each of its monomorphs.
+    #[serde(default)]
+    attributes: Vec<Attribute>,
     /// The monomorphs of the procedure.
     #[serde(skip)]
     monomorphs: Arc<RwLock<HashMap<String, Procedure>>>,
+    /// The procedures which override the monomorphs for specific type arguments.
+    /// These are shared by every copy of the procedure, so that a specialization
+    /// declared anywhere in its scope is used for every call.
+    #[serde(skip)]
+    specializations: Arc<RwLock<Vec<(Vec<Type>, Procedure)>>>,
     #[serde(skip)]
     has_type_checked: Arc<RwLock<bool>>,
 }
@@ -44,6 +58,7 @@ impl PartialEq for PolyProcedure {
             && self.args == other.args
             && self.ret == other.ret
             && self.body == other.body
+            && self.attributes == other.attributes
     }
 }
 
@@ -63,11 +78,24 @@ impl PolyProcedure {
             args,
             ret,
             body: Box::new(body.into()),
+            attributes: vec![],
             monomorphs: Arc::new(RwLock::new(HashMap::new())),
+            specializations: Arc::new(RwLock::new(vec![])),
             has_type_checked: Arc::new(RwLock::new(false)),
         }
     }
 
+    /// Add attributes to the procedure.
+    pub fn with_attributes(mut self, attributes: impl IntoIterator<Item = Attribute>) -> Self {
+        self.attributes.extend(attributes);
+        self
+    }
+
+    /// Get the attributes of the procedure.
+    pub fn get_attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
     pub fn with(&self, decls: impl Into<Declaration>) -> Self {
         Self {
             body: Box::new(self.body.with(decls)),
@@ -94,7 +122,9 @@ impl PolyProcedure {
             args: mono.get_args().to_vec(),
             ret: mono.get_ret().clone(),
             body: mono.get_body().clone().into(),
+            attributes: mono.get_attributes().to_vec(),
             monomorphs: Arc::new(RwLock::new(HashMap::new())),
+            specializations: Arc::new(RwLock::new(vec![])),
             has_type_checked: Arc::new(RwLock::new(false)),
         }
     }
@@ -106,6 +136,148 @@ impl PolyProcedure {
         &self.name
     }
 
+    /// Get the arguments of the procedure.
+    pub fn get_args(&self) -> &[(String, Mutability, Type)] {
+        &self.args
+    }
+
+    /// Get the return type of the procedure.
+    pub fn get_ret(&self) -> &Type {
+        &self.ret
+    }
+
+    /// Get the body of the procedure.
+    pub fn get_body(&self) -> &Expr {
+        &self.body
+    }
+
+    /// Get mutable references to the type parameters, arguments, return type, and body
+    /// of the procedure, for passes which rewrite the procedure in place.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn parts_mut(
+        &mut self,
+    ) -> (
+        &mut Vec<(String, Option<Type>)>,
+        &mut Vec<(String, Mutability, Type)>,
+        &mut Type,
+        &mut Expr,
+    ) {
+        (&mut self.ty_params, &mut self.args, &mut self.ret, &mut self.body)
+    }
+
+    /// Make a copy of a procedure nested in the body of a monomorph, which doesn't share
+    /// its monomorphs with the original. The monomorphs of the copy are named after the
+    /// enclosing monomorph, so they get their own labels too.
+    fn nested_in(self, scope: &str) -> Self {
+        Self {
+            name: format!("{scope}::{}", self.name),
+            monomorphs: Arc::new(RwLock::new(HashMap::new())),
+            has_type_checked: Arc::new(RwLock::new(false)),
+            ..self
+        }
+    }
+
+    /// Use a procedure for the monomorph of this procedure with the given type arguments,
+    /// instead of the generic body. This replaces any previous specialization for
+    /// the same type arguments.
+    pub fn specialize(&self, ty_args: Vec<Type>, proc: Procedure, env: &Env) -> Result<(), Error> {
+        if ty_args.len() != self.ty_params.len() {
+            return Err(Error::InvalidSpecialization(
+                self.name.clone(),
+                format!(
+                    "expected {} type arguments, found {}",
+                    self.ty_params.len(),
+                    ty_args.len()
+                ),
+            ));
+        }
+
+        let mut specializations = self.specializations.write().unwrap();
+        for (specialized_ty_args, specialized) in specializations.iter_mut() {
+            if Self::matches(specialized_ty_args, &ty_args, env)? {
+                *specialized = proc;
+                return Ok(());
+            }
+        }
+        specializations.push((ty_args, proc));
+        Ok(())
+    }
+
+    /// Get the specialization of this procedure for some type arguments, if there is one.
+    fn get_specialization(&self, ty_args: &[Type], env: &Env) -> Result<Option<Procedure>, Error> {
+        for (specialized_ty_args, specialized) in self.specializations.read().unwrap().iter() {
+            if Self::matches(specialized_ty_args, ty_args, env)? {
+                return Ok(Some(specialized.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Are two lists of type arguments the same?
+    fn matches(a: &[Type], b: &[Type], env: &Env) -> Result<bool, Error> {
+        for (a, b) in a.iter().zip(b) {
+            if !a.equals(b, env)? {
+                return Ok(false);
+            }
+        }
+        Ok(a.len() == b.len())
+    }
+
+    /// Check that the type arguments supplied to this procedure match its type parameters:
+    /// there must be one for each parameter, constant parameters must be given constants
+    /// of the declared types, and type parameters must be given types.
+    pub fn check_ty_args(&self, ty_args: &[Type], env: &Env) -> Result<(), Error> {
+        // The monomorph as it's written at the call site, for reporting errors.
+        let call =
+            || Expr::ConstExpr(ConstExpr::Symbol(self.name.clone()).monomorphize(ty_args.to_vec()));
+        if ty_args.len() != self.ty_params.len() {
+            error!(
+                "Expected {} type arguments for {}, found {}",
+                self.ty_params.len(),
+                self.name,
+                ty_args.len()
+            );
+            return Err(Error::InvalidMonomorphize(
+                ConstExpr::PolyProc(self.clone()).monomorphize(ty_args.to_vec()),
+            ));
+        }
+
+        for ((param, bound), ty_arg) in self.ty_params.iter().zip(ty_args) {
+            match bound {
+                Some(expected) if ty_arg.is_const_param() => {
+                    let found = ty_arg
+                        .simplify_until_const_param(env, false)?
+                        .get_type(env)?;
+                    if !found.equals(expected, env)? {
+                        error!("Mismatch in expected type for constant parameter {param}");
+                        return Err(Error::MismatchedTypes {
+                            expected: expected.clone(),
+                            found,
+                            expr: call(),
+                        });
+                    }
+                }
+                Some(expected) => {
+                    error!("Expected a constant for parameter {param}, found type {ty_arg}");
+                    return Err(Error::MismatchedTypes {
+                        expected: expected.clone(),
+                        found: Type::Type(Box::new(ty_arg.clone())),
+                        expr: call(),
+                    });
+                }
+                None if matches!(ty_arg, Type::ConstParam(_)) => {
+                    error!("Expected a type for parameter {param}, found constant {ty_arg}");
+                    return Err(Error::UnexpectedConstParam {
+                        found: ty_arg.clone(),
+                        expr: call(),
+                    });
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
     fn type_param_names(&self) -> Vec<String> {
         self.ty_params.clone().into_iter().map(|(ty, _)| ty).collect()
     }
@@ -114,6 +286,11 @@ impl PolyProcedure {
     /// This monomorphized version can then be compiled directly. Additionally, the
     /// mono version of the procedure is memoized, so that it is only compiled once.
     pub fn monomorphize(&self, ty_args: Vec<Type>, env: &Env) -> Result<Procedure, Error> {
+        env.time_pass("monomorphize", || self.monomorphize_untimed(ty_args, env))
+    }
+
+    /// Monomorphize the procedure, without timing the pass.
+    fn monomorphize_untimed(&self, ty_args: Vec<Type>, env: &Env) -> Result<Procedure, Error> {
         debug!(target: "mono", "Monomorphizing {} with {:?}", self, ty_args);
 
         // This is a helper function to distribute the defined type
@@ -130,6 +307,9 @@ impl PolyProcedure {
                 Ok(concrete)
             })
             .collect::<Result<Vec<_>, Error>>()?;
+        // Check the type arguments against the type parameters before they're used,
+        // so mistakes are reported at the call instead of inside the monomorph.
+        self.check_ty_args(&simplified_ty_args, env)?;
 
         debug!(target: "mono", "Simplified type arguments: {:?}", simplified_ty_args);
         // This is a helper function to bind the type arguments to the type parameters.
@@ -156,8 +336,33 @@ impl PolyProcedure {
             .collect::<Result<Vec<_>, Error>>()?;
         debug!(target: "mono", "Distributed type arguments over the return type of the function {}", self.name);
         let ret = bind_type_args(self.ret.clone())?;
+
+        // Use the specialization for these type arguments, if there is one.
+        if let Some(specialized) = self.get_specialization(&simplified_ty_args, env)? {
+            debug!(target: "mono", "Using specialization {} of {}", specialized, self.name);
+            let expected = Type::Proc(
+                args.iter().map(|(_, _, t)| t.clone()).collect(),
+                Box::new(ret),
+            );
+            let found = Type::Proc(
+                specialized
+                    .get_args()
+                    .iter()
+                    .map(|(_, _, t)| t.clone())
+                    .collect(),
+                Box::new(specialized.get_ret().clone()),
+            );
+            if !found.equals(&expected, env)? {
+                return Err(Error::MismatchedTypes {
+                    expected,
+                    found,
+                    expr: ConstExpr::Proc(specialized).into(),
+                });
+            }
+            return Ok(specialized);
+        }
         // Generate a mangled name for the monomorphized procedure.
-        let mangled_name = format!("__MONOMORPHIZED_({ty_args:?}){}{args:?}{ret:?}", self.name);
+        let mangled_name = format!("{MONOMORPH_PREFIX}({ty_args:?}){}{args:?}{ret:?}", self.name);
         // Check if the procedure has already been memoized.
         debug!(target: "mono", "Checking if monomorphized procedure {} has already been memoized", mangled_name);
         let monomorphs = self.monomorphs.read().unwrap();
@@ -176,6 +381,9 @@ impl PolyProcedure {
 
         // Substitute the type arguments into the body of the function.
         body.substitute_types(&self.type_param_names(), &simplified_ty_args);
+        // The procedures defined in the body can use the type parameters too,
+        // so each monomorph gets its own copies of them.
+        body = NestedProcedures(&mangled_name).fold_expr(body);
 
         // Wrap the body in a let expression to bind the type arguments.
         body = body.with(
@@ -186,7 +394,13 @@ impl PolyProcedure {
                 .collect::<Vec<_>>(),
         );
 
-        let monomorph = Procedure::new(Some(mangled_name.clone()), args, ret, body);
+        // Every monomorph needs its own label, so they can't keep the `no_mangle` attribute.
+        let monomorph = Procedure::new(Some(mangled_name.clone()), args, ret, body).with_attributes(
+            self.attributes
+                .iter()
+                .filter(|attribute| **attribute != Attribute::NoMangle)
+                .cloned(),
+        );
 
         // If the monomorphized procedure has already been memoized, return it, otherwise memoize it.
         debug!(target: "mono", "Inserting entry for {}", mangled_name);
@@ -201,6 +415,20 @@ impl PolyProcedure {
     }
 }
 
+/// A fold which copies the procedures nested in the body of a monomorph,
+/// so that they are monomorphized along with the procedure around them.
+struct NestedProcedures<'a>(&'a str);
+
+impl Fold for NestedProcedures<'_> {
+    fn fold_procedure(&mut self, proc: Procedure) -> Procedure {
+        fold_procedure_children(self, proc).with_fresh_name()
+    }
+
+    fn fold_poly_procedure(&mut self, proc: PolyProcedure) -> PolyProcedure {
+        fold_poly_procedure_children(self, proc).nested_in(self.0)
+    }
+}
+
 impl GetType for PolyProcedure {
     fn get_type_checked(&self, _env: &Env, _i: usize) -> Result<Type, Error> {
         Ok(Type::Poly(
@@ -321,5 +549,6 @@ impl Hash for PolyProcedure {
         self.args.hash(state);
         self.ret.hash(state);
         self.body.hash(state);
+        self.attributes.hash(state);
     }
 }
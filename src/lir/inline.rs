@@ -0,0 +1,107 @@
+//! # Inlining
+//!
+//! Calls to procedures with the `inline` attribute are replaced with the
+//! body of the procedure, with the arguments bound to its parameters.
+//! This saves the cost of the call, and lets the arguments live in the caller's frame.
+//!
+//! A call is only inlined when it means the same thing as the call would:
+//! - The procedure isn't also `cold`.
+//! - The body doesn't `return`, since that would return from the caller instead.
+//! - The body doesn't refer to a global which is hidden by a local variable at the call site.
+//! - The call isn't nested too deeply in other inlined bodies, so recursive procedures terminate.
+use super::{
+    walk_const, walk_expr, Attribute, ConstExpr, Declaration, Env, Expr, Mutability, PolyProcedure,
+    Procedure, Type, Visit,
+};
+use std::collections::HashSet;
+
+/// The maximum number of inlined bodies which can be nested inside each other.
+const MAX_INLINE_DEPTH: usize = 4;
+
+/// The name of the tuple which holds the arguments of an inlined call.
+/// All of the arguments are evaluated before any parameters are bound,
+/// so a parameter can't hide a variable used by a later argument.
+const INLINE_ARGS: &str = "__inline_args";
+
+/// Try to inline a call to a procedure with the given arguments.
+/// This returns `None` if the procedure shouldn't (or can't) be inlined here.
+pub(super) fn inline_call(proc: &Procedure, args: &[Expr], env: &Env) -> Option<Expr> {
+    if !proc.has_attribute(&Attribute::Inline)
+        || proc.has_attribute(&Attribute::Cold)
+        || env.get_inline_depth() >= MAX_INLINE_DEPTH
+        || proc.get_args().len() != args.len()
+    {
+        return None;
+    }
+
+    let mut body = BodyInfo::default();
+    body.visit_expr(proc.get_body());
+    if body.returns {
+        return None;
+    }
+    // Every symbol which isn't a parameter must mean the same thing
+    // at the call site as it does where the procedure was defined.
+    let params = proc
+        .get_args()
+        .iter()
+        .map(|(name, ..)| name.as_str())
+        .collect::<HashSet<_>>();
+    if body
+        .symbols
+        .iter()
+        .any(|name| !params.contains(name) && env.has_local_var(name))
+    {
+        return None;
+    }
+
+    let mut decls = vec![Declaration::Var(
+        INLINE_ARGS.to_string(),
+        Mutability::Immutable,
+        Some(Type::Tuple(
+            proc.get_args()
+                .iter()
+                .map(|(_, _, ty)| ty.clone())
+                .collect(),
+        )),
+        Expr::Tuple(args.to_vec()),
+    )];
+    for (i, (name, mutability, ty)) in proc.get_args().iter().enumerate() {
+        decls.push(Declaration::Var(
+            name.clone(),
+            *mutability,
+            Some(ty.clone()),
+            Expr::var(INLINE_ARGS).field(ConstExpr::Int(i as i64)),
+        ));
+    }
+    Some(proc.get_body().hard_with(Declaration::many(decls)))
+}
+
+/// The facts about a procedure body which decide whether it can be inlined.
+#[derive(Default)]
+struct BodyInfo<'a> {
+    /// Does the body contain a `return` expression?
+    returns: bool,
+    /// The symbols used in the body.
+    symbols: HashSet<&'a str>,
+}
+
+impl<'a> Visit<'a> for BodyInfo<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        if let Expr::Return(_) = expr {
+            self.returns = true;
+        }
+        walk_expr(self, expr)
+    }
+
+    fn visit_const(&mut self, expr: &'a ConstExpr) {
+        if let ConstExpr::Symbol(name) = expr {
+            self.symbols.insert(name);
+        }
+        walk_const(self, expr)
+    }
+
+    // Nested procedures are compiled in their own scope, so their bodies don't matter.
+    fn visit_procedure(&mut self, _proc: &'a Procedure) {}
+
+    fn visit_poly_procedure(&mut self, _proc: &'a PolyProcedure) {}
+}
@@ -0,0 +1,311 @@
+//! # Lints
+//!
+//! Lints are extra checks over LIR code which enforce rules that the type checker
+//! doesn't, like naming conventions or suspicious constructs. A lint is any type
+//! implementing the `Lint` trait, which has a callback for each kind of node in
+//! the LIR: expressions, constant expressions, types, patterns, and declarations.
+//!
+//! Lints are registered on the `Env` with `Env::add_lint`, and run over the whole
+//! program after it type checks. Every problem a lint finds is reported as a
+//! `Warning::Lint`, so lints can be allowed or denied by name like any other warning.
+//!
+//! ```text
+//! warning: dereference of null pointer
+//!  --> main.sg:2:5
+//!   |
+//! 2 |     *NULL
+//!   |     ^^^^^
+//!   = note: this is the `null-deref` lint
+//! ```
+use super::{
+    walk_const, walk_declaration, walk_expr, walk_pattern, walk_type, Annotation, ConstExpr,
+    Declaration, Env, Error, Expr, Pattern, Type, Visit, Warning,
+};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A check over LIR code which is run after the code type checks.
+///
+/// Each callback is called for every node of that kind in the program,
+/// including the nodes inside procedure bodies and types. Problems are
+/// reported with `LintContext::report`.
+pub trait Lint: Send + Sync + Debug {
+    /// The name of the lint, used to allow or deny it (such as `null-deref`).
+    fn name(&self) -> &str;
+
+    /// Check an expression.
+    fn check_expr(&self, _expr: &Expr, _cx: &mut LintContext) {}
+
+    /// Check a constant expression.
+    fn check_const(&self, _expr: &ConstExpr, _cx: &mut LintContext) {}
+
+    /// Check a type.
+    fn check_type(&self, _ty: &Type, _cx: &mut LintContext) {}
+
+    /// Check a pattern.
+    fn check_pattern(&self, _pat: &Pattern, _cx: &mut LintContext) {}
+
+    /// Check a declaration.
+    fn check_declaration(&self, _decl: &Declaration, _cx: &mut LintContext) {}
+}
+
+/// The context a lint checks a node under.
+pub struct LintContext<'a> {
+    /// The environment the program was type checked under.
+    env: &'a Env,
+    /// The name of the lint being run.
+    lint: &'a str,
+    /// The annotation of the closest annotated node enclosing the node being checked.
+    annotation: Option<&'a Annotation>,
+    /// The warnings reported so far.
+    warnings: &'a mut Vec<Warning>,
+}
+
+impl LintContext<'_> {
+    /// The top-level environment of the program being linted.
+    pub fn env(&self) -> &Env {
+        self.env
+    }
+
+    /// The annotation of the closest annotated node enclosing the node being checked.
+    pub fn annotation(&self) -> Option<&Annotation> {
+        self.annotation
+    }
+
+    /// Report a problem with the node being checked.
+    pub fn report(&mut self, message: impl ToString) {
+        let warning = Warning::Lint(self.lint.to_string(), message.to_string());
+        self.warnings.push(match self.annotation {
+            Some(annotation) => warning.annotate(annotation.clone()),
+            None => warning,
+        });
+    }
+}
+
+/// Walks over a program, running every lint on each node.
+struct LintPass<'a> {
+    env: &'a Env,
+    lints: &'a [Arc<dyn Lint>],
+    annotations: Vec<&'a Annotation>,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> LintPass<'a> {
+    fn new(env: &'a Env, lints: &'a [Arc<dyn Lint>]) -> Self {
+        Self {
+            env,
+            lints,
+            annotations: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Run each lint's callback on a node.
+    fn run(&mut self, check: impl Fn(&dyn Lint, &mut LintContext)) {
+        for lint in self.lints {
+            let mut cx = LintContext {
+                env: self.env,
+                lint: lint.name(),
+                annotation: self.annotations.last().copied(),
+                warnings: &mut self.warnings,
+            };
+            check(lint.as_ref(), &mut cx);
+        }
+    }
+
+    /// Report the warnings found by the lints to the environment.
+    fn finish(self) -> Result<(), Error> {
+        let env = self.env;
+        Error::collect(self.warnings.into_iter().map(|warning| env.warn(warning)))
+    }
+}
+
+impl<'a> Visit<'a> for LintPass<'a> {
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        self.run(|lint, cx| lint.check_expr(expr, cx));
+        if let Expr::Annotated(_, annotation) = expr {
+            self.annotations.push(annotation);
+            walk_expr(self, expr);
+            self.annotations.pop();
+        } else {
+            walk_expr(self, expr);
+        }
+    }
+
+    fn visit_const(&mut self, expr: &'a ConstExpr) {
+        self.run(|lint, cx| lint.check_const(expr, cx));
+        if let ConstExpr::Annotated(_, annotation) = expr {
+            self.annotations.push(annotation);
+            walk_const(self, expr);
+            self.annotations.pop();
+        } else {
+            walk_const(self, expr);
+        }
+    }
+
+    fn visit_type(&mut self, ty: &'a Type) {
+        self.run(|lint, cx| lint.check_type(ty, cx));
+        walk_type(self, ty);
+    }
+
+    fn visit_pattern(&mut self, pat: &'a Pattern) {
+        self.run(|lint, cx| lint.check_pattern(pat, cx));
+        walk_pattern(self, pat);
+    }
+
+    fn visit_declaration(&mut self, decl: &'a Declaration) {
+        self.run(|lint, cx| lint.check_declaration(decl, cx));
+        walk_declaration(self, decl);
+    }
+}
+
+/// Run the lints registered on the environment over an expression,
+/// and report the problems they find as warnings.
+pub(super) fn lint_expr(expr: &Expr, env: &Env) -> Result<(), Error> {
+    let lints = env.get_lints();
+    if lints.is_empty() {
+        return Ok(());
+    }
+    let mut pass = LintPass::new(env, &lints);
+    pass.visit_expr(expr);
+    pass.finish()
+}
+
+/// Run the lints registered on the environment over a constant expression,
+/// and report the problems they find as warnings.
+pub(super) fn lint_const(expr: &ConstExpr, env: &Env) -> Result<(), Error> {
+    let lints = env.get_lints();
+    if lints.is_empty() {
+        return Ok(());
+    }
+    let mut pass = LintPass::new(env, &lints);
+    pass.visit_const(expr);
+    pass.finish()
+}
+
+/// Get the constant value of an expression, if it's a literal.
+fn literal(mut expr: &Expr) -> Option<&ConstExpr> {
+    while let Expr::Annotated(inner, _) = expr {
+        expr = inner;
+    }
+    let Expr::ConstExpr(mut cexpr) = expr else {
+        return None;
+    };
+    while let ConstExpr::Annotated(inner, _) = cexpr {
+        cexpr = inner;
+    }
+    Some(cexpr)
+}
+
+/// Get a built-in lint by its name.
+pub fn builtin_lint(name: &str) -> Option<Arc<dyn Lint>> {
+    builtin_lints().into_iter().find(|lint| lint.name() == name)
+}
+
+/// Get every built-in lint. None of these are run unless they are added to the environment.
+pub fn builtin_lints() -> Vec<Arc<dyn Lint>> {
+    vec![
+        Arc::new(ConstantCondition),
+        Arc::new(NullDeref),
+        Arc::new(LargeStackArray::default()),
+        Arc::new(NonCamelCaseTypes),
+    ]
+}
+
+/// Warns about `if` expressions whose condition is a boolean literal,
+/// and `while` loops that never run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConstantCondition;
+
+impl Lint for ConstantCondition {
+    fn name(&self) -> &str {
+        "constant-condition"
+    }
+
+    fn check_expr(&self, expr: &Expr, cx: &mut LintContext) {
+        match expr {
+            Expr::If(cond, ..) => {
+                if let Some(ConstExpr::Bool(b)) = literal(cond) {
+                    cx.report(format!("condition of if expression is always {b}"));
+                }
+            }
+            Expr::While(cond, _) => {
+                if let Some(ConstExpr::Bool(false)) = literal(cond) {
+                    cx.report("body of while loop never runs");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Warns about dereferencing the null pointer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullDeref;
+
+impl Lint for NullDeref {
+    fn name(&self) -> &str {
+        "null-deref"
+    }
+
+    fn check_expr(&self, expr: &Expr, cx: &mut LintContext) {
+        if let Expr::Deref(ptr) | Expr::DerefMut(ptr, _) = expr {
+            if let Some(ConstExpr::Null) = literal(ptr) {
+                cx.report("dereference of null pointer");
+            }
+        }
+    }
+}
+
+/// Warns about array types with more elements than some limit,
+/// which can overflow the stack when they're stored in a variable.
+#[derive(Clone, Copy, Debug)]
+pub struct LargeStackArray {
+    /// The largest number of elements allowed in an array type.
+    pub max_len: i64,
+}
+
+impl Default for LargeStackArray {
+    fn default() -> Self {
+        Self { max_len: 65536 }
+    }
+}
+
+impl Lint for LargeStackArray {
+    fn name(&self) -> &str {
+        "large-stack-array"
+    }
+
+    fn check_type(&self, ty: &Type, cx: &mut LintContext) {
+        if let Type::Array(_, len) = ty {
+            if let Ok(ConstExpr::Int(n)) = len.clone().eval(cx.env()) {
+                if n > self.max_len {
+                    cx.report(format!(
+                        "array type {ty} has {n} elements, more than the limit of {}",
+                        self.max_len
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Warns about types that aren't named in CamelCase.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NonCamelCaseTypes;
+
+impl Lint for NonCamelCaseTypes {
+    fn name(&self) -> &str {
+        "non-camel-case-types"
+    }
+
+    fn check_declaration(&self, decl: &Declaration, cx: &mut LintContext) {
+        if let Declaration::Type(name, ..) = decl {
+            let camel_case = name.starts_with(|c: char| c.is_uppercase()) && !name.contains('_');
+            // Names starting with an underscore are reserved for the compiler.
+            if !camel_case && !name.starts_with('_') {
+                cx.report(format!("type {name} should be named in CamelCase"));
+            }
+        }
+    }
+}
@@ -10,7 +10,11 @@
 //! 1. [Types](./enum.Type.html)
 //! 2. [Constants](./enum.ConstExpr.html) and [Expressions](./enum.Expr.html)
 //! 3. [Core Builtins](struct.CoreBuiltin.html) and [Standard Builtins](struct.StandardBuiltin.html)
-//! 4. [Compilation Errors](./enum.Error.html)
+//! 4. [Compilation Errors](./enum.Error.html), [their codes](./struct.ErrorCode.html), and [Diagnostics](./struct.Diagnostic.html)
+//! 5. [Lints](./trait.Lint.html)
+//! 6. [Text Format](./trait.ToText.html) and [its parser](./trait.FromText.html)
+//! 7. [Documentation](./struct.ModuleDocs.html)
+//! 8. [Pass Timing](./struct.PassTimings.html)
 
 //! ## Purpose
 //!
@@ -66,18 +70,41 @@
 //! **The LIR compiler will only use a standard instruction if it has to.**
 
 mod annotate;
+mod attribute;
 mod compile;
+mod diagnostic;
+mod doc;
+mod effects;
+mod entry;
 mod env;
 mod error;
+mod explain;
 mod expr;
+mod inline;
+mod lint;
+mod text;
+mod timing;
 mod types;
+mod visit;
+mod warning;
 
 pub use annotate::*;
+pub use attribute::*;
 pub use compile::*;
+pub use diagnostic::*;
+pub use doc::*;
+pub use effects::*;
+pub use entry::*;
 pub use env::*;
 pub use error::*;
+pub use explain::*;
 pub use expr::*;
+pub use lint::*;
+pub use text::*;
+pub use timing::*;
 pub use types::*;
+pub use visit::*;
+pub use warning::*;
 
 /// Simplify an expression while maintaining structural equality.
 pub trait Simplify: Sized {
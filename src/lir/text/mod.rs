@@ -0,0 +1,348 @@
+//! # LIR Text Format
+//!
+//! This module defines a stable, human readable text format for LIR programs,
+//! with a pretty-printer (`ToText`) and a parser (`FromText`). Unlike the
+//! `Display` implementations, which are meant for debugging, the text format
+//! can represent every LIR expression, constant, type, pattern, and declaration,
+//! so it can be written by hand, checked into golden tests, and passed between tools.
+//!
+//! ## Syntax
+//!
+//! The format is made of S-expressions. Every compound node is a list whose first
+//! element names the kind of node, and leaves are written as atoms.
+//!
+//! ```text
+//! ; Comments start with a semicolon.
+//! (let (def-proc fact (proc fact ((n const Int)) Int
+//!     (if (binop <= n 1)
+//!       1
+//!       (binop * n (call fact (binop - n 1))))))
+//!   (unop put (call fact 5)))
+//! ```
+//!
+//! - Integers (`5`, `-3`), floats (`1.5`, `2e10`), characters (`'a'`, `'\n'`),
+//!   and strings (`"hello"`, an array of characters) are literals.
+//! - `true`, `false`, `none`, `null`, `any` are constants, and `_` is the wildcard pattern.
+//! - `Int`, `Float`, `Cell`, `Char`, `Bool`, `None`, `Any`, `Never` are the primitive types.
+//! - Any other atom is a symbol. A symbol which can't be written as an atom is written `(sym "name")`.
+//! - Mutability is written `mut`, `const`, or `any`.
+//!
+//! Expressions are written with the heads `do`, `let`, `while`, `if`, `when`, `match`, `if-let`,
+//! `while-let`, `unop`, `binop`, `ternop`, `assign`, `ref`, `deref`, `store`, `call`, `return`,
+//! `array`, `array-repeat`, `tuple`, `union`, `enum-union`, `struct`, `as`, `member`, `index`,
+//! and `hole`.
+//! A constant expression can be used anywhere an expression can, but constants which share
+//! a head with an expression (like a constant `tuple`) are wrapped in `(const ...)`.
+//! In patterns, a bare symbol binds an immutable variable, and `(bind mut x)` binds a mutable one.
+//! Procedures and type declarations end with their attributes, if they have any:
+//! `(attrs inline no_mangle cold pure deprecated)`, or `(deprecated "note")` for a deprecation with a note.
+//! A specialization lists its type arguments, like `(specialize Float)`.
+//! A constant table is written `(table i 0 16 (binop * i i))`, which is the array of `i * i`
+//! for each `i` from 0 up to (but not including) 16.
+//! An enum with chosen tag values or a wider tag is wrapped in its representation:
+//! `(enum-repr 2 ((A 1) (B 5)) (enum A B))` gives `A` the tag 1 and `B` the tag 5, in two cells.
+//!
+//! Source code locations are not part of the format. Procedures are given new mangled names
+//! when they are parsed, so printing a parsed program gives back the same text, but the
+//! parsed procedures are distinct from the printed ones.
+//!
+//! A static assertion is written `(static-assert cond "message")`.
+//!
+//! Modules are printed after their imports have been distributed to their declarations,
+//! along with the unique ID the compiler gave them: `(module name checked id decls...)`.
+//! Text which defines modules by hand should give every distinct module its own ID.
+//!
+//! ## Building LIR in Rust
+//!
+//! The [`lir!`](crate::lir!) macro parses the text format into an expression, a constant,
+//! a type, a pattern, or a declaration, depending on what it's assigned to. This is much
+//! shorter than nesting constructors by hand when writing frontends and compiler tests.
+//! Like `format!`, `{}` in the text is replaced with an argument, which is any LIR node
+//! printed in the text format, so nodes can be built up from smaller ones.
+//!
+//! ```rust
+//! use sage::{lir, lir::*};
+//!
+//! let ty: Type = lir!("(ptr mut Int)");
+//! let recursive_call: Expr = lir!("(call fact (binop - n 1))");
+//! let program: Expr = lir!(
+//!     "(let (def-proc fact (proc fact ((n const Int)) {ret}
+//!         (if (binop <= n 1) 1 (binop * n {call}))))
+//!       (unop put (call fact 5)))",
+//!     ret = Type::Int,
+//!     call = recursive_call,
+//! );
+//! ```
+mod parse;
+mod print;
+
+pub use parse::*;
+pub use print::*;
+
+/// Parse an LIR node from the text format, with `{}` replaced by the arguments like in
+/// `format!`. The arguments are LIR nodes, which are printed in the text format. The node
+/// parsed is whichever of `Expr`, `ConstExpr`, `Type`, `Pattern`, or `Declaration` is
+/// expected. This panics if the text is invalid, so it's meant for code written by hand.
+#[macro_export]
+macro_rules! lir {
+    ($text:literal $(, $name:ident = $arg:expr)* $(,)?) => {
+        $crate::lir::FromText::from_text(&format!(
+            $text
+            $(, $name = $crate::lir::ToText::to_text(&$arg))*
+        ))
+        .unwrap_or_else(|e| panic!("invalid LIR text: {e}"))
+    };
+    ($text:literal $(, $arg:expr)* $(,)?) => {
+        $crate::lir::FromText::from_text(&format!(
+            $text
+            $(, $crate::lir::ToText::to_text(&$arg))*
+        ))
+        .unwrap_or_else(|e| panic!("invalid LIR text: {e}"))
+    };
+}
+
+use crate::lir::{unescape, unescape_char};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// The maximum width of a line in the pretty-printed output, before it is broken up.
+const LINE_WIDTH: usize = 80;
+
+/// Atoms with a special meaning, which can't be used as bare symbols.
+const RESERVED: &[&str] = &[
+    "_", "true", "false", "none", "null", "any", "Int", "Float", "Cell", "Char", "Bool", "None",
+    "Any", "Never",
+];
+
+/// A position in the text, used to report parse errors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Pos {
+    line: usize,
+    column: usize,
+}
+
+impl Display for Pos {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// An S-expression, the syntax tree of the text format.
+#[derive(Clone, Debug, PartialEq)]
+enum Sexp {
+    /// A symbol, a number, or a keyword.
+    Atom(String, Pos),
+    /// A string literal.
+    Str(String, Pos),
+    /// A character literal.
+    Char(char, Pos),
+    /// A list of S-expressions.
+    List(Vec<Sexp>, Pos),
+}
+
+impl Sexp {
+    /// The position of this S-expression in the text.
+    fn pos(&self) -> Pos {
+        match self {
+            Self::Atom(_, pos) | Self::Str(_, pos) | Self::Char(_, pos) | Self::List(_, pos) => {
+                *pos
+            }
+        }
+    }
+
+    /// The head of the list, if this is a list starting with an atom.
+    fn head(&self) -> Option<&str> {
+        match self {
+            Self::List(items, _) => match items.first() {
+                Some(Self::Atom(head, _)) => Some(head),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Can a symbol be written as a bare atom?
+    fn is_bare_symbol(name: &str) -> bool {
+        let mut chars = name.chars();
+        let starts_like_number = match (chars.next(), chars.next()) {
+            (Some(c), _) if c.is_ascii_digit() => true,
+            (Some('-' | '+' | '.'), Some(c)) => c.is_ascii_digit(),
+            (None, _) => return false,
+            _ => false,
+        };
+        !starts_like_number
+            && !RESERVED.contains(&name)
+            && name.chars().all(|c| !c.is_whitespace() && !is_delimiter(c))
+    }
+
+    /// Pretty-print the S-expression, breaking lists that don't fit on a line.
+    fn pretty(&self, indent: usize, out: &mut String) {
+        let flat = self.to_string();
+        let Self::List(items, _) = self else {
+            *out += &flat;
+            return;
+        };
+        if indent + flat.len() <= LINE_WIDTH {
+            *out += &flat;
+            return;
+        }
+        // Keep the head and any atoms after it on the first line,
+        // and put every item after the first list on its own line.
+        out.push('(');
+        let mut broken = false;
+        for (i, item) in items.iter().enumerate() {
+            if broken || (i > 0 && matches!(item, Self::List(..))) {
+                broken = true;
+                out.push('\n');
+                *out += &" ".repeat(indent + 2);
+            } else if i > 0 {
+                out.push(' ');
+            }
+            item.pretty(indent + 2, out);
+        }
+        out.push(')');
+    }
+}
+
+impl Display for Sexp {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Atom(atom, _) => write!(f, "{atom}"),
+            Self::Str(s, _) => write!(f, "{s:?}"),
+            Self::Char(c, _) => write!(f, "{c:?}"),
+            Self::List(items, _) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Does this character end an atom?
+fn is_delimiter(c: char) -> bool {
+    matches!(c, '(' | ')' | '"' | '\'' | ';')
+}
+
+/// Read the S-expressions in some text.
+fn read(text: &str) -> Result<Vec<Sexp>, String> {
+    let mut reader = Reader {
+        chars: text.chars().collect(),
+        i: 0,
+        pos: Pos { line: 1, column: 1 },
+    };
+    let mut result = vec![];
+    loop {
+        reader.skip_whitespace();
+        if reader.peek().is_none() {
+            return Ok(result);
+        }
+        result.push(reader.sexp()?);
+    }
+}
+
+/// A cursor over the characters of the text being read.
+struct Reader {
+    chars: Vec<char>,
+    i: usize,
+    pos: Pos,
+}
+
+impl Reader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.i).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.i += 1;
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        } else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
+
+    /// Skip whitespace and comments.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                while !matches!(self.next(), Some('\n') | None) {}
+            } else if c.is_whitespace() {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn sexp(&mut self) -> Result<Sexp, String> {
+        let pos = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.next();
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(')') => {
+                            self.next();
+                            return Ok(Sexp::List(items, pos));
+                        }
+                        Some(_) => items.push(self.sexp()?),
+                        None => return Err(format!("{pos}: unclosed parenthesis")),
+                    }
+                }
+            }
+            Some(')') => Err(format!("{pos}: unexpected closing parenthesis")),
+            Some('"') => {
+                let s = self.literal('"', "string")?;
+                let s = unescape(&s).map_err(|e| format!("{pos}: {e}"))?;
+                Ok(Sexp::Str(s, pos))
+            }
+            Some('\'') => {
+                let c = self.literal('\'', "character")?;
+                let c = unescape_char(&c).map_err(|e| format!("{pos}: {e}"))?;
+                Ok(Sexp::Char(c, pos))
+            }
+            _ => {
+                let mut atom = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_whitespace() || is_delimiter(c) {
+                        break;
+                    }
+                    atom.push(c);
+                    self.next();
+                }
+                Ok(Sexp::Atom(atom, pos))
+            }
+        }
+    }
+
+    /// Read the text of a literal between two quotes, leaving escape sequences as they are.
+    fn literal(&mut self, quote: char, kind: &str) -> Result<String, String> {
+        let pos = self.pos;
+        self.next();
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some(c) if c == quote => return Ok(text),
+                Some('\\') => {
+                    text.push('\\');
+                    match self.next() {
+                        Some(c) => text.push(c),
+                        None => return Err(format!("{pos}: unclosed {kind} literal")),
+                    }
+                }
+                Some(c) => text.push(c),
+                None => return Err(format!("{pos}: unclosed {kind} literal")),
+            }
+        }
+    }
+}
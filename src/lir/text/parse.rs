@@ -0,0 +1,795 @@
+//! Parsing LIR from the text format.
+use super::{print::EXPR_HEADS, read, Sexp, RESERVED};
+use crate::lir::{
+    Attribute, ConstExpr, CoreBuiltin, Declaration, Derive, EnumRepr, Expr, FFIProcedure,
+    Mutability, Pattern, PolyProcedure, Procedure, Reflection, StandardBuiltin, Type,
+};
+use crate::parse::{CoreProgramParser, StandardProgramParser};
+use std::sync::Arc;
+
+/// Parse a node of LIR from the text format.
+pub trait FromText: Sized {
+    /// Parse the text of a node, or return an error with the line and column of the problem.
+    fn from_text(text: &str) -> Result<Self, String>;
+}
+
+impl FromText for Expr {
+    /// Parse an expression. A text with several expressions is parsed as a `do` block.
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut sexps = read(text)?;
+        if sexps.len() == 1 {
+            expr(&sexps.remove(0))
+        } else {
+            Ok(Expr::Many(
+                sexps.iter().map(expr).collect::<Result<_, _>>()?,
+            ))
+        }
+    }
+}
+
+impl FromText for ConstExpr {
+    fn from_text(text: &str) -> Result<Self, String> {
+        const_expr(&read_one(text)?)
+    }
+}
+
+impl FromText for Type {
+    fn from_text(text: &str) -> Result<Self, String> {
+        ty(&read_one(text)?)
+    }
+}
+
+impl FromText for Pattern {
+    fn from_text(text: &str) -> Result<Self, String> {
+        pattern(&read_one(text)?)
+    }
+}
+
+impl FromText for Declaration {
+    fn from_text(text: &str) -> Result<Self, String> {
+        declaration(&read_one(text)?)
+    }
+}
+
+/// Read a text containing exactly one S-expression.
+fn read_one(text: &str) -> Result<Sexp, String> {
+    let mut sexps = read(text)?;
+    match sexps.len() {
+        1 => Ok(sexps.remove(0)),
+        0 => Err("1:1: expected an S-expression, found nothing".to_string()),
+        _ => Err(format!("{}: unexpected trailing input", sexps[1].pos())),
+    }
+}
+
+/// Create an error for finding the wrong kind of S-expression.
+fn expected(what: &str, found: &Sexp) -> String {
+    let pos = found.pos();
+    let found = match (found, found.head()) {
+        (Sexp::List(..), Some(head)) => format!("`({head} ...)`"),
+        (Sexp::List(items, _), None) if !items.is_empty() => "a list".to_string(),
+        _ => format!("`{found}`"),
+    };
+    format!("{pos}: expected {what}, found {found}")
+}
+
+/// Split a list into its head and the rest of its items.
+fn split(sexp: &Sexp) -> Option<(&str, &[Sexp])> {
+    match sexp {
+        Sexp::List(items, _) => match items.split_first() {
+            Some((Sexp::Atom(head, _), rest)) => Some((head, rest)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Get the items of a list.
+fn items<'a>(what: &str, sexp: &'a Sexp) -> Result<&'a [Sexp], String> {
+    match sexp {
+        Sexp::List(items, _) => Ok(items),
+        _ => Err(expected(what, sexp)),
+    }
+}
+
+/// Check that a form has exactly `N` arguments.
+fn arity<'a, const N: usize>(
+    sexp: &Sexp,
+    head: &str,
+    args: &'a [Sexp],
+) -> Result<&'a [Sexp; N], String> {
+    args.try_into().map_err(|_| {
+        format!(
+            "{}: expected {N} arguments to `{head}`, found {}",
+            sexp.pos(),
+            args.len()
+        )
+    })
+}
+
+fn symbol(sexp: &Sexp) -> Result<String, String> {
+    match sexp {
+        Sexp::Atom(name, _) if Sexp::is_bare_symbol(name) => Ok(name.clone()),
+        _ => match split(sexp) {
+            Some(("sym", [Sexp::Str(name, _)])) => Ok(name.clone()),
+            _ => Err(expected("a symbol", sexp)),
+        },
+    }
+}
+
+fn mutability(sexp: &Sexp) -> Result<Mutability, String> {
+    match sexp {
+        Sexp::Atom(m, _) if m == "mut" => Ok(Mutability::Mutable),
+        Sexp::Atom(m, _) if m == "const" => Ok(Mutability::Immutable),
+        Sexp::Atom(m, _) if m == "any" => Ok(Mutability::Any),
+        _ => Err(expected("`mut`, `const`, or `any`", sexp)),
+    }
+}
+
+fn boolean(sexp: &Sexp) -> Result<bool, String> {
+    match sexp {
+        Sexp::Atom(b, _) if b == "true" => Ok(true),
+        Sexp::Atom(b, _) if b == "false" => Ok(false),
+        _ => Err(expected("`true` or `false`", sexp)),
+    }
+}
+
+fn integer<T: std::str::FromStr>(sexp: &Sexp) -> Result<T, String> {
+    match sexp {
+        Sexp::Atom(n, _) => n.parse().map_err(|_| expected("an integer", sexp)),
+        _ => Err(expected("an integer", sexp)),
+    }
+}
+
+fn string(sexp: &Sexp) -> Result<&str, String> {
+    match sexp {
+        Sexp::Str(s, _) => Ok(s),
+        _ => Err(expected("a string", sexp)),
+    }
+}
+
+/// Parse a list of `(name item)` pairs.
+fn fields<T>(
+    sexps: &[Sexp],
+    f: impl Fn(&Sexp) -> Result<T, String>,
+) -> Result<Vec<(String, T)>, String> {
+    sexps
+        .iter()
+        .map(|sexp| match items("a `(name value)` pair", sexp)? {
+            [name, item] => Ok((symbol(name)?, f(item)?)),
+            _ => Err(expected("a `(name value)` pair", sexp)),
+        })
+        .collect()
+}
+
+/// Parse a list of type parameters with their optional bounds.
+fn ty_params(sexp: &Sexp) -> Result<Vec<(String, Option<Type>)>, String> {
+    items("a list of type parameters", sexp)?
+        .iter()
+        .map(|param| match items("a type parameter", param)? {
+            [name] => Ok((symbol(name)?, None)),
+            [name, bound] => Ok((symbol(name)?, Some(ty(bound)?))),
+            _ => Err(expected("a type parameter", param)),
+        })
+        .collect()
+}
+
+/// Parse a list of procedure arguments.
+fn args(sexp: &Sexp) -> Result<Vec<(String, Mutability, Type)>, String> {
+    items("a list of arguments", sexp)?
+        .iter()
+        .map(|arg| match items("an argument", arg)? {
+            [name, m, t] => Ok((symbol(name)?, mutability(m)?, ty(t)?)),
+            _ => Err(expected("an argument `(name mutability type)`", arg)),
+        })
+        .collect()
+}
+
+fn exprs(sexps: &[Sexp]) -> Result<Vec<Expr>, String> {
+    sexps.iter().map(expr).collect()
+}
+
+fn boxed(sexp: &Sexp) -> Result<Box<Expr>, String> {
+    expr(sexp).map(Box::new)
+}
+
+fn expr(sexp: &Sexp) -> Result<Expr, String> {
+    let Some((head, rest)) = split(sexp).filter(|(head, _)| EXPR_HEADS.contains(head)) else {
+        return const_expr(sexp).map(Expr::ConstExpr);
+    };
+    Ok(match head {
+        "const" => {
+            let [cexpr] = arity(sexp, head, rest)?;
+            Expr::ConstExpr(const_expr(cexpr)?)
+        }
+        "do" => Expr::Many(exprs(rest)?),
+        "let" => {
+            let [decl, body] = arity(sexp, head, rest)?;
+            Expr::Declare(Box::new(declaration(decl)?), boxed(body)?)
+        }
+        "while" => {
+            let [cond, body] = arity(sexp, head, rest)?;
+            Expr::While(boxed(cond)?, boxed(body)?)
+        }
+        "if" => {
+            let [cond, then, otherwise] = arity(sexp, head, rest)?;
+            Expr::If(boxed(cond)?, boxed(then)?, boxed(otherwise)?)
+        }
+        "when" => {
+            let [cond, then, otherwise] = arity(sexp, head, rest)?;
+            Expr::When(const_expr(cond)?, boxed(then)?, boxed(otherwise)?)
+        }
+        "match" => {
+            let Some((inner, arms)) = rest.split_first() else {
+                return Err(expected("`(match expr arms...)`", sexp));
+            };
+            let arms = arms
+                .iter()
+                .map(|arm| match items("a match arm", arm)? {
+                    [pat, body] => Ok((pattern(pat)?, expr(body)?)),
+                    _ => Err(expected("a match arm `(pattern expr)`", arm)),
+                })
+                .collect::<Result<_, String>>()?;
+            Expr::Match(boxed(inner)?, arms)
+        }
+        "if-let" => {
+            let [pat, inner, then, otherwise] = arity(sexp, head, rest)?;
+            Expr::IfLet(
+                pattern(pat)?,
+                boxed(inner)?,
+                boxed(then)?,
+                boxed(otherwise)?,
+            )
+        }
+        "while-let" => {
+            let [pat, inner, body] = arity(sexp, head, rest)?;
+            Expr::WhileLet(pattern(pat)?, boxed(inner)?, boxed(body)?)
+        }
+        "unop" => {
+            let [op, a] = arity(sexp, head, rest)?;
+            Expr::UnaryOp(symbol(op)?, boxed(a)?)
+        }
+        "binop" => {
+            let [op, a, b] = arity(sexp, head, rest)?;
+            Expr::BinaryOp(symbol(op)?, boxed(a)?, boxed(b)?)
+        }
+        "ternop" => {
+            let [op, a, b, c] = arity(sexp, head, rest)?;
+            Expr::TernaryOp(symbol(op)?, boxed(a)?, boxed(b)?, boxed(c)?)
+        }
+        "assign" => {
+            let [op, a, b] = arity(sexp, head, rest)?;
+            Expr::AssignOp(symbol(op)?, boxed(a)?, boxed(b)?)
+        }
+        "ref" => {
+            let [m, a] = arity(sexp, head, rest)?;
+            Expr::Refer(mutability(m)?, boxed(a)?)
+        }
+        "deref" => {
+            let [a] = arity(sexp, head, rest)?;
+            Expr::Deref(boxed(a)?)
+        }
+        "store" => {
+            let [ptr, val] = arity(sexp, head, rest)?;
+            Expr::DerefMut(boxed(ptr)?, boxed(val)?)
+        }
+        "call" => {
+            let Some((f, args)) = rest.split_first() else {
+                return Err(expected("`(call proc args...)`", sexp));
+            };
+            Expr::Apply(boxed(f)?, exprs(args)?)
+        }
+        "return" => {
+            let [a] = arity(sexp, head, rest)?;
+            Expr::Return(boxed(a)?)
+        }
+        "array" => Expr::Array(exprs(rest)?),
+        "array-repeat" => {
+            let [elem, len] = arity(sexp, head, rest)?;
+            Expr::ArrayRepeat(boxed(elem)?, boxed_const(len)?)
+        }
+        "tuple" => Expr::Tuple(exprs(rest)?),
+        "union" => {
+            let [t, variant, a] = arity(sexp, head, rest)?;
+            Expr::Union(ty(t)?, symbol(variant)?, boxed(a)?)
+        }
+        "enum-union" => {
+            let [t, variant, a] = arity(sexp, head, rest)?;
+            Expr::EnumUnion(ty(t)?, symbol(variant)?, boxed(a)?)
+        }
+        "struct" => Expr::Struct(fields(rest, expr)?.into_iter().collect()),
+        "as" => {
+            let [a, t] = arity(sexp, head, rest)?;
+            Expr::As(boxed(a)?, ty(t)?)
+        }
+        "member" => {
+            let [a, field] = arity(sexp, head, rest)?;
+            Expr::Member(boxed(a)?, const_expr(field)?)
+        }
+        "index" => {
+            let [a, i] = arity(sexp, head, rest)?;
+            Expr::Index(boxed(a)?, boxed(i)?)
+        }
+        "hole" => match rest {
+            [] => Expr::Hole(None),
+            [name] => Expr::Hole(Some(symbol(name)?)),
+            _ => return Err(expected("`(hole [name])`", sexp)),
+        },
+        _ => unreachable!("unhandled expression head `{head}`"),
+    })
+}
+
+fn consts(sexps: &[Sexp]) -> Result<Vec<ConstExpr>, String> {
+    sexps.iter().map(const_expr).collect()
+}
+
+fn boxed_const(sexp: &Sexp) -> Result<Box<ConstExpr>, String> {
+    const_expr(sexp).map(Box::new)
+}
+
+fn const_expr(sexp: &Sexp) -> Result<ConstExpr, String> {
+    let (head, rest) = match sexp {
+        Sexp::Atom(atom, _) => {
+            return Ok(match atom.as_str() {
+                "any" => ConstExpr::Any,
+                "none" => ConstExpr::None,
+                "null" => ConstExpr::Null,
+                "true" => ConstExpr::Bool(true),
+                "false" => ConstExpr::Bool(false),
+                _ if Sexp::is_bare_symbol(atom) => ConstExpr::Symbol(atom.clone()),
+                _ if RESERVED.contains(&atom.as_str()) => {
+                    return Err(expected("a constant expression", sexp))
+                }
+                _ if atom.contains(['.', 'e', 'E']) => ConstExpr::Float(
+                    atom.parse()
+                        .map_err(|_| expected("a floating point number", sexp))?,
+                ),
+                _ => ConstExpr::Int(atom.parse().map_err(|_| expected("an integer", sexp))?),
+            })
+        }
+        Sexp::Str(s, _) => return Ok(ConstExpr::Array(s.chars().map(ConstExpr::Char).collect())),
+        Sexp::Char(c, _) => return Ok(ConstExpr::Char(*c)),
+        Sexp::List(..) => split(sexp).ok_or_else(|| expected("a constant expression", sexp))?,
+    };
+    Ok(match head {
+        "const" => {
+            let [cexpr] = arity(sexp, head, rest)?;
+            const_expr(cexpr)?
+        }
+        "sym" => ConstExpr::Symbol(symbol(sexp)?),
+        "let" => {
+            let [decl, body] = arity(sexp, head, rest)?;
+            ConstExpr::Declare(Box::new(declaration(decl)?), boxed_const(body)?)
+        }
+        "cell" => {
+            let [n] = arity(sexp, head, rest)?;
+            ConstExpr::Cell(integer(n)?)
+        }
+        "float" => {
+            let [n] = arity(sexp, head, rest)?;
+            ConstExpr::Float(
+                string(n)?
+                    .parse()
+                    .map_err(|_| expected("a floating point number", n))?,
+            )
+        }
+        "of" => {
+            let [t, variant] = arity(sexp, head, rest)?;
+            ConstExpr::Of(ty(t)?, symbol(variant)?)
+        }
+        "typeof" => {
+            let [e] = arity(sexp, head, rest)?;
+            ConstExpr::TypeOf(boxed(e)?)
+        }
+        "sizeof" => {
+            let [t] = arity(sexp, head, rest)?;
+            ConstExpr::SizeOfType(ty(t)?)
+        }
+        "sizeof-expr" => {
+            let [e] = arity(sexp, head, rest)?;
+            ConstExpr::SizeOfExpr(boxed(e)?)
+        }
+        "alignof" => {
+            let [t] = arity(sexp, head, rest)?;
+            ConstExpr::AlignOfType(ty(t)?)
+        }
+        "offsetof" => {
+            let [t, field] = arity(sexp, head, rest)?;
+            ConstExpr::OffsetOf(ty(t)?, boxed_const(field)?)
+        }
+        "cfg" => match rest {
+            [name] => ConstExpr::Cfg(symbol(name)?, None),
+            [name, value] => ConstExpr::Cfg(symbol(name)?, Some(string(value)?.to_string())),
+            _ => return Err(expected("`(cfg name [\"value\"])`", sexp)),
+        },
+        "typename" | "fieldnames" | "fieldoffsets" | "variantnames" => {
+            let [t] = arity(sexp, head, rest)?;
+            ConstExpr::Reflect(ty(t)?, Reflection::from_name(head).unwrap())
+        }
+        "type" => {
+            let [t] = arity(sexp, head, rest)?;
+            ConstExpr::Type(ty(t)?)
+        }
+        "tuple" => ConstExpr::Tuple(consts(rest)?),
+        "array" => ConstExpr::Array(consts(rest)?),
+        "table" => {
+            let [name, start, end, elem] = arity(sexp, head, rest)?;
+            ConstExpr::Table(
+                symbol(name)?,
+                boxed_const(start)?,
+                boxed_const(end)?,
+                boxed(elem)?,
+            )
+        }
+        "struct" => ConstExpr::Struct(fields(rest, const_expr)?.into_iter().collect()),
+        "union" => {
+            let [t, variant, a] = arity(sexp, head, rest)?;
+            ConstExpr::Union(ty(t)?, symbol(variant)?, boxed_const(a)?)
+        }
+        "enum-union" => {
+            let [t, variant, a] = arity(sexp, head, rest)?;
+            ConstExpr::EnumUnion(ty(t)?, symbol(variant)?, boxed_const(a)?)
+        }
+        "core-builtin" => {
+            let [name, builtin_args, ret, asm] = arity(sexp, head, rest)?;
+            let body = CoreProgramParser::new()
+                .parse(string(asm)?)
+                .map_err(|e| format!("{}: invalid core assembly: {e}", asm.pos()))?
+                .code;
+            ConstExpr::CoreBuiltin(CoreBuiltin {
+                name: symbol(name)?,
+                args: builtin_args_list(builtin_args)?,
+                ret: ty(ret)?,
+                body,
+            })
+        }
+        "std-builtin" => {
+            let [name, builtin_args, ret, asm] = arity(sexp, head, rest)?;
+            let body = StandardProgramParser::new()
+                .parse(string(asm)?)
+                .map_err(|e| format!("{}: invalid standard assembly: {e}", asm.pos()))?
+                .code;
+            ConstExpr::StandardBuiltin(StandardBuiltin {
+                name: symbol(name)?,
+                args: builtin_args_list(builtin_args)?,
+                ret: ty(ret)?,
+                body,
+            })
+        }
+        "ffi" => ConstExpr::FFIProcedure(ffi_procedure(sexp)?),
+        "proc" => ConstExpr::Proc(procedure(sexp)?),
+        "poly-proc" => ConstExpr::PolyProc(poly_procedure(sexp)?),
+        "monomorphize" => {
+            let Some((a, ty_args)) = rest.split_first() else {
+                return Err(expected("`(monomorphize template types...)`", sexp));
+            };
+            ConstExpr::Monomorphize(boxed_const(a)?, types(ty_args)?)
+        }
+        "template" => {
+            let [params, a] = arity(sexp, head, rest)?;
+            ConstExpr::Template(ty_params(params)?, boxed_const(a)?)
+        }
+        "member" => {
+            let [a, field] = arity(sexp, head, rest)?;
+            ConstExpr::Member(boxed_const(a)?, boxed_const(field)?)
+        }
+        "as" => {
+            let [a, t] = arity(sexp, head, rest)?;
+            ConstExpr::As(boxed_const(a)?, ty(t)?)
+        }
+        _ => return Err(expected("a constant expression", sexp)),
+    })
+}
+
+/// Parse the arguments of a builtin procedure, which have no mutability.
+fn builtin_args_list(sexp: &Sexp) -> Result<Vec<(String, Type)>, String> {
+    fields(items("a list of arguments", sexp)?, ty)
+}
+
+fn ffi_procedure(sexp: &Sexp) -> Result<FFIProcedure, String> {
+    match split(sexp) {
+        Some(("ffi", [name, ffi_args, ret])) => Ok(FFIProcedure::new(
+            symbol(name)?,
+            types(items("a list of types", ffi_args)?)?,
+            ty(ret)?,
+        )),
+        _ => Err(expected("`(ffi name (types...) ret)`", sexp)),
+    }
+}
+
+/// Split the optional trailing `(attrs ...)` off of the items of a declaration.
+fn attributes(items: &[Sexp]) -> Result<(&[Sexp], Vec<Attribute>), String> {
+    let Some((last, rest)) = items.split_last() else {
+        return Ok((items, vec![]));
+    };
+    let Some(("attrs", attrs)) = split(last) else {
+        return Ok((items, vec![]));
+    };
+    let attrs = attrs
+        .iter()
+        .map(|attr| match attr {
+            Sexp::Atom(name, _) if name == "inline" => Ok(Attribute::Inline),
+            Sexp::Atom(name, _) if name == "no_mangle" => Ok(Attribute::NoMangle),
+            Sexp::Atom(name, _) if name == "cold" => Ok(Attribute::Cold),
+            Sexp::Atom(name, _) if name == "pure" => Ok(Attribute::Pure),
+            Sexp::Atom(name, _) if name == "test" => Ok(Attribute::Test),
+            Sexp::Atom(name, _) if name == "bench" => Ok(Attribute::Bench),
+            Sexp::Atom(name, _) if name == "deprecated" => Ok(Attribute::Deprecated(None)),
+            _ => match split(attr) {
+                Some(("deprecated", [note])) => {
+                    Ok(Attribute::Deprecated(Some(string(note)?.to_string())))
+                }
+                Some(("doc", [doc])) => Ok(Attribute::Doc(string(doc)?.to_string())),
+                Some(("derive", derives)) => Ok(Attribute::Derive(
+                    derives
+                        .iter()
+                        .map(|derive| match derive {
+                            Sexp::Atom(name, _) => Derive::from_name(name),
+                            _ => None,
+                        })
+                        .map(|derive| derive.ok_or_else(|| expected("a derivable operation", attr)))
+                        .collect::<Result<_, _>>()?,
+                )),
+                Some(("specialize", ty_args)) => Ok(Attribute::Specialize(types(ty_args)?)),
+                _ => Err(expected("an attribute", attr)),
+            },
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((rest, attrs))
+}
+
+fn procedure(sexp: &Sexp) -> Result<Procedure, String> {
+    let Some(("proc", rest)) = split(sexp) else {
+        return Err(expected("`(proc [name] (args...) ret body)`", sexp));
+    };
+    let (rest, attrs) = attributes(rest)?;
+    let (name, proc_args, ret, body) = match rest {
+        [name, proc_args, ret, body] => (Some(symbol(name)?), proc_args, ret, body),
+        [proc_args, ret, body] => (None, proc_args, ret, body),
+        _ => return Err(expected("`(proc [name] (args...) ret body)`", sexp)),
+    };
+    Ok(Procedure::new(name, args(proc_args)?, ty(ret)?, expr(body)?).with_attributes(attrs))
+}
+
+fn poly_procedure(sexp: &Sexp) -> Result<PolyProcedure, String> {
+    let expected_form = "`(poly-proc name (params...) (args...) ret body)`";
+    let Some(("poly-proc", rest)) = split(sexp) else {
+        return Err(expected(expected_form, sexp));
+    };
+    match attributes(rest)? {
+        ([name, params, proc_args, ret, body], attrs) => Ok(PolyProcedure::new(
+            symbol(name)?,
+            ty_params(params)?,
+            args(proc_args)?,
+            ty(ret)?,
+            expr(body)?,
+        )
+        .with_attributes(attrs)),
+        _ => Err(expected(expected_form, sexp)),
+    }
+}
+
+fn types(sexps: &[Sexp]) -> Result<Vec<Type>, String> {
+    sexps.iter().map(ty).collect()
+}
+
+fn boxed_ty(sexp: &Sexp) -> Result<Box<Type>, String> {
+    ty(sexp).map(Box::new)
+}
+
+fn ty(sexp: &Sexp) -> Result<Type, String> {
+    let (head, rest) = match sexp {
+        Sexp::Atom(atom, _) => {
+            return Ok(match atom.as_str() {
+                "None" => Type::None,
+                "Int" => Type::Int,
+                "Float" => Type::Float,
+                "Cell" => Type::Cell,
+                "Char" => Type::Char,
+                "Bool" => Type::Bool,
+                "Any" => Type::Any,
+                "Never" => Type::Never,
+                _ => Type::Symbol(symbol(sexp).map_err(|_| expected("a type", sexp))?),
+            })
+        }
+        _ => split(sexp).ok_or_else(|| expected("a type", sexp))?,
+    };
+    Ok(match head {
+        "sym" => Type::Symbol(symbol(sexp)?),
+        "let" => {
+            let [name, binding, ret] = arity(sexp, head, rest)?;
+            Type::Let(symbol(name)?, boxed_ty(binding)?, boxed_ty(ret)?)
+        }
+        "unit" => {
+            let [name, inner] = arity(sexp, head, rest)?;
+            Type::Unit(symbol(name)?, boxed_ty(inner)?)
+        }
+        "enum" => Type::Enum(
+            rest.iter().map(symbol).collect::<Result<_, _>>()?,
+            EnumRepr::default(),
+        ),
+        "enum-repr" => {
+            let [tag_cells, tags, inner] = arity(sexp, head, rest)?;
+            let tags = fields(items("a list of `(name tag)` pairs", tags)?, integer)?;
+            let repr = EnumRepr::new(tags, integer(tag_cells)?);
+            match ty(inner)? {
+                Type::Enum(variants, _) => Type::Enum(variants, repr),
+                Type::EnumUnion(variants, _) => Type::EnumUnion(variants, repr),
+                _ => return Err(expected("an enum type", inner)),
+            }
+        }
+        "tuple" => Type::Tuple(types(rest)?),
+        "array" => {
+            let [inner, len] = arity(sexp, head, rest)?;
+            Type::Array(boxed_ty(inner)?, boxed_const(len)?)
+        }
+        "struct" => Type::Struct(fields(rest, ty)?.into_iter().collect()),
+        "enum-union" => {
+            Type::EnumUnion(fields(rest, ty)?.into_iter().collect(), EnumRepr::default())
+        }
+        "type" => {
+            let [inner] = arity(sexp, head, rest)?;
+            Type::Type(boxed_ty(inner)?)
+        }
+        "union" => Type::Union(fields(rest, ty)?.into_iter().collect()),
+        "repr" => {
+            let Some((align, rest)) = rest.split_first() else {
+                return Err(expected("`(repr align (name type)...)`", sexp));
+            };
+            Type::Repr(fields(rest, ty)?, integer(align)?)
+        }
+        "bitfield" => Type::Bitfield(fields(rest, integer)?),
+        "proc" => {
+            let [proc_args, ret] = arity(sexp, head, rest)?;
+            Type::Proc(types(items("a list of types", proc_args)?)?, boxed_ty(ret)?)
+        }
+        "ptr" => {
+            let [m, inner] = arity(sexp, head, rest)?;
+            Type::Pointer(mutability(m)?, boxed_ty(inner)?)
+        }
+        "poly" => {
+            let [params, inner] = arity(sexp, head, rest)?;
+            Type::Poly(ty_params(params)?, boxed_ty(inner)?)
+        }
+        "apply" => {
+            let Some((poly, ty_args)) = rest.split_first() else {
+                return Err(expected("`(apply type args...)`", sexp));
+            };
+            Type::Apply(boxed_ty(poly)?, types(ty_args)?)
+        }
+        "const-param" => {
+            let [cexpr] = arity(sexp, head, rest)?;
+            Type::ConstParam(boxed_const(cexpr)?)
+        }
+        _ => return Err(expected("a type", sexp)),
+    })
+}
+
+fn patterns(sexps: &[Sexp]) -> Result<Vec<Pattern>, String> {
+    sexps.iter().map(pattern).collect()
+}
+
+fn pattern(sexp: &Sexp) -> Result<Pattern, String> {
+    let (head, rest) = match sexp {
+        Sexp::Atom(atom, _) if atom == "_" => return Ok(Pattern::Wildcard),
+        Sexp::Atom(atom, _) if Sexp::is_bare_symbol(atom) => {
+            return Ok(Pattern::Symbol(Mutability::Immutable, atom.clone()))
+        }
+        Sexp::Atom(..) | Sexp::Str(..) | Sexp::Char(..) => {
+            return const_expr(sexp).map(Pattern::ConstExpr)
+        }
+        Sexp::List(..) => split(sexp).ok_or_else(|| expected("a pattern", sexp))?,
+    };
+    Ok(match head {
+        "sym" => Pattern::Symbol(Mutability::Immutable, symbol(sexp)?),
+        "bind" => {
+            let [m, name] = arity(sexp, head, rest)?;
+            Pattern::Symbol(mutability(m)?, symbol(name)?)
+        }
+        "const" => {
+            let [cexpr] = arity(sexp, head, rest)?;
+            Pattern::ConstExpr(const_expr(cexpr)?)
+        }
+        "tuple" => Pattern::Tuple(patterns(rest)?),
+        "struct" => Pattern::Struct(fields(rest, pattern)?.into_iter().collect()),
+        "variant" => match rest {
+            [name] => Pattern::Variant(symbol(name)?, None),
+            [name, pat] => Pattern::Variant(symbol(name)?, Some(Box::new(pattern(pat)?))),
+            _ => return Err(expected("`(variant name [pattern])`", sexp)),
+        },
+        "alt" => Pattern::Alt(patterns(rest)?),
+        "ptr" => {
+            let [pat] = arity(sexp, head, rest)?;
+            Pattern::Pointer(Box::new(pattern(pat)?))
+        }
+        _ => return Err(expected("a pattern", sexp)),
+    })
+}
+
+fn declarations(sexps: &[Sexp]) -> Result<Vec<Declaration>, String> {
+    sexps.iter().map(declaration).collect()
+}
+
+fn declaration(sexp: &Sexp) -> Result<Declaration, String> {
+    let (head, rest) = split(sexp).ok_or_else(|| expected("a declaration", sexp))?;
+    Ok(match head {
+        "static" => {
+            let [name, m, t, e] = arity(sexp, head, rest)?;
+            Declaration::StaticVar(symbol(name)?, mutability(m)?, ty(t)?, expr(e)?)
+        }
+        "var" => {
+            let [name, m, t, e] = arity(sexp, head, rest)?;
+            let t = match t {
+                Sexp::Atom(atom, _) if atom == "_" => None,
+                _ => Some(ty(t)?),
+            };
+            Declaration::Var(symbol(name)?, mutability(m)?, t, expr(e)?)
+        }
+        "var-pat" => {
+            let [pat, e] = arity(sexp, head, rest)?;
+            Declaration::VarPat(pattern(pat)?, expr(e)?)
+        }
+        "def-proc" => {
+            let [name, proc] = arity(sexp, head, rest)?;
+            Declaration::Proc(symbol(name)?, procedure(proc)?)
+        }
+        "def-poly-proc" => {
+            let [name, proc] = arity(sexp, head, rest)?;
+            Declaration::PolyProc(symbol(name)?, poly_procedure(proc)?)
+        }
+        "def-type" => {
+            let (rest, attrs) = attributes(rest)?;
+            let [name, t] = arity(sexp, head, rest)?;
+            Declaration::Type(symbol(name)?, ty(t)?, attrs)
+        }
+        "def-const" => {
+            let [name, cexpr] = arity(sexp, head, rest)?;
+            Declaration::Const(symbol(name)?, const_expr(cexpr)?)
+        }
+        "extern" => {
+            let [name, ffi] = arity(sexp, head, rest)?;
+            Declaration::ExternProc(symbol(name)?, ffi_procedure(ffi)?)
+        }
+        "impl" => {
+            let Some((t, items)) = rest.split_first() else {
+                return Err(expected("`(impl type (name value)...)`", sexp));
+            };
+            Declaration::Impl(ty(t)?, fields(items, const_expr)?)
+        }
+        "decls" => Declaration::Many(Arc::new(declarations(rest)?)),
+        "module" => match rest {
+            [name, checked, id, decls @ ..] => Declaration::Module(
+                symbol(name)?,
+                Arc::new(declarations(decls)?),
+                boolean(checked)?,
+                integer(id)?,
+            ),
+            _ => return Err(expected("`(module name checked id decls...)`", sexp)),
+        },
+        "from-import" => {
+            let Some((module, names)) = rest.split_first() else {
+                return Err(expected("`(from-import module (name [alias])...)`", sexp));
+            };
+            let names = names
+                .iter()
+                .map(|name| match items("an imported name", name)? {
+                    [name] => Ok((symbol(name)?, None)),
+                    [name, alias] => Ok((symbol(name)?, Some(symbol(alias)?))),
+                    _ => Err(expected("an imported name `(name [alias])`", name)),
+                })
+                .collect::<Result<_, String>>()?;
+            Declaration::FromImport {
+                module: const_expr(module)?,
+                names,
+            }
+        }
+        "from-import-all" => {
+            let [module] = arity(sexp, head, rest)?;
+            Declaration::FromImportAll(const_expr(module)?)
+        }
+        "static-assert" => {
+            let [cond, message] = arity(sexp, head, rest)?;
+            Declaration::StaticAssert(const_expr(cond)?, string(message)?.to_string())
+        }
+        _ => return Err(expected("a declaration", sexp)),
+    })
+}
@@ -0,0 +1,511 @@
+//! Pretty-printing LIR in the text format.
+use super::{Pos, Sexp};
+use crate::asm::{CoreProgram, StandardProgram};
+use crate::lir::{
+    Attribute, ConstExpr, Declaration, EnumRepr, Expr, FFIProcedure, Mutability, Pattern,
+    PolyProcedure, Procedure, Type,
+};
+
+/// The heads of the expression forms. Constant expressions printed with one
+/// of these heads must be wrapped in `(const ...)` to be read back as constants.
+pub(super) const EXPR_HEADS: &[&str] = &[
+    "const",
+    "do",
+    "let",
+    "while",
+    "if",
+    "when",
+    "match",
+    "if-let",
+    "while-let",
+    "array-repeat",
+    "unop",
+    "binop",
+    "ternop",
+    "assign",
+    "ref",
+    "deref",
+    "store",
+    "call",
+    "return",
+    "array",
+    "tuple",
+    "union",
+    "enum-union",
+    "struct",
+    "as",
+    "member",
+    "index",
+    "hole",
+];
+
+/// Print a node of LIR in the text format.
+pub trait ToText {
+    /// Get the text of this node, pretty-printed to fit in 80 columns where possible.
+    fn to_text(&self) -> String;
+}
+
+fn pretty(sexp: Sexp) -> String {
+    let mut out = String::new();
+    sexp.pretty(0, &mut out);
+    out
+}
+
+impl ToText for Expr {
+    fn to_text(&self) -> String {
+        pretty(expr(self))
+    }
+}
+
+impl ToText for ConstExpr {
+    fn to_text(&self) -> String {
+        pretty(const_expr(self))
+    }
+}
+
+impl ToText for Type {
+    fn to_text(&self) -> String {
+        pretty(ty(self))
+    }
+}
+
+impl ToText for Pattern {
+    fn to_text(&self) -> String {
+        pretty(pattern(self))
+    }
+}
+
+impl ToText for Declaration {
+    fn to_text(&self) -> String {
+        pretty(declaration(self))
+    }
+}
+
+fn atom(atom: impl ToString) -> Sexp {
+    Sexp::Atom(atom.to_string(), Pos::default())
+}
+
+fn list(items: impl IntoIterator<Item = Sexp>) -> Sexp {
+    Sexp::List(items.into_iter().collect(), Pos::default())
+}
+
+/// Create a list starting with a head, followed by some items.
+fn form(head: &str, items: impl IntoIterator<Item = Sexp>) -> Sexp {
+    list(std::iter::once(atom(head)).chain(items))
+}
+
+fn symbol(name: &str) -> Sexp {
+    if Sexp::is_bare_symbol(name) {
+        atom(name)
+    } else {
+        form("sym", [Sexp::Str(name.to_string(), Pos::default())])
+    }
+}
+
+fn mutability(mutability: &Mutability) -> Sexp {
+    atom(mutability)
+}
+
+/// Print a list of `(name item)` pairs.
+fn fields<'a, T: 'a>(
+    fields: impl IntoIterator<Item = (&'a String, &'a T)>,
+    f: impl Fn(&T) -> Sexp,
+) -> impl Iterator<Item = Sexp> {
+    fields
+        .into_iter()
+        .map(move |(name, item)| list([symbol(name), f(item)]))
+}
+
+/// Print a list of type parameters with their optional bounds.
+fn ty_params(params: &[(String, Option<Type>)]) -> Sexp {
+    list(
+        params
+            .iter()
+            .map(|(name, bound)| list(std::iter::once(symbol(name)).chain(bound.as_ref().map(ty)))),
+    )
+}
+
+/// Print a list of procedure arguments.
+fn args(args: &[(String, Mutability, Type)]) -> Sexp {
+    list(
+        args.iter()
+            .map(|(name, m, t)| list([symbol(name), mutability(m), ty(t)])),
+    )
+}
+
+fn expr(expr: &Expr) -> Sexp {
+    match expr {
+        Expr::Annotated(inner, _) => self::expr(inner),
+        Expr::ConstExpr(cexpr) => {
+            let sexp = const_expr(cexpr);
+            match sexp.head() {
+                Some(head) if EXPR_HEADS.contains(&head) => form("const", [sexp]),
+                _ => sexp,
+            }
+        }
+        Expr::Many(exprs) => form("do", exprs.iter().map(self::expr)),
+        Expr::Declare(decl, body) => form("let", [declaration(decl), self::expr(body)]),
+        Expr::While(cond, body) => form("while", [self::expr(cond), self::expr(body)]),
+        Expr::If(cond, then, otherwise) => form(
+            "if",
+            [self::expr(cond), self::expr(then), self::expr(otherwise)],
+        ),
+        Expr::When(cond, then, otherwise) => form(
+            "when",
+            [const_expr(cond), self::expr(then), self::expr(otherwise)],
+        ),
+        Expr::Match(inner, arms) => form(
+            "match",
+            std::iter::once(self::expr(inner)).chain(
+                arms.iter()
+                    .map(|(pat, arm)| list([pattern(pat), self::expr(arm)])),
+            ),
+        ),
+        Expr::IfLet(pat, inner, then, otherwise) => form(
+            "if-let",
+            [
+                pattern(pat),
+                self::expr(inner),
+                self::expr(then),
+                self::expr(otherwise),
+            ],
+        ),
+        Expr::WhileLet(pat, inner, body) => form(
+            "while-let",
+            [pattern(pat), self::expr(inner), self::expr(body)],
+        ),
+        Expr::UnaryOp(op, a) => form("unop", [symbol(op), self::expr(a)]),
+        Expr::BinaryOp(op, a, b) => form("binop", [symbol(op), self::expr(a), self::expr(b)]),
+        Expr::TernaryOp(op, a, b, c) => form(
+            "ternop",
+            [symbol(op), self::expr(a), self::expr(b), self::expr(c)],
+        ),
+        Expr::AssignOp(op, a, b) => form("assign", [symbol(op), self::expr(a), self::expr(b)]),
+        Expr::Refer(m, a) => form("ref", [mutability(m), self::expr(a)]),
+        Expr::Deref(a) => form("deref", [self::expr(a)]),
+        Expr::DerefMut(ptr, val) => form("store", [self::expr(ptr), self::expr(val)]),
+        Expr::Apply(f, args) => form(
+            "call",
+            std::iter::once(self::expr(f)).chain(args.iter().map(self::expr)),
+        ),
+        Expr::Return(a) => form("return", [self::expr(a)]),
+        Expr::Array(items) => form("array", items.iter().map(self::expr)),
+        Expr::ArrayRepeat(elem, len) => form("array-repeat", [self::expr(elem), const_expr(len)]),
+        Expr::Tuple(items) => form("tuple", items.iter().map(self::expr)),
+        Expr::Union(t, variant, a) => form("union", [ty(t), symbol(variant), self::expr(a)]),
+        Expr::EnumUnion(t, variant, a) => {
+            form("enum-union", [ty(t), symbol(variant), self::expr(a)])
+        }
+        Expr::Struct(items) => form("struct", fields(items, self::expr)),
+        Expr::As(a, t) => form("as", [self::expr(a), ty(t)]),
+        Expr::Member(a, field) => form("member", [self::expr(a), const_expr(field)]),
+        Expr::Index(a, i) => form("index", [self::expr(a), self::expr(i)]),
+        Expr::Hole(name) => form("hole", name.iter().map(|name| symbol(name))),
+    }
+}
+
+fn const_expr(expr: &ConstExpr) -> Sexp {
+    match expr {
+        ConstExpr::Annotated(inner, _) => const_expr(inner),
+        ConstExpr::Declare(decl, body) => form("let", [declaration(decl), const_expr(body)]),
+        ConstExpr::Any => atom("any"),
+        ConstExpr::None => atom("none"),
+        ConstExpr::Null => atom("null"),
+        ConstExpr::Symbol(name) => symbol(name),
+        ConstExpr::Int(n) => atom(n),
+        ConstExpr::Cell(n) => form("cell", [atom(n)]),
+        ConstExpr::Float(n) if n.is_finite() => atom(format!("{n:?}")),
+        ConstExpr::Float(n) => form("float", [Sexp::Str(n.to_string(), Pos::default())]),
+        ConstExpr::Char(c) => Sexp::Char(*c, Pos::default()),
+        ConstExpr::Bool(b) => atom(b),
+        ConstExpr::Of(t, variant) => form("of", [ty(t), symbol(variant)]),
+        ConstExpr::TypeOf(e) => form("typeof", [self::expr(e)]),
+        ConstExpr::SizeOfType(t) => form("sizeof", [ty(t)]),
+        ConstExpr::SizeOfExpr(e) => form("sizeof-expr", [self::expr(e)]),
+        ConstExpr::AlignOfType(t) => form("alignof", [ty(t)]),
+        ConstExpr::OffsetOf(t, field) => form("offsetof", [ty(t), const_expr(field)]),
+        ConstExpr::Cfg(name, value) => form(
+            "cfg",
+            std::iter::once(symbol(name))
+                .chain(value.iter().map(|v| Sexp::Str(v.clone(), Pos::default()))),
+        ),
+        ConstExpr::Reflect(t, reflection) => form(reflection.name(), [ty(t)]),
+        ConstExpr::Type(t) => form("type", [ty(t)]),
+        ConstExpr::Tuple(items) => form("tuple", items.iter().map(const_expr)),
+        ConstExpr::Array(items) => {
+            let string = items
+                .iter()
+                .map(|item| match item {
+                    ConstExpr::Char(c) => Some(*c),
+                    _ => None,
+                })
+                .collect::<Option<String>>();
+            match string {
+                Some(s) if !items.is_empty() => Sexp::Str(s, Pos::default()),
+                _ => form("array", items.iter().map(const_expr)),
+            }
+        }
+        ConstExpr::Table(name, start, end, elem) => form(
+            "table",
+            [
+                symbol(name),
+                const_expr(start),
+                const_expr(end),
+                self::expr(elem),
+            ],
+        ),
+        ConstExpr::Struct(items) => form("struct", fields(items, const_expr)),
+        ConstExpr::Union(t, variant, a) => form("union", [ty(t), symbol(variant), const_expr(a)]),
+        ConstExpr::EnumUnion(t, variant, a) => {
+            form("enum-union", [ty(t), symbol(variant), const_expr(a)])
+        }
+        ConstExpr::CoreBuiltin(builtin) => {
+            let asm = CoreProgram::new(builtin.body.clone()).to_string();
+            builtin_form(
+                "core-builtin",
+                &builtin.name,
+                &builtin.args,
+                &builtin.ret,
+                asm,
+            )
+        }
+        ConstExpr::StandardBuiltin(builtin) => {
+            let asm = StandardProgram::new(builtin.body.clone()).to_string();
+            builtin_form(
+                "std-builtin",
+                &builtin.name,
+                &builtin.args,
+                &builtin.ret,
+                asm,
+            )
+        }
+        ConstExpr::FFIProcedure(ffi) => ffi_procedure(ffi),
+        ConstExpr::Proc(proc) => procedure(proc),
+        ConstExpr::PolyProc(proc) => poly_procedure(proc),
+        ConstExpr::Monomorphize(a, ty_args) => form(
+            "monomorphize",
+            std::iter::once(const_expr(a)).chain(ty_args.iter().map(ty)),
+        ),
+        ConstExpr::Template(params, a) => form("template", [ty_params(params), const_expr(a)]),
+        ConstExpr::Member(a, field) => form("member", [const_expr(a), const_expr(field)]),
+        ConstExpr::As(a, t) => form("as", [const_expr(a), ty(t)]),
+    }
+}
+
+/// Print a builtin procedure with its body of assembly code.
+fn builtin_form(head: &str, name: &str, args: &[(String, Type)], ret: &Type, asm: String) -> Sexp {
+    form(
+        head,
+        [
+            symbol(name),
+            list(args.iter().map(|(name, t)| list([symbol(name), ty(t)]))),
+            ty(ret),
+            Sexp::Str(asm.trim().to_string(), Pos::default()),
+        ],
+    )
+}
+
+fn ffi_procedure(ffi: &FFIProcedure) -> Sexp {
+    form(
+        "ffi",
+        [
+            symbol(ffi.get_name()),
+            list(ffi.get_args().iter().map(ty)),
+            ty(ffi.get_ret()),
+        ],
+    )
+}
+
+/// Print the attributes of a declaration as `(attrs ...)`, if it has any.
+fn attributes(attributes: &[Attribute]) -> Option<Sexp> {
+    if attributes.is_empty() {
+        return None;
+    }
+    Some(form(
+        "attrs",
+        attributes.iter().map(|attribute| match attribute {
+            Attribute::Deprecated(Some(note)) | Attribute::Doc(note) => {
+                form(attribute.name(), [Sexp::Str(note.clone(), Pos::default())])
+            }
+            Attribute::Derive(derives) => form(
+                attribute.name(),
+                derives.iter().map(|derive| atom(derive.name())),
+            ),
+            Attribute::Specialize(ty_args) => form(attribute.name(), ty_args.iter().map(ty)),
+            _ => atom(attribute.name()),
+        }),
+    ))
+}
+
+fn procedure(proc: &Procedure) -> Sexp {
+    form(
+        "proc",
+        proc.get_common_name()
+            .map(symbol)
+            .into_iter()
+            .chain([
+                args(proc.get_args()),
+                ty(proc.get_ret()),
+                expr(proc.get_body()),
+            ])
+            .chain(attributes(proc.get_attributes())),
+    )
+}
+
+fn poly_procedure(proc: &PolyProcedure) -> Sexp {
+    form(
+        "poly-proc",
+        [
+            symbol(proc.get_name()),
+            ty_params(proc.get_type_params()),
+            args(proc.get_args()),
+            ty(proc.get_ret()),
+            expr(proc.get_body()),
+        ]
+        .into_iter()
+        .chain(attributes(proc.get_attributes())),
+    )
+}
+
+/// Wrap an enum type in its representation, if it isn't the default one:
+/// `(enum-repr tag-cells ((name tag)...) enum)`.
+fn enum_repr(repr: &EnumRepr, enum_ty: Sexp) -> Sexp {
+    if repr.is_default() {
+        return enum_ty;
+    }
+    form(
+        "enum-repr",
+        [
+            atom(repr.tag_cells),
+            list(fields(&repr.tags, |tag| atom(tag))),
+            enum_ty,
+        ],
+    )
+}
+
+fn ty(ty: &Type) -> Sexp {
+    match ty {
+        Type::Let(name, binding, ret) => {
+            form("let", [symbol(name), self::ty(binding), self::ty(ret)])
+        }
+        Type::Unit(name, inner) => form("unit", [symbol(name), self::ty(inner)]),
+        Type::Symbol(name) => symbol(name),
+        Type::None => atom("None"),
+        Type::Int => atom("Int"),
+        Type::Float => atom("Float"),
+        Type::Cell => atom("Cell"),
+        Type::Char => atom("Char"),
+        Type::Bool => atom("Bool"),
+        Type::Any => atom("Any"),
+        Type::Never => atom("Never"),
+        Type::Enum(variants, repr) => {
+            enum_repr(repr, form("enum", variants.iter().map(|v| symbol(v))))
+        }
+        Type::Tuple(items) => form("tuple", items.iter().map(self::ty)),
+        Type::Array(inner, len) => form("array", [self::ty(inner), const_expr(len)]),
+        Type::Struct(items) => form("struct", fields(items, self::ty)),
+        Type::EnumUnion(items, repr) => {
+            enum_repr(repr, form("enum-union", fields(items, self::ty)))
+        }
+        Type::Type(inner) => form("type", [self::ty(inner)]),
+        Type::Union(items) => form("union", fields(items, self::ty)),
+        Type::Repr(items, align) => form(
+            "repr",
+            std::iter::once(atom(align))
+                .chain(fields(items.iter().map(|(name, t)| (name, t)), self::ty)),
+        ),
+        Type::Bitfield(items) => form(
+            "bitfield",
+            fields(items.iter().map(|(name, bits)| (name, bits)), |bits| {
+                atom(bits)
+            }),
+        ),
+        Type::Proc(args, ret) => form("proc", [list(args.iter().map(self::ty)), self::ty(ret)]),
+        Type::Pointer(m, inner) => form("ptr", [mutability(m), self::ty(inner)]),
+        Type::Poly(params, inner) => form("poly", [ty_params(params), self::ty(inner)]),
+        Type::Apply(poly, ty_args) => form(
+            "apply",
+            std::iter::once(self::ty(poly)).chain(ty_args.iter().map(self::ty)),
+        ),
+        Type::ConstParam(cexpr) => form("const-param", [const_expr(cexpr)]),
+    }
+}
+
+fn pattern(pat: &Pattern) -> Sexp {
+    match pat {
+        Pattern::Wildcard => atom("_"),
+        Pattern::Symbol(Mutability::Immutable, name) => symbol(name),
+        Pattern::Symbol(m, name) => form("bind", [mutability(m), symbol(name)]),
+        Pattern::ConstExpr(cexpr) => match cexpr {
+            ConstExpr::Int(_)
+            | ConstExpr::Float(_)
+            | ConstExpr::Char(_)
+            | ConstExpr::Bool(_)
+            | ConstExpr::None
+            | ConstExpr::Null
+            | ConstExpr::Any => const_expr(cexpr),
+            _ => form("const", [const_expr(cexpr)]),
+        },
+        Pattern::Tuple(pats) => form("tuple", pats.iter().map(pattern)),
+        Pattern::Struct(items) => form("struct", fields(items, pattern)),
+        Pattern::Variant(name, pat) => form(
+            "variant",
+            std::iter::once(symbol(name)).chain(pat.iter().map(|pat| pattern(pat))),
+        ),
+        Pattern::Alt(pats) => form("alt", pats.iter().map(pattern)),
+        Pattern::Pointer(pat) => form("ptr", [pattern(pat)]),
+    }
+}
+
+fn declaration(decl: &Declaration) -> Sexp {
+    match decl {
+        Declaration::StaticVar(name, m, t, e) => {
+            form("static", [symbol(name), mutability(m), ty(t), expr(e)])
+        }
+        Declaration::Var(name, m, t, e) => form(
+            "var",
+            [
+                symbol(name),
+                mutability(m),
+                t.as_ref().map(ty).unwrap_or_else(|| atom("_")),
+                expr(e),
+            ],
+        ),
+        Declaration::VarPat(pat, e) => form("var-pat", [pattern(pat), expr(e)]),
+        Declaration::Proc(name, proc) => form("def-proc", [symbol(name), procedure(proc)]),
+        Declaration::PolyProc(name, proc) => {
+            form("def-poly-proc", [symbol(name), poly_procedure(proc)])
+        }
+        Declaration::Type(name, t, attrs) => form(
+            "def-type",
+            [symbol(name), ty(t)].into_iter().chain(attributes(attrs)),
+        ),
+        Declaration::Const(name, cexpr) => form("def-const", [symbol(name), const_expr(cexpr)]),
+        Declaration::ExternProc(name, ffi) => form("extern", [symbol(name), ffi_procedure(ffi)]),
+        Declaration::Impl(t, items) => form(
+            "impl",
+            std::iter::once(ty(t)).chain(fields(
+                items.iter().map(|(name, cexpr)| (name, cexpr)),
+                const_expr,
+            )),
+        ),
+        Declaration::Many(decls) => form("decls", decls.iter().map(declaration)),
+        Declaration::Module(name, decls, checked, id) => form(
+            "module",
+            [symbol(name), atom(checked), atom(id)]
+                .into_iter()
+                .chain(decls.iter().map(declaration)),
+        ),
+        Declaration::FromImport { module, names } => form(
+            "from-import",
+            std::iter::once(const_expr(module)).chain(names.iter().map(|(name, alias)| {
+                list(std::iter::once(symbol(name)).chain(alias.iter().map(|a| symbol(a))))
+            })),
+        ),
+        Declaration::FromImportAll(module) => form("from-import-all", [const_expr(module)]),
+        Declaration::StaticAssert(cond, message) => form(
+            "static-assert",
+            [const_expr(cond), Sexp::Str(message.clone(), Pos::default())],
+        ),
+    }
+}
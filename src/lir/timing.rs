@@ -0,0 +1,180 @@
+//! # Pass Timing
+//!
+//! This module measures how long each pass of the compiler takes, and how much
+//! memory it uses, so that slow builds can be traced to the pass at fault.
+//!
+//! A [`PassTimings`] is shared by every scope of an [`Env`](super::Env) which has
+//! timing enabled, and each pass is timed with [`Env::time_pass`](super::Env::time_pass).
+//! The passes run under the environment (type checking, monomorphization, and
+//! compiling LIR to assembly) are timed by the compiler itself, and the passes
+//! before and after them (parsing, assembling, and building for a target) are
+//! timed by the code which runs them.
+//!
+//! A pass may run many times, like monomorphization, which runs once for every
+//! instantiation of a polymorphic procedure. Its times are added together.
+//! Passes can also run inside other passes: monomorphization happens while
+//! type checking and compiling, so its time is included in theirs too.
+//!
+//! The peak memory of a pass is the most memory the process had resident while
+//! it ran. This is only measured on Linux, and only for passes which don't run
+//! inside other passes, since it's measured for the whole process.
+use serde_json::json;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    /// The names of the passes running on this thread, so that a pass
+    /// which runs inside itself isn't counted twice.
+    static RUNNING: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The measurements of a pass of the compiler.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PassTime {
+    /// The name of the pass, like `typecheck`.
+    pub name: String,
+    /// The total time spent in the pass. When a pass runs on several
+    /// threads at once, this is the time spent on all of them.
+    pub time: Duration,
+    /// The number of times the pass ran.
+    pub runs: usize,
+    /// The most memory the process had resident while the pass ran, in bytes.
+    /// This is `None` if it couldn't be measured.
+    pub peak_memory: Option<usize>,
+}
+
+/// The times of the passes run by the compiler, in the order they first ran.
+#[derive(Debug, Default)]
+pub struct PassTimings {
+    /// The measurements of each pass.
+    passes: RwLock<Vec<PassTime>>,
+    /// The number of passes running, on any thread.
+    running: AtomicUsize,
+}
+
+impl PassTimings {
+    /// Create an empty record of pass times.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a pass, and add its time and memory to the record.
+    pub fn time<T>(&self, name: &str, pass: impl FnOnce() -> T) -> T {
+        let is_nested = RUNNING.with(|running| {
+            let mut running = running.borrow_mut();
+            let is_nested = running.iter().any(|running| running == name);
+            running.push(name.to_string());
+            is_nested
+        });
+        if is_nested {
+            let result = pass();
+            RUNNING.with(|running| running.borrow_mut().pop());
+            return result;
+        }
+
+        // Only measure the memory of passes which aren't run inside of others.
+        let is_outermost = self.running.fetch_add(1, Ordering::SeqCst) == 0;
+        if is_outermost {
+            reset_peak_memory();
+        }
+        let start = Instant::now();
+        let result = pass();
+        let time = start.elapsed();
+        let is_outermost = self.running.fetch_sub(1, Ordering::SeqCst) == 1 && is_outermost;
+        let peak_memory = if is_outermost { peak_memory() } else { None };
+        RUNNING.with(|running| running.borrow_mut().pop());
+
+        let mut passes = self.passes.write().unwrap();
+        match passes.iter_mut().find(|pass| pass.name == name) {
+            Some(pass) => {
+                pass.time += time;
+                pass.runs += 1;
+                pass.peak_memory = pass.peak_memory.max(peak_memory);
+            }
+            None => passes.push(PassTime {
+                name: name.to_string(),
+                time,
+                runs: 1,
+                peak_memory,
+            }),
+        }
+        result
+    }
+
+    /// The measurements of the passes which have run, in the order they first ran.
+    pub fn passes(&self) -> Vec<PassTime> {
+        self.passes.read().unwrap().clone()
+    }
+
+    /// A table of the pass times, for people to read.
+    pub fn report(&self) -> String {
+        let passes = self.passes();
+        let width = passes
+            .iter()
+            .map(|pass| pass.name.len())
+            .chain(std::iter::once("pass".len()))
+            .max()
+            .unwrap_or_default();
+        let mut report = format!(
+            "{:width$}  {:>12}  {:>6}  {:>12}\n",
+            "pass", "time", "runs", "peak memory"
+        );
+        for pass in passes {
+            let memory = match pass.peak_memory {
+                Some(bytes) => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+                None => "-".to_string(),
+            };
+            report += &format!(
+                "{:width$}  {:>12}  {:>6}  {:>12}\n",
+                pass.name,
+                format!("{:.3}ms", pass.time.as_secs_f64() * 1000.0),
+                pass.runs,
+                memory
+            );
+        }
+        report
+    }
+
+    /// The pass times as JSON, for tools to read. Times are in seconds, and memory in bytes.
+    pub fn to_json(&self) -> String {
+        let passes = self
+            .passes()
+            .into_iter()
+            .map(|pass| {
+                json!({
+                    "name": pass.name,
+                    "seconds": pass.time.as_secs_f64(),
+                    "runs": pass.runs,
+                    "peak_memory": pass.peak_memory,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "passes": passes }).to_string()
+    }
+}
+
+/// The most memory the process has had resident since the peak was last reset, in bytes.
+fn peak_memory() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Reset the peak memory of the process to the memory it has resident now.
+fn reset_peak_memory() {
+    // Writing `5` to `clear_refs` resets the peak resident memory on Linux.
+    let _ = std::fs::write("/proc/self/clear_refs", "5");
+}
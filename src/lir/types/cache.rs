@@ -0,0 +1,61 @@
+//! # Type Caches
+//!
+//! This module implements the memoization tables for type equality, type sizes,
+//! and concrete type simplification. These are computed with identical arguments
+//! many times while compiling a program, so the `Env` remembers their results.
+//!
+//! Every result depends on the definitions in scope, so the `Env` starts a new,
+//! empty cache whenever something is defined under it. Scopes which share the
+//! same definitions share the same cache.
+
+use super::Type;
+use std::collections::HashMap;
+
+/// The memoized results of type operations under a single set of definitions.
+#[derive(Clone, Debug, Default)]
+pub struct TypeCache {
+    /// The results of `Type::equals`.
+    equalities: HashMap<(Type, Type), bool>,
+    /// The results of `GetSize::get_size`.
+    sizes: HashMap<Type, usize>,
+    /// The results of `Type::simplify_until_concrete`, keyed by whether the
+    /// simplification was checked.
+    concrete: HashMap<(Type, bool), Type>,
+}
+
+impl TypeCache {
+    /// Create a new, empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the memoized result of comparing two types.
+    pub fn get_equality(&self, a: &Type, b: &Type) -> Option<bool> {
+        self.equalities.get(&(a.clone(), b.clone())).copied()
+    }
+
+    /// Remember the result of comparing two types.
+    pub fn set_equality(&mut self, a: Type, b: Type, equal: bool) {
+        self.equalities.insert((a, b), equal);
+    }
+
+    /// Get the memoized size of a type.
+    pub fn get_size(&self, ty: &Type) -> Option<usize> {
+        self.sizes.get(ty).copied()
+    }
+
+    /// Remember the size of a type.
+    pub fn set_size(&mut self, ty: Type, size: usize) {
+        self.sizes.insert(ty, size);
+    }
+
+    /// Get the memoized concrete simplification of a type.
+    pub fn get_concrete(&self, ty: &Type, checked: bool) -> Option<Type> {
+        self.concrete.get(&(ty.clone(), checked)).cloned()
+    }
+
+    /// Remember the concrete simplification of a type.
+    pub fn set_concrete(&mut self, ty: Type, checked: bool, concrete: Type) {
+        self.concrete.insert((ty, checked), concrete);
+    }
+}
@@ -10,7 +10,7 @@
 //! - Ensuring that all array lengths are non-negative.
 //! - Ensuring that you don't attempt to access a variable that is out of scope.
 use super::*;
-use crate::lir::Pattern;
+use crate::lir::{Attribute, Declaration, Pattern, Warning};
 
 use rayon::prelude::*;
 
@@ -37,8 +37,34 @@ impl TypeCheck for Type {
             | Self::Int
             | Self::Float
             | Self::Bool
-            | Self::Char
-            | Self::Enum(_) => Ok(()),
+            | Self::Char => Ok(()),
+
+            Self::Enum(variants, repr) => repr
+                .check(variants)
+                .map_err(|reason| Error::InvalidEnumRepr(self.clone(), reason)),
+
+            Self::Repr(fields, align) => {
+                // Every field must have a unique name, and the alignment can't be zero.
+                let mut names = HashSet::new();
+                for (name, t) in fields {
+                    if !names.insert(name) || *align == 0 {
+                        return Err(Error::InvalidRepr(self.clone(), name.clone()));
+                    }
+                    t.type_check(env)?;
+                }
+                Ok(())
+            }
+
+            Self::Bitfield(fields) => {
+                // Every field must have a unique name, and must fit in a single cell on the target.
+                let mut names = HashSet::new();
+                for (name, bits) in fields {
+                    if !names.insert(name) || *bits == 0 || *bits > env.get_cell_width() {
+                        return Err(Error::InvalidBitfield(self.clone(), name.clone()));
+                    }
+                }
+                Ok(())
+            }
 
             Self::ConstParam(cexpr) => cexpr.type_check(env),
             Self::Type(t) => t.type_check(env),
@@ -49,6 +75,10 @@ impl TypeCheck for Type {
             // Symbols are sound if they are defined in the environment
             Self::Symbol(name) => {
                 if env.get_type(name).is_some() || env.get_const(name).is_some() {
+                    // Warn about uses of deprecated types.
+                    if let Some(note) = Attribute::find_deprecation(env.get_type_attributes(name)) {
+                        env.warn(Warning::Deprecated(name.clone(), note.map(String::from)))?;
+                    }
                     Ok(())
                 } else {
                     debug!("Type {name} not defined in environment {env}");
@@ -96,7 +126,17 @@ impl TypeCheck for Type {
                 // Return success if all the types are sound.
                 Ok(())
             }
-            Self::Struct(fields) | Self::Union(fields) | Self::EnumUnion(fields) => {
+            Self::EnumUnion(fields, repr) => {
+                // The tags must be valid for the variants.
+                repr.check(&fields.keys().cloned().collect::<Vec<_>>())
+                    .map_err(|reason| Error::InvalidEnumRepr(self.clone(), reason))?;
+                fields
+                    .values()
+                    .collect::<Vec<&Type>>()
+                    .into_par_iter()
+                    .try_for_each(|t| t.type_check(env))
+            }
+            Self::Struct(fields) | Self::Union(fields) => {
                 // Check each inner type.
                 /*
                 for t in fields.values() {
@@ -170,7 +210,11 @@ impl TypeCheck for Type {
                                 // Check that the number of type arguments matches the number of type parameters.
                                 if ty_args.len() != ty_params.len() {
                                     error!("Expected {} type arguments for type {name}, but found {} in environment {env}", ty_params.len(), ty_args.len());
-                                    Err(Error::InvalidTemplateArgs(self.clone()))?;
+                                    Err(Error::MismatchedTemplateArgs {
+                                        ty: self.clone(),
+                                        expected: ty_params.len(),
+                                        found: ty_args.len(),
+                                    })?;
                                 }
                             }
                             _ => {
@@ -183,7 +227,11 @@ impl TypeCheck for Type {
                         // Check that the number of type arguments matches the number of type parameters.
                         if ty_params.len() != ty_args.len() {
                             error!("Expected {} type arguments for type {self}, but found {} in environment {env}", ty_params.len(), ty_args.len());
-                            Err(Error::InvalidTemplateArgs(self.clone()))?;
+                            Err(Error::MismatchedTemplateArgs {
+                                ty: self.clone(),
+                                expected: ty_params.len(),
+                                found: ty_args.len(),
+                            })?;
                         }
                     }
                     _ => {
@@ -205,8 +253,12 @@ impl TypeCheck for Type {
 impl TypeCheck for Expr {
     fn type_check(&self, env: &Env) -> Result<(), Error> {
         trace!("Type checking expression: {self}");
-        let ty = self.get_type(env)?;
-        ty.type_check(env)?;
+        // Blocks and declarations have the type of their body, which is checked on
+        // its own, so the errors in each part of them can be collected separately.
+        if !matches!(self, Self::Annotated(..) | Self::Many(_) | Self::Declare(..)) {
+            let ty = self.get_type(env)?;
+            ty.type_check(env)?;
+        }
 
         match self {
             Self::Annotated(expr, metadata) => {
@@ -218,12 +270,31 @@ impl TypeCheck for Expr {
             Self::Declare(declaration, body) => {
                 // Create a new environment with the declarations defined.
                 let mut new_env = env.clone();
+                // Record which variables are used by the declarations and the body.
+                new_env.track_used_vars();
                 // Check the declaration.
-                declaration.type_check(&new_env)?;
-                // Add the declarations to the environment.
-                new_env.add_declaration(declaration, false)?;
+                let decl_result = declaration.type_check(&new_env);
+                // Add the declarations to the environment. If they're invalid, still
+                // define what we can so that the body can be checked for more errors.
+                let add_result = match &decl_result {
+                    Ok(()) => new_env.add_declaration(declaration, false),
+                    Err(_) => Ok(()),
+                };
+                if decl_result.is_err() || add_result.is_err() {
+                    new_env.add_declaration_with_recovery(declaration);
+                }
                 // Check the body with the declarations defined.
-                body.type_check(&new_env)
+                Error::collect([decl_result, add_result, body.type_check(&new_env)])?;
+
+                // Warn about any declared variables that were never used.
+                for decl in declaration.clone().flatten() {
+                    if let Declaration::Var(name, _, _, expr) = decl {
+                        if !name.starts_with('_') && !new_env.is_var_used(&name) {
+                            env.warn(Warning::UnusedVariable(name).annotate_with(&expr))?;
+                        }
+                    }
+                }
+                Ok(())
             }
 
             Self::UnaryOp(unop, expr) => {
@@ -311,7 +382,21 @@ impl TypeCheck for Expr {
                 let mut result_ty: Option<Type> = None;
 
                 // Check each branch.
-                for (pat, branch) in branches {
+                for (i, (pat, branch)) in branches.iter().enumerate() {
+                    // If the arms before this one are exhaustive, it can never be reached.
+                    let previous = branches[..i]
+                        .iter()
+                        .map(|(pat, _)| pat.clone())
+                        .collect::<Vec<Pattern>>();
+                    if !previous.is_empty()
+                        && Pattern::are_patterns_exhaustive(self, &previous, &ty, env)?
+                    {
+                        env.warn(
+                            Warning::UnreachableMatchArm(pat.clone(), *expr.clone())
+                                .annotate_with(branch),
+                        )?;
+                    }
+
                     // Create a new environment with the bindings defined.
                     let mut new_env = env.clone();
                     // Get the bindings from the pattern.
@@ -384,6 +469,14 @@ impl TypeCheck for Expr {
                 Ok(())
             }
 
+            // Typecheck a while-let loop.
+            Self::WhileLet(pat, expr, body) => {
+                // Check the expression we're matching on.
+                expr.type_check(env)?;
+                // Check the body, with the variables bound by the pattern.
+                pat.type_check(expr, body, env)
+            }
+
             // Typecheck the inner constant expression.
             Self::ConstExpr(c) => c.type_check(env),
 
@@ -408,25 +501,33 @@ impl TypeCheck for Expr {
                 */
 
                 let count = exprs.len();
-                exprs.into_par_iter()
+                // Check every expression, collecting all of their errors.
+                let results = exprs.into_par_iter()
                     .enumerate()
-                    .try_for_each(|(i, expr)| {
+                    .map(|(i, expr)| {
                         expr.type_check(env)?;
                         if i < count - 1 {
                             // If it's not the last expression, confirm that it's of type `None`.
                             // Otherwise, return an error.
                             let ty = expr.get_type(env)?;
                             if !ty.can_decay_to(&Type::None, env)? {
+                                // The results of procedure calls may be discarded, with a warning.
+                                if expr.is_apply() {
+                                    return env.warn(
+                                        Warning::UnusedResult(expr.clone(), ty).annotate_with(expr),
+                                    );
+                                }
                                 error!("Expected type {} for expression {expr}, but found type {ty} in environment {env}", Type::None);
                                 // If it's not, return an error.
                                 return Err(Error::UnusedExpr(expr.clone(), ty));
                             }
                         }
                         Ok(())
-                    })?;
+                    })
+                    .collect::<Vec<_>>();
 
                 // Return success if all the expressions are sound.
-                Ok(())
+                Error::collect(results)
             }
 
             Self::While(cond, body) => {
@@ -478,6 +579,17 @@ impl TypeCheck for Expr {
                 Expr::Annotated(expr, metadata) => Self::Refer(*expected_mutability, expr)
                     .type_check(env)
                     .map_err(|e| e.annotate(metadata)),
+                // Bitfield members don't have their own addresses.
+                Expr::Member(inner, member)
+                    if inner.get_type(env)?.get_bitfield_member(&member, env)?.is_some() =>
+                {
+                    Err(Error::InvalidRefer(Expr::Member(inner, member)))
+                }
+                Expr::ConstExpr(ConstExpr::Member(inner, member))
+                    if inner.get_type(env)?.get_bitfield_member(&member, env)?.is_some() =>
+                {
+                    Err(Error::InvalidRefer(Expr::ConstExpr(ConstExpr::Member(inner, member))))
+                }
                 Expr::ConstExpr(ConstExpr::Symbol(name)) => {
                     // Check if the symbol is defined as mutable
                     if env.is_defined_as_mutable(&name) || expected_mutability.is_constant() {
@@ -526,7 +638,7 @@ impl TypeCheck for Expr {
                     match inner.get_type(env)? {
                         // If we are getting a member of a struct/union/tuple,
                         // check if we can reference the inner expression with the expected mutability.
-                        Type::Struct(_) | Type::Union(_) | Type::Tuple(_) => {
+                        Type::Struct(_) | Type::Union(_) | Type::Tuple(_) | Type::Repr(_, _) => {
                             inner.refer(*expected_mutability).type_check(env)?;
                         }
                         // If we are getting a member of a pointer,
@@ -575,6 +687,11 @@ impl TypeCheck for Expr {
             // Typecheck an assignment of a value to the data stored at
             // a given pointer.
             Self::DerefMut(ptr, val) => {
+                // If we're assigning to a member of a bitfield, then typecheck
+                // the assignment as a store to the cell containing the member.
+                if let Some((addr, _, _)) = ptr.get_bitfield_member_reference(env)? {
+                    return Self::DerefMut(addr.into(), val.clone()).type_check(env);
+                }
                 // Typecheck the pointer and the value we want to assign.
                 ptr.type_check(env)?;
                 val.type_check(env)?;
@@ -626,9 +743,7 @@ impl TypeCheck for Expr {
 
                     if let Self::Apply(f, args) = method_call.clone() {
                         // Typecheck the supplied arguments.
-                        for arg in &args {
-                            arg.type_check(env)?;
-                        }
+                        type_check_args(&f, &args, env)?;
 
                         f.type_check(env)?;
 
@@ -683,9 +798,7 @@ impl TypeCheck for Expr {
                 // Typecheck the expression we want to call as a procedure.
                 f.type_check(env)?;
                 // Typecheck the supplied arguments.
-                for arg in args {
-                    arg.type_check(env)?;
-                }
+                type_check_args(f, args, env)?;
 
                 // Get the type of the function.
                 let f_type = f.get_type(env)?.simplify_until_concrete(env, true)?;
@@ -737,12 +850,12 @@ impl TypeCheck for Expr {
 
             // Typecheck a return statement.
             Self::Return(e) => {
-                e.type_check(env)?;
-                let found = e.get_type(env)?;
                 let expected = env
                     .get_expected_return_type()
                     .cloned()
                     .unwrap_or(Type::None).simplify(env)?;
+                e.type_check_against(&expected, env)?;
+                let found = e.get_type(env)?;
                 if !found.can_decay_to(&expected, env)? {
                     error!("The found return type {found} does not match expected type {expected} in {env}");
                     return Err(Error::MismatchedTypes {
@@ -774,6 +887,12 @@ impl TypeCheck for Expr {
                 // Return success.
                 Ok(())
             }
+            // Typecheck an array of copies of an element.
+            Self::ArrayRepeat(elem, len) => {
+                elem.type_check(env)?;
+                // The length must be a non-negative constant.
+                Type::Array(Box::new(elem.get_type(env)?), len.clone()).type_check(env)
+            }
             Self::Tuple(elems) => {
                 for elem in elems {
                     elem.type_check(env)?;
@@ -830,7 +949,7 @@ impl TypeCheck for Expr {
                 let t = t.simplify_until_union(env, true)?;
 
                 match t {
-                    Type::EnumUnion(fields) => {
+                    Type::EnumUnion(fields, repr) => {
                         // Confirm that the variant is a valid variant.
                         if let Some(expected_ty) = fields.get(variant) {
                             // Typecheck the value assigned to the variant.
@@ -847,7 +966,7 @@ impl TypeCheck for Expr {
                             Ok(())
                         } else {
                             Err(Error::VariantNotFound(
-                                Type::EnumUnion(fields),
+                                Type::EnumUnion(fields, repr),
                                 variant.clone(),
                             ))
                         }
@@ -928,10 +1047,47 @@ impl TypeCheck for Expr {
                     Err(Error::InvalidIndex(self.clone()))
                 }
             }
+
+            // A hole without an expected type reports every binding in scope.
+            Self::Hole(name) => Err(Error::Hole {
+                name: name.clone(),
+                expected: None,
+                fits: env.get_bindings_fitting(None),
+            }),
         }
     }
 }
 
+impl Expr {
+    /// Type check the expression where a value of the expected type is needed.
+    /// If the expression is a hole, this reports the expected type and the bindings
+    /// which fit it.
+    pub fn type_check_against(&self, expected: &Type, env: &Env) -> Result<(), Error> {
+        match self {
+            Self::Annotated(expr, metadata) => expr
+                .type_check_against(expected, env)
+                .map_err(|e| e.annotate(metadata.clone())),
+            Self::Hole(name) => Err(Error::Hole {
+                name: name.clone(),
+                expected: Some(expected.clone()),
+                fits: env.get_bindings_fitting(Some(expected)),
+            }),
+            _ => self.type_check(env),
+        }
+    }
+}
+
+/// Type check the arguments of a procedure call against its parameters, if they're known.
+fn type_check_args(f: &Expr, args: &[Expr], env: &Env) -> Result<(), Error> {
+    let params = match f.get_type(env).and_then(|t| t.simplify_until_concrete(env, true)) {
+        Ok(Type::Proc(params, _)) if params.len() == args.len() => params,
+        _ => return args.iter().try_for_each(|arg| arg.type_check(env)),
+    };
+    args.iter()
+        .zip(params.iter())
+        .try_for_each(|(arg, param)| arg.type_check_against(param, env))
+}
+
 // Typecheck a constant expression.
 impl TypeCheck for ConstExpr {
     fn type_check(&self, env: &Env) -> Result<(), Error> {
@@ -1005,9 +1161,20 @@ impl TypeCheck for ConstExpr {
             | Self::Int(_)
             | Self::Float(_)
             | Self::Char(_)
-            | Self::Bool(_) => Ok(()),
+            | Self::Bool(_)
+            | Self::Cfg(..) => Ok(()),
 
-            Self::SizeOfType(t) => t.type_check(env),
+            Self::SizeOfType(t) | Self::AlignOfType(t) => t.type_check(env),
+            Self::OffsetOf(t, member) => {
+                t.type_check(env)?;
+                t.get_offset_of(member, env).map(|_| ())
+            }
+            Self::Reflect(t, reflection) => {
+                t.type_check(env)?;
+                reflection.reflect(t, env).map(|_| ())
+            }
+            // A table is sound if the array it evaluates to is sound.
+            Self::Table(..) => self.clone().eval(env)?.type_check(env),
 
             Self::Declare(bindings, expr) => {
                 // Create a new environment with the declarations defined.
@@ -1127,6 +1294,17 @@ impl TypeCheck for ConstExpr {
                     || env.get_static_var(name).is_some()
                     || env.get_type(name).is_some()
                 {
+                    // Warn about uses of deprecated procedures, unless a variable hides them.
+                    if !env.has_local_var(name) && env.get_static_var(name).is_none() {
+                        let attributes = match env.get_const(name) {
+                            Some(Self::Proc(proc)) => proc.get_attributes(),
+                            Some(Self::PolyProc(proc)) => proc.get_attributes(),
+                            _ => &[],
+                        };
+                        if let Some(note) = Attribute::find_deprecation(attributes) {
+                            env.warn(Warning::Deprecated(name.clone(), note.map(String::from)))?;
+                        }
+                    }
                     // Return success.
                     Ok(())
                 } else {
@@ -1143,7 +1321,7 @@ impl TypeCheck for ConstExpr {
                     .map_err(|_| Error::VariantNotFound(t.clone(), variant.clone()))?;
 
                 match t {
-                    Type::Enum(variants) => {
+                    Type::Enum(variants, repr) => {
                         // If the enum contains the variant, return success.
                         if variants.contains(variant) {
                             // Return success.
@@ -1152,12 +1330,14 @@ impl TypeCheck for ConstExpr {
                             // Otherwise, the variant isn't contained in the enum,
                             // so return an error.
                             Err(Error::VariantNotFound(
-                                Type::Enum(variants),
+                                Type::Enum(variants, repr),
                                 variant.clone(),
                             ))
                         }
                     }
-                    Type::EnumUnion(variants) if variants.get(variant) == Some(&Type::None) => {
+                    Type::EnumUnion(variants, repr)
+                        if variants.get(variant) == Some(&Type::None) =>
+                    {
                         // If the enum union contains the variant, and the variant is empty, return success.
                         if variants.contains_key(variant)
                             && variants.get(variant) == Some(&Type::None)
@@ -1168,7 +1348,7 @@ impl TypeCheck for ConstExpr {
                             // Otherwise, the variant isn't contained in the enum,
                             // so return an error.
                             Err(Error::VariantNotFound(
-                                Type::EnumUnion(variants),
+                                Type::EnumUnion(variants, repr),
                                 variant.clone(),
                             ))
                         }
@@ -1302,7 +1482,7 @@ impl TypeCheck for ConstExpr {
                 // Confirm the type supplied is a union.
                 let t = t.simplify_until_union(env, true)?;
                 match t {
-                    Type::EnumUnion(fields) => {
+                    Type::EnumUnion(fields, repr) => {
                         // Confirm that the variant is a valid variant.
                         if let Some(expected_ty) = fields.get(variant) {
                             // Typecheck the value assigned to the variant.
@@ -1322,7 +1502,7 @@ impl TypeCheck for ConstExpr {
                                 "Variant {variant} not found in type {self} in environment {env}"
                             );
                             Err(Error::VariantNotFound(
-                                Type::EnumUnion(fields),
+                                Type::EnumUnion(fields, repr),
                                 variant.clone(),
                             ))
                         }
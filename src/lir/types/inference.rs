@@ -10,8 +10,12 @@
 //! or stack overflow is a [bug that should be reported](https://github.com/adam-mcdaniel/sage/issues/new).
 
 use super::*;
+use crate::lir::{
+    fold_const_children, fold_declaration_children, fold_poly_procedure_children,
+    fold_type_children, Declaration, Fold, Pattern, PolyProcedure,
+};
 
-use log::trace;
+use log::{debug, trace};
 use rayon::prelude::*;
 
 /// Get the type associated with a value under a given environment.
@@ -35,6 +39,85 @@ pub trait GetType {
     }
 }
 
+/// A fold which substitutes a type for a type variable, without
+/// traversing into the scopes where the type variable is shadowed.
+pub(crate) struct Substitute<'a> {
+    /// The name of the type variable.
+    name: &'a str,
+    /// The type to substitute for the type variable.
+    substitution: &'a Type,
+}
+
+impl<'a> Substitute<'a> {
+    pub(crate) fn new(name: &'a str, substitution: &'a Type) -> Self {
+        Self { name, substitution }
+    }
+
+    /// Does a list of type parameters shadow the type variable?
+    fn is_shadowed_by(&self, params: &[(String, Option<Type>)]) -> bool {
+        params.iter().any(|(param, _)| param == self.name)
+    }
+}
+
+impl Fold for Substitute<'_> {
+    fn fold_type(&mut self, ty: Type) -> Type {
+        match ty {
+            Type::Symbol(name) if name == self.name => self.substitution.clone(),
+            // If the variable is overshadowed, then don't substitute.
+            Type::Let(ref name, ..) if name == self.name => ty,
+            Type::Poly(ref params, _) if self.is_shadowed_by(params) => ty,
+            ty => fold_type_children(self, ty),
+        }
+    }
+
+    fn fold_const(&mut self, expr: ConstExpr) -> ConstExpr {
+        match expr {
+            // A constant symbol cannot be substituted for a type variable.
+            ConstExpr::Symbol(name) if name == self.name => {
+                ConstExpr::Type(self.substitution.clone())
+            }
+            ConstExpr::Template(ref params, _) if self.is_shadowed_by(params) => expr,
+            // The signatures of builtins aren't traversed by folds.
+            ConstExpr::CoreBuiltin(mut builtin) => {
+                builtin.substitute(self.name, self.substitution);
+                ConstExpr::CoreBuiltin(builtin)
+            }
+            ConstExpr::StandardBuiltin(mut builtin) => {
+                builtin.substitute(self.name, self.substitution);
+                ConstExpr::StandardBuiltin(builtin)
+            }
+            ConstExpr::FFIProcedure(mut ffi_proc) => {
+                ffi_proc.substitute(self.name, self.substitution);
+                ConstExpr::FFIProcedure(ffi_proc)
+            }
+            expr => fold_const_children(self, expr),
+        }
+    }
+
+    fn fold_pattern(&mut self, pat: Pattern) -> Pattern {
+        // Patterns only match on values, so they never mention type variables.
+        pat
+    }
+
+    fn fold_declaration(&mut self, decl: Declaration) -> Declaration {
+        match decl {
+            Declaration::ExternProc(name, mut ffi_proc) => {
+                ffi_proc.substitute(self.name, self.substitution);
+                Declaration::ExternProc(name, ffi_proc)
+            }
+            decl => fold_declaration_children(self, decl),
+        }
+    }
+
+    fn fold_poly_procedure(&mut self, proc: PolyProcedure) -> PolyProcedure {
+        if self.is_shadowed_by(proc.get_type_params()) {
+            debug!("Not substituting {} in {proc} because of symbol conflict", self.name);
+            return proc;
+        }
+        fold_poly_procedure_children(self, proc)
+    }
+}
+
 /// Infer the type associated with an expression under a given environment.
 impl GetType for Expr {
     fn get_type_checked(&self, env: &Env, i: usize) -> Result<Type, Error> {
@@ -73,6 +156,9 @@ impl GetType for Expr {
                 }
             }
 
+            // A while-let loop returns the None value.
+            Self::WhileLet(..) => Type::None,
+
             Self::IfLet(_pat, _expr, _a, b) => {
                 // We could get the type of the then branch,
                 // but the else branch should always be the same type.
@@ -279,6 +365,10 @@ impl GetType for Expr {
                 // Get the length of the array.
                 Box::new(ConstExpr::Int(items.len() as i64)),
             ),
+            // Get the type of an array of copies of an element.
+            Self::ArrayRepeat(elem, len) => {
+                Type::Array(Box::new(elem.get_type_checked(env, i)?), len.clone())
+            }
             // Get the type of a struct literal.
             Self::Struct(fields) => Type::Struct(
                 // Get the type of each field in the struct.
@@ -411,142 +501,15 @@ impl GetType for Expr {
                 // we cannot access an index.
                 _ => return Err(Error::InvalidIndex(self.clone())),
             },
+
+            // A hole can stand in for an expression of any type.
+            Self::Hole(_) => Type::Any,
         })
     }
 
     /// Substitute a type in a given expression.
     fn substitute(&mut self, name: &str, ty: &Type) {
         trace!("Substituting {name} for {ty} in {self}");
-        match self {
-            Self::Annotated(expr, _) => {
-                expr.substitute(name, ty);
-            }
-
-            Self::Declare(declaration, body) => {
-                declaration.substitute(name, ty);
-                body.substitute(name, ty);
-            }
-
-            Self::ConstExpr(cexpr) => cexpr.substitute(name, ty),
-
-            Self::If(cond, then, els) => {
-                cond.substitute(name, ty);
-                then.substitute(name, ty);
-                els.substitute(name, ty)
-            }
-            Self::While(cond, body) => {
-                cond.substitute(name, ty);
-                body.substitute(name, ty)
-            }
-
-            Self::Many(exprs) => {
-                // for expr in exprs.iter_mut() {
-                //     expr.substitute(name, ty);
-                // }
-                exprs
-                    .par_iter_mut()
-                    .for_each(|expr| expr.substitute(name, ty));
-            }
-            Self::When(cond, then_body, else_body) => {
-                cond.substitute(name, ty);
-                then_body.substitute(name, ty);
-                else_body.substitute(name, ty)
-            }
-
-            Self::Match(expr, arms) => {
-                expr.substitute(name, ty);
-                // for (_, arm) in arms.iter_mut() {
-                //     arm.substitute(name, ty);
-                // }
-                arms.par_iter_mut()
-                    .for_each(|(_, arm)| arm.substitute(name, ty));
-            }
-
-            Self::IfLet(_pat, expr, then_body, else_body) => {
-                expr.substitute(name, ty);
-                then_body.substitute(name, ty);
-                else_body.substitute(name, ty)
-            }
-
-            Self::UnaryOp(_op, expr) => expr.substitute(name, ty),
-
-            Self::BinaryOp(_op, lhs, rhs) => {
-                lhs.substitute(name, ty);
-                rhs.substitute(name, ty)
-            }
-
-            Self::TernaryOp(_op, cond, then, els) => {
-                cond.substitute(name, ty);
-                then.substitute(name, ty);
-                els.substitute(name, ty)
-            }
-
-            Self::AssignOp(_op, lhs, rhs) => {
-                lhs.substitute(name, ty);
-                rhs.substitute(name, ty)
-            }
-
-            Self::Refer(_, expr) => expr.substitute(name, ty),
-
-            Self::Deref(expr) => expr.substitute(name, ty),
-
-            Self::DerefMut(expr, val) => {
-                expr.substitute(name, ty);
-                val.substitute(name, ty)
-            }
-
-            Self::Apply(expr, args) => {
-                expr.substitute(name, ty);
-                // for arg in args.iter_mut() {
-                //     arg.substitute(name, ty);
-                // }
-                args.par_iter_mut().for_each(|arg| arg.substitute(name, ty));
-            }
-
-            Self::Return(expr) => expr.substitute(name, ty),
-
-            Self::Array(exprs) | Self::Tuple(exprs) => {
-                // for expr in exprs.iter_mut() {
-                //     expr.substitute(name, ty);
-                // }
-                exprs
-                    .par_iter_mut()
-                    .for_each(|expr| expr.substitute(name, ty));
-            }
-
-            Self::Union(t, _, expr) => {
-                *t = t.substitute(name, ty);
-                expr.substitute(name, ty)
-            }
-
-            Self::EnumUnion(t, _, expr) => {
-                *t = t.substitute(name, ty);
-                expr.substitute(name, ty)
-            }
-
-            Self::Struct(fields) => {
-                // for (_, expr) in fields.iter_mut() {
-                //     expr.substitute(name, ty);
-                // }
-                fields
-                    .par_iter_mut()
-                    .for_each(|(_, expr)| expr.substitute(name, ty));
-            }
-
-            Self::As(expr, t) => {
-                expr.substitute(name, ty);
-                *t = t.substitute(name, ty)
-            }
-
-            Self::Member(expr, cexpr) => {
-                expr.substitute(name, ty);
-                cexpr.substitute(name, ty)
-            }
-
-            Self::Index(expr, cexpr) => {
-                expr.substitute(name, ty);
-                cexpr.substitute(name, ty)
-            }
-        }
+        *self = Substitute::new(name, ty).fold_expr(std::mem::replace(self, Expr::NONE));
     }
 }
@@ -0,0 +1,96 @@
+//! # Type Interning
+//!
+//! This module implements hash-consing for types. Interning a type returns a
+//! shared handle to the single copy of every structurally identical type, so
+//! repeated types share memory.
+//!
+//! Only type definitions are interned: the `Env` interns the type bound to
+//! each name defined under it, and the interner is shared by all of the scopes
+//! created from the same environment. When two names are bound to the same
+//! handle, `Type::equals` knows they're equal without comparing their structure.
+//! Every other type is still compared structurally.
+
+use super::Type;
+use core::fmt;
+use std::{
+    collections::HashSet,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    sync::Arc,
+};
+
+/// A shared handle to an interned type.
+///
+/// Two handles from the same interner are equal if and only if
+/// they point to the same type.
+#[derive(Clone, Debug)]
+pub struct InternedType(Arc<Type>);
+
+impl InternedType {
+    /// Do these two handles point to the same interned type?
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Deref for InternedType {
+    type Target = Type;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PartialEq for InternedType {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other)
+    }
+}
+
+impl Eq for InternedType {}
+
+impl Hash for InternedType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state);
+    }
+}
+
+impl fmt::Display for InternedType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A table of interned types.
+#[derive(Clone, Debug, Default)]
+pub struct TypeInterner {
+    types: HashSet<Arc<Type>>,
+}
+
+impl TypeInterner {
+    /// Create a new, empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the shared handle for a type, adding it to the table if it
+    /// hasn't been interned before.
+    pub fn intern(&mut self, ty: Type) -> InternedType {
+        if let Some(interned) = self.types.get(&ty) {
+            return InternedType(interned.clone());
+        }
+        let interned = Arc::new(ty);
+        self.types.insert(interned.clone());
+        InternedType(interned)
+    }
+
+    /// The number of distinct types in the table.
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
@@ -0,0 +1,164 @@
+//! # Type Layouts
+//!
+//! This module flattens the layout of a type into the segments of cells which
+//! hold its data, in the order its members are declared. Padding between the
+//! members of a `Repr` struct is skipped, and the fields of a bitfield are
+//! unpacked into a cell each, so the flattened layout doesn't depend on the
+//! alignment or field reordering that the compiler chose.
+//!
+//! This is the format used by the `serialize` and `deserialize` builtins:
+//! a value is serialized as an array of cells, with one cell for each cell
+//! in its segments.
+//!
+//! The variant of a tagged union or union is only known at runtime, so their
+//! cells are copied as they are laid out, tag and all.
+
+use super::*;
+
+/// A segment of the layout of a type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Segment {
+    /// A run of `size` cells, starting at `offset` cells into the value.
+    Cells { offset: usize, size: usize },
+    /// A field of a bitfield, which is `bits` wide and starts at bit `shift`
+    /// of the cell `offset` cells into the value.
+    Bits {
+        offset: usize,
+        shift: usize,
+        bits: usize,
+    },
+}
+
+impl Segment {
+    /// The number of cells this segment takes up when it's serialized.
+    pub fn serialized_size(&self) -> usize {
+        match self {
+            Self::Cells { size, .. } => *size,
+            Self::Bits { .. } => 1,
+        }
+    }
+
+    /// This segment, moved `offset` cells further into the value.
+    fn offset(self, by: usize) -> Self {
+        match self {
+            Self::Cells { offset, size } => Self::Cells {
+                offset: offset + by,
+                size,
+            },
+            Self::Bits {
+                offset,
+                shift,
+                bits,
+            } => Self::Bits {
+                offset: offset + by,
+                shift,
+                bits,
+            },
+        }
+    }
+}
+
+impl Type {
+    /// Flatten the layout of this type into the segments which hold its data,
+    /// in the order its members are declared. Runs of cells which are next to
+    /// each other are merged into a single segment.
+    pub fn get_segments(&self, env: &Env) -> Result<Vec<Segment>, Error> {
+        let mut segments: Vec<Segment> = vec![];
+        for segment in self.get_segments_unmerged(env)? {
+            match (segments.last_mut(), segment) {
+                (
+                    Some(Segment::Cells { offset, size }),
+                    Segment::Cells {
+                        offset: next,
+                        size: next_size,
+                    },
+                ) if *offset + *size == next => *size += next_size,
+                (_, Segment::Cells { size: 0, .. }) => {}
+                _ => segments.push(segment),
+            }
+        }
+        Ok(segments)
+    }
+
+    /// Get the number of cells this type takes up when it's serialized.
+    pub fn get_serialized_size(&self, env: &Env) -> Result<usize, Error> {
+        Ok(self
+            .get_segments(env)?
+            .iter()
+            .map(Segment::serialized_size)
+            .sum())
+    }
+
+    fn get_segments_unmerged(&self, env: &Env) -> Result<Vec<Segment>, Error> {
+        let ty = self.simplify_until_concrete(env, false)?;
+        // The segments of each member, moved to the member's offset.
+        let members = |members: Vec<(ConstExpr, Type)>| -> Result<Vec<Segment>, Error> {
+            let mut segments = vec![];
+            for (member, member_ty) in members {
+                let offset = ty.get_offset_of(&member, env)?;
+                segments.extend(
+                    member_ty
+                        .get_segments_unmerged(env)?
+                        .into_iter()
+                        .map(|segment| segment.offset(offset)),
+                );
+            }
+            Ok(segments)
+        };
+
+        Ok(match &ty {
+            Type::Unit(_, inner) => inner.get_segments_unmerged(env)?,
+            Type::Struct(fields) => members(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (ConstExpr::Symbol(name.clone()), ty.clone()))
+                    .collect(),
+            )?,
+            Type::Repr(fields, _) => members(
+                fields
+                    .iter()
+                    .map(|(name, ty)| (ConstExpr::Symbol(name.clone()), ty.clone()))
+                    .collect(),
+            )?,
+            Type::Tuple(items) => members(
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| (ConstExpr::Int(i as i64), ty.clone()))
+                    .collect(),
+            )?,
+            Type::Array(elem, len) => {
+                let len = len.clone().as_int(env)?.max(0) as usize;
+                let elem_size = elem.get_size(env)?;
+                let elem_segments = elem.get_segments_unmerged(env)?;
+                (0..len)
+                    .flat_map(|i| {
+                        elem_segments
+                            .iter()
+                            .map(move |segment| segment.offset(i * elem_size))
+                    })
+                    .collect()
+            }
+            Type::Bitfield(fields) => {
+                let mut segments = vec![];
+                for (name, _) in fields {
+                    if let Some((offset, shift, bits)) =
+                        ty.get_bitfield_member(&ConstExpr::Symbol(name.clone()), env)?
+                    {
+                        segments.push(Segment::Bits {
+                            offset,
+                            shift,
+                            bits,
+                        });
+                    }
+                }
+                segments
+            }
+            // Everything else is copied as it's laid out.
+            _ => vec![Segment::Cells {
+                offset: 0,
+                size: ty.get_size(env)?,
+            }],
+        })
+    }
+}
@@ -3,18 +3,26 @@
 //! This module contains a collection of types and traits
 //! used to implement and confirm the soundness of the LIR
 //! typesystem.
-use super::{ConstExpr, Env, Error, Expr, Simplify};
+use super::{ConstExpr, Env, Error, Expr, Fold, Simplify};
 use core::fmt;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::sync::Mutex;
 
+mod cache;
 mod check;
 mod inference;
+mod intern;
+mod layout;
+mod reflect;
 mod size;
+pub use cache::*;
 pub use check::*;
 pub use inference::*;
+pub use intern::*;
+pub use layout::*;
+pub use reflect::*;
 pub use size::*;
 
 use log::*;
@@ -117,6 +125,113 @@ impl fmt::Display for Mutability {
     }
 }
 
+/// How the tag of an `Enum` or `EnumUnion` is represented in memory.
+///
+/// By default, the tag of a variant is its index in the alphabetical order
+/// of the variants, and the tag takes up a single cell. Enums which are shared
+/// with foreign code or external protocols can instead give every variant a
+/// chosen tag value, and widen the tag to take up more cells.
+///
+/// The value of the tag is always stored in the last cell of the tag,
+/// and the cells before it are zero.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub struct EnumRepr {
+    /// The tag value of each variant. If this is empty, the variants are numbered alphabetically.
+    pub tags: BTreeMap<String, i64>,
+    /// The number of cells the tag takes up.
+    pub tag_cells: usize,
+}
+
+impl Default for EnumRepr {
+    fn default() -> Self {
+        Self {
+            tags: BTreeMap::new(),
+            tag_cells: 1,
+        }
+    }
+}
+
+impl EnumRepr {
+    /// Create a representation with the given tag values and tag width.
+    pub fn new(tags: impl IntoIterator<Item = (String, i64)>, tag_cells: usize) -> Self {
+        Self {
+            tags: tags.into_iter().collect(),
+            tag_cells,
+        }
+    }
+
+    /// Is this the default representation?
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Get the tag value of a variant of an enum with this representation.
+    /// This returns `None` if the variant is not one of the enum's variants.
+    pub fn tag_of(&self, variants: &[String], variant: &String) -> Option<i64> {
+        if self.tags.is_empty() {
+            Type::variant_index(variants, variant).map(|index| index as i64)
+        } else if variants.contains(variant) {
+            self.tags.get(variant).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Get the variant with the given tag value.
+    pub fn variant_of(&self, variants: &[String], tag: i64) -> Option<String> {
+        variants
+            .iter()
+            .find(|variant| self.tag_of(variants, variant) == Some(tag))
+            .cloned()
+    }
+
+    /// Get the tag values of the variants, in the same order as the variants.
+    pub fn tags_of(&self, variants: &[String]) -> Vec<i64> {
+        variants
+            .iter()
+            .map(|variant| self.tag_of(variants, variant).unwrap_or(0))
+            .collect()
+    }
+
+    /// Get the cells of the tag for a variant, as they are stored in memory.
+    pub fn tag_cells_of(&self, variants: &[String], variant: &String) -> Option<Vec<i64>> {
+        let mut cells = vec![0; self.tag_cells.max(1)];
+        *cells.last_mut()? = self.tag_of(variants, variant)?;
+        Some(cells)
+    }
+
+    /// Confirm that this representation is valid for the given variants.
+    /// Either every variant has a distinct tag value or none of them do,
+    /// and the tag must take up at least one cell.
+    pub fn check(&self, variants: &[String]) -> Result<(), String> {
+        if self.tag_cells == 0 {
+            return Err("the tag must take up at least one cell".to_string());
+        }
+        if self.tags.is_empty() {
+            return Ok(());
+        }
+        for variant in variants {
+            if !self.tags.contains_key(variant) {
+                return Err(format!("variant `{variant}` has no tag value"));
+            }
+        }
+        let mut seen = HashMap::new();
+        for (variant, tag) in &self.tags {
+            if !variants.contains(variant) {
+                return Err(format!(
+                    "`{variant}` is given a tag value, but it isn't a variant"
+                ));
+            }
+            if let Some(other) = seen.insert(*tag, variant) {
+                return Err(format!(
+                    "variants `{other}` and `{variant}` have the same tag value {tag}"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// The representation of a type in the LIR type system.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub enum Type {
@@ -147,7 +262,8 @@ pub enum Type {
     Bool,
     /// An enumeration of a list of possible named values.
     /// A boolean could be considered an enumeration of `true` and `false`.
-    Enum(Vec<String>),
+    /// The representation controls the tag value of each variant, and how many cells it takes up.
+    Enum(Vec<String>, EnumRepr),
     /// A heterogenous collection of types. This is a product type.
     Tuple(Vec<Self>),
     /// An array of a given type, with a constant size.
@@ -158,8 +274,9 @@ pub enum Type {
     /// The enum union is essentially a regular union type, but with a tag
     /// to guarantee typesafety of accessing members.
     ///
-    /// The tag is stored at the very beginning of the value.
-    EnumUnion(BTreeMap<String, Self>),
+    /// The tag is stored at the very end of the value, after the largest variant.
+    /// The representation controls the tag value of each variant, and how many cells it takes up.
+    EnumUnion(BTreeMap<String, Self>, EnumRepr),
 
     /// A trait object. This is internally represented as an `EnumUnion` over the possible
     /// types that can be stored in the trait object.
@@ -175,6 +292,24 @@ pub enum Type {
     ///
     /// This is a sum type.
     Union(BTreeMap<String, Self>),
+    /// A struct with a guaranteed layout, for exchanging values with foreign code
+    /// and raw memory. Unlike `Struct`, whose members are laid out in alphabetical
+    /// order, the members are laid out in the order they are declared.
+    ///
+    /// Each member's offset is padded up to a multiple of the alignment (in cells),
+    /// as is the size of the whole struct. An alignment of `1` is a packed struct.
+    Repr(Vec<(String, Self)>, usize),
+    /// A collection of named integer fields, each with a width in bits.
+    /// The fields are packed into cells in the order they are declared,
+    /// starting from the least significant bit of the first cell. A field
+    /// never straddles two cells: if it doesn't fit in the remaining bits
+    /// of the current cell, it starts at the beginning of the next one.
+    /// With field reordering enabled, the compiler chooses the order instead,
+    /// to pack the fields into as few cells as possible.
+    ///
+    /// Reading a field extracts its bits as an unsigned integer, and writing
+    /// a field masks the value to the field's width before storing it.
+    Bitfield(Vec<(String, usize)>),
     /// A procedure with a list of parameters and a return type.
     Proc(Vec<Type>, Box<Type>),
     /// A pointer to another type.
@@ -212,7 +347,8 @@ unsafe impl Send for Type {}
 unsafe impl Sync for Type {}
 
 impl Type {
-    /// This is the maximum number of times a type will be simplified recursively.
+    /// This is the default maximum number of times a type will be simplified recursively.
+    /// The limit used during compilation is configured on the `Env`.
     pub const SIMPLIFY_RECURSION_LIMIT: usize = 30;
 
     pub fn is_recursive(&self, env: &Env) -> Result<bool, Error> {
@@ -326,6 +462,14 @@ impl Type {
                 }
                 Ok(false)
             }
+            Self::Repr(fields, _) => {
+                for (_, t) in fields {
+                    if t.is_recursive_helper(symbols, env)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
             Self::Union(fields) => {
                 for t in fields.values() {
                     if t.is_recursive_helper(symbols, env)? {
@@ -334,7 +478,7 @@ impl Type {
                 }
                 Ok(false)
             }
-            Self::EnumUnion(fields) => {
+            Self::EnumUnion(fields, _) => {
                 for t in fields.values() {
                     if t.is_recursive_helper(symbols, env)? {
                         return Ok(true);
@@ -342,7 +486,7 @@ impl Type {
                 }
                 Ok(false)
             }
-            Self::Enum(_) => Ok(false),
+            Self::Enum(..) | Self::Bitfield(_) => Ok(false),
 
             Self::ConstParam(_)
             | Self::None
@@ -418,6 +562,18 @@ impl Type {
                     }
                 }
             }
+            (Self::Repr(fields1, _), Self::Repr(fields2, _)) => {
+                for ((field_name, field_ty), (field_name2, field_ty2)) in fields1.iter().zip(fields2) {
+                    if field_name == field_name2 {
+                        field_ty.get_monomorph_template_args(
+                            field_ty2,
+                            matched_symbols,
+                            param_symbols,
+                            env,
+                        )?;
+                    }
+                }
+            }
             (Self::Union(fields1), Self::Union(fields2)) => {
                 for (field_name, field_ty) in fields1 {
                     if let Some(field_ty2) = fields2.get(field_name) {
@@ -431,7 +587,7 @@ impl Type {
                 }
             }
 
-            (Self::EnumUnion(fields1), Self::EnumUnion(fields2)) => {
+            (Self::EnumUnion(fields1, _), Self::EnumUnion(fields2, _)) => {
                 for (field_name, field_ty) in fields1 {
                     if let Some(field_ty2) = fields2.get(field_name) {
                         field_ty.get_monomorph_template_args(
@@ -628,7 +784,13 @@ impl Type {
                 }
             }
 
-            Self::EnumUnion(fields) => {
+            Self::Repr(fields, _) => {
+                for (_, field_ty) in fields {
+                    field_ty.add_monomorphized_associated_consts(env)?;
+                }
+            }
+
+            Self::EnumUnion(fields, _) => {
                 for (_, field_ty) in fields {
                     field_ty.add_monomorphized_associated_consts(env)?;
                 }
@@ -667,7 +829,8 @@ impl Type {
             | Self::Cell
             | Self::Char
             | Self::Bool
-            | Self::Enum(_) => {}
+            | Self::Enum(..)
+            | Self::Bitfield(_) => {}
         }
         Ok(())
     }
@@ -721,16 +884,18 @@ impl Type {
             | Self::Any
             | Self::Never
             | Self::ConstParam(_)
-            | Self::Enum(_)
+            | Self::Enum(..)
+            | Self::Bitfield(_)
             | Self::Type(_) => true,
             Self::Unit(_, t) => t.is_simple(),
             Self::Tuple(inner) => inner.iter().all(|t| t.is_simple()),
             Self::Array(inner, expr) => inner.is_simple() && matches!(**expr, ConstExpr::Int(_)),
             Self::Proc(args, ret) => args.iter().all(|t| t.is_simple()) && ret.is_simple(),
             Self::Pointer(_, inner) => inner.is_simple(),
-            Self::Struct(inner) | Self::Union(inner) | Self::EnumUnion(inner) => {
+            Self::Struct(inner) | Self::Union(inner) | Self::EnumUnion(inner, _) => {
                 inner.iter().all(|(_, t)| t.is_simple())
             }
+            Self::Repr(inner, _) => inner.iter().all(|(_, t)| t.is_simple()),
             Self::Symbol(_) => false,
             Self::Poly(_params, _ret) => true,
 
@@ -774,10 +939,12 @@ impl Type {
             | Self::Any
             | Self::Never
             | Self::ConstParam(_)
-            | Self::Enum(_)
-            | Self::EnumUnion(_)
+            | Self::Enum(..)
+            | Self::EnumUnion(..)
             | Self::Struct(_)
             | Self::Union(_)
+            | Self::Repr(_, _)
+            | Self::Bitfield(_)
             | Self::Proc(_, _)
             | Self::Tuple(_)
             | Self::Unit(_, _)
@@ -798,7 +965,8 @@ impl Type {
             | Self::Bool
             | Self::Any
             | Self::Never
-            | Self::Enum(_)
+            | Self::Enum(..)
+            | Self::Bitfield(_)
             | Self::ConstParam(_)
             | Self::Type(_) => true,
             
@@ -808,7 +976,8 @@ impl Type {
             Self::Proc(args, ret) => args.iter().all(|t| t.is_atomic()) && ret.is_atomic(),
             Self::Pointer(_, inner) => inner.is_atomic(),
             Self::Struct(inner) => inner.iter().all(|(_, t)| t.is_atomic()),
-            Self::EnumUnion(inner) => inner.iter().all(|(_, t)| t.is_atomic()),
+            Self::Repr(inner, _) => inner.iter().all(|(_, t)| t.is_atomic()),
+            Self::EnumUnion(inner, _) => inner.iter().all(|(_, t)| t.is_atomic()),
 
             _ => false,
         }
@@ -862,7 +1031,13 @@ impl Type {
     fn possibly_has_members(&self) -> bool {
         if matches!(
             self,
-            Self::Tuple(_) | Self::Struct(_) | Self::Union(_) | Self::Pointer(_, _) | Self::Type(_)
+            Self::Tuple(_)
+                | Self::Struct(_)
+                | Self::Union(_)
+                | Self::Repr(_, _)
+                | Self::Bitfield(_)
+                | Self::Pointer(_, _)
+                | Self::Type(_)
         ) {
             return true;
         }
@@ -889,7 +1064,7 @@ impl Type {
 
     /// Is this a union type?
     fn is_union(&self) -> bool {
-        if matches!(self, Self::Union(_) | Self::EnumUnion(_)) {
+        if matches!(self, Self::Union(_) | Self::EnumUnion(..)) {
             return true;
         }
 
@@ -913,7 +1088,7 @@ impl Type {
 
     /// Does this type have enum variants?
     fn has_variants(&self) -> bool {
-        if matches!(self, Self::Enum(_) | Self::EnumUnion(_)) {
+        if matches!(self, Self::Enum(..) | Self::EnumUnion(..)) {
             return true;
         }
 
@@ -926,9 +1101,12 @@ impl Type {
 
     /// Simplify a type until you can get its variants.
     pub fn simplify_until_has_variants(&self, env: &Env, checked: bool) -> Result<Self, Error> {
-        let result = self
-            .clone()
-            .simplify_until_matches(env, Type::Enum(vec![]), |t, _| Ok(t.has_variants()), checked);
+        let result = self.clone().simplify_until_matches(
+            env,
+            Type::Enum(vec![], EnumRepr::default()),
+            |t, _| Ok(t.has_variants()),
+            checked,
+        );
         if result.is_err() {
             debug!("Couldn't simplify {} to a type with variants", self);
         }
@@ -983,11 +1161,15 @@ impl Type {
 
     /// Simplify until the type is concrete.
     pub fn simplify_until_concrete(&self, env: &Env, checked: bool) -> Result<Self, Error> {
+        if let Some(concrete) = env.get_cached_concrete(self, checked) {
+            return Ok(concrete);
+        }
         let result = self
             .clone()
             .simplify_until_matches(env, Type::Any, |t, _env| Ok(t.is_concrete()), checked);
-        if result.is_err() {
-            debug!("Couldn't simplify {} to a concrete type", self);
+        match &result {
+            Ok(concrete) => env.cache_concrete(self.clone(), checked, concrete.clone()),
+            Err(_) => debug!("Couldn't simplify {} to a concrete type", self),
         }
         
         result
@@ -1079,7 +1261,9 @@ impl Type {
         Self::Let(name.to_string(), Box::new(t), Box::new(ret))
     }
 
-    /// Calculate the integral value of a variant in an enum.
+    /// Calculate the alphabetical index of a variant in an enum.
+    /// This is the tag of the variant, unless the enum's representation
+    /// gives it a chosen tag value (see `EnumRepr::tag_of`).
     pub fn variant_index(variants: &[String], variant: &String) -> Option<usize> {
         let mut variants = variants.to_vec();
         variants.sort();
@@ -1134,13 +1318,15 @@ impl Type {
             | Self::Cell
             | Self::Char
             | Self::Bool
-            | Self::Enum(_) => false,
+            | Self::Enum(..)
+            | Self::Bitfield(_) => false,
 
             Self::Tuple(items) => items.iter().any(|t| t.contains_symbol(name)),
             Self::Array(t, _) => t.contains_symbol(name),
             Self::Struct(fields) => fields.values().any(|t| t.contains_symbol(name)),
+            Self::Repr(fields, _) => fields.iter().any(|(_, t)| t.contains_symbol(name)),
             Self::Union(fields) => fields.values().any(|t| t.contains_symbol(name)),
-            Self::EnumUnion(fields) => fields.values().any(|t| t.contains_symbol(name)),
+            Self::EnumUnion(fields, _) => fields.values().any(|t| t.contains_symbol(name)),
 
             Self::Proc(params, ret) => {
                 params.iter().any(|t| t.contains_symbol(name)) || ret.contains_symbol(name)
@@ -1152,117 +1338,7 @@ impl Type {
     /// Substitute all occurences of a symbol with another type.
     /// This will not traverse into let-bindings when the symbol is overshadowed.
     pub fn substitute(&self, name: &str, substitution: &Self) -> Self {
-        let result = match self {
-            Self::Type(t) => Self::Type(Box::new(t.substitute(name, substitution))),
-            Self::Let(typename, binding, ret) => Self::Let(
-                typename.clone(),
-                if typename == name {
-                    // If the variable is overshadowed, then don't substitute.
-                    binding.clone()
-                } else {
-                    // If the variable is not overshadowed, then we're free to substitute.
-                    Box::new(binding.substitute(name, substitution))
-                },
-                if typename == name {
-                    // If the variable is overshadowed, then don't substitute in the body of the let.
-                    ret.clone()
-                } else {
-                    // If the variable is not overshadowed, then we're free to substitute in the body of the let.
-                    ret.substitute(name, substitution).into()
-                },
-            ),
-            Self::Poly(ty_params, template) => {
-                if ty_params.iter().map(|x| x.0.clone()).collect::<Vec<_>>().contains(&name.to_string()) {
-                    // This type variable is shadowed by a template variable.
-                    self.clone()
-                } else {
-                    // Does the inner symbol use this type variable?
-                    Self::Poly(
-                        ty_params.iter().map(|(x, ty)| (x.clone(), ty.as_ref().map(|ty| ty.substitute(name, substitution)))).collect(),
-                        template.substitute(name, substitution).into(),
-                    )
-                }
-            }
-            Self::Apply(poly, ty_args) => Self::Apply(
-                Box::new(poly.substitute(name, substitution)),
-                ty_args
-                    .iter()
-                    .map(|t| t.substitute(name, substitution))
-                    .collect(),
-            ),
-            Self::Symbol(typename) => {
-                if typename == name {
-                    return substitution.clone();
-                }
-                Self::Symbol(typename.clone())
-            }
-            Self::Unit(unit_name, inner) => Self::Unit(
-                unit_name.clone(),
-                Box::new(inner.substitute(name, substitution)),
-            ),
-            Self::ConstParam(cexpr) => {
-                let mut cexpr = cexpr.clone();
-                cexpr.substitute(name, substitution);
-                Self::ConstParam(cexpr)
-            },
-            Self::None
-            | Self::Never
-            | Self::Any
-            | Self::Int
-            | Self::Float
-            | Self::Cell
-            | Self::Char
-            | Self::Bool
-            | Self::Enum(_) => self.clone(),
-            Self::Tuple(items) => Self::Tuple(
-                items
-                    .iter()
-                    .map(|field_t| field_t.substitute(name, substitution))
-                    .collect(),
-            ),
-            Self::Array(item_t, size) => {
-                let mut size = size.clone();
-                size.substitute(name, substitution);
-                Self::Array(
-                    Box::new(item_t.substitute(name, substitution)),
-                    size,
-                )
-            },
-            Self::Struct(fields) => Self::Struct(
-                fields
-                    .iter()
-                    .map(|(field_name, field_t)| {
-                        (field_name.clone(), field_t.substitute(name, substitution))
-                    })
-                    .collect(),
-            ),
-            Self::Union(fields) => Self::Union(
-                fields
-                    .iter()
-                    .map(|(field_name, field_t)| {
-                        (field_name.clone(), field_t.substitute(name, substitution))
-                    })
-                    .collect(),
-            ),
-            Self::EnumUnion(fields) => Self::EnumUnion(
-                fields
-                    .iter()
-                    .map(|(field_name, field_t)| {
-                        (field_name.clone(), field_t.substitute(name, substitution))
-                    })
-                    .collect(),
-            ),
-            Self::Proc(args, ret) => Self::Proc(
-                args.iter()
-                    .map(|arg| arg.substitute(name, substitution))
-                    .collect(),
-                Box::new(ret.substitute(name, substitution)),
-            ),
-            Self::Pointer(mutability, ptr) => {
-                Self::Pointer(*mutability, Box::new(ptr.substitute(name, substitution)))
-            }
-        };
-        result
+        Substitute::new(name, substitution).fold_type(self.clone())
     }
 
     /// Does this type have an element type matching the supplied type?
@@ -1377,8 +1453,15 @@ impl Type {
                 Ok(false)
             }
 
+            // Enums with different tags can't decay to one another.
+            (Self::EnumUnion(_, found_repr), Self::EnumUnion(_, desired_repr))
+                if found_repr != desired_repr =>
+            {
+                Ok(false)
+            }
+
             // Can a struct decay to another struct?
-            (Self::EnumUnion(found_fields), Self::EnumUnion(desired_fields))
+            (Self::EnumUnion(found_fields, _), Self::EnumUnion(desired_fields, _))
             | (Self::Union(found_fields), Self::Union(desired_fields))
             | (Self::Struct(found_fields), Self::Struct(desired_fields)) => {
                 // If the structs have a different number of fields, then they can't be equal.
@@ -1401,6 +1484,20 @@ impl Type {
                 trace!("{} can decay to {}", self, desired);
                 Ok(true)
             }
+            (Self::Repr(found_fields, found_align), Self::Repr(desired_fields, desired_align)) => {
+                // The layouts must match exactly, so the fields must be in the same order.
+                if found_align != desired_align || found_fields.len() != desired_fields.len() {
+                    return Ok(false);
+                }
+                for ((found_name, found_ty), (desired_name, desired_ty)) in
+                    found_fields.iter().zip(desired_fields)
+                {
+                    if found_name != desired_name || !found_ty.can_decay_to(desired_ty, env)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
             (Type::Cell, Type::Int) | (Type::Int, Type::Cell) => Ok(true),
 
             (a, b) => a.equals(b, env),
@@ -1422,7 +1519,7 @@ impl Type {
         }
 
         let i = i + 1;
-        if i > Self::SIMPLIFY_RECURSION_LIMIT {
+        if i > env.get_type_recursion_limit() {
             error!(
                 "Recursion depth limit reached while checking if {} can be cast to {}",
                 self, other
@@ -1469,7 +1566,22 @@ impl Type {
             (Self::Int, Self::Float) | (Self::Float, Self::Int) => Ok(true),
             (Self::Int, Self::Char) | (Self::Char, Self::Int) => Ok(true),
             (Self::Int, Self::Bool) | (Self::Bool, Self::Int) => Ok(true),
-            (Self::Int, Self::Enum(_)) | (Self::Enum(_), Self::Int) => Ok(true),
+            (Self::Float, Self::Char) | (Self::Char, Self::Float) => Ok(true),
+            (Self::Float, Self::Bool) | (Self::Bool, Self::Float) => Ok(true),
+            (Self::Char, Self::Bool) | (Self::Bool, Self::Char) => Ok(true),
+            // Only enums with single cell tags can be cast to and from integers.
+            (Self::Int, Self::Enum(_, repr)) | (Self::Enum(_, repr), Self::Int) => {
+                Ok(repr.tag_cells == 1)
+            }
+            (Self::Int, Self::Bitfield(_))
+            | (Self::Bitfield(_), Self::Int)
+            | (Self::Cell, Self::Bitfield(_))
+            | (Self::Bitfield(_), Self::Cell) => Ok(self.get_size(env)? == other.get_size(env)?),
+            (Self::Array(elem, _), Self::Bitfield(_)) | (Self::Bitfield(_), Self::Array(elem, _))
+                if matches!(**elem, Self::Int | Self::Cell) =>
+            {
+                Ok(self.get_size(env)? == other.get_size(env)?)
+            }
 
             (Self::Cell, Self::Int) | (Self::Int, Self::Cell) => Ok(true),
             (Self::Cell, Self::Float) | (Self::Float, Self::Cell) => Ok(true),
@@ -1481,6 +1593,35 @@ impl Type {
 
             (Self::Any, _) | (_, Self::Any) => Ok(true),
 
+            // A struct can be cast to and from a struct with a guaranteed layout
+            // if they have the same members. The members are rearranged by the cast.
+            (Self::Struct(fields1), Self::Repr(fields2, _))
+            | (Self::Repr(fields2, _), Self::Struct(fields1)) => {
+                if fields1.len() != fields2.len() {
+                    return Ok(false);
+                }
+                for (name2, t2) in fields2.iter() {
+                    match fields1.get(name2) {
+                        Some(t1) if t1.equals(t2, env)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+
+            // The members are only moved by the cast, not converted, so they must have the same types.
+            (Self::Repr(fields1, _), Self::Repr(fields2, _)) => {
+                if fields1.len() != fields2.len() {
+                    return Ok(false);
+                }
+                for ((name1, t1), (name2, t2)) in fields1.iter().zip(fields2) {
+                    if name1 != name2 || !t1.equals(t2, env)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+
             (Self::Struct(fields1), Self::Struct(fields2)) => {
                 // If the structs have a different number of fields, then they can't be equal.
                 if fields1.len() != fields2.len() {
@@ -1549,7 +1690,62 @@ impl Type {
 
     /// Are two types structurally equal?
     pub fn equals(&self, other: &Self, env: &Env) -> Result<bool, Error> {
-        self.equals_checked(other, &mut HashSet::new(), env, 0)
+        if let Some(equal) = env.get_cached_equality(self, other) {
+            return Ok(equal);
+        }
+        let equal = self.equals_checked(other, &mut HashSet::new(), env, 0)?;
+        env.cache_equality(self.clone(), other.clone(), equal);
+        Ok(equal)
+    }
+
+    /// Substitute the arguments of this template application for the parameters
+    /// of the template. When `checked` is set, the types of the const parameters
+    /// are checked against their arguments.
+    fn instantiate_template(
+        &self,
+        params: &[(String, Option<Type>)],
+        mut mono_ty: Type,
+        ty_args: &[Type],
+        env: &Env,
+        checked: bool,
+    ) -> Result<Self, Error> {
+        // Don't let a template be applied to too few or too many arguments.
+        if params.len() != ty_args.len() {
+            error!(
+                "Expected {} type arguments for type {self}, but found {} in environment {env}",
+                params.len(),
+                ty_args.len()
+            );
+            return Err(Error::MismatchedTemplateArgs {
+                ty: self.clone(),
+                expected: params.len(),
+                found: ty_args.len(),
+            });
+        }
+
+        if checked {
+            let mut new_env = env.clone();
+            for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
+                new_env.define_type(param, ty_arg.clone());
+            }
+            for ((param, expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
+                if let Some(expected_ty) = expected_ty {
+                    if !expected_ty.equals(ty_arg, &new_env)?
+                        && !matches!(ty_arg, Type::Unit(name, inner) if param == name || **inner == Type::None)
+                    {
+                        return Err(Error::MismatchedTypes {
+                            expected: expected_ty.clone(),
+                            found: ty_arg.clone(),
+                            expr: Expr::ConstExpr(self.clone().into()),
+                        });
+                    }
+                }
+            }
+        }
+        for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
+            mono_ty = mono_ty.substitute(param, ty_arg);
+        }
+        Ok(mono_ty)
     }
 
     /// Perform type applications if possible.
@@ -1581,51 +1777,11 @@ impl Type {
 
                     match poly {
                         Self::Poly(params, mono_ty) => {
-                            let _poly = Self::Poly(params.clone(), mono_ty.clone());
-                            let mut mono_ty = *mono_ty;
-                            if !checked {
-                                for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                    mono_ty = mono_ty.substitute(param, ty_arg);
-                                }
-                            } else {
-                                let mut new_env = env.clone();
-                                for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                    new_env.define_type(param, ty_arg.clone());
-                                }
-                                for ((param, expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                    if let Some(expected_ty) = expected_ty {
-                                        if !expected_ty.equals(ty_arg, &new_env)? && !matches!(ty_arg, Type::Unit(name, inner) if param == name || **inner == Type::None) {
-                                            return Err(Error::MismatchedTypes { expected: expected_ty.clone(), found: ty_arg.clone(), expr: Expr::ConstExpr(self.clone().into()) })
-                                        }
-                                    }
-                                    mono_ty = mono_ty.substitute(param, ty_arg);
-                                }
-                            }
-                            mono_ty
+                            self.instantiate_template(&params, *mono_ty, &ty_args, env, checked)?
                         }
                         Self::Symbol(s) => match env.get_type(s.as_str()).cloned() {
-                            Some(Self::Poly(params, mono_ty)) => {
-                                let mut mono_ty = *mono_ty;
-                                if !checked {
-                                    for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                        mono_ty = mono_ty.substitute(param, ty_arg);
-                                    }
-                                } else {
-                                    let mut new_env = env.clone();
-                                    for ((param, _expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                        new_env.define_type(param, ty_arg.clone());
-                                    }
-                                    for ((param, expected_ty), ty_arg) in params.iter().zip(ty_args.iter()) {
-                                        if let Some(expected_ty) = expected_ty {
-                                            if !expected_ty.equals(ty_arg, &new_env)? && !matches!(ty_arg, Type::Unit(name, inner) if param == name || **inner == Type::None) {
-                                                return Err(Error::MismatchedTypes { expected: expected_ty.clone(), found: ty_arg.clone(), expr: Expr::ConstExpr(self.clone().into()) })
-                                            }
-                                        }
-                                        mono_ty = mono_ty.substitute(param, ty_arg);
-                                    }
-                                }
-                                mono_ty
-                            }
+                            Some(Self::Poly(params, mono_ty)) => self
+                                .instantiate_template(&params, *mono_ty, &ty_args, env, checked)?,
                             Some(other) => Self::Apply(Box::new(other), ty_args),
                             None => self.clone(),
                         },
@@ -1665,8 +1821,20 @@ impl Type {
                     })
                     .collect::<Result<BTreeMap<_, _>, Error>>()?,
             ),
+            Self::Repr(fields, align) if !self.is_recursive(env)? => Self::Repr(
+                fields
+                    .into_iter()
+                    .map(|(name, t)| {
+                        Ok((
+                            name,
+                            t.perform_template_applications(env, previous_applications, checked)?,
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?,
+                align,
+            ),
 
-            Self::EnumUnion(fields) if !self.is_recursive(env)? => Self::EnumUnion(
+            Self::EnumUnion(fields, repr) if !self.is_recursive(env)? => Self::EnumUnion(
                 fields
                     .into_iter()
                     .map(|(name, t)| {
@@ -1676,6 +1844,7 @@ impl Type {
                         ))
                     })
                     .collect::<Result<BTreeMap<_, _>, Error>>()?,
+                repr,
             ),
 
             Self::Type(ty) if !self.is_recursive(env)? => {
@@ -1701,18 +1870,40 @@ impl Type {
 
     /// Are two types structurally equal?
     /// This function should always halt (type equality *MUST* be decidable).
+    ///
+    /// The members of two types are compared using a worklist instead of recursion,
+    /// so only unfolding symbols and let-bindings counts toward the recursion limit.
     fn equals_checked(
         &self,
         other: &Self,
         compared_symbols: &mut HashSet<(String, String)>,
         env: &Env,
         i: usize,
+    ) -> Result<bool, Error> {
+        let mut worklist = vec![(self.clone(), other.clone(), i)];
+        while let Some((a, b, i)) = worklist.pop() {
+            if !a.equals_step(&b, &mut worklist, compared_symbols, env, i)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Compare the outermost structure of two types, and push the pairs of
+    /// member types which must also be equal onto the worklist.
+    fn equals_step(
+        &self,
+        other: &Self,
+        worklist: &mut Vec<(Self, Self, usize)>,
+        compared_symbols: &mut HashSet<(String, String)>,
+        env: &Env,
+        i: usize,
     ) -> Result<bool, Error> {
         if self == other {
             return Ok(true);
         }
 
-        if i >= Self::SIMPLIFY_RECURSION_LIMIT {
+        if i >= env.get_type_recursion_limit() {
             warn!(
                 "Recursion depth limit reached while checking if {} equals {}",
                 self, other
@@ -1720,7 +1911,8 @@ impl Type {
             return Ok(false);
         }
 
-        let i = i + 1;
+        // The depth used when unfolding a symbol or let-binding.
+        let j = i + 1;
 
         Ok(match (self, other) {
             (Self::Any, _)
@@ -1739,7 +1931,14 @@ impl Type {
                     // If the two types have the same name, they must equal the same type
                     // under the same environment.
                     true
-                } else if i > Self::SIMPLIFY_RECURSION_LIMIT / 2
+                } else if matches!(
+                    (env.get_interned_type(a), env.get_interned_type(b)),
+                    (Some(a), Some(b)) if a.ptr_eq(b)
+                ) {
+                    // Defined types are interned, so two names bound to the same handle
+                    // are bound to the same type.
+                    true
+                } else if j > env.get_type_recursion_limit() / 2
                     && compared_symbols.contains(&(a.clone(), b.clone()))
                 {
                     // If we've seen this comparison of these two symbols before, and we've done
@@ -1751,7 +1950,10 @@ impl Type {
                     // we just say the types are unequal if we cannot simplify symbols further.
                     compared_symbols.insert((a.clone(), b.clone()));
                     match (env.get_type(a), env.get_type(b)) {
-                        (Some(a), Some(b)) => a.equals_checked(b, compared_symbols, env, i)?,
+                        (Some(a), Some(b)) => {
+                            worklist.push((a.clone(), b.clone(), j));
+                            true
+                        }
                         _ => false,
                     }
                 }
@@ -1760,7 +1962,10 @@ impl Type {
                 // To get as much specific information as possible about type errors,
                 // we just say the types are unequal if we cannot simplify symbols further.
                 match env.get_type(x) {
-                    Some(t) => t.equals_checked(y, compared_symbols, env, i)?,
+                    Some(t) => {
+                        worklist.push((t.clone(), y.clone(), j));
+                        true
+                    }
                     None => false,
                 }
             }
@@ -1769,36 +1974,37 @@ impl Type {
                 if !t1.contains_symbol(name1) {
                     // If the type we're binding doesn't contain itself, then we can
                     // just substitute it in the body and compare it to the other type.
-                    ret1.substitute(name1, t1)
-                        .equals_checked(other, compared_symbols, env, i)?
+                    worklist.push((ret1.substitute(name1, t1), other.clone(), j));
+                    true
                 } else if !t2.contains_symbol(name2) {
                     // If the type we're binding doesn't contain itself, then we can
                     // just substitute it in the body and compare it to the other type.
-                    self.equals_checked(&ret2.substitute(name2, t2), compared_symbols, env, i)?
+                    worklist.push((self.clone(), ret2.substitute(name2, t2), j));
+                    true
                 } else {
                     // Otherwise, we are comparing recursive types.
 
                     // Check if the bound types are equal when substituting in the name of the other let's bound variable.
                     // Then, confirm that the results of the let bodies are equal under the substitution.
-                    t2.equals_checked(
-                        &t1.substitute(name1, &Self::Symbol(name2.clone())),
-                        compared_symbols,
-                        env,
-                        i,
-                    )? && ret2.equals_checked(
-                        &ret1.substitute(name1, &Self::Symbol(name2.clone())),
-                        compared_symbols,
-                        env,
-                        i,
-                    )?
+                    worklist.push((
+                        *t2.clone(),
+                        t1.substitute(name1, &Self::Symbol(name2.clone())),
+                        j,
+                    ));
+                    worklist.push((
+                        *ret2.clone(),
+                        ret1.substitute(name1, &Self::Symbol(name2.clone())),
+                        j,
+                    ));
+                    true
                 }
             }
             (Self::Let(name, t, ret), x) | (x, Self::Let(name, t, ret)) => {
                 if !t.contains_symbol(name) {
                     // If the type we're binding doesn't contain itself, then we can
                     // just substitute it in the body and compare it to the other type.
-                    ret.substitute(name, t)
-                        .equals_checked(x, compared_symbols, env, i)?
+                    worklist.push((ret.substitute(name, t), x.clone(), j));
+                    true
                 } else {
                     // If the type does contain itself, we'll have to do some more legwork.
                     // Create a new environment with the type binding.
@@ -1817,22 +2023,38 @@ impl Type {
                     );
 
                     // Check if the two types are equal under the new environment.
-                    ret.equals_checked(x, compared_symbols, &new_env, i)?
+                    ret.equals_checked(x, compared_symbols, &new_env, j)?
                 }
             }
 
             // If we're comparing two units, then we can just compare their names and confirm
             // their structures are equal.
             (Self::Unit(unit_name1, t1), Self::Unit(unit_name2, t2)) => {
-                unit_name1 == unit_name2 && t1.equals_checked(t2, compared_symbols, env, i)?
+                worklist.push((*t1.clone(), *t2.clone(), i));
+                unit_name1 == unit_name2
             }
 
-            (Self::Enum(a), Self::Enum(b)) => {
+            (Self::Enum(a, repr1), Self::Enum(b, repr2)) => {
                 let mut a = a.clone();
                 let mut b = b.clone();
                 a.sort();
                 b.sort();
-                a == b
+                a == b && repr1 == repr2
+            }
+
+            (Self::Bitfield(a), Self::Bitfield(b)) => a == b,
+
+            (Self::Repr(a, align1), Self::Repr(b, align2)) => {
+                if a.len() != b.len() || align1 != align2 {
+                    return Ok(false);
+                }
+                for ((name1, item1), (name2, item2)) in a.iter().zip(b.iter()) {
+                    if name1 != name2 {
+                        return Ok(false);
+                    }
+                    worklist.push((item1.clone(), item2.clone(), i));
+                }
+                true
             }
 
             (Self::Tuple(a), Self::Tuple(b)) => {
@@ -1840,24 +2062,23 @@ impl Type {
                     return Ok(false);
                 }
                 for (item1, item2) in a.iter().zip(b.iter()) {
-                    if !item1.equals_checked(item2, compared_symbols, env, i)? {
-                        return Ok(false);
-                    }
+                    worklist.push((item1.clone(), item2.clone(), i));
                 }
                 true
             }
             (Self::Array(t1, size1), Self::Array(t2, size2)) => {
-                t1.equals_checked(t2, compared_symbols, env, i)?
-                    && size1.clone().eval(env)?.equals(&size2.clone().eval(env)?, env)
+                worklist.push((*t1.clone(), *t2.clone(), i));
+                size1.clone().eval(env)?.equals(&size2.clone().eval(env)?, env)
             }
             (Self::Struct(a), Self::Struct(b)) => {
                 if a.len() != b.len() {
                     return Ok(false);
                 }
                 for ((name1, item1), (name2, item2)) in a.iter().zip(b.iter()) {
-                    if name1 != name2 || !item1.equals_checked(item2, compared_symbols, env, i)? {
+                    if name1 != name2 {
                         return Ok(false);
                     }
+                    worklist.push((item1.clone(), item2.clone(), i));
                 }
                 true
             }
@@ -1867,21 +2088,23 @@ impl Type {
                     return Ok(false);
                 }
                 for ((name1, item1), (name2, item2)) in a.iter().zip(b.iter()) {
-                    if name1 != name2 || !item1.equals_checked(item2, compared_symbols, env, i)? {
+                    if name1 != name2 {
                         return Ok(false);
                     }
+                    worklist.push((item1.clone(), item2.clone(), i));
                 }
                 true
             }
 
-            (Self::EnumUnion(a), Self::EnumUnion(b)) => {
-                if a.len() != b.len() {
+            (Self::EnumUnion(a, repr1), Self::EnumUnion(b, repr2)) => {
+                if a.len() != b.len() || repr1 != repr2 {
                     return Ok(false);
                 }
                 for ((name1, item1), (name2, item2)) in a.iter().zip(b.iter()) {
-                    if name1 != name2 || !item1.equals_checked(item2, compared_symbols, env, i)? {
+                    if name1 != name2 {
                         return Ok(false);
                     }
+                    worklist.push((item1.clone(), item2.clone(), i));
                 }
                 true
             }
@@ -1891,15 +2114,15 @@ impl Type {
                     return Ok(false);
                 }
                 for (arg1, arg2) in args1.iter().zip(args2.iter()) {
-                    if !arg1.equals_checked(arg2, compared_symbols, env, i)? {
-                        return Ok(false);
-                    }
+                    worklist.push((arg1.clone(), arg2.clone(), i));
                 }
-                ret1.equals_checked(ret2, compared_symbols, env, i)?
+                worklist.push((*ret1.clone(), *ret2.clone(), i));
+                true
             }
 
             (Self::Pointer(m1, t1), Self::Pointer(m2, t2)) => {
-                m1 == m2 && t1.equals_checked(t2, compared_symbols, env, i)?
+                worklist.push((*t1.clone(), *t2.clone(), i));
+                m1 == m2
             }
 
             (Self::Poly(ty_params1, template1), Self::Poly(ty_params2, template2)) => {
@@ -1910,7 +2133,8 @@ impl Type {
                 if ty_params1 == ty_params2 {
                     // If the two templates have the same type parameters, then we can just compare
                     // the two templates.
-                    return template1.equals_checked(template2, compared_symbols, env, i);
+                    worklist.push((*template1.clone(), *template2.clone(), i));
+                    return Ok(true);
                 }
 
                 // Create a new environment.
@@ -1938,11 +2162,11 @@ impl Type {
                 }
 
                 // Now, we can compare the two templates under the new environment.
-                template1.equals_checked(template2, compared_symbols, &new_env, i)?
+                template1.equals_checked(template2, compared_symbols, &new_env, j)?
             }
 
             (Self::Apply(poly1, ty_args1), Self::Apply(poly2, ty_args2)) => {
-                if poly1.equals_checked(poly2, compared_symbols, env, i)? {
+                if poly1.equals_checked(poly2, compared_symbols, env, j)? {
                     // If the two polymorphic types are equal, then we can just compare the two
                     // types' parameters.
                     if ty_args1.len() != ty_args2.len() {
@@ -1951,10 +2175,7 @@ impl Type {
 
                     // Iterate over the two type arguments and compare them.
                     for (arg1, arg2) in ty_args1.iter().zip(ty_args2.iter()) {
-                        // If the two arguments are not equal, then the two types are not equal.
-                        if !arg1.equals_checked(arg2, compared_symbols, env, i)? {
-                            return Ok(false);
-                        }
+                        worklist.push((arg1.clone(), arg2.clone(), i));
                     }
 
                     true
@@ -1963,13 +2184,15 @@ impl Type {
                     for ((param, _), arg) in ty_params.iter().zip(ty_args1.iter()) {
                         template = template.substitute(param, arg);
                     }
-                    template.equals_checked(other, compared_symbols, env, i)?
+                    worklist.push((template, other.clone(), j));
+                    true
                 } else if let Self::Poly(ty_params, template) = poly2.clone().simplify(env)? {
                     let mut template = *template.clone();
                     for ((param, _), arg) in ty_params.iter().zip(ty_args2.iter()) {
                         template = template.substitute(param, arg);
                     }
-                    template.equals_checked(other, compared_symbols, env, i)?
+                    worklist.push((template, other.clone(), j));
+                    true
                 } else {
                     // If the two polymorphic types are not equal, then we can't just compare the two
                     // types' parameters. We need to simplify the types first.
@@ -1984,14 +2207,19 @@ impl Type {
             }
 
             (Self::Apply(poly, ty_args), b) | (b, Self::Apply(poly, ty_args)) => {
-                Self::Apply(poly.clone(), ty_args.clone())
-                    .simplify_until_concrete(env, false)?
-                    .equals_checked(b, compared_symbols, env, i)?
+                worklist.push((
+                    Self::Apply(poly.clone(), ty_args.clone())
+                        .simplify_until_concrete(env, false)?,
+                    b.clone(),
+                    j,
+                ));
+                true
             }
 
             (Self::ConstParam(a), Self::ConstParam(b)) => a == b,
             (Self::ConstParam(cexpr), other) | (other, Self::ConstParam(cexpr)) => {
-                cexpr.get_type(env)?.equals_checked(other, compared_symbols, env, i)?
+                worklist.push((cexpr.get_type(env)?, other.clone(), j));
+                true
             },
             (_a, _b) => {
                 // error!("{} is not equal to {}", a, b);
@@ -2048,6 +2276,25 @@ impl Type {
                 Some(t) => Ok((t.clone().simplify(env)?, 0)),
                 None => Err(Error::MemberNotFound(expr.clone(), member.clone())),
             },
+            Type::Repr(fields, align) => {
+                let align = (*align).max(1);
+                let mut offset: usize = 0;
+                for (k, t) in fields {
+                    // Pad the member's offset up to the alignment.
+                    offset = offset.next_multiple_of(align);
+                    if &ConstExpr::Symbol(k.clone()) == member {
+                        return Ok((t.clone().simplify(env)?, offset));
+                    }
+                    offset += t.get_size(env)?;
+                }
+                Err(Error::MemberNotFound(expr.clone(), member.clone()))
+            }
+            // A bitfield member is read as an integer. The offset is the
+            // offset of the cell which contains the member's bits.
+            Type::Bitfield(_) => match self.get_bitfield_member(member, env)? {
+                Some((offset, _, _)) => Ok((Type::Int, offset)),
+                None => Err(Error::MemberNotFound(expr.clone(), member.clone())),
+            },
             Type::Let(name, t, ret) => {
                 // Create a new scope and define the new type within it
                 let mut new_env = env.clone();
@@ -2149,6 +2396,22 @@ impl Type {
                     Err(Error::MemberNotFound(expr.clone(), member.clone()))
                 }
             }
+            Type::Repr(fields, _) => {
+                let name = member.clone().as_symbol(env)?;
+                if fields.iter().any(|(k, _)| k == &name) || env.has_associated_const(self, &name) {
+                    Ok(())
+                } else {
+                    Err(Error::MemberNotFound(expr.clone(), member.clone()))
+                }
+            }
+            Type::Bitfield(fields) => {
+                let name = member.clone().as_symbol(env)?;
+                if fields.iter().any(|(k, _)| k == &name) || env.has_associated_const(self, &name) {
+                    Ok(())
+                } else {
+                    Err(Error::MemberNotFound(expr.clone(), member.clone()))
+                }
+            }
             Type::Union(types) => {
                 let name = member.clone().as_symbol(env)?;
                 match types.get(&name) {
@@ -2216,14 +2479,25 @@ impl Type {
 }
 
 impl Simplify for Type {
-    fn simplify_checked(self, env: &Env, i: usize) -> Result<Self, Error> {
+    fn simplify_checked(mut self, env: &Env, depth: usize) -> Result<Self, Error> {
         trace!("Simplifying type {self} in environment {env}");
+        // Substitute away non-recursive let-bindings iteratively, so that long
+        // chains of bindings don't count toward the recursion limit.
+        while let Self::Let(name, t, ret) = &self {
+            if t.contains_symbol(name) {
+                break;
+            }
+            let body = ret.substitute(name, t);
+            self = body;
+        }
         if self.is_atomic() {
             return Ok(self);
         }
 
-        let i = i + 1;
-        if i > Self::SIMPLIFY_RECURSION_LIMIT {
+        // Only simplifying under a new binding counts toward the recursion limit,
+        // the members of a type are simplified at the same depth as the type itself.
+        let i = depth + 1;
+        if i > env.get_type_recursion_limit() {
             error!("Recursion depth limit reached while simplifying type {self}");
             return Err(Error::UnsizedType(self.clone()));
         }
@@ -2247,7 +2521,8 @@ impl Simplify for Type {
             | Self::Char
             | Self::Bool
             | Self::Cell
-            | Self::Enum(_) => self.clone(),
+            | Self::Enum(..)
+            | Self::Bitfield(_) => self.clone(),
             Self::Poly(mut ty_params, body) => {
                 for (_name, ty) in &mut ty_params {
                     if let Some(ty) = ty {
@@ -2257,7 +2532,7 @@ impl Simplify for Type {
                 Self::Poly(ty_params, body)
             }
             Self::Pointer(mutability, inner) => {
-                Self::Pointer(mutability, Box::new(inner.simplify_checked(env, i)?))
+                Self::Pointer(mutability, Box::new(inner.simplify_checked(env, depth)?))
             }
 
             Self::Let(name, t, ret) => {
@@ -2286,7 +2561,7 @@ impl Simplify for Type {
             }
 
             Self::Unit(unit_name, t) => {
-                Self::Unit(unit_name, Box::new(t.simplify_checked(env, i)?))
+                Self::Unit(unit_name, Box::new(t.simplify_checked(env, depth)?))
             }
 
             Self::Symbol(ref name) => {
@@ -2301,20 +2576,20 @@ impl Simplify for Type {
 
             Self::Proc(args, ret) => Self::Proc(
                 args.into_iter()
-                    .flat_map(|t| t.simplify_checked(env, i))
+                    .flat_map(|t| t.simplify_checked(env, depth))
                     .collect(),
-                Box::new(ret.simplify_checked(env, i)?),
+                Box::new(ret.simplify_checked(env, depth)?),
             ),
 
             Self::Array(inner, size) => Self::Array(
-                Box::new(inner.simplify_checked(env, i)?),
+                Box::new(inner.simplify_checked(env, depth)?),
                 Box::new(size.eval(env)?),
             ),
 
             Self::Tuple(items) => {
                 let simple_items = items
                     .into_iter()
-                    .map(|t| t.simplify_checked(env, i))
+                    .map(|t| t.simplify_checked(env, depth))
                     .collect::<Result<Vec<_>, Error>>()?;
                 
                 if simple_items.iter().all(Self::is_const_param) {
@@ -2332,7 +2607,7 @@ impl Simplify for Type {
             Self::Struct(fields) => {
                 let simple_fields = fields
                     .into_iter()
-                    .map(|(name, t)| Ok((name, t.simplify_checked(env, i)?)))
+                    .map(|(name, t)| Ok((name, t.simplify_checked(env, depth)?)))
                     .collect::<Result<BTreeMap<_, _>, Error>>()?;
                 
                 if simple_fields.iter().map(|(_x, t)| t).all(Self::is_const_param) {
@@ -2350,14 +2625,22 @@ impl Simplify for Type {
             Self::Union(types) => Self::Union(
                 types
                     .into_iter()
-                    .map(|(k, t)| Ok((k, t.simplify_checked(env, i)?)))
+                    .map(|(k, t)| Ok((k, t.simplify_checked(env, depth)?)))
                     .collect::<Result<BTreeMap<String, Type>, Error>>()?,
             ),
-            Self::EnumUnion(types) => Self::EnumUnion(
+            Self::Repr(fields, align) => Self::Repr(
+                fields
+                    .into_iter()
+                    .map(|(k, t)| Ok((k, t.simplify_checked(env, depth)?)))
+                    .collect::<Result<Vec<_>, Error>>()?,
+                align,
+            ),
+            Self::EnumUnion(types, repr) => Self::EnumUnion(
                 types
                     .into_iter()
-                    .map(|(k, t)| Ok((k, t.simplify_checked(env, i)?)))
+                    .map(|(k, t)| Ok((k, t.simplify_checked(env, depth)?)))
                     .collect::<Result<BTreeMap<String, Type>, Error>>()?,
+                repr,
             ),
 
             Self::Apply(poly, ty_args) => {
@@ -2412,16 +2695,47 @@ impl fmt::Display for Type {
                 }
                 write!(f, ">")
             }
-            Self::Enum(variants) => {
-                write!(f, "enum {{")?;
+            Self::Enum(variants, repr) => {
+                write!(f, "enum")?;
+                if repr.tag_cells != 1 {
+                    write!(f, ": {}", repr.tag_cells)?;
+                }
+                write!(f, " {{")?;
                 for (i, variant) in variants.iter().enumerate() {
                     write!(f, "{variant}")?;
+                    if let Some(tag) = repr.tags.get(variant) {
+                        write!(f, " = {tag}")?;
+                    }
                     if i < variants.len() - 1 {
                         write!(f, ", ")?
                     }
                 }
                 write!(f, "}}")
             }
+            Self::Repr(fields, align) => {
+                if *align == 1 {
+                    write!(f, "struct packed {{")?;
+                } else {
+                    write!(f, "struct align({align}) {{")?;
+                }
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    write!(f, "{name}: {ty}")?;
+                    if i < fields.len() - 1 {
+                        write!(f, ", ")?
+                    }
+                }
+                write!(f, "}}")
+            }
+            Self::Bitfield(fields) => {
+                write!(f, "bitfield {{")?;
+                for (i, (name, bits)) in fields.iter().enumerate() {
+                    write!(f, "{name}: {bits}")?;
+                    if i < fields.len() - 1 {
+                        write!(f, ", ")?
+                    }
+                }
+                write!(f, "}}")
+            }
             Self::Tuple(items) => {
                 write!(f, "(")?;
                 for (i, item) in items.iter().enumerate() {
@@ -2452,10 +2766,17 @@ impl fmt::Display for Type {
                 }
                 write!(f, "}}")
             }
-            Self::EnumUnion(fields) => {
-                write!(f, "enum {{")?;
+            Self::EnumUnion(fields, repr) => {
+                write!(f, "enum")?;
+                if repr.tag_cells != 1 {
+                    write!(f, ": {}", repr.tag_cells)?;
+                }
+                write!(f, " {{")?;
                 for (i, (name, ty)) in fields.iter().enumerate() {
                     write!(f, "{name} {ty}")?;
+                    if let Some(tag) = repr.tags.get(name) {
+                        write!(f, " = {tag}")?;
+                    }
                     if i < fields.len() - 1 {
                         write!(f, ", ")?
                     }
@@ -2536,9 +2857,10 @@ impl std::hash::Hash for Type {
                 poly.hash(state);
                 ty_args.hash(state);
             }
-            Self::Enum(variants) => {
+            Self::Enum(variants, repr) => {
                 state.write_u8(12);
                 variants.hash(state);
+                repr.hash(state);
             }
             Self::Tuple(items) => {
                 state.write_u8(13);
@@ -2552,9 +2874,10 @@ impl std::hash::Hash for Type {
                 state.write_u8(15);
                 fields.hash(state);
             }
-            Self::EnumUnion(fields) => {
+            Self::EnumUnion(fields, repr) => {
                 state.write_u8(16);
                 fields.hash(state);
+                repr.hash(state);
             }
             Self::Proc(args, ret) => {
                 state.write_u8(17);
@@ -2584,6 +2907,15 @@ impl std::hash::Hash for Type {
                 state.write_u8(22);
                 cexpr.hash(state);
             }
+            Self::Bitfield(fields) => {
+                state.write_u8(23);
+                fields.hash(state);
+            }
+            Self::Repr(fields, align) => {
+                state.write_u8(24);
+                fields.hash(state);
+                align.hash(state);
+            }
         }
     }
 }
@@ -0,0 +1,128 @@
+//! # Type Reflection
+//!
+//! This module implements the reflection builtins, which describe a type as constant
+//! data at compile time. This lets libraries for things like serialization and debugging
+//! be written in the language itself.
+//!
+//! |Builtin|Value|
+//! |---|---|
+//! |`typename<T>()`|The name of `T`, as an array of characters|
+//! |`fieldnames<T>()`|The names of `T`'s members in layout order, as an array of names|
+//! |`fieldoffsets<T>()`|The offsets of `T`'s members in layout order, as an array of integers|
+//! |`variantnames<T>()`|The names of `T`'s variants, as an array of names|
+//!
+//! Names are null-terminated, like string literals. The names in an array of names are all
+//! padded with null characters to the length of the longest name, so that they have the
+//! same type. A type without any members or variants gives an empty array.
+
+use super::*;
+use serde_derive::{Deserialize, Serialize};
+
+/// A property of a type, which is reflected as constant data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Reflection {
+    /// The name of the type.
+    Name,
+    /// The names of the members of the type, in layout order.
+    FieldNames,
+    /// The offsets of the members of the type, in layout order.
+    FieldOffsets,
+    /// The names of the variants of the type.
+    VariantNames,
+}
+
+impl Reflection {
+    /// The name of the builtin for this reflection.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Name => "typename",
+            Self::FieldNames => "fieldnames",
+            Self::FieldOffsets => "fieldoffsets",
+            Self::VariantNames => "variantnames",
+        }
+    }
+
+    /// Get the reflection builtin with the given name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Name,
+            Self::FieldNames,
+            Self::FieldOffsets,
+            Self::VariantNames,
+        ]
+        .into_iter()
+        .find(|reflection| reflection.name() == name)
+    }
+
+    /// Reflect this property of a type as a constant value.
+    pub fn reflect(&self, ty: &Type, env: &Env) -> Result<ConstExpr, Error> {
+        Ok(match self {
+            Self::Name => {
+                let name = ty.to_string();
+                chars(&name, name.chars().count() + 1)
+            }
+            Self::FieldNames => names(
+                Self::members(ty, env)?
+                    .into_iter()
+                    .map(|(name, _)| name)
+                    .collect(),
+            ),
+            Self::FieldOffsets => ConstExpr::Array(
+                Self::members(ty, env)?
+                    .into_iter()
+                    .map(|(_, member)| Ok(ConstExpr::Int(ty.get_offset_of(&member, env)? as i64)))
+                    .collect::<Result<_, Error>>()?,
+            ),
+            Self::VariantNames => {
+                let variants = match ty.simplify_until_has_variants(env, false)? {
+                    Type::Enum(variants, _) => variants,
+                    Type::EnumUnion(variants, _) => variants.into_keys().collect(),
+                    _ => vec![],
+                };
+                names(variants)
+            }
+        })
+    }
+
+    /// Get the name of each member of a type in layout order, along with the
+    /// constant which accesses the member. Bitfield members are in declared order.
+    fn members(ty: &Type, env: &Env) -> Result<Vec<(String, ConstExpr)>, Error> {
+        let symbol = |name: &String| (name.clone(), ConstExpr::Symbol(name.clone()));
+        Ok(match ty.simplify_until_has_members(env, false)? {
+            Type::Struct(fields) => fields.keys().map(symbol).collect(),
+            Type::Union(fields) => fields.keys().map(symbol).collect(),
+            Type::Repr(fields, _) => fields.iter().map(|(name, _)| symbol(name)).collect(),
+            Type::Bitfield(fields) => fields.iter().map(|(name, _)| symbol(name)).collect(),
+            Type::Tuple(items) => (0..items.len())
+                .map(|i| (i.to_string(), ConstExpr::Int(i as i64)))
+                .collect(),
+            _ => vec![],
+        })
+    }
+}
+
+/// An array of the characters of a name, padded with null characters to a length.
+fn chars(name: &str, len: usize) -> ConstExpr {
+    let mut result: Vec<ConstExpr> = name.chars().map(ConstExpr::Char).collect();
+    while result.len() < len {
+        result.push(ConstExpr::Char('\0'));
+    }
+    ConstExpr::Array(result)
+}
+
+/// An array of names, each padded to the length of the longest name
+/// and its null terminator.
+fn names(names: Vec<String>) -> ConstExpr {
+    let len = names
+        .iter()
+        .map(|name| name.chars().count() + 1)
+        .max()
+        .unwrap_or(0);
+    ConstExpr::Array(names.iter().map(|name| chars(name, len)).collect())
+}
+
+impl fmt::Display for Reflection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -22,11 +22,26 @@
 //! |`union {a: A, b: B, ... i: Int = 5}`|The max size of any type of `A`, `B`, ...|
 //! |`proc(A, B, ...) -> T`|1|
 //! |`enum {A, B, ...}`|1|
+//! |`struct packed {a: A, b: B, ...}`|The sum of the sizes of `A`, `B`, ...|
+//! |`struct align(N) {a: A, b: B, ...}`|The sum of the sizes of `A`, `B`, ..., each padded to a multiple of `N`|
+//! |`bitfield {a: 3, b: 5, ...}`|The number of cells needed to pack the fields|
+//!
+//! With field reordering enabled (see `Env::set_field_reordering`), the fields of a bitfield
+//! are packed widest first, which can take fewer cells than packing them in declared order.
+//!
+//! Every sized type is aligned to a single cell, except for `struct align(N)`, which is
+//! aligned to `N` cells. Arrays, tuples, structs, and unions are aligned to their most
+//! aligned member.
 
 use super::*;
 use log::*;
 use rayon::prelude::*;
 
+/// The number of bits in a single cell on the virtual machine, and the widest cell
+/// on any target. Targets with narrower cells set the `cell_width` compile parameter
+/// (see `Env::get_cell_width`).
+pub const BITS_PER_CELL: usize = 64;
+
 /// Get the size of something in memory (number of cells).
 pub trait GetSize {
     /// Get the size of something in memory (number of cells).
@@ -42,11 +57,13 @@ pub trait GetSize {
 
 /// Calculate the size of a type in units of cells.
 impl GetSize for Type {
-    fn get_size_checked(&self, env: &Env, i: usize) -> Result<usize, Error> {
-        let i = i + 1;
+    fn get_size_checked(&self, env: &Env, depth: usize) -> Result<usize, Error> {
+        // Only unfolding named types counts toward the recursion limit,
+        // the members of a type are sized at the same depth as the type itself.
+        let i = depth + 1;
         trace!("Getting the size of type {self} in environment {env} with depth {i}");
 
-        if i > Type::SIMPLIFY_RECURSION_LIMIT {
+        if i > env.get_type_recursion_limit() {
             error!("Recursion limit reached while calculating size of type {self}");
             return Err(Error::UnsizedType(self.clone()));
         }
@@ -58,6 +75,10 @@ impl GetSize for Type {
             });
         }
 
+        if let Some(size) = env.get_cached_size(self) {
+            return Ok(size);
+        }
+
         if self.is_const_param() {
             let cexpr = self.clone().simplify_until_const_param(env, false)?;
             return cexpr.get_size_checked(env, i)
@@ -100,7 +121,7 @@ impl GetSize for Type {
             }
 
             // Get the size of a unit type. (Its size is the size of its inner type.)
-            Self::Unit(_unit_name, t) => t.get_size_checked(env, i)?,
+            Self::Unit(_unit_name, t) => t.get_size_checked(env, depth)?,
 
             // These types are all one cell.
             Self::Int
@@ -108,26 +129,28 @@ impl GetSize for Type {
             | Self::Char
             | Self::Bool
             | Self::Cell
-            | Self::Enum(_)
             | Self::Pointer(_, _)
             | Self::Proc(_, _) => 1,
 
+            // Enum types are the size of their tag.
+            Self::Enum(_, repr) => repr.tag_cells,
+
             // Tuple types are the sum of the sizes of their elements.
             Self::Tuple(items) => items
                 .par_iter()
-                .flat_map(|t| t.get_size_checked(env, i))
+                .flat_map(|t| t.get_size_checked(env, depth))
                 .sum(),
             // Array types are the size of their element type times the size of
             // the array.
             Self::Array(elem, size) => {
-                elem.get_size_checked(env, i)? * size.clone().as_int(env)? as usize
+                elem.get_size_checked(env, depth)? * size.clone().as_int(env)? as usize
             }
             // Struct types are the sum of the sizes of their fields.
             Self::Struct(fields) => fields
                 // Make an iterator over the fields.
                 .par_iter()
                 // Get the size of each field.
-                .map(|(_, t)| t.get_size_checked(env, i))
+                .map(|(_, t)| t.get_size_checked(env, depth))
                 // Catch any errors.
                 .collect::<Result<Vec<_>, _>>()?
                 .into_iter()
@@ -138,7 +161,7 @@ impl GetSize for Type {
                 // Make an iterator over the fields.
                 .par_iter()
                 // Get the size of each field.
-                .map(|(_, t)| t.get_size_checked(env, i))
+                .map(|(_, t)| t.get_size_checked(env, depth))
                 // Catch any errors.
                 .collect::<Result<Vec<_>, _>>()?
                 .into_iter()
@@ -147,13 +170,30 @@ impl GetSize for Type {
                 // If there are no fields, just return 0.
                 .unwrap_or(0),
 
-            // EnumUnion types are the size of the largest field + 1 (for the tag). (All other fields are padded to this size.)
-            Self::EnumUnion(types) => {
+            // Structs with a guaranteed layout are the sum of the sizes of their fields,
+            // with each field padded up to the alignment.
+            Self::Repr(fields, align) => {
+                let align = (*align).max(1);
+                let mut size: usize = 0;
+                for (_, t) in fields {
+                    size = size.next_multiple_of(align) + t.get_size_checked(env, depth)?;
+                }
+                size.next_multiple_of(align)
+            }
+
+            // Bitfield types are the number of cells needed to pack their fields.
+            Self::Bitfield(fields) => Self::pack_bitfield(fields, env)
+                .last()
+                .map(|(_, cell, _, _)| cell + 1)
+                .unwrap_or(0),
+
+            // EnumUnion types are the size of the largest field + the size of the tag. (All other fields are padded to this size.)
+            Self::EnumUnion(types, repr) => {
                 types
                 // Make an iterator over the fields.
                 .par_iter()
                 // Get the size of each field.
-                .map(|(_, t)| t.get_size_checked(env, i))
+                .map(|(_, t)| t.get_size_checked(env, depth))
                 // Catch any errors.
                 .collect::<Result<Vec<_>, _>>()?
                 .into_iter()
@@ -161,8 +201,8 @@ impl GetSize for Type {
                 .max()
                 // If there are no fields, just return 0.
                 .unwrap_or(0)
-                // Add 1 for the tag.
-                + 1
+                // Add the size of the tag.
+                + repr.tag_cells
             }
 
             // Get the size of an `Apply` type.
@@ -182,12 +222,143 @@ impl GetSize for Type {
         };
 
         // env.set_precalculated_size(self.clone(), result);
+        env.cache_size(self.clone(), result);
 
         debug!("Size of type {self} is {result}");
         Ok(result)
     }
 }
 
+impl Type {
+    /// Get the alignment of a type in units of cells.
+    ///
+    /// Every value is stored in whole cells, so most types are aligned to a single cell.
+    /// A `struct align(N)` is aligned to `N` cells, and a type containing one is aligned
+    /// to the largest alignment of its members.
+    pub fn get_alignment(&self, env: &Env) -> Result<usize, Error> {
+        // Confirm that the type is sized.
+        self.get_size(env)?;
+        self.get_alignment_checked(env, 0)
+    }
+
+    /// Get the alignment of a type, limiting the number of named types unfolded.
+    fn get_alignment_checked(&self, env: &Env, depth: usize) -> Result<usize, Error> {
+        if depth > env.get_type_recursion_limit() {
+            return Err(Error::UnsizedType(self.clone()));
+        }
+        // The largest alignment of some members, or a single cell if there are none.
+        let max = |types: &mut dyn Iterator<Item = &Type>| -> Result<usize, Error> {
+            let mut result = 1;
+            for t in types {
+                result = result.max(t.get_alignment_checked(env, depth)?);
+            }
+            Ok(result)
+        };
+        Ok(match self {
+            Self::Repr(_, align) => (*align).max(1),
+            Self::Unit(_, t) | Self::Array(t, _) => t.get_alignment_checked(env, depth)?,
+            Self::Tuple(items) => max(&mut items.iter())?,
+            Self::Struct(fields) | Self::Union(fields) | Self::EnumUnion(fields, _) => {
+                max(&mut fields.values())?
+            }
+            Self::Let(..) | Self::Symbol(_) | Self::Apply(..) | Self::ConstParam(_) => {
+                let t = self.simplify_until_concrete(env, false)?;
+                if t == *self {
+                    1
+                } else {
+                    t.get_alignment_checked(env, depth + 1)?
+                }
+            }
+            _ => 1,
+        })
+    }
+
+    /// Get the offset of a member of a struct, tuple, or union type in units
+    /// of cells, using the same layout as member access.
+    pub fn get_offset_of(&self, member: &ConstExpr, env: &Env) -> Result<usize, Error> {
+        let expr = Expr::ConstExpr(ConstExpr::Type(self.clone()));
+        let (_, offset) = self
+            .simplify_until_has_members(env, false)?
+            .get_member_offset(member, &expr, env)?;
+        Ok(offset)
+    }
+
+    /// Pack the fields of a bitfield into cells. This returns each field's name,
+    /// the offset of the cell it's stored in, the offset of its least significant
+    /// bit within that cell, and its width in bits.
+    ///
+    /// Fields are packed in declaration order. A field which doesn't fit in the
+    /// remaining bits of the current cell starts at the beginning of the next one.
+    /// Cells are as wide as they are on the target.
+    ///
+    /// With field reordering, the fields are instead packed widest first, each into
+    /// the first cell with enough bits left for it. The result is sorted by position.
+    fn pack_bitfield(fields: &[(String, usize)], env: &Env) -> Vec<(String, usize, usize, usize)> {
+        let width = env.get_cell_width();
+        if env.has_field_reordering() {
+            let mut fields = fields.to_vec();
+            // Ties keep their declared order.
+            fields.sort_by(|(_, a), (_, b)| b.cmp(a));
+            // The number of bits used in each cell.
+            let mut used: Vec<usize> = vec![];
+            let mut result = Vec::with_capacity(fields.len());
+            for (name, bits) in fields {
+                let cell = match used.iter().position(|n| n + bits <= width) {
+                    Some(cell) => cell,
+                    None => {
+                        used.push(0);
+                        used.len() - 1
+                    }
+                };
+                result.push((name, cell, used[cell], bits));
+                used[cell] += bits;
+            }
+            result.sort_by_key(|(_, cell, shift, _)| (*cell, *shift));
+            return result;
+        }
+
+        let mut result = Vec::with_capacity(fields.len());
+        let mut cell = 0;
+        let mut shift = 0;
+        for (name, bits) in fields {
+            if shift + bits > width && shift > 0 {
+                cell += 1;
+                shift = 0;
+            }
+            result.push((name.clone(), cell, shift, *bits));
+            shift += bits;
+        }
+        result
+    }
+
+    /// Get the location of a member of a bitfield type. This returns the offset
+    /// of the cell containing the member, the offset of the member's least
+    /// significant bit within that cell, and the member's width in bits.
+    ///
+    /// If the type is not a bitfield, or the bitfield does not have the member,
+    /// this returns `None`.
+    pub fn get_bitfield_member(
+        &self,
+        member: &ConstExpr,
+        env: &Env,
+    ) -> Result<Option<(usize, usize, usize)>, Error> {
+        match self.simplify_until_has_members(env, false)? {
+            Self::Bitfield(fields) => {
+                let name = match member.clone().as_symbol(env) {
+                    Ok(name) => name,
+                    Err(_) => return Ok(None),
+                };
+                Ok(Self::pack_bitfield(&fields, env)
+                    .into_iter()
+                    .find(|(k, _, _, _)| k == &name)
+                    .map(|(_, cell, shift, bits)| (cell, shift, bits)))
+            }
+            Self::Unit(_, t) | Self::Pointer(_, t) => t.get_bitfield_member(member, env),
+            _ => Ok(None),
+        }
+    }
+}
+
 /// Implement `GetSize` for any type that implements `GetType`.
 ///
 /// We can do this because the size of a variable should be exactly
@@ -0,0 +1,750 @@
+//! # Visitors and Folds
+//!
+//! This module contains two traits for traversing LIR code without writing
+//! a match over every variant of every node:
+//!
+//! - `Visit` walks over a borrowed program, to analyze it.
+//! - `Fold` takes ownership of a program and rebuilds it, to transform it.
+//!
+//! Each trait has a method for each kind of node: expressions, constant expressions,
+//! types, patterns, declarations, and procedures. By default, each method just
+//! traverses the children of the node with the matching `walk_*` (for `Visit`)
+//! or `fold_*_children` (for `Fold`) function. Override the methods for the nodes
+//! you care about, and call the traversal function to continue into their children.
+//!
+//! ```rust
+//! use sage::lir::*;
+//!
+//! /// Count the number of integer literals in a program.
+//! struct CountInts(usize);
+//!
+//! impl<'a> Visit<'a> for CountInts {
+//!     fn visit_const(&mut self, expr: &'a ConstExpr) {
+//!         if let ConstExpr::Int(_) = expr {
+//!             self.0 += 1;
+//!         }
+//!         walk_const(self, expr);
+//!     }
+//! }
+//!
+//! let mut counter = CountInts(0);
+//! counter.visit_expr(&Expr::from(ConstExpr::Int(1)).add(ConstExpr::Int(2)));
+//! assert_eq!(counter.0, 2);
+//! ```
+//!
+//! Builtin and foreign procedures are the leaves of the traversal: their signatures
+//! are provided by the host, so the types inside of them aren't visited.
+use super::{ConstExpr, Declaration, Expr, Pattern, PolyProcedure, Procedure, Type};
+use std::sync::Arc;
+
+/// A traversal over a borrowed LIR program.
+///
+/// The lifetime `'a` is the lifetime of the program, so visitors can
+/// keep references to the nodes they visit.
+pub trait Visit<'a> {
+    /// Visit an expression.
+    fn visit_expr(&mut self, expr: &'a Expr) {
+        walk_expr(self, expr)
+    }
+
+    /// Visit a constant expression.
+    fn visit_const(&mut self, expr: &'a ConstExpr) {
+        walk_const(self, expr)
+    }
+
+    /// Visit a type.
+    fn visit_type(&mut self, ty: &'a Type) {
+        walk_type(self, ty)
+    }
+
+    /// Visit a pattern.
+    fn visit_pattern(&mut self, pat: &'a Pattern) {
+        walk_pattern(self, pat)
+    }
+
+    /// Visit a declaration.
+    fn visit_declaration(&mut self, decl: &'a Declaration) {
+        walk_declaration(self, decl)
+    }
+
+    /// Visit a monomorphic procedure.
+    fn visit_procedure(&mut self, proc: &'a Procedure) {
+        walk_procedure(self, proc)
+    }
+
+    /// Visit a polymorphic procedure.
+    fn visit_poly_procedure(&mut self, proc: &'a PolyProcedure) {
+        walk_poly_procedure(self, proc)
+    }
+}
+
+/// Visit the children of an expression.
+pub fn walk_expr<'a, V: Visit<'a> + ?Sized>(v: &mut V, expr: &'a Expr) {
+    match expr {
+        Expr::Annotated(inner, _) => v.visit_expr(inner),
+        Expr::ConstExpr(cexpr) => v.visit_const(cexpr),
+        Expr::Declare(decl, body) => {
+            v.visit_declaration(decl);
+            v.visit_expr(body);
+        }
+        Expr::When(cond, then, otherwise) => {
+            v.visit_const(cond);
+            v.visit_expr(then);
+            v.visit_expr(otherwise);
+        }
+        Expr::Match(inner, arms) => {
+            v.visit_expr(inner);
+            for (pat, arm) in arms {
+                v.visit_pattern(pat);
+                v.visit_expr(arm);
+            }
+        }
+        Expr::IfLet(pat, inner, then, otherwise) => {
+            v.visit_pattern(pat);
+            v.visit_expr(inner);
+            v.visit_expr(then);
+            v.visit_expr(otherwise);
+        }
+        Expr::WhileLet(pat, inner, body) => {
+            v.visit_pattern(pat);
+            v.visit_expr(inner);
+            v.visit_expr(body);
+        }
+        Expr::Union(ty, _, inner) | Expr::EnumUnion(ty, _, inner) => {
+            v.visit_type(ty);
+            v.visit_expr(inner);
+        }
+        Expr::As(inner, ty) => {
+            v.visit_expr(inner);
+            v.visit_type(ty);
+        }
+        Expr::Member(inner, field) => {
+            v.visit_expr(inner);
+            v.visit_const(field);
+        }
+        Expr::Apply(f, args) => {
+            v.visit_expr(f);
+            args.iter().for_each(|arg| v.visit_expr(arg));
+        }
+        Expr::Many(exprs) | Expr::Array(exprs) | Expr::Tuple(exprs) => {
+            exprs.iter().for_each(|expr| v.visit_expr(expr));
+        }
+        Expr::ArrayRepeat(elem, len) => {
+            v.visit_expr(elem);
+            v.visit_const(len);
+        }
+        Expr::Struct(fields) => fields.values().for_each(|expr| v.visit_expr(expr)),
+        Expr::UnaryOp(_, a) | Expr::Refer(_, a) | Expr::Deref(a) | Expr::Return(a) => {
+            v.visit_expr(a)
+        }
+        Expr::While(a, b)
+        | Expr::BinaryOp(_, a, b)
+        | Expr::AssignOp(_, a, b)
+        | Expr::DerefMut(a, b)
+        | Expr::Index(a, b) => {
+            v.visit_expr(a);
+            v.visit_expr(b);
+        }
+        Expr::If(a, b, c) | Expr::TernaryOp(_, a, b, c) => {
+            v.visit_expr(a);
+            v.visit_expr(b);
+            v.visit_expr(c);
+        }
+        Expr::Hole(_) => {}
+    }
+}
+
+/// Visit the children of a constant expression.
+pub fn walk_const<'a, V: Visit<'a> + ?Sized>(v: &mut V, expr: &'a ConstExpr) {
+    match expr {
+        ConstExpr::Annotated(inner, _) => v.visit_const(inner),
+        ConstExpr::Declare(decl, body) => {
+            v.visit_declaration(decl);
+            v.visit_const(body);
+        }
+        ConstExpr::TypeOf(inner) | ConstExpr::SizeOfExpr(inner) => v.visit_expr(inner),
+        ConstExpr::Of(ty, _)
+        | ConstExpr::SizeOfType(ty)
+        | ConstExpr::AlignOfType(ty)
+        | ConstExpr::Reflect(ty, _)
+        | ConstExpr::Type(ty) => v.visit_type(ty),
+        ConstExpr::OffsetOf(ty, inner)
+        | ConstExpr::Union(ty, _, inner)
+        | ConstExpr::EnumUnion(ty, _, inner) => {
+            v.visit_type(ty);
+            v.visit_const(inner);
+        }
+        ConstExpr::As(inner, ty) => {
+            v.visit_const(inner);
+            v.visit_type(ty);
+        }
+        ConstExpr::Tuple(items) | ConstExpr::Array(items) => {
+            items.iter().for_each(|item| v.visit_const(item));
+        }
+        ConstExpr::Table(_, start, end, elem) => {
+            v.visit_const(start);
+            v.visit_const(end);
+            v.visit_expr(elem);
+        }
+        ConstExpr::Struct(fields) => fields.values().for_each(|item| v.visit_const(item)),
+        ConstExpr::Proc(proc) => v.visit_procedure(proc),
+        ConstExpr::PolyProc(proc) => v.visit_poly_procedure(proc),
+        ConstExpr::Monomorphize(inner, ty_args) => {
+            v.visit_const(inner);
+            ty_args.iter().for_each(|ty| v.visit_type(ty));
+        }
+        ConstExpr::Template(params, inner) => {
+            params
+                .iter()
+                .flat_map(|(_, bound)| bound)
+                .for_each(|ty| v.visit_type(ty));
+            v.visit_const(inner);
+        }
+        ConstExpr::Member(inner, field) => {
+            v.visit_const(inner);
+            v.visit_const(field);
+        }
+        ConstExpr::Any
+        | ConstExpr::None
+        | ConstExpr::Null
+        | ConstExpr::Symbol(_)
+        | ConstExpr::Int(_)
+        | ConstExpr::Cell(_)
+        | ConstExpr::Float(_)
+        | ConstExpr::Char(_)
+        | ConstExpr::Bool(_)
+        | ConstExpr::Cfg(..)
+        | ConstExpr::CoreBuiltin(_)
+        | ConstExpr::StandardBuiltin(_)
+        | ConstExpr::FFIProcedure(_) => {}
+    }
+}
+
+/// Visit the children of a type.
+pub fn walk_type<'a, V: Visit<'a> + ?Sized>(v: &mut V, ty: &'a Type) {
+    match ty {
+        Type::Let(_, binding, ret) => {
+            v.visit_type(binding);
+            v.visit_type(ret);
+        }
+        Type::Unit(_, inner) | Type::Type(inner) | Type::Pointer(_, inner) => v.visit_type(inner),
+        Type::Poly(params, inner) => {
+            params
+                .iter()
+                .flat_map(|(_, bound)| bound)
+                .for_each(|ty| v.visit_type(ty));
+            v.visit_type(inner);
+        }
+        Type::Tuple(items) => items.iter().for_each(|ty| v.visit_type(ty)),
+        Type::Array(inner, len) => {
+            v.visit_type(inner);
+            v.visit_const(len);
+        }
+        Type::Struct(fields) | Type::EnumUnion(fields, _) | Type::Union(fields) => {
+            fields.values().for_each(|ty| v.visit_type(ty));
+        }
+        Type::Repr(fields, _) => fields.iter().for_each(|(_, ty)| v.visit_type(ty)),
+        Type::Proc(args, ret) => {
+            args.iter().for_each(|ty| v.visit_type(ty));
+            v.visit_type(ret);
+        }
+        Type::Apply(poly, ty_args) => {
+            v.visit_type(poly);
+            ty_args.iter().for_each(|ty| v.visit_type(ty));
+        }
+        Type::ConstParam(cexpr) => v.visit_const(cexpr),
+        Type::Symbol(_)
+        | Type::None
+        | Type::Int
+        | Type::Float
+        | Type::Cell
+        | Type::Char
+        | Type::Bool
+        | Type::Enum(..)
+        | Type::Bitfield(_)
+        | Type::Any
+        | Type::Never => {}
+    }
+}
+
+/// Visit the children of a pattern.
+pub fn walk_pattern<'a, V: Visit<'a> + ?Sized>(v: &mut V, pat: &'a Pattern) {
+    match pat {
+        Pattern::Tuple(pats) | Pattern::Alt(pats) => {
+            pats.iter().for_each(|pat| v.visit_pattern(pat))
+        }
+        Pattern::Struct(fields) => fields.values().for_each(|pat| v.visit_pattern(pat)),
+        Pattern::Variant(_, Some(pat)) | Pattern::Pointer(pat) => v.visit_pattern(pat),
+        Pattern::ConstExpr(cexpr) => v.visit_const(cexpr),
+        Pattern::Variant(_, None) | Pattern::Symbol(..) | Pattern::Wildcard => {}
+    }
+}
+
+/// Visit the children of a declaration.
+pub fn walk_declaration<'a, V: Visit<'a> + ?Sized>(v: &mut V, decl: &'a Declaration) {
+    match decl {
+        Declaration::StaticVar(_, _, ty, expr) => {
+            v.visit_type(ty);
+            v.visit_expr(expr);
+        }
+        Declaration::Var(_, _, ty, expr) => {
+            if let Some(ty) = ty {
+                v.visit_type(ty);
+            }
+            v.visit_expr(expr);
+        }
+        Declaration::VarPat(pat, expr) => {
+            v.visit_pattern(pat);
+            v.visit_expr(expr);
+        }
+        Declaration::Proc(_, proc) => v.visit_procedure(proc),
+        Declaration::PolyProc(_, proc) => v.visit_poly_procedure(proc),
+        Declaration::Type(_, ty, _) => v.visit_type(ty),
+        Declaration::Const(_, cexpr) => v.visit_const(cexpr),
+        Declaration::Impl(ty, items) => {
+            v.visit_type(ty);
+            items.iter().for_each(|(_, cexpr)| v.visit_const(cexpr));
+        }
+        Declaration::Many(decls) | Declaration::Module(_, decls, ..) => {
+            decls.iter().for_each(|decl| v.visit_declaration(decl));
+        }
+        Declaration::FromImport { module, .. } | Declaration::FromImportAll(module) => {
+            v.visit_const(module)
+        }
+        Declaration::StaticAssert(cond, _) => v.visit_const(cond),
+        Declaration::ExternProc(..) => {}
+    }
+}
+
+/// Visit the argument types, return type, and body of a procedure.
+pub fn walk_procedure<'a, V: Visit<'a> + ?Sized>(v: &mut V, proc: &'a Procedure) {
+    proc.get_args()
+        .iter()
+        .for_each(|(_, _, ty)| v.visit_type(ty));
+    v.visit_type(proc.get_ret());
+    v.visit_expr(proc.get_body());
+}
+
+/// Visit the type parameter bounds, argument types, return type, and body of a polymorphic procedure.
+pub fn walk_poly_procedure<'a, V: Visit<'a> + ?Sized>(v: &mut V, proc: &'a PolyProcedure) {
+    proc.get_type_params()
+        .iter()
+        .flat_map(|(_, bound)| bound)
+        .for_each(|ty| v.visit_type(ty));
+    proc.get_args()
+        .iter()
+        .for_each(|(_, _, ty)| v.visit_type(ty));
+    v.visit_type(proc.get_ret());
+    v.visit_expr(proc.get_body());
+}
+
+/// A transformation of an owned LIR program.
+///
+/// Each method takes a node and returns the node to replace it with.
+pub trait Fold {
+    /// Fold an expression.
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr_children(self, expr)
+    }
+
+    /// Fold a constant expression.
+    fn fold_const(&mut self, expr: ConstExpr) -> ConstExpr {
+        fold_const_children(self, expr)
+    }
+
+    /// Fold a type.
+    fn fold_type(&mut self, ty: Type) -> Type {
+        fold_type_children(self, ty)
+    }
+
+    /// Fold a pattern.
+    fn fold_pattern(&mut self, pat: Pattern) -> Pattern {
+        fold_pattern_children(self, pat)
+    }
+
+    /// Fold a declaration.
+    fn fold_declaration(&mut self, decl: Declaration) -> Declaration {
+        fold_declaration_children(self, decl)
+    }
+
+    /// Fold a monomorphic procedure.
+    fn fold_procedure(&mut self, proc: Procedure) -> Procedure {
+        fold_procedure_children(self, proc)
+    }
+
+    /// Fold a polymorphic procedure.
+    fn fold_poly_procedure(&mut self, proc: PolyProcedure) -> PolyProcedure {
+        fold_poly_procedure_children(self, proc)
+    }
+}
+
+/// Fold each item in a list.
+fn fold_all<T>(items: Vec<T>, mut f: impl FnMut(T) -> T) -> Vec<T> {
+    items.into_iter().map(&mut f).collect()
+}
+
+/// Fold a boxed node, reusing its allocation.
+fn fold_box<T>(mut item: Box<T>, f: impl FnOnce(T) -> T) -> Box<T> {
+    *item = f(*item);
+    item
+}
+
+/// Fold a type in place.
+fn fold_type_in_place<F: Fold + ?Sized>(f: &mut F, ty: &mut Type) {
+    *ty = f.fold_type(std::mem::replace(ty, Type::None));
+}
+
+/// Fold the children of an expression.
+pub fn fold_expr_children<F: Fold + ?Sized>(f: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Annotated(inner, annotation) => {
+            Expr::Annotated(fold_box(inner, |e| f.fold_expr(e)), annotation)
+        }
+        Expr::ConstExpr(cexpr) => Expr::ConstExpr(f.fold_const(cexpr)),
+        Expr::Declare(decl, body) => Expr::Declare(
+            fold_box(decl, |d| f.fold_declaration(d)),
+            fold_box(body, |e| f.fold_expr(e)),
+        ),
+        Expr::When(cond, then, otherwise) => Expr::When(
+            f.fold_const(cond),
+            fold_box(then, |e| f.fold_expr(e)),
+            fold_box(otherwise, |e| f.fold_expr(e)),
+        ),
+        Expr::Match(inner, arms) => {
+            let inner = fold_box(inner, |e| f.fold_expr(e));
+            let arms = fold_all(arms, |(pat, arm)| (f.fold_pattern(pat), f.fold_expr(arm)));
+            Expr::Match(inner, arms)
+        }
+        Expr::IfLet(pat, inner, then, otherwise) => Expr::IfLet(
+            f.fold_pattern(pat),
+            fold_box(inner, |e| f.fold_expr(e)),
+            fold_box(then, |e| f.fold_expr(e)),
+            fold_box(otherwise, |e| f.fold_expr(e)),
+        ),
+        Expr::WhileLet(pat, inner, body) => Expr::WhileLet(
+            f.fold_pattern(pat),
+            fold_box(inner, |e| f.fold_expr(e)),
+            fold_box(body, |e| f.fold_expr(e)),
+        ),
+        Expr::Union(ty, variant, inner) => Expr::Union(
+            f.fold_type(ty),
+            variant,
+            fold_box(inner, |e| f.fold_expr(e)),
+        ),
+        Expr::EnumUnion(ty, variant, inner) => Expr::EnumUnion(
+            f.fold_type(ty),
+            variant,
+            fold_box(inner, |e| f.fold_expr(e)),
+        ),
+        Expr::As(inner, ty) => Expr::As(fold_box(inner, |e| f.fold_expr(e)), f.fold_type(ty)),
+        Expr::Member(inner, field) => {
+            Expr::Member(fold_box(inner, |e| f.fold_expr(e)), f.fold_const(field))
+        }
+        Expr::Apply(func, args) => Expr::Apply(
+            fold_box(func, |e| f.fold_expr(e)),
+            fold_all(args, |e| f.fold_expr(e)),
+        ),
+        Expr::Many(exprs) => Expr::Many(fold_all(exprs, |e| f.fold_expr(e))),
+        Expr::Array(exprs) => Expr::Array(fold_all(exprs, |e| f.fold_expr(e))),
+        Expr::ArrayRepeat(elem, len) => Expr::ArrayRepeat(
+            fold_box(elem, |e| f.fold_expr(e)),
+            fold_box(len, |c| f.fold_const(c)),
+        ),
+        Expr::Tuple(exprs) => Expr::Tuple(fold_all(exprs, |e| f.fold_expr(e))),
+        Expr::Struct(fields) => Expr::Struct(
+            fields
+                .into_iter()
+                .map(|(name, e)| (name, f.fold_expr(e)))
+                .collect(),
+        ),
+        Expr::UnaryOp(op, a) => Expr::UnaryOp(op, fold_box(a, |e| f.fold_expr(e))),
+        Expr::Refer(mutability, a) => Expr::Refer(mutability, fold_box(a, |e| f.fold_expr(e))),
+        Expr::Deref(a) => Expr::Deref(fold_box(a, |e| f.fold_expr(e))),
+        Expr::Return(a) => Expr::Return(fold_box(a, |e| f.fold_expr(e))),
+        Expr::While(a, b) => Expr::While(
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+        ),
+        Expr::BinaryOp(op, a, b) => Expr::BinaryOp(
+            op,
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+        ),
+        Expr::AssignOp(op, a, b) => Expr::AssignOp(
+            op,
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+        ),
+        Expr::DerefMut(a, b) => Expr::DerefMut(
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+        ),
+        Expr::Index(a, b) => Expr::Index(
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+        ),
+        Expr::If(a, b, c) => Expr::If(
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+            fold_box(c, |e| f.fold_expr(e)),
+        ),
+        Expr::TernaryOp(op, a, b, c) => Expr::TernaryOp(
+            op,
+            fold_box(a, |e| f.fold_expr(e)),
+            fold_box(b, |e| f.fold_expr(e)),
+            fold_box(c, |e| f.fold_expr(e)),
+        ),
+        Expr::Hole(name) => Expr::Hole(name),
+    }
+}
+
+/// Fold the children of a constant expression.
+pub fn fold_const_children<F: Fold + ?Sized>(f: &mut F, expr: ConstExpr) -> ConstExpr {
+    match expr {
+        ConstExpr::Annotated(inner, annotation) => {
+            ConstExpr::Annotated(fold_box(inner, |e| f.fold_const(e)), annotation)
+        }
+        ConstExpr::Declare(decl, body) => ConstExpr::Declare(
+            fold_box(decl, |d| f.fold_declaration(d)),
+            fold_box(body, |e| f.fold_const(e)),
+        ),
+        ConstExpr::TypeOf(inner) => ConstExpr::TypeOf(fold_box(inner, |e| f.fold_expr(e))),
+        ConstExpr::SizeOfExpr(inner) => ConstExpr::SizeOfExpr(fold_box(inner, |e| f.fold_expr(e))),
+        ConstExpr::Of(ty, variant) => ConstExpr::Of(f.fold_type(ty), variant),
+        ConstExpr::SizeOfType(ty) => ConstExpr::SizeOfType(f.fold_type(ty)),
+        ConstExpr::AlignOfType(ty) => ConstExpr::AlignOfType(f.fold_type(ty)),
+        ConstExpr::Reflect(ty, reflection) => ConstExpr::Reflect(f.fold_type(ty), reflection),
+        ConstExpr::Type(ty) => ConstExpr::Type(f.fold_type(ty)),
+        ConstExpr::OffsetOf(ty, member) => {
+            ConstExpr::OffsetOf(f.fold_type(ty), fold_box(member, |e| f.fold_const(e)))
+        }
+        ConstExpr::Union(ty, variant, inner) => ConstExpr::Union(
+            f.fold_type(ty),
+            variant,
+            fold_box(inner, |e| f.fold_const(e)),
+        ),
+        ConstExpr::EnumUnion(ty, variant, inner) => ConstExpr::EnumUnion(
+            f.fold_type(ty),
+            variant,
+            fold_box(inner, |e| f.fold_const(e)),
+        ),
+        ConstExpr::As(inner, ty) => {
+            ConstExpr::As(fold_box(inner, |e| f.fold_const(e)), f.fold_type(ty))
+        }
+        ConstExpr::Tuple(items) => ConstExpr::Tuple(fold_all(items, |e| f.fold_const(e))),
+        ConstExpr::Array(items) => ConstExpr::Array(fold_all(items, |e| f.fold_const(e))),
+        ConstExpr::Table(name, start, end, elem) => ConstExpr::Table(
+            name,
+            fold_box(start, |e| f.fold_const(e)),
+            fold_box(end, |e| f.fold_const(e)),
+            fold_box(elem, |e| f.fold_expr(e)),
+        ),
+        ConstExpr::Struct(fields) => ConstExpr::Struct(
+            fields
+                .into_iter()
+                .map(|(name, e)| (name, f.fold_const(e)))
+                .collect(),
+        ),
+        ConstExpr::Proc(proc) => ConstExpr::Proc(f.fold_procedure(proc)),
+        ConstExpr::PolyProc(proc) => ConstExpr::PolyProc(f.fold_poly_procedure(proc)),
+        ConstExpr::Monomorphize(inner, ty_args) => ConstExpr::Monomorphize(
+            fold_box(inner, |e| f.fold_const(e)),
+            fold_all(ty_args, |ty| f.fold_type(ty)),
+        ),
+        ConstExpr::Template(params, inner) => ConstExpr::Template(
+            fold_all(params, |(name, bound)| {
+                (name, bound.map(|ty| f.fold_type(ty)))
+            }),
+            fold_box(inner, |e| f.fold_const(e)),
+        ),
+        ConstExpr::Member(inner, field) => ConstExpr::Member(
+            fold_box(inner, |e| f.fold_const(e)),
+            fold_box(field, |e| f.fold_const(e)),
+        ),
+        leaf @ (ConstExpr::Any
+        | ConstExpr::None
+        | ConstExpr::Null
+        | ConstExpr::Symbol(_)
+        | ConstExpr::Int(_)
+        | ConstExpr::Cell(_)
+        | ConstExpr::Float(_)
+        | ConstExpr::Char(_)
+        | ConstExpr::Bool(_)
+        | ConstExpr::Cfg(..)
+        | ConstExpr::CoreBuiltin(_)
+        | ConstExpr::StandardBuiltin(_)
+        | ConstExpr::FFIProcedure(_)) => leaf,
+    }
+}
+
+/// Fold the children of a type.
+pub fn fold_type_children<F: Fold + ?Sized>(f: &mut F, ty: Type) -> Type {
+    match ty {
+        Type::Let(name, binding, ret) => Type::Let(
+            name,
+            fold_box(binding, |ty| f.fold_type(ty)),
+            fold_box(ret, |ty| f.fold_type(ty)),
+        ),
+        Type::Unit(name, inner) => Type::Unit(name, fold_box(inner, |ty| f.fold_type(ty))),
+        Type::Type(inner) => Type::Type(fold_box(inner, |ty| f.fold_type(ty))),
+        Type::Pointer(mutability, inner) => {
+            Type::Pointer(mutability, fold_box(inner, |ty| f.fold_type(ty)))
+        }
+        Type::Poly(params, inner) => Type::Poly(
+            fold_all(params, |(name, bound)| {
+                (name, bound.map(|ty| f.fold_type(ty)))
+            }),
+            fold_box(inner, |ty| f.fold_type(ty)),
+        ),
+        Type::Tuple(items) => Type::Tuple(fold_all(items, |ty| f.fold_type(ty))),
+        Type::Array(inner, len) => Type::Array(
+            fold_box(inner, |ty| f.fold_type(ty)),
+            fold_box(len, |e| f.fold_const(e)),
+        ),
+        Type::Struct(fields) => Type::Struct(
+            fields
+                .into_iter()
+                .map(|(name, ty)| (name, f.fold_type(ty)))
+                .collect(),
+        ),
+        Type::EnumUnion(fields, repr) => Type::EnumUnion(
+            fields
+                .into_iter()
+                .map(|(name, ty)| (name, f.fold_type(ty)))
+                .collect(),
+            repr,
+        ),
+        Type::Union(fields) => Type::Union(
+            fields
+                .into_iter()
+                .map(|(name, ty)| (name, f.fold_type(ty)))
+                .collect(),
+        ),
+        Type::Repr(fields, align) => Type::Repr(
+            fold_all(fields, |(name, ty)| (name, f.fold_type(ty))),
+            align,
+        ),
+        Type::Proc(args, ret) => Type::Proc(
+            fold_all(args, |ty| f.fold_type(ty)),
+            fold_box(ret, |ty| f.fold_type(ty)),
+        ),
+        Type::Apply(poly, ty_args) => Type::Apply(
+            fold_box(poly, |ty| f.fold_type(ty)),
+            fold_all(ty_args, |ty| f.fold_type(ty)),
+        ),
+        Type::ConstParam(cexpr) => Type::ConstParam(fold_box(cexpr, |e| f.fold_const(e))),
+        leaf @ (Type::Symbol(_)
+        | Type::None
+        | Type::Int
+        | Type::Float
+        | Type::Cell
+        | Type::Char
+        | Type::Bool
+        | Type::Enum(..)
+        | Type::Bitfield(_)
+        | Type::Any
+        | Type::Never) => leaf,
+    }
+}
+
+/// Fold the children of a pattern.
+pub fn fold_pattern_children<F: Fold + ?Sized>(f: &mut F, pat: Pattern) -> Pattern {
+    match pat {
+        Pattern::Tuple(pats) => Pattern::Tuple(fold_all(pats, |pat| f.fold_pattern(pat))),
+        Pattern::Alt(pats) => Pattern::Alt(fold_all(pats, |pat| f.fold_pattern(pat))),
+        Pattern::Struct(fields) => Pattern::Struct(
+            fields
+                .into_iter()
+                .map(|(name, pat)| (name, f.fold_pattern(pat)))
+                .collect(),
+        ),
+        Pattern::Variant(name, pat) => Pattern::Variant(
+            name,
+            pat.map(|pat| fold_box(pat, |pat| f.fold_pattern(pat))),
+        ),
+        Pattern::Pointer(pat) => Pattern::Pointer(fold_box(pat, |pat| f.fold_pattern(pat))),
+        Pattern::ConstExpr(cexpr) => Pattern::ConstExpr(f.fold_const(cexpr)),
+        leaf @ (Pattern::Symbol(..) | Pattern::Wildcard) => leaf,
+    }
+}
+
+/// Fold the children of a declaration.
+pub fn fold_declaration_children<F: Fold + ?Sized>(f: &mut F, decl: Declaration) -> Declaration {
+    match decl {
+        Declaration::StaticVar(name, mutability, ty, expr) => {
+            Declaration::StaticVar(name, mutability, f.fold_type(ty), f.fold_expr(expr))
+        }
+        Declaration::Var(name, mutability, ty, expr) => Declaration::Var(
+            name,
+            mutability,
+            ty.map(|ty| f.fold_type(ty)),
+            f.fold_expr(expr),
+        ),
+        Declaration::VarPat(pat, expr) => {
+            Declaration::VarPat(f.fold_pattern(pat), f.fold_expr(expr))
+        }
+        Declaration::Proc(name, proc) => Declaration::Proc(name, f.fold_procedure(proc)),
+        Declaration::PolyProc(name, proc) => {
+            Declaration::PolyProc(name, f.fold_poly_procedure(proc))
+        }
+        Declaration::Type(name, ty, attributes) => {
+            Declaration::Type(name, f.fold_type(ty), attributes)
+        }
+        Declaration::Const(name, cexpr) => Declaration::Const(name, f.fold_const(cexpr)),
+        Declaration::Impl(ty, items) => Declaration::Impl(
+            f.fold_type(ty),
+            fold_all(items, |(name, cexpr)| (name, f.fold_const(cexpr))),
+        ),
+        Declaration::Many(decls) => {
+            Declaration::Many(Arc::new(fold_all(Arc::unwrap_or_clone(decls), |decl| {
+                f.fold_declaration(decl)
+            })))
+        }
+        Declaration::Module(name, decls, checked, id) => Declaration::Module(
+            name,
+            Arc::new(fold_all(Arc::unwrap_or_clone(decls), |decl| {
+                f.fold_declaration(decl)
+            })),
+            checked,
+            id,
+        ),
+        Declaration::FromImport { module, names } => Declaration::FromImport {
+            module: f.fold_const(module),
+            names,
+        },
+        Declaration::FromImportAll(module) => Declaration::FromImportAll(f.fold_const(module)),
+        Declaration::StaticAssert(cond, message) => {
+            Declaration::StaticAssert(f.fold_const(cond), message)
+        }
+        Declaration::ExternProc(name, proc) => Declaration::ExternProc(name, proc),
+    }
+}
+
+/// Fold the argument types, return type, and body of a procedure.
+///
+/// The procedure keeps its mangled name, so it still refers to the same label.
+pub fn fold_procedure_children<F: Fold + ?Sized>(f: &mut F, mut proc: Procedure) -> Procedure {
+    let (args, ret, body) = proc.parts_mut();
+    args.iter_mut()
+        .for_each(|(_, _, ty)| fold_type_in_place(f, ty));
+    fold_type_in_place(f, ret);
+    *body = f.fold_expr(std::mem::replace(body, Expr::NONE));
+    proc
+}
+
+/// Fold the type parameter bounds, argument types, return type, and body of a polymorphic procedure.
+pub fn fold_poly_procedure_children<F: Fold + ?Sized>(
+    f: &mut F,
+    mut proc: PolyProcedure,
+) -> PolyProcedure {
+    let (ty_params, args, ret, body) = proc.parts_mut();
+    ty_params
+        .iter_mut()
+        .flat_map(|(_, bound)| bound)
+        .for_each(|ty| fold_type_in_place(f, ty));
+    args.iter_mut()
+        .for_each(|(_, _, ty)| fold_type_in_place(f, ty));
+    fold_type_in_place(f, ret);
+    *body = f.fold_expr(std::mem::replace(body, Expr::NONE));
+    proc
+}
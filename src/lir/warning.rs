@@ -0,0 +1,180 @@
+//! # Warnings
+//!
+//! Warnings are non-fatal diagnostics reported while type checking. They are
+//! collected on the environment, and each kind of warning can be allowed,
+//! reported, or denied (turned into an error).
+use super::{Annotation, Diagnostic, Error, Expr, Pattern, Type};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use core::str::FromStr;
+
+/// A non-fatal problem found while type checking.
+#[derive(Clone, Debug)]
+pub enum Warning {
+    /// A warning with some annotation about the source code that caused it.
+    Annotated(Box<Self>, Annotation),
+
+    /// A variable was declared, but never used.
+    UnusedVariable(String),
+    /// The non-None result of a procedure call was discarded.
+    UnusedResult(Expr, Type),
+    /// A match arm can never be reached because the arms before it are exhaustive.
+    UnreachableMatchArm(Pattern, Expr),
+    /// A type was declared with the same name as a different type in scope.
+    ShadowedType(String),
+    /// A procedure or type marked `deprecated` was used, with the note on the attribute.
+    Deprecated(String, Option<String>),
+    /// A problem found by a lint, with the name of the lint and its message.
+    Lint(String, String),
+}
+
+/// The kinds of warnings, which can each be allowed, reported, or denied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningKind {
+    UnusedVariable,
+    UnusedResult,
+    UnreachableMatchArm,
+    ShadowedType,
+    Deprecated,
+    /// Every problem found by a lint. Each lint can also be given its own level.
+    Lint,
+}
+
+/// What to do when a warning is found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum WarningLevel {
+    /// Ignore the warning.
+    Allow,
+    /// Report the warning, and continue compiling.
+    #[default]
+    Warn,
+    /// Report the warning as an error.
+    Deny,
+}
+
+impl Warning {
+    /// Annotate a warning with some metadata.
+    pub fn annotate(self, annotation: Annotation) -> Self {
+        match self {
+            Self::Annotated(warning, previous_annotation) => {
+                Self::Annotated(warning, annotation | previous_annotation)
+            }
+            _ => Self::Annotated(Box::new(self), annotation),
+        }
+    }
+
+    /// Annotate a warning with the annotation of an expression, if it has one.
+    pub fn annotate_with(self, expr: &Expr) -> Self {
+        match expr {
+            Expr::Annotated(_, annotation) => self.annotate(annotation.clone()),
+            _ => self,
+        }
+    }
+
+    /// Get the warning without any of the annotations attached to it.
+    pub fn unannotated(&self) -> &Self {
+        match self {
+            Self::Annotated(warning, _) => warning.unannotated(),
+            _ => self,
+        }
+    }
+
+    /// Get the kind of this warning.
+    pub fn kind(&self) -> WarningKind {
+        match self {
+            Self::Annotated(warning, _) => warning.kind(),
+            Self::UnusedVariable(_) => WarningKind::UnusedVariable,
+            Self::UnusedResult(..) => WarningKind::UnusedResult,
+            Self::UnreachableMatchArm(..) => WarningKind::UnreachableMatchArm,
+            Self::ShadowedType(_) => WarningKind::ShadowedType,
+            Self::Deprecated(..) => WarningKind::Deprecated,
+            Self::Lint(..) => WarningKind::Lint,
+        }
+    }
+
+    /// Create a diagnostic describing this warning, which can be rendered with the source code.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut diagnostic = Diagnostic::warning(self.unannotated());
+        if let Self::Annotated(_, annotation) = self {
+            diagnostic = diagnostic.with_annotation(annotation);
+        }
+        match self.unannotated() {
+            Self::UnusedVariable(name) => diagnostic
+                .with_note(format!(
+                    "if this is intentional, prefix the variable with an underscore: _{name}"
+                ))
+                .with_note(format!("this is the `{}` warning", self.kind())),
+            Self::Deprecated(_, Some(note)) => diagnostic
+                .with_note(note.clone())
+                .with_note(format!("this is the `{}` warning", self.kind())),
+            Self::Lint(name, _) => diagnostic.with_note(format!("this is the `{name}` lint")),
+            _ => diagnostic.with_note(format!("this is the `{}` warning", self.kind())),
+        }
+    }
+
+    /// Turn this warning into an error, for when it is denied.
+    pub fn into_error(self) -> Error {
+        match self {
+            Self::Annotated(warning, annotation) => warning.into_error().annotate(annotation),
+            warning => Error::DeniedWarning(Box::new(warning)),
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Annotated(warning, _) => write!(f, "{warning}"),
+            Self::UnusedVariable(name) => write!(f, "unused variable {name}"),
+            Self::UnusedResult(expr, ty) => {
+                write!(f, "unused result of type {ty} from procedure call {expr}")
+            }
+            Self::UnreachableMatchArm(pat, expr) => {
+                write!(f, "unreachable match arm {pat} in {expr}")
+            }
+            Self::ShadowedType(name) => write!(f, "type {name} shadows another type"),
+            Self::Deprecated(name, _) => write!(f, "use of deprecated {name}"),
+            Self::Lint(_, message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl WarningKind {
+    /// Every kind of warning.
+    pub const ALL: [WarningKind; 6] = [
+        Self::UnusedVariable,
+        Self::UnusedResult,
+        Self::UnreachableMatchArm,
+        Self::ShadowedType,
+        Self::Deprecated,
+        Self::Lint,
+    ];
+
+    /// The default level of this kind of warning.
+    pub fn default_level(&self) -> WarningLevel {
+        WarningLevel::Warn
+    }
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::UnusedVariable => write!(f, "unused-variable"),
+            Self::UnusedResult => write!(f, "unused-result"),
+            Self::UnreachableMatchArm => write!(f, "unreachable-match-arm"),
+            Self::ShadowedType => write!(f, "shadowed-type"),
+            Self::Deprecated => write!(f, "deprecated"),
+            Self::Lint => write!(f, "lint"),
+        }
+    }
+}
+
+impl FromStr for WarningKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| format!("unknown warning {s}"))
+    }
+}
@@ -0,0 +1,193 @@
+//! # Build Profiles
+//!
+//! A [`BuildProfile`] chooses how a program is built as a bundle: its optimization
+//! level, whether array indices are bounds checked, whether assertions are compiled
+//! in, and whether the source lines of the instructions are marked (the debug info
+//! written as `#line` directives by the C target). There are two built in profiles:
+//! `debug`, for checking programs while writing them, and `release`, for building
+//! programs to ship.
+//!
+//! A project can change these, or add its own profiles, in a `sage.toml` manifest
+//! next to its source code (or in any directory above it):
+//!
+//! ```toml
+//! [profile.release]
+//! debug-info = true
+//!
+//! [profile.fuzz]
+//! inherits = "release"
+//! bounds-checks = true
+//! ```
+//!
+//! A profile in the manifest starts from the built in profile it inherits from (or
+//! the one with its name, or `debug`), and only changes the options it sets.
+use crate::lir::Env;
+use serde_derive::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// The options a program is built with, chosen together by name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildProfile {
+    /// The optimization level, from `0` to `2`.
+    pub opt_level: u8,
+    /// Check array indices against the lengths of their arrays at runtime?
+    pub bounds_checks: bool,
+    /// Compile assertions into the program? If not, they're removed.
+    pub assertions: bool,
+    /// Mark the source line each instruction comes from?
+    pub debug_info: bool,
+}
+
+impl Default for BuildProfile {
+    /// The options used without a profile: the program is kept as written,
+    /// with its assertions and debug info, but without bounds checks.
+    fn default() -> Self {
+        Self {
+            opt_level: 0,
+            bounds_checks: false,
+            assertions: true,
+            debug_info: true,
+        }
+    }
+}
+
+impl BuildProfile {
+    /// The profile for checking programs while writing them: nothing is
+    /// optimized away, and array indices are bounds checked.
+    pub fn debug() -> Self {
+        Self {
+            bounds_checks: true,
+            ..Self::default()
+        }
+    }
+
+    /// The profile for building programs to ship: the program is fully optimized,
+    /// without bounds checks, assertions, or debug info.
+    pub fn release() -> Self {
+        Self {
+            opt_level: 2,
+            bounds_checks: false,
+            assertions: false,
+            debug_info: false,
+        }
+    }
+
+    /// A built in profile, by its name.
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "debug" => Some(Self::debug()),
+            "release" => Some(Self::release()),
+            _ => None,
+        }
+    }
+
+    /// The profile with another optimization level. Like the `-O` flag,
+    /// levels above `0` remove assertions.
+    pub fn with_opt_level(mut self, level: u8) -> Self {
+        self.opt_level = level.min(2);
+        self.assertions &= self.opt_level < 1;
+        self
+    }
+
+    /// Set the profile's options on an environment to compile under.
+    pub fn configure(&self, env: &mut Env) {
+        env.set_bounds_checks(self.bounds_checks);
+        env.set_assertions(self.assertions);
+        env.set_field_reordering(self.opt_level >= 2);
+        env.set_debug_info(self.debug_info);
+    }
+}
+
+/// A profile as written in the manifest. Its options are all optional, and
+/// it may name the profile it inherits the options it leaves out from.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct ProfileConfig {
+    inherits: Option<String>,
+    opt_level: Option<u8>,
+    bounds_checks: Option<bool>,
+    assertions: Option<bool>,
+    debug_info: Option<bool>,
+}
+
+/// A project's `sage.toml` manifest.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// The profiles configured by the project, by name.
+    profile: BTreeMap<String, ProfileConfig>,
+}
+
+impl Manifest {
+    /// The name of a project's manifest file.
+    pub const FILENAME: &'static str = "sage.toml";
+
+    /// Parse the text of a manifest.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let manifest: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        for (name, config) in &manifest.profile {
+            if let Some(level) = config.opt_level.filter(|level| *level > 2) {
+                return Err(format!(
+                    "profile `{name}` has opt-level {level}, which must be from 0 to 2"
+                ));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Read and parse a manifest file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read manifest {path:?}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("Invalid manifest {path:?}: {e}"))
+    }
+
+    /// Find the manifest for a source file: the nearest `sage.toml`
+    /// in the directory of the file, or in any directory above it.
+    pub fn find(source: impl AsRef<Path>) -> Option<PathBuf> {
+        let source = source.as_ref().canonicalize().ok()?;
+        source
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join(Self::FILENAME))
+            .find(|path| path.is_file())
+    }
+
+    /// The profile with a name, as configured by the manifest on top of the built in profiles.
+    pub fn profile(&self, name: &str) -> Result<BuildProfile, String> {
+        self.resolve(name, &mut vec![])
+    }
+
+    /// Resolve a profile, keeping track of the profiles it's inherited from to catch cycles.
+    fn resolve(&self, name: &str, seen: &mut Vec<String>) -> Result<BuildProfile, String> {
+        if seen.iter().any(|other| other == name) {
+            return Err(format!("profile `{name}` inherits from itself"));
+        }
+        seen.push(name.to_string());
+
+        let Some(config) = self.profile.get(name) else {
+            return BuildProfile::builtin(name).ok_or_else(|| format!("unknown profile `{name}`"));
+        };
+        let mut profile = match &config.inherits {
+            Some(parent) if parent != name => self.resolve(parent, seen)?,
+            _ => BuildProfile::builtin(name).unwrap_or_else(BuildProfile::debug),
+        };
+        if let Some(level) = config.opt_level {
+            profile.opt_level = level;
+        }
+        if let Some(bounds_checks) = config.bounds_checks {
+            profile.bounds_checks = bounds_checks;
+        }
+        if let Some(assertions) = config.assertions {
+            profile.assertions = assertions;
+        }
+        if let Some(debug_info) = config.debug_info {
+            profile.debug_info = debug_info;
+        }
+        Ok(profile)
+    }
+}
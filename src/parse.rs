@@ -5,6 +5,7 @@
 //!
 //! Right now, this module supports parsing:
 //! - LIR source
+//! - LIR text format source
 //! - Core Assembly source
 //! - Standard Assembly source
 //! - Core Virtual Machine code source
@@ -26,8 +27,8 @@
 //! error enums in the future.
 
 use super::asm::{CoreProgram, StandardProgram};
-use super::frontend;
-use super::lir::Expr;
+use super::frontend::{self, Prelude};
+use super::lir::{Expr, FromText};
 use super::vm;
 
 use log::trace;
@@ -74,6 +75,18 @@ impl SourceCodeLocation {
     }
 }
 
+impl std::fmt::Display for SourceCodeLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            self.filename.as_deref().unwrap_or("unknown"),
+            self.line,
+            self.column
+        )
+    }
+}
+
 lalrpop_mod!(
     #[allow(clippy::all)]
     asm_parser
@@ -147,9 +160,23 @@ pub fn parse_lir(input: impl ToString) -> Result<Expr, String> {
     }
 }
 
+/// Parse LIR written in the text format as an LIR expression.
+pub fn parse_lir_text(input: impl ToString) -> Result<Expr, String> {
+    Expr::from_text(&input.to_string())
+}
+
 /// Parse frontend sage code into an LIR expression.
 pub fn parse_frontend(input: impl ToString, filename: Option<&str>) -> Result<Expr, String> {
-    let result = frontend::parse(input, filename, true, true)?;
+    parse_frontend_with_prelude(input, filename, Prelude::Std)
+}
+
+/// Parse frontend code, with only the given prelude declared around it.
+pub fn parse_frontend_with_prelude(
+    input: impl ToString,
+    filename: Option<&str>,
+    prelude: Prelude,
+) -> Result<Expr, String> {
+    let result = frontend::parse(input, filename, prelude.has_builtins(), prelude.has_std())?;
     trace!(target: "parse", "Parsed frontend code: {result}");
     Ok(result)
 }
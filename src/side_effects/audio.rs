@@ -0,0 +1,92 @@
+//! # Audio
+//!
+//! This module defines the convention for playing sound with the PCM output modes,
+//! so that any device with a speaker can play a program's audio. Sound is played
+//! on a single channel, as a mono stream of 16-bit samples:
+//!
+//! ```text
+//! samplerate #0  ; the value is the number of samples per second, before any are played
+//! sample #0      ; the value is the next sample, from -32768 to 32767
+//! ```
+//!
+//! [`AudioBuffer`] records the samples without playing them anywhere, so that
+//! devices can play them however they like, and tests can check what was played.
+
+use super::{Output, OutputMode};
+
+/// The samples a program has played, and the rate they're played at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioBuffer {
+    /// The number of samples per second.
+    sample_rate: u32,
+    /// The samples which haven't been taken yet.
+    samples: Vec<i16>,
+    /// The number of samples which have been played.
+    played: usize,
+}
+
+impl Default for AudioBuffer {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SAMPLE_RATE)
+    }
+}
+
+impl AudioBuffer {
+    /// The sample rate, if the program doesn't choose one.
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+    /// Create an empty buffer with a given sample rate.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            samples: vec![],
+            played: 0,
+        }
+    }
+
+    /// Is this output one of the audio outputs?
+    pub fn handles(dst: &Output) -> bool {
+        matches!(dst.mode, OutputMode::Sample | OutputMode::SampleRate)
+    }
+
+    /// Apply an audio output to the buffer. Samples outside of the 16-bit range are
+    /// clamped, and sample rates below 1 hertz are ignored.
+    pub fn put(&mut self, val: i64, dst: &Output) {
+        match dst.mode {
+            OutputMode::Sample => {
+                self.samples
+                    .push(val.clamp(i16::MIN as i64, i16::MAX as i64) as i16);
+                self.played += 1;
+            }
+            OutputMode::SampleRate if val > 0 => {
+                self.sample_rate = val.min(u32::MAX as i64) as u32;
+            }
+            _ => {}
+        }
+    }
+
+    /// The number of samples per second.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The samples which haven't been taken yet.
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Take the samples recorded so far, so a device can play them.
+    pub fn take(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// The number of samples which have been played, including those taken.
+    pub fn played(&self) -> usize {
+        self.played
+    }
+
+    /// The number of seconds of audio which have been played.
+    pub fn duration(&self) -> f64 {
+        self.played as f64 / self.sample_rate as f64
+    }
+}
@@ -0,0 +1,315 @@
+//! # Standard FFI Bindings
+//!
+//! This module defines the foreign functions for files and the clock, which devices
+//! provide under the same names, so that programs can use them on any device. The
+//! standard library declares them in its `std.io` and `std.time` modules:
+//!
+//! ```text
+//! extern fun fs_open(path: &Char, mode: Int): Int;
+//! extern fun fs_read(handle: Int, buf: &mut Char, len: Int): Int;
+//! extern fun fs_write(handle: Int, buf: &Char, len: Int): Int;
+//! extern fun fs_close(handle: Int);
+//! extern fun time_now(): Int;
+//! extern fun sleep_ms(ms: Int);
+//! ```
+//!
+//! A file is opened with a mode of `0` to read it, `1` to write it from the start, or `2`
+//! to append to it, and `fs_open` returns a handle to it, or `-1` if it couldn't be opened.
+//! `fs_read` and `fs_write` return how many characters they moved, or `-1` if they failed.
+//! `time_now` is the number of microseconds since the Unix epoch.
+//!
+//! A device implements the bindings with a [`BindingHost`], and calls them with
+//! [`call_standard_binding`]. [`SystemHost`] uses the host's files and clock, and
+//! [`MockHost`] keeps files in memory with a clock which only moves when the program
+//! sleeps, for tests.
+
+use super::{pop_ffi_value, push_ffi_value, FFIBinding};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How a file is opened by `fs_open`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileMode {
+    /// Read the file from the start.
+    Read,
+    /// Write the file from the start, creating it or throwing away its contents.
+    Write,
+    /// Write to the end of the file, creating it if it doesn't exist.
+    Append,
+}
+
+impl FileMode {
+    /// The mode a program passes to `fs_open`, if it's a valid one.
+    pub fn from_int(mode: i64) -> Option<Self> {
+        match mode {
+            0 => Some(Self::Read),
+            1 => Some(Self::Write),
+            2 => Some(Self::Append),
+            _ => None,
+        }
+    }
+}
+
+/// `fs_open(path: &Char, mode: Int): Int`
+pub fn fs_open() -> FFIBinding {
+    FFIBinding::typed::<(i64, i64), i64>("fs_open")
+}
+
+/// `fs_read(handle: Int, buf: &mut Char, len: Int): Int`
+pub fn fs_read() -> FFIBinding {
+    FFIBinding::typed::<(i64, i64, i64), i64>("fs_read")
+}
+
+/// `fs_write(handle: Int, buf: &Char, len: Int): Int`
+pub fn fs_write() -> FFIBinding {
+    FFIBinding::typed::<(i64, i64, i64), i64>("fs_write")
+}
+
+/// `fs_close(handle: Int)`
+pub fn fs_close() -> FFIBinding {
+    FFIBinding::typed::<i64, ()>("fs_close")
+}
+
+/// `time_now(): Int`
+pub fn time_now() -> FFIBinding {
+    FFIBinding::typed::<(), i64>("time_now")
+}
+
+/// `sleep_ms(ms: Int)`
+pub fn sleep_ms() -> FFIBinding {
+    FFIBinding::typed::<i64, ()>("sleep_ms")
+}
+
+/// The bindings for files.
+pub fn fs_bindings() -> Vec<FFIBinding> {
+    vec![fs_open(), fs_read(), fs_write(), fs_close()]
+}
+
+/// The bindings for the clock.
+pub fn time_bindings() -> Vec<FFIBinding> {
+    vec![time_now(), sleep_ms()]
+}
+
+/// The files and clock behind the standard bindings.
+pub trait BindingHost {
+    /// Open a file, and return its handle.
+    fn open(&mut self, path: &str, mode: FileMode) -> Option<i64>;
+    /// Read up to `len` bytes from an open file.
+    fn read(&mut self, handle: i64, len: usize) -> Option<Vec<u8>>;
+    /// Write bytes to an open file, and return how many were written.
+    fn write(&mut self, handle: i64, bytes: &[u8]) -> Option<usize>;
+    /// Close an open file.
+    fn close(&mut self, handle: i64);
+    /// The number of microseconds since the Unix epoch.
+    fn now_us(&mut self) -> i64;
+    /// Wait for a number of milliseconds.
+    fn sleep_ms(&mut self, ms: i64);
+}
+
+/// Call one of the standard bindings on a host, with its arguments on the FFI channel.
+/// This returns `false` if the binding isn't one of them, so the device can look for it
+/// elsewhere. The file bindings need the tape, to read and write the program's buffers.
+pub fn call_standard_binding(
+    host: &mut impl BindingHost,
+    ffi: &FFIBinding,
+    channel: &mut VecDeque<i64>,
+    tape: Option<&mut Vec<i64>>,
+) -> Result<bool, String> {
+    let missing = || format!("not enough arguments on the ffi channel for {ffi:?}");
+    let no_tape = || format!("{ffi} needs the tape");
+    if *ffi == fs_open() {
+        let (path, mode): (i64, i64) = pop_ffi_value(channel).ok_or_else(missing)?;
+        let path = read_string(tape.ok_or_else(no_tape)?, path)?;
+        let handle = FileMode::from_int(mode).and_then(|mode| host.open(&path, mode));
+        push_ffi_value(channel, handle.unwrap_or(-1));
+    } else if *ffi == fs_read() {
+        let (handle, buf, len): (i64, i64, i64) = pop_ffi_value(channel).ok_or_else(missing)?;
+        let tape = tape.ok_or_else(no_tape)?;
+        let read = match host.read(handle, len.max(0) as usize) {
+            Some(bytes) => {
+                let cells = tape_cells(tape, buf, bytes.len())?;
+                for (cell, byte) in cells.iter_mut().zip(&bytes) {
+                    *cell = *byte as i64;
+                }
+                bytes.len() as i64
+            }
+            None => -1,
+        };
+        push_ffi_value(channel, read);
+    } else if *ffi == fs_write() {
+        let (handle, buf, len): (i64, i64, i64) = pop_ffi_value(channel).ok_or_else(missing)?;
+        let tape = tape.ok_or_else(no_tape)?;
+        let bytes = tape_cells(tape, buf, len.max(0) as usize)?
+            .iter()
+            .map(|cell| *cell as u8)
+            .collect::<Vec<_>>();
+        let written = host.write(handle, &bytes).map_or(-1, |n| n as i64);
+        push_ffi_value(channel, written);
+    } else if *ffi == fs_close() {
+        let handle: i64 = pop_ffi_value(channel).ok_or_else(missing)?;
+        host.close(handle);
+    } else if *ffi == time_now() {
+        push_ffi_value(channel, host.now_us());
+    } else if *ffi == sleep_ms() {
+        let ms: i64 = pop_ffi_value(channel).ok_or_else(missing)?;
+        host.sleep_ms(ms);
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// The `len` cells of the tape starting at an address.
+fn tape_cells(tape: &mut [i64], addr: i64, len: usize) -> Result<&mut [i64], String> {
+    usize::try_from(addr)
+        .ok()
+        .and_then(|addr| tape.get_mut(addr..addr.checked_add(len)?))
+        .ok_or_else(|| format!("buffer of {len} cells at {addr} is outside the tape"))
+}
+
+/// Read a null-terminated string from the tape.
+fn read_string(tape: &[i64], addr: i64) -> Result<String, String> {
+    let start = usize::try_from(addr).map_err(|_| format!("invalid string address {addr}"))?;
+    let len = tape
+        .get(start..)
+        .and_then(|cells| cells.iter().position(|cell| *cell == 0))
+        .ok_or_else(|| format!("string at {addr} isn't terminated on the tape"))?;
+    Ok(tape[start..start + len]
+        .iter()
+        .map(|cell| *cell as u8 as char)
+        .collect())
+}
+
+/// The host's own files and clock.
+#[derive(Clone, Debug, Default)]
+pub struct SystemHost {
+    /// The open files, by their handles. Closed files are `None`.
+    files: Vec<Option<Arc<File>>>,
+}
+
+impl SystemHost {
+    fn file(&self, handle: i64) -> Option<&File> {
+        let file = self.files.get(usize::try_from(handle).ok()?)?;
+        file.as_deref()
+    }
+}
+
+impl BindingHost for SystemHost {
+    fn open(&mut self, path: &str, mode: FileMode) -> Option<i64> {
+        let file = match mode {
+            FileMode::Read => File::open(path),
+            FileMode::Write => File::create(path),
+            FileMode::Append => OpenOptions::new().append(true).create(true).open(path),
+        }
+        .ok()?;
+        self.files.push(Some(Arc::new(file)));
+        Some(self.files.len() as i64 - 1)
+    }
+
+    fn read(&mut self, handle: i64, len: usize) -> Option<Vec<u8>> {
+        let mut file = self.file(handle)?;
+        let mut bytes = vec![0; len];
+        let read = file.read(&mut bytes).ok()?;
+        bytes.truncate(read);
+        Some(bytes)
+    }
+
+    fn write(&mut self, handle: i64, bytes: &[u8]) -> Option<usize> {
+        let mut file = self.file(handle)?;
+        file.write_all(bytes).ok()?;
+        Some(bytes.len())
+    }
+
+    fn close(&mut self, handle: i64) {
+        if let Some(file) = usize::try_from(handle)
+            .ok()
+            .and_then(|handle| self.files.get_mut(handle))
+        {
+            *file = None;
+        }
+    }
+
+    fn now_us(&mut self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_micros() as i64)
+    }
+
+    fn sleep_ms(&mut self, ms: i64) {
+        std::thread::sleep(Duration::from_millis(ms.max(0) as u64));
+    }
+}
+
+/// Files kept in memory, and a clock which only moves when the program sleeps,
+/// so that programs using the standard bindings can be tested.
+#[derive(Clone, Debug, Default)]
+pub struct MockHost {
+    /// The contents of the files, by their paths.
+    pub files: HashMap<String, Vec<u8>>,
+    /// The time of the clock, in microseconds since the Unix epoch.
+    pub now_us: i64,
+    /// The paths of the open files and the positions in them, by their handles.
+    /// Closed files are `None`.
+    open: Vec<Option<(String, usize)>>,
+}
+
+impl BindingHost for MockHost {
+    fn open(&mut self, path: &str, mode: FileMode) -> Option<i64> {
+        let position = match mode {
+            FileMode::Read => {
+                self.files.get(path)?;
+                0
+            }
+            FileMode::Write => {
+                self.files.insert(path.to_string(), vec![]);
+                0
+            }
+            FileMode::Append => self.files.entry(path.to_string()).or_default().len(),
+        };
+        self.open.push(Some((path.to_string(), position)));
+        Some(self.open.len() as i64 - 1)
+    }
+
+    fn read(&mut self, handle: i64, len: usize) -> Option<Vec<u8>> {
+        let (path, position) = self.open.get_mut(usize::try_from(handle).ok()?)?.as_mut()?;
+        let contents = self.files.get(path.as_str())?;
+        let start = (*position).min(contents.len());
+        let bytes = contents[start..(start + len).min(contents.len())].to_vec();
+        *position = start + bytes.len();
+        Some(bytes)
+    }
+
+    fn write(&mut self, handle: i64, bytes: &[u8]) -> Option<usize> {
+        let (path, position) = self.open.get_mut(usize::try_from(handle).ok()?)?.as_mut()?;
+        let contents = self.files.entry(path.clone()).or_default();
+        let end = *position + bytes.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[*position..end].copy_from_slice(bytes);
+        *position = end;
+        Some(bytes.len())
+    }
+
+    fn close(&mut self, handle: i64) {
+        if let Some(file) = usize::try_from(handle)
+            .ok()
+            .and_then(|handle| self.open.get_mut(handle))
+        {
+            *file = None;
+        }
+    }
+
+    fn now_us(&mut self) -> i64 {
+        self.now_us
+    }
+
+    fn sleep_ms(&mut self, ms: i64) {
+        self.now_us += ms.max(0) * 1000;
+    }
+}
@@ -2,9 +2,42 @@
 //!
 //! This module contains the definition of the foreign function interface (FFI) bindings, which
 //! are used in the various stages of IR to represent calls to foreign functions.
+//!
+//! The arguments of a foreign function are sent through the FFI channel one cell at a time,
+//! in the same layout they have in memory, and the return value is read back the same way.
+//! Structs, tuples, and arrays are flattened automatically by the compiler, so the host
+//! only has to decode the cells. The `FFIValue` trait does this for Rust values:
+//!
+//! ```rust
+//! use sage::ffi_struct;
+//! use sage::side_effects::*;
+//! use std::collections::VecDeque;
+//!
+//! struct Point {
+//!     x: f64,
+//!     y: f64,
+//! }
+//! ffi_struct!(Point { x: f64, y: f64 });
+//!
+//! // The host side of `extern fun midpoint(a: Point, b: Point): Point;`
+//! fn midpoint(channel: &mut VecDeque<i64>, _tape: Option<&mut Vec<i64>>) {
+//!     let (a, b) = pop_ffi_value::<(Point, Point)>(channel).unwrap();
+//!     push_ffi_value(channel, Point { x: (a.x + b.x) / 2.0, y: (a.y + b.y) / 2.0 });
+//! }
+//!
+//! let binding = FFIBinding::typed::<(Point, Point), Point>("midpoint");
+//! assert_eq!((binding.input_cells, binding.output_cells), (4, 2));
+//! ```
+//!
+//! `ffi_struct!` also gives the struct's LIR type, and the offsets of its members,
+//! so the host can declare the type to the program and read its values from the tape.
 
+use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
 
+use crate::lir::{ConstExpr, Type};
 use serde_derive::{Deserialize, Serialize};
 
 /// This is an FFI binding, which is used to call a foreign function in the virtual machine code.
@@ -12,11 +45,18 @@ use serde_derive::{Deserialize, Serialize};
 /// The name is the symbol for the foreign function. The input cells is the number of cells that
 /// the foreign function will read from the FFI channel. The output cells is the number of cells
 /// that the foreign function will write to the FFI channel.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+///
+/// The binding may also carry the C signature of the function, if every argument and the
+/// return value have a C equivalent. Targets which link with C libraries use it to call the
+/// function directly. It isn't part of the binding's identity: two bindings with the same
+/// name and cells are equal, whether or not they know the signature.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FFIBinding {
     pub name: String,
     pub input_cells: usize,
     pub output_cells: usize,
+    #[serde(default)]
+    pub signature: Option<CSignature>,
 }
 
 impl FFIBinding {
@@ -26,8 +66,111 @@ impl FFIBinding {
             name,
             input_cells,
             output_cells,
+            signature: None,
         }
     }
+
+    /// Give the binding the C signature of the foreign function.
+    pub fn with_signature(self, signature: CSignature) -> Self {
+        Self {
+            signature: Some(signature),
+            ..self
+        }
+    }
+
+    /// The fields which identify the binding.
+    fn key(&self) -> (&str, usize, usize) {
+        (&self.name, self.input_cells, self.output_cells)
+    }
+
+    /// Create an FFI binding for a foreign function which takes the arguments `Args`
+    /// (a tuple of them, for more than one) and returns `Ret`.
+    pub fn typed<Args: FFIValue, Ret: FFIValue>(name: impl ToString) -> Self {
+        Self::new(name.to_string(), Args::CELLS, Ret::CELLS)
+    }
+}
+
+impl PartialEq for FFIBinding {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for FFIBinding {}
+
+impl PartialOrd for FFIBinding {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FFIBinding {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for FFIBinding {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// The C type of a foreign function's argument or return value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CType {
+    /// No value, for functions which return nothing.
+    Void,
+    /// An `Int` or `Cell`, as an `int64_t`.
+    Int,
+    /// A `Float`, as a `double`.
+    Float,
+    /// A `Char`, as a `char`.
+    Char,
+    /// A `Bool`, as an `int` which is `0` or `1`.
+    Bool,
+    /// A pointer to cells, as a `void *`.
+    Pointer,
+    /// A pointer to a null-terminated string of `Char`s, which is copied into a C string
+    /// for the call.
+    String,
+}
+
+impl Display for CType {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Void => write!(f, "void"),
+            Self::Int => write!(f, "int64_t"),
+            Self::Float => write!(f, "double"),
+            Self::Char => write!(f, "char"),
+            Self::Bool => write!(f, "int"),
+            Self::Pointer => write!(f, "void *"),
+            Self::String => write!(f, "const char *"),
+        }
+    }
+}
+
+/// The C signature of a foreign function: the types of its arguments, and its return type.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CSignature {
+    pub args: Vec<CType>,
+    pub ret: CType,
+}
+
+impl CSignature {
+    /// The C prototype of a function with this signature.
+    pub fn prototype(&self, name: &str) -> String {
+        let args = if self.args.is_empty() {
+            "void".to_string()
+        } else {
+            self.args
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{} {name}({args});", self.ret)
+    }
 }
 
 impl Display for FFIBinding {
@@ -45,3 +188,266 @@ impl Debug for FFIBinding {
         )
     }
 }
+
+/// A Rust value which can be sent through the FFI channel, cell by cell,
+/// in the same layout as the matching LIR type.
+pub trait FFIValue: Sized {
+    /// The size of the value in cells.
+    const CELLS: usize;
+
+    /// The LIR type with the same layout as the value.
+    fn lir_type() -> Type;
+
+    /// The offsets of the members of a struct in cells, in the order they're declared.
+    /// Values which aren't structs have no members.
+    fn member_offsets() -> Vec<(&'static str, usize)> {
+        vec![]
+    }
+
+    /// Decode the value from exactly `CELLS` cells.
+    fn from_cells(cells: &[i64]) -> Self;
+
+    /// Encode the value into exactly `CELLS` cells.
+    fn to_cells(&self, cells: &mut [i64]);
+
+    /// Encode the value into a new list of cells, like to write it to a program's tape.
+    fn encode(&self) -> Vec<i64> {
+        let mut cells = vec![0; Self::CELLS];
+        self.to_cells(&mut cells);
+        cells
+    }
+}
+
+/// Pop a value from the front of the FFI channel, or return `None` if there aren't enough cells.
+pub fn pop_ffi_value<T: FFIValue>(channel: &mut VecDeque<i64>) -> Option<T> {
+    if channel.len() < T::CELLS {
+        return None;
+    }
+    let cells = channel.drain(..T::CELLS).collect::<Vec<_>>();
+    Some(T::from_cells(&cells))
+}
+
+/// Push a value to the back of the FFI channel.
+pub fn push_ffi_value<T: FFIValue>(channel: &mut VecDeque<i64>, value: T) {
+    let mut cells = vec![0; T::CELLS];
+    value.to_cells(&mut cells);
+    channel.extend(cells);
+}
+
+/// Get the offsets of the members of a struct, given in any order with their sizes.
+/// The members of a struct are laid out in the alphabetical order of their names.
+pub fn struct_offsets(members: &[(&str, usize)]) -> Vec<usize> {
+    let mut sorted = members.iter().enumerate().collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, (name, _))| *name);
+    let mut offsets = vec![0; members.len()];
+    let mut offset = 0;
+    for (i, (_, size)) in sorted {
+        offsets[i] = offset;
+        offset += size;
+    }
+    offsets
+}
+
+/// Implement `FFIValue` for a Rust struct which matches an LIR struct with the same members.
+/// The members can be listed in any order, since they're laid out by name. The struct's
+/// LIR type can be declared to programs with `Env::define_types`, so the host and the
+/// program always agree on its layout.
+#[macro_export]
+macro_rules! ffi_struct {
+    ($name:ident { $($member:ident : $ty:ty),* $(,)? }) => {
+        impl $crate::side_effects::FFIValue for $name {
+            const CELLS: usize = 0 $(+ <$ty as $crate::side_effects::FFIValue>::CELLS)*;
+
+            fn lir_type() -> $crate::lir::Type {
+                $crate::lir::Type::Struct(
+                    [$((
+                        stringify!($member).to_string(),
+                        <$ty as $crate::side_effects::FFIValue>::lir_type(),
+                    )),*]
+                    .into_iter()
+                    .collect(),
+                )
+            }
+
+            fn member_offsets() -> Vec<(&'static str, usize)> {
+                let names = [$(stringify!($member)),*];
+                let offsets = $crate::side_effects::struct_offsets(&[
+                    $((stringify!($member), <$ty as $crate::side_effects::FFIValue>::CELLS)),*
+                ]);
+                names.into_iter().zip(offsets).collect()
+            }
+
+            fn from_cells(cells: &[i64]) -> Self {
+                let offsets = $crate::side_effects::struct_offsets(&[
+                    $((stringify!($member), <$ty as $crate::side_effects::FFIValue>::CELLS)),*
+                ]);
+                let mut offsets = offsets.into_iter();
+                $(
+                    let offset = offsets.next().unwrap();
+                    let $member = <$ty as $crate::side_effects::FFIValue>::from_cells(
+                        &cells[offset..offset + <$ty as $crate::side_effects::FFIValue>::CELLS],
+                    );
+                )*
+                Self { $($member),* }
+            }
+
+            fn to_cells(&self, cells: &mut [i64]) {
+                let offsets = $crate::side_effects::struct_offsets(&[
+                    $((stringify!($member), <$ty as $crate::side_effects::FFIValue>::CELLS)),*
+                ]);
+                let mut offsets = offsets.into_iter();
+                $(
+                    let offset = offsets.next().unwrap();
+                    $crate::side_effects::FFIValue::to_cells(
+                        &self.$member,
+                        &mut cells[offset..offset + <$ty as $crate::side_effects::FFIValue>::CELLS],
+                    );
+                )*
+            }
+        }
+    };
+}
+
+impl FFIValue for () {
+    const CELLS: usize = 0;
+
+    fn lir_type() -> Type {
+        Type::None
+    }
+
+    fn from_cells(_cells: &[i64]) -> Self {}
+
+    fn to_cells(&self, _cells: &mut [i64]) {}
+}
+
+/// `Int` and `Cell` values.
+impl FFIValue for i64 {
+    const CELLS: usize = 1;
+
+    fn lir_type() -> Type {
+        Type::Int
+    }
+
+    fn from_cells(cells: &[i64]) -> Self {
+        cells[0]
+    }
+
+    fn to_cells(&self, cells: &mut [i64]) {
+        cells[0] = *self;
+    }
+}
+
+/// `Float` values, which are stored as their bits.
+impl FFIValue for f64 {
+    const CELLS: usize = 1;
+
+    fn lir_type() -> Type {
+        Type::Float
+    }
+
+    fn from_cells(cells: &[i64]) -> Self {
+        f64::from_bits(cells[0] as u64)
+    }
+
+    fn to_cells(&self, cells: &mut [i64]) {
+        cells[0] = self.to_bits() as i64;
+    }
+}
+
+/// `Char` values. Invalid characters decode as the replacement character.
+impl FFIValue for char {
+    const CELLS: usize = 1;
+
+    fn lir_type() -> Type {
+        Type::Char
+    }
+
+    fn from_cells(cells: &[i64]) -> Self {
+        u32::try_from(cells[0])
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn to_cells(&self, cells: &mut [i64]) {
+        cells[0] = *self as i64;
+    }
+}
+
+/// `Bool` values.
+impl FFIValue for bool {
+    const CELLS: usize = 1;
+
+    fn lir_type() -> Type {
+        Type::Bool
+    }
+
+    fn from_cells(cells: &[i64]) -> Self {
+        cells[0] != 0
+    }
+
+    fn to_cells(&self, cells: &mut [i64]) {
+        cells[0] = *self as i64;
+    }
+}
+
+/// Arrays, with their elements laid out one after another.
+impl<T: FFIValue, const N: usize> FFIValue for [T; N] {
+    const CELLS: usize = T::CELLS * N;
+
+    fn lir_type() -> Type {
+        Type::Array(Box::new(T::lir_type()), Box::new(ConstExpr::Int(N as i64)))
+    }
+
+    fn from_cells(cells: &[i64]) -> Self {
+        std::array::from_fn(|i| T::from_cells(&cells[i * T::CELLS..(i + 1) * T::CELLS]))
+    }
+
+    fn to_cells(&self, cells: &mut [i64]) {
+        for (item, cells) in self.iter().zip(cells.chunks_mut(T::CELLS.max(1))) {
+            item.to_cells(cells);
+        }
+    }
+}
+
+/// Implement `FFIValue` for tuples, with their items laid out in order.
+macro_rules! impl_ffi_value_for_tuple {
+    ($($item:ident $var:ident),+) => {
+        impl<$($item: FFIValue),+> FFIValue for ($($item,)+) {
+            const CELLS: usize = 0 $(+ $item::CELLS)+;
+
+            fn lir_type() -> Type {
+                Type::Tuple(vec![$($item::lir_type()),+])
+            }
+
+            #[allow(unused_assignments)]
+            fn from_cells(cells: &[i64]) -> Self {
+                let mut offset = 0;
+                $(
+                    let $var = $item::from_cells(&cells[offset..offset + $item::CELLS]);
+                    offset += $item::CELLS;
+                )+
+                ($($var,)+)
+            }
+
+            #[allow(unused_assignments)]
+            fn to_cells(&self, cells: &mut [i64]) {
+                let ($($var,)+) = self;
+                let mut offset = 0;
+                $(
+                    $var.to_cells(&mut cells[offset..offset + $item::CELLS]);
+                    offset += $item::CELLS;
+                )+
+            }
+        }
+    };
+}
+
+impl_ffi_value_for_tuple!(A a);
+impl_ffi_value_for_tuple!(A a, B b);
+impl_ffi_value_for_tuple!(A a, B b, C c);
+impl_ffi_value_for_tuple!(A a, B b, C c, D d);
+impl_ffi_value_for_tuple!(A a, B b, C c, D d, E e);
+impl_ffi_value_for_tuple!(A a, B b, C c, D d, E e, F f);
+impl_ffi_value_for_tuple!(A a, B b, C c, D d, E e, F f, G g);
+impl_ffi_value_for_tuple!(A a, B b, C c, D d, E e, F f, G g, H h);
@@ -0,0 +1,132 @@
+//! # Framebuffer
+//!
+//! This module defines the convention for drawing to a pixel framebuffer with the
+//! display output modes, so that any device with a screen can show a program's
+//! graphics. A framebuffer is drawn to on a single channel:
+//!
+//! ```text
+//! setcursorcolumn #0  ; the value is the x coordinate of the cursor
+//! setcursorrow #0     ; the value is the y coordinate of the cursor
+//! pixel #0            ; the value is a 0xRRGGBB color for the pixel under the cursor,
+//!                     ; and then the cursor moves right, wrapping onto the next row
+//! cleardisplay #0     ; the value is a 0xRRGGBB color to fill the framebuffer with
+//! updatedisplay #0    ; present the frame drawn so far
+//! ```
+//!
+//! [`Framebuffer`] keeps the pixels of a frame without showing them anywhere, so that
+//! devices can display it however they like, and tests can check what was drawn.
+
+use super::{Output, OutputMode};
+
+/// The pixels of a framebuffer, and the cursor drawing them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Framebuffer {
+    /// The width of the framebuffer in pixels.
+    width: usize,
+    /// The height of the framebuffer in pixels.
+    height: usize,
+    /// The 0xRRGGBB colors of the pixels, row by row. This is empty until something is drawn.
+    pixels: Vec<u32>,
+    /// The column and row of the cursor.
+    cursor: (usize, usize),
+    /// The number of frames which have been presented.
+    frames: usize,
+}
+
+impl Framebuffer {
+    /// The width of a framebuffer, if the device doesn't choose one.
+    pub const DEFAULT_WIDTH: usize = 320;
+    /// The height of a framebuffer, if the device doesn't choose one.
+    pub const DEFAULT_HEIGHT: usize = 240;
+
+    /// Create a black framebuffer with a given size.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            ..Self::default()
+        }
+    }
+
+    /// Is this output one of the framebuffer's display outputs?
+    pub fn handles(dst: &Output) -> bool {
+        matches!(
+            dst.mode,
+            OutputMode::SetCursorColumn
+                | OutputMode::SetCursorRow
+                | OutputMode::Pixel
+                | OutputMode::ClearDisplay
+                | OutputMode::UpdateDisplay
+        )
+    }
+
+    /// Apply a display output to the framebuffer. This returns `true` if a frame
+    /// was presented, so the device can show it.
+    pub fn put(&mut self, val: i64, dst: &Output) -> bool {
+        match dst.mode {
+            OutputMode::SetCursorColumn => self.cursor.0 = val.max(0) as usize,
+            OutputMode::SetCursorRow => self.cursor.1 = val.max(0) as usize,
+            OutputMode::Pixel => {
+                let (x, y) = self.cursor;
+                self.set_pixel(x, y, val as u32 & 0xFFFFFF);
+                self.cursor = if x + 1 < self.width {
+                    (x + 1, y)
+                } else {
+                    (0, y + 1)
+                };
+            }
+            OutputMode::ClearDisplay => {
+                self.pixels = vec![val as u32 & 0xFFFFFF; self.width * self.height];
+            }
+            OutputMode::UpdateDisplay => {
+                self.frames += 1;
+                return true;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Set the color of a pixel, if it's on the framebuffer.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u32) {
+        if x < self.width && y < self.height {
+            if self.pixels.is_empty() {
+                self.pixels = vec![0; self.width * self.height];
+            }
+            self.pixels[y * self.width + x] = color;
+        }
+    }
+
+    /// The color of a pixel, if it's on the framebuffer.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x < self.width && y < self.height {
+            Some(self.pixels.get(y * self.width + x).copied().unwrap_or(0))
+        } else {
+            None
+        }
+    }
+
+    /// The colors of all the pixels, row by row.
+    pub fn pixels(&self) -> Vec<u32> {
+        if self.pixels.is_empty() {
+            vec![0; self.width * self.height]
+        } else {
+            self.pixels.clone()
+        }
+    }
+
+    /// The width of the framebuffer in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The height of the framebuffer in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of frames which have been presented.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
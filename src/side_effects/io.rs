@@ -52,6 +52,18 @@ pub enum Color {
     RGB(u8, u8, u8),
 }
 
+/// The value a `keypoll` input reads when no key has been pressed.
+pub const NO_KEY: i64 = -1;
+/// The value a `keypoll` input reads for the up arrow key. Other keys read
+/// as their character codes, and the rest of the arrow keys follow this one.
+pub const KEY_UP: i64 = 0x100;
+/// The value a `keypoll` input reads for the down arrow key.
+pub const KEY_DOWN: i64 = 0x101;
+/// The value a `keypoll` input reads for the left arrow key.
+pub const KEY_LEFT: i64 = 0x102;
+/// The value a `keypoll` input reads for the right arrow key.
+pub const KEY_RIGHT: i64 = 0x103;
+
 /// The different types of input modes a program might use.
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub enum InputMode {
@@ -85,6 +97,12 @@ pub enum InputMode {
     Keyboard,
     /// Input from a JoyStick the degree of displacement in a given direction (from -128 to 128).
     JoyStick(Direction),
+    /// The next key pressed, without waiting for one (see [`NO_KEY`] and [`KEY_UP`])
+    KeyPoll,
+    /// The width of the terminal (in columns)
+    TerminalWidth,
+    /// The height of the terminal (in rows)
+    TerminalHeight,
 
     ///////////////////////////////////////////////////////////////
     /// Physical sensor input modes
@@ -267,6 +285,10 @@ pub enum OutputMode {
     SpeakerVolume,
     /// Set the frequency of a given speaker (in hertz)
     SpeakerFrequency,
+    /// Play a PCM sample on a given speaker (from -32768 to 32767)
+    Sample,
+    /// Set the sample rate of a given speaker (in hertz)
+    SampleRate,
 
     ///////////////////////////////////////////////////////////////
     /// Display output modes
@@ -291,6 +313,14 @@ pub enum OutputMode {
     SetCursorChar(Color),
     /// Set the color of a given pixel on the display
     SetCursorPixel(Color),
+    /// Set the pixel under the cursor to a 0xRRGGBB color, and move the cursor right
+    Pixel,
+
+    ///////////////////////////////////////////////////////////////
+    /// Program control output modes
+    ///////////////////////////////////////////////////////////////
+    /// Halt the program with the given exit status (0=success)
+    Exit,
 
     ///////////////////////////////////////////////////////////////
     /// Custom output modes
@@ -392,6 +422,11 @@ impl Output {
     pub const fn stderr_float() -> Self {
         Self::new(OutputMode::StderrFloat, 0)
     }
+
+    /// Halt the program with an exit status
+    pub const fn exit() -> Self {
+        Self::new(OutputMode::Exit, 0)
+    }
 }
 
 impl Display for Input {
@@ -439,6 +474,12 @@ impl Display for InputMode {
             InputMode::Keyboard => write!(f, "keyboard"),
             // Input from a JoyStick the degree of displacement in a given direction (from -128 to 128).
             InputMode::JoyStick(dir) => write!(f, "joystick.{dir}"),
+            // The next key pressed, without waiting for one
+            InputMode::KeyPoll => write!(f, "keypoll"),
+            // The width of the terminal (in columns)
+            InputMode::TerminalWidth => write!(f, "terminalwidth"),
+            // The height of the terminal (in rows)
+            InputMode::TerminalHeight => write!(f, "terminalheight"),
 
             ///////////////////////////////////////////////////////////////
             // Physical sensor input modes
@@ -624,6 +665,10 @@ impl Display for OutputMode {
             OutputMode::SpeakerVolume => write!(f, "speakervolume"),
             // Set the frequency of a given speaker (in hertz)
             OutputMode::SpeakerFrequency => write!(f, "speakerfrequency"),
+            // Play a PCM sample on a given speaker (from -32768 to 32767)
+            OutputMode::Sample => write!(f, "sample"),
+            // Set the sample rate of a given speaker (in hertz)
+            OutputMode::SampleRate => write!(f, "samplerate"),
 
             ///////////////////////////////////////////////////////////////
             // Display output modes
@@ -648,6 +693,14 @@ impl Display for OutputMode {
             OutputMode::SetCursorChar(c) => write!(f, "setcursorchar.{c}"),
             // Set the color of a given pixel on the display
             OutputMode::SetCursorPixel(c) => write!(f, "setcursorpixel.{c}"),
+            // Set the pixel under the cursor to a 0xRRGGBB color, and move the cursor right
+            OutputMode::Pixel => write!(f, "pixel"),
+
+            ///////////////////////////////////////////////////////////////
+            // Program control output modes
+            ///////////////////////////////////////////////////////////////
+            // Halt the program with the given exit status (0=success)
+            OutputMode::Exit => write!(f, "exit"),
 
             ///////////////////////////////////////////////////////////////
             // Custom output modes
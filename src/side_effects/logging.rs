@@ -0,0 +1,83 @@
+//! # Logging
+//!
+//! Programs log messages with the `log_debug`, `log_info`, `log_warn`, and `log_error`
+//! builtins. Each message is written to standard error on [`LOG_CHANNEL`] as a line,
+//! starting with the prefix of its [`LogLevel`]:
+//!
+//! ```text
+//! [WARN] the disk is almost full
+//! ```
+//!
+//! Devices keep these lines apart from the program's own output, so that logging
+//! doesn't change what a program prints.
+
+use super::{Output, OutputMode};
+
+/// The channel that log messages are written to, on the standard error output modes.
+pub const LOG_CHANNEL: usize = 2;
+
+/// How important a log message is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Every log level, from the least important to the most.
+    pub const ALL: [Self; 4] = [Self::Debug, Self::Info, Self::Warn, Self::Error];
+
+    /// The prefix of a message logged at this level.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Debug => "[DEBUG] ",
+            Self::Info => "[INFO] ",
+            Self::Warn => "[WARN] ",
+            Self::Error => "[ERROR] ",
+        }
+    }
+
+    /// The name of the builtin which logs at this level.
+    pub fn builtin(&self) -> &'static str {
+        match self {
+            Self::Debug => "log_debug",
+            Self::Info => "log_info",
+            Self::Warn => "log_warn",
+            Self::Error => "log_error",
+        }
+    }
+
+    /// The level a logging builtin logs at, if the name is one of them.
+    pub fn from_builtin(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.builtin() == name)
+    }
+
+    /// Split a logged line into its level and its message.
+    pub fn parse_line(line: &str) -> Option<(Self, &str)> {
+        Self::ALL
+            .into_iter()
+            .find_map(|level| Some((level, line.strip_prefix(level.prefix())?)))
+    }
+
+    /// Is this output part of a log message?
+    pub fn is_log(dst: &Output) -> bool {
+        dst.channel.0 == LOG_CHANNEL
+            && matches!(
+                dst.mode,
+                OutputMode::StderrChar | OutputMode::StderrInt | OutputMode::StderrFloat
+            )
+    }
+}
+
+impl From<LogLevel> for log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Debug => Self::Debug,
+            LogLevel::Info => Self::Info,
+            LogLevel::Warn => Self::Warn,
+            LogLevel::Error => Self::Error,
+        }
+    }
+}
@@ -3,8 +3,16 @@
 //! This module implements all the types related to the foreign function interface
 //! and I/O operations for the Sage VM and all other stages of IR
 
+pub mod audio;
+pub mod bindings;
 pub mod ffi;
+pub mod framebuffer;
 pub mod io;
+pub mod logging;
 
+pub use audio::*;
+pub use bindings::*;
 pub use ffi::*;
+pub use framebuffer::*;
 pub use io::*;
+pub use logging::*;
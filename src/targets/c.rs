@@ -3,17 +3,134 @@
 //! An implementation of the virtual machine for the C language.
 //!
 //! This allows the virtual machine to target C programs.
-use super::{Architecture, CompiledTarget};
+//!
+//! The output is meant to be debugged with tools like `gdb`:
+//! - Each procedure is named after the label it was assembled from (like `f3_main`).
+//! - The registers of the assembly language are named (like `sp_reg` and `a_reg`).
+//! - Loops and conditionals are indented by their nesting.
+//! - `#line` directives map the code back to the lines of the source program.
+//!
+//! Foreign functions are normally called as `__name`, which the user defines in `ffi.h`
+//! to read its arguments from the FFI channel. With a [`CLinkage`], the foreign functions
+//! are instead called directly from the given C headers and libraries, with their arguments
+//! converted from their LIR types (see [`CSignature`]). Without headers, prototypes are
+//! declared from the LIR types.
+//!
+//! Characters are read and written with the [`IoHooks`] in the target's [`TargetConfig`],
+//! which are libc's `putchar` and `getchar` by default. Other hooks must be declared
+//! in `ffi.h`, or in one of the linked headers.
+use super::{Architecture, CompiledTarget, Target, TargetConfig, TAPE_BOUNDS_ERROR};
 use crate::{
-    side_effects::{Input, InputMode, Output, OutputMode},
-    vm::{CoreOp, StandardOp},
+    asm::{self, Location},
+    side_effects::{CSignature, CType, Input, InputMode, Output, OutputMode},
+    vm::{self, CoreOp, StandardOp},
 };
 use log::warn;
 
 /// The type for the C target which implements the `Target` trait.
 /// This allows the compiler to target the C language.
-#[derive(Default)]
-pub struct C;
+pub struct C {
+    /// The headers and libraries foreign functions are called from directly, if any.
+    linkage: Option<CLinkage>,
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for C {
+    fn default() -> Self {
+        Self {
+            linkage: None,
+            config: TargetConfig::default(),
+        }
+    }
+}
+
+impl C {
+    /// Call the foreign functions directly from the given headers and libraries,
+    /// instead of through the FFI channel.
+    pub fn with_linkage(linkage: CLinkage) -> Self {
+        Self {
+            linkage: Some(linkage),
+            ..Self::default()
+        }
+    }
+}
+
+/// The C headers and libraries which foreign functions are linked from.
+#[derive(Clone, Debug, Default)]
+pub struct CLinkage {
+    /// The headers which declare the foreign functions (like `math.h`).
+    pub headers: Vec<String>,
+    /// The libraries which define the foreign functions (like `m` for `-lm`).
+    pub libraries: Vec<String>,
+}
+
+impl CLinkage {
+    /// The preprocessor lines which include the headers, after a comment
+    /// listing the libraries to link with.
+    fn includes(&self) -> String {
+        let mut result = String::new();
+        if !self.libraries.is_empty() {
+            let flags = self
+                .libraries
+                .iter()
+                .map(|lib| format!("-l{lib}"))
+                .collect::<Vec<_>>();
+            result += &format!("// Link with: {}\n", flags.join(" "));
+        }
+        for header in &self.headers {
+            if header.starts_with('<') || header.starts_with('"') {
+                result += &format!("#include {header}\n");
+            } else {
+                result += &format!("#include \"{header}\"\n");
+            }
+        }
+        result
+    }
+}
+
+/// Call a foreign function directly with its C signature. The arguments are popped
+/// from the FFI channel, and the return value is pushed back onto it.
+fn direct_call(name: &str, signature: &CSignature, prototype: bool) -> String {
+    let mut result = String::from("{\n");
+    if !signature.args.is_empty() {
+        result += &format!(
+            "ffi_ptr -= {};\ncell *args = ffi_ptr + 1;\n",
+            signature.args.len()
+        );
+    }
+    if prototype {
+        result += &format!("extern {}\n", signature.prototype(name));
+    }
+    let mut args = vec![];
+    let mut strings = vec![];
+    for (i, ty) in signature.args.iter().enumerate() {
+        args.push(match ty {
+            CType::Int => format!("args[{i}].i"),
+            CType::Float => format!("args[{i}].f"),
+            CType::Char => format!("(char)args[{i}].i"),
+            CType::Bool => format!("args[{i}].i != 0"),
+            CType::Pointer | CType::Void => format!("(void *)args[{i}].p"),
+            CType::String => {
+                result += &format!("char *str{i} = sage_c_string(args[{i}].p);\n");
+                strings.push(i);
+                format!("str{i}")
+            }
+        });
+    }
+    let call = format!("{name}({})", args.join(", "));
+    result += &match signature.ret {
+        CType::Void => format!("{call};\n"),
+        CType::Int | CType::Char => format!("(++ffi_ptr)->i = {call};\n"),
+        CType::Bool => format!("(++ffi_ptr)->i = {call} != 0;\n"),
+        CType::Float => format!("(++ffi_ptr)->f = {call};\n"),
+        CType::Pointer | CType::String => format!("(++ffi_ptr)->p = (cell *){call};\n"),
+    };
+    for i in strings {
+        result += &format!("free(str{i});\n");
+    }
+    result + "}"
+}
 
 impl Architecture for C {
     fn supports_input(&self, i: &Input) -> bool {
@@ -26,12 +143,17 @@ impl Architecture for C {
     fn supports_output(&self, o: &Output) -> bool {
         matches!(
             o.mode,
-            OutputMode::StdoutChar | OutputMode::StdoutFloat | OutputMode::StdoutInt
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::Exit
         )
     }
 
     fn op(&mut self, op: &CoreOp) -> String {
         match op {
+            // Source lines are marked with `#line` directives, so debuggers show the source.
+            CoreOp::Comment(text) if vm::is_line_comment(text) => text.clone(),
             CoreOp::Comment(text) => {
                 format!("// {}", text.replace('\n', "\n// ").replace('\r', ""))
                 // let mut comment = String::new();
@@ -51,7 +173,7 @@ impl Architecture for C {
                 // } else {
                 // }
                 for (i, val) in n.iter().enumerate() {
-                    tmp += &format!("vector_reg[{}].i = {};", i, val);
+                    tmp += &format!(" vector_reg[{}].i = {};", i, val);
                 }
                 tmp
                 // format!("scalar_reg.i = {};", n)
@@ -73,6 +195,10 @@ impl Architecture for C {
                 format!("memcpy(vector_reg, ptr, {n} * sizeof(cell)); scalar_reg = ptr[0];")
             }
 
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "ptr += {n}; if (!ref_ptr && (ptr < tape || ptr >= tape + {})) tape_out_of_bounds();",
+                self.config.tape_cells
+            ),
             CoreOp::Move(n) => format!("ptr += {};", n),
             CoreOp::Where => "scalar_reg.p = ptr;".to_string(),
             CoreOp::Deref => "refs[ref_ptr++] = ptr; ptr = ptr->p;".to_string(),
@@ -83,20 +209,26 @@ impl Architecture for C {
             CoreOp::Index(1) => "scalar_reg.p += ptr->i;".to_string(),
             CoreOp::Index(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].p += ptr[i].i;"),
 
-            CoreOp::Add(1) => "scalar_reg.i += ptr->i;".to_string(),
-            CoreOp::Add(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i += ptr[i].i;"),
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            // Signed overflow is undefined in C, so the arithmetic is done on unsigned integers.
+            CoreOp::Add(1) => "scalar_reg.i = (int64_t)((uint64_t)scalar_reg.i + (uint64_t)ptr->i);".to_string(),
+            CoreOp::Add(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (int64_t)((uint64_t)vector_reg[i].i + (uint64_t)ptr[i].i);"),
 
-            CoreOp::Sub(1) => "scalar_reg.i -= ptr->i;".to_string(),
-            CoreOp::Sub(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i -= ptr[i].i;"),
+            CoreOp::Sub(1) => "scalar_reg.i = (int64_t)((uint64_t)scalar_reg.i - (uint64_t)ptr->i);".to_string(),
+            CoreOp::Sub(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (int64_t)((uint64_t)vector_reg[i].i - (uint64_t)ptr[i].i);"),
 
-            CoreOp::Mul(1) => "scalar_reg.i *= ptr->i;".to_string(),
-            CoreOp::Mul(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i *= ptr[i].i;"),
+            CoreOp::Mul(1) => "scalar_reg.i = (int64_t)((uint64_t)scalar_reg.i * (uint64_t)ptr->i);".to_string(),
+            CoreOp::Mul(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = (int64_t)((uint64_t)vector_reg[i].i * (uint64_t)ptr[i].i);"),
 
-            CoreOp::Div(1) => "scalar_reg.i /= ptr->i;".to_string(),
-            CoreOp::Div(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i /= ptr[i].i;"),
+            CoreOp::Div(1) => "scalar_reg.i = int_div(scalar_reg.i, ptr->i);".to_string(),
+            CoreOp::Div(n) => format!(
+                "for (int i = 0; i < {n}; i++) vector_reg[i].i = int_div(vector_reg[i].i, ptr[i].i);"
+            ),
 
-            CoreOp::Rem(1) => "scalar_reg.i %= ptr->i;".to_string(),
-            CoreOp::Rem(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i %= ptr[i].i;"),
+            CoreOp::Rem(1) => "scalar_reg.i = int_rem(scalar_reg.i, ptr->i);".to_string(),
+            CoreOp::Rem(n) => format!(
+                "for (int i = 0; i < {n}; i++) vector_reg[i].i = int_rem(vector_reg[i].i, ptr[i].i);"
+            ),
 
             CoreOp::Neg(1) => "scalar_reg.i = -scalar_reg.i;".to_string(),
             CoreOp::Neg(n) => format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = -vector_reg[i].i;"),
@@ -158,13 +290,18 @@ impl Architecture for C {
 
     fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
         Ok(match op {
-            StandardOp::Call(ffi) => format!("__{}();", ffi.name),
+            StandardOp::Call(ffi) => match (&self.linkage, &ffi.signature) {
+                (Some(linkage), Some(signature)) => {
+                    direct_call(&ffi.name, signature, linkage.headers.is_empty())
+                }
+                _ => format!("__{}();", ffi.name),
+            },
             StandardOp::Peek => self.peek()?,
             StandardOp::Poke => self.poke()?,
             StandardOp::Set(n) => {
                 let mut tmp = format!("scalar_reg.f = {};", n[0]);
                 for (i, val) in n.iter().enumerate() {
-                    tmp += &format!("vector_reg[{}].f = {};", i, val);
+                    tmp += &format!(" vector_reg[{}].f = {};", i, val);
                 }
                 tmp
             }
@@ -244,10 +381,24 @@ impl Architecture for C {
                 format!("for (int i = 0; i < {n}; i++) vector_reg[i].i = vector_reg[i].f >= 0;")
             }
 
-            StandardOp::Alloc => {
-                "scalar_reg.p = (cell*)malloc(scalar_reg.i * sizeof(cell));".to_string()
+            StandardOp::Alloc => "scalar_reg.p = heap_alloc(scalar_reg.i);".to_string(),
+            StandardOp::Free => "heap_free(scalar_reg.p);".to_string(),
+            StandardOp::Realloc => "scalar_reg.p = heap_realloc(scalar_reg.p, ptr->i);".to_string(),
+            StandardOp::HeapUsed => "scalar_reg.i = heap_used;".to_string(),
+            // Freed memory is returned to `malloc`, so the runtime never holds onto any.
+            StandardOp::HeapFree => "scalar_reg.i = 0;".to_string(),
+
+            StandardOp::AtomicLoad => {
+                "scalar_reg.i = __atomic_load_n(&scalar_reg.p->i, __ATOMIC_SEQ_CST);".to_string()
+            }
+            StandardOp::AtomicStore => {
+                "__atomic_store_n(&scalar_reg.p->i, ptr->i, __ATOMIC_SEQ_CST);".to_string()
             }
-            StandardOp::Free => "free(scalar_reg.p);".to_string(),
+            StandardOp::AtomicAdd => {
+                "scalar_reg.i = __atomic_fetch_add(&scalar_reg.p->i, ptr->i, __ATOMIC_SEQ_CST);"
+                    .to_string()
+            }
+            StandardOp::AtomicCompareSwap => "scalar_reg.i = __atomic_compare_exchange_n(&scalar_reg.p->i, &ptr[0].i, ptr[1].i, 0, __ATOMIC_SEQ_CST, __ATOMIC_SEQ_CST);".to_string(),
             _ => return Err(format!("Invalid standard op for C target {op:?}")),
         })
     }
@@ -259,8 +410,8 @@ impl Architecture for C {
         }
     }
 
-    fn declare_proc(&mut self, label_id: usize) -> String {
-        format!("void f{label_id}() {{")
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        format!("\nvoid {}() {{", function_name(label_id, label))
     }
 
     fn name(&self) -> &str {
@@ -276,30 +427,33 @@ impl Architecture for C {
 
     fn get(&mut self, src: &Input) -> Result<String, String> {
         let ch = src.channel.0;
+        let get_char = &self.config.hooks.get_char;
         match src.mode {
-            InputMode::StdinChar => Ok("tmp = getchar(); scalar_reg.i = tmp == EOF? 0 : tmp;".to_string()),
+            InputMode::StdinChar => Ok(format!("tmp = {get_char}(); scalar_reg.i = tmp == EOF? 0 : tmp;")),
             InputMode::StdinInt => Ok("scanf(\"%ld\", &tmp_scalar_reg.i); scalar_reg = tmp_scalar_reg;".to_string()),
             InputMode::StdinFloat => Ok("scanf(\"%lf\", &tmp_scalar_reg.f); scalar_reg = tmp_scalar_reg;".to_string()),
             InputMode::Thermometer => Ok("scalar_reg.f = 293.15;".to_string()),
             InputMode::Clock => Ok("scalar_reg.i = time(NULL);".to_string()),
             InputMode::Random => Ok("scalar_reg.i = rand();".to_string()),
             InputMode::Button => Ok(format!(
-                "printf(\"Button #{ch}: \"); scalar_reg.i = getchar() == 'y'; while (getchar() != 10);"
+                "printf(\"Button #{ch}: \"); scalar_reg.i = {get_char}() == 'y'; while ({get_char}() != 10);"
             )),
             _ => Err("Input not supported by this target".to_string()),
         }
     }
 
     fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let hooks = &self.config.hooks;
         match dst.mode {
-            OutputMode::StdoutChar => Ok("putchar(scalar_reg.i);".to_string()),
+            OutputMode::StdoutChar => Ok(format!("{}(scalar_reg.i);", hooks.put_char)),
             OutputMode::StdoutInt => Ok("printf(\"%lld\", scalar_reg.i);".to_string()),
             OutputMode::StdoutFloat => Ok("printf(\"%.1lf\", scalar_reg.f);".to_string()),
-            OutputMode::StderrChar => Ok("fprintf(stderr, \"%c\", scalar_reg.i);".to_string()),
+            OutputMode::StderrChar => Ok(format!("{}(scalar_reg.i);", hooks.eput_char)),
             OutputMode::StderrInt => Ok("fprintf(stderr, \"%lld\", scalar_reg.i);".to_string()),
             OutputMode::StderrFloat => Ok("fprintf(stderr, \"%.1lf\", scalar_reg.f);".to_string()),
             OutputMode::Heater => Ok("printf(\"Heating...\");".to_string()),
             OutputMode::Cooler => Ok("printf(\"Cooling...\");".to_string()),
+            OutputMode::Exit => Ok("exit(scalar_reg.i);".to_string()),
             _ => Err("Output not supported by this target".to_string()),
         }
     }
@@ -309,19 +463,25 @@ impl Architecture for C {
     fn poke(&mut self) -> Result<String, String> {
         Ok("*(++ffi_ptr) = scalar_reg;".to_string())
     }
-    fn prelude(&self, is_core: bool) -> Option<String> {
-        let mut result = r#"#include <stdint.h>
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let result = format!(
+            r#"#include <stdlib.h>
+#include <stdint.h>
 #include <stdio.h>
 #include <math.h>
 #include <string.h>
 
-typedef union cell {
+typedef union cell {{
     int64_t i;
     double f;
     union cell *p;
-} cell;
+}} cell;
 
-cell tape[67108864], *refs[1024], *ptr = tape, **ref = refs, scalar_reg, vector_reg[1024], tmp_reg, ffi_channel[256], *ffi_ptr = ffi_channel;
+cell tape[{}], *refs[1024], *ptr = tape, **ref = refs, scalar_reg, vector_reg[1024], tmp_reg, ffi_channel[256], *ffi_ptr = ffi_channel;
+"#,
+            self.config.tape_cells
+        ) + &register_names()
+            + r#"
 
 unsigned int ref_ptr = 0;
 void (*funs[10000])(void);
@@ -331,20 +491,78 @@ void (*funs[10000])(void);
 #endif
 
 int tmp;
-"#
-        .to_string();
+"# + INT_DIVISION
+            + r#"
+// Every allocation is preceded by a cell holding its size,
+// so that the runtime can keep track of how many cells are in use.
+// Like the interpreter, freeing NULL does nothing, and reallocating NULL allocates.
+int64_t heap_used = 0;
+
+cell *heap_alloc(int64_t size) {
+    cell *block = (cell*)malloc((size + 1) * sizeof(cell));
+    if (block == NULL) return NULL;
+    block->i = size;
+    heap_used += size;
+    return block + 1;
+}
 
-        if !is_core {
-            result = "#include <stdlib.h>\n".to_string() + &result;
-        }
+void heap_free(cell *p) {
+    if (p == NULL) return;
+    heap_used -= p[-1].i;
+    free(p - 1);
+}
 
-        Some(result)
+cell *heap_realloc(cell *p, int64_t size) {
+    if (p == NULL) return heap_alloc(size);
+    int64_t old = p[-1].i;
+    cell *block = (cell*)realloc(p - 1, (size + 1) * sizeof(cell));
+    // If the block can't be resized, it's left as it was.
+    if (block == NULL) return NULL;
+    block->i = size;
+    heap_used += size - old;
+    return block + 1;
+}
+"# + &format!(
+            r#"
+// Write a character to the standard error, the default hook for it.
+int sage_eputchar(int ch) {{
+    return fputc(ch, stderr);
+}}
+
+void tape_out_of_bounds(void) {{
+    fprintf(stderr, "{TAPE_BOUNDS_ERROR}\n");
+    exit(1);
+}}
+"#
+        );
+
+        match &self.linkage {
+            Some(linkage) => Some(
+                linkage.includes()
+                    + &result
+                    + r#"
+// Copy a null-terminated string of cells into a C string, to pass to a C function.
+char *sage_c_string(cell *s) {
+    size_t n = 0;
+    while (s[n].i) n++;
+    char *result = (char*)malloc(n + 1);
+    for (size_t i = 0; i < n; i++) result[i] = (char)s[i].i;
+    result[n] = 0;
+    return result;
+}
+"#,
+            ),
+            None => Some(result),
+        }
     }
 
-    fn post_funs(&self, funs: Vec<i32>) -> Option<String> {
-        let mut result = String::from("int main () {\n");
-        for fun in funs {
-            result += &format!("\tfuns[{fun}] = f{fun};\n", fun = fun)
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let mut result = String::from("\nint main () {\n");
+        for (fun, label) in funs {
+            result += &format!(
+                "\tfuns[{fun}] = {};\n",
+                function_name(fun as usize, label.as_deref())
+            )
         }
         Some(result)
     }
@@ -359,3 +577,83 @@ int tmp;
 }
 
 impl CompiledTarget for C {}
+
+impl Target for C {
+    fn extension(&self) -> &str {
+        "c"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
+
+/// The name of the C function for a procedure, after the label it was assembled from.
+pub(super) fn function_name(label_id: usize, label: Option<&str>) -> String {
+    let mut name = format!("f{label_id}");
+    if let Some(label) = label {
+        // Replace the characters which can't be in an identifier, like in mangled names.
+        let mut sanitized = String::new();
+        for ch in label.chars() {
+            if ch.is_ascii_alphanumeric() {
+                sanitized.push(ch);
+            } else if !sanitized.is_empty() && !sanitized.ends_with('_') {
+                sanitized.push('_');
+            }
+        }
+        let sanitized: String = sanitized.trim_end_matches('_').chars().take(48).collect();
+        if !sanitized.is_empty() {
+            name += "_";
+            name += &sanitized;
+        }
+    }
+    name
+}
+
+/// The functions which integer division and remainder are compiled to,
+/// shared by the hosted and freestanding C preludes.
+pub(super) const INT_DIVISION: &str = r#"
+// Dividing by zero, and the smallest integer by -1, are undefined in C,
+// so these divisors are handled first.
+int64_t int_div(int64_t a, int64_t b) {
+    if (b == 0) return a;
+    if (b == -1) return (int64_t)(0 - (uint64_t)a);
+    return a / b;
+}
+
+int64_t int_rem(int64_t a, int64_t b) {
+    if (b == 0) return a;
+    if (b == -1) return 0;
+    return a % b;
+}
+"#;
+
+/// Name the registers of the assembly language, which are cells on the tape,
+/// so that they can be inspected in a debugger (like `print *fp_reg`).
+pub(super) fn register_names() -> String {
+    let registers = [
+        ("sp", asm::SP),
+        ("fp", asm::FP),
+        ("gp", asm::GP),
+        ("a", asm::A),
+        ("b", asm::B),
+        ("c", asm::C),
+        ("d", asm::D),
+        ("e", asm::E),
+        ("f", asm::F),
+    ];
+    let mut result = String::from("cell");
+    for (i, (name, register)) in registers.iter().enumerate() {
+        if let Location::Address(addr) = register {
+            if i > 0 {
+                result += ",";
+            }
+            result += &format!(" *const {name}_reg = tape + {addr}");
+        }
+    }
+    result + ";\n"
+}
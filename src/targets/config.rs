@@ -0,0 +1,111 @@
+//! # Target Configuration
+//!
+//! Every target emits a small runtime along with the compiled program: the tape,
+//! the functions it does I/O with, and so on. A [`TargetConfig`] describes that
+//! runtime, so that the same target can be built for a machine with a smaller
+//! memory, or linked against a different set of I/O functions.
+//!
+//! Each target starts from its own default configuration (see
+//! [`Target::config`](super::Target::config)), which can be replaced with
+//! [`Target::configure`](super::Target::configure).
+
+/// The names of the functions a target calls to read and write characters.
+///
+/// Hosted targets use libc's `putchar` and `getchar` by default, and the
+/// freestanding targets expect the user to provide `sage_putchar`,
+/// `sage_eputchar`, and `sage_getchar`. The JavaScript and Python targets
+/// do I/O through a device object instead, so they have no hooks.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IoHooks {
+    /// Write a character to the standard output.
+    pub put_char: String,
+    /// Write a character to the standard error.
+    pub eput_char: String,
+    /// Read a character from the standard input.
+    pub get_char: String,
+}
+
+impl IoHooks {
+    /// Use the given functions to read and write characters.
+    pub fn new(put_char: impl ToString, eput_char: impl ToString, get_char: impl ToString) -> Self {
+        Self {
+            put_char: put_char.to_string(),
+            eput_char: eput_char.to_string(),
+            get_char: get_char.to_string(),
+        }
+    }
+
+    /// The libc functions used by the hosted targets. Standard error
+    /// is written with `sage_eputchar`, which the target defines.
+    pub fn libc() -> Self {
+        Self::new("putchar", "sage_eputchar", "getchar")
+    }
+
+    /// The functions the user provides for the freestanding targets.
+    pub fn freestanding() -> Self {
+        Self::new("sage_putchar", "sage_eputchar", "sage_getchar")
+    }
+}
+
+/// The configuration of the runtime a target emits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TargetConfig {
+    /// The number of cells on the tape. This must be larger than the
+    /// call stack size the program is assembled with.
+    pub tape_cells: usize,
+    /// The width of a cell in bits. Every target only implements one cell width,
+    /// so this can't be changed, but it is checked when configuring a target.
+    pub cell_width: usize,
+    /// Check that the tape pointer stays on the tape when it's moved outside of
+    /// a `Deref` (when it must point into the tape), and exit with an error if it doesn't.
+    pub bounds_check: bool,
+    /// The functions to read and write characters with.
+    pub hooks: IoHooks,
+}
+
+/// The configuration of the hosted targets: a tape of 64M 64-bit cells,
+/// without bounds checking, using libc for I/O.
+impl Default for TargetConfig {
+    fn default() -> Self {
+        Self::new(67108864, 64, IoHooks::libc())
+    }
+}
+
+impl TargetConfig {
+    /// A configuration without bounds checking.
+    pub fn new(tape_cells: usize, cell_width: usize, hooks: IoHooks) -> Self {
+        Self {
+            tape_cells,
+            cell_width,
+            bounds_check: false,
+            hooks,
+        }
+    }
+
+    /// Use a tape with the given number of cells.
+    pub fn with_tape_cells(mut self, tape_cells: usize) -> Self {
+        self.tape_cells = tape_cells;
+        self
+    }
+
+    /// Enable or disable bounds checking the tape.
+    pub fn with_bounds_check(mut self, bounds_check: bool) -> Self {
+        self.bounds_check = bounds_check;
+        self
+    }
+
+    /// Use the given functions to read and write characters.
+    pub fn with_hooks(mut self, hooks: IoHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// The number of bytes on the tape.
+    pub fn tape_bytes(&self) -> usize {
+        self.tape_cells * self.cell_width / 8
+    }
+}
+
+/// The message a target prints when bounds checking finds
+/// the tape pointer off the end of the tape.
+pub(crate) const TAPE_BOUNDS_ERROR: &str = "error: tape pointer moved out of bounds";
@@ -0,0 +1,804 @@
+//! # ARM Cortex-M Target
+//!
+//! An implementation of the virtual machine for bare-metal ARM Cortex-M microcontrollers
+//! (ARMv7-M, like the Cortex-M3 and M4), which emits Thumb-2 GNU assembler (`.s`) files:
+//!
+//! ```bash
+//! $ arm-none-eabi-gcc -mcpu=cortex-m3 -mthumb -nostartfiles -T board.ld output.s uart.c -o output.elf
+//! ```
+//!
+//! Like the freestanding C target, the program doesn't use an operating system or libc,
+//! and I/O calls hooks which the user supplies, when linking the program. The names of the
+//! character hooks can be changed in the target's [`TargetConfig`]:
+//!
+//! ```c
+//! void sage_putchar(int32_t ch);   // Write a character to the UART.
+//! void sage_eputchar(int32_t ch);  // Write a character to the error UART (or the same one).
+//! int32_t sage_getchar(void);      // Read a character, or return -1 at the end of input.
+//! void sage_exit(int32_t status);  // Halt with an exit status. This must not return.
+//! ```
+//!
+//! Cells are 32 bits wide, and there is no floating point, so only programs without floats
+//! can be compiled. The heap is a static arena with a first fit free list, the same as in
+//! the freestanding C target, so nothing needs to be linked besides the hooks.
+//!
+//! The tape is a static array of `SAGE_TAPE_CELLS` cells in SRAM, and the heap is an array of
+//! `SAGE_HEAP_CELLS` cells, 4096 each by default (the size of the tape is set by the
+//! configuration). They can be resized when assembling, like with
+//! `-Wa,--defsym,SAGE_TAPE_CELLS=8192`. The call stack size the program is assembled
+//! with must be smaller than the tape. With bounds checking, moving the tape pointer off the
+//! tape (outside of a `Deref`) writes an error with the error hook, and calls `sage_exit(1)`. The tape and the rest of the machine's state are
+//! cleared when the program starts, so it doesn't depend on how the linker script
+//! initializes memory.
+//!
+//! The output includes a vector table in the `.isr_vector` section, which starts the stack at
+//! `_estack` (from the linker script), and a weak `Reset_Handler` which runs the program and
+//! then calls `sage_exit(0)`. Boards with their own startup code can call `sage_main` instead.
+//!
+//! The machine's state is kept in callee-saved registers:
+//!
+//! | Register | Contents                                                   |
+//! |----------|------------------------------------------------------------|
+//! | `r4`     | The tape pointer.                                          |
+//! | `r5`     | The register (the first cell of the vector register).      |
+//! | `r6`     | The top of the stack of pointers saved by `Deref`.         |
+//! | `r7`     | The vector register, which is in memory.                   |
+//! | `r8`     | The start of the tape.                                     |
+//!
+//! The registers of the assembly language (like `SP` and `A`) stay on the tape, at the
+//! offsets from `r8` named like `A_REG`.
+use super::{
+    c::function_name, Architecture, CompiledTarget, IoHooks, LineDirectives, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    asm::{self, Location},
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{self, CoreOp, StandardOp},
+};
+
+/// The size of a cell in bytes.
+const CELL: isize = 4;
+
+/// The code which returns from a function. The return address is pushed with a scratch
+/// register, to keep the stack aligned to 8 bytes for calls to C.
+const RETURN: &str = "pop {r0, pc}";
+
+/// The type for the Cortex-M target which implements the `Target` trait.
+/// This allows the compiler to target microcontrollers without a C compiler.
+pub struct CortexM {
+    /// The number of loops and conditionals compiled so far, to label them uniquely.
+    blocks: usize,
+    /// The labels of the loops and conditionals being compiled.
+    open_blocks: Vec<usize>,
+    /// The source files named by line directives.
+    lines: LineDirectives,
+    /// The name of the function being compiled, to mark its size at its end.
+    function: Option<String>,
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for CortexM {
+    fn default() -> Self {
+        Self {
+            blocks: 0,
+            open_blocks: Vec::new(),
+            lines: LineDirectives::default(),
+            function: None,
+            config: TargetConfig::new(4096, 32, IoHooks::freestanding()),
+        }
+    }
+}
+
+/// Load a constant into a register. Cells are 32 bits, so larger constants wrap.
+fn load_imm(reg: &str, value: i64) -> String {
+    let value = value as u32;
+    if value <= 0xffff {
+        format!("movw {reg}, #{value}")
+    } else {
+        format!(
+            "movw {reg}, #{}\nmovt {reg}, #{}",
+            value & 0xffff,
+            value >> 16
+        )
+    }
+}
+
+/// Load the address of a symbol into a register.
+fn load_addr(reg: &str, symbol: &str) -> String {
+    format!("movw {reg}, #:lower16:{symbol}\nmovt {reg}, #:upper16:{symbol}")
+}
+
+/// Add a constant to a register, using `r0` if it doesn't fit in an immediate.
+fn add_imm(reg: &str, imm: isize) -> String {
+    match imm {
+        0..=4095 => format!("addw {reg}, {reg}, #{imm}"),
+        -4095..=-1 => format!("subw {reg}, {reg}, #{}", -imm),
+        _ => format!("{}\nadd {reg}, {reg}, r0", load_imm("r0", imm as i64)),
+    }
+}
+
+/// Repeat an instruction body over the first `n` cells of the vector register, which `r1`
+/// points to, and the cells under the tape pointer, which `r2` points to.
+/// The register is kept in sync with the first cell.
+fn vector(n: usize, body: &str) -> String {
+    format!(
+        "str r5, [r7]
+mov r1, r7
+mov r2, r4
+{}
+1:
+{body}
+adds r1, r1, #4
+adds r2, r2, #4
+subs r3, r3, #1
+bne 1b
+ldr r5, [r7]",
+        load_imm("r3", n as i64)
+    )
+}
+
+/// An instruction on the register and the cell under the tape pointer,
+/// or on each of the first `n` cells of the vector register and the tape.
+fn binop(n: usize, op: &str) -> String {
+    if n == 1 {
+        format!("ldr r0, [r4]\n{op} r5, r5, r0")
+    } else {
+        vector(
+            n,
+            &format!("ldr r0, [r1]\nldr r12, [r2]\n{op} r0, r0, r12\nstr r0, [r1]"),
+        )
+    }
+}
+
+/// An instruction on the register, or on each of the first `n` cells of the vector register.
+fn unop(n: usize, op: &str) -> String {
+    if n == 1 {
+        op.replace("{}", "r5")
+    } else {
+        vector(
+            n,
+            &format!("ldr r0, [r1]\n{}\nstr r0, [r1]", op.replace("{}", "r0")),
+        )
+    }
+}
+
+impl Architecture for CortexM {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(i.mode, InputMode::StdinChar | InputMode::StdinInt)
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrInt
+                | OutputMode::Exit
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => match vm::comment_line(text) {
+                Some((line, filename)) => self.lines.directive(line, filename),
+                None => format!("@ {}", text.replace('\n', "\n@ ").replace('\r', "")),
+            },
+            // Conditional branches have a shorter range, so they skip over jumps to the labels.
+            CoreOp::While => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!(
+                    ".Lwhile{n}:\ncmp r5, #0\nbne 1f\nb .Lend{n}\n1:",
+                    n = self.blocks
+                )
+            }
+            CoreOp::If => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!("cmp r5, #0\nbne 1f\nb .Lelse{}\n1:", self.blocks)
+            }
+            CoreOp::Else => {
+                let n = self.open_blocks.last().copied().unwrap_or_default();
+                format!("b .Lend{n}\n.Lelse{n}:")
+            }
+            CoreOp::Set(n) => {
+                let mut result = load_imm("r5", n[0]);
+                for (i, val) in n.iter().enumerate().skip(1) {
+                    result += &format!("\n{}\nstr r0, [r7, #{}]", load_imm("r0", *val), i * 4);
+                }
+                result
+            }
+            CoreOp::Call => format!(
+                "{}\nldr r0, [r0, r5, lsl #2]\nblx r0",
+                load_addr("r0", "funs")
+            ),
+            CoreOp::Return => RETURN.to_string(),
+            CoreOp::Store(1) => "str r5, [r4]".to_string(),
+            CoreOp::Load(1) => "ldr r5, [r4]".to_string(),
+            CoreOp::Store(n) => vector(*n, "ldr r0, [r1]\nstr r0, [r2]"),
+            CoreOp::Load(n) => vector(*n, "ldr r0, [r2]\nstr r0, [r1]"),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "{}\n{}\ncmp r6, r0\nbne 1f\n{}\nsub r0, r0, r8\nsub r1, r4, r8\ncmp r1, r0\nbhs tape_out_of_bounds\n1:",
+                add_imm("r4", n * CELL),
+                load_addr("r0", "refs"),
+                load_addr("r0", "tape_end")
+            ),
+            CoreOp::Move(n) => add_imm("r4", n * CELL),
+            CoreOp::Where => "mov r5, r4".to_string(),
+            CoreOp::Deref => "str r4, [r6], #4\nldr r4, [r4]".to_string(),
+            CoreOp::Refer => "ldr r4, [r6, #-4]!".to_string(),
+            CoreOp::Offset(n, 1) => add_imm("r5", n * CELL),
+            CoreOp::Offset(n, size) => vector(
+                *size,
+                &format!(
+                    "ldr r0, [r1]\n{}\nadd r0, r0, r12\nstr r0, [r1]",
+                    load_imm("r12", (n * CELL) as i64)
+                ),
+            ),
+
+            CoreOp::Index(1) => "ldr r0, [r4]\nadd r5, r5, r0, lsl #2".to_string(),
+            CoreOp::Index(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\nadd r0, r0, r12, lsl #2\nstr r0, [r1]",
+            ),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            CoreOp::Add(n) => binop(*n, "add"),
+            CoreOp::Sub(n) => binop(*n, "sub"),
+            CoreOp::Mul(n) => binop(*n, "mul"),
+            // `sdiv` by zero gives 0 (or traps, if the core is set up to), so zero divisors
+            // are skipped. The smallest integer divided by -1 already wraps.
+            CoreOp::Div(1) => "ldr r0, [r4]\ncmp r0, #0\nbeq 2f\nsdiv r5, r5, r0\n2:".to_string(),
+            CoreOp::Div(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\ncmp r12, #0\nbeq 2f\nsdiv r0, r0, r12\nstr r0, [r1]\n2:",
+            ),
+            CoreOp::Rem(1) => {
+                "ldr r0, [r4]\ncmp r0, #0\nbeq 2f\nsdiv r1, r5, r0\nmls r5, r1, r0, r5\n2:".to_string()
+            }
+            CoreOp::Rem(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\ncmp r12, #0\nbeq 2f\nsdiv r10, r0, r12\nmls r0, r10, r12, r0\nstr r0, [r1]\n2:",
+            ),
+            CoreOp::Neg(n) => unop(*n, "rsb {}, {}, #0"),
+            CoreOp::Inc(n) => unop(*n, "add {}, {}, #1"),
+            CoreOp::Dec(n) => unop(*n, "sub {}, {}, #1"),
+
+            CoreOp::Swap(1) => "ldr r0, [r4]\nstr r5, [r4]\nmov r5, r0".to_string(),
+            CoreOp::Swap(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\nstr r12, [r1]\nstr r0, [r2]",
+            ),
+
+            CoreOp::And(1) => "ldr r0, [r4]\ncmp r0, #0\nit ne\nmovne r0, #1\ncmp r5, #0\nit ne\nmovne r5, #1\nand r5, r5, r0".to_string(),
+            CoreOp::And(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\ncmp r0, #0\nit ne\nmovne r0, #1\ncmp r12, #0\nit ne\nmovne r12, #1\nand r0, r0, r12\nstr r0, [r1]",
+            ),
+
+            CoreOp::Or(1) => "ldr r0, [r4]\norrs r5, r5, r0\nit ne\nmovne r5, #1".to_string(),
+            CoreOp::Or(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\norrs r0, r0, r12\nit ne\nmovne r0, #1\nstr r0, [r1]",
+            ),
+
+            CoreOp::Not(n) => unop(*n, "cmp {}, #0\nite eq\nmoveq {}, #1\nmovne {}, #0"),
+
+            CoreOp::BitwiseNand(1) => "ldr r0, [r4]\nand r5, r5, r0\nmvn r5, r5".to_string(),
+            CoreOp::BitwiseNand(n) => vector(
+                *n,
+                "ldr r0, [r1]\nldr r12, [r2]\nand r0, r0, r12\nmvn r0, r0\nstr r0, [r1]",
+            ),
+
+            CoreOp::BitwiseAnd(n) => binop(*n, "and"),
+            CoreOp::BitwiseOr(n) => binop(*n, "orr"),
+            CoreOp::BitwiseXor(n) => binop(*n, "eor"),
+            CoreOp::BitwiseNot(n) => unop(*n, "mvn {}, {}"),
+
+            CoreOp::LeftShift(n) => binop(*n, "lsl"),
+            CoreOp::LogicalRightShift(n) => binop(*n, "lsr"),
+            CoreOp::ArithmeticRightShift(n) => binop(*n, "asr"),
+
+            // The sign bit of the complement is set for non-negative numbers.
+            CoreOp::IsNonNegative(n) => unop(*n, "mvn {}, {}\nlsr {}, {}, #31"),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for Cortex-M target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => format!("bl __{}", ffi.name),
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+
+            StandardOp::Alloc => "mov r0, r5\nbl heap_alloc\nmov r5, r0".to_string(),
+            StandardOp::Free => "mov r0, r5\nbl heap_free".to_string(),
+            StandardOp::Realloc => {
+                "mov r0, r5\nldr r1, [r4]\nbl heap_realloc\nmov r5, r0".to_string()
+            }
+            StandardOp::HeapUsed => format!("{}\nldr r5, [r0]", load_addr("r0", "heap_used")),
+            StandardOp::HeapFree => "bl heap_available\nmov r5, r0".to_string(),
+
+            StandardOp::AtomicLoad => "ldr r5, [r5]\ndmb".to_string(),
+            StandardOp::AtomicStore => "ldr r0, [r4]\ndmb\nstr r0, [r5]\ndmb".to_string(),
+            StandardOp::AtomicAdd => "ldr r0, [r4]\ndmb\n1:\nldrex r1, [r5]\nadd r2, r1, r0\nstrex r3, r2, [r5]\ncmp r3, #0\nbne 1b\ndmb\nmov r5, r1".to_string(),
+            // Store the value found back over the expected value, like `__atomic_compare_exchange_n`.
+            StandardOp::AtomicCompareSwap => "ldr r0, [r4]\nldr r12, [r4, #4]\ndmb\n1:\nldrex r1, [r5]\ncmp r1, r0\nbne 2f\nstrex r3, r12, [r5]\ncmp r3, #0\nbne 1b\n2:\nclrex\ndmb\nstr r1, [r4]\ncmp r1, r0\nite eq\nmoveq r5, #1\nmovne r5, #0".to_string(),
+            _ => return Err(format!("Invalid standard op for Cortex-M target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => match self.function.take() {
+                // Debuggers find the function a line is in by the function's size.
+                Some(name) => format!("{RETURN}\n.size {name}, .-{name}"),
+                None => RETURN.to_string(),
+            },
+            (CoreOp::While, _) => {
+                let n = self.open_blocks.pop().unwrap_or_default();
+                format!("b .Lwhile{n}\n.Lend{n}:")
+            }
+            (CoreOp::If, _) => format!(".Lelse{}:", self.open_blocks.pop().unwrap_or_default()),
+            (CoreOp::Else, _) => format!(".Lend{}:", self.open_blocks.pop().unwrap_or_default()),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        let name = function_name(label_id, label);
+        let header = format!("\n.type {name}, %function\n.thumb_func\n{name}:\npush {{r0, lr}}");
+        self.function = Some(name);
+        header
+    }
+
+    fn name(&self) -> &str {
+        "Cortex-M"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        false
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => {
+                Ok("bl next_char\ncmp r0, #0\nit lt\nmovlt r0, #0\nmov r5, r0".to_string())
+            }
+            InputMode::StdinInt => Ok("bl get_int\nmov r5, r0".to_string()),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let hooks = &self.config.hooks;
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(format!("mov r0, r5\nbl {}", hooks.put_char)),
+            OutputMode::StdoutInt => Ok(format!(
+                "mov r0, r5\n{}\nbl put_int",
+                load_addr("r1", &hooks.put_char)
+            )),
+            OutputMode::StderrChar => Ok(format!("mov r0, r5\nbl {}", hooks.eput_char)),
+            OutputMode::StderrInt => Ok(format!(
+                "mov r0, r5\n{}\nbl put_int",
+                load_addr("r1", &hooks.eput_char)
+            )),
+            OutputMode::Exit => Ok("mov r0, r5\nbl sage_exit".to_string()),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok(format!(
+            "{}\nldr r1, [r0]\nldr r5, [r1]\nsub r1, r1, #4\nstr r1, [r0]",
+            load_addr("r0", "ffi_ptr")
+        ))
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok(format!(
+            "{}\nldr r1, [r0]\nadd r1, r1, #4\nstr r1, [r0]\nstr r5, [r1]",
+            load_addr("r0", "ffi_ptr")
+        ))
+    }
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let mut result = format!(
+            "@ Assemble and link with `arm-none-eabi-gcc -mcpu=cortex-m3 -mthumb -nostartfiles`.
+.syntax unified
+.cpu cortex-m3
+.thumb
+
+.ifndef SAGE_TAPE_CELLS
+.set SAGE_TAPE_CELLS, {}
+.endif
+.ifndef SAGE_HEAP_CELLS
+.set SAGE_HEAP_CELLS, 4096
+.endif
+
+@ The registers of the assembly language, at their offsets from the start of the tape in r8.
+",
+            self.config.tape_cells
+        );
+        let registers = [
+            ("SP", asm::SP),
+            ("FP", asm::FP),
+            ("GP", asm::GP),
+            ("A", asm::A),
+            ("B", asm::B),
+            ("C", asm::C),
+            ("D", asm::D),
+            ("E", asm::E),
+            ("F", asm::F),
+        ];
+        for (name, register) in registers {
+            if let Location::Address(addr) = register {
+                result += &format!(".set {name}_REG, {}\n", addr as isize * CELL);
+            }
+        }
+        result += r#"
+@ The machine's state, which is cleared when the program starts.
+.section .bss.sage, "aw", %nobits
+.align 3
+sage_bss_start:
+tape: .space SAGE_TAPE_CELLS * 4
+tape_end:
+vector_reg: .space 4096
+refs: .space 1024
+ffi_channel: .space 1024
+.globl ffi_ptr
+ffi_ptr: .space 4
+heap: .space SAGE_HEAP_CELLS * 4
+heap_end:
+heap_top: .space 4
+free_blocks: .space 4
+heap_used: .space 4
+@ The last character read by `get_int` which wasn't part of the number, or -2.
+unread: .space 4
+sage_bss_end:
+
+@ The vector table: the initial stack pointer, and the reset handler.
+.section .isr_vector, "a", %progbits
+.align 2
+.globl sage_vectors
+sage_vectors:
+	.word _estack
+	.word Reset_Handler
+
+.text
+
+.weak Reset_Handler
+.type Reset_Handler, %function
+.thumb_func
+Reset_Handler:
+	bl sage_main
+	movs r0, #0
+	bl sage_exit
+1:
+	b 1b
+
+@ Read a decimal integer, skipping whitespace before it.
+.type get_int, %function
+.thumb_func
+get_int:
+	push {r4, r5, r6, lr}
+	movs r5, #0
+	movs r6, #1
+1:
+	bl next_char
+	cmp r0, #32
+	beq 1b
+	cmp r0, #9
+	beq 1b
+	cmp r0, #10
+	beq 1b
+	cmp r0, #13
+	beq 1b
+	cmp r0, #'-'
+	bne 2f
+	mvn r6, #0
+	bl next_char
+	b 3f
+2:
+	cmp r0, #'+'
+	bne 3f
+	bl next_char
+3:
+	sub r1, r0, #'0'
+	cmp r1, #9
+	bhi 4f
+	movs r2, #10
+	mla r5, r5, r2, r1
+	bl next_char
+	b 3b
+4:
+	movw r1, #:lower16:unread
+	movt r1, #:upper16:unread
+	str r0, [r1]
+	mul r0, r5, r6
+	pop {r4, r5, r6, pc}
+
+@ Allocate a block of r0 cells from the heap, returning a null pointer if it's full.
+@ Every block is preceded by a cell holding its size, and freed blocks are linked
+@ through their first cell, to be reused first fit.
+.type heap_alloc, %function
+.thumb_func
+heap_alloc:
+	cmp r0, #1
+	it lt
+	movlt r0, #1
+	movw r1, #:lower16:free_blocks
+	movt r1, #:upper16:free_blocks
+1:
+	ldr r2, [r1]
+	cmp r2, #0
+	beq 2f
+	ldr r3, [r2]
+	cmp r3, r0
+	bge 3f
+	add r1, r2, #4
+	b 1b
+3:
+	ldr r12, [r2, #4]
+	str r12, [r1]
+	b 4f
+2:
+	movw r1, #:lower16:heap_top
+	movt r1, #:upper16:heap_top
+	ldr r2, [r1]
+	movw r3, #:lower16:heap_end
+	movt r3, #:upper16:heap_end
+	sub r3, r3, r2
+	add r12, r0, #1
+	cmp r3, r12, lsl #2
+	bhs 5f
+	movs r0, #0
+	bx lr
+5:
+	add r12, r2, r12, lsl #2
+	str r12, [r1]
+	str r0, [r2]
+4:
+	ldr r3, [r2]
+	movw r1, #:lower16:heap_used
+	movt r1, #:upper16:heap_used
+	ldr r12, [r1]
+	add r12, r12, r3
+	str r12, [r1]
+	add r0, r2, #4
+	bx lr
+
+@ Free the block at r0.
+.type heap_free, %function
+.thumb_func
+heap_free:
+	ldr r1, [r0, #-4]
+	movw r2, #:lower16:heap_used
+	movt r2, #:upper16:heap_used
+	ldr r3, [r2]
+	sub r3, r3, r1
+	str r3, [r2]
+	movw r2, #:lower16:free_blocks
+	movt r2, #:upper16:free_blocks
+	ldr r3, [r2]
+	str r3, [r0]
+	sub r0, r0, #4
+	str r0, [r2]
+	bx lr
+
+@ Resize the block at r0 to r1 cells, moving it if it's too small.
+.type heap_realloc, %function
+.thumb_func
+heap_realloc:
+	ldr r2, [r0, #-4]
+	cmp r2, r1
+	it ge
+	bxge lr
+	push {r4, r5, r6, lr}
+	mov r4, r0
+	mov r0, r1
+	bl heap_alloc
+	cmp r0, #0
+	beq 2f
+	mov r5, r0
+	ldr r1, [r4, #-4]
+	mov r2, r4
+1:
+	ldr r3, [r2], #4
+	str r3, [r0], #4
+	subs r1, r1, #1
+	bne 1b
+	mov r0, r4
+	bl heap_free
+	mov r0, r5
+2:
+	pop {r4, r5, r6, pc}
+
+@ The number of cells which can still be allocated, in the free blocks and the rest of the arena.
+.type heap_available, %function
+.thumb_func
+heap_available:
+	movw r1, #:lower16:heap_top
+	movt r1, #:upper16:heap_top
+	ldr r1, [r1]
+	movw r0, #:lower16:heap_end
+	movt r0, #:upper16:heap_end
+	sub r0, r0, r1
+	lsr r0, r0, #2
+	movw r1, #:lower16:free_blocks
+	movt r1, #:upper16:free_blocks
+	ldr r1, [r1]
+1:
+	cmp r1, #0
+	beq 2f
+	ldr r2, [r1]
+	add r0, r0, r2
+	ldr r1, [r1, #4]
+	b 1b
+2:
+	bx lr
+
+@ Print the integer in r0 in decimal, with the character hook in r1.
+.type put_int, %function
+.thumb_func
+put_int:
+	push {r4, r5, r6, lr}
+	sub sp, sp, #16
+	mov r4, r0
+	mov r5, r1
+	cmp r4, #0
+	bge 1f
+	movs r0, #'-'
+	blx r5
+	rsb r4, r4, #0
+1:
+	movs r6, #0
+2:
+	movs r0, #10
+	udiv r1, r4, r0
+	mls r2, r1, r0, r4
+	add r2, r2, #'0'
+	strb r2, [sp, r6]
+	add r6, r6, #1
+	movs r4, r1
+	bne 2b
+3:
+	sub r6, r6, #1
+	ldrb r0, [sp, r6]
+	blx r5
+	cmp r6, #0
+	bne 3b
+	add sp, sp, #16
+	pop {r4, r5, r6, pc}
+"#;
+        result += &format!(
+            r#"
+@ Read a character, after the one `get_int` didn't use.
+.type next_char, %function
+.thumb_func
+next_char:
+	movw r1, #:lower16:unread
+	movt r1, #:upper16:unread
+	ldr r0, [r1]
+	cmn r0, #2
+	bne 1f
+	b {}
+1:
+	mvn r2, #1
+	str r2, [r1]
+	bx lr
+
+@ Report the tape pointer moving off the tape with the error hook, and exit.
+.type tape_out_of_bounds, %function
+.thumb_func
+tape_out_of_bounds:
+	{}
+1:
+	ldrb r0, [r4], #1
+	cmp r0, #0
+	beq 2f
+	bl {}
+	b 1b
+2:
+	movs r0, #1
+	bl sage_exit
+3:
+	b 3b
+
+.section .rodata
+tape_bounds_error: .string "{TAPE_BOUNDS_ERROR}\n"
+"#,
+            self.config.hooks.get_char,
+            load_addr("r4", "tape_bounds_error").replace('\n', "\n\t"),
+            self.config.hooks.eput_char
+        );
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        // The table of functions is constant, so it stays in flash.
+        let mut result = String::from("\n.section .rodata\n.align 2\nfuns:\n");
+        for (fun, label) in funs {
+            result += &format!(
+                "\t.word {}\n",
+                function_name(fun as usize, label.as_deref())
+            );
+        }
+        result += r#"
+.text
+.globl sage_main
+.type sage_main, %function
+.thumb_func
+sage_main:
+	push {r4-r11, lr}
+	sub sp, sp, #4
+	@ Clear the machine's state.
+	movw r0, #:lower16:sage_bss_start
+	movt r0, #:upper16:sage_bss_start
+	movw r1, #:lower16:sage_bss_end
+	movt r1, #:upper16:sage_bss_end
+	movs r2, #0
+1:
+	cmp r0, r1
+	bhs 2f
+	str r2, [r0], #4
+	b 1b
+2:
+	movw r0, #:lower16:unread
+	movt r0, #:upper16:unread
+	mvn r1, #1
+	str r1, [r0]
+	movw r0, #:lower16:heap_top
+	movt r0, #:upper16:heap_top
+	movw r1, #:lower16:heap
+	movt r1, #:upper16:heap
+	str r1, [r0]
+	movw r0, #:lower16:ffi_ptr
+	movt r0, #:upper16:ffi_ptr
+	movw r1, #:lower16:ffi_channel
+	movt r1, #:upper16:ffi_channel
+	str r1, [r0]
+	movw r4, #:lower16:tape
+	movt r4, #:upper16:tape
+	mov r8, r4
+	movs r5, #0
+	movw r6, #:lower16:refs
+	movt r6, #:upper16:refs
+	movw r7, #:lower16:vector_reg
+	movt r7, #:upper16:vector_reg
+"#;
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some("add sp, sp, #4\n\tpop {r4-r11, pc}\n.size sage_main, .-sage_main\n".to_string())
+    }
+}
+
+impl CompiledTarget for CortexM {}
+
+impl Target for CortexM {
+    fn extension(&self) -> &str {
+        "s"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -0,0 +1,355 @@
+//! # Freestanding C Target
+//!
+//! An implementation of the virtual machine for C without the standard library,
+//! so that programs can be compiled for microcontrollers and kernels.
+//!
+//! The generated code only includes the freestanding headers `stdint.h` and `stddef.h`.
+//! Everything else the hosted C target gets from libc is replaced:
+//!
+//! - I/O calls hooks which the user supplies, when linking the program (the names of
+//!   the character hooks can be changed in the target's [`TargetConfig`]):
+//!   ```c
+//!   void sage_putchar(int64_t ch);   // Write a character to the standard output.
+//!   void sage_eputchar(int64_t ch);  // Write a character to the standard error.
+//!   int64_t sage_getchar(void);      // Read a character, or return -1 at the end of input.
+//!   void sage_exit(int64_t status);  // Halt with an exit status. This must not return.
+//!   ```
+//!   Integers and floats are printed and parsed a character at a time with these hooks.
+//! - The heap is a static arena of `SAGE_HEAP_CELLS` cells, with a first fit free list.
+//!   Allocating more than the arena holds returns a null pointer.
+//! - `memcpy` is defined weakly, so a libc's version is used instead if one is linked.
+//! - The floating point math functions (like `sin` and `pow`) are declared, but not
+//!   defined: link with a math library, or define them, if the program uses them.
+//!
+//! There is no `main` function: the startup code calls `sage_main` to run the program.
+//! The size of the tape can be set with `SAGE_TAPE_CELLS` (which defaults to the size in the
+//! configuration), and it must be larger than the call stack size the program was assembled
+//! with. With bounds checking, moving the tape pointer off the tape (outside of a `Deref`)
+//! writes an error with the error hook, and calls `sage_exit(1)`.
+use super::{
+    c::{function_name, register_names, INT_DIVISION},
+    Architecture, CompiledTarget, IoHooks, Target, TargetConfig, C, TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp},
+};
+
+/// The type for the freestanding C target which implements the `Target` trait.
+/// This allows the compiler to target C without a hosted environment.
+pub struct FreestandingC {
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for FreestandingC {
+    fn default() -> Self {
+        Self {
+            config: TargetConfig::new(67108864, 64, IoHooks::freestanding()),
+        }
+    }
+}
+
+impl Architecture for FreestandingC {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+                | OutputMode::Exit
+        )
+    }
+
+    /// The core instructions are the same as for hosted C.
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "ptr += {n}; if (!ref_ptr && (ptr < tape || ptr >= tape + SAGE_TAPE_CELLS)) tape_out_of_bounds();"
+            ),
+            _ => C::default().op(op),
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        match op {
+            StandardOp::HeapFree => Ok("scalar_reg.i = heap_available();".to_string()),
+            other => C::default().std_op(other),
+        }
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        C::default().end(matching, fun)
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        C::default().declare_proc(label_id, label)
+    }
+
+    fn name(&self) -> &str {
+        "Freestanding C"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok(
+                "tmp_int = next_char(); scalar_reg.i = tmp_int < 0? 0 : tmp_int;".to_string(),
+            ),
+            InputMode::StdinInt => Ok("scalar_reg.i = get_int();".to_string()),
+            InputMode::StdinFloat => Ok("scalar_reg.f = get_float();".to_string()),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let IoHooks {
+            put_char,
+            eput_char,
+            ..
+        } = &self.config.hooks;
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(format!("{put_char}(scalar_reg.i);")),
+            OutputMode::StdoutInt => Ok(format!("put_int({put_char}, scalar_reg.i);")),
+            OutputMode::StdoutFloat => Ok(format!("put_float({put_char}, scalar_reg.f);")),
+            OutputMode::StderrChar => Ok(format!("{eput_char}(scalar_reg.i);")),
+            OutputMode::StderrInt => Ok(format!("put_int({eput_char}, scalar_reg.i);")),
+            OutputMode::StderrFloat => Ok(format!("put_float({eput_char}, scalar_reg.f);")),
+            OutputMode::Exit => Ok("sage_exit(scalar_reg.i);".to_string()),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        C::default().peek()
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        C::default().poke()
+    }
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let IoHooks {
+            put_char,
+            eput_char,
+            get_char,
+        } = &self.config.hooks;
+        let result = format!(
+            r#"#include <stdint.h>
+#include <stddef.h>
+
+#ifndef SAGE_TAPE_CELLS
+#define SAGE_TAPE_CELLS {}
+#endif
+#ifndef SAGE_HEAP_CELLS
+#define SAGE_HEAP_CELLS 65536
+#endif
+
+typedef union cell {{
+    int64_t i;
+    double f;
+    union cell *p;
+}} cell;
+
+cell tape[SAGE_TAPE_CELLS], *refs[1024], *ptr = tape, **ref = refs, scalar_reg, vector_reg[1024], tmp_reg, ffi_channel[256], *ffi_ptr = ffi_channel;
+"#,
+            self.config.tape_cells
+        ) + &register_names()
+            + r#"
+
+unsigned int ref_ptr = 0;
+void (*funs[10000])(void);
+
+#if __has_include("ffi.h")
+#include "ffi.h"
+#endif
+
+int64_t tmp_int;
+"# + &format!(
+            r#"
+// The hooks for I/O, which are supplied by the user.
+void {put_char}(int64_t ch);
+void {eput_char}(int64_t ch);
+int64_t {get_char}(void);
+void sage_exit(int64_t status);
+
+// The last character read by `get_int` or `get_float` which wasn't part of the number.
+int64_t unread = -2;
+
+int64_t next_char() {{
+    int64_t ch = unread == -2? {get_char}() : unread;
+    unread = -2;
+    return ch;
+}}
+
+void tape_out_of_bounds(void) {{
+    for (const char *s = "{TAPE_BOUNDS_ERROR}\n"; *s; s++) {eput_char}(*s);
+    sage_exit(1);
+}}
+"#
+        ) + r#"
+// The floating point math functions, which are supplied by a math library or the user.
+double sin(double), cos(double), tan(double), asin(double), acos(double), atan(double);
+double fmod(double, double), pow(double, double);
+
+__attribute__((weak)) void *memcpy(void *dst, const void *src, size_t n) {
+    char *d = dst;
+    const char *s = src;
+    while (n--) *d++ = *s++;
+    return dst;
+}
+
+void put_int(void (*put)(int64_t), int64_t n) {
+    char digits[20];
+    int len = 0;
+    uint64_t u = n < 0? -(uint64_t)n : (uint64_t)n;
+    if (n < 0) put('-');
+    do {
+        digits[len++] = '0' + u % 10;
+        u /= 10;
+    } while (u);
+    while (len) put(digits[--len]);
+}
+
+// Print a float rounded to one decimal place, like `%.1lf`.
+void put_float(void (*put)(int64_t), double f) {
+    if (f < 0) {
+        put('-');
+        f = -f;
+    }
+    uint64_t tenths = f * 10 + 0.5;
+    put_int(put, tenths / 10);
+    put('.');
+    put('0' + tenths % 10);
+}
+
+int64_t get_int() {
+    int64_t ch = next_char(), n = 0, sign = 1;
+    while (ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r') ch = next_char();
+    if (ch == '-' || ch == '+') {
+        if (ch == '-') sign = -1;
+        ch = next_char();
+    }
+    for (; ch >= '0' && ch <= '9'; ch = next_char()) n = n * 10 + (ch - '0');
+    unread = ch;
+    return sign * n;
+}
+
+double get_float() {
+    int64_t ch = next_char(), sign = 1;
+    double n = 0, scale = 1;
+    while (ch == ' ' || ch == '\t' || ch == '\n' || ch == '\r') ch = next_char();
+    if (ch == '-' || ch == '+') {
+        if (ch == '-') sign = -1;
+        ch = next_char();
+    }
+    for (; ch >= '0' && ch <= '9'; ch = next_char()) n = n * 10 + (ch - '0');
+    if (ch == '.') {
+        for (ch = next_char(); ch >= '0' && ch <= '9'; ch = next_char()) {
+            scale /= 10;
+            n += (ch - '0') * scale;
+        }
+    }
+    unread = ch;
+    return sign * n;
+}
+
+// The heap is a static arena. Every block is preceded by a cell holding its size,
+// and freed blocks are linked through their first cell, to be reused first fit.
+cell heap[SAGE_HEAP_CELLS], *heap_top = heap, *free_blocks = NULL;
+int64_t heap_used = 0;
+
+cell *heap_alloc(int64_t size) {
+    // Every block needs room for the link to the next free block.
+    if (size < 1) size = 1;
+    for (cell **prev = &free_blocks; *prev; prev = &(*prev)[1].p) {
+        cell *block = *prev;
+        if (block->i >= size) {
+            *prev = block[1].p;
+            heap_used += block->i;
+            return block + 1;
+        }
+    }
+    if (size + 1 > heap + SAGE_HEAP_CELLS - heap_top) return NULL;
+    cell *block = heap_top;
+    heap_top += size + 1;
+    block->i = size;
+    heap_used += size;
+    return block + 1;
+}
+
+void heap_free(cell *p) {
+    heap_used -= p[-1].i;
+    p->p = free_blocks;
+    free_blocks = p - 1;
+}
+
+cell *heap_realloc(cell *p, int64_t size) {
+    if (p[-1].i >= size) return p;
+    cell *block = heap_alloc(size);
+    if (block) {
+        memcpy(block, p, p[-1].i * sizeof(cell));
+        heap_free(p);
+    }
+    return block;
+}
+
+// The number of cells which can still be allocated, in the free blocks and the rest of the arena.
+int64_t heap_available() {
+    int64_t available = heap + SAGE_HEAP_CELLS - heap_top;
+    for (cell *block = free_blocks; block; block = block[1].p) available += block->i;
+    return available;
+}
+"# + INT_DIVISION;
+
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let mut result = String::from("\nvoid sage_main() {\n");
+        for (fun, label) in funs {
+            result += &format!(
+                "\tfuns[{fun}] = {};\n",
+                function_name(fun as usize, label.as_deref())
+            )
+        }
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some("return;\n}".to_string())
+    }
+}
+
+impl CompiledTarget for FreestandingC {}
+
+impl Target for FreestandingC {
+    fn extension(&self) -> &str {
+        "c"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -0,0 +1,501 @@
+//! # JavaScript Target
+//!
+//! An implementation of the virtual machine for JavaScript, which emits a self-contained
+//! ES module (`.mjs`), for embedding compiled programs in web pages, tools, and demos:
+//!
+//! ```js
+//! import { run, BufferDevice } from "./out.mjs";
+//!
+//! const device = new BufferDevice("some input");
+//! const status = run(device);
+//! console.log(device.output);
+//! ```
+//!
+//! The tape is a `BigInt64Array`, with a `Float64Array` view of the same memory for floats,
+//! so cells are 64 bits wide like on the other targets. Pointers are indices into the tape,
+//! and the heap is a static arena after the tape, with a first fit free list (like in the
+//! freestanding C target). `run` takes the sizes of the tape and the heap as options (the
+//! tape defaults to the size in the target's [`TargetConfig`]), and the tape must be larger
+//! than the call stack size the program was assembled with. With bounds checking, moving the
+//! tape pointer off the tape (outside of a `Deref`) writes an error to the device's standard
+//! error, and exits with status 1.
+//!
+//! All I/O goes through the device passed to `run`, which is any object with these methods:
+//!
+//! ```js
+//! get(mode, channel)        // Input, as an integer (or a float, for the "stdin.float" mode).
+//! put(value, mode, channel) // Output a BigInt (or a number, for the float modes).
+//! peek()                    // Take the next BigInt from the FFI channel.
+//! poke(value)               // Add a BigInt to the FFI channel.
+//! ffiCall(name, tape)       // Call a foreign function, which may use the tape.
+//! ```
+//!
+//! The modes are named like `stdout.char` or `dpad.up`, and a device can throw for the modes it
+//! doesn't support. The included `BufferDevice` reads input from a string, and collects the
+//! standard output and error in the strings `output` and `error`. Since the device does
+//! all of the I/O, the configuration's I/O hooks are unused.
+use super::{
+    c::function_name, Architecture, CompiledTarget, IoHooks, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp},
+};
+
+/// The type for the JavaScript target which implements the `Target` trait.
+/// This allows the compiler to target web browsers and Node.js.
+pub struct JavaScript {
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for JavaScript {
+    fn default() -> Self {
+        Self {
+            config: TargetConfig::new(16777216, 64, IoHooks::libc()),
+        }
+    }
+}
+
+/// Apply an instruction to the register and the cell under the tape pointer, or to each of
+/// the first `n` cells of the vector register and the tape. The body uses `reg[i]`, `regf[i]`,
+/// `mem[ptr + i]`, and `memf[ptr + i]`.
+fn each(n: usize, body: &str) -> String {
+    if n == 1 {
+        body.replace("ptr + i", "ptr").replace("[i]", "[0]")
+    } else {
+        format!("for (let i = 0; i < {n}; i++) {body}")
+    }
+}
+
+/// A JavaScript literal for a float.
+fn float_literal(val: f64) -> String {
+    if val.is_nan() {
+        "NaN".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else {
+        format!("{val:?}")
+    }
+}
+
+/// Is the value read by an input mode a float?
+fn is_float_input(mode: &InputMode) -> bool {
+    matches!(mode, InputMode::StdinFloat)
+}
+
+/// Is the value written by an output mode a float?
+fn is_float_output(mode: &OutputMode) -> bool {
+    matches!(
+        mode,
+        OutputMode::StdoutFloat | OutputMode::StderrFloat | OutputMode::PrinterFloat
+    )
+}
+
+impl Architecture for JavaScript {
+    /// Every input and output is passed to the device, which decides what it supports.
+    fn supports_input(&self, _i: &Input) -> bool {
+        true
+    }
+
+    fn supports_output(&self, _o: &Output) -> bool {
+        true
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => {
+                format!("// {}", text.replace('\n', "\n// ").replace('\r', ""))
+            }
+            CoreOp::While => "while (reg[0]) {".to_string(),
+            CoreOp::If => "if (reg[0]) {".to_string(),
+            CoreOp::Else => "} else {".to_string(),
+            CoreOp::Set(n) => n
+                .iter()
+                .enumerate()
+                .map(|(i, val)| format!("reg[{i}] = {val}n;"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            CoreOp::Call => "funs[Number(reg[0])]();".to_string(),
+            CoreOp::Return => "return;".to_string(),
+            CoreOp::Store(1) => "mem[ptr] = reg[0];".to_string(),
+            CoreOp::Load(1) => "reg[0] = mem[ptr];".to_string(),
+            CoreOp::Store(n) => format!("mem.set(reg.subarray(0, {n}), ptr);"),
+            CoreOp::Load(n) => format!("reg.set(mem.subarray(ptr, ptr + {n}));"),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "ptr += {n}; if (!refs.length && (ptr < 1 || ptr >= heapStart)) tapeOutOfBounds();"
+            ),
+            CoreOp::Move(n) => format!("ptr += {n};"),
+            CoreOp::Where => "reg[0] = BigInt(ptr);".to_string(),
+            CoreOp::Deref => "refs.push(ptr); ptr = Number(mem[ptr]);".to_string(),
+            CoreOp::Refer => "ptr = refs.pop();".to_string(),
+            CoreOp::Offset(n, size) => each(*size, &format!("reg[i] += {n}n;")),
+            CoreOp::Index(n) => each(*n, "reg[i] += mem[ptr + i];"),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            // Storing to the typed arrays wraps the results to 64 bits.
+            CoreOp::Add(n) => each(*n, "reg[i] += mem[ptr + i];"),
+            CoreOp::Sub(n) => each(*n, "reg[i] -= mem[ptr + i];"),
+            CoreOp::Mul(n) => each(*n, "reg[i] *= mem[ptr + i];"),
+            // BigInts throw on division by zero, so zero divisors are skipped.
+            CoreOp::Div(n) => each(*n, "if (mem[ptr + i] !== 0n) reg[i] /= mem[ptr + i];"),
+            CoreOp::Rem(n) => each(*n, "if (mem[ptr + i] !== 0n) reg[i] %= mem[ptr + i];"),
+            CoreOp::Neg(n) => each(*n, "reg[i] = -reg[i];"),
+            CoreOp::Inc(n) => each(*n, "reg[i]++;"),
+            CoreOp::Dec(n) => each(*n, "reg[i]--;"),
+            CoreOp::Swap(n) => each(
+                *n,
+                "{ const tmp = reg[i]; reg[i] = mem[ptr + i]; mem[ptr + i] = tmp; }",
+            ),
+
+            CoreOp::And(n) => each(*n, "reg[i] = reg[i] && mem[ptr + i] ? 1n : 0n;"),
+            CoreOp::Or(n) => each(*n, "reg[i] = reg[i] || mem[ptr + i] ? 1n : 0n;"),
+            CoreOp::Not(n) => each(*n, "reg[i] = reg[i] ? 0n : 1n;"),
+
+            CoreOp::BitwiseNand(n) => each(*n, "reg[i] = ~(reg[i] & mem[ptr + i]);"),
+            CoreOp::BitwiseAnd(n) => each(*n, "reg[i] &= mem[ptr + i];"),
+            CoreOp::BitwiseOr(n) => each(*n, "reg[i] |= mem[ptr + i];"),
+            CoreOp::BitwiseXor(n) => each(*n, "reg[i] ^= mem[ptr + i];"),
+            CoreOp::BitwiseNot(n) => each(*n, "reg[i] = ~reg[i];"),
+
+            // Shifts use the low six bits of the amount, like 64 bit machines do.
+            CoreOp::LeftShift(n) => each(*n, "reg[i] <<= mem[ptr + i] & 63n;"),
+            CoreOp::LogicalRightShift(n) => each(
+                *n,
+                "reg[i] = BigInt.asUintN(64, reg[i]) >> (mem[ptr + i] & 63n);",
+            ),
+            CoreOp::ArithmeticRightShift(n) => each(*n, "reg[i] >>= mem[ptr + i] & 63n;"),
+
+            CoreOp::IsNonNegative(n) => each(*n, "reg[i] = reg[i] >= 0n ? 1n : 0n;"),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for JavaScript target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => format!("device.ffiCall({:?}, mem);", ffi.name),
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => n
+                .iter()
+                .enumerate()
+                .map(|(i, val)| format!("regf[{i}] = {};", float_literal(*val)))
+                .collect::<Vec<_>>()
+                .join(" "),
+
+            StandardOp::ToInt(n) => each(*n, "reg[i] = toInt(regf[i]);"),
+            StandardOp::ToFloat(n) => each(*n, "regf[i] = Number(reg[i]);"),
+
+            StandardOp::ACos(n) => each(*n, "regf[i] = Math.acos(regf[i]);"),
+            StandardOp::ASin(n) => each(*n, "regf[i] = Math.asin(regf[i]);"),
+            StandardOp::ATan(n) => each(*n, "regf[i] = Math.atan(regf[i]);"),
+            StandardOp::Sin(n) => each(*n, "regf[i] = Math.sin(regf[i]);"),
+            StandardOp::Cos(n) => each(*n, "regf[i] = Math.cos(regf[i]);"),
+            StandardOp::Tan(n) => each(*n, "regf[i] = Math.tan(regf[i]);"),
+
+            StandardOp::Add(n) => each(*n, "regf[i] += memf[ptr + i];"),
+            StandardOp::Sub(n) => each(*n, "regf[i] -= memf[ptr + i];"),
+            StandardOp::Mul(n) => each(*n, "regf[i] *= memf[ptr + i];"),
+            StandardOp::Div(n) => each(*n, "regf[i] /= memf[ptr + i];"),
+            StandardOp::Rem(n) => each(*n, "regf[i] %= memf[ptr + i];"),
+            StandardOp::Pow(n) => each(*n, "regf[i] **= memf[ptr + i];"),
+            StandardOp::IsNonNegative(n) => each(*n, "reg[i] = regf[i] >= 0 ? 1n : 0n;"),
+
+            StandardOp::Alloc => "reg[0] = BigInt(heapAlloc(Number(reg[0])));".to_string(),
+            StandardOp::Free => "heapFree(Number(reg[0]));".to_string(),
+            StandardOp::Realloc => {
+                "reg[0] = BigInt(heapRealloc(Number(reg[0]), Number(mem[ptr])));".to_string()
+            }
+            StandardOp::HeapUsed => "reg[0] = BigInt(heapUsed);".to_string(),
+            StandardOp::HeapFree => "reg[0] = BigInt(heapAvailable());".to_string(),
+
+            // JavaScript runs the program on one thread, so the atomic instructions are plain.
+            StandardOp::AtomicLoad => "reg[0] = mem[Number(reg[0])];".to_string(),
+            StandardOp::AtomicStore => "mem[Number(reg[0])] = mem[ptr];".to_string(),
+            StandardOp::AtomicAdd => {
+                "{ const p = Number(reg[0]), old = mem[p]; mem[p] += mem[ptr]; reg[0] = old; }"
+                    .to_string()
+            }
+            // Store the value found back over the expected value, like `__atomic_compare_exchange_n`.
+            StandardOp::AtomicCompareSwap => "{ const p = Number(reg[0]); if (mem[p] === mem[ptr]) { mem[p] = mem[ptr + 1]; reg[0] = 1n; } else { mem[ptr] = mem[p]; reg[0] = 0n; } }".to_string(),
+            _ => return Err(format!("Invalid standard op for JavaScript target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function | CoreOp::While | CoreOp::If | CoreOp::Else, _) => "}".to_string(),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        format!("\nfunction {}() {{", function_name(label_id, label))
+    }
+
+    fn name(&self) -> &str {
+        "JavaScript"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        let mode = format!("{:?}", src.mode.to_string());
+        Ok(if is_float_input(&src.mode) {
+            format!("regf[0] = device.get({mode}, {});", src.channel.0)
+        } else {
+            format!("reg[0] = BigInt(device.get({mode}, {}));", src.channel.0)
+        })
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let mode = format!("{:?}", dst.mode.to_string());
+        Ok(if dst.mode == OutputMode::Exit {
+            "throw new SageExit(Number(reg[0]));".to_string()
+        } else if is_float_output(&dst.mode) {
+            format!("device.put(regf[0], {mode}, {});", dst.channel.0)
+        } else {
+            format!("device.put(reg[0], {mode}, {});", dst.channel.0)
+        })
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok("reg[0] = device.peek();".to_string())
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok("device.poke(reg[0]);".to_string())
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn indentation(&self) -> Option<String> {
+        Some("    ".to_string())
+    }
+
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        Some(
+            r#"// The state of the machine, which `run` resets.
+// Pointers are indices into `mem`, which starts with an unused cell, so that 0 is null.
+let mem, memf, ptr, refs, device;
+const reg = new BigInt64Array(1024), regf = new Float64Array(reg.buffer);
+let heapStart, heapEnd, heapTop, freeBlocks, heapUsed;
+
+/** Thrown to halt the program with an exit status. */
+class SageExit {
+    constructor(status) {
+        this.status = status;
+    }
+}
+
+// Convert a float to an integer, saturating like Rust's `as`.
+function toInt(x) {
+    if (Number.isNaN(x)) return 0n;
+    if (x >= 2 ** 63) return 9223372036854775807n;
+    if (x <= -(2 ** 63)) return -9223372036854775808n;
+    return BigInt(Math.trunc(x));
+}
+
+// Format a float like the interpreter does.
+function formatFloat(x) {
+    if (Number.isNaN(x)) return "NaN";
+    if (!Number.isFinite(x)) return x > 0 ? "inf" : "-inf";
+    return Number.isInteger(x) && Math.abs(x) < 1e16 ? x.toFixed(1) : String(x);
+}
+
+// The heap is a static arena after the tape. Every block is preceded by a cell holding
+// its size, and freed blocks are linked through their first cell, to be reused first fit.
+function heapAlloc(size) {
+    // Every block needs room for the link to the next free block.
+    if (size < 1) size = 1;
+    for (let prev = 0, block = freeBlocks; block; prev = block, block = Number(mem[block + 1])) {
+        if (Number(mem[block]) >= size) {
+            if (prev) mem[prev + 1] = mem[block + 1];
+            else freeBlocks = Number(mem[block + 1]);
+            heapUsed += Number(mem[block]);
+            return block + 1;
+        }
+    }
+    if (size + 1 > heapEnd - heapTop) return 0;
+    const block = heapTop;
+    heapTop += size + 1;
+    mem[block] = BigInt(size);
+    heapUsed += size;
+    return block + 1;
+}
+
+function heapFree(p) {
+    heapUsed -= Number(mem[p - 1]);
+    mem[p] = BigInt(freeBlocks);
+    freeBlocks = p - 1;
+}
+
+function heapRealloc(p, size) {
+    if (Number(mem[p - 1]) >= size) return p;
+    const block = heapAlloc(size);
+    if (block) {
+        mem.copyWithin(block, p, p + Number(mem[p - 1]));
+        heapFree(p);
+    }
+    return block;
+}
+
+// The number of cells which can still be allocated, in the free blocks and the rest of the arena.
+function heapAvailable() {
+    let available = heapEnd - heapTop;
+    for (let block = freeBlocks; block; block = Number(mem[block + 1])) available += Number(mem[block]);
+    return available;
+}
+
+/**
+ * A device which reads input from a string, and collects the standard output and error
+ * in the strings `output` and `error`. Foreign functions can be added to `ffi` by name,
+ * and are called with the FFI channel (an array of BigInts) and the tape.
+ */
+export class BufferDevice {
+    constructor(input = "") {
+        this.input = input;
+        this.position = 0;
+        this.output = "";
+        this.error = "";
+        this.channel = [];
+        this.ffi = {};
+    }
+
+    // Read the next match of a sticky pattern from the input, skipping whitespace before it.
+    read(pattern) {
+        pattern.lastIndex = this.position;
+        const match = pattern.exec(this.input);
+        if (!match) return null;
+        this.position = pattern.lastIndex;
+        return match[1];
+    }
+
+    get(mode, channel) {
+        switch (mode) {
+            case "stdin.char":
+                return this.position < this.input.length ? this.input.charCodeAt(this.position++) : 0;
+            case "stdin.int":
+                return BigInt(this.read(/\s*([-+]?\d+)/y) ?? 0);
+            case "stdin.float":
+                return parseFloat(this.read(/\s*([-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?)/y) ?? 0);
+            case "random":
+                return Math.floor(Math.random() * 2 ** 31);
+            default:
+                throw new Error(`Input ${mode} #${channel} not supported by this device`);
+        }
+    }
+
+    put(value, mode, channel) {
+        switch (mode) {
+            case "stdout.char": this.output += String.fromCharCode(Number(value)); break;
+            case "stdout.int": this.output += value.toString(); break;
+            case "stdout.float": this.output += formatFloat(value); break;
+            case "stderr.char": this.error += String.fromCharCode(Number(value)); break;
+            case "stderr.int": this.error += value.toString(); break;
+            case "stderr.float": this.error += formatFloat(value); break;
+            default:
+                throw new Error(`Output ${mode} #${channel} not supported by this device`);
+        }
+    }
+
+    peek() {
+        if (!this.channel.length) throw new Error("Tried to peek from empty FFI channel");
+        return this.channel.shift();
+    }
+
+    poke(value) {
+        this.channel.push(value);
+    }
+
+    ffiCall(name, tape) {
+        if (!(name in this.ffi)) throw new Error(`Foreign function ${name} not found`);
+        this.ffi[name](this.channel, tape);
+    }
+}
+"#
+            .to_string()
+                + &format!(
+                    r#"
+// Report the tape pointer moving off the tape on the standard error, and exit.
+function tapeOutOfBounds() {{
+    for (const ch of "{TAPE_BOUNDS_ERROR}\n") device.put(BigInt(ch.charCodeAt(0)), "stderr.char", 0);
+    throw new SageExit(1);
+}}
+"#
+                ),
+        )
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let names: Vec<String> = funs
+            .into_iter()
+            .map(|(fun, label)| function_name(fun as usize, label.as_deref()))
+            .collect();
+        Some(format!(
+            "\nconst funs = [{}];\n\nfunction sage_main() {{\n",
+            names.join(", ")
+        ))
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some(format!(
+            r#"return;
+}}
+
+/**
+ * Run the program with the given device, and return its exit status.
+ * The tape and the heap are arrays of `tapeCells` and `heapCells` cells.
+ */
+export function run(target = new BufferDevice(), {{ tapeCells = {}, heapCells = 1048576 }} = {{}}) {{
+    device = target;
+    const buffer = new ArrayBuffer((1 + tapeCells + heapCells) * 8);
+    mem = new BigInt64Array(buffer);
+    memf = new Float64Array(buffer);
+    ptr = 1;
+    refs = [];
+    reg.fill(0n);
+    heapStart = heapTop = 1 + tapeCells;
+    heapEnd = heapStart + heapCells;
+    freeBlocks = 0;
+    heapUsed = 0;
+    try {{
+        sage_main();
+    }} catch (e) {{
+        if (e instanceof SageExit) return e.status;
+        throw e;
+    }}
+    return 0;
+}}
+"#,
+            self.config.tape_cells
+        ))
+    }
+}
+
+impl CompiledTarget for JavaScript {}
+
+impl Target for JavaScript {
+    fn extension(&self) -> &str {
+        "mjs"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -6,10 +6,25 @@
 //! ## Current Structure
 //!
 //! Right now, this module is a bit empty, only implementing C (GCC only)
-//! as a compiler target. This is due to the fact that it has been much
+//! as a compiler target, either hosted or freestanding (without libc), x86-64
+//! and RISC-V assembly for Linux, Thumb assembly for bare-metal Cortex-M
+//! microcontrollers, 6502 assembly for 8-bit computers, and JavaScript and
+//! Python modules. This is due to the fact that it has been much
 //! simpler to build the language on top of the virtual machine when there
 //! are fewer implementations to change.
 //!
+//! ## Adding Targets
+//!
+//! Each backend implements the [`Architecture`] trait to lower every instruction,
+//! and the [`Target`] trait to describe its capabilities and the file it emits.
+//! Backends are looked up by name in a registry, so a crate using Sage as a
+//! library can add its own target with [`register_target`] without changing
+//! this module.
+//!
+//! Each target's runtime (the size of the tape, whether it's bounds checked,
+//! and the functions it does I/O with) is described by a [`TargetConfig`],
+//! instead of being baked into the code the target emits.
+//!
 //! ## Future Structure
 //!
 //! In the future, this module will be *much* more featured.
@@ -38,18 +53,35 @@
 
 pub mod c;
 pub use c::*;
+pub mod config;
+pub use config::*;
+pub mod freestanding_c;
+pub use freestanding_c::*;
 pub mod sage_lisp;
 pub use sage_lisp::*;
 
 // pub mod sage_os;
 // pub use sage_os::*;
 
-// pub mod x86;
-// pub use x86::*;
-
+pub mod cortex_m;
+pub use cortex_m::*;
+pub mod js;
+pub use js::*;
+pub mod mos6502;
+pub use mos6502::*;
+pub mod python;
+pub use python::*;
+pub mod riscv64;
+pub use riscv64::*;
+pub mod x86_64;
+pub use x86_64::*;
+
+use lazy_static::lazy_static;
 use log::info;
+use std::{collections::HashMap, sync::RwLock};
 
 use crate::{
+    asm::TargetFeatures,
     side_effects::{Input, Output},
     vm::{self, *},
 };
@@ -92,13 +124,15 @@ pub trait Architecture {
         None
     }
 
-    /// The code before the function definitions.
-    fn pre_funs(&self, _funs: Vec<i32>) -> Option<String> {
+    /// The code before the function definitions, given the ID of each function
+    /// and the label it was assembled from (if known).
+    fn pre_funs(&self, _funs: Vec<(i32, Option<String>)>) -> Option<String> {
         None
     }
 
-    /// The code after the function definitions.
-    fn post_funs(&self, _funs: Vec<i32>) -> Option<String> {
+    /// The code after the function definitions, given the ID of each function
+    /// and the label it was assembled from (if known).
+    fn post_funs(&self, _funs: Vec<(i32, Option<String>)>) -> Option<String> {
         None
     }
 
@@ -107,8 +141,8 @@ pub trait Architecture {
         Some("\t".to_string())
     }
 
-    /// Compile the declaration of a procedure.
-    fn declare_proc(&mut self, label_id: usize) -> String;
+    /// Compile the declaration of a procedure, given the label it was assembled from (if known).
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String;
     /// Compile an `End` instruction (with the matching `If` or `While` or `Function`)
     fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String;
     /// Compile a `CoreOp` instruction.
@@ -126,13 +160,15 @@ pub trait CompiledTarget: Architecture {
         matching_funs: &mut Vec<usize>,
         current_fun: &mut usize,
         indent: &mut usize,
+        labels: &HashMap<i32, String>,
     ) -> Result<String, String> {
         Ok(match op {
             CoreOp::Function => {
                 matching_ops.push(op.clone());
 
                 matching_funs.push(*current_fun);
-                let fun_header = self.declare_proc(*current_fun);
+                let label = labels.get(&(*current_fun as i32)).map(String::as_str);
+                let fun_header = self.declare_proc(*current_fun, label);
                 *current_fun += 1;
 
                 *indent += 1;
@@ -199,10 +235,11 @@ pub trait CompiledTarget: Architecture {
         matching_funs: &mut Vec<usize>,
         current_fun: &mut usize,
         indent: &mut usize,
+        labels: &HashMap<i32, String>,
     ) -> Result<String, String> {
         match std_op {
             StandardOp::CoreOp(op) => {
-                self.build_op(op, matching_ops, matching_funs, current_fun, indent)
+                self.build_op(op, matching_ops, matching_funs, current_fun, indent, labels)
             }
             other => self.std_op(other),
         }
@@ -212,6 +249,7 @@ pub trait CompiledTarget: Architecture {
     fn build_core(&mut self, program: &vm::CoreProgram) -> Result<String, String> {
         info!("Compiling core program for target {}", self.name());
         let (main_ops, function_defs) = program.clone().get_main_and_functions();
+        let labels = function_labels(&function_defs, |op| Some(op));
         let mut result = self.prelude(true).unwrap_or("".to_string());
 
         let mut matching_ops = vec![];
@@ -223,35 +261,39 @@ pub trait CompiledTarget: Architecture {
         let mut indent = 0;
 
         result += &self
-            .pre_funs(function_defs.keys().cloned().collect())
+            .pre_funs(function_ids(&function_defs, &labels))
             .unwrap_or("".to_string());
         for i in 0..function_defs.len() as i32 {
             let f = &function_defs[&i];
             for op in f {
-                result += &tab.repeat(indent);
-                result += &self.build_op(
+                let before = indent;
+                let code = self.build_op(
                     op,
                     &mut matching_ops,
                     &mut matching_funs,
                     &mut current_fun,
                     &mut indent,
+                    &labels,
                 )?;
+                result += &indent_code(&code, &tab, line_indent(op, before, indent));
                 result += &self.postop().unwrap_or("".to_string());
             }
         }
         result += &self
-            .post_funs(function_defs.keys().cloned().collect())
+            .post_funs(function_ids(&function_defs, &labels))
             .unwrap_or("".to_string());
         indent = 1;
         for op in main_ops {
-            result += &tab.repeat(indent);
-            result += &self.build_op(
+            let before = indent;
+            let code = self.build_op(
                 &op,
                 &mut matching_ops,
                 &mut matching_funs,
                 &mut current_fun,
                 &mut indent,
+                &labels,
             )?;
+            result += &indent_code(&code, &tab, line_indent(&op, before, indent));
             result += &self.postop().unwrap_or("".to_string());
         }
 
@@ -263,6 +305,10 @@ pub trait CompiledTarget: Architecture {
     fn build_std(&mut self, program: &vm::StandardProgram) -> Result<String, String> {
         info!("Compiling standard program for target {}", self.name());
         let (main_ops, function_defs) = program.clone().get_main_and_functions();
+        let labels = function_labels(&function_defs, |op| match op {
+            StandardOp::CoreOp(op) => Some(op),
+            _ => None,
+        });
         let mut result = self.prelude(false).unwrap_or("".to_string());
 
         let mut matching_ops = vec![];
@@ -273,35 +319,39 @@ pub trait CompiledTarget: Architecture {
 
         let mut indent = 0;
         result += &self
-            .pre_funs(function_defs.keys().cloned().collect())
+            .pre_funs(function_ids(&function_defs, &labels))
             .unwrap_or("".to_string());
         for i in 0..function_defs.len() as i32 {
             let f = &function_defs[&i];
             for op in f {
-                result += &tab.repeat(indent);
-                result += &self.build_std_op(
+                let before = indent;
+                let code = self.build_std_op(
                     op,
                     &mut matching_ops,
                     &mut matching_funs,
                     &mut current_fun,
                     &mut indent,
+                    &labels,
                 )?;
+                result += &indent_code(&code, &tab, line_indent_std(op, before, indent));
                 result += &self.postop().unwrap_or("".to_string());
             }
         }
         result += &self
-            .post_funs(function_defs.keys().cloned().collect())
+            .post_funs(function_ids(&function_defs, &labels))
             .unwrap_or("".to_string());
         indent = 1;
         for op in main_ops {
-            result += &tab.repeat(indent);
-            result += &self.build_std_op(
+            let before = indent;
+            let code = self.build_std_op(
                 &op,
                 &mut matching_ops,
                 &mut matching_funs,
                 &mut current_fun,
                 &mut indent,
+                &labels,
             )?;
+            result += &indent_code(&code, &tab, line_indent_std(&op, before, indent));
             result += &self.postop().unwrap_or("".to_string());
         }
 
@@ -312,3 +362,292 @@ pub trait CompiledTarget: Architecture {
         Ok(result + &tab + self.postlude(false).unwrap_or("".to_string()).as_str())
     }
 }
+
+/// The features a target provides, so that a driver can check a program
+/// against a target before building it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// The standard instructions the target supports.
+    pub features: TargetFeatures,
+    /// The width of a cell on the target in bits.
+    pub cell_width: usize,
+}
+
+/// A complete compiler backend: the instruction lowering hooks and runtime
+/// prelude/postlude of its `Architecture`, along with the file it emits.
+///
+/// Every target in this crate implements this trait, and other crates can
+/// implement it for their own targets and add them with [`register_target`].
+pub trait Target: CompiledTarget {
+    /// The extension of the file the target emits (without the leading dot).
+    fn extension(&self) -> &str;
+
+    /// The configuration of the runtime the target emits.
+    fn config(&self) -> &TargetConfig;
+
+    /// The configuration of the runtime the target emits, to change it
+    /// without any checks. Prefer [`Target::configure`].
+    fn config_mut(&mut self) -> &mut TargetConfig;
+
+    /// Replace the configuration of the runtime the target emits,
+    /// if the target can implement it.
+    fn configure(&mut self, config: TargetConfig) -> Result<(), String> {
+        if config.cell_width != self.cell_width() {
+            return Err(format!(
+                "Target {} only supports {}-bit cells, not {}-bit cells",
+                self.name(),
+                self.cell_width(),
+                config.cell_width
+            ));
+        }
+        if config.tape_cells == 0 {
+            return Err(format!("The tape on target {} can't be empty", self.name()));
+        }
+        *self.config_mut() = config;
+        Ok(())
+    }
+
+    /// The width of a cell on the target in bits.
+    fn cell_width(&self) -> usize {
+        self.config().cell_width
+    }
+
+    /// Whether or not the target can build standard programs.
+    fn supports_std(&self) -> bool {
+        true
+    }
+
+    /// The standard instructions the target supports. The compiler checks the
+    /// program against these, so unsupported code is reported where it's used.
+    fn features(&self) -> TargetFeatures {
+        let std = self.supports_std();
+        TargetFeatures {
+            name: self.name().to_string(),
+            floats: std && self.supports_floats(),
+            alloc: std,
+            atomics: std,
+            ffi: std,
+        }
+    }
+
+    /// The features the target provides.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            features: self.features(),
+            cell_width: self.cell_width(),
+        }
+    }
+
+    /// Build a program of either variant.
+    fn build(
+        &mut self,
+        program: &Result<vm::CoreProgram, vm::StandardProgram>,
+    ) -> Result<String, String> {
+        match program {
+            Ok(core) => self.build_core(core),
+            Err(std) if self.supports_std() => self.build_std(std),
+            Err(_) => Err(format!(
+                "Standard programs are not supported on target {}",
+                self.name()
+            )),
+        }
+    }
+
+    /// Write the built code to the output path (without an extension),
+    /// returning the names of the files written.
+    fn emit(&self, output: &str, code: &str) -> Result<Vec<String>, String> {
+        let file = format!("{output}.{}", self.extension());
+        std::fs::write(&file, code).map_err(|e| format!("Could not write {file}: {e}"))?;
+        Ok(vec![file])
+    }
+}
+
+/// A function which creates a fresh instance of a target.
+pub type TargetConstructor = fn() -> Box<dyn Target>;
+
+/// A target added to the registry, under the name it is selected by.
+#[derive(Clone)]
+pub struct RegisteredTarget {
+    /// The name the target is selected by, like `x86-64`.
+    pub name: String,
+    /// A short description of the target.
+    pub description: String,
+    /// Creates an instance of the target.
+    pub constructor: TargetConstructor,
+}
+
+lazy_static! {
+    static ref TARGETS: RwLock<Vec<RegisteredTarget>> = RwLock::new(builtin_targets());
+}
+
+/// The targets implemented in this crate.
+fn builtin_targets() -> Vec<RegisteredTarget> {
+    let targets: [(&str, &str, TargetConstructor); 9] = [
+        ("c", "C source code (GCC only)", || Box::new(C::default())),
+        (
+            "freestanding-c",
+            "Freestanding C source code, which doesn't use libc (GCC only)",
+            || Box::new(FreestandingC::default()),
+        ),
+        (
+            "x86-64",
+            "x86-64 assembly code for Linux (GNU assembler)",
+            || Box::new(X86_64::default()),
+        ),
+        (
+            "riscv64",
+            "64-bit RISC-V assembly code for Linux (GNU assembler)",
+            || Box::new(RiscV64::default()),
+        ),
+        (
+            "cortex-m",
+            "Thumb assembly code for bare-metal ARM Cortex-M microcontrollers (GNU assembler)",
+            || Box::new(CortexM::default()),
+        ),
+        (
+            "6502",
+            "6502 assembly code for 8-bit computers (ca65 assembler)",
+            || Box::new(Mos6502::default()),
+        ),
+        (
+            "js",
+            "A JavaScript module, for Node.js and web browsers",
+            || Box::new(JavaScript::default()),
+        ),
+        ("python", "A Python module, which runs as a script", || {
+            Box::new(Python::default())
+        }),
+        (
+            "sage-lisp",
+            "The Sage-Lisp backend provided by the user",
+            || Box::new(SageLisp::new(crate::frontend::get_lisp_env())),
+        ),
+    ];
+    targets
+        .into_iter()
+        .map(|(name, description, constructor)| RegisteredTarget {
+            name: name.to_string(),
+            description: description.to_string(),
+            constructor,
+        })
+        .collect()
+}
+
+/// Add a target to the registry under the given name, replacing any target
+/// already registered with that name.
+pub fn register_target(name: &str, description: &str, constructor: TargetConstructor) {
+    let mut targets = TARGETS.write().unwrap();
+    let target = RegisteredTarget {
+        name: name.to_string(),
+        description: description.to_string(),
+        constructor,
+    };
+    match targets.iter_mut().find(|t| t.name == name) {
+        Some(existing) => *existing = target,
+        None => targets.push(target),
+    }
+}
+
+/// Create an instance of the target registered with the given name.
+pub fn get_target(name: &str) -> Option<Box<dyn Target>> {
+    TARGETS
+        .read()
+        .unwrap()
+        .iter()
+        .find(|t| t.name == name)
+        .map(|t| (t.constructor)())
+}
+
+/// All of the registered targets, in the order they were registered.
+pub fn registered_targets() -> Vec<RegisteredTarget> {
+    TARGETS.read().unwrap().clone()
+}
+
+/// The source files named by the line comments in a program, for the targets which
+/// emit GNU assembler debug line directives (`.file` and `.loc`).
+#[derive(Default)]
+pub(crate) struct LineDirectives(Vec<String>);
+
+impl LineDirectives {
+    /// Compile a line comment's line and (escaped) filename to debug line directives.
+    pub(crate) fn directive(&mut self, line: usize, filename: Option<&str>) -> String {
+        let mut result = String::new();
+        let file = match filename {
+            Some(filename) => match self.0.iter().position(|f| f == filename) {
+                Some(i) => i + 1,
+                None => {
+                    self.0.push(filename.to_string());
+                    result += &format!(".file {} \"{filename}\"\n", self.0.len());
+                    self.0.len()
+                }
+            },
+            // Without a filename, the line is in the last file.
+            None if !self.0.is_empty() => self.0.len(),
+            None => return format!("# line {line}"),
+        };
+        result + &format!(".loc {file} {line}")
+    }
+}
+
+/// Find the label each function was assembled from, by the comment that begins its body.
+fn function_labels<T>(
+    function_defs: &HashMap<i32, Vec<T>>,
+    core_op: impl Fn(&T) -> Option<&CoreOp>,
+) -> HashMap<i32, String> {
+    let mut labels = HashMap::new();
+    for (id, f) in function_defs {
+        let label = f.iter().find_map(|op| match core_op(op) {
+            Some(CoreOp::Comment(comment)) => vm::comment_label(comment),
+            _ => None,
+        });
+        if let Some(label) = label {
+            labels.insert(*id, label.to_string());
+        }
+    }
+    labels
+}
+
+/// The IDs of the functions in order, with the labels they were assembled from.
+fn function_ids<T>(
+    function_defs: &HashMap<i32, Vec<T>>,
+    labels: &HashMap<i32, String>,
+) -> Vec<(i32, Option<String>)> {
+    let mut ids: Vec<i32> = function_defs.keys().cloned().collect();
+    ids.sort();
+    ids.into_iter()
+        .map(|id| (id, labels.get(&id).cloned()))
+        .collect()
+}
+
+/// The indentation of the line an instruction is compiled to, given the indentation
+/// before and after it: the end of a block lines up with its beginning.
+fn line_indent(op: &CoreOp, before: usize, after: usize) -> usize {
+    match op {
+        CoreOp::End => after,
+        CoreOp::Else => before.saturating_sub(1),
+        _ => before,
+    }
+}
+
+/// The indentation of the line a standard instruction is compiled to.
+fn line_indent_std(op: &StandardOp, before: usize, after: usize) -> usize {
+    match op {
+        StandardOp::CoreOp(op) => line_indent(op, before, after),
+        _ => before,
+    }
+}
+
+/// Indent every line of the code an instruction is compiled to.
+fn indent_code(code: &str, tab: &str, indent: usize) -> String {
+    let indentation = tab.repeat(indent);
+    code.split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                format!("{indentation}{line}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
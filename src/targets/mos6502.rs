@@ -0,0 +1,1120 @@
+//! # 6502 Target
+//!
+//! An implementation of the virtual machine for the MOS 6502, the 8-bit processor in the
+//! Apple II, the Commodore 64, and the NES. It emits assembly for `ca65`, the assembler of
+//! the cc65 toolchain, so the output can be linked for any of the machines cc65 supports:
+//!
+//! ```bash
+//! $ cl65 -t sim6502 -D SAGE_TAPE_CELLS=2048 output.s hooks.c -o output
+//! $ sim65 output
+//! ```
+//!
+//! Cells are 16 bits wide (so the `cell_width` compile parameter is 16), and there is no
+//! floating point or heap, so only core programs can be compiled. The call stack size must
+//! be given with `-c`, and be much smaller than the tape, which is a static array of
+//! `SAGE_TAPE_CELLS` cells (4096 by default, or the size in the target's [`TargetConfig`]).
+//! With bounds checking, moving the tape pointer off the tape (outside of a `Deref`) writes
+//! an error with the error hook, and calls `sage_exit(1)`.
+//!
+//! The memory map is the one of the linker configuration: the registers live in the
+//! `ZEROPAGE` segment, the tape and the vector register in `BSS`, and the program in `CODE`
+//! and `RODATA`. The machine's state is cleared when the program starts, so it doesn't depend
+//! on the startup code clearing `BSS`.
+//!
+//! The program is called from C as `int sage_main(void)`, which returns the exit status.
+//! I/O calls hooks which the user supplies, with the cc65 `__fastcall__` convention
+//! (the argument or result is in `A`), so they can be written in C or assembly. The names
+//! of the character hooks can be changed in the configuration:
+//!
+//! ```c
+//! void __fastcall__ sage_putchar(unsigned char ch);   // Write a character to the screen.
+//! void __fastcall__ sage_eputchar(unsigned char ch);  // Write a character for errors.
+//! unsigned char sage_getchar(void);                   // Read a character, or 0 at the end.
+//! void __fastcall__ sage_exit(unsigned char status);  // Halt with an exit status.
+//! ```
+//!
+//! Assembling with `ca65 -g` and linking with `ld65 --dbgfile` produces a debug info file
+//! mapping the machine code back to the lines of the source program, for debuggers and
+//! emulators which read it.
+//!
+//! Every function call uses two bytes of the 6502's 256 byte hardware stack, and the
+//! instructions on vectors use a few more, so recursion is limited to about a hundred calls.
+use super::{
+    c::function_name, Architecture, CompiledTarget, IoHooks, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    asm::{self, Location},
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{self, CoreOp, StandardOp},
+};
+
+/// The size of a cell in bytes.
+const CELL: isize = 2;
+
+/// The type for the 6502 target which implements the `Target` trait.
+/// This allows the compiler to target 8-bit computers.
+pub struct Mos6502 {
+    /// The number of loops and conditionals compiled so far, to label them uniquely.
+    blocks: usize,
+    /// The labels of the loops and conditionals being compiled.
+    open_blocks: Vec<usize>,
+    /// The source files declared to the debug info so far.
+    files: Vec<String>,
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for Mos6502 {
+    fn default() -> Self {
+        Self {
+            blocks: 0,
+            open_blocks: Vec::new(),
+            files: Vec::new(),
+            config: TargetConfig::new(4096, 16, IoHooks::freestanding()),
+        }
+    }
+}
+
+/// Store a constant in a 16-bit location, wrapping it to 16 bits.
+fn store_imm(dst: &str, value: i64) -> String {
+    let value = value as u16;
+    format!(
+        "lda #${:02x}\nsta {dst}\nlda #${:02x}\nsta {dst}+1",
+        value & 0xff,
+        value >> 8
+    )
+}
+
+/// Add a constant to a 16-bit location in the zero page.
+fn add_imm(dst: &str, value: isize) -> String {
+    let value = value as u16;
+    format!(
+        "clc\nlda {dst}\nadc #${:02x}\nsta {dst}\nlda {dst}+1\nadc #${:02x}\nsta {dst}+1",
+        value & 0xff,
+        value >> 8
+    )
+}
+
+/// Apply a runtime routine to the register and the cell under the tape pointer, or to each
+/// of the first `n` cells of the vector register and the tape.
+fn each(n: usize, routine: &str) -> String {
+    if n == 1 {
+        format!("jsr vec_one\njsr {routine}")
+    } else {
+        format!(
+            "{}\nlda #<{routine}\nldx #>{routine}\njsr vec_apply",
+            store_imm("count", n as i64)
+        )
+    }
+}
+
+impl Architecture for Mos6502 {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(i.mode, InputMode::StdinChar | InputMode::StdinInt)
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrInt
+                | OutputMode::Exit
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            // Source lines are marked with debug info, for `ca65 -g`.
+            CoreOp::Comment(text) => match vm::comment_line(text) {
+                Some((line, Some(filename))) => {
+                    let mut result = String::new();
+                    if !self.files.iter().any(|f| f == filename) {
+                        self.files.push(filename.to_string());
+                        result += &format!(".dbg file, \"{filename}\", 0, 0\n");
+                    }
+                    result + &format!(".dbg line, \"{filename}\", {line}")
+                }
+                _ => format!("; {}", text.replace('\n', "\n; ").replace('\r', "")),
+            },
+            // Branches only reach 127 bytes, so they skip over jumps to the labels.
+            CoreOp::While => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!(
+                    "while_{n}:\nlda vreg\nora vreg+1\nbne :+\njmp end_{n}\n:",
+                    n = self.blocks
+                )
+            }
+            CoreOp::If => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!("lda vreg\nora vreg+1\nbne :+\njmp else_{}\n:", self.blocks)
+            }
+            CoreOp::Else => {
+                let n = self.open_blocks.last().copied().unwrap_or_default();
+                format!("jmp end_{n}\nelse_{n}:")
+            }
+            CoreOp::Set(n) => n
+                .iter()
+                .enumerate()
+                .map(|(i, val)| store_imm(&format!("vreg+{}", i as isize * CELL), *val))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            CoreOp::Call => "jsr call_fun".to_string(),
+            CoreOp::Return => "rts".to_string(),
+            CoreOp::Store(1) => {
+                "ldy #0\nlda vreg\nsta (ptr),y\niny\nlda vreg+1\nsta (ptr),y".to_string()
+            }
+            CoreOp::Load(1) => {
+                "ldy #0\nlda (ptr),y\nsta vreg\niny\nlda (ptr),y\nsta vreg+1".to_string()
+            }
+            CoreOp::Store(n) => each(*n, "op_store"),
+            CoreOp::Load(n) => each(*n, "op_load"),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "{}\nlda refsp\nbne :+\njsr check_tape\n:",
+                add_imm("ptr", n * CELL)
+            ),
+            CoreOp::Move(n) => add_imm("ptr", n * CELL),
+            CoreOp::Where => "lda ptr\nsta vreg\nlda ptr+1\nsta vreg+1".to_string(),
+            CoreOp::Deref => "jsr deref".to_string(),
+            CoreOp::Refer => "jsr refer".to_string(),
+            CoreOp::Offset(n, 1) => add_imm("vreg", n * CELL),
+            CoreOp::Offset(n, size) => {
+                format!(
+                    "{}\n{}",
+                    store_imm("operand", (n * CELL) as i64),
+                    each(*size, "op_offset")
+                )
+            }
+            CoreOp::Index(n) => each(*n, "op_index"),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            CoreOp::Add(n) => each(*n, "op_add"),
+            CoreOp::Sub(n) => each(*n, "op_sub"),
+            CoreOp::Mul(n) => each(*n, "op_mul"),
+            CoreOp::Div(n) => each(*n, "op_div"),
+            CoreOp::Rem(n) => each(*n, "op_rem"),
+            CoreOp::Neg(n) => each(*n, "op_neg"),
+            CoreOp::Inc(1) => "inc vreg\nbne :+\ninc vreg+1\n:".to_string(),
+            CoreOp::Inc(n) => each(*n, "op_inc"),
+            CoreOp::Dec(n) => each(*n, "op_dec"),
+            CoreOp::Swap(n) => each(*n, "op_swap"),
+
+            CoreOp::And(n) => each(*n, "op_and"),
+            CoreOp::Or(n) => each(*n, "op_or"),
+            CoreOp::Not(n) => each(*n, "op_not"),
+
+            CoreOp::BitwiseNand(n) => each(*n, "op_bitwise_nand"),
+            CoreOp::BitwiseAnd(n) => each(*n, "op_bitwise_and"),
+            CoreOp::BitwiseOr(n) => each(*n, "op_bitwise_or"),
+            CoreOp::BitwiseXor(n) => each(*n, "op_bitwise_xor"),
+            CoreOp::BitwiseNot(n) => each(*n, "op_bitwise_not"),
+
+            CoreOp::LeftShift(n) => each(*n, "op_left_shift"),
+            CoreOp::LogicalRightShift(n) => each(*n, "op_logical_right_shift"),
+            CoreOp::ArithmeticRightShift(n) => each(*n, "op_arithmetic_right_shift"),
+
+            CoreOp::IsNonNegative(n) => each(*n, "op_is_non_negative"),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for 6502 target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Err(format!("Invalid standard op for 6502 target {op:?}"))
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => "rts".to_string(),
+            (CoreOp::While, _) => {
+                let n = self.open_blocks.pop().unwrap_or_default();
+                format!("jmp while_{n}\nend_{n}:")
+            }
+            (CoreOp::If, _) => format!("else_{}:", self.open_blocks.pop().unwrap_or_default()),
+            (CoreOp::Else, _) => format!("end_{}:", self.open_blocks.pop().unwrap_or_default()),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        format!("\n{}:", function_name(label_id, label))
+    }
+
+    fn name(&self) -> &str {
+        "6502"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        false
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok("jsr next_char\nsta vreg\nlda #0\nsta vreg+1".to_string()),
+            InputMode::StdinInt => {
+                Ok("jsr get_int\nlda tmp\nsta vreg\nlda tmp+1\nsta vreg+1".to_string())
+            }
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let IoHooks {
+            put_char,
+            eput_char,
+            ..
+        } = &self.config.hooks;
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(format!("lda vreg\njsr _{put_char}")),
+            OutputMode::StdoutInt => {
+                Ok(format!("lda #<_{put_char}\nldx #>_{put_char}\njsr put_int"))
+            }
+            OutputMode::StderrChar => Ok(format!("lda vreg\njsr _{eput_char}")),
+            OutputMode::StderrInt => Ok(format!(
+                "lda #<_{eput_char}\nldx #>_{eput_char}\njsr put_int"
+            )),
+            OutputMode::Exit => Ok("lda vreg\njsr _sage_exit".to_string()),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Err("FFI not supported by this target".to_string())
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Err("FFI not supported by this target".to_string())
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let IoHooks {
+            put_char,
+            eput_char,
+            get_char,
+        } = &self.config.hooks;
+        let mut result = format!(
+            "; Assemble with `ca65`, and link with a cc65 linker configuration.
+.setcpu \"6502\"
+.export _sage_main
+.import _{put_char}, _{eput_char}, _{get_char}, _sage_exit
+
+.ifndef SAGE_TAPE_CELLS
+SAGE_TAPE_CELLS = {}
+.endif
+
+; The registers of the assembly language, at their offsets from the start of the tape.
+",
+            self.config.tape_cells
+        );
+        let registers = [
+            ("SP", asm::SP),
+            ("FP", asm::FP),
+            ("GP", asm::GP),
+            ("A", asm::A),
+            ("B", asm::B),
+            ("C", asm::C),
+            ("D", asm::D),
+            ("E", asm::E),
+            ("F", asm::F),
+        ];
+        for (name, register) in registers {
+            if let Location::Address(addr) = register {
+                result += &format!("{name}_REG = {}\n", addr as isize * CELL);
+            }
+        }
+        result += r#"
+.zeropage
+ptr:        .res 2  ; The tape pointer.
+vptr:       .res 2  ; The cell of the vector register a routine works on.
+tptr:       .res 2  ; The cell of the tape a routine works on.
+count:      .res 2  ; The number of cells left for `vec_apply`.
+routine:    .res 2  ; The routine for `vec_apply`, or the function for `call_fun`.
+operand:    .res 2  ; The constant for `op_offset`.
+output:     .res 2  ; The output hook for `put_int`.
+tmp:        .res 2
+tmp2:       .res 2
+res:        .res 2
+sign:       .res 1
+refsp:      .res 1  ; The top of the stack of pointers saved by `Deref`.
+
+; The machine's state, which is cleared when the program starts.
+.bss
+sage_bss_start:
+tape:       .res SAGE_TAPE_CELLS * 2
+tape_end:
+vreg:       .res 2048  ; The vector register, which starts with the register.
+refs:       .res 128
+digits:     .res 1
+unread:     .res 1  ; The last character `get_int` didn't use.
+has_unread: .res 1
+sage_bss_end:
+
+.code
+
+; Set up the routines to work on the register and the cell under the tape pointer.
+vec_one:
+    lda ptr
+    sta tptr
+    lda ptr+1
+    sta tptr+1
+    lda #<vreg
+    sta vptr
+    lda #>vreg
+    sta vptr+1
+    rts
+
+; Apply the routine in A (low) and X (high) to the first `count` cells
+; of the vector register and the cells under the tape pointer.
+vec_apply:
+    sta routine
+    stx routine+1
+    jsr vec_one
+@loop:
+    jsr @call
+    clc
+    lda vptr
+    adc #2
+    sta vptr
+    bcc :+
+    inc vptr+1
+:   clc
+    lda tptr
+    adc #2
+    sta tptr
+    bcc :+
+    inc tptr+1
+:   lda count
+    bne :+
+    dec count+1
+:   dec count
+    lda count
+    ora count+1
+    bne @loop
+    rts
+@call:
+    jmp (routine)
+
+; Call the function whose index is in the register.
+call_fun:
+    lda vreg
+    asl
+    sta tmp
+    lda vreg+1
+    rol
+    sta tmp+1
+    clc
+    lda tmp
+    adc #<funs
+    sta tmp
+    lda tmp+1
+    adc #>funs
+    sta tmp+1
+    ldy #0
+    lda (tmp),y
+    sta routine
+    iny
+    lda (tmp),y
+    sta routine+1
+    jmp (routine)
+
+; Save the tape pointer, and move it to the address under it.
+deref:
+    ldx refsp
+    lda ptr
+    sta refs,x
+    lda ptr+1
+    sta refs+1,x
+    inx
+    inx
+    stx refsp
+    ldy #0
+    lda (ptr),y
+    tax
+    iny
+    lda (ptr),y
+    sta ptr+1
+    stx ptr
+    rts
+
+; Restore the tape pointer saved by `deref`.
+refer:
+    ldx refsp
+    dex
+    dex
+    stx refsp
+    lda refs,x
+    sta ptr
+    lda refs+1,x
+    sta ptr+1
+    rts
+
+; Negate the 16-bit zero page location at X.
+neg_zp:
+    sec
+    lda #0
+    sbc 0,x
+    sta 0,x
+    lda #0
+    sbc 1,x
+    sta 1,x
+    rts
+
+; Load the cells the routines work on into tmp and tmp2.
+load_operands:
+    ldy #0
+    lda (vptr),y
+    sta tmp
+    lda (tptr),y
+    sta tmp2
+    iny
+    lda (vptr),y
+    sta tmp+1
+    lda (tptr),y
+    sta tmp2+1
+    rts
+
+; Store A (low) and X (high) in the cell of the vector register.
+store_result:
+    ldy #0
+    sta (vptr),y
+    iny
+    txa
+    sta (vptr),y
+    rts
+
+; Divide tmp by tmp2, unsigned. The quotient is left in tmp, and the remainder in res.
+udiv16:
+    lda #0
+    sta res
+    sta res+1
+    ldx #16
+@loop:
+    asl tmp
+    rol tmp+1
+    rol res
+    rol res+1
+    lda res
+    sec
+    sbc tmp2
+    tay
+    lda res+1
+    sbc tmp2+1
+    bcc @skip
+    sta res+1
+    sty res
+    inc tmp
+@skip:
+    dex
+    bne @loop
+    rts
+
+; Divide the operands by their magnitudes, remembering the signs of the quotient (bit 7 of
+; sign) and the dividend (bit 6 of sign).
+sdiv16:
+    jsr load_operands
+    lda tmp+1
+    eor tmp2+1
+    and #$80
+    sta sign
+    lda tmp+1
+    bpl :+
+    lda sign
+    ora #$40
+    sta sign
+    ldx #tmp
+    jsr neg_zp
+:   lda tmp2+1
+    bpl :+
+    ldx #tmp2
+    jsr neg_zp
+:   jmp udiv16
+
+op_store:
+    ldy #0
+    lda (vptr),y
+    sta (tptr),y
+    iny
+    lda (vptr),y
+    sta (tptr),y
+    rts
+
+op_load:
+    ldy #0
+    lda (tptr),y
+    sta (vptr),y
+    iny
+    lda (tptr),y
+    sta (vptr),y
+    rts
+
+op_offset:
+    ldy #0
+    clc
+    lda (vptr),y
+    adc operand
+    sta (vptr),y
+    iny
+    lda (vptr),y
+    adc operand+1
+    sta (vptr),y
+    rts
+
+; Add the cell under the tape pointer to the pointer, scaled by the size of a cell.
+op_index:
+    jsr load_operands
+    asl tmp2
+    rol tmp2+1
+    clc
+    lda tmp
+    adc tmp2
+    pha
+    lda tmp+1
+    adc tmp2+1
+    tax
+    pla
+    jmp store_result
+
+op_add:
+    ldy #0
+    clc
+    lda (vptr),y
+    adc (tptr),y
+    sta (vptr),y
+    iny
+    lda (vptr),y
+    adc (tptr),y
+    sta (vptr),y
+    rts
+
+op_sub:
+    ldy #0
+    sec
+    lda (vptr),y
+    sbc (tptr),y
+    sta (vptr),y
+    iny
+    lda (vptr),y
+    sbc (tptr),y
+    sta (vptr),y
+    rts
+
+; Multiply by shifting and adding. Only the low 16 bits of the product are kept.
+op_mul:
+    jsr load_operands
+    lda #0
+    sta res
+    sta res+1
+    ldx #16
+@loop:
+    lsr tmp2+1
+    ror tmp2
+    bcc @skip
+    clc
+    lda res
+    adc tmp
+    sta res
+    lda res+1
+    adc tmp+1
+    sta res+1
+@skip:
+    asl tmp
+    rol tmp+1
+    dex
+    bne @loop
+    lda res
+    ldx res+1
+    jmp store_result
+
+; Divide, rounding towards zero. A zero divisor leaves the dividend unchanged, and
+; dividing by -1 negates the dividend, wrapping the smallest integer around to itself.
+op_div:
+    jsr load_operands
+    lda tmp2
+    ora tmp2+1
+    beq @keep
+    lda tmp2
+    and tmp2+1
+    cmp #$ff
+    bne :+
+    ldx #tmp
+    jsr neg_zp
+@keep:
+    lda tmp
+    ldx tmp+1
+    jmp store_result
+:   jsr sdiv16
+    bit sign
+    bpl :+
+    ldx #tmp
+    jsr neg_zp
+:   lda tmp
+    ldx tmp+1
+    jmp store_result
+
+; The remainder has the sign of the dividend. A zero divisor leaves the dividend
+; unchanged, and the remainder of dividing by -1 is zero.
+op_rem:
+    jsr load_operands
+    lda tmp2
+    ora tmp2+1
+    beq @keep
+    lda tmp2
+    and tmp2+1
+    cmp #$ff
+    bne :+
+    lda #0
+    tax
+    jmp store_result
+@keep:
+    lda tmp
+    ldx tmp+1
+    jmp store_result
+:   jsr sdiv16
+    bit sign
+    bvc :+
+    ldx #res
+    jsr neg_zp
+:   lda res
+    ldx res+1
+    jmp store_result
+
+op_neg:
+    ldy #0
+    sec
+    lda #0
+    sbc (vptr),y
+    sta (vptr),y
+    iny
+    lda #0
+    sbc (vptr),y
+    sta (vptr),y
+    rts
+
+op_inc:
+    ldy #0
+    clc
+    lda (vptr),y
+    adc #1
+    sta (vptr),y
+    iny
+    lda (vptr),y
+    adc #0
+    sta (vptr),y
+    rts
+
+op_dec:
+    ldy #0
+    sec
+    lda (vptr),y
+    sbc #1
+    sta (vptr),y
+    iny
+    lda (vptr),y
+    sbc #0
+    sta (vptr),y
+    rts
+
+op_swap:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    tax
+    lda (tptr),y
+    sta (vptr),y
+    txa
+    sta (tptr),y
+    rts
+
+; Store 1 in the cell of the vector register if Z is clear, and 0 otherwise.
+store_bool:
+    beq :+
+    lda #1
+:   ldx #0
+    jmp store_result
+
+op_and:
+    jsr load_operands
+    lda tmp
+    ora tmp+1
+    beq store_bool
+    lda tmp2
+    ora tmp2+1
+    jmp store_bool
+
+op_or:
+    jsr load_operands
+    lda tmp
+    ora tmp+1
+    ora tmp2
+    ora tmp2+1
+    jmp store_bool
+
+op_not:
+    jsr load_operands
+    lda tmp
+    ora tmp+1
+    beq :+
+    lda #0
+    tax
+    jmp store_result
+:   lda #1
+    ldx #0
+    jmp store_result
+
+op_bitwise_nand:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    and (tptr),y
+    eor #$ff
+    sta (vptr),y
+    rts
+
+op_bitwise_and:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    and (tptr),y
+    sta (vptr),y
+    rts
+
+op_bitwise_or:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    ora (tptr),y
+    sta (vptr),y
+    rts
+
+op_bitwise_xor:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    eor (tptr),y
+    sta (vptr),y
+    rts
+
+op_bitwise_not:
+    ldy #0
+    jsr @byte
+    iny
+@byte:
+    lda (vptr),y
+    eor #$ff
+    sta (vptr),y
+    rts
+
+; Shifts use the low four bits of the amount, like 16-bit machines do.
+op_left_shift:
+    jsr load_operands
+    lda tmp2
+    and #15
+    tax
+    beq @done
+@loop:
+    asl tmp
+    rol tmp+1
+    dex
+    bne @loop
+@done:
+    lda tmp
+    ldx tmp+1
+    jmp store_result
+
+op_logical_right_shift:
+    jsr load_operands
+    lda tmp2
+    and #15
+    tax
+    beq @done
+@loop:
+    lsr tmp+1
+    ror tmp
+    dex
+    bne @loop
+@done:
+    lda tmp
+    ldx tmp+1
+    jmp store_result
+
+op_arithmetic_right_shift:
+    jsr load_operands
+    lda tmp2
+    and #15
+    tax
+    beq @done
+@loop:
+    lda tmp+1
+    cmp #$80
+    ror tmp+1
+    ror tmp
+    dex
+    bne @loop
+@done:
+    lda tmp
+    ldx tmp+1
+    jmp store_result
+
+op_is_non_negative:
+    ldy #1
+    lda (vptr),y
+    bmi :+
+    lda #1
+    ldx #0
+    jmp store_result
+:   lda #0
+    tax
+    jmp store_result
+
+; Read a decimal integer into tmp, skipping whitespace before it.
+get_int:
+    lda #0
+    sta tmp
+    sta tmp+1
+    sta sign
+@skip:
+    jsr next_char
+    cmp #' '
+    beq @skip
+    cmp #9
+    beq @skip
+    cmp #10
+    beq @skip
+    cmp #13
+    beq @skip
+    cmp #'-'
+    bne @plus
+    lda #$80
+    sta sign
+    jsr next_char
+    jmp @digit
+@plus:
+    cmp #'+'
+    bne @digit
+    jsr next_char
+@digit:
+    cmp #'0'
+    bcc @done
+    cmp #'9'+1
+    bcs @done
+    and #$0f
+    pha
+    ; Multiply by 10, as (tmp * 4 + tmp) * 2.
+    lda tmp
+    sta tmp2
+    lda tmp+1
+    sta tmp2+1
+    asl tmp
+    rol tmp+1
+    asl tmp
+    rol tmp+1
+    clc
+    lda tmp
+    adc tmp2
+    sta tmp
+    lda tmp+1
+    adc tmp2+1
+    sta tmp+1
+    asl tmp
+    rol tmp+1
+    pla
+    clc
+    adc tmp
+    sta tmp
+    bcc :+
+    inc tmp+1
+:   jsr next_char
+    jmp @digit
+@done:
+    sta unread
+    lda #1
+    sta has_unread
+    lda sign
+    beq :+
+    ldx #tmp
+    jsr neg_zp
+:   rts
+
+; Print the register in decimal, with the output hook in A (low) and X (high).
+put_int:
+    sta output
+    stx output+1
+    lda vreg
+    sta tmp
+    lda vreg+1
+    sta tmp+1
+    bpl :+
+    lda #'-'
+    jsr @out
+    ldx #tmp
+    jsr neg_zp
+:   lda #0
+    sta digits
+@divide:
+    lda #10
+    sta tmp2
+    lda #0
+    sta tmp2+1
+    jsr udiv16
+    lda res
+    ora #'0'
+    pha
+    inc digits
+    lda tmp
+    ora tmp+1
+    bne @divide
+@print:
+    pla
+    jsr @out
+    dec digits
+    bne @print
+    rts
+@out:
+    jmp (output)
+"#;
+        result += &format!(
+            r#"
+; Read a character, after the one `get_int` didn't use.
+next_char:
+    lda has_unread
+    beq :+
+    lda #0
+    sta has_unread
+    lda unread
+    rts
+:   jmp _{get_char}
+
+; Exit with an error if the tape pointer is off the tape.
+check_tape:
+    lda ptr+1
+    cmp #>tape
+    bcc tape_out_of_bounds
+    bne :+
+    lda ptr
+    cmp #<tape
+    bcc tape_out_of_bounds
+:   lda ptr+1
+    cmp #>tape_end
+    bcc @ok
+    bne tape_out_of_bounds
+    lda ptr
+    cmp #<tape_end
+    bcs tape_out_of_bounds
+@ok:
+    rts
+
+; Report the tape pointer moving off the tape with the error hook, and exit.
+tape_out_of_bounds:
+    lda #0
+    sta tmp
+@print:
+    ldx tmp
+    lda tape_bounds_error,x
+    beq @exit
+    jsr _{eput_char}
+    inc tmp
+    jmp @print
+@exit:
+    lda #1
+    jmp _sage_exit
+
+.rodata
+tape_bounds_error: .byte "{TAPE_BOUNDS_ERROR}", 10, 0
+.code
+"#
+        );
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let mut result = String::from("\n.rodata\nfuns:\n");
+        for (fun, label) in funs {
+            result += &format!(
+                "    .word {}\n",
+                function_name(fun as usize, label.as_deref())
+            );
+        }
+        result += r#"
+.code
+_sage_main:
+    ; Clear the machine's state.
+    lda #<sage_bss_start
+    sta tmp
+    lda #>sage_bss_start
+    sta tmp+1
+    ldy #0
+@clear:
+    lda tmp
+    cmp #<sage_bss_end
+    bne :+
+    lda tmp+1
+    cmp #>sage_bss_end
+    beq @cleared
+:   tya
+    sta (tmp),y
+    inc tmp
+    bne @clear
+    inc tmp+1
+    jmp @clear
+@cleared:
+    lda #<tape
+    sta ptr
+    lda #>tape
+    sta ptr+1
+    lda #0
+    sta refsp
+"#;
+        Some(result)
+    }
+
+    fn indentation(&self) -> Option<String> {
+        Some("    ".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some("lda #0\n    tax\n    rts\n".to_string())
+    }
+}
+
+impl CompiledTarget for Mos6502 {}
+
+impl Target for Mos6502 {
+    fn extension(&self) -> &str {
+        "s"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+
+    fn supports_std(&self) -> bool {
+        false
+    }
+}
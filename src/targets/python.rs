@@ -0,0 +1,625 @@
+//! # Python Target
+//!
+//! An implementation of the virtual machine for Python 3, which emits a self-contained module.
+//! It's slow, but its arithmetic is exact, so it's useful as an oracle when testing the other
+//! targets, and for running programs in scripting environments that can't run native code.
+//!
+//! Run as a script, the module reads its input from the standard input, and writes its output
+//! when the program ends:
+//!
+//! ```bash
+//! $ python3 out.py < input.txt
+//! ```
+//!
+//! It can also be imported, and run with any device:
+//!
+//! ```python
+//! from out import run, BufferDevice
+//!
+//! device = BufferDevice("some input")
+//! status = run(device)
+//! print(device.output)
+//! ```
+//!
+//! The tape is a list of integers, which are wrapped to 64 bits after every instruction.
+//! Floats are stored on the tape as their bits, like on the other targets. Pointers are
+//! indices into the tape, and the heap is a static arena after the tape, with a first fit free
+//! list (like in the freestanding C target). `run` takes the sizes of the tape and the heap
+//! as arguments (the tape defaults to the size in the target's [`TargetConfig`]), and the
+//! tape must be larger than the call stack size the program was assembled with. With bounds
+//! checking, moving the tape pointer off the tape (outside of a `Deref`) writes an error to
+//! the device's standard error, and exits with status 1.
+//!
+//! All I/O goes through the device passed to `run`: the `Device` class is the protocol that
+//! devices implement, with the methods `get`, `put`, `peek`, `poke`, and `ffi_call`. The modes
+//! are named like `stdout.char` or `dpad.up`, and a device raises `NotImplementedError` for
+//! the modes it doesn't support. The included `BufferDevice` reads input from a string, and
+//! collects the standard output and error in the strings `output` and `error`. Since the
+//! device does all of the I/O, the configuration's I/O hooks are unused.
+use super::{
+    c::function_name, Architecture, CompiledTarget, IoHooks, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{CoreOp, StandardOp},
+};
+
+/// The type for the Python target which implements the `Target` trait.
+/// This allows the compiler to target Python scripts.
+pub struct Python {
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for Python {
+    fn default() -> Self {
+        Self {
+            config: TargetConfig::new(16777216, 64, IoHooks::libc()),
+        }
+    }
+}
+
+/// Apply an instruction to the register and the cell under the tape pointer, or to each of
+/// the first `n` cells of the vector register and the tape. The body uses `reg[i]` and
+/// `mem[ptr + i]`.
+fn each(n: usize, body: &str) -> String {
+    if n == 1 {
+        body.replace("ptr + i", "ptr").replace("[i]", "[0]")
+    } else {
+        format!("for i in range({n}): {body}")
+    }
+}
+
+/// Apply a floating point expression of `{a}` (the register) and `{b}` (the cell under the
+/// tape pointer) to the register, or to each of the first `n` cells of the vector register.
+fn each_float(n: usize, expr: &str) -> String {
+    each(
+        n,
+        &format!(
+            "reg[i] = bits({})",
+            expr.replace("{a}", "fl(reg[i])")
+                .replace("{b}", "fl(mem[ptr + i])")
+        ),
+    )
+}
+
+/// A Python literal for a float.
+fn float_literal(val: f64) -> String {
+    if val.is_nan() {
+        "math.nan".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "math.inf" } else { "-math.inf" }.to_string()
+    } else {
+        format!("{val:?}")
+    }
+}
+
+/// A Python literal for a string.
+fn string_literal(text: &str) -> String {
+    let mut result = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '\\' => result += "\\\\",
+            '"' => result += "\\\"",
+            ' '..='~' => result.push(ch),
+            _ if (ch as u32) < 0x10000 => result += &format!("\\u{:04x}", ch as u32),
+            _ => result += &format!("\\U{:08x}", ch as u32),
+        }
+    }
+    result + "\""
+}
+
+/// Is the value read by an input mode a float?
+fn is_float_input(mode: &InputMode) -> bool {
+    matches!(mode, InputMode::StdinFloat)
+}
+
+/// Is the value written by an output mode a float?
+fn is_float_output(mode: &OutputMode) -> bool {
+    matches!(
+        mode,
+        OutputMode::StdoutFloat | OutputMode::StderrFloat | OutputMode::PrinterFloat
+    )
+}
+
+impl Architecture for Python {
+    /// Every input and output is passed to the device, which decides what it supports.
+    fn supports_input(&self, _i: &Input) -> bool {
+        true
+    }
+
+    fn supports_output(&self, _o: &Output) -> bool {
+        true
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => {
+                format!("# {}", text.replace('\n', "\n# ").replace('\r', ""))
+            }
+            // Every block starts with `pass`, in case it's empty.
+            CoreOp::While => "while reg[0]:\n    pass".to_string(),
+            CoreOp::If => "if reg[0]:\n    pass".to_string(),
+            CoreOp::Else => "else:\n    pass".to_string(),
+            CoreOp::Set(n) => n
+                .iter()
+                .enumerate()
+                .map(|(i, val)| format!("reg[{i}] = {val}"))
+                .collect::<Vec<_>>()
+                .join("; "),
+            CoreOp::Call => "funs[reg[0]]()".to_string(),
+            CoreOp::Return => "return".to_string(),
+            CoreOp::Store(1) => "mem[ptr] = reg[0]".to_string(),
+            CoreOp::Load(1) => "reg[0] = mem[ptr]".to_string(),
+            CoreOp::Store(n) => format!("mem[ptr:ptr + {n}] = reg[:{n}]"),
+            CoreOp::Load(n) => format!("reg[:{n}] = mem[ptr:ptr + {n}]"),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!("ptr += {n}; check_tape()"),
+            CoreOp::Move(n) => format!("ptr += {n}"),
+            CoreOp::Where => "reg[0] = ptr".to_string(),
+            CoreOp::Deref => "refs.append(ptr); ptr = mem[ptr]".to_string(),
+            CoreOp::Refer => "ptr = refs.pop()".to_string(),
+            CoreOp::Offset(n, size) => each(*size, &format!("reg[i] = wrap(reg[i] + {n})")),
+            CoreOp::Index(n) => each(*n, "reg[i] = wrap(reg[i] + mem[ptr + i])"),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            CoreOp::Add(n) => each(*n, "reg[i] = wrap(reg[i] + mem[ptr + i])"),
+            CoreOp::Sub(n) => each(*n, "reg[i] = wrap(reg[i] - mem[ptr + i])"),
+            CoreOp::Mul(n) => each(*n, "reg[i] = wrap(reg[i] * mem[ptr + i])"),
+            CoreOp::Div(n) => each(*n, "reg[i] = idiv(reg[i], mem[ptr + i])"),
+            CoreOp::Rem(n) => each(*n, "reg[i] = irem(reg[i], mem[ptr + i])"),
+            CoreOp::Neg(n) => each(*n, "reg[i] = wrap(-reg[i])"),
+            CoreOp::Inc(n) => each(*n, "reg[i] = wrap(reg[i] + 1)"),
+            CoreOp::Dec(n) => each(*n, "reg[i] = wrap(reg[i] - 1)"),
+            CoreOp::Swap(n) => each(*n, "reg[i], mem[ptr + i] = mem[ptr + i], reg[i]"),
+
+            CoreOp::And(n) => each(*n, "reg[i] = int(bool(reg[i] and mem[ptr + i]))"),
+            CoreOp::Or(n) => each(*n, "reg[i] = int(bool(reg[i] or mem[ptr + i]))"),
+            CoreOp::Not(n) => each(*n, "reg[i] = int(not reg[i])"),
+
+            CoreOp::BitwiseNand(n) => each(*n, "reg[i] = ~(reg[i] & mem[ptr + i])"),
+            CoreOp::BitwiseAnd(n) => each(*n, "reg[i] &= mem[ptr + i]"),
+            CoreOp::BitwiseOr(n) => each(*n, "reg[i] |= mem[ptr + i]"),
+            CoreOp::BitwiseXor(n) => each(*n, "reg[i] ^= mem[ptr + i]"),
+            CoreOp::BitwiseNot(n) => each(*n, "reg[i] = ~reg[i]"),
+
+            // Shifts use the low six bits of the amount, like 64 bit machines do.
+            CoreOp::LeftShift(n) => each(*n, "reg[i] = wrap(reg[i] << (mem[ptr + i] & 63))"),
+            CoreOp::LogicalRightShift(n) => {
+                each(*n, "reg[i] = wrap((reg[i] & MASK) >> (mem[ptr + i] & 63))")
+            }
+            CoreOp::ArithmeticRightShift(n) => each(*n, "reg[i] >>= mem[ptr + i] & 63"),
+
+            CoreOp::IsNonNegative(n) => each(*n, "reg[i] = int(reg[i] >= 0)"),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for Python target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => format!("device.ffi_call({}, mem)", string_literal(&ffi.name)),
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => n
+                .iter()
+                .enumerate()
+                .map(|(i, val)| format!("reg[{i}] = bits({})", float_literal(*val)))
+                .collect::<Vec<_>>()
+                .join("; "),
+
+            StandardOp::ToInt(n) => each(*n, "reg[i] = to_int(fl(reg[i]))"),
+            StandardOp::ToFloat(n) => each(*n, "reg[i] = bits(float(reg[i]))"),
+
+            StandardOp::ACos(n) => each_float(*n, "fmath(math.acos, {a})"),
+            StandardOp::ASin(n) => each_float(*n, "fmath(math.asin, {a})"),
+            StandardOp::ATan(n) => each_float(*n, "fmath(math.atan, {a})"),
+            StandardOp::Sin(n) => each_float(*n, "fmath(math.sin, {a})"),
+            StandardOp::Cos(n) => each_float(*n, "fmath(math.cos, {a})"),
+            StandardOp::Tan(n) => each_float(*n, "fmath(math.tan, {a})"),
+
+            StandardOp::Add(n) => each_float(*n, "{a} + {b}"),
+            StandardOp::Sub(n) => each_float(*n, "{a} - {b}"),
+            StandardOp::Mul(n) => each_float(*n, "{a} * {b}"),
+            StandardOp::Div(n) => each_float(*n, "fdiv({a}, {b})"),
+            StandardOp::Rem(n) => each_float(*n, "fmath(math.fmod, {a}, {b})"),
+            StandardOp::Pow(n) => each_float(*n, "fmath(math.pow, {a}, {b})"),
+            StandardOp::IsNonNegative(n) => each(*n, "reg[i] = int(fl(reg[i]) >= 0)"),
+
+            StandardOp::Alloc => "reg[0] = heap_alloc(reg[0])".to_string(),
+            StandardOp::Free => "heap_free(reg[0])".to_string(),
+            StandardOp::Realloc => "reg[0] = heap_realloc(reg[0], mem[ptr])".to_string(),
+            StandardOp::HeapUsed => "reg[0] = heap.used".to_string(),
+            StandardOp::HeapFree => "reg[0] = heap_available()".to_string(),
+
+            // Python runs the program on one thread, so the atomic instructions are plain.
+            StandardOp::AtomicLoad => "reg[0] = mem[reg[0]]".to_string(),
+            StandardOp::AtomicStore => "mem[reg[0]] = mem[ptr]".to_string(),
+            StandardOp::AtomicAdd => {
+                "p = reg[0]; reg[0], mem[p] = mem[p], wrap(mem[p] + mem[ptr])".to_string()
+            }
+            // Store the value found back over the expected value, like `__atomic_compare_exchange_n`.
+            StandardOp::AtomicCompareSwap => "if mem[reg[0]] == mem[ptr]: mem[reg[0]] = mem[ptr + 1]; reg[0] = 1\nelse: mem[ptr] = mem[reg[0]]; reg[0] = 0".to_string(),
+            _ => return Err(format!("Invalid standard op for Python target {op:?}")),
+        })
+    }
+
+    /// Blocks end by dedenting, so there's nothing to write.
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function | CoreOp::While | CoreOp::If | CoreOp::Else, _) => String::new(),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        format!(
+            "\n\ndef {}():\n    global ptr",
+            function_name(label_id, label)
+        )
+    }
+
+    fn name(&self) -> &str {
+        "Python"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        let mode = string_literal(&src.mode.to_string());
+        Ok(if is_float_input(&src.mode) {
+            format!(
+                "reg[0] = bits(float(device.get({mode}, {})))",
+                src.channel.0
+            )
+        } else {
+            format!("reg[0] = wrap(int(device.get({mode}, {})))", src.channel.0)
+        })
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let mode = string_literal(&dst.mode.to_string());
+        Ok(if dst.mode == OutputMode::Exit {
+            "raise SageExit(reg[0])".to_string()
+        } else if is_float_output(&dst.mode) {
+            format!("device.put(fl(reg[0]), {mode}, {})", dst.channel.0)
+        } else {
+            format!("device.put(reg[0], {mode}, {})", dst.channel.0)
+        })
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok("reg[0] = wrap(device.peek())".to_string())
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok("device.poke(reg[0])".to_string())
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn indentation(&self) -> Option<String> {
+        Some("    ".to_string())
+    }
+
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        Some(
+            r#"import math
+import re
+import struct
+import sys
+
+MASK = (1 << 64) - 1
+
+# The state of the machine, which `run` resets.
+# Pointers are indices into `mem`, which starts with an unused cell, so that 0 is null.
+mem, ptr, refs, device = [], 1, [], None
+tape_end = 1
+reg = [0] * 1024
+
+
+class SageExit(Exception):
+    """Raised to halt the program with an exit status."""
+
+    def __init__(self, status):
+        super().__init__(status)
+        self.status = status
+
+
+def wrap(x):
+    """Wrap an integer to 64 bits."""
+    x &= MASK
+    return x - (1 << 64) if x >> 63 else x
+
+
+def idiv(a, b):
+    """Divide integers, rounding towards zero. Dividing by zero gives the dividend."""
+    if b == 0:
+        return a
+    q = abs(a) // abs(b)
+    return wrap(q if (a < 0) == (b < 0) else -q)
+
+
+def irem(a, b):
+    """The remainder of dividing integers, with the sign of the dividend.
+    The remainder of dividing by zero is the dividend."""
+    if b == 0:
+        return a
+    r = abs(a) % abs(b)
+    return -r if a < 0 else r
+
+
+def fl(x):
+    """The float with the bits of a cell."""
+    return struct.unpack("<d", struct.pack("<q", x))[0]
+
+
+def bits(x):
+    """The bits of a float, as a cell."""
+    return struct.unpack("<q", struct.pack("<d", x))[0]
+
+
+def to_int(x):
+    """Convert a float to an integer, saturating like Rust's `as`."""
+    if math.isnan(x):
+        return 0
+    if x >= 2.0**63:
+        return (1 << 63) - 1
+    if x <= -(2.0**63):
+        return -(1 << 63)
+    return int(x)
+
+
+def fdiv(a, b):
+    """Divide floats, with IEEE 754 results for division by zero."""
+    if b == 0:
+        if a == 0 or math.isnan(a):
+            return math.nan
+        return math.copysign(math.inf, a) * math.copysign(1.0, b)
+    return a / b
+
+
+def fmath(f, *args):
+    """Call a math function, with IEEE 754 results instead of exceptions."""
+    try:
+        return f(*args)
+    except ValueError:
+        return math.nan
+    except OverflowError:
+        return math.inf
+
+
+def format_float(x):
+    """Format a float like the interpreter does."""
+    if math.isnan(x):
+        return "NaN"
+    mantissa, _, exponent = repr(x).partition("e")
+    return f"{mantissa}e{int(exponent)}" if exponent else mantissa
+
+
+class Heap:
+    """The heap is a static arena after the tape. Every block is preceded by a cell holding
+    its size, and freed blocks are linked through their first cell, to be reused first fit."""
+
+    def __init__(self, start, cells):
+        self.top, self.end = start, start + cells
+        self.free_blocks, self.used = 0, 0
+
+
+heap = Heap(0, 0)
+
+
+def heap_alloc(size):
+    # Every block needs room for the link to the next free block.
+    size = max(size, 1)
+    prev, block = 0, heap.free_blocks
+    while block:
+        if mem[block] >= size:
+            if prev:
+                mem[prev + 1] = mem[block + 1]
+            else:
+                heap.free_blocks = mem[block + 1]
+            heap.used += mem[block]
+            return block + 1
+        prev, block = block, mem[block + 1]
+    if size + 1 > heap.end - heap.top:
+        return 0
+    block = heap.top
+    heap.top += size + 1
+    mem[block] = size
+    heap.used += size
+    return block + 1
+
+
+def heap_free(p):
+    heap.used -= mem[p - 1]
+    mem[p] = heap.free_blocks
+    heap.free_blocks = p - 1
+
+
+def heap_realloc(p, size):
+    if mem[p - 1] >= size:
+        return p
+    block = heap_alloc(size)
+    if block:
+        mem[block:block + mem[p - 1]] = mem[p:p + mem[p - 1]]
+        heap_free(p)
+    return block
+
+
+def heap_available():
+    """The number of cells which can still be allocated, in the free blocks and the rest of the arena."""
+    available, block = heap.end - heap.top, heap.free_blocks
+    while block:
+        available += mem[block]
+        block = mem[block + 1]
+    return available
+
+
+class Device:
+    """The protocol for the devices which programs do I/O with.
+    Override the methods for the modes and features the device supports."""
+
+    def get(self, mode, channel):
+        """Input an integer (or a float, for the "stdin.float" mode)."""
+        raise NotImplementedError(f"Input {mode} #{channel} not supported by this device")
+
+    def put(self, value, mode, channel):
+        """Output an integer (or a float, for the float modes)."""
+        raise NotImplementedError(f"Output {mode} #{channel} not supported by this device")
+
+    def peek(self):
+        """Take the next integer from the FFI channel."""
+        raise NotImplementedError("FFI not supported by this device")
+
+    def poke(self, value):
+        """Add an integer to the FFI channel."""
+        raise NotImplementedError("FFI not supported by this device")
+
+    def ffi_call(self, name, tape):
+        """Call a foreign function, which may use the tape."""
+        raise NotImplementedError(f"Foreign function {name} not supported by this device")
+
+
+class BufferDevice(Device):
+    """A device which reads input from a string, and collects the standard output and error
+    in the strings `output` and `error`. Foreign functions can be added to `ffi` by name,
+    and are called with the FFI channel (a list of integers) and the tape."""
+
+    INT = re.compile(r"\s*([-+]?\d+)")
+    FLOAT = re.compile(r"\s*([-+]?(?:\d+\.?\d*|\.\d+)(?:[eE][-+]?\d+)?)")
+
+    def __init__(self, input=""):
+        self.input, self.position = input, 0
+        self.output, self.error = "", ""
+        self.channel, self.ffi = [], {}
+
+    def read(self, pattern, default):
+        """Read the next match of a pattern from the input, skipping whitespace before it."""
+        match = pattern.match(self.input, self.position)
+        if not match:
+            return default
+        self.position = match.end()
+        return match.group(1)
+
+    def get(self, mode, channel):
+        if mode == "stdin.char":
+            if self.position >= len(self.input):
+                return 0
+            self.position += 1
+            return ord(self.input[self.position - 1])
+        if mode == "stdin.int":
+            return int(self.read(self.INT, 0))
+        if mode == "stdin.float":
+            return float(self.read(self.FLOAT, 0))
+        return super().get(mode, channel)
+
+    def put(self, value, mode, channel):
+        stream, kind = mode.split(".", 1) if "." in mode else (mode, "")
+        if stream not in ("stdout", "stderr") or kind not in ("char", "int", "float"):
+            return super().put(value, mode, channel)
+        text = chr(value & 0xFF) if kind == "char" else format_float(value) if kind == "float" else str(value)
+        if stream == "stdout":
+            self.output += text
+        else:
+            self.error += text
+
+    def peek(self):
+        if not self.channel:
+            raise RuntimeError("Tried to peek from empty FFI channel")
+        return self.channel.pop(0)
+
+    def poke(self, value):
+        self.channel.append(value)
+
+    def ffi_call(self, name, tape):
+        if name not in self.ffi:
+            raise RuntimeError(f"Foreign function {name} not found")
+        self.ffi[name](self.channel, tape)
+"#
+            .to_string()
+                + &format!(
+                    r#"
+
+def check_tape():
+    """Exit with an error if the tape pointer is off the tape, outside of a `Deref`."""
+    if not refs and not 1 <= ptr < tape_end:
+        for ch in "{TAPE_BOUNDS_ERROR}\n":
+            device.put(ord(ch), "stderr.char", 0)
+        raise SageExit(1)
+"#
+                ),
+        )
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let names: Vec<String> = funs
+            .into_iter()
+            .map(|(fun, label)| function_name(fun as usize, label.as_deref()))
+            .collect();
+        Some(format!(
+            "\n\nfuns = [{}]\n\n\ndef sage_main():\n    global ptr\n",
+            names.join(", ")
+        ))
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some(format!(
+            r#"return
+
+
+def run(target=None, tape_cells={}, heap_cells=1048576):
+    """Run the program with the given device (a `BufferDevice` by default), and return its
+    exit status. The tape and the heap are lists of `tape_cells` and `heap_cells` cells."""
+    global mem, ptr, refs, device, heap, tape_end
+    device = BufferDevice() if target is None else target
+    tape_end = 1 + tape_cells
+    mem = [0] * (1 + tape_cells + heap_cells)
+    ptr, refs = 1, []
+    reg[:] = [0] * len(reg)
+    heap = Heap(1 + tape_cells, heap_cells)
+    # Every function call in the program is a Python call.
+    sys.setrecursionlimit(max(sys.getrecursionlimit(), 100000))
+    try:
+        sage_main()
+    except SageExit as e:
+        return e.status
+    return 0
+
+
+if __name__ == "__main__":
+    stdio = BufferDevice(sys.stdin.read())
+    status = run(stdio)
+    sys.stdout.write(stdio.output)
+    sys.stderr.write(stdio.error)
+    sys.exit(status)
+"#,
+            self.config.tape_cells
+        ))
+    }
+}
+
+impl CompiledTarget for Python {}
+
+impl Target for Python {
+    fn extension(&self) -> &str {
+        "py"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -0,0 +1,601 @@
+//! # RISC-V Target
+//!
+//! An implementation of the virtual machine for 64-bit RISC-V Linux (RV64GC with the
+//! LP64D ABI), which emits GNU assembler (`.s`) files. The runtime is the same as the
+//! x86-64 target's: the tape is mapped with `mmap`, and libc and libm provide I/O,
+//! the heap, and the floating point math functions. The output runs on boards with
+//! Linux, or under QEMU:
+//!
+//! ```bash
+//! $ riscv64-linux-gnu-gcc output.s -o output -lm
+//! $ qemu-riscv64 -L /usr/riscv64-linux-gnu ./output
+//! ```
+//!
+//! Cells are 64 bits wide, like on the other targets. The machine's state is kept in
+//! callee-saved registers, so that calls to C don't disturb it:
+//!
+//! | Register  | Contents                                                      |
+//! |-----------|---------------------------------------------------------------|
+//! | `s1`      | The tape pointer.                                             |
+//! | `s2`      | The register (the first cell of the vector register).         |
+//! | `s3`      | The top of the stack of pointers saved by `Deref`.            |
+//! | `s4`      | The vector register, which is in memory.                      |
+//! | `s5`      | The start of the tape.                                        |
+//! | `s6`-`s8` | Loop counters for instructions which call C functions.        |
+//!
+//! The registers of the assembly language (like `SP` and `A`) stay on the tape, at the
+//! offsets from `s5` named like `A_REG`. Every function saves its return address on the
+//! stack, so the stack stays aligned to 16 bytes for calls to C.
+//! Like on x86-64, `.loc` directives and function sizes give the output DWARF line tables,
+//! and the size of the tape, bounds checking, and the I/O hooks are set by the target's
+//! [`TargetConfig`].
+use super::{
+    c::function_name, Architecture, CompiledTarget, LineDirectives, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    asm::{self, Location},
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{self, CoreOp, StandardOp},
+};
+
+/// The code which returns from a function, restoring its return address.
+const RETURN: &str = "ld ra, 8(sp)\naddi sp, sp, 16\nret";
+
+/// The type for the RISC-V target which implements the `Target` trait.
+/// This allows the compiler to target RISC-V boards without a C compiler.
+pub struct RiscV64 {
+    /// The number of loops and conditionals compiled so far, to label them uniquely.
+    blocks: usize,
+    /// The labels of the loops and conditionals being compiled.
+    open_blocks: Vec<usize>,
+    /// The source files named by line directives.
+    lines: LineDirectives,
+    /// The name of the function being compiled, to mark its size at its end.
+    function: Option<String>,
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for RiscV64 {
+    fn default() -> Self {
+        Self {
+            blocks: 0,
+            open_blocks: Vec::new(),
+            lines: LineDirectives::default(),
+            function: None,
+            config: TargetConfig::default(),
+        }
+    }
+}
+
+/// Add a constant to a register, using `t0` if it doesn't fit in an immediate.
+fn add_imm(reg: &str, imm: isize) -> String {
+    if (-2048..2048).contains(&imm) {
+        format!("addi {reg}, {reg}, {imm}")
+    } else {
+        format!("li t0, {imm}\nadd {reg}, {reg}, t0")
+    }
+}
+
+/// Repeat an instruction body over the first `n` cells of the vector register, which `t3`
+/// points to, and the cells under the tape pointer, which `t4` points to.
+/// The register is kept in sync with the first cell.
+fn vector(n: usize, body: &str) -> String {
+    format!(
+        "sd s2, 0(s4)
+mv t3, s4
+mv t4, s1
+li t5, {n}
+1:
+{body}
+addi t3, t3, 8
+addi t4, t4, 8
+addi t5, t5, -1
+bnez t5, 1b
+ld s2, 0(s4)"
+    )
+}
+
+/// Call a C function of one or two floats for each of the first `n` cells of the vector
+/// register (and the cells under the tape pointer). The loop uses the callee-saved `s6`-`s8`.
+fn float_call(n: usize, f: &str, binary: bool) -> String {
+    let second = if binary { "fld fa1, 0(s7)\n" } else { "" };
+    if n == 1 {
+        let second = if binary { "fld fa1, 0(s1)\n" } else { "" };
+        return format!("fmv.d.x fa0, s2\n{second}call {f}\nfmv.x.d s2, fa0");
+    }
+    format!(
+        "sd s2, 0(s4)
+mv s6, s4
+mv s7, s1
+li s8, {n}
+1:
+fld fa0, 0(s6)
+{second}call {f}
+fsd fa0, 0(s6)
+addi s6, s6, 8
+addi s7, s7, 8
+addi s8, s8, -1
+bnez s8, 1b
+ld s2, 0(s4)"
+    )
+}
+
+/// A floating point instruction on the register and the cell under the tape pointer,
+/// or on each of the first `n` cells of the vector register and the tape.
+fn float_binop(n: usize, op: &str) -> String {
+    if n == 1 {
+        format!("fmv.d.x ft0, s2\nfld ft1, 0(s1)\n{op} ft0, ft0, ft1\nfmv.x.d s2, ft0")
+    } else {
+        vector(
+            n,
+            &format!("fld ft0, 0(t3)\nfld ft1, 0(t4)\n{op} ft0, ft0, ft1\nfsd ft0, 0(t3)"),
+        )
+    }
+}
+
+/// An integer instruction on the register and the cell under the tape pointer,
+/// or on each of the first `n` cells of the vector register and the tape.
+fn binop(n: usize, op: &str) -> String {
+    if n == 1 {
+        format!("ld t0, 0(s1)\n{op} s2, s2, t0")
+    } else {
+        vector(
+            n,
+            &format!("ld t0, 0(t3)\nld t1, 0(t4)\n{op} t0, t0, t1\nsd t0, 0(t3)"),
+        )
+    }
+}
+
+/// An integer instruction on the register, or on each of the first `n` cells of the vector register.
+fn unop(n: usize, op: &str) -> String {
+    if n == 1 {
+        op.replace("{}", "s2")
+    } else {
+        vector(
+            n,
+            &format!("ld t0, 0(t3)\n{}\nsd t0, 0(t3)", op.replace("{}", "t0")),
+        )
+    }
+}
+
+impl Architecture for RiscV64 {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+                | OutputMode::Exit
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => match vm::comment_line(text) {
+                Some((line, filename)) => self.lines.directive(line, filename),
+                None => format!("# {}", text.replace('\n', "\n# ").replace('\r', "")),
+            },
+            // Conditional branches only reach 4KiB, so they skip over jumps to the labels.
+            CoreOp::While => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!(".Lwhile{n}:\nbnez s2, 1f\nj .Lend{n}\n1:", n = self.blocks)
+            }
+            CoreOp::If => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!("bnez s2, 1f\nj .Lelse{}\n1:", self.blocks)
+            }
+            CoreOp::Else => {
+                let n = self.open_blocks.last().copied().unwrap_or_default();
+                format!("j .Lend{n}\n.Lelse{n}:")
+            }
+            CoreOp::Set(n) => {
+                let mut result = format!("li s2, {}", n[0]);
+                for (i, val) in n.iter().enumerate().skip(1) {
+                    result += &format!("\nli t0, {val}\nli t1, {}\nadd t1, s4, t1\nsd t0, 0(t1)", i * 8);
+                }
+                result
+            }
+            CoreOp::Call => "slli t0, s2, 3\nlla t1, funs\nadd t0, t0, t1\nld t0, 0(t0)\njalr t0".to_string(),
+            CoreOp::Return => RETURN.to_string(),
+            CoreOp::Store(1) => "sd s2, 0(s1)".to_string(),
+            CoreOp::Load(1) => "ld s2, 0(s1)".to_string(),
+            CoreOp::Store(n) => vector(*n, "ld t0, 0(t3)\nsd t0, 0(t4)"),
+            CoreOp::Load(n) => vector(*n, "ld t0, 0(t4)\nsd t0, 0(t3)"),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "{}\nlla t0, refs\nbne s3, t0, 1f\nsub t0, s1, s5\nli t1, {}\nbltu t0, t1, 1f\ntail tape_out_of_bounds\n1:",
+                add_imm("s1", n * 8),
+                self.config.tape_bytes()
+            ),
+            CoreOp::Move(n) => add_imm("s1", n * 8),
+            CoreOp::Where => "mv s2, s1".to_string(),
+            CoreOp::Deref => "sd s1, 0(s3)\naddi s3, s3, 8\nld s1, 0(s1)".to_string(),
+            CoreOp::Refer => "addi s3, s3, -8\nld s1, 0(s3)".to_string(),
+            CoreOp::Offset(n, 1) => add_imm("s2", n * 8),
+            CoreOp::Offset(n, size) => vector(
+                *size,
+                &format!("ld t1, 0(t3)\nli t0, {}\nadd t1, t1, t0\nsd t1, 0(t3)", n * 8),
+            ),
+
+            CoreOp::Index(1) => "ld t0, 0(s1)\nslli t0, t0, 3\nadd s2, s2, t0".to_string(),
+            CoreOp::Index(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nslli t1, t1, 3\nadd t0, t0, t1\nsd t0, 0(t3)",
+            ),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            CoreOp::Add(n) => binop(*n, "add"),
+            CoreOp::Sub(n) => binop(*n, "sub"),
+            CoreOp::Mul(n) => binop(*n, "mul"),
+            // `div` by zero gives -1, so zero divisors are skipped. The smallest integer
+            // divided by -1 already wraps, and `rem` by zero already gives the dividend.
+            CoreOp::Div(1) => "ld t0, 0(s1)\nbeqz t0, 2f\ndiv s2, s2, t0\n2:".to_string(),
+            CoreOp::Div(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nbeqz t1, 2f\ndiv t0, t0, t1\nsd t0, 0(t3)\n2:",
+            ),
+            CoreOp::Rem(n) => binop(*n, "rem"),
+            CoreOp::Neg(n) => unop(*n, "neg {}, {}"),
+            CoreOp::Inc(n) => unop(*n, "addi {}, {}, 1"),
+            CoreOp::Dec(n) => unop(*n, "addi {}, {}, -1"),
+
+            CoreOp::Swap(1) => "ld t0, 0(s1)\nsd s2, 0(s1)\nmv s2, t0".to_string(),
+            CoreOp::Swap(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nsd t1, 0(t3)\nsd t0, 0(t4)",
+            ),
+
+            CoreOp::And(1) => "ld t0, 0(s1)\nsnez t0, t0\nsnez s2, s2\nand s2, s2, t0".to_string(),
+            CoreOp::And(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nsnez t0, t0\nsnez t1, t1\nand t0, t0, t1\nsd t0, 0(t3)",
+            ),
+
+            CoreOp::Or(1) => "ld t0, 0(s1)\nor s2, s2, t0\nsnez s2, s2".to_string(),
+            CoreOp::Or(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nor t0, t0, t1\nsnez t0, t0\nsd t0, 0(t3)",
+            ),
+
+            CoreOp::Not(n) => unop(*n, "seqz {}, {}"),
+
+            CoreOp::BitwiseNand(1) => "ld t0, 0(s1)\nand s2, s2, t0\nnot s2, s2".to_string(),
+            CoreOp::BitwiseNand(n) => vector(
+                *n,
+                "ld t0, 0(t3)\nld t1, 0(t4)\nand t0, t0, t1\nnot t0, t0\nsd t0, 0(t3)",
+            ),
+
+            CoreOp::BitwiseAnd(n) => binop(*n, "and"),
+            CoreOp::BitwiseOr(n) => binop(*n, "or"),
+            CoreOp::BitwiseXor(n) => binop(*n, "xor"),
+            CoreOp::BitwiseNot(n) => unop(*n, "not {}, {}"),
+
+            CoreOp::LeftShift(n) => binop(*n, "sll"),
+            CoreOp::LogicalRightShift(n) => binop(*n, "srl"),
+            CoreOp::ArithmeticRightShift(n) => binop(*n, "sra"),
+
+            CoreOp::IsNonNegative(n) => unop(*n, "slti {}, {}, 0\nxori {}, {}, 1"),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for RISC-V target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => format!("call __{}", ffi.name),
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => {
+                let mut result = format!("li s2, {}", n[0].to_bits() as i64);
+                for (i, val) in n.iter().enumerate().skip(1) {
+                    result += &format!(
+                        "\nli t0, {}\nli t1, {}\nadd t1, s4, t1\nsd t0, 0(t1)",
+                        val.to_bits() as i64,
+                        i * 8
+                    );
+                }
+                result
+            }
+
+            StandardOp::ToInt(1) => "fmv.d.x ft0, s2\nfcvt.l.d s2, ft0, rtz".to_string(),
+            StandardOp::ToInt(n) => vector(*n, "fld ft0, 0(t3)\nfcvt.l.d t0, ft0, rtz\nsd t0, 0(t3)"),
+
+            StandardOp::ToFloat(1) => "fcvt.d.l ft0, s2\nfmv.x.d s2, ft0".to_string(),
+            StandardOp::ToFloat(n) => vector(*n, "ld t0, 0(t3)\nfcvt.d.l ft0, t0\nfsd ft0, 0(t3)"),
+
+            StandardOp::ACos(n) => float_call(*n, "acos", false),
+            StandardOp::ASin(n) => float_call(*n, "asin", false),
+            StandardOp::ATan(n) => float_call(*n, "atan", false),
+            StandardOp::Sin(n) => float_call(*n, "sin", false),
+            StandardOp::Cos(n) => float_call(*n, "cos", false),
+            StandardOp::Tan(n) => float_call(*n, "tan", false),
+            StandardOp::Rem(n) => float_call(*n, "fmod", true),
+            StandardOp::Pow(n) => float_call(*n, "pow", true),
+
+            StandardOp::Add(n) => float_binop(*n, "fadd.d"),
+            StandardOp::Sub(n) => float_binop(*n, "fsub.d"),
+            StandardOp::Mul(n) => float_binop(*n, "fmul.d"),
+            StandardOp::Div(n) => float_binop(*n, "fdiv.d"),
+
+            StandardOp::Neg(1) => "fmv.d.x ft0, s2\nfneg.d ft0, ft0\nfmv.x.d s2, ft0".to_string(),
+            StandardOp::Neg(n) => vector(*n, "fld ft0, 0(t3)\nfneg.d ft0, ft0\nfsd ft0, 0(t3)"),
+
+            StandardOp::IsNonNegative(1) => "fmv.d.x ft0, s2\nfmv.d.x ft1, zero\nfle.d s2, ft1, ft0".to_string(),
+            StandardOp::IsNonNegative(n) => vector(
+                *n,
+                "fld ft0, 0(t3)\nfmv.d.x ft1, zero\nfle.d t0, ft1, ft0\nsd t0, 0(t3)",
+            ),
+
+            // Every allocation is preceded by a cell holding its size, like in the C target.
+            StandardOp::Alloc => "slli a0, s2, 3\naddi a0, a0, 8\ncall malloc\nsd s2, 0(a0)\nlla t0, heap_used\nld t1, 0(t0)\nadd t1, t1, s2\nsd t1, 0(t0)\naddi s2, a0, 8".to_string(),
+            StandardOp::Free => "ld t2, -8(s2)\nlla t0, heap_used\nld t1, 0(t0)\nsub t1, t1, t2\nsd t1, 0(t0)\naddi a0, s2, -8\ncall free".to_string(),
+            StandardOp::Realloc => "ld t2, -8(s2)\nlla t0, heap_used\nld t1, 0(t0)\nsub t1, t1, t2\nsd t1, 0(t0)\naddi a0, s2, -8\nld a1, 0(s1)\nslli a1, a1, 3\naddi a1, a1, 8\ncall realloc\nld t2, 0(s1)\nsd t2, 0(a0)\nlla t0, heap_used\nld t1, 0(t0)\nadd t1, t1, t2\nsd t1, 0(t0)\naddi s2, a0, 8".to_string(),
+            StandardOp::HeapUsed => "lla t0, heap_used\nld s2, 0(t0)".to_string(),
+            // Freed memory is returned to `malloc`, so the runtime never holds onto any.
+            StandardOp::HeapFree => "li s2, 0".to_string(),
+
+            StandardOp::AtomicLoad => "amoadd.d.aqrl t0, zero, (s2)\nmv s2, t0".to_string(),
+            StandardOp::AtomicStore => "ld t0, 0(s1)\namoswap.d.aqrl zero, t0, (s2)".to_string(),
+            StandardOp::AtomicAdd => "ld t0, 0(s1)\namoadd.d.aqrl t1, t0, (s2)\nmv s2, t1".to_string(),
+            // Store the value found back over the expected value, like `__atomic_compare_exchange_n`.
+            StandardOp::AtomicCompareSwap => "ld t0, 0(s1)\nld t1, 8(s1)\n1:\nlr.d.aqrl t2, (s2)\nbne t2, t0, 2f\nsc.d.aqrl t3, t1, (s2)\nbnez t3, 1b\n2:\nsd t2, 0(s1)\nsub t2, t2, t0\nseqz s2, t2".to_string(),
+            _ => return Err(format!("Invalid standard op for RISC-V target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => match self.function.take() {
+                // Debuggers find the function a line is in by the function's size.
+                Some(name) => format!("{RETURN}\n.size {name}, .-{name}"),
+                None => RETURN.to_string(),
+            },
+            (CoreOp::While, _) => {
+                let n = self.open_blocks.pop().unwrap_or_default();
+                format!("j .Lwhile{n}\n.Lend{n}:")
+            }
+            (CoreOp::If, _) => format!(".Lelse{}:", self.open_blocks.pop().unwrap_or_default()),
+            (CoreOp::Else, _) => format!(".Lend{}:", self.open_blocks.pop().unwrap_or_default()),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        let name = function_name(label_id, label);
+        let header = format!("\n.type {name}, @function\n{name}:\naddi sp, sp, -16\nsd ra, 8(sp)");
+        self.function = Some(name);
+        header
+    }
+
+    fn name(&self) -> &str {
+        "RISC-V"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok(format!(
+                "call {}\nmv s2, a0\nli t0, -1\nbne s2, t0, 1f\nli s2, 0\n1:",
+                self.config.hooks.get_char
+            )),
+            InputMode::StdinInt => Ok(
+                "lla a0, scan_int\nlla a1, tmp_cell\ncall scanf\nlla t0, tmp_cell\nld s2, 0(t0)"
+                    .to_string(),
+            ),
+            InputMode::StdinFloat => Ok(
+                "lla a0, scan_float\nlla a1, tmp_cell\ncall scanf\nlla t0, tmp_cell\nld s2, 0(t0)"
+                    .to_string(),
+            ),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        // Variadic floats are passed in integer registers, so every value is printed from one.
+        let stderr = "la t0, stderr\nld a0, 0(t0)";
+        let hooks = &self.config.hooks;
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(format!("mv a0, s2\ncall {}", hooks.put_char)),
+            OutputMode::StdoutInt => Ok("lla a0, fmt_int\nmv a1, s2\ncall printf".to_string()),
+            OutputMode::StdoutFloat => Ok("lla a0, fmt_float\nmv a1, s2\ncall printf".to_string()),
+            OutputMode::StderrChar => Ok(format!("mv a0, s2\ncall {}", hooks.eput_char)),
+            OutputMode::StderrInt => Ok(format!(
+                "{stderr}\nlla a1, fmt_int\nmv a2, s2\ncall fprintf"
+            )),
+            OutputMode::StderrFloat => Ok(format!(
+                "{stderr}\nlla a1, fmt_float\nmv a2, s2\ncall fprintf"
+            )),
+            OutputMode::Exit => Ok("mv a0, s2\ncall exit".to_string()),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok(
+            "lla t0, ffi_ptr\nld t1, 0(t0)\nld s2, 0(t1)\naddi t1, t1, -8\nsd t1, 0(t0)"
+                .to_string(),
+        )
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok("lla t0, ffi_ptr\nld t1, 0(t0)\naddi t1, t1, 8\nsd t1, 0(t0)\nsd s2, 0(t1)".to_string())
+    }
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let mut result = String::from(
+            "# Assemble and link with `riscv64-linux-gnu-gcc output.s -o output -lm`.
+
+# The registers of the assembly language, at their offsets from the start of the tape in s5.
+",
+        );
+        let registers = [
+            ("SP", asm::SP),
+            ("FP", asm::FP),
+            ("GP", asm::GP),
+            ("A", asm::A),
+            ("B", asm::B),
+            ("C", asm::C),
+            ("D", asm::D),
+            ("E", asm::E),
+            ("F", asm::F),
+        ];
+        for (name, register) in registers {
+            if let Location::Address(addr) = register {
+                result += &format!(".set {name}_REG, {}\n", addr * 8);
+            }
+        }
+        result += r#"
+.bss
+.align 4
+vector_reg: .zero 8192
+refs: .zero 8192
+funs: .zero 80000
+ffi_channel: .zero 2048
+heap_used: .zero 8
+tmp_cell: .zero 8
+
+.data
+.align 3
+.globl ffi_ptr
+ffi_ptr: .dword ffi_channel
+
+# The stack doesn't need to be executable.
+.section .note.GNU-stack, "", @progbits
+
+.section .rodata
+fmt_int: .string "%lld"
+fmt_float: .string "%.1f"
+scan_int: .string "%ld"
+scan_float: .string "%lf"
+"#;
+        result += &format!(
+            r#"tape_bounds_error: .string "{TAPE_BOUNDS_ERROR}\n"
+
+.text
+# Write a character to the standard error, the default hook for it.
+sage_eputchar:
+	la t0, stderr
+	ld a1, 0(t0)
+	tail fputc
+
+# Report the tape pointer moving off the tape, and exit.
+tape_out_of_bounds:
+	andi sp, sp, -16
+	la t0, stderr
+	ld a1, 0(t0)
+	lla a0, tape_bounds_error
+	call fputs
+	li a0, 1
+	call exit
+"#
+        );
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let mut result = format!(
+            r#"
+.globl main
+.type main, @function
+main:
+	addi sp, sp, -80
+	sd ra, 72(sp)
+	sd s1, 64(sp)
+	sd s2, 56(sp)
+	sd s3, 48(sp)
+	sd s4, 40(sp)
+	sd s5, 32(sp)
+	sd s6, 24(sp)
+	sd s7, 16(sp)
+	sd s8, 8(sp)
+	# Map the tape: PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE.
+	li a0, 0
+	li a1, {tape_bytes}
+	li a2, 3
+	li a3, 0x4022
+	li a4, -1
+	li a5, 0
+	call mmap
+	li t0, -1
+	bne a0, t0, 1f
+	li a0, 1
+	call exit
+1:
+	mv s1, a0
+	mv s5, a0
+	li s2, 0
+	lla s3, refs
+	lla s4, vector_reg
+"#,
+            tape_bytes = self.config.tape_bytes()
+        );
+        for (fun, label) in funs {
+            result += &format!(
+                "\tlla t0, {}\n\tlla t1, funs+{}\n\tsd t0, 0(t1)\n",
+                function_name(fun as usize, label.as_deref()),
+                fun * 8
+            )
+        }
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some(
+            "li a0, 0
+	ld ra, 72(sp)
+	ld s1, 64(sp)
+	ld s2, 56(sp)
+	ld s3, 48(sp)
+	ld s4, 40(sp)
+	ld s5, 32(sp)
+	ld s6, 24(sp)
+	ld s7, 16(sp)
+	ld s8, 8(sp)
+	addi sp, sp, 80
+	ret
+.size main, .-main
+"
+            .to_string(),
+        )
+    }
+}
+
+impl CompiledTarget for RiscV64 {}
+
+impl Target for RiscV64 {
+    fn extension(&self) -> &str {
+        "s"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -3,7 +3,7 @@
 //! An implementation of the virtual machine for the C language.
 //!
 //! This allows the virtual machine to target C programs.
-use super::{Architecture, CompiledTarget};
+use super::{Architecture, CompiledTarget, Target, TargetConfig};
 use crate::{
     side_effects::{Input, Output},
     vm::{CoreOp, StandardOp},
@@ -12,11 +12,11 @@ use crate::{
 /// The type for the C target which implements the `Target` trait.
 /// This allows the compiler to target the C language.
 #[derive(Default)]
-pub struct SageLisp(sage_lisp::Env);
+pub struct SageLisp(sage_lisp::Env, TargetConfig);
 
 impl SageLisp {
     pub fn new(env: sage_lisp::Env) -> Self {
-        Self(env)
+        Self(env, TargetConfig::default())
     }
 }
 
@@ -41,7 +41,7 @@ impl Architecture for SageLisp {
         String::new()
     }
 
-    fn declare_proc(&mut self, _label_id: usize) -> String {
+    fn declare_proc(&mut self, _label_id: usize, _label: Option<&str>) -> String {
         String::new()
     }
 
@@ -73,7 +73,7 @@ impl Architecture for SageLisp {
         Default::default()
     }
 
-    fn post_funs(&self, _funs: Vec<i32>) -> Option<String> {
+    fn post_funs(&self, _funs: Vec<(i32, Option<String>)>) -> Option<String> {
         Default::default()
     }
 
@@ -111,3 +111,17 @@ impl CompiledTarget for SageLisp {
         Ok(result.to_string())
     }
 }
+
+impl Target for SageLisp {
+    fn extension(&self) -> &str {
+        "txt"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.1
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.1
+    }
+}
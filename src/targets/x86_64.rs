@@ -0,0 +1,640 @@
+//! # x86-64 Target
+//!
+//! An implementation of the virtual machine for x86-64 Linux, which emits
+//! GNU assembler (`.s`) files following the System V ABI. The output uses
+//! libc for I/O and the heap, and libm for the floating point math functions:
+//!
+//! ```bash
+//! $ gcc output.s -o output -lm
+//! ```
+//!
+//! The machine's state is kept in callee-saved registers, so that calls to C don't disturb it:
+//!
+//! | Register | Contents                                                   |
+//! |----------|------------------------------------------------------------|
+//! | `%rbx`   | The tape pointer.                                          |
+//! | `%r12`   | The register (the first cell of the vector register).      |
+//! | `%r13`   | The top of the stack of pointers saved by `Deref`.         |
+//! | `%r14`   | The vector register, which is in memory.                   |
+//! | `%r15`   | The start of the tape, which is mapped with `mmap`.        |
+//! | `%rbp`   | The stack pointer, saved while calling C functions.        |
+//!
+//! The registers of the assembly language (like `SP` and `A`) can be addressed with
+//! pointers in the virtual machine code, so they stay on the tape. They are at fixed
+//! offsets from `%r15`, which are named (like `A_REG`) to inspect them in a debugger.
+//! The lines of the source program are marked with `.loc` directives, and each function
+//! with its size, so the assembler writes DWARF line tables which `gdb` can step through.
+//!
+//! Foreign functions are called as `__name`, and they communicate with the program
+//! through the global `ffi_ptr`, which is a `cell *` like in the C target.
+//!
+//! The size of the tape, bounds checking, and the functions used to read and write
+//! characters are set by the target's [`TargetConfig`]. With bounds checking, moving
+//! the tape pointer off the tape (outside of a `Deref`) exits with an error.
+use super::{
+    c::function_name, Architecture, CompiledTarget, LineDirectives, Target, TargetConfig,
+    TAPE_BOUNDS_ERROR,
+};
+use crate::{
+    asm::{self, Location},
+    side_effects::{Input, InputMode, Output, OutputMode},
+    vm::{self, CoreOp, StandardOp},
+};
+
+/// The type for the x86-64 target which implements the `Target` trait.
+/// This allows the compiler to target native code without a C compiler.
+pub struct X86_64 {
+    /// The number of loops and conditionals compiled so far, to label them uniquely.
+    blocks: usize,
+    /// The labels of the loops and conditionals being compiled.
+    open_blocks: Vec<usize>,
+    /// The source files named by line directives.
+    lines: LineDirectives,
+    /// The name of the function being compiled, to mark its size at its end.
+    function: Option<String>,
+    /// The configuration of the runtime.
+    config: TargetConfig,
+}
+
+impl Default for X86_64 {
+    fn default() -> Self {
+        Self {
+            blocks: 0,
+            open_blocks: Vec::new(),
+            lines: LineDirectives::default(),
+            function: None,
+            config: TargetConfig::default(),
+        }
+    }
+}
+
+/// Repeat an instruction body over the first `n` cells of the vector register,
+/// indexed by `%rsi`. The register is kept in sync with the first cell.
+fn vector(n: usize, body: &str) -> String {
+    format!(
+        "mov %r12, (%r14)
+xor %esi, %esi
+1:
+{body}
+inc %rsi
+cmp ${n}, %rsi
+jb 1b
+mov (%r14), %r12"
+    )
+}
+
+/// Call a C function of one float for each of the first `n` cells of the vector register.
+fn float_call(n: usize, f: &str) -> String {
+    if n == 1 {
+        return format!("movq %r12, %xmm0\nccall {f}\nmovq %xmm0, %r12");
+    }
+    let mut result = String::from("mov %r12, (%r14)");
+    for i in 0..n {
+        result += &format!(
+            "\nmovsd {}(%r14), %xmm0\nccall {f}\nmovsd %xmm0, {}(%r14)",
+            i * 8,
+            i * 8
+        );
+    }
+    result + "\nmov (%r14), %r12"
+}
+
+/// Call a C function of two floats for each of the first `n` cells of the vector register,
+/// and the cells under the tape pointer.
+fn float_call2(n: usize, f: &str) -> String {
+    if n == 1 {
+        return format!("movq %r12, %xmm0\nmovsd (%rbx), %xmm1\nccall {f}\nmovq %xmm0, %r12");
+    }
+    let mut result = String::from("mov %r12, (%r14)");
+    for i in 0..n {
+        result += &format!(
+            "\nmovsd {}(%r14), %xmm0\nmovsd {}(%rbx), %xmm1\nccall {f}\nmovsd %xmm0, {}(%r14)",
+            i * 8,
+            i * 8,
+            i * 8
+        );
+    }
+    result + "\nmov (%r14), %r12"
+}
+
+impl Architecture for X86_64 {
+    fn supports_input(&self, i: &Input) -> bool {
+        matches!(
+            i.mode,
+            InputMode::StdinChar | InputMode::StdinFloat | InputMode::StdinInt
+        )
+    }
+
+    fn supports_output(&self, o: &Output) -> bool {
+        matches!(
+            o.mode,
+            OutputMode::StdoutChar
+                | OutputMode::StdoutFloat
+                | OutputMode::StdoutInt
+                | OutputMode::StderrChar
+                | OutputMode::StderrFloat
+                | OutputMode::StderrInt
+                | OutputMode::Exit
+        )
+    }
+
+    fn op(&mut self, op: &CoreOp) -> String {
+        match op {
+            CoreOp::Comment(text) => match vm::comment_line(text) {
+                Some((line, filename)) => self.lines.directive(line, filename),
+                None => format!("# {}", text.replace('\n', "\n# ").replace('\r', "")),
+            },
+            CoreOp::While => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!(
+                    ".Lwhile{n}:\ntest %r12, %r12\njz .Lend{n}",
+                    n = self.blocks
+                )
+            }
+            CoreOp::If => {
+                self.blocks += 1;
+                self.open_blocks.push(self.blocks);
+                format!("test %r12, %r12\njz .Lelse{}", self.blocks)
+            }
+            CoreOp::Else => {
+                let n = self.open_blocks.last().copied().unwrap_or_default();
+                format!("jmp .Lend{n}\n.Lelse{n}:")
+            }
+            CoreOp::Set(n) => {
+                let mut result = format!("mov ${}, %r12", n[0]);
+                for (i, val) in n.iter().enumerate().skip(1) {
+                    result += &format!("\nmov ${val}, %rax\nmov %rax, {}(%r14)", i * 8);
+                }
+                result
+            }
+            CoreOp::Call => "lea funs(%rip), %rax\ncall *(%rax,%r12,8)".to_string(),
+            CoreOp::Return => "ret".to_string(),
+            CoreOp::Store(1) => "mov %r12, (%rbx)".to_string(),
+            CoreOp::Load(1) => "mov (%rbx), %r12".to_string(),
+            CoreOp::Store(n) => format!(
+                "mov %r12, (%r14)\nmov %rbx, %rdi\nmov %r14, %rsi\nmov ${n}, %ecx\nrep movsq"
+            ),
+            CoreOp::Load(n) => format!(
+                "mov %r14, %rdi\nmov %rbx, %rsi\nmov ${n}, %ecx\nrep movsq\nmov (%r14), %r12"
+            ),
+
+            // Outside of a `Deref`, the tape pointer must stay on the tape.
+            CoreOp::Move(n) if self.config.bounds_check => format!(
+                "lea {}(%rbx), %rbx\nlea refs(%rip), %rax\ncmp %rax, %r13\njne 1f\nmov %rbx, %rax\nsub %r15, %rax\nmov $TAPE_BYTES, %rcx\ncmp %rcx, %rax\njae tape_out_of_bounds\n1:",
+                n * 8
+            ),
+            CoreOp::Move(n) => format!("lea {}(%rbx), %rbx", n * 8),
+            CoreOp::Where => "mov %rbx, %r12".to_string(),
+            CoreOp::Deref => "mov %rbx, (%r13)\nadd $8, %r13\nmov (%rbx), %rbx".to_string(),
+            CoreOp::Refer => "sub $8, %r13\nmov (%r13), %rbx".to_string(),
+            CoreOp::Offset(n, 1) => format!("lea {}(%r12), %r12", n * 8),
+            CoreOp::Offset(n, size) => vector(*size, &format!("addq ${}, (%r14,%rsi,8)", n * 8)),
+
+            CoreOp::Index(1) => "mov (%rbx), %rax\nlea (%r12,%rax,8), %r12".to_string(),
+            CoreOp::Index(n) => vector(
+                *n,
+                "mov (%rbx,%rsi,8), %rax\nshl $3, %rax\nadd %rax, (%r14,%rsi,8)",
+            ),
+
+            // See `CoreOp` for how the integer arithmetic overflows and divides by zero.
+            CoreOp::Add(1) => "add (%rbx), %r12".to_string(),
+            CoreOp::Add(n) => vector(*n, "mov (%rbx,%rsi,8), %rax\nadd %rax, (%r14,%rsi,8)"),
+
+            CoreOp::Sub(1) => "sub (%rbx), %r12".to_string(),
+            CoreOp::Sub(n) => vector(*n, "mov (%rbx,%rsi,8), %rax\nsub %rax, (%r14,%rsi,8)"),
+
+            CoreOp::Mul(1) => "imul (%rbx), %r12".to_string(),
+            CoreOp::Mul(n) => vector(
+                *n,
+                "mov (%r14,%rsi,8), %rax\nimul (%rbx,%rsi,8), %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            // `idiv` traps when dividing by zero, and on the smallest integer divided by -1.
+            CoreOp::Div(1) => "mov %r12, %rax\nmov (%rbx), %rcx\ncall sage_div\nmov %rax, %r12".to_string(),
+            CoreOp::Div(n) => vector(
+                *n,
+                "mov (%r14,%rsi,8), %rax\nmov (%rbx,%rsi,8), %rcx\ncall sage_div\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::Rem(1) => "mov %r12, %rax\nmov (%rbx), %rcx\ncall sage_rem\nmov %rax, %r12".to_string(),
+            CoreOp::Rem(n) => vector(
+                *n,
+                "mov (%r14,%rsi,8), %rax\nmov (%rbx,%rsi,8), %rcx\ncall sage_rem\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::Neg(1) => "neg %r12".to_string(),
+            CoreOp::Neg(n) => vector(*n, "negq (%r14,%rsi,8)"),
+
+            CoreOp::Inc(1) => "inc %r12".to_string(),
+            CoreOp::Inc(n) => vector(*n, "incq (%r14,%rsi,8)"),
+
+            CoreOp::Dec(1) => "dec %r12".to_string(),
+            CoreOp::Dec(n) => vector(*n, "decq (%r14,%rsi,8)"),
+
+            CoreOp::Swap(1) => "mov (%rbx), %rax\nmov %r12, (%rbx)\nmov %rax, %r12".to_string(),
+            CoreOp::Swap(n) => vector(
+                *n,
+                "mov (%rbx,%rsi,8), %rax\nmov (%r14,%rsi,8), %rdx\nmov %rdx, (%rbx,%rsi,8)\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::And(1) => "test %r12, %r12\nsetne %al\ncmpq $0, (%rbx)\nsetne %dl\nand %dl, %al\nmovzbq %al, %r12".to_string(),
+            CoreOp::And(n) => vector(
+                *n,
+                "cmpq $0, (%r14,%rsi,8)\nsetne %al\ncmpq $0, (%rbx,%rsi,8)\nsetne %dl\nand %dl, %al\nmovzbq %al, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::Or(1) => "or (%rbx), %r12\nsetne %al\nmovzbq %al, %r12".to_string(),
+            CoreOp::Or(n) => vector(
+                *n,
+                "mov (%rbx,%rsi,8), %rax\nor (%r14,%rsi,8), %rax\nsetne %al\nmovzbq %al, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::Not(1) => "test %r12, %r12\nsete %al\nmovzbq %al, %r12".to_string(),
+            CoreOp::Not(n) => vector(
+                *n,
+                "cmpq $0, (%r14,%rsi,8)\nsete %al\nmovzbq %al, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::BitwiseNand(1) => "and (%rbx), %r12\nnot %r12".to_string(),
+            CoreOp::BitwiseNand(n) => vector(
+                *n,
+                "mov (%rbx,%rsi,8), %rax\nand (%r14,%rsi,8), %rax\nnot %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::BitwiseAnd(1) => "and (%rbx), %r12".to_string(),
+            CoreOp::BitwiseAnd(n) => vector(*n, "mov (%rbx,%rsi,8), %rax\nand %rax, (%r14,%rsi,8)"),
+
+            CoreOp::BitwiseOr(1) => "or (%rbx), %r12".to_string(),
+            CoreOp::BitwiseOr(n) => vector(*n, "mov (%rbx,%rsi,8), %rax\nor %rax, (%r14,%rsi,8)"),
+
+            CoreOp::BitwiseXor(1) => "xor (%rbx), %r12".to_string(),
+            CoreOp::BitwiseXor(n) => vector(*n, "mov (%rbx,%rsi,8), %rax\nxor %rax, (%r14,%rsi,8)"),
+
+            CoreOp::BitwiseNot(1) => "not %r12".to_string(),
+            CoreOp::BitwiseNot(n) => vector(*n, "notq (%r14,%rsi,8)"),
+
+            CoreOp::LeftShift(1) => "mov (%rbx), %rcx\nshl %cl, %r12".to_string(),
+            CoreOp::LeftShift(n) => vector(*n, "mov (%rbx,%rsi,8), %rcx\nshlq %cl, (%r14,%rsi,8)"),
+
+            CoreOp::LogicalRightShift(1) => "mov (%rbx), %rcx\nshr %cl, %r12".to_string(),
+            CoreOp::LogicalRightShift(n) => vector(*n, "mov (%rbx,%rsi,8), %rcx\nshrq %cl, (%r14,%rsi,8)"),
+
+            CoreOp::ArithmeticRightShift(1) => "mov (%rbx), %rcx\nsar %cl, %r12".to_string(),
+            CoreOp::ArithmeticRightShift(n) => vector(*n, "mov (%rbx,%rsi,8), %rcx\nsarq %cl, (%r14,%rsi,8)"),
+
+            CoreOp::IsNonNegative(1) => "test %r12, %r12\nsetns %al\nmovzbq %al, %r12".to_string(),
+            CoreOp::IsNonNegative(n) => vector(
+                *n,
+                "cmpq $0, (%r14,%rsi,8)\nsetge %al\nmovzbq %al, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            CoreOp::End | CoreOp::Function | CoreOp::Put(_) | CoreOp::Get(_) => {
+                unreachable!("Invalid core op for x86-64 target")
+            }
+        }
+    }
+
+    fn std_op(&mut self, op: &StandardOp) -> Result<String, String> {
+        Ok(match op {
+            StandardOp::Call(ffi) => format!("ccall __{}", ffi.name),
+            StandardOp::Peek => self.peek()?,
+            StandardOp::Poke => self.poke()?,
+            StandardOp::Set(n) => {
+                let mut result = format!("mov ${}, %r12", n[0].to_bits() as i64);
+                for (i, val) in n.iter().enumerate().skip(1) {
+                    result += &format!(
+                        "\nmov ${}, %rax\nmov %rax, {}(%r14)",
+                        val.to_bits() as i64,
+                        i * 8
+                    );
+                }
+                result
+            }
+
+            StandardOp::ToInt(1) => "movq %r12, %xmm0\ncvttsd2si %xmm0, %r12".to_string(),
+            StandardOp::ToInt(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\ncvttsd2si %xmm0, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            StandardOp::ToFloat(1) => "cvtsi2sd %r12, %xmm0\nmovq %xmm0, %r12".to_string(),
+            StandardOp::ToFloat(n) => vector(
+                *n,
+                "cvtsi2sdq (%r14,%rsi,8), %xmm0\nmovsd %xmm0, (%r14,%rsi,8)",
+            ),
+
+            StandardOp::ACos(n) => float_call(*n, "acos"),
+            StandardOp::ASin(n) => float_call(*n, "asin"),
+            StandardOp::ATan(n) => float_call(*n, "atan"),
+            StandardOp::Sin(n) => float_call(*n, "sin"),
+            StandardOp::Cos(n) => float_call(*n, "cos"),
+            StandardOp::Tan(n) => float_call(*n, "tan"),
+            StandardOp::Rem(n) => float_call2(*n, "fmod"),
+            StandardOp::Pow(n) => float_call2(*n, "pow"),
+
+            StandardOp::Add(1) => "movq %r12, %xmm0\naddsd (%rbx), %xmm0\nmovq %xmm0, %r12".to_string(),
+            StandardOp::Add(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\naddsd (%rbx,%rsi,8), %xmm0\nmovsd %xmm0, (%r14,%rsi,8)",
+            ),
+
+            StandardOp::Sub(1) => "movq %r12, %xmm0\nsubsd (%rbx), %xmm0\nmovq %xmm0, %r12".to_string(),
+            StandardOp::Sub(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\nsubsd (%rbx,%rsi,8), %xmm0\nmovsd %xmm0, (%r14,%rsi,8)",
+            ),
+
+            StandardOp::Mul(1) => "movq %r12, %xmm0\nmulsd (%rbx), %xmm0\nmovq %xmm0, %r12".to_string(),
+            StandardOp::Mul(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\nmulsd (%rbx,%rsi,8), %xmm0\nmovsd %xmm0, (%r14,%rsi,8)",
+            ),
+
+            StandardOp::Div(1) => "movq %r12, %xmm0\ndivsd (%rbx), %xmm0\nmovq %xmm0, %r12".to_string(),
+            StandardOp::Div(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\ndivsd (%rbx,%rsi,8), %xmm0\nmovsd %xmm0, (%r14,%rsi,8)",
+            ),
+
+            // Flip the sign bit.
+            StandardOp::Neg(1) => "btc $63, %r12".to_string(),
+            StandardOp::Neg(n) => vector(*n, "btcq $63, (%r14,%rsi,8)"),
+
+            StandardOp::IsNonNegative(1) => "movq %r12, %xmm0\nxorpd %xmm1, %xmm1\nucomisd %xmm1, %xmm0\nsetae %al\nmovzbq %al, %r12".to_string(),
+            StandardOp::IsNonNegative(n) => vector(
+                *n,
+                "movsd (%r14,%rsi,8), %xmm0\nxorpd %xmm1, %xmm1\nucomisd %xmm1, %xmm0\nsetae %al\nmovzbq %al, %rax\nmov %rax, (%r14,%rsi,8)",
+            ),
+
+            // Every allocation is preceded by a cell holding its size, like in the C target.
+            StandardOp::Alloc => "mov %r12, %rdi\nshl $3, %rdi\nadd $8, %rdi\nccall malloc\nmov %r12, (%rax)\nadd %r12, heap_used(%rip)\nlea 8(%rax), %r12".to_string(),
+            StandardOp::Free => "mov -8(%r12), %rax\nsub %rax, heap_used(%rip)\nlea -8(%r12), %rdi\nccall free".to_string(),
+            StandardOp::Realloc => "mov -8(%r12), %rax\nsub %rax, heap_used(%rip)\nlea -8(%r12), %rdi\nmov (%rbx), %rsi\nshl $3, %rsi\nadd $8, %rsi\nccall realloc\nmov (%rbx), %rcx\nmov %rcx, (%rax)\nadd %rcx, heap_used(%rip)\nlea 8(%rax), %r12".to_string(),
+            StandardOp::HeapUsed => "mov heap_used(%rip), %r12".to_string(),
+            // Freed memory is returned to `malloc`, so the runtime never holds onto any.
+            StandardOp::HeapFree => "xor %r12d, %r12d".to_string(),
+
+            // Loads and locked instructions are sequentially consistent on x86-64.
+            StandardOp::AtomicLoad => "mov (%r12), %r12".to_string(),
+            StandardOp::AtomicStore => "mov (%rbx), %rax\nxchg %rax, (%r12)".to_string(),
+            StandardOp::AtomicAdd => "mov (%rbx), %rax\nlock xadd %rax, (%r12)\nmov %rax, %r12".to_string(),
+            StandardOp::AtomicCompareSwap => "mov (%rbx), %rax\nmov 8(%rbx), %rcx\nlock cmpxchg %rcx, (%r12)\nsete %dl\nmov %rax, (%rbx)\nmovzbq %dl, %r12".to_string(),
+            _ => return Err(format!("Invalid standard op for x86-64 target {op:?}")),
+        })
+    }
+
+    fn end(&mut self, matching: &CoreOp, fun: Option<usize>) -> String {
+        match (matching, fun) {
+            (CoreOp::Function, _) => match self.function.take() {
+                // Debuggers find the function a line is in by the function's size.
+                Some(name) => format!("ret\n.size {name}, .-{name}"),
+                None => "ret".to_string(),
+            },
+            (CoreOp::While, _) => {
+                let n = self.open_blocks.pop().unwrap_or_default();
+                format!("jmp .Lwhile{n}\n.Lend{n}:")
+            }
+            (CoreOp::If, _) => format!(".Lelse{}:", self.open_blocks.pop().unwrap_or_default()),
+            (CoreOp::Else, _) => format!(".Lend{}:", self.open_blocks.pop().unwrap_or_default()),
+            _ => unreachable!("Invalid matching op for end"),
+        }
+    }
+
+    fn declare_proc(&mut self, label_id: usize, label: Option<&str>) -> String {
+        let name = function_name(label_id, label);
+        let header = format!("\n.type {name}, @function\n{name}:");
+        self.function = Some(name);
+        header
+    }
+
+    fn name(&self) -> &str {
+        "x86-64"
+    }
+    fn version(&self) -> &str {
+        "1.0"
+    }
+
+    fn supports_floats(&self) -> bool {
+        true
+    }
+
+    fn get(&mut self, src: &Input) -> Result<String, String> {
+        match src.mode {
+            InputMode::StdinChar => Ok(format!("ccall {}\nmovslq %eax, %r12\nxor %eax, %eax\ncmp $-1, %r12\ncmove %rax, %r12", self.config.hooks.get_char)),
+            InputMode::StdinInt => Ok("lea scan_int(%rip), %rdi\nlea tmp_cell(%rip), %rsi\nxor %eax, %eax\nccall scanf\nmov tmp_cell(%rip), %r12".to_string()),
+            InputMode::StdinFloat => Ok("lea scan_float(%rip), %rdi\nlea tmp_cell(%rip), %rsi\nxor %eax, %eax\nccall scanf\nmov tmp_cell(%rip), %r12".to_string()),
+            _ => Err("Input not supported by this target".to_string()),
+        }
+    }
+
+    fn put(&mut self, dst: &Output) -> Result<String, String> {
+        let stderr = "mov stderr@GOTPCREL(%rip), %rdi\nmov (%rdi), %rdi";
+        let hooks = &self.config.hooks;
+        match dst.mode {
+            OutputMode::StdoutChar => Ok(format!("mov %r12, %rdi\nccall {}", hooks.put_char)),
+            OutputMode::StdoutInt => Ok("lea fmt_int(%rip), %rdi\nmov %r12, %rsi\nxor %eax, %eax\nccall printf".to_string()),
+            OutputMode::StdoutFloat => Ok("lea fmt_float(%rip), %rdi\nmovq %r12, %xmm0\nmov $1, %eax\nccall printf".to_string()),
+            OutputMode::StderrChar => Ok(format!("mov %r12, %rdi\nccall {}", hooks.eput_char)),
+            OutputMode::StderrInt => Ok(format!("{stderr}\nlea fmt_int(%rip), %rsi\nmov %r12, %rdx\nxor %eax, %eax\nccall fprintf")),
+            OutputMode::StderrFloat => Ok(format!("{stderr}\nlea fmt_float(%rip), %rsi\nmovq %r12, %xmm0\nmov $1, %eax\nccall fprintf")),
+            OutputMode::Exit => Ok("mov %r12, %rdi\nccall exit".to_string()),
+            _ => Err("Output not supported by this target".to_string()),
+        }
+    }
+    fn peek(&mut self) -> Result<String, String> {
+        Ok("mov ffi_ptr(%rip), %rax\nmov (%rax), %r12\nsubq $8, ffi_ptr(%rip)".to_string())
+    }
+    fn poke(&mut self) -> Result<String, String> {
+        Ok("addq $8, ffi_ptr(%rip)\nmov ffi_ptr(%rip), %rax\nmov %r12, (%rax)".to_string())
+    }
+    fn prelude(&self, _is_core: bool) -> Option<String> {
+        let mut result = format!(
+            r#"# Assemble and link with `gcc output.s -o output -lm`.
+.set TAPE_BYTES, {tape_bytes}
+
+# The registers of the assembly language, at their offsets from the start of the tape in %r15.
+"#,
+            tape_bytes = self.config.tape_bytes()
+        );
+        let registers = [
+            ("SP", asm::SP),
+            ("FP", asm::FP),
+            ("GP", asm::GP),
+            ("A", asm::A),
+            ("B", asm::B),
+            ("C", asm::C),
+            ("D", asm::D),
+            ("E", asm::E),
+            ("F", asm::F),
+        ];
+        for (name, register) in registers {
+            if let Location::Address(addr) = register {
+                result += &format!(".set {name}_REG, {}\n", addr * 8);
+            }
+        }
+        result += r#"
+# Call a C function with the stack aligned to 16 bytes, as the ABI requires.
+.macro ccall fn
+	mov %rsp, %rbp
+	and $-16, %rsp
+	call \fn\()@PLT
+	mov %rbp, %rsp
+.endm
+
+.bss
+.align 16
+vector_reg: .zero 8192
+refs: .zero 8192
+funs: .zero 80000
+ffi_channel: .zero 2048
+heap_used: .zero 8
+tmp_cell: .zero 8
+
+.data
+.globl ffi_ptr
+ffi_ptr: .quad ffi_channel
+
+# The stack doesn't need to be executable.
+.section .note.GNU-stack, "", @progbits
+
+.section .rodata
+fmt_int: .string "%lld"
+fmt_float: .string "%.1f"
+scan_int: .string "%ld"
+scan_float: .string "%lf"
+"#;
+        result += &format!(
+            r#"tape_bounds_error: .string "{TAPE_BOUNDS_ERROR}\n"
+
+.text
+# Write a character to the standard error, the default hook for it.
+sage_eputchar:
+	mov stderr@GOTPCREL(%rip), %rsi
+	mov (%rsi), %rsi
+	jmp fputc@PLT
+
+# Divide %rax by %rcx, leaving the quotient in %rax. A zero divisor leaves %rax unchanged,
+# and -1 negates it, since `idiv` traps on both.
+sage_div:
+	test %rcx, %rcx
+	je 1f
+	cmp $-1, %rcx
+	je 2f
+	cqo
+	idivq %rcx
+1:
+	ret
+2:
+	neg %rax
+	ret
+
+# Divide %rax by %rcx, leaving the remainder in %rax, with the same cases as `sage_div`.
+sage_rem:
+	test %rcx, %rcx
+	je 1f
+	cmp $-1, %rcx
+	je 2f
+	cqo
+	idivq %rcx
+	mov %rdx, %rax
+1:
+	ret
+2:
+	xor %eax, %eax
+	ret
+
+# Report the tape pointer moving off the tape, and exit.
+tape_out_of_bounds:
+	and $-16, %rsp
+	mov stderr@GOTPCREL(%rip), %rsi
+	mov (%rsi), %rsi
+	lea tape_bounds_error(%rip), %rdi
+	call fputs@PLT
+	mov $1, %edi
+	call exit@PLT
+"#
+        );
+        Some(result)
+    }
+
+    fn post_funs(&self, funs: Vec<(i32, Option<String>)>) -> Option<String> {
+        let mut result = String::from(
+            r#"
+.globl main
+.type main, @function
+main:
+	push %rbx
+	push %rbp
+	push %r12
+	push %r13
+	push %r14
+	push %r15
+	sub $8, %rsp
+	# Map the tape: PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE.
+	xor %edi, %edi
+	mov $TAPE_BYTES, %rsi
+	mov $3, %edx
+	mov $0x4022, %ecx
+	mov $-1, %r8d
+	xor %r9d, %r9d
+	call mmap@PLT
+	cmp $-1, %rax
+	jne 1f
+	mov $1, %edi
+	call exit@PLT
+1:
+	mov %rax, %rbx
+	mov %rax, %r15
+	xor %r12d, %r12d
+	lea refs(%rip), %r13
+	lea vector_reg(%rip), %r14
+"#,
+        );
+        for (fun, label) in funs {
+            result += &format!(
+                "\tlea {}(%rip), %rax\n\tmov %rax, funs+{}(%rip)\n",
+                function_name(fun as usize, label.as_deref()),
+                fun * 8
+            )
+        }
+        Some(result)
+    }
+
+    fn postop(&self) -> Option<String> {
+        Some("\n".to_string())
+    }
+
+    fn postlude(&self, _is_core: bool) -> Option<String> {
+        Some(
+            "xor %eax, %eax
+	add $8, %rsp
+	pop %r15
+	pop %r14
+	pop %r13
+	pop %r12
+	pop %rbp
+	pop %rbx
+	ret
+.size main, .-main
+"
+            .to_string(),
+        )
+    }
+}
+
+impl CompiledTarget for X86_64 {}
+
+impl Target for X86_64 {
+    fn extension(&self) -> &str {
+        "s"
+    }
+
+    fn config(&self) -> &TargetConfig {
+        &self.config
+    }
+
+    fn config_mut(&mut self) -> &mut TargetConfig {
+        &mut self.config
+    }
+}
@@ -0,0 +1,122 @@
+//! # Coverage
+//!
+//! The compiler marks the line of source code that each expression's instructions
+//! come from with a comment in the virtual machine code (the same comments the C
+//! target writes as `#line` directives), and the interpreter counts how many times
+//! each comment is passed while profiling a program. A [`Coverage`] maps those counts
+//! back to the lines of the source code, and writes them as an lcov report for
+//! coverage tools (like `genhtml`, or a CI service's coverage viewer).
+//!
+//! Only the lines which were compiled to instructions are reported: a procedure which
+//! is never used isn't compiled, so its lines are left out instead of counted as missed.
+//! Code without a file name, like the prelude, is left out too.
+use super::Program;
+use crate::vm::{self, CoreOp, Profile, StandardOp};
+use std::collections::BTreeMap;
+
+/// How many times each line of source code ran, in each file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// The number of times each line ran, by its line number, by the name of its file.
+    files: BTreeMap<String, BTreeMap<usize, u64>>,
+}
+
+impl Coverage {
+    /// Create an empty coverage record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lines a program ran, from its profile in the interpreter.
+    pub fn of<T>(program: &Program, profile: &Profile<T>) -> Self {
+        let comments = match program {
+            Ok(code) => code
+                .0
+                .iter()
+                .map(|op| match op {
+                    CoreOp::Comment(comment) => Some(comment.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            Err(code) => code
+                .0
+                .iter()
+                .map(|op| match op {
+                    StandardOp::CoreOp(CoreOp::Comment(comment)) => Some(comment.as_str()),
+                    _ => None,
+                })
+                .collect(),
+        };
+
+        let mut coverage = Self::new();
+        for (i, comment) in comments.into_iter().enumerate() {
+            let Some((line, Some(filename))) = comment.and_then(vm::comment_line) else {
+                continue;
+            };
+            let hits = profile.comment_hits.get(i).copied().unwrap_or(0);
+            // A line can be marked many times, like when an expression on it contains
+            // an expression on another line. The line ran as often as its most run mark.
+            let count = coverage
+                .files
+                .entry(unescape(filename))
+                .or_default()
+                .entry(line)
+                .or_default();
+            *count = (*count).max(hits);
+        }
+        coverage
+    }
+
+    /// Add the lines run in another record to this one, like those run by another test.
+    pub fn merge(&mut self, other: &Self) {
+        for (file, lines) in &other.files {
+            let counts = self.files.entry(file.clone()).or_default();
+            for (line, hits) in lines {
+                *counts.entry(*line).or_default() += hits;
+            }
+        }
+    }
+
+    /// The names of the files with lines in the record.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// The number of times each line of a file ran, in order of their line numbers.
+    /// The lines which didn't run are included, with a count of zero.
+    pub fn lines(&self, file: &str) -> Vec<(usize, u64)> {
+        self.files
+            .get(file)
+            .map(|lines| lines.iter().map(|(line, hits)| (*line, *hits)).collect())
+            .unwrap_or_default()
+    }
+
+    /// The coverage as an lcov tracefile.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (file, lines) in &self.files {
+            lcov += "TN:\n";
+            lcov += &format!("SF:{file}\n");
+            for (line, hits) in lines {
+                lcov += &format!("DA:{line},{hits}\n");
+            }
+            lcov += &format!("LF:{}\n", lines.len());
+            lcov += &format!("LH:{}\n", lines.values().filter(|hits| **hits > 0).count());
+            lcov += "end_of_record\n";
+        }
+        lcov
+    }
+}
+
+/// Undo the escaping of a file name in a line comment.
+fn unescape(filename: &str) -> String {
+    let mut result = String::new();
+    let mut chars = filename.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => result.extend(chars.next()),
+            ch => result.push(ch),
+        }
+    }
+    result
+}
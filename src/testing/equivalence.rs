@@ -0,0 +1,584 @@
+//! # Equivalence Testing
+//!
+//! Every target implements the same virtual machine, so a program should print
+//! the same output wherever it runs. This module checks that on random programs:
+//! [`RandomProgram::generate`] builds a well typed LIR program from a seed,
+//! and [`check_equivalence`] runs the programs under the interpreter and under
+//! another runner (like a target's toolchain). When their outputs differ, the
+//! program is shrunk to the smallest program which still shows the difference,
+//! by repeatedly replacing its statements and expressions with smaller ones.
+//!
+//! ```rust
+//! use sage::testing::{check_equivalence, run_interpreter};
+//!
+//! // The interpreter always agrees with itself.
+//! let result = check_equivalence(0..8, |program| run_interpreter(program).ok());
+//! assert!(result.is_ok());
+//! ```
+//!
+//! The generated programs only use integers and booleans, mutable variables,
+//! conditionals, and loops which run a fixed number of times, so they always halt.
+//! They never divide by zero, and their arithmetic wraps around like the virtual
+//! machine's, so every program has exactly one correct output.
+//!
+//! With the `fuzzing` feature, [`RandomProgram`] implements `arbitrary::Arbitrary`,
+//! so a fuzzer can search for programs instead of trying seeds in order.
+use crate::{
+    lir::{Compile, ConstExpr, Expr, Mutability, Type},
+    vm::{self, CoreInterpreter, StandardInterpreter, TestingDevice},
+};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A program compiled for the virtual machine, of the core variant or the standard variant.
+pub type Program = Result<vm::CoreProgram, vm::StandardProgram>;
+
+/// The number of cells allocated for the call stack of the generated programs.
+const CALL_STACK_SIZE: usize = 8192;
+/// The number of variables every generated program declares.
+const VARS: usize = 3;
+/// The most statements in a generated block.
+const MAX_STATEMENTS: u64 = 4;
+/// The most times a generated loop runs.
+const MAX_REPETITIONS: u64 = 3;
+/// The most blocks a generated statement is nested in.
+const MAX_BLOCK_DEPTH: usize = 3;
+/// The most expressions a generated expression is nested in.
+const MAX_EXPR_DEPTH: usize = 4;
+
+/// A pseudorandom number generator (SplitMix64), so the same seed always
+/// generates the same program.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `0` up to (but not including) `n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    /// A small integer literal.
+    fn int(&mut self) -> i64 {
+        self.below(201) as i64 - 100
+    }
+
+    /// A divisor, which is never zero.
+    fn divisor(&mut self) -> i64 {
+        self.below(9) as i64 + 1
+    }
+}
+
+/// A generated expression which evaluates to an integer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntExpr {
+    /// An integer literal.
+    Int(i64),
+    /// One of the program's variables, by its index.
+    Var(usize),
+    Neg(Box<Self>),
+    Add(Box<Self>, Box<Self>),
+    Sub(Box<Self>, Box<Self>),
+    Mul(Box<Self>, Box<Self>),
+    /// Divide by a positive constant, so the program never divides by zero.
+    Div(Box<Self>, i64),
+    /// The remainder of dividing by a positive constant.
+    Rem(Box<Self>, i64),
+    If(Box<BoolExpr>, Box<Self>, Box<Self>),
+}
+
+/// A generated expression which evaluates to a boolean.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BoolExpr {
+    Bool(bool),
+    Lt(IntExpr, IntExpr),
+    Eq(IntExpr, IntExpr),
+    Not(Box<Self>),
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+}
+
+/// A generated statement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stmt {
+    /// Print an integer on its own line.
+    PrintInt(IntExpr),
+    /// Print a boolean on its own line.
+    PrintBool(BoolExpr),
+    /// Assign to one of the program's variables, by its index.
+    Assign(usize, IntExpr),
+    If(BoolExpr, Vec<Self>, Vec<Self>),
+    /// Run some statements a fixed number of times.
+    Repeat(usize, Vec<Self>),
+}
+
+/// A randomly generated, well typed program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RandomProgram {
+    /// The initial values of the program's variables.
+    pub vars: Vec<i64>,
+    /// The statements the program runs.
+    pub body: Vec<Stmt>,
+}
+
+impl RandomProgram {
+    /// Generate a program from a seed.
+    pub fn generate(seed: u64) -> Self {
+        let mut rng = Rng(seed);
+        Self {
+            vars: (0..VARS).map(|_| rng.int()).collect(),
+            body: Stmt::generate_block(&mut rng, MAX_BLOCK_DEPTH),
+        }
+    }
+
+    /// The LIR code of the program.
+    pub fn to_lir(&self) -> Expr {
+        self.vars.iter().enumerate().rev().fold(
+            Stmt::block_to_lir(&self.body, 0),
+            |body, (i, value)| {
+                Expr::let_var(
+                    var_name(i),
+                    Mutability::Mutable,
+                    Some(Type::Int),
+                    ConstExpr::Int(*value),
+                    body,
+                )
+            },
+        )
+    }
+
+    /// Compile the program for the virtual machine.
+    pub fn compile(&self) -> Result<Program, String> {
+        let asm_code = self.to_lir().compile(false).map_err(|e| e.to_string())?;
+        match asm_code {
+            Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+            Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// The programs one step smaller than this one.
+    pub fn shrinks(&self) -> Vec<Self> {
+        let mut shrinks = Stmt::shrink_block(&self.body)
+            .into_iter()
+            .map(|body| Self {
+                vars: self.vars.clone(),
+                body,
+            })
+            .collect::<Vec<_>>();
+        for (i, value) in self.vars.iter().enumerate() {
+            if *value != 0 {
+                let mut vars = self.vars.clone();
+                vars[i] = 0;
+                shrinks.push(Self {
+                    vars,
+                    body: self.body.clone(),
+                });
+            }
+        }
+        shrinks
+    }
+}
+
+impl Display for RandomProgram {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}", self.to_lir())
+    }
+}
+
+#[cfg(feature = "fuzzing")]
+impl<'a> arbitrary::Arbitrary<'a> for RandomProgram {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::generate(u.arbitrary()?))
+    }
+}
+
+impl Stmt {
+    /// Generate a block of statements, nested in at most `depth` more blocks.
+    fn generate_block(rng: &mut Rng, depth: usize) -> Vec<Self> {
+        let len = rng.below(MAX_STATEMENTS) + 1;
+        (0..len).map(|_| Self::generate(rng, depth)).collect()
+    }
+
+    fn generate(rng: &mut Rng, depth: usize) -> Self {
+        let choices = if depth == 0 { 3 } else { 5 };
+        match rng.below(choices) {
+            0 => Self::PrintInt(IntExpr::generate(rng, MAX_EXPR_DEPTH)),
+            1 => Self::PrintBool(BoolExpr::generate(rng, MAX_EXPR_DEPTH)),
+            2 => Self::Assign(
+                rng.below(VARS as u64) as usize,
+                IntExpr::generate(rng, MAX_EXPR_DEPTH),
+            ),
+            3 => Self::If(
+                BoolExpr::generate(rng, MAX_EXPR_DEPTH),
+                Self::generate_block(rng, depth - 1),
+                Self::generate_block(rng, depth - 1),
+            ),
+            _ => Self::Repeat(
+                rng.below(MAX_REPETITIONS + 1) as usize,
+                Self::generate_block(rng, depth - 1),
+            ),
+        }
+    }
+
+    /// The LIR code of a block of statements, nested in `loops` loops.
+    fn block_to_lir(block: &[Self], loops: usize) -> Expr {
+        Expr::Many(block.iter().map(|stmt| stmt.to_lir(loops)).collect())
+    }
+
+    /// The LIR code of the statement, nested in `loops` loops.
+    fn to_lir(&self, loops: usize) -> Expr {
+        match self {
+            Self::PrintInt(e) => e.to_lir().println(),
+            Self::PrintBool(e) => e.to_lir().println(),
+            Self::Assign(var, e) => Expr::var(var_name(*var))
+                .refer(Mutability::Mutable)
+                .deref_mut(e.to_lir()),
+            Self::If(cond, then, otherwise) => cond.to_lir().if_then(
+                Self::block_to_lir(then, loops),
+                Self::block_to_lir(otherwise, loops),
+            ),
+            Self::Repeat(times, body) => {
+                // Each loop has its own counter, which the generated statements never assign to.
+                let counter = format!("i{loops}");
+                Expr::let_var(
+                    &counter,
+                    Mutability::Mutable,
+                    Some(Type::Int),
+                    ConstExpr::Int(0),
+                    Expr::var(&counter)
+                        .lt(ConstExpr::Int(*times as i64))
+                        .while_loop(Expr::Many(vec![
+                            Self::block_to_lir(body, loops + 1),
+                            Expr::var(&counter).assign("+=", ConstExpr::Int(1)),
+                        ])),
+                )
+            }
+        }
+    }
+
+    /// The blocks one step smaller than a block: without one of its statements,
+    /// with a compound statement replaced by its body, or with a smaller statement.
+    fn shrink_block(block: &[Self]) -> Vec<Vec<Self>> {
+        let mut shrinks = vec![];
+        for (i, stmt) in block.iter().enumerate() {
+            let (before, after) = (&block[..i], &block[i + 1..]);
+            shrinks.push([before, after].concat());
+            match stmt {
+                Self::If(_, then, otherwise) => {
+                    shrinks.push([before, then, after].concat());
+                    shrinks.push([before, otherwise, after].concat());
+                }
+                Self::Repeat(_, body) => shrinks.push([before, body, after].concat()),
+                _ => {}
+            }
+            for stmt in stmt.shrinks() {
+                shrinks.push([before, &[stmt], after].concat());
+            }
+        }
+        shrinks
+    }
+
+    /// The statements one step smaller than this one.
+    fn shrinks(&self) -> Vec<Self> {
+        match self {
+            Self::PrintInt(e) => e.shrinks().into_iter().map(Self::PrintInt).collect(),
+            Self::PrintBool(e) => e.shrinks().into_iter().map(Self::PrintBool).collect(),
+            Self::Assign(var, e) => e
+                .shrinks()
+                .into_iter()
+                .map(|e| Self::Assign(*var, e))
+                .collect(),
+            Self::If(cond, then, otherwise) => {
+                let mut shrinks = cond
+                    .shrinks()
+                    .into_iter()
+                    .map(|cond| Self::If(cond, then.clone(), otherwise.clone()))
+                    .collect::<Vec<_>>();
+                for then in Self::shrink_block(then) {
+                    shrinks.push(Self::If(cond.clone(), then, otherwise.clone()));
+                }
+                for otherwise in Self::shrink_block(otherwise) {
+                    shrinks.push(Self::If(cond.clone(), then.clone(), otherwise));
+                }
+                shrinks
+            }
+            Self::Repeat(times, body) => {
+                let mut shrinks = vec![];
+                if *times > 0 {
+                    shrinks.push(Self::Repeat(times - 1, body.clone()));
+                }
+                for body in Self::shrink_block(body) {
+                    shrinks.push(Self::Repeat(*times, body));
+                }
+                shrinks
+            }
+        }
+    }
+}
+
+impl IntExpr {
+    /// Generate an expression, nested at most `depth` more times.
+    fn generate(rng: &mut Rng, depth: usize) -> Self {
+        if depth == 0 {
+            return match rng.below(2) {
+                0 => Self::Int(rng.int()),
+                _ => Self::Var(rng.below(VARS as u64) as usize),
+            };
+        }
+        let sub = |rng: &mut Rng| Box::new(Self::generate(rng, depth - 1));
+        match rng.below(9) {
+            0 => Self::Int(rng.int()),
+            1 => Self::Var(rng.below(VARS as u64) as usize),
+            2 => Self::Neg(sub(rng)),
+            3 => Self::Add(sub(rng), sub(rng)),
+            4 => Self::Sub(sub(rng), sub(rng)),
+            5 => Self::Mul(sub(rng), sub(rng)),
+            6 => Self::Div(sub(rng), rng.divisor()),
+            7 => Self::Rem(sub(rng), rng.divisor()),
+            _ => Self::If(
+                Box::new(BoolExpr::generate(rng, depth - 1)),
+                sub(rng),
+                sub(rng),
+            ),
+        }
+    }
+
+    /// The LIR code of the expression.
+    fn to_lir(&self) -> Expr {
+        match self {
+            Self::Int(n) => ConstExpr::Int(*n).into(),
+            Self::Var(var) => Expr::var(var_name(*var)),
+            Self::Neg(e) => e.to_lir().neg(),
+            Self::Add(a, b) => a.to_lir().add(b.to_lir()),
+            Self::Sub(a, b) => a.to_lir().sub(b.to_lir()),
+            Self::Mul(a, b) => a.to_lir().mul(b.to_lir()),
+            Self::Div(e, n) => e.to_lir().div(ConstExpr::Int(*n)),
+            Self::Rem(e, n) => e.to_lir().rem(ConstExpr::Int(*n)),
+            Self::If(cond, then, otherwise) => {
+                cond.to_lir().if_then(then.to_lir(), otherwise.to_lir())
+            }
+        }
+    }
+
+    /// The expressions one step smaller than this one.
+    fn shrinks(&self) -> Vec<Self> {
+        match self {
+            Self::Int(0) => vec![],
+            Self::Int(n) if n.abs() > 1 => vec![Self::Int(0), Self::Int(n / 2)],
+            Self::Int(_) | Self::Var(_) => vec![Self::Int(0)],
+            Self::Neg(e) => {
+                let mut shrinks = vec![(**e).clone()];
+                shrinks.extend(e.shrinks().into_iter().map(|e| Self::Neg(Box::new(e))));
+                shrinks
+            }
+            Self::Add(a, b) => Self::shrink_binary(a, b, Self::Add),
+            Self::Sub(a, b) => Self::shrink_binary(a, b, Self::Sub),
+            Self::Mul(a, b) => Self::shrink_binary(a, b, Self::Mul),
+            Self::Div(e, n) => {
+                let mut shrinks = vec![(**e).clone()];
+                shrinks.extend(e.shrinks().into_iter().map(|e| Self::Div(Box::new(e), *n)));
+                shrinks
+            }
+            Self::Rem(e, n) => {
+                let mut shrinks = vec![(**e).clone()];
+                shrinks.extend(e.shrinks().into_iter().map(|e| Self::Rem(Box::new(e), *n)));
+                shrinks
+            }
+            Self::If(cond, then, otherwise) => {
+                let mut shrinks = vec![(**then).clone(), (**otherwise).clone()];
+                for cond in cond.shrinks() {
+                    shrinks.push(Self::If(Box::new(cond), then.clone(), otherwise.clone()));
+                }
+                for then in then.shrinks() {
+                    shrinks.push(Self::If(cond.clone(), Box::new(then), otherwise.clone()));
+                }
+                for otherwise in otherwise.shrinks() {
+                    shrinks.push(Self::If(cond.clone(), then.clone(), Box::new(otherwise)));
+                }
+                shrinks
+            }
+        }
+    }
+
+    /// The shrinks of a binary operation: either operand on its own, or the operation on smaller operands.
+    fn shrink_binary(a: &Self, b: &Self, op: fn(Box<Self>, Box<Self>) -> Self) -> Vec<Self> {
+        let mut shrinks = vec![a.clone(), b.clone()];
+        for a in a.shrinks() {
+            shrinks.push(op(Box::new(a), Box::new(b.clone())));
+        }
+        for b in b.shrinks() {
+            shrinks.push(op(Box::new(a.clone()), Box::new(b)));
+        }
+        shrinks
+    }
+}
+
+impl BoolExpr {
+    /// Generate an expression, nested at most `depth` more times.
+    fn generate(rng: &mut Rng, depth: usize) -> Self {
+        if depth == 0 {
+            return Self::Bool(rng.below(2) == 0);
+        }
+        let sub = |rng: &mut Rng| Box::new(Self::generate(rng, depth - 1));
+        match rng.below(6) {
+            0 => Self::Bool(rng.below(2) == 0),
+            1 => Self::Lt(
+                IntExpr::generate(rng, depth - 1),
+                IntExpr::generate(rng, depth - 1),
+            ),
+            2 => Self::Eq(
+                IntExpr::generate(rng, depth - 1),
+                IntExpr::generate(rng, depth - 1),
+            ),
+            3 => Self::Not(sub(rng)),
+            4 => Self::And(sub(rng), sub(rng)),
+            _ => Self::Or(sub(rng), sub(rng)),
+        }
+    }
+
+    /// The LIR code of the expression.
+    fn to_lir(&self) -> Expr {
+        match self {
+            Self::Bool(b) => ConstExpr::Bool(*b).into(),
+            Self::Lt(a, b) => a.to_lir().lt(b.to_lir()),
+            Self::Eq(a, b) => a.to_lir().eq(b.to_lir()),
+            Self::Not(e) => e.to_lir().not(),
+            Self::And(a, b) => a.to_lir().and(b.to_lir()),
+            Self::Or(a, b) => a.to_lir().or(b.to_lir()),
+        }
+    }
+
+    /// The expressions one step smaller than this one.
+    fn shrinks(&self) -> Vec<Self> {
+        let mut shrinks = vec![];
+        match self {
+            Self::Bool(false) => {}
+            Self::Bool(true) => shrinks.push(Self::Bool(false)),
+            Self::Lt(a, b) | Self::Eq(a, b) => {
+                shrinks.push(Self::Bool(false));
+                let op = match self {
+                    Self::Lt(..) => Self::Lt,
+                    _ => Self::Eq,
+                };
+                shrinks.extend(a.shrinks().into_iter().map(|a| op(a, b.clone())));
+                shrinks.extend(b.shrinks().into_iter().map(|b| op(a.clone(), b)));
+            }
+            Self::Not(e) => {
+                shrinks.push((**e).clone());
+                shrinks.extend(e.shrinks().into_iter().map(|e| Self::Not(Box::new(e))));
+            }
+            Self::And(a, b) | Self::Or(a, b) => {
+                shrinks.push((**a).clone());
+                shrinks.push((**b).clone());
+                let op = match self {
+                    Self::And(..) => Self::And,
+                    _ => Self::Or,
+                };
+                shrinks.extend(a.shrinks().into_iter().map(|a| op(Box::new(a), b.clone())));
+                shrinks.extend(b.shrinks().into_iter().map(|b| op(a.clone(), Box::new(b))));
+            }
+        }
+        shrinks
+    }
+}
+
+/// The name of one of the generated program's variables.
+fn var_name(var: usize) -> String {
+    format!("v{var}")
+}
+
+/// A generated program which printed different output under the interpreter than under another runner.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// The seed the program was generated from, before it was shrunk.
+    pub seed: u64,
+    /// The shrunk program.
+    pub program: RandomProgram,
+    /// What the program printed under the interpreter.
+    pub expected: String,
+    /// What the program printed under the other runner.
+    pub found: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        writeln!(f, "program generated from seed {} diverged:", self.seed)?;
+        writeln!(f, "{}", self.program)?;
+        writeln!(f, "the interpreter printed {:?}", self.expected)?;
+        write!(f, "but the other runner printed {:?}", self.found)
+    }
+}
+
+/// Run a program under the interpreter, returning everything it printed.
+pub fn run_interpreter(program: &Program) -> Result<String, String> {
+    let device = match program {
+        Ok(code) => CoreInterpreter::new(TestingDevice::new("")).run(code),
+        Err(code) => StandardInterpreter::new(TestingDevice::new("")).run(code),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(device.output_str())
+}
+
+/// Generate a program from each seed, and run it under the interpreter and under
+/// `run`, which returns what the program printed (or `None` if it couldn't run it).
+/// The first program whose outputs differ is shrunk, and returned as a [`Divergence`].
+///
+/// The generated programs are well typed, so this panics if one doesn't compile,
+/// or fails in the interpreter: that's a bug in the compiler or the generator.
+pub fn check_equivalence(
+    seeds: impl IntoIterator<Item = u64>,
+    mut run: impl FnMut(&Program) -> Option<String>,
+) -> Result<(), Divergence> {
+    for seed in seeds {
+        let program = RandomProgram::generate(seed);
+        if outputs(&program, &mut run).is_some() {
+            let program = shrink(program, |program| outputs(program, &mut run).is_some());
+            let (expected, found) = outputs(&program, &mut run).unwrap();
+            return Err(Divergence {
+                seed,
+                program,
+                expected,
+                found,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Shrink a program for as long as a smaller program still fails the check.
+pub fn shrink(
+    mut program: RandomProgram,
+    mut fails: impl FnMut(&RandomProgram) -> bool,
+) -> RandomProgram {
+    'shrinking: loop {
+        for candidate in program.shrinks() {
+            if fails(&candidate) {
+                program = candidate;
+                continue 'shrinking;
+            }
+        }
+        return program;
+    }
+}
+
+/// What a program printed under the interpreter and under the other runner, if they're different.
+fn outputs(
+    program: &RandomProgram,
+    run: &mut impl FnMut(&Program) -> Option<String>,
+) -> Option<(String, String)> {
+    let code = program
+        .compile()
+        .unwrap_or_else(|e| panic!("Could not compile generated program {program}: {e}"));
+    let expected = run_interpreter(&code)
+        .unwrap_or_else(|e| panic!("Could not interpret generated program {program}: {e}"));
+    match run(&code) {
+        Some(found) if found != expected => Some((expected, found)),
+        _ => None,
+    }
+}
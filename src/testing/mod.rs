@@ -0,0 +1,18 @@
+//! # Testing Utilities
+//!
+//! This module contains utilities for testing the compiler and its targets:
+//!
+//! 1. Equivalence testing ([`check_equivalence`]), which checks that random
+//!    programs print the same output on a target as they do in the interpreter.
+//! 2. Snapshot testing ([`DeviceSnapshot`] and [`assert_snapshot`]), which checks
+//!    what a program writes to each channel of a `TestingDevice` against a snapshot
+//!    file checked into the repository, instead of an expected string in the test.
+//! 3. Coverage ([`Coverage`]), which maps the instructions a program ran in the
+//!    interpreter back to the lines of its source code, and writes an lcov report.
+mod coverage;
+mod equivalence;
+mod snapshot;
+
+pub use coverage::*;
+pub use equivalence::*;
+pub use snapshot::*;
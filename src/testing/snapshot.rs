@@ -0,0 +1,181 @@
+//! # Snapshot Testing
+//!
+//! A [`DeviceSnapshot`] records everything a program wrote to a `TestingDevice`,
+//! split up by channel: the standard output, the standard error, and every other
+//! output mode and channel (like a display or a digital pin), along with the error
+//! the program halted with, if any. [`assert_snapshot`] compares it against a
+//! snapshot file checked into the repository:
+//!
+//! ```rust,no_run
+//! use sage::{testing::{assert_snapshot, DeviceSnapshot}, Compiler};
+//!
+//! let compilation = Compiler::new("println(\"Hello world!\");").compile();
+//! let snapshot = DeviceSnapshot::run(compilation.vm().unwrap(), "");
+//! assert_snapshot("tests/snapshots/hello.snap", &snapshot);
+//! ```
+//!
+//! When the output of a program changes on purpose, rerun the tests with the
+//! `SAGE_BLESS` environment variable set (like `SAGE_BLESS=1 cargo test`). This
+//! writes the new output to the snapshot files, so the changes can be reviewed
+//! in the diff, and checked in with the change that caused them. Snapshot files
+//! which don't exist yet are created the same way.
+use super::Program;
+use crate::{
+    side_effects::{Output, OutputMode},
+    vm::{CoreInterpreter, RuntimeError, StandardInterpreter, TestingDevice},
+};
+use core::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::Path;
+
+/// The environment variable which makes [`assert_snapshot`] overwrite snapshots instead of checking them.
+pub const BLESS_VAR: &str = "SAGE_BLESS";
+
+/// Everything a program wrote to a `TestingDevice`, split up by channel, and how it halted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceSnapshot {
+    /// The name of each channel the program wrote to, and what it wrote to it,
+    /// in the order the channels were first written to. The standard output and
+    /// standard error are text, and the values written to any other channel are
+    /// separated by spaces.
+    pub channels: Vec<(String, String)>,
+    /// The error the program halted with, if any.
+    pub error: Option<String>,
+}
+
+impl DeviceSnapshot {
+    /// Run a program in the interpreter with some input, and capture what it wrote.
+    pub fn run(program: &Program, input: &str) -> Self {
+        let (device, result) = match program {
+            Ok(code) => CoreInterpreter::new(TestingDevice::new(input)).run_with_device(code),
+            Err(code) => StandardInterpreter::new(TestingDevice::new(input)).run_with_device(code),
+        };
+        Self::capture(&device, &result)
+    }
+
+    /// Capture what a program wrote to a device, and the result it halted with.
+    pub fn capture(device: &TestingDevice, result: &Result<(), RuntimeError>) -> Self {
+        let mut channels = vec![];
+        for (ch, dst) in &device.output {
+            let name = match dst.channel.0 {
+                0 => "stdout".to_string(),
+                n => format!("stdout #{n}"),
+            };
+            write(&mut channels, name, &char::from(*ch as u8).to_string(), "");
+        }
+        if !device.error.is_empty() {
+            write(&mut channels, "stderr".to_string(), &device.error_str(), "");
+        }
+        for (val, dst) in &device.other {
+            write(&mut channels, dst.to_string(), &value(*val, dst), " ");
+        }
+
+        Self {
+            channels,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+
+    /// What the program wrote to a channel, like `stdout`, `stderr`, or `brightness #2`.
+    pub fn channel(&self, name: &str) -> Option<&str> {
+        self.channels
+            .iter()
+            .find(|(channel, _)| channel == name)
+            .map(|(_, written)| written.as_str())
+    }
+}
+
+impl Display for DeviceSnapshot {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        for (channel, written) in &self.channels {
+            writeln!(f, "-- {channel} --")?;
+            write!(f, "{written}")?;
+            if !written.ends_with('\n') {
+                writeln!(f)?;
+            }
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "-- error --")?;
+            writeln!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Add some text to what was written to a channel. If the channel was
+/// already written to, the text is added after a separator.
+fn write(channels: &mut Vec<(String, String)>, name: String, text: &str, separator: &str) {
+    match channels.iter_mut().find(|(channel, _)| *channel == name) {
+        Some((_, written)) => {
+            written.push_str(separator);
+            written.push_str(text);
+        }
+        None => channels.push((name, text.to_string())),
+    }
+}
+
+/// A value written to a channel, as text. Characters written to a printer are written as themselves.
+fn value(val: i64, dst: &Output) -> String {
+    match dst.mode {
+        OutputMode::PrinterChar => char::from(val as u8).to_string(),
+        _ => val.to_string(),
+    }
+}
+
+/// Is the `SAGE_BLESS` environment variable set?
+pub fn blessing() -> bool {
+    std::env::var_os(BLESS_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Check some output against the snapshot file at a path, panicking with the
+/// differences if they don't match. With the `SAGE_BLESS` environment variable
+/// set, this writes the output to the snapshot file instead.
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: impl Display) {
+    if let Err(e) = check_snapshot(path, &actual.to_string(), blessing()) {
+        panic!("{e}");
+    }
+}
+
+/// Check some output against the snapshot file at a path. If `bless` is true,
+/// the output is written to the snapshot file, and the check always passes.
+pub fn check_snapshot(path: impl AsRef<Path>, actual: &str, bless: bool) -> Result<(), String> {
+    let path = path.as_ref();
+    if bless {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Could not create directory {dir:?}: {e}"))?;
+        }
+        return std::fs::write(path, actual)
+            .map_err(|e| format!("Could not write snapshot {path:?}: {e}"));
+    }
+
+    let expected = std::fs::read_to_string(path).map_err(|e| {
+        format!("Could not read snapshot {path:?} ({e}), rerun with {BLESS_VAR}=1 to create it")
+    })?;
+    // Snapshots checked out on Windows may have their line endings converted.
+    let expected = expected.replace("\r\n", "\n");
+    if expected == actual {
+        return Ok(());
+    }
+
+    let mut message = format!("Output does not match snapshot {path:?}:\n");
+    let expected_lines = expected.lines().collect::<Vec<_>>();
+    let actual_lines = actual.lines().collect::<Vec<_>>();
+    for i in 0..expected_lines.len().max(actual_lines.len()) {
+        match (expected_lines.get(i), actual_lines.get(i)) {
+            (Some(expected), Some(actual)) if expected == actual => {
+                message += &format!("  {expected}\n")
+            }
+            (expected, actual) => {
+                if let Some(expected) = expected {
+                    message += &format!("- {expected}\n");
+                }
+                if let Some(actual) = actual {
+                    message += &format!("+ {actual}\n");
+                }
+            }
+        }
+    }
+    message +=
+        &format!("Rerun with {BLESS_VAR}=1 to update the snapshot, if the change is intended.");
+    Err(message)
+}
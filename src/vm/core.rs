@@ -294,6 +294,11 @@ pub enum CoreOp {
 
     /// Add the value pointed to on the tape to the register.
     /// The argument is the size of the vector to add to the register.
+    ///
+    /// Like the other integer arithmetic, this wraps around on overflow. Every target
+    /// computes the integer arithmetic the same way as the virtual machine, including
+    /// the cases of `Div` and `Rem` below which hardware traps on or leaves undefined.
+    /// The differential tests check this with the division and overflow examples.
     Add(usize),
     /// Subtract the value pointed to on the tape from the register.
     /// The argument is the size of the vector to subtract from the register.
@@ -301,11 +306,17 @@ pub enum CoreOp {
     /// Multiply the register by the value pointed to on the tape.
     /// The argument is the size of the vector to multiply the register by.
     Mul(usize),
-    /// Divide the register by the value pointed to on the tape.
+    /// Divide the register by the value pointed to on the tape, rounding towards zero.
     /// The argument is the size of the vector to divide the register by.
+    ///
+    /// Dividing by zero leaves the register unchanged, and the smallest integer
+    /// divided by -1 wraps around to itself.
     Div(usize),
     /// Store the remainder of the register and the value pointed to in the tape into the register.
     /// The argument is the size of the vector to take the remainder of the register by.
+    ///
+    /// The remainder has the sign of the dividend. The remainder of dividing by zero
+    /// leaves the register unchanged, and the remainder of dividing by -1 is zero.
     Rem(usize),
     /// Negate the register.
     /// The argument is the size of the vector to negate the register by.
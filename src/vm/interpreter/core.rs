@@ -2,7 +2,8 @@
 //!
 //! This module implements an interpreter for the Core virtual machine
 //! variant.
-use super::TAPE_EXTENSION_SIZE;
+use super::{Limits, Profile, RuntimeError, TAPE_EXTENSION_SIZE};
+use crate::side_effects::OutputMode;
 use crate::vm::{CoreOp, CoreProgram, Device, StandardDevice};
 
 impl Default for CoreInterpreter<StandardDevice> {
@@ -38,6 +39,16 @@ where
     i: usize,
     /// Is the interpreter finished interpreting?s
     done: bool,
+    /// The exit status the program halted with, if it halted itself.
+    status: i64,
+    /// The number of instructions executed so far, not counting comments.
+    instructions: u64,
+    /// The number of times each comment has been passed, by its index in the program.
+    comment_hits: Vec<u64>,
+    /// The number of values read and written so far, through input and output.
+    io: u64,
+    /// The limits on what the program can do.
+    limits: Limits,
 }
 
 impl<T> CoreInterpreter<T>
@@ -55,9 +66,20 @@ where
             refs: vec![],
             i: 0,
             done: false,
+            status: 0,
+            instructions: 0,
+            comment_hits: vec![],
+            io: 0,
+            limits: Limits::default(),
         }
     }
 
+    /// Limit what programs run by this interpreter can do.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     fn reg_scalar(&self) -> i64 {
         self.register[0]
     }
@@ -243,18 +265,62 @@ where
     }
 
     /// Run a core program using this interpreter and its device.
-    pub fn run(mut self, code: &CoreProgram) -> Result<T, String> {
-        while !self.done {
-            self.step(code)?
+    /// If the program halts itself with a failing exit status, that is returned as an error.
+    pub fn run(self, code: &CoreProgram) -> Result<T, RuntimeError> {
+        let (device, result) = self.run_with_device(code);
+        result.map(|_| device)
+    }
+
+    /// Run a core program using this interpreter and its device, returning the device
+    /// even if the program fails, so that what it wrote before failing can be inspected.
+    pub fn run_with_device(self, code: &CoreProgram) -> (T, Result<(), RuntimeError>) {
+        let profile = self.profile(code);
+        (profile.device, profile.result)
+    }
+
+    /// Run a core program using this interpreter and its device,
+    /// counting the instructions it executes.
+    pub fn profile(mut self, code: &CoreProgram) -> Profile<T> {
+        self.comment_hits = vec![0; code.0.len()];
+        let started = ::std::time::Instant::now();
+        let result = loop {
+            if self.done {
+                break match self.status {
+                    0 => Ok(()),
+                    status => Err(RuntimeError::Exit(status)),
+                };
+            }
+            if let Err(limit) = self
+                .limits
+                .check(self.instructions, self.pointer, self.io, started)
+            {
+                break Err(RuntimeError::Limit(limit));
+            }
+            if let Err(e) = self.step(code) {
+                break Err(RuntimeError::Machine(e));
+            }
+        };
+        Profile {
+            device: self.device,
+            instructions: self.instructions,
+            comment_hits: self.comment_hits,
+            result,
+            leaks: vec![],
         }
-        Ok(self.device)
     }
 
     /// Run a single step of the interpreter.
     fn step(&mut self, code: &CoreProgram) -> Result<(), String> {
         if let Some(op) = self.fetch(code) {
+            if !matches!(op, CoreOp::Comment(_)) {
+                self.instructions += 1;
+            }
             match op {
-                CoreOp::Comment(_) => {}
+                CoreOp::Comment(_) => {
+                    if let Some(hits) = self.comment_hits.get_mut(self.i) {
+                        *hits += 1;
+                    }
+                }
                 CoreOp::Set(n) => *self.reg_mut_vector() = n.clone(),
                 CoreOp::Function => {
                     if !self.functions.contains(&self.i) {
@@ -466,8 +532,23 @@ where
                         self.reg_mut_vector()[i] = i64::from(self.reg_vector()[i] >= 0);
                     }
                 }
-                CoreOp::Get(i) => *self.reg_mut_scalar() = self.device.get(i.clone())?,
-                CoreOp::Put(o) => self.device.put(self.reg_scalar(), o.clone())?,
+                CoreOp::Get(i) => {
+                    self.io += 1;
+                    if self.limits.allows_io(self.io) {
+                        *self.reg_mut_scalar() = self.device.get(i.clone())?
+                    }
+                }
+                CoreOp::Put(o) if o.mode == OutputMode::Exit => {
+                    // Halt the program with the exit status in the register.
+                    self.status = self.reg_scalar();
+                    self.done = true
+                }
+                CoreOp::Put(o) => {
+                    self.io += 1;
+                    if self.limits.allows_io(self.io) {
+                        self.device.put(self.reg_scalar(), o.clone())?
+                    }
+                }
             }
             self.i += 1
         } else {
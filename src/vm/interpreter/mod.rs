@@ -6,23 +6,213 @@
 //! supplying the input and handling the output of the program. For testing the compiler,
 //! assembler, and virtual machine, we use a `TestingDevice` object to supply sample input
 //! and capture the output to test against the predicted output.
-use crate::side_effects::{FFIBinding, Input, InputMode, Output, OutputMode};
+use crate::side_effects::{
+    call_standard_binding, pop_ffi_value, push_ffi_value, AudioBuffer, FFIBinding, Framebuffer,
+    Input, InputMode, LogLevel, MockHost, Output, OutputMode, SystemHost, NO_KEY,
+};
 
 use log::{error, trace, warn};
 
 mod core;
 pub use self::core::*;
+mod sanitizer;
 mod std;
 pub use self::std::*;
+#[cfg(feature = "framebuffer")]
+mod window;
+#[cfg(feature = "framebuffer")]
+pub use self::window::*;
+#[cfg(feature = "audio")]
+mod speaker;
+#[cfg(feature = "audio")]
+pub use self::speaker::*;
+#[cfg(feature = "terminal")]
+mod terminal;
+#[cfg(feature = "terminal")]
+pub use self::terminal::*;
 
 use ::std::{
     collections::{HashMap, VecDeque},
+    fmt::{Display, Formatter, Result as FmtResult},
     io::{stdin, stdout, Read, Write},
+    time::{Duration, Instant},
 };
 
+/// The target of the log messages programs write to the `log` crate.
+pub const LOG_TARGET: &str = "sage::program";
+
 /// The amount by which the tape is extended whenever the pointer moves past the end
 pub(super) const TAPE_EXTENSION_SIZE: usize = 100000;
 
+/// The reason an interpreter stopped running a program before it finished.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// The program halted itself with a failing exit status, like when it panics.
+    /// The program writes its own message to the standard error stream before it halts.
+    Exit(i64),
+    /// The machine couldn't execute an instruction, like a call to
+    /// an undefined function, or input or output which the device failed.
+    Machine(String),
+    /// The program went past one of the limits the interpreter was given.
+    Limit(Limit),
+    /// The sanitizer caught the program using an uninitialized value or freed memory.
+    /// The message says what went wrong, where, and which calls reached it.
+    Memory(String),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Exit(status) => write!(f, "program exited with status {status}"),
+            Self::Machine(msg) => write!(f, "{msg}"),
+            Self::Limit(limit) => write!(f, "{limit}"),
+            Self::Memory(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+/// Limits on what an interpreter lets a program do, so that untrusted programs
+/// can be run without hanging or using up the host's memory. By default, there
+/// are no limits. A program which goes past a limit stops with a `RuntimeError::Limit`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// The most instructions the program can execute, not counting comments.
+    pub max_steps: Option<u64>,
+    /// The number of cells on the tape: the program can't move its pointer past them.
+    /// This must be larger than the call stack, which is at the start of the tape.
+    pub max_tape: Option<usize>,
+    /// The longest the program can run for.
+    pub timeout: Option<Duration>,
+    /// The most values the program can read and write, through input and output.
+    pub io_quota: Option<u64>,
+}
+
+impl Limits {
+    /// How many instructions are executed between checks of the clock for the timeout.
+    const CLOCK_INTERVAL: u64 = 4096;
+
+    /// Check what a program has done so far against the limits.
+    fn check(&self, steps: u64, pointer: usize, io: u64, started: Instant) -> Result<(), Limit> {
+        if let Some(max_steps) = self.max_steps.filter(|max_steps| steps > *max_steps) {
+            return Err(Limit::Steps(max_steps));
+        }
+        if let Some(max_tape) = self.max_tape.filter(|max_tape| pointer >= *max_tape) {
+            return Err(Limit::Tape(max_tape));
+        }
+        if let Some(io_quota) = self.io_quota.filter(|io_quota| io > *io_quota) {
+            return Err(Limit::Io(io_quota));
+        }
+        if let Some(timeout) = self.timeout {
+            if steps % Self::CLOCK_INTERVAL == 0 && started.elapsed() > timeout {
+                return Err(Limit::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Can a program read or write its `io`th value? If it can't, the
+    /// value isn't read or written, and the program is stopped instead.
+    fn allows_io(&self, io: u64) -> bool {
+        !self.io_quota.is_some_and(|io_quota| io > io_quota)
+    }
+}
+
+/// A limit which stopped a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// The program executed more than this many instructions.
+    Steps(u64),
+    /// The program moved its pointer past this many cells.
+    Tape(usize),
+    /// The program ran for longer than this.
+    Timeout(Duration),
+    /// The program read and wrote more than this many values.
+    Io(u64),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Steps(steps) => write!(f, "program executed more than {steps} instructions"),
+            Self::Tape(cells) => write!(f, "program moved its pointer past {cells} cells"),
+            Self::Timeout(time) => {
+                write!(f, "program ran for longer than {:.3}s", time.as_secs_f64())
+            }
+            Self::Io(values) => write!(f, "program read and wrote more than {values} values"),
+        }
+    }
+}
+
+impl From<String> for RuntimeError {
+    fn from(msg: String) -> Self {
+        Self::Machine(msg)
+    }
+}
+
+impl From<RuntimeError> for String {
+    fn from(err: RuntimeError) -> Self {
+        err.to_string()
+    }
+}
+
+/// The result of running a program with an interpreter's `profile` method.
+#[derive(Clone, Debug)]
+pub struct Profile<T> {
+    /// The interpreter's device, after the program has run.
+    pub device: T,
+    /// The number of instructions the program executed, not counting comments.
+    pub instructions: u64,
+    /// The number of times the program passed each comment, by its index in the program
+    /// (and zero for the other instructions). Comments marking the lines of the source
+    /// code map these back to the source code, to measure which lines ran.
+    pub comment_hits: Vec<u64>,
+    /// Why the program stopped before it finished, if it did.
+    pub result: Result<(), RuntimeError>,
+    /// The allocations the program never freed, if the interpreter checked for leaks
+    /// and the program ran until it exited.
+    pub leaks: Vec<Leak>,
+}
+
+/// An allocation which a program never freed, found by the standard interpreter's leak check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Leak {
+    /// The address of the first cell of the allocation.
+    pub addr: usize,
+    /// The number of cells allocated.
+    pub size: usize,
+    /// Where the program allocated the cells: the instruction, and the source line
+    /// it came from if the program was compiled with debug info.
+    pub site: String,
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        let plural = if self.size == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} cell{plural} at address {}, allocated at {}",
+            self.size, self.addr, self.site
+        )
+    }
+}
+
+/// Describe an instruction in a program, with the source line it came from if it's marked.
+/// `comment` gets the comment at an index in the program, to find the lines they mark.
+pub(super) fn instruction_location<'a>(
+    i: usize,
+    comment: impl Fn(usize) -> Option<&'a str>,
+) -> String {
+    // The instruction came from the last line marked before it.
+    let line = (0..=i)
+        .rev()
+        .find_map(|j| comment(j).and_then(crate::vm::comment_line));
+    match line {
+        Some((line, Some(filename))) => format!("instruction #{i} ({filename}:{line})"),
+        Some((line, None)) => format!("instruction #{i} (line {line})"),
+        None => format!("instruction #{i}"),
+    }
+}
+
 /// Create an input / output device for the virtual machine interpreter
 /// to operate on. The method `get` retrieves the device's input, and the
 /// function `put` writes to the devices output.
@@ -48,18 +238,58 @@ pub trait Device {
     fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String>;
 }
 
+/// A boxed device, so that devices can be chosen when the program runs.
+impl<D: Device + ?Sized> Device for Box<D> {
+    fn get(&mut self, src: Input) -> Result<i64, String> {
+        (**self).get(src)
+    }
+
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+        (**self).put(val, dst)
+    }
+
+    fn peek(&mut self) -> Result<i64, String> {
+        (**self).peek()
+    }
+
+    fn poke(&mut self, val: i64) -> Result<(), String> {
+        (**self).poke(val)
+    }
+
+    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+        (**self).ffi_call(ffi, tape)
+    }
+}
+
 /// A device used for testing the compiler. This simply keeps a buffer
 /// of sample input to supply to the virtual machine, and keeps an output
 /// buffer to keep track of the output of the virtual machine.
 ///
 /// The tests interpret the program and populate the device with output.
 /// Then, we check the devices output against the correct output.
-#[derive(Debug, Default)]
+/// What the program writes to the standard error stream is kept separately,
+/// and so are its log messages, and what it writes to any other output, like a display.
+/// What it draws on channel 0 is also kept in a framebuffer,
+/// and the sound it plays on channel 0 is recorded without being played.
+/// Keys are polled from the sample input, and the terminal is 80 by 24.
+/// The standard file and clock bindings use files in memory, and a mock clock.
+#[derive(Clone, Debug, Default)]
 pub struct TestingDevice {
     pub ffi: HashMap<FFIBinding, fn(&mut VecDeque<i64>, Option<&mut Vec<i64>>)>,
     pub ffi_channel: VecDeque<i64>,
     pub input: VecDeque<i64>,
     pub output: Vec<(i64, Output)>,
+    pub error: Vec<i64>,
+    /// The characters of the log messages, which are kept out of the standard error stream.
+    pub log: Vec<i64>,
+    /// The values written to every other output mode and channel, in the order they were written.
+    pub other: Vec<(i64, Output)>,
+    /// The files and clock of the standard bindings, which tests can set up and check.
+    pub host: MockHost,
+    /// The framebuffer drawn on channel 0.
+    pub framebuffer: Framebuffer,
+    /// The samples played on channel 0.
+    pub audio: AudioBuffer,
 }
 
 impl TestingDevice {
@@ -74,6 +304,12 @@ impl TestingDevice {
                 .map(|ch| ch as i64)
                 .collect(),
             output: vec![],
+            error: vec![],
+            log: vec![],
+            other: vec![],
+            host: MockHost::default(),
+            framebuffer: Framebuffer::new(Framebuffer::DEFAULT_WIDTH, Framebuffer::DEFAULT_HEIGHT),
+            audio: AudioBuffer::default(),
         }
     }
 
@@ -83,6 +319,12 @@ impl TestingDevice {
             ffi_channel: VecDeque::new(),
             input: input.into(),
             output: vec![],
+            error: vec![],
+            log: vec![],
+            other: vec![],
+            host: MockHost::default(),
+            framebuffer: Framebuffer::new(Framebuffer::DEFAULT_WIDTH, Framebuffer::DEFAULT_HEIGHT),
+            audio: AudioBuffer::default(),
         }
     }
 
@@ -189,6 +431,29 @@ impl TestingDevice {
     pub fn output_vals(&self) -> Vec<i64> {
         self.output.iter().map(|(val, _)| *val).collect()
     }
+
+    /// Get what the program wrote to the standard error stream as a string (ascii).
+    pub fn error_str(&self) -> String {
+        self.error
+            .iter()
+            .map(|ch| *ch as i8 as u8 as char)
+            .collect()
+    }
+
+    /// Get the messages the program logged, with their levels.
+    pub fn logs(&self) -> Vec<(LogLevel, String)> {
+        let log = self
+            .log
+            .iter()
+            .map(|ch| *ch as i8 as u8 as char)
+            .collect::<String>();
+        log.lines()
+            .filter_map(|line| {
+                let (level, message) = LogLevel::parse_line(line)?;
+                Some((level, message.to_string()))
+            })
+            .collect()
+    }
 }
 
 /// Make the testing device work with the interpreter.
@@ -205,6 +470,10 @@ impl Device for TestingDevice {
             }
             InputMode::StdinInt => self.get_int(),
             InputMode::StdinFloat => self.get_float().map(as_int),
+            // Keys are polled from the sample input, as if they were all pressed at once.
+            InputMode::KeyPoll => Ok(self.input.pop_front().unwrap_or(NO_KEY)),
+            InputMode::TerminalWidth => Ok(80),
+            InputMode::TerminalHeight => Ok(24),
             _ => {
                 warn!("Requested input mode: {}", src.mode);
                 Ok(0)
@@ -220,8 +489,30 @@ impl Device for TestingDevice {
             }
             OutputMode::StdoutInt => self.put_int(val),
             OutputMode::StdoutFloat => self.put_float(as_float(val)),
+            OutputMode::StderrChar | OutputMode::StderrInt | OutputMode::StderrFloat => {
+                let chars = match dst.mode {
+                    OutputMode::StderrChar => vec![val],
+                    OutputMode::StderrInt => val.to_string().chars().map(|ch| ch as i64).collect(),
+                    _ => format!("{:?}", as_float(val))
+                        .chars()
+                        .map(|ch| ch as i64)
+                        .collect(),
+                };
+                if LogLevel::is_log(&dst) {
+                    self.log.extend(chars);
+                } else {
+                    self.error.extend(chars);
+                }
+                Ok(())
+            }
             _ => {
-                warn!("Requested output mode: {} (with output={val})", dst.mode);
+                trace!("Requested output mode: {} (with output={val})", dst.mode);
+                if dst.channel.0 == 0 && Framebuffer::handles(&dst) {
+                    self.framebuffer.put(val, &dst);
+                } else if dst.channel.0 == 0 && AudioBuffer::handles(&dst) {
+                    self.audio.put(val, &dst);
+                }
+                self.other.push((val, dst));
                 Ok(())
             }
         }
@@ -246,6 +537,9 @@ impl Device for TestingDevice {
             trace!("Calling FFI: {}", ffi);
             f(&mut self.ffi_channel, tape);
             Ok(())
+        } else if call_standard_binding(&mut self.host, ffi, &mut self.ffi_channel, tape)? {
+            trace!("Called standard FFI: {}", ffi);
+            Ok(())
         } else {
             error!("FFI call not found: {:?}", ffi);
             Err(format!("ffi call not found: {:?}", ffi))
@@ -256,10 +550,16 @@ impl Device for TestingDevice {
 /// A device used for standard input and output.
 /// This simply retrieves a character from standard-in with `get`,
 /// and writes a character to standard-out with `put`.
+/// The standard file and clock bindings use the host's files and clock.
+/// Log messages go to the `log` crate if its logger shows their level,
+/// and to standard-error otherwise.
 #[derive(Debug, Clone)]
 pub struct StandardDevice {
     ffi: HashMap<FFIBinding, fn(&mut VecDeque<i64>, Option<&mut Vec<i64>>)>,
     ffi_channel: VecDeque<i64>,
+    host: SystemHost,
+    /// The log message being written, up to its newline.
+    log_line: String,
 }
 
 impl Default for StandardDevice {
@@ -267,20 +567,21 @@ impl Default for StandardDevice {
         let mut result = Self {
             ffi: HashMap::new(),
             ffi_channel: VecDeque::new(),
+            host: SystemHost::default(),
+            log_line: String::new(),
         };
 
         result.add_binding(
-            FFIBinding::new("square_root".to_string(), 1, 1),
+            FFIBinding::typed::<f64, f64>("square_root"),
             |channel, _| {
-                let val = as_float(channel.pop_front().unwrap());
-                channel.push_back(as_int(val.sqrt()));
+                let val: f64 = pop_ffi_value(channel).unwrap();
+                push_ffi_value(channel, val.sqrt());
             },
         );
 
-        result.add_binding(FFIBinding::new("add".to_string(), 2, 1), |channel, _| {
-            let a = as_float(channel.pop_front().unwrap());
-            let b = as_float(channel.pop_front().unwrap());
-            channel.push_back(as_int(a + b));
+        result.add_binding(FFIBinding::typed::<(f64, f64), f64>("add"), |channel, _| {
+            let (a, b): (f64, f64) = pop_ffi_value(channel).unwrap();
+            push_ffi_value(channel, a + b);
         });
 
         result
@@ -297,6 +598,19 @@ impl StandardDevice {
         self.ffi.insert(ffi, f);
     }
 
+    /// Write the log message which has just been finished.
+    fn flush_log(&mut self) {
+        let line = ::std::mem::take(&mut self.log_line);
+        let logged =
+            LogLevel::parse_line(&line).map(|(level, message)| (log::Level::from(level), message));
+        match logged {
+            Some((level, message)) if log::log_enabled!(target: LOG_TARGET, level) => {
+                log::log!(target: LOG_TARGET, level, "{message}")
+            }
+            _ => eprintln!("{line}"),
+        }
+    }
+
     fn get_char(&mut self) -> Result<char, String> {
         let mut buf = [0];
         if stdout().flush().is_err() {
@@ -357,6 +671,15 @@ impl StandardDevice {
     }
 }
 
+/// The size of the terminal in one dimension, from an environment variable
+/// like `COLUMNS`, if it's set.
+fn terminal_dimension(var: &str, default: i64) -> i64 {
+    ::std::env::var(var)
+        .ok()
+        .and_then(|n| n.trim().parse().ok())
+        .unwrap_or(default)
+}
+
 impl Device for StandardDevice {
     fn get(&mut self, src: Input) -> Result<i64, String> {
         Ok(match src.mode {
@@ -364,6 +687,13 @@ impl Device for StandardDevice {
             InputMode::StdinInt => self.get_int()?,
             InputMode::StdinFloat => as_int(self.get_float()?),
             InputMode::Thermometer => as_int(295.15),
+            // Standard input is line-buffered, so keys can't be polled without waiting.
+            InputMode::KeyPoll => {
+                warn!("Keys can't be polled from line-buffered input, use a terminal device");
+                NO_KEY
+            }
+            InputMode::TerminalWidth => terminal_dimension("COLUMNS", 80),
+            InputMode::TerminalHeight => terminal_dimension("LINES", 24),
             _ => {
                 warn!(
                     "Requested input mode: {} (on channel #{})",
@@ -375,6 +705,15 @@ impl Device for StandardDevice {
     }
 
     fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+        if LogLevel::is_log(&dst) {
+            match dst.mode {
+                OutputMode::StderrChar if val == '\n' as i64 => self.flush_log(),
+                OutputMode::StderrChar => self.log_line.push(val as u8 as char),
+                OutputMode::StderrInt => self.log_line += &val.to_string(),
+                _ => self.log_line += &format!("{:?}", as_float(val)),
+            }
+            return Ok(());
+        }
         // Print the character without a newline
         match dst.mode {
             OutputMode::StdoutChar => print!("{}", val as u8 as char),
@@ -416,6 +755,9 @@ impl Device for StandardDevice {
             trace!("Calling FFI: {}", ffi);
             f(&mut self.ffi_channel, tape);
             Ok(())
+        } else if call_standard_binding(&mut self.host, ffi, &mut self.ffi_channel, tape)? {
+            trace!("Called standard FFI: {}", ffi);
+            Ok(())
         } else {
             error!("FFI call not found: {:?}", ffi);
             Err(format!("ffi call not found: {:?}", ffi))
@@ -0,0 +1,238 @@
+//! # Memory Sanitizer
+//!
+//! When the standard interpreter runs a program with the sanitizer enabled, it keeps
+//! a shadow of the tape and the register, which records whether each value has been
+//! initialized, and which cells belong to freed allocations.
+//!
+//! Copying an uninitialized value isn't an error by itself: the code generated to copy
+//! a struct or a tagged union copies all of its cells, even those which were never set.
+//! So uninitialized values are tracked through loads, stores, and arithmetic, and the
+//! program is stopped when it *uses* one: to decide a branch, as a pointer, to call
+//! a function, to allocate memory, or as output. Any access to a freed cell stops
+//! the program immediately.
+//!
+//! The cells of a new allocation are uninitialized until the program writes them,
+//! even though the interpreter fills them with zeroes. The rest of the tape (the call
+//! stack and static memory) starts out initialized, because programs rely on it being
+//! zeroed. Foreign functions can't be tracked, so a foreign function which is given
+//! the tape initializes the cells it changes.
+//!
+//! The core variant has no heap, so only the standard interpreter has a sanitizer.
+use super::instruction_location;
+use crate::vm::CoreOp;
+
+/// What the sanitizer knows about a cell on the tape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shadow {
+    /// The cell holds a value written by the program, or the tape's initial zero.
+    Defined,
+    /// The cell holds a value which came from the uninitialized cell at this address.
+    Uninit(usize),
+    /// The cell belongs to an allocation which has been freed.
+    Freed,
+}
+
+/// Tracks which values are initialized, and which cells have been freed.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Sanitizer {
+    /// The shadow of each cell on the tape. The cells past the end are initialized.
+    cells: Vec<Shadow>,
+    /// The address of the uninitialized cell the value in each lane of the register
+    /// came from, if it came from one. The lanes past the end are initialized.
+    register: Vec<Option<usize>>,
+}
+
+/// Check that a value is initialized before the program uses it for something.
+pub(super) fn check_used(origin: Option<usize>, what: &str) -> Result<(), String> {
+    match origin {
+        Some(origin) => Err(format!(
+            "{what} uses an uninitialized value from address {origin}"
+        )),
+        None => Ok(()),
+    }
+}
+
+impl Sanitizer {
+    fn cell(&self, addr: usize) -> Shadow {
+        self.cells.get(addr).copied().unwrap_or(Shadow::Defined)
+    }
+
+    fn set_cell(&mut self, addr: usize, shadow: Shadow) {
+        if addr >= self.cells.len() {
+            if shadow == Shadow::Defined {
+                return;
+            }
+            self.cells.resize(addr + 1, Shadow::Defined);
+        }
+        self.cells[addr] = shadow;
+    }
+
+    fn lane(&self, i: usize) -> Option<usize> {
+        self.register.get(i).copied().flatten()
+    }
+
+    fn set_lane(&mut self, i: usize, origin: Option<usize>) {
+        if i >= self.register.len() {
+            if origin.is_none() {
+                return;
+            }
+            self.register.resize(i + 1, None);
+        }
+        self.register[i] = origin;
+    }
+
+    /// Read the value in a cell, and get where it came from if it's uninitialized.
+    pub(super) fn read_cell(&self, addr: usize) -> Result<Option<usize>, String> {
+        match self.cell(addr) {
+            Shadow::Defined => Ok(None),
+            Shadow::Uninit(origin) => Ok(Some(origin)),
+            Shadow::Freed => Err(format!("read of freed memory at address {addr}")),
+        }
+    }
+
+    /// Write a value to a cell, which is uninitialized if it came from an uninitialized cell.
+    pub(super) fn write_cell(&mut self, addr: usize, origin: Option<usize>) -> Result<(), String> {
+        if self.cell(addr) == Shadow::Freed {
+            return Err(format!("write to freed memory at address {addr}"));
+        }
+        self.set_cell(addr, origin.map_or(Shadow::Defined, Shadow::Uninit));
+        Ok(())
+    }
+
+    /// Is a cell part of a freed allocation?
+    pub(super) fn is_freed(&self, addr: usize) -> bool {
+        self.cell(addr) == Shadow::Freed
+    }
+
+    /// Get where the value in the first lane of the register came from, if it's uninitialized.
+    pub(super) fn scalar(&self) -> Option<usize> {
+        self.lane(0)
+    }
+
+    /// Put a value in the first lane of the register.
+    pub(super) fn set_scalar(&mut self, origin: Option<usize>) {
+        self.set_lane(0, origin)
+    }
+
+    /// Fill the register with initialized values.
+    pub(super) fn define_register(&mut self) {
+        self.register.clear()
+    }
+
+    /// Combine the first `n` lanes of the register with the cells at the pointer.
+    /// A result is uninitialized if either of its operands is.
+    pub(super) fn combine(&mut self, pointer: usize, n: usize) -> Result<(), String> {
+        for i in 0..n {
+            let origin = self.read_cell(pointer + i)?;
+            self.set_lane(i, self.lane(i).or(origin));
+        }
+        Ok(())
+    }
+
+    /// Track what a core instruction does with memory, before it runs with the
+    /// tape pointer at `pointer`. `loops` says whether an `End` instruction ends a loop.
+    pub(super) fn core_op(
+        &mut self,
+        op: &CoreOp,
+        pointer: usize,
+        loops: impl FnOnce() -> bool,
+    ) -> Result<(), String> {
+        match op {
+            CoreOp::Set(_) => self.define_register(),
+            CoreOp::Where | CoreOp::Get(_) => self.set_scalar(None),
+            CoreOp::If | CoreOp::While => check_used(self.scalar(), "a branch")?,
+            // Only the `End` of a loop checks the register.
+            CoreOp::End => {
+                if self.scalar().is_some() && loops() {
+                    check_used(self.scalar(), "a loop")?
+                }
+            }
+            CoreOp::Call => check_used(self.scalar(), "a call")?,
+            CoreOp::Put(_) => check_used(self.scalar(), "an output")?,
+            CoreOp::Deref => check_used(self.read_cell(pointer)?, "a dereference")?,
+            CoreOp::Load(n) => {
+                self.register = (0..*n)
+                    .map(|i| self.read_cell(pointer + i))
+                    .collect::<Result<_, _>>()?;
+            }
+            CoreOp::Store(n) => {
+                for i in 0..*n {
+                    self.write_cell(pointer + i, self.lane(i))?;
+                }
+            }
+            CoreOp::Swap(n) => {
+                for i in 0..*n {
+                    let origin = self.read_cell(pointer + i)?;
+                    self.write_cell(pointer + i, self.lane(i))?;
+                    self.set_lane(i, origin);
+                }
+            }
+            CoreOp::Index(n)
+            | CoreOp::BitwiseNand(n)
+            | CoreOp::BitwiseAnd(n)
+            | CoreOp::BitwiseOr(n)
+            | CoreOp::BitwiseXor(n)
+            | CoreOp::LeftShift(n)
+            | CoreOp::LogicalRightShift(n)
+            | CoreOp::ArithmeticRightShift(n)
+            | CoreOp::Add(n)
+            | CoreOp::Sub(n)
+            | CoreOp::Mul(n)
+            | CoreOp::Div(n)
+            | CoreOp::Rem(n)
+            | CoreOp::And(n)
+            | CoreOp::Or(n) => self.combine(pointer, *n)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Mark the cells of a new allocation as uninitialized.
+    pub(super) fn allocate(&mut self, addr: usize, size: usize) {
+        for i in addr..addr + size {
+            self.set_cell(i, Shadow::Uninit(i));
+        }
+    }
+
+    /// Mark the cells of an allocation as freed.
+    pub(super) fn free(&mut self, addr: usize, size: usize) {
+        for i in addr..addr + size {
+            self.set_cell(i, Shadow::Freed);
+        }
+    }
+
+    /// Copy the shadows of `n` cells, when an allocation is moved.
+    pub(super) fn copy(&mut self, src: usize, dst: usize, n: usize) {
+        for i in 0..n {
+            self.set_cell(dst + i, self.cell(src + i));
+        }
+    }
+
+    /// Mark the cells a foreign function changed as initialized.
+    pub(super) fn define_changed(&mut self, before: &[i64], after: &[i64]) {
+        for (addr, (old, new)) in before.iter().zip(after).enumerate() {
+            if old != new {
+                self.set_cell(addr, Shadow::Defined);
+            }
+        }
+    }
+}
+
+/// Describe a memory error at an instruction, with a backtrace of the calls which reached it.
+/// The interpreter's own call stack is used, so this doesn't need the program's backtraces.
+/// `comment` gets the comment at an index in the program, to find the source lines they mark.
+pub(super) fn diagnostic<'a>(
+    error: &str,
+    i: usize,
+    calls: &[usize],
+    comment: impl Fn(usize) -> Option<&'a str>,
+) -> String {
+    let mut result = format!(
+        "memory error: {error}\n  at {}",
+        instruction_location(i, &comment)
+    );
+    for call in calls.iter().rev() {
+        result += &format!("\n  called from {}", instruction_location(*call, &comment));
+    }
+    result
+}
@@ -0,0 +1,165 @@
+//! A device which plays a program's audio on the host's speakers.
+//!
+//! This is only available with the `audio` feature, which uses `cpal`
+//! to play the sound.
+use super::Device;
+use crate::side_effects::{AudioBuffer, FFIBinding, Input, Output, OutputMode};
+
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    OutputCallbackInfo, SampleFormat, Stream, StreamConfig,
+};
+use log::{error, warn};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// A device which plays the samples on one channel through the host's default
+/// output device, and passes everything else on to another device. The sound
+/// starts when the program plays its first sample, and the program waits when
+/// it gets too far ahead of what has been heard.
+pub struct SpeakerDevice<D: Device> {
+    device: D,
+    /// The channel the samples are played on.
+    channel: usize,
+    audio: AudioBuffer,
+    /// The samples waiting for the stream to play them.
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    stream: Option<Stream>,
+}
+
+impl<D: Device> SpeakerDevice<D> {
+    /// Play the samples on channel 0, and pass everything else on to a device.
+    pub fn new(device: D) -> Self {
+        Self {
+            device,
+            channel: 0,
+            audio: AudioBuffer::default(),
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+            stream: None,
+        }
+    }
+
+    /// Play the samples on a different channel.
+    pub fn with_channel(mut self, channel: usize) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// The samples which have been played so far.
+    pub fn audio(&self) -> &AudioBuffer {
+        &self.audio
+    }
+
+    /// Wait for the samples which have been played to be heard.
+    pub fn finish(&mut self) {
+        if self.stream.is_none() {
+            return;
+        }
+        // Don't wait forever if the stream stops taking samples.
+        let waiting = self.queue.lock().unwrap().len();
+        let deadline = Instant::now()
+            + Duration::from_secs_f64(waiting as f64 / self.audio.sample_rate() as f64 + 1.0);
+        while !self.queue.lock().unwrap().is_empty() && Instant::now() < deadline {
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Open a stream on the default output device, which plays the samples in the
+    /// queue at the program's sample rate.
+    fn open(&mut self) -> Result<Stream, String> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or_else(|| "there is no audio output device".to_string())?;
+        let config = device
+            .default_output_config()
+            .map_err(|e| format!("could not configure the audio output: {e}"))?;
+        if config.sample_format() != SampleFormat::F32 {
+            return Err(format!(
+                "unsupported audio sample format {:?}",
+                config.sample_format()
+            ));
+        }
+        let config: StreamConfig = config.into();
+        let channels = config.channels as usize;
+        // How far through the program's samples each of the output's samples moves.
+        let step = self.audio.sample_rate() as f64 / config.sample_rate.0 as f64;
+        let mut position = 1.0;
+        let mut current = 0.0;
+        let queue = self.queue.clone();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &OutputCallbackInfo| {
+                    let mut queue = queue.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        while position >= 1.0 {
+                            position -= 1.0;
+                            current = queue
+                                .pop_front()
+                                .map_or(0.0, |sample| sample as f32 / 32768.0);
+                        }
+                        position += step;
+                        frame.fill(current);
+                    }
+                },
+                |e| error!("Audio stream error: {e}"),
+                None,
+            )
+            .map_err(|e| format!("could not open the audio output: {e}"))?;
+        stream
+            .play()
+            .map_err(|e| format!("could not play the audio output: {e}"))?;
+        Ok(stream)
+    }
+}
+
+impl<D: Device> Device for SpeakerDevice<D> {
+    fn get(&mut self, src: Input) -> Result<i64, String> {
+        self.device.get(src)
+    }
+
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+        if dst.channel.0 != self.channel || !AudioBuffer::handles(&dst) {
+            return self.device.put(val, dst);
+        }
+        if dst.mode == OutputMode::SampleRate && self.stream.is_some() {
+            warn!("The sample rate can't change after the sound starts");
+            return Ok(());
+        }
+        self.audio.put(val, &dst);
+        if dst.mode == OutputMode::Sample {
+            if self.stream.is_none() {
+                self.stream = Some(self.open()?);
+            }
+            self.queue.lock().unwrap().extend(self.audio.take());
+            // Stay at most a quarter of a second ahead of the speakers.
+            let ahead = self.audio.sample_rate() as usize / 4;
+            while self.queue.lock().unwrap().len() > ahead {
+                sleep(Duration::from_millis(1));
+            }
+        }
+        Ok(())
+    }
+
+    fn peek(&mut self) -> Result<i64, String> {
+        self.device.peek()
+    }
+
+    fn poke(&mut self, val: i64) -> Result<(), String> {
+        self.device.poke(val)
+    }
+
+    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+        self.device.ffi_call(ffi, tape)
+    }
+}
+
+impl<D: Device> Drop for SpeakerDevice<D> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
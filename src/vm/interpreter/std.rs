@@ -3,8 +3,14 @@
 //! This module implements an interpreter for the Standard virtual machine
 //! variant.
 
-use super::TAPE_EXTENSION_SIZE;
+use super::{
+    instruction_location,
+    sanitizer::{self, Sanitizer},
+    Leak, Limits, Profile, RuntimeError, TAPE_EXTENSION_SIZE,
+};
+use crate::side_effects::OutputMode;
 use crate::vm::{CoreOp, Device, StandardDevice, StandardOp, StandardProgram};
+use std::collections::BTreeMap;
 
 /// A function to reinterpret the bits of an integer as a float.
 pub fn as_float(n: i64) -> f64 {
@@ -16,6 +22,14 @@ pub fn as_int(n: f64) -> i64 {
     n.to_bits() as i64
 }
 
+/// The comment at an index in a program, if there is one.
+fn comment(code: &StandardProgram, i: usize) -> Option<&str> {
+    match code.0.get(i) {
+        Some(StandardOp::CoreOp(CoreOp::Comment(comment))) => Some(comment.as_str()),
+        _ => None,
+    }
+}
+
 impl Default for StandardInterpreter<StandardDevice> {
     fn default() -> Self {
         Self::new(StandardDevice::default())
@@ -23,6 +37,7 @@ impl Default for StandardInterpreter<StandardDevice> {
 }
 
 /// The interpreter which runs the standard variant of virtual machine programs.
+#[derive(Clone)]
 pub struct StandardInterpreter<T>
 where
     T: Device,
@@ -35,6 +50,13 @@ where
     register: Vec<i64>,
     /// The turing tape (composed of integer cells)
     cells: Vec<i64>,
+    /// The sizes of the blocks of memory allocated on the tape, by their addresses.
+    allocations: BTreeMap<usize, usize>,
+    /// The blocks of freed memory which can be reused, as their addresses and sizes.
+    freed: Vec<(usize, usize)>,
+    /// The instructions which allocated the blocks of memory, by their addresses,
+    /// if the interpreter is checking for leaks.
+    sites: Option<BTreeMap<usize, usize>>,
     /// The addresses of defined functions. `functions[N]` is the
     /// instruction pointer for the Nth function's code.
     functions: Vec<usize>,
@@ -49,6 +71,18 @@ where
     i: usize,
     /// Is the interpreter finished interpreting?s
     done: bool,
+    /// The exit status the program halted with, if it halted itself.
+    status: i64,
+    /// The number of instructions executed so far, not counting comments.
+    instructions: u64,
+    /// The number of times each comment has been passed, by its index in the program.
+    comment_hits: Vec<u64>,
+    /// The number of values read and written so far, through input and output.
+    io: u64,
+    /// The limits on what the program can do.
+    limits: Limits,
+    /// The shadow of the memory, if the sanitizer is checking it.
+    sanitizer: Option<Sanitizer>,
 }
 
 impl<T> StandardInterpreter<T>
@@ -61,14 +95,43 @@ where
             pointer: 0,
             register: vec![0; 1024],
             cells: vec![],
+            allocations: BTreeMap::new(),
+            freed: vec![],
+            sites: None,
             functions: vec![],
             calls: vec![],
             refs: vec![],
             i: 0,
             done: false,
+            status: 0,
+            instructions: 0,
+            comment_hits: vec![],
+            io: 0,
+            limits: Limits::default(),
+            sanitizer: None,
         }
     }
 
+    /// Limit what programs run by this interpreter can do.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Check the program's memory as it runs, and stop it with a `RuntimeError::Memory`
+    /// when it uses an uninitialized value or freed memory. This makes programs much slower.
+    pub fn with_sanitizer(mut self, enabled: bool) -> Self {
+        self.sanitizer = enabled.then(Sanitizer::default);
+        self
+    }
+
+    /// Remember where each block of memory is allocated, and report the blocks
+    /// which are never freed in the `leaks` of the program's profile.
+    pub fn with_leak_check(mut self, enabled: bool) -> Self {
+        self.sites = enabled.then(BTreeMap::new);
+        self
+    }
+
     fn reg_scalar(&self) -> i64 {
         self.register[0]
     }
@@ -261,21 +324,282 @@ where
         &mut self.cells[self.pointer]
     }
 
+    /// Allocate a block of cells, reusing freed memory if a block is large enough.
+    /// Returns the address of the first cell.
+    fn alloc(&mut self, size: usize) -> usize {
+        let result = match self.freed.iter().position(|(_, free)| *free >= size) {
+            Some(i) => {
+                let (addr, free) = self.freed.remove(i);
+                if free > size {
+                    self.freed.push((addr + size, free - size));
+                }
+                self.cells[addr..addr + size].fill(0);
+                addr
+            }
+            None => {
+                // If the virtual machine doesn't have a thousand cells,
+                // allocate some.
+                if self.cells.len() < 30000 {
+                    self.cells.extend(vec![0; 30000]);
+                }
+                // Save the address of where the new cells will start.
+                let result = self.cells.len() - 1;
+                // Allocate new space at the end of the type.
+                self.cells.extend(vec![0; size]);
+                result
+            }
+        };
+        self.allocations.insert(result, size);
+        if let Some(sites) = &mut self.sites {
+            sites.insert(result, self.i);
+        }
+        if let Some(sanitizer) = &mut self.sanitizer {
+            sanitizer.allocate(result, size);
+        }
+        result
+    }
+
+    /// Free the block of cells allocated at an address.
+    fn free(&mut self, addr: usize) {
+        if let Some(size) = self.allocations.remove(&addr) {
+            self.freed.push((addr, size));
+            if let Some(sites) = &mut self.sites {
+                sites.remove(&addr);
+            }
+            if let Some(sanitizer) = &mut self.sanitizer {
+                sanitizer.free(addr, size);
+            }
+        }
+    }
+
+    /// Resize the block of cells allocated at an address, keeping its contents.
+    /// Returns the address of the resized block.
+    fn realloc(&mut self, addr: usize, size: usize) -> usize {
+        let Some(&old) = self.allocations.get(&addr) else {
+            return self.alloc(size);
+        };
+        if size <= old {
+            // Shrink the block in place.
+            self.allocations.insert(addr, size);
+            if old > size {
+                self.freed.push((addr + size, old - size));
+                if let Some(sanitizer) = &mut self.sanitizer {
+                    sanitizer.free(addr + size, old - size);
+                }
+            }
+            return addr;
+        }
+        let result = self.alloc(size);
+        self.cells.copy_within(addr..addr + old, result);
+        if let Some(sanitizer) = &mut self.sanitizer {
+            sanitizer.copy(addr, result, old);
+        }
+        self.free(addr);
+        result
+    }
+
     /// Run a core program using this interpreter and its device.
-    pub fn run(mut self, code: &StandardProgram) -> Result<T, String> {
-        while !self.done {
-            self.step(code)?
+    /// If the program halts itself with a failing exit status, that is returned as an error.
+    pub fn run(self, code: &StandardProgram) -> Result<T, RuntimeError> {
+        let (device, result) = self.run_with_device(code);
+        result.map(|_| device)
+    }
+
+    /// Run a standard program using this interpreter and its device, returning the device
+    /// even if the program fails, so that what it wrote before failing can be inspected.
+    pub fn run_with_device(self, code: &StandardProgram) -> (T, Result<(), RuntimeError>) {
+        let profile = self.profile(code);
+        (profile.device, profile.result)
+    }
+
+    /// Run a standard program using this interpreter and its device,
+    /// counting the instructions it executes.
+    pub fn profile(mut self, code: &StandardProgram) -> Profile<T> {
+        self.comment_hits = vec![0; code.0.len()];
+        let result = self.run_until_done(code);
+        // Leaks are only reported when the program exits, not when it's stopped.
+        let leaks = match (&self.sites, &result) {
+            (Some(sites), Ok(()) | Err(RuntimeError::Exit(_))) => self
+                .allocations
+                .iter()
+                .map(|(&addr, &size)| Leak {
+                    addr,
+                    size,
+                    site: instruction_location(sites[&addr], |i| comment(code, i)),
+                })
+                .collect(),
+            _ => vec![],
+        };
+        Profile {
+            device: self.device,
+            instructions: self.instructions,
+            comment_hits: self.comment_hits,
+            result,
+            leaks,
         }
+    }
+
+    /// Run more of a program, starting from the instruction at `start`, without resetting
+    /// the tape, the heap, or the device. The program must extend the one this interpreter
+    /// ran before, keeping its procedures and the code outside of them, so that the state
+    /// left behind by that program is still valid. This is how the REPL runs each input.
+    pub fn resume(&mut self, code: &StandardProgram, start: usize) -> Result<(), RuntimeError> {
+        self.i = start;
+        self.done = false;
+        self.status = 0;
+        self.comment_hits.resize(code.0.len(), 0);
+        // The new procedures are defined before the new code outside of them, which moves it.
+        // So, find every procedure again, since the code before `start` may not be run.
+        self.functions = code
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| matches!(op, StandardOp::CoreOp(CoreOp::Function)))
+            .map(|(i, _)| i)
+            .collect();
+        self.run_until_done(code)
+    }
 
-        Ok(self.device)
+    /// Get the interpreter's device, to inspect what the program has done with it so far.
+    pub fn device_mut(&mut self) -> &mut T {
+        &mut self.device
+    }
+
+    /// Run the program from the current instruction until it halts or fails.
+    fn run_until_done(&mut self, code: &StandardProgram) -> Result<(), RuntimeError> {
+        let started = ::std::time::Instant::now();
+        loop {
+            if self.done {
+                return match self.status {
+                    0 => Ok(()),
+                    status => Err(RuntimeError::Exit(status)),
+                };
+            }
+            if let Err(limit) = self
+                .limits
+                .check(self.instructions, self.pointer, self.io, started)
+            {
+                return Err(RuntimeError::Limit(limit));
+            }
+            if let Err(e) = self.sanitize(code) {
+                return Err(RuntimeError::Memory(e));
+            }
+            if let Err(e) = self.step(code) {
+                return Err(RuntimeError::Machine(e));
+            }
+        }
+    }
+
+    /// Check the current instruction with the sanitizer before it runs, if it's enabled.
+    fn sanitize(&mut self, code: &StandardProgram) -> Result<(), String> {
+        let Some(mut shadow) = self.sanitizer.take() else {
+            return Ok(());
+        };
+        let result = match self.fetch(code) {
+            Some(op) => self.sanitize_op(&mut shadow, op, code),
+            None => Ok(()),
+        };
+        self.sanitizer = Some(shadow);
+        result.map_err(|error| {
+            sanitizer::diagnostic(&error, self.i, &self.calls, |i| comment(code, i))
+        })
+    }
+
+    /// Track what an instruction does with memory in the shadow, before it runs.
+    fn sanitize_op(
+        &mut self,
+        shadow: &mut Sanitizer,
+        op: &StandardOp,
+        code: &StandardProgram,
+    ) -> Result<(), String> {
+        let pointer = self.pointer;
+        match op {
+            StandardOp::CoreOp(op) => shadow.core_op(op, pointer, || {
+                matches!(
+                    self.get_matching_for_end(code),
+                    Some(StandardOp::CoreOp(CoreOp::While))
+                )
+            })?,
+            StandardOp::Set(_) => shadow.define_register(),
+            StandardOp::Add(n)
+            | StandardOp::Sub(n)
+            | StandardOp::Mul(n)
+            | StandardOp::Div(n)
+            | StandardOp::Rem(n)
+            | StandardOp::Pow(n) => shadow.combine(pointer, *n)?,
+            StandardOp::Peek | StandardOp::HeapUsed | StandardOp::HeapFree => {
+                shadow.set_scalar(None)
+            }
+            StandardOp::Poke => sanitizer::check_used(shadow.scalar(), "a foreign function")?,
+
+            StandardOp::Alloc => {
+                sanitizer::check_used(shadow.scalar(), "an allocation")?;
+                shadow.set_scalar(None);
+            }
+            StandardOp::Free => {
+                sanitizer::check_used(shadow.scalar(), "a free")?;
+                let addr = self.reg_scalar() as usize;
+                if shadow.is_freed(addr) {
+                    return Err(format!("double free of address {addr}"));
+                }
+            }
+            StandardOp::Realloc => {
+                sanitizer::check_used(shadow.scalar(), "a reallocation")?;
+                sanitizer::check_used(shadow.read_cell(pointer)?, "a reallocation")?;
+                let addr = self.reg_scalar() as usize;
+                if shadow.is_freed(addr) {
+                    return Err(format!("reallocation of freed memory at address {addr}"));
+                }
+                shadow.set_scalar(None);
+            }
+
+            StandardOp::AtomicLoad => {
+                sanitizer::check_used(shadow.scalar(), "an atomic load")?;
+                let origin = shadow.read_cell(self.reg_scalar() as usize)?;
+                shadow.set_scalar(origin);
+            }
+            StandardOp::AtomicStore => {
+                sanitizer::check_used(shadow.scalar(), "an atomic store")?;
+                let origin = shadow.read_cell(pointer)?;
+                shadow.write_cell(self.reg_scalar() as usize, origin)?;
+            }
+            StandardOp::AtomicAdd => {
+                sanitizer::check_used(shadow.scalar(), "an atomic add")?;
+                let addr = self.reg_scalar() as usize;
+                let old = shadow.read_cell(addr)?;
+                let origin = old.or(shadow.read_cell(pointer)?);
+                shadow.write_cell(addr, origin)?;
+                shadow.set_scalar(old);
+            }
+            StandardOp::AtomicCompareSwap => {
+                sanitizer::check_used(shadow.scalar(), "an atomic compare and swap")?;
+                let addr = self.reg_scalar() as usize;
+                let compared = shadow.read_cell(addr)?.or(shadow.read_cell(pointer)?);
+                sanitizer::check_used(compared, "an atomic compare and swap")?;
+                if self.cells.get(addr) == self.cells.get(pointer) {
+                    let origin = shadow.read_cell(pointer + 1)?;
+                    shadow.write_cell(addr, origin)?;
+                }
+                shadow.set_scalar(None);
+            }
+            _ => {}
+        }
+        Ok(())
     }
 
     /// Run a single step of the interpreter.
     fn step(&mut self, code: &StandardProgram) -> Result<(), String> {
         if let Some(op) = self.fetch(code) {
+            if !matches!(op, StandardOp::CoreOp(CoreOp::Comment(_))) {
+                self.instructions += 1;
+            }
             match op {
                 StandardOp::CoreOp(core_op) => match core_op {
-                    CoreOp::Comment(_) => {}
+                    CoreOp::Comment(_) => {
+                        if let Some(hits) = self.comment_hits.get_mut(self.i) {
+                            *hits += 1;
+                        }
+                    }
                     CoreOp::Set(n) => *self.reg_mut_vector() = n.clone(),
                     CoreOp::Function => {
                         if !self.functions.contains(&self.i) {
@@ -497,8 +821,23 @@ where
                             self.reg_mut_vector()[i] = i64::from(self.reg_vector()[i] >= 0);
                         }
                     }
-                    CoreOp::Get(i) => *self.reg_mut_scalar() = self.device.get(i.clone())?,
-                    CoreOp::Put(o) => self.device.put(self.reg_scalar(), o.clone())?,
+                    CoreOp::Get(i) => {
+                        self.io += 1;
+                        if self.limits.allows_io(self.io) {
+                            *self.reg_mut_scalar() = self.device.get(i.clone())?
+                        }
+                    }
+                    CoreOp::Put(o) if o.mode == OutputMode::Exit => {
+                        // Halt the program with the exit status in the register.
+                        self.status = self.reg_scalar();
+                        self.done = true
+                    }
+                    CoreOp::Put(o) => {
+                        self.io += 1;
+                        if self.limits.allows_io(self.io) {
+                            self.device.put(self.reg_scalar(), o.clone())?
+                        }
+                    }
                 },
 
                 StandardOp::Set(n) => {
@@ -609,21 +948,57 @@ where
                 }
 
                 StandardOp::Alloc => {
-                    // If the virtual machine doesn't have a thousand cells,
-                    // allocate some.
-                    if self.cells.len() < 30000 {
-                        self.cells.extend(vec![0; 30000]);
-                    }
-                    // Save the address of where the new cells will start.
-                    let result = self.cells.len() - 1;
-                    // Allocate new space at the end of the type.
-                    self.cells.extend(vec![0; self.reg_scalar() as usize]);
+                    let result = self.alloc(self.reg_scalar() as usize);
                     // Store the address of the new space in the register.
                     *self.reg_mut_scalar() = result as i64;
                 }
-                StandardOp::Free => {}
+                StandardOp::Free => self.free(self.reg_scalar() as usize),
+                StandardOp::Realloc => {
+                    let size = *self.get_cell() as usize;
+                    let result = self.realloc(self.reg_scalar() as usize, size);
+                    *self.reg_mut_scalar() = result as i64;
+                }
+                StandardOp::HeapUsed => {
+                    let used: usize = self.allocations.values().sum();
+                    *self.reg_mut_scalar() = used as i64;
+                }
+                StandardOp::HeapFree => {
+                    let free: usize = self.freed.iter().map(|(_, size)| size).sum();
+                    *self.reg_mut_scalar() = free as i64;
+                }
+
+                // The interpreter is single-threaded, so every operation is already atomic.
+                StandardOp::AtomicLoad => {
+                    let val = self.cells[self.reg_scalar() as usize];
+                    *self.reg_mut_scalar() = val;
+                }
+                StandardOp::AtomicStore => {
+                    let addr = self.reg_scalar() as usize;
+                    self.cells[addr] = self.cells[self.pointer];
+                }
+                StandardOp::AtomicAdd => {
+                    let addr = self.reg_scalar() as usize;
+                    let old = self.cells[addr];
+                    self.cells[addr] = old.wrapping_add(self.cells[self.pointer]);
+                    *self.reg_mut_scalar() = old;
+                }
+                StandardOp::AtomicCompareSwap => {
+                    let addr = self.reg_scalar() as usize;
+                    let swapped = self.cells[addr] == self.cells[self.pointer];
+                    if swapped {
+                        self.cells[addr] = self.cells[self.pointer + 1];
+                    } else {
+                        self.cells[self.pointer] = self.cells[addr];
+                    }
+                    *self.reg_mut_scalar() = swapped as i64;
+                }
                 StandardOp::Call(binding) => {
+                    // The sanitizer can't see which cells the foreign function writes.
+                    let before = self.sanitizer.is_some().then(|| self.cells.clone());
                     self.device.ffi_call(binding, Some(&mut self.cells))?;
+                    if let (Some(sanitizer), Some(before)) = (&mut self.sanitizer, before) {
+                        sanitizer.define_changed(&before, &self.cells);
+                    }
                 }
             }
             self.i += 1
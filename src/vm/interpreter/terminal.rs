@@ -0,0 +1,139 @@
+//! A device which reads keys from the terminal as they're pressed.
+//!
+//! This is only available with the `terminal` feature, which uses `crossterm`
+//! to put the terminal in raw mode.
+use super::Device;
+use crate::side_effects::{
+    FFIBinding, Input, InputMode, Output, OutputMode, KEY_DOWN, KEY_LEFT, KEY_RIGHT, KEY_UP, NO_KEY,
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    terminal,
+};
+use std::time::Duration;
+
+/// A device which polls keys and the size of the terminal, and passes everything
+/// else on to another device. The terminal is put in raw mode when the program
+/// first polls a key, and back in its normal mode when the program reads from
+/// standard input, or when the device is dropped.
+pub struct TerminalDevice<D: Device> {
+    device: D,
+    /// Is the terminal in raw mode?
+    raw: bool,
+}
+
+impl<D: Device> TerminalDevice<D> {
+    /// Poll keys from the terminal, and pass everything else on to a device.
+    pub fn new(device: D) -> Self {
+        Self { device, raw: false }
+    }
+
+    /// Put the terminal in raw mode, or back in its normal mode.
+    fn set_raw(&mut self, raw: bool) -> Result<(), String> {
+        if self.raw != raw {
+            if raw {
+                terminal::enable_raw_mode()
+            } else {
+                terminal::disable_raw_mode()
+            }
+            .map_err(|e| format!("could not set the terminal mode: {e}"))?;
+            self.raw = raw;
+        }
+        Ok(())
+    }
+
+    /// The next key pressed, or `NO_KEY` if there isn't one waiting.
+    fn poll_key(&mut self) -> Result<i64, String> {
+        self.set_raw(true)?;
+        let error = |e| format!("could not read from the terminal: {e}");
+        while event::poll(Duration::ZERO).map_err(error)? {
+            if let Event::Key(key) = event::read().map_err(error)? {
+                // Raw mode stops the terminal from interrupting the program itself.
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    return Err("interrupted".to_string());
+                }
+                if let Some(code) = key_code(&key) {
+                    return Ok(code);
+                }
+            }
+        }
+        Ok(NO_KEY)
+    }
+}
+
+/// The value a program reads for a key press, if it's one programs can read.
+fn key_code(key: &KeyEvent) -> Option<i64> {
+    if key.kind != KeyEventKind::Press {
+        return None;
+    }
+    Some(match key.code {
+        KeyCode::Char(ch)
+            if key.modifiers.contains(KeyModifiers::CONTROL) && ch.is_ascii_alphabetic() =>
+        {
+            (ch.to_ascii_lowercase() as u8 & 0x1f) as i64
+        }
+        KeyCode::Char(ch) => ch as i64,
+        KeyCode::Backspace => 8,
+        KeyCode::Tab => 9,
+        KeyCode::Enter => 10,
+        KeyCode::Esc => 27,
+        KeyCode::Up => KEY_UP,
+        KeyCode::Down => KEY_DOWN,
+        KeyCode::Left => KEY_LEFT,
+        KeyCode::Right => KEY_RIGHT,
+        _ => return None,
+    })
+}
+
+impl<D: Device> Device for TerminalDevice<D> {
+    fn get(&mut self, src: Input) -> Result<i64, String> {
+        match src.mode {
+            InputMode::KeyPoll => self.poll_key(),
+            InputMode::TerminalWidth | InputMode::TerminalHeight => {
+                let (width, height) = terminal::size()
+                    .map_err(|e| format!("could not get the size of the terminal: {e}"))?;
+                Ok(if src.mode == InputMode::TerminalWidth {
+                    width as i64
+                } else {
+                    height as i64
+                })
+            }
+            InputMode::StdinChar | InputMode::StdinInt | InputMode::StdinFloat => {
+                // Let the user see and edit what they type.
+                self.set_raw(false)?;
+                self.device.get(src)
+            }
+            _ => self.device.get(src),
+        }
+    }
+
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+        // Raw mode doesn't move back to the start of the line after a newline.
+        if self.raw
+            && val == '\n' as i64
+            && matches!(dst.mode, OutputMode::StdoutChar | OutputMode::StderrChar)
+        {
+            self.device.put('\r' as i64, dst.clone())?;
+        }
+        self.device.put(val, dst)
+    }
+
+    fn peek(&mut self) -> Result<i64, String> {
+        self.device.peek()
+    }
+
+    fn poke(&mut self, val: i64) -> Result<(), String> {
+        self.device.poke(val)
+    }
+
+    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+        self.device.ffi_call(ffi, tape)
+    }
+}
+
+impl<D: Device> Drop for TerminalDevice<D> {
+    fn drop(&mut self) {
+        let _ = self.set_raw(false);
+    }
+}
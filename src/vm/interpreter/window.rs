@@ -0,0 +1,96 @@
+//! A device which shows a program's framebuffer in a window.
+//!
+//! This is only available with the `framebuffer` feature, which uses `minifb`
+//! to open the window.
+use super::Device;
+use crate::side_effects::{FFIBinding, Framebuffer, Input, Output};
+
+use log::trace;
+use minifb::{Window, WindowOptions};
+
+/// A device which draws the framebuffer on one channel to a window, and passes
+/// everything else on to another device. The window opens when the program
+/// presents its first frame, and the program stops if the window is closed.
+pub struct WindowDevice<D: Device> {
+    device: D,
+    /// The channel the framebuffer is drawn on.
+    channel: usize,
+    framebuffer: Framebuffer,
+    window: Option<Window>,
+}
+
+impl<D: Device> WindowDevice<D> {
+    /// Show the framebuffer drawn on channel 0 in a window of a given size,
+    /// and pass everything else on to a device.
+    pub fn new(device: D, width: usize, height: usize) -> Self {
+        Self {
+            device,
+            channel: 0,
+            framebuffer: Framebuffer::new(width, height),
+            window: None,
+        }
+    }
+
+    /// Show the framebuffer drawn on a different channel.
+    pub fn with_channel(mut self, channel: usize) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// The framebuffer as it was last drawn.
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// The device which everything but the framebuffer is passed on to.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Show the framebuffer in the window, opening it if it isn't open yet.
+    fn present(&mut self) -> Result<(), String> {
+        let (width, height) = (self.framebuffer.width(), self.framebuffer.height());
+        if self.window.is_none() {
+            let window = Window::new("Sage", width, height, WindowOptions::default())
+                .map_err(|e| format!("could not open a window: {e}"))?;
+            self.window = Some(window);
+        }
+        let window = self.window.as_mut().unwrap();
+        if !window.is_open() {
+            return Err("the window was closed".to_string());
+        }
+        trace!("Presenting frame {}", self.framebuffer.frames());
+        window
+            .update_with_buffer(&self.framebuffer.pixels(), width, height)
+            .map_err(|e| format!("could not draw to the window: {e}"))
+    }
+}
+
+impl<D: Device> Device for WindowDevice<D> {
+    fn get(&mut self, src: Input) -> Result<i64, String> {
+        self.device.get(src)
+    }
+
+    fn put(&mut self, val: i64, dst: Output) -> Result<(), String> {
+        if dst.channel.0 == self.channel && Framebuffer::handles(&dst) {
+            if self.framebuffer.put(val, &dst) {
+                self.present()?;
+            }
+            Ok(())
+        } else {
+            self.device.put(val, dst)
+        }
+    }
+
+    fn peek(&mut self) -> Result<i64, String> {
+        self.device.peek()
+    }
+
+    fn poke(&mut self, val: i64) -> Result<(), String> {
+        self.device.poke(val)
+    }
+
+    fn ffi_call(&mut self, ffi: &FFIBinding, tape: Option<&mut Vec<i64>>) -> Result<(), String> {
+        self.device.ffi_call(ffi, tape)
+    }
+}
@@ -85,6 +85,44 @@ impl Display for Error {
     }
 }
 
+/// The comment which begins the body of a function assembled from a label,
+/// so that targets can name the function after the label.
+pub fn label_comment(label: &str) -> String {
+    format!("fun @{label}")
+}
+
+/// Get the label named by a comment made with `label_comment`.
+pub fn comment_label(comment: &str) -> Option<&str> {
+    comment.strip_prefix("fun @")
+}
+
+/// The comment which marks the line of source code that the following instructions
+/// were compiled from. This is written as a C `#line` directive.
+pub fn line_comment(line: usize, filename: Option<&str>) -> String {
+    match filename {
+        Some(filename) => format!(
+            "#line {line} \"{}\"",
+            filename.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("#line {line}"),
+    }
+}
+
+/// Is a comment one made with `line_comment`?
+pub fn is_line_comment(comment: &str) -> bool {
+    comment.starts_with("#line ")
+}
+
+/// Get the line and the (still escaped) filename marked by a comment made with `line_comment`.
+pub fn comment_line(comment: &str) -> Option<(usize, Option<&str>)> {
+    let rest = comment.strip_prefix("#line ")?;
+    let (line, filename) = match rest.split_once(' ') {
+        Some((line, filename)) => (line, Some(filename.strip_prefix('"')?.strip_suffix('"')?)),
+        None => (rest, None),
+    };
+    Some((line.parse().ok()?, filename))
+}
+
 /// An interface to conveniently create virtual machine programs,
 /// of either the core or standard variant.
 pub trait VirtualMachineProgram {
@@ -302,6 +302,28 @@ pub enum StandardOp {
 
     /// Free the memory pointed to by the register.
     Free,
+    /// Resize the memory pointed to by the register to the number of cells pointed
+    /// to on the tape. Set the register to the address of the resized memory, which
+    /// holds the contents of the original memory.
+    Realloc,
+    /// Set the register to the number of cells currently allocated.
+    HeapUsed,
+    /// Set the register to the number of freed cells that the allocator can reuse.
+    HeapFree,
+
+    /// Atomically load the cell at the address in the register into the register.
+    AtomicLoad,
+    /// Atomically store the value pointed to on the tape into the cell at the
+    /// address in the register.
+    AtomicStore,
+    /// Atomically add the value pointed to on the tape to the cell at the address
+    /// in the register, and store the cell's previous value in the register.
+    AtomicAdd,
+    /// Atomically compare the cell at the address in the register with the value
+    /// pointed to on the tape. If they are equal, replace the cell with the value
+    /// in the next cell on the tape, and set the register to 1. Otherwise, store the
+    /// cell's current value in the cell pointed to on the tape, and set the register to 0.
+    AtomicCompareSwap,
 
     /// Convert the register from a float to an integer.
     ToInt(usize),
@@ -370,6 +392,13 @@ impl fmt::Display for StandardOp {
             StandardOp::Set(val) => write!(f, "set-f {:?}", val),
             StandardOp::Alloc => write!(f, "alloc"),
             StandardOp::Free => write!(f, "free"),
+            StandardOp::Realloc => write!(f, "realloc"),
+            StandardOp::HeapUsed => write!(f, "heap-used"),
+            StandardOp::HeapFree => write!(f, "heap-free"),
+            StandardOp::AtomicLoad => write!(f, "atomic-load"),
+            StandardOp::AtomicStore => write!(f, "atomic-store"),
+            StandardOp::AtomicAdd => write!(f, "atomic-add"),
+            StandardOp::AtomicCompareSwap => write!(f, "atomic-cas"),
             StandardOp::ToInt(n) => write!(f, "to-int {n}"),
             StandardOp::ToFloat(n) => write!(f, "to-float {n}"),
             StandardOp::Add(n) => write!(f, "add-f {n}"),
@@ -0,0 +1,144 @@
+mod support;
+
+use sage::{vm::RuntimeError, BuildProfile, Compiler};
+use support::{compile, run_interpreter, run_with, with_large_stack, RunOptions};
+
+/// Arrays of copies of an element, whose element is only evaluated once.
+const ARRAY_REPEAT: &str = r#"
+fun tick(n: Int): Int {
+    print("tick ");
+    return n;
+}
+
+let zeros = [0; 4];
+let mut counts = [tick(1); 3];
+counts[1] = 5;
+let empty = [tick(2); 0];
+let rows = [[7; 2]; 3];
+let pairs = [(3, 'a'); 2];
+println();
+println(zeros, " ", counts, " ", empty);
+println(rows[0], " ", rows[2], " ", pairs[1].0, pairs[1].1);
+"#;
+
+/// Indices into an array which can be referenced, and into nested arrays which can't.
+const INDICES: &str = r#"
+fun get(xs: [Int * 3], i: Int): Int {
+    return xs[i];
+}
+
+fun grid(): [Int * 2, 2] {
+    return [[1, 2], [3, 4]];
+}
+
+println(get([10, 20, 30], 2), " ", grid()[1][0]);
+println(grid()[{row}][0]);
+println(get([10, 20, 30], {index}));
+"#;
+
+/// Every element of a multi-dimensional array, indexed through a chain of indices
+/// into an array which can't be referenced, which are folded into one offset,
+/// and through indices into a variable, which aren't.
+const FOLDED_INDICES: &str = r#"
+fun cube(): [(Int, Int) * 2, 3, 4] {
+    let mut result = [[[(0, 0); 4]; 3]; 2];
+    for let mut i = 0; i < 2; i += 1; {
+        for let mut j = 0; j < 3; j += 1; {
+            for let mut k = 0; k < 4; k += 1; {
+                result[i][j][k] = (i * 100 + j * 10 + k, i + j + k);
+            }
+        }
+    }
+    return result;
+}
+
+let stored = cube();
+let mut mismatches = 0;
+let mut total = 0;
+for let mut i = 0; i < 2; i += 1; {
+    for let mut j = 0; j < 3; j += 1; {
+        for let mut k = 0; k < 4; k += 1; {
+            let folded = cube()[i][j][k];
+            let unfolded = stored[i, j, k];
+            let row = cube()[i][j];
+            if folded.0 != unfolded.0 || folded.1 != unfolded.1 || row[k].0 != folded.0 {
+                mismatches += 1;
+            }
+            total += folded.0 + folded.1;
+        }
+    }
+}
+println(mismatches, " ", total, " ", cube()[1][2][3].0, " ", stored[1, 2, 3].1);
+"#;
+
+#[test]
+fn test_array_repeat() {
+    with_large_stack(test_array_repeat_helper);
+}
+
+fn test_array_repeat_helper() {
+    let program = compile(Compiler::new(ARRAY_REPEAT));
+    assert_eq!(
+        run_interpreter(&program, ""),
+        "tick tick \n[0, 0, 0, 0] [1, 5, 1] []\n[7, 7] [7, 7] 3a\n"
+    );
+}
+
+#[test]
+fn test_bounds_checks() {
+    with_large_stack(test_bounds_checks_helper);
+}
+
+/// Run the indices program with a row of the grid and an index into the array,
+/// returning what it wrote to stdout and stderr, and how it stopped.
+fn run_indices(
+    row: &str,
+    index: &str,
+    profile: BuildProfile,
+) -> (String, String, Result<(), RuntimeError>) {
+    let src = INDICES.replace("{row}", row).replace("{index}", index);
+    let program = compile(Compiler::new(src).filename("indices.sg").profile(profile));
+    let profile = run_with(&program, RunOptions::default());
+    (
+        profile.device.output_str(),
+        profile.device.error_str(),
+        profile.result,
+    )
+}
+
+fn test_bounds_checks_helper() {
+    // Indices in bounds give the same values with and without the checks.
+    for profile in [BuildProfile::debug(), BuildProfile::release()] {
+        let (stdout, stderr, result) = run_indices("1", "1", profile);
+        assert_eq!(stdout, "30 3\n3\n20\n");
+        assert_eq!(stderr, "");
+        assert_eq!(result, Ok(()));
+    }
+
+    // An index past the end of the nested arrays, which can't be referenced.
+    let (stdout, stderr, result) = run_indices("2", "1", BuildProfile::debug());
+    assert_eq!(stdout, "30 3\n");
+    assert!(stderr.starts_with("panicked at indices.sg:11:"), "{stderr}");
+    assert!(stderr.ends_with(": index out of bounds for array of length 2\n"));
+    assert_eq!(result, Err(RuntimeError::Exit(1)));
+
+    // A negative index into the array which can be referenced.
+    let (stdout, stderr, result) = run_indices("0", "0 - 1", BuildProfile::debug());
+    assert_eq!(stdout, "30 3\n1\n");
+    assert!(stderr.starts_with("panicked at indices.sg:3:"), "{stderr}");
+    assert!(stderr.ends_with(": index out of bounds for array of length 3\n"));
+    assert_eq!(result, Err(RuntimeError::Exit(1)));
+}
+
+#[test]
+fn test_folded_indices() {
+    with_large_stack(test_folded_indices_helper);
+}
+
+fn test_folded_indices_helper() {
+    // The folded indices are checked in each dimension with the bounds checks.
+    for profile in [BuildProfile::default(), BuildProfile::debug()] {
+        let program = compile(Compiler::new(FOLDED_INDICES).profile(profile));
+        assert_eq!(run_interpreter(&program, ""), "0 1548 123 6\n");
+    }
+}
@@ -365,3 +365,25 @@ fn test_factorial() {
 
     assert_eq!(&device.output_str(), "3628800")
 }
+
+#[test]
+fn test_target_features() {
+    let features = TargetFeatures {
+        floats: false,
+        ..TargetFeatures::all("test")
+    };
+    let mut program = StandardProgram::default();
+    let mut target = TargetProgram::new(&mut program, &features);
+
+    target.op(CoreOp::Set(A, 5));
+    target.std_op(StandardOp::Alloc(A)).unwrap();
+    assert_eq!(
+        target.std_op(StandardOp::ToFloat(A)),
+        Err(Error::UnsupportedOnTarget(
+            StandardOp::ToFloat(A),
+            Feature::Floats,
+            "test".to_string()
+        ))
+    );
+    assert_eq!(program.code.len(), 2);
+}
@@ -0,0 +1,64 @@
+mod support;
+
+use sage::{compiler::Variant, side_effects::*, Compiler};
+use support::{compile, run_device, with_large_stack};
+
+#[test]
+fn test_audio() {
+    with_large_stack(test_audio_helper);
+}
+
+fn test_audio_helper() {
+    test_sound_builtins();
+    test_audio_convention();
+}
+
+fn test_sound_builtins() {
+    // A square wave at 1000 hertz, for 10 milliseconds.
+    let src = r#"
+let rate = 8000;
+set_sample_rate(rate);
+for let mut i=0; i<rate / 100; i+=1; {
+    if i % 8 < 4 {
+        play_sample(16000);
+    } else {
+        play_sample(-16000);
+    }
+}
+"#;
+    let program = compile(Compiler::new(src).variant(Variant::CoreOnly));
+    let device = run_device(&program, "");
+
+    let audio = &device.audio;
+    assert_eq!(audio.sample_rate(), 8000);
+    assert_eq!(audio.played(), 80);
+    assert_eq!(audio.duration(), 0.01);
+    assert_eq!(
+        &audio.samples()[..8],
+        &[16000, 16000, 16000, 16000, -16000, -16000, -16000, -16000]
+    );
+    // The testing device is silent: nothing is written to stdout.
+    assert_eq!(device.output_str(), "");
+}
+
+fn test_audio_convention() {
+    let mut audio = AudioBuffer::default();
+    let sample = Output::new(OutputMode::Sample, 0);
+    let rate = Output::new(OutputMode::SampleRate, 0);
+    assert_eq!(audio.sample_rate(), AudioBuffer::DEFAULT_SAMPLE_RATE);
+
+    // Invalid sample rates are ignored, and samples are clamped to 16 bits.
+    audio.put(0, &rate);
+    audio.put(22050, &rate);
+    audio.put(100000, &sample);
+    audio.put(-100000, &sample);
+    audio.put(7, &sample);
+    assert_eq!(audio.sample_rate(), 22050);
+    assert_eq!(audio.take(), vec![i16::MAX, i16::MIN, 7]);
+
+    // Taking the samples doesn't forget how many were played.
+    audio.put(1, &sample);
+    assert_eq!(audio.samples(), &[1]);
+    assert_eq!(audio.played(), 4);
+    assert!(!AudioBuffer::handles(&Output::stdout_char()));
+}
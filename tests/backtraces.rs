@@ -0,0 +1,20 @@
+mod support;
+
+use sage::{lir::Env, testing::*, Compiler};
+use std::fs::read_to_string;
+use support::{compile, with_large_stack};
+
+#[test]
+fn test_backtraces() {
+    with_large_stack(test_backtraces_helper);
+}
+
+fn test_backtraces_helper() {
+    // The example panics, so it's kept out of the examples which are run as they are.
+    let src = read_to_string("examples/frontend/backtraces/backtrace.sg").unwrap();
+    let mut env = Env::default();
+    env.set_backtraces(true);
+    let program = compile(Compiler::new(src).filename("backtrace.sg").env(env));
+    let snapshot = DeviceSnapshot::run(&program, "");
+    assert_snapshot("examples/test-output/backtrace.txt", &snapshot);
+}
@@ -0,0 +1,48 @@
+mod support;
+
+use sage::{frontend::BenchRunner, lir::Env};
+use support::with_large_stack;
+
+const SOURCE: &str = r#"fun count(n: Int): Int {
+    let mut i = 0;
+    while i < n {
+        i += 1;
+    }
+    return i;
+}
+
+#[bench]
+fun bench_small() {
+    count(10);
+}
+
+#[bench]
+fun bench_large() {
+    count(100);
+}
+"#;
+
+#[test]
+fn test_bench_runner() {
+    with_large_stack(test_bench_runner_helper);
+}
+
+fn test_bench_runner_helper() {
+    let runner = BenchRunner::new(Env::default(), 8192, 3);
+    let results = runner.run(SOURCE, None, None).unwrap();
+    let (small, large) = (&results[0], &results[1]);
+    assert_eq!(small.name, "bench_small");
+    assert_eq!(large.name, "bench_large");
+    assert_eq!(small.iterations, 3);
+
+    // The work done by the loop is counted, and the work done before the benchmark isn't.
+    assert!(small.instructions > 0);
+    assert!(large.instructions > small.instructions * 5);
+    assert!(large.change_since(small) > 400.0);
+
+    // The number of instructions is the same every time, so it can be compared to a baseline.
+    let again = runner.run(SOURCE, None, Some("large")).unwrap();
+    assert_eq!(again.len(), 1);
+    assert_eq!(again[0].instructions, large.instructions);
+    assert_eq!(again[0].change_since(large), 0.0);
+}
@@ -0,0 +1,111 @@
+mod support;
+
+use sage::{
+    side_effects::*,
+    vm::{StandardInterpreter, TestingDevice},
+    Compiler,
+};
+use std::collections::VecDeque;
+use support::{compile, with_large_stack};
+
+#[test]
+fn test_standard_bindings() {
+    with_large_stack(test_standard_bindings_helper);
+}
+
+fn test_standard_bindings_helper() {
+    let src = r#"
+from std.io import File;
+from std.time import Instant, Duration, sleep;
+
+let out = File.open(&"out.txt", File.WRITE).unwrap();
+println(out.write_str(&"hello, file"));
+out.close();
+
+let mut buf = ['\0'] * 32;
+let input = File.open(&"in.txt", File.READ).unwrap();
+let n = input.read((&mut buf) as &mut Char, 31);
+println(n);
+for let mut i=0; i<n; i+=1; {
+    print(buf[i]);
+}
+println();
+input.close();
+
+if File.open(&"missing.txt", File.READ).is_some() {
+    println(1);
+} else {
+    println(0);
+}
+
+let start = Instant.now();
+sleep(Duration.from_ms(1500));
+println(start.elapsed().us);
+"#;
+    let Err(code) = compile(Compiler::new(src)) else {
+        panic!("foreign functions need the standard variant");
+    };
+
+    let mut device = TestingDevice::new("");
+    device
+        .host
+        .files
+        .insert("in.txt".to_string(), b"file contents".to_vec());
+    let device = StandardInterpreter::new(device).run(&code).unwrap();
+    assert_eq!(device.output_str(), "11\n13\nfile contents\n0\n1500000\n");
+    assert_eq!(device.host.files["out.txt"], b"hello, file");
+    // Only the program's sleep moves the mock clock.
+    assert_eq!(device.host.now_us, 1500000);
+}
+
+/// Write a null-terminated string to the tape.
+fn write_string(tape: &mut [i64], addr: usize, s: &str) {
+    for (i, ch) in s.chars().chain(['\0']).enumerate() {
+        tape[addr + i] = ch as i64;
+    }
+}
+
+/// Call a standard binding on a host with some arguments, and return its result.
+fn call(host: &mut impl BindingHost, ffi: FFIBinding, args: &[i64], tape: &mut Vec<i64>) -> i64 {
+    let mut channel = args.iter().copied().collect::<VecDeque<_>>();
+    assert!(call_standard_binding(host, &ffi, &mut channel, Some(tape)).unwrap());
+    assert_eq!(channel.len(), ffi.output_cells);
+    channel.pop_front().unwrap_or(0)
+}
+
+#[test]
+fn test_system_host() {
+    let path = std::env::temp_dir().join(format!("sage-bindings-{}.txt", std::process::id()));
+    let mut host = SystemHost::default();
+    let mut tape = vec![0; 256];
+    write_string(&mut tape, 0, path.to_str().unwrap());
+    write_string(&mut tape, 128, "some text");
+
+    let file = call(&mut host, fs_open(), &[0, 1], &mut tape);
+    assert!(file >= 0);
+    assert_eq!(call(&mut host, fs_write(), &[file, 128, 9], &mut tape), 9);
+    call(&mut host, fs_close(), &[file], &mut tape);
+
+    let file = call(&mut host, fs_open(), &[0, 2], &mut tape);
+    assert_eq!(call(&mut host, fs_write(), &[file, 128, 4], &mut tape), 4);
+    call(&mut host, fs_close(), &[file], &mut tape);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "some textsome");
+
+    let file = call(&mut host, fs_open(), &[0, 0], &mut tape);
+    assert_eq!(call(&mut host, fs_read(), &[file, 200, 50], &mut tape), 13);
+    assert_eq!(tape[200], 's' as i64);
+    assert_eq!(tape[212], 'e' as i64);
+    call(&mut host, fs_close(), &[file], &mut tape);
+    // Closed files can't be used.
+    assert_eq!(call(&mut host, fs_read(), &[file, 200, 50], &mut tape), -1);
+    std::fs::remove_file(&path).unwrap();
+
+    // Missing files and invalid modes can't be opened.
+    assert_eq!(call(&mut host, fs_open(), &[0, 0], &mut tape), -1);
+    assert_eq!(call(&mut host, fs_open(), &[0, 7], &mut tape), -1);
+
+    assert!(call(&mut host, time_now(), &[], &mut tape) > 0);
+    let mut channel = VecDeque::new();
+    let unknown = FFIBinding::typed::<(), ()>("unknown");
+    assert!(!call_standard_binding(&mut host, &unknown, &mut channel, None).unwrap());
+}
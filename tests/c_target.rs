@@ -1,5 +1,10 @@
 use log::warn;
-use sage::{lir::Compile, parse::*, targets::*};
+use sage::{
+    lir::Compile,
+    parse::*,
+    targets::*,
+    vm::{CoreOp, CoreProgram},
+};
 use std::{
     fs::{read_dir, read_to_string},
     io::Write,
@@ -116,8 +121,8 @@ fn test_c_target_frontend_examples_helper() {
             .unwrap();
 
             let c_code = match vm_code {
-                Ok(vm_code) => C.build_core(&vm_code.flatten()).unwrap(),
-                Err(vm_code) => C.build_std(&vm_code.flatten()).unwrap(),
+                Ok(vm_code) => C::default().build_core(&vm_code.flatten()).unwrap(),
+                Err(vm_code) => C::default().build_std(&vm_code.flatten()).unwrap(),
             };
 
             // Write the C code to a file.
@@ -193,3 +198,51 @@ fn test_c_target_frontend_examples_helper() {
         );
     }
 }
+
+#[test]
+fn test_c_target_config() {
+    // Moving past the end of a four cell tape is only caught with bounds checking.
+    let program = CoreProgram(vec![CoreOp::Move(2), CoreOp::Move(5)]);
+    for bounds_check in [false, true] {
+        let mut target = C::default();
+        let config = target
+            .config()
+            .clone()
+            .with_tape_cells(4)
+            .with_bounds_check(bounds_check);
+        target.configure(config).unwrap();
+        let c_code = target.build_core(&program).unwrap();
+        assert!(c_code.contains("tape[4]"));
+
+        let c_code_path = format!("tmp_c_config_{bounds_check}.c");
+        let c_exe_path = format!("tmp_c_config_{bounds_check}.exe");
+        std::fs::write(&c_code_path, c_code).unwrap();
+        let c_compile_output = std::process::Command::new("gcc")
+            .arg(&c_code_path)
+            .arg("-o")
+            .arg(&c_exe_path)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(
+            c_compile_output.status.success(),
+            "Could not compile C code: {c_compile_output:?}"
+        );
+        let output = std::process::Command::new(format!("./{c_exe_path}"))
+            .output()
+            .unwrap();
+        assert_eq!(output.status.success(), !bounds_check);
+        std::fs::remove_file(&c_code_path).unwrap();
+        std::fs::remove_file(&c_exe_path).unwrap();
+    }
+
+    // The C target only has 64-bit cells.
+    let mut target = C::default();
+    let config = target.config().clone();
+    assert!(target
+        .configure(TargetConfig {
+            cell_width: 32,
+            ..config
+        })
+        .is_err());
+}
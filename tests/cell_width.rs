@@ -0,0 +1,90 @@
+use sage::lir::*;
+
+/// An environment for a target whose cells are `bits` wide.
+fn env_with_cell_width(bits: usize) -> Env {
+    let mut env = Env::default();
+    env.set_cfg("cell_width", Some(bits));
+    env
+}
+
+#[test]
+fn test_overflow_bounds() {
+    let eval = |op: OverflowArithmetic, a: i64, b: i64, env: &mut Env| {
+        op.eval(&ConstExpr::Int(a), &ConstExpr::Int(b), env)
+            .unwrap()
+    };
+    let saturating_add = OverflowArithmetic(Arithmetic::Add, Overflow::Saturating);
+    let checked_add = OverflowArithmetic(Arithmetic::Add, Overflow::Checked);
+    let wrapping_mul = OverflowArithmetic(Arithmetic::Multiply, Overflow::Wrapping);
+    let saturating_sub = OverflowArithmetic(Arithmetic::Subtract, Overflow::Saturating);
+
+    // On the virtual machine, an `Int` has 64 bits.
+    let mut env = Env::default();
+    assert_eq!(env.get_cell_width(), 64);
+    assert_eq!(
+        eval(saturating_add, i64::MAX, 1, &mut env),
+        ConstExpr::Int(i64::MAX)
+    );
+    assert_eq!(
+        eval(checked_add, 32767, 1, &mut env),
+        ConstExpr::Tuple(vec![ConstExpr::Int(32768), ConstExpr::Bool(false)])
+    );
+
+    // On a target with 16-bit cells, like the 6502, the bounds are those of a 16-bit integer.
+    let mut env = env_with_cell_width(16);
+    assert_eq!(env.get_cell_width(), 16);
+    assert_eq!(
+        eval(saturating_add, 32767, 1, &mut env),
+        ConstExpr::Int(32767)
+    );
+    assert_eq!(
+        eval(saturating_sub, -32768, 1, &mut env),
+        ConstExpr::Int(-32768)
+    );
+    assert_eq!(
+        eval(checked_add, 32767, 1, &mut env),
+        ConstExpr::Tuple(vec![ConstExpr::Int(-32768), ConstExpr::Bool(true)])
+    );
+    assert_eq!(eval(wrapping_mul, 256, 256, &mut env), ConstExpr::Int(0));
+
+    // On a target with 32-bit cells, like the Cortex-M.
+    let mut env = env_with_cell_width(32);
+    assert_eq!(
+        eval(saturating_add, i32::MAX as i64, 1, &mut env),
+        ConstExpr::Int(i32::MAX as i64)
+    );
+    assert_eq!(
+        eval(checked_add, i32::MAX as i64, 1, &mut env),
+        ConstExpr::Tuple(vec![ConstExpr::Int(i32::MIN as i64), ConstExpr::Bool(true)])
+    );
+}
+
+#[test]
+fn test_bitfield_cell_width() {
+    let bitfield = |fields: &[(&str, usize)]| {
+        Type::Bitfield(
+            fields
+                .iter()
+                .map(|(name, bits)| (name.to_string(), *bits))
+                .collect(),
+        )
+    };
+    let wide = bitfield(&[("a", 24)]);
+    let pair = bitfield(&[("a", 10), ("b", 10)]);
+
+    // Members can be as wide as a cell on the target, and no wider.
+    let env = Env::default();
+    assert!(wide.type_check(&env).is_ok());
+    assert_eq!(pair.get_size(&env).unwrap(), 1);
+
+    let env = env_with_cell_width(16);
+    assert!(wide.type_check(&env).is_err());
+    assert!(bitfield(&[("a", 16)]).type_check(&env).is_ok());
+    // Two members which fit in a 64-bit cell need two 16-bit cells.
+    assert_eq!(pair.get_size(&env).unwrap(), 2);
+    assert_eq!(
+        pair.get_bitfield_member(&ConstExpr::Symbol("b".to_string()), &env)
+            .unwrap(),
+        Some((1, 0, 10))
+    );
+}
@@ -0,0 +1,172 @@
+mod support;
+
+use sage::{compiler::Variant, Compiler};
+use std::collections::{HashMap, VecDeque};
+use support::{compile, run_interpreter, with_large_stack};
+
+const OPS: usize = 400;
+const KEYS: i64 = 200;
+
+/// Compile a program to a variant, and run it in the interpreter, returning what it printed.
+fn run(src: &str, variant: Variant) -> String {
+    let program = compile(Compiler::new(src).variant(variant));
+    // The fixed-capacity collections don't need the standard instructions.
+    assert_eq!(program.is_ok(), variant == Variant::Core);
+    run_interpreter(&program, "")
+}
+
+/// A deterministic sequence of operations on collections: the kind of each
+/// operation, and the value it uses.
+fn ops() -> Vec<(u64, i64)> {
+    let mut state = 12345u64;
+    (0..OPS)
+        .map(|i| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let value = (state >> 33) as i64 % 1000;
+            // Start with a run of pushes, so the collections have to grow.
+            let kind = if i < 50 { 0 } else { (state >> 20) % 4 };
+            (kind, value)
+        })
+        .collect()
+}
+
+/// Print a boolean as `1` or `0`.
+fn bit(b: bool) -> String {
+    format!("{}\n", b as i64)
+}
+
+#[test]
+fn test_collections() {
+    with_large_stack(test_collections_helper);
+}
+
+fn test_collections_helper() {
+    test_heap_collections();
+    test_fixed_collections();
+}
+
+fn test_heap_collections() {
+    let mut src = "from std.collections import Vec, Stack, Queue, HashMap;
+let mut v = Vec.make<Int>();
+let mut s = Stack.make<Int>();
+let mut q = Queue.make<Int>();
+let mut m = HashMap.make<Int, Int>();
+"
+    .to_string();
+    let (mut v, mut s, mut q, mut m) = (vec![], vec![], VecDeque::new(), HashMap::new());
+    let mut expected = String::new();
+    for (i, (kind, x)) in ops().into_iter().enumerate() {
+        match kind {
+            0 => {
+                src += &format!("v.push({x}); s.push({x}); q.push({x});\n");
+                v.push(x);
+                s.push(x);
+                q.push_back(x);
+            }
+            1 => {
+                src += "println(s.pop().unwrap_or(-1));\nprintln(q.pop().unwrap_or(-1));\n";
+                expected += &format!(
+                    "{}\n{}\n",
+                    s.pop().unwrap_or(-1),
+                    q.pop_front().unwrap_or(-1)
+                );
+            }
+            2 => {
+                src += &format!("m.insert({}, {i});\n", x % KEYS);
+                m.insert(x % KEYS, i as i64);
+            }
+            _ => {
+                src += &format!("m.remove({});\n", x % KEYS);
+                m.remove(&(x % KEYS));
+            }
+        }
+    }
+    src += &format!(
+        "println(v.len());\nprintln(s.len());\nprintln(q.len());\nprintln(m.len());
+for let mut k=0; k<{KEYS}; k+=1; {{
+    if m.contains_key(k) {{
+        println(m.get_and_unwrap(k));
+    }} else {{
+        println(-1);
+    }}
+}}
+"
+    );
+    expected += &format!("{}\n{}\n{}\n{}\n", v.len(), s.len(), q.len(), m.len());
+    for k in 0..KEYS {
+        expected += &format!("{}\n", m.get(&k).copied().unwrap_or(-1));
+    }
+
+    assert_eq!(run(&src, Variant::Standard), expected);
+}
+
+fn test_fixed_collections() {
+    const VEC_CAP: usize = 32;
+    const QUEUE_CAP: usize = 8;
+    const MAP_CAP: usize = 16;
+    let mut src = format!(
+        "from std.collections import FixedVec, FixedQueue, FixedMap;
+fun show(b: Bool) {{
+    if b {{
+        println(1);
+    }} else {{
+        println(0);
+    }}
+}}
+let mut v = FixedVec.make<Int, {VEC_CAP}>(0);
+let mut q = FixedQueue.make<Int, {QUEUE_CAP}>(0);
+let mut m = FixedMap.make<Int, Int, {MAP_CAP}>();
+"
+    );
+    let (mut v, mut q, mut m) = (vec![], VecDeque::new(), HashMap::new());
+    let mut expected = String::new();
+    for (i, (kind, x)) in ops().into_iter().enumerate() {
+        let key = x % 20;
+        match kind {
+            0 => {
+                src += &format!("show(v.push({x}));\nshow(q.push({x}));\n");
+                expected += &bit(v.len() < VEC_CAP);
+                if v.len() < VEC_CAP {
+                    v.push(x);
+                }
+                expected += &bit(q.len() < QUEUE_CAP);
+                if q.len() < QUEUE_CAP {
+                    q.push_back(x);
+                }
+            }
+            1 => {
+                src += "println(v.pop().unwrap_or(-1));\nprintln(q.pop().unwrap_or(-1));\n";
+                expected += &format!(
+                    "{}\n{}\n",
+                    v.pop().unwrap_or(-1),
+                    q.pop_front().unwrap_or(-1)
+                );
+            }
+            2 => {
+                src += &format!("show(m.insert({key}, {i}));\n");
+                let room = m.len() < MAP_CAP || m.contains_key(&key);
+                expected += &bit(room);
+                if room {
+                    m.insert(key, i as i64);
+                }
+            }
+            _ => {
+                src += &format!("m.remove({key});\n");
+                m.remove(&key);
+            }
+        }
+    }
+    src += "println(v.len());\nprintln(q.len());\nprintln(m.len());
+for let mut k=0; k<20; k+=1; {
+    show(m.contains_key(k));
+}
+";
+    expected += &format!("{}\n{}\n{}\n", v.len(), q.len(), m.len());
+    for k in 0..20 {
+        expected += &bit(m.contains_key(&k));
+    }
+
+    assert_eq!(run(&src, Variant::Core), expected);
+}
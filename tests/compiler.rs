@@ -0,0 +1,120 @@
+mod support;
+
+use sage::{
+    compiler::Variant,
+    lir::{Env, Severity},
+    Compiler,
+};
+use support::{assert_succeeded, compile, run_interpreter, scratch_dir};
+
+#[test]
+fn test_compiler_builder() {
+    // A program compiled for a target has every stage, and the code built for the target.
+    let compilation = Compiler::new("println(1 + 2);")
+        .target("c")
+        .opt_level(1)
+        .compile();
+    assert_succeeded(&compilation);
+    assert!(compilation.lir().is_some());
+    assert!(compilation.asm().is_some());
+    assert!(compilation.vm().is_some());
+    assert!(compilation.code().unwrap().contains("int main"));
+
+    // Without a target, the program is only compiled to virtual machine code.
+    let compilation = Compiler::new("println(1 + 2);")
+        .variant(Variant::Core)
+        .compile();
+    assert!(compilation.vm().is_some());
+    assert!(compilation.code().is_none());
+
+    // A program with a type error has diagnostics, and no stages after its LIR.
+    let compilation = Compiler::new("let x: Int = 'a';")
+        .filename("main.sg")
+        .compile();
+    assert!(!compilation.succeeded());
+    assert!(compilation.lir().is_some());
+    assert!(compilation.asm().is_none());
+    let error = compilation
+        .diagnostics()
+        .iter()
+        .find(|diagnostic| diagnostic.severity == Severity::Error)
+        .unwrap();
+    assert_eq!(error.code, Some("E0028"));
+    assert!(compilation.render_diagnostics().contains("error[E0028]"));
+
+    // Syntax errors keep their codes.
+    let compilation = Compiler::new("let x = ;").compile();
+    assert_eq!(compilation.diagnostics()[0].code, Some("E0100"));
+
+    // A target can only build programs for its own cell width.
+    let compilation = Compiler::new("println(1);")
+        .target("c")
+        .cell_width(16)
+        .compile();
+    assert!(!compilation.succeeded());
+    assert!(compilation.vm().is_some());
+    assert!(compilation.code().is_none());
+}
+
+#[test]
+fn test_monomorph_cache_fingerprint() {
+    // The monomorph's body is the same in both programs, but the associated
+    // constant it refers to isn't, so its cached code can't be reused.
+    let program = |value: i64| {
+        format!(
+            "struct Config {{}}
+            impl Config {{ const VALUE = {value}; }}
+            fun get<T>(x: T): Int {{ Config::VALUE }}
+            println(get<Int>(0));"
+        )
+    };
+    let dir = scratch_dir("monomorph-cache");
+    let mut env = Env::default();
+    env.set_monomorph_cache_dir(&dir);
+    let entries = || std::fs::read_dir(&dir).unwrap().count();
+
+    let first = compile(Compiler::new(program(1)).env(env.clone()));
+    assert_eq!(run_interpreter(&first, ""), "1\n");
+    assert_eq!(entries(), 1);
+
+    // Compiling the same program again reuses its entry.
+    let first = compile(Compiler::new(program(1)).env(env.clone()));
+    assert_eq!(run_interpreter(&first, ""), "1\n");
+    assert_eq!(entries(), 1);
+
+    let second = compile(Compiler::new(program(2)).env(env));
+    assert_eq!(run_interpreter(&second, ""), "2\n");
+    assert_eq!(entries(), 2);
+}
+
+#[test]
+fn test_cfg() {
+    // Only the branch of a `when` on a compile parameter is checked and compiled,
+    // so the other may refer to things which don't exist under these parameters.
+    let src = r#"
+        when cfg(feature = "fast") {
+            println("fast");
+        } else {
+            println("slow");
+        }
+        when cfg(logging) {
+            log_enabled();
+        }
+        println(cfg(target = "run"), " ", cfg(cell_width = "64"), " ", cfg(std), " ", cfg(core));
+    "#;
+    let program = compile(Compiler::new(src));
+    assert_eq!(
+        run_interpreter(&program, ""),
+        "slow\ntrue true true false\n"
+    );
+
+    let program = compile(
+        Compiler::new(src)
+            .cfg("feature", Some("fast"))
+            .variant(Variant::Core),
+    );
+    assert_eq!(
+        run_interpreter(&program, ""),
+        "fast\ntrue true false true\n"
+    );
+}
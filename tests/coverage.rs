@@ -0,0 +1,82 @@
+mod support;
+
+use sage::{
+    frontend::TestRunner,
+    lir::Env,
+    testing::Coverage,
+    vm::{line_comment, CoreOp, CoreProgram, Profile, TestingDevice},
+};
+use support::with_large_stack;
+
+const SOURCE: &str = r#"fun sign(x: Int): Int {
+    if x < 0 {
+        return -1;
+    }
+    return 1;
+}
+
+#[test]
+fun test_positive() {
+    assert(sign(5) == 1, "5 should be positive");
+}
+"#;
+
+#[test]
+fn test_coverage_of_profile() {
+    let program = CoreProgram(vec![
+        CoreOp::Comment(line_comment(1, Some("main.sg"))),
+        CoreOp::Set(vec![0]),
+        CoreOp::Comment(line_comment(2, Some("main.sg"))),
+        CoreOp::Comment("not a line".to_string()),
+        CoreOp::Comment(line_comment(1, Some("main.sg"))),
+        CoreOp::Comment(line_comment(7, Some("lib \"a\".sg"))),
+        CoreOp::Comment(line_comment(9, None)),
+    ]);
+    let profile = Profile {
+        device: TestingDevice::default(),
+        instructions: 1,
+        comment_hits: vec![1, 0, 0, 5, 3, 2, 4],
+        result: Ok(()),
+        leaks: vec![],
+    };
+
+    let coverage = Coverage::of(&Ok(program), &profile);
+    // Lines without a file name are left out, and file names are unescaped.
+    assert_eq!(
+        coverage.files().collect::<Vec<_>>(),
+        ["lib \"a\".sg", "main.sg"]
+    );
+    assert_eq!(coverage.lines("main.sg"), [(1, 3), (2, 0)]);
+
+    let mut total = coverage.clone();
+    total.merge(&coverage);
+    assert_eq!(total.lines("main.sg"), [(1, 6), (2, 0)]);
+    assert_eq!(
+        total.to_lcov(),
+        "TN:\nSF:lib \"a\".sg\nDA:7,4\nLF:1\nLH:1\nend_of_record\n\
+         TN:\nSF:main.sg\nDA:1,6\nDA:2,0\nLF:2\nLH:1\nend_of_record\n"
+    );
+}
+
+#[test]
+fn test_coverage_of_tests() {
+    with_large_stack(test_coverage_of_tests_helper);
+}
+
+fn test_coverage_of_tests_helper() {
+    let runner = TestRunner::new(Env::default(), 8192);
+    let results = runner.run(SOURCE, Some("sign.sg"), None).unwrap();
+    assert!(results[0].passed());
+
+    let lines = results[0].coverage.lines("sign.sg");
+    let hits = |line| {
+        lines
+            .iter()
+            .find(|(n, _)| *n == line)
+            .map(|(_, hits)| *hits)
+    };
+    // The branch for negative numbers never ran.
+    assert_eq!(hits(3), Some(0));
+    assert!(hits(5).unwrap() > 0);
+    assert!(hits(10).unwrap() > 0);
+}
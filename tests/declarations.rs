@@ -0,0 +1,84 @@
+mod support;
+
+use sage::Compiler;
+use support::{compile, run_interpreter, with_large_stack};
+
+/// Procedures which call each other, where the first calls one defined after it.
+const MUTUALLY_RECURSIVE_PROCEDURES: &str = r#"
+fun is_even(n: Int): Bool {
+    if n == 0 { return true; }
+    return is_odd(n - 1);
+}
+
+fun is_odd(n: Int): Bool {
+    if n == 0 { return false; }
+    return is_even(n - 1);
+}
+
+println(is_even(10), " ", is_odd(7), " ", is_even(3));
+"#;
+
+/// Types which refer to each other, and the procedures which walk them.
+const MUTUALLY_RECURSIVE_TYPES: &str = r#"
+enum Expr {
+    Num(Int),
+    Neg(&Expr),
+    Block(&Stmt)
+}
+
+enum Stmt {
+    Print(&Expr),
+    Skip
+}
+
+fun eval(expr: &Expr): Int {
+    match expr {
+        &of Num(n) => n,
+        &of Neg(inner) => 0 - eval(inner),
+        &of Block(stmt) => run(stmt),
+        _ => 0
+    }
+}
+
+fun run(stmt: &Stmt): Int {
+    match stmt {
+        &of Print(expr) => eval(expr),
+        &of Skip => 0,
+        _ => 0
+    }
+}
+
+let expr = new Expr of Neg(new Expr of Block(new Stmt of Print(new Expr of Num(5))));
+println(eval(expr), " ", run(new Stmt of Skip));
+"#;
+
+/// Polymorphic procedures which call each other's monomorphs.
+const POLYMORPHIC_MUTUAL_RECURSION: &str = r#"
+fun count_down<T>(x: T, n: Int): Int {
+    if n == 0 { return 0; }
+    return 1 + count_up<T>(x, n - 1);
+}
+
+fun count_up<T>(x: T, n: Int): Int {
+    if n == 0 { return 0; }
+    return 1 + count_down<T>(x, n - 1);
+}
+
+println(count_down<Int>(1, 5), " ", count_up<Bool>(true, 4));
+"#;
+
+#[test]
+fn test_mutual_recursion() {
+    with_large_stack(test_mutual_recursion_helper);
+}
+
+fn test_mutual_recursion_helper() {
+    for (src, expected) in [
+        (MUTUALLY_RECURSIVE_PROCEDURES, "true true false\n"),
+        (MUTUALLY_RECURSIVE_TYPES, "-5 0\n"),
+        (POLYMORPHIC_MUTUAL_RECURSION, "5 4\n"),
+    ] {
+        let program = compile(Compiler::new(src));
+        assert_eq!(run_interpreter(&program, ""), expected, "for {src}");
+    }
+}
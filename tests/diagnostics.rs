@@ -0,0 +1,161 @@
+use sage::lir::*;
+
+fn int(n: i64) -> Expr {
+    Expr::from(ConstExpr::Int(n))
+}
+
+#[test]
+fn test_warnings() {
+    // Two of the variables are never used, and one is ignored on purpose.
+    let program = Expr::let_vars(
+        vec![
+            ("first", Mutability::Immutable, None, int(1)),
+            ("_ignored", Mutability::Immutable, None, int(2)),
+            ("second", Mutability::Immutable, None, int(3)),
+            ("used", Mutability::Immutable, None, int(4)),
+        ],
+        Expr::var("used"),
+    );
+
+    // The warnings are reported in the order the variables are declared.
+    let env = Env::default();
+    program.type_check(&env).unwrap();
+    let diagnostics = env
+        .get_warnings()
+        .iter()
+        .map(Warning::diagnostic)
+        .collect::<Vec<_>>();
+    assert_eq!(diagnostics.len(), 2);
+    for (diagnostic, name) in diagnostics.iter().zip(["first", "second"]) {
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.message, format!("unused variable {name}"));
+        let notes = diagnostic
+            .notes
+            .iter()
+            .map(|note| note.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            notes,
+            [
+                format!("if this is intentional, prefix the variable with an underscore: _{name}"),
+                "this is the `unused-variable` warning".to_string(),
+            ]
+        );
+    }
+
+    // Allowed warnings aren't reported.
+    let mut env = Env::default();
+    env.set_warning_level(WarningKind::UnusedVariable, WarningLevel::Allow);
+    program.type_check(&env).unwrap();
+    assert!(env.get_warnings().is_empty());
+
+    // Denied warnings stop at the first one, as an error.
+    let mut env = Env::default();
+    env.set_warning_level(WarningKind::UnusedVariable, WarningLevel::Deny);
+    let error = program.type_check(&env).unwrap_err();
+    assert!(env.get_warnings().is_empty());
+    assert_eq!(
+        error.to_string(),
+        "unused variable first (the `unused-variable` warning is denied)"
+    );
+}
+
+#[test]
+fn test_multiple_errors() {
+    // The declaration and the block after it both have errors. The variable which
+    // couldn't be declared is still defined, so its use isn't reported too.
+    let program = Expr::let_var(
+        "x",
+        Mutability::Immutable,
+        None,
+        Expr::var("missing_first"),
+        Expr::Many(vec![Expr::var("missing_second"), Expr::var("x")]),
+    );
+    let error = program.type_check(&Env::default()).unwrap_err();
+    let messages = error
+        .errors()
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "symbol missing_first not defined",
+            "symbol missing_second not defined"
+        ]
+    );
+    assert_eq!(
+        error.to_string(),
+        "2 errors:\nsymbol missing_first not defined\nsymbol missing_second not defined"
+    );
+
+    // The same error found twice is only reported once.
+    let program = Expr::Many(vec![
+        Expr::var("missing_first"),
+        Expr::var("missing_first"),
+        Expr::NONE,
+    ]);
+    let error = program.type_check(&Env::default()).unwrap_err();
+    assert_eq!(error.errors().len(), 1);
+    assert_eq!(error.to_string(), "symbol missing_first not defined");
+}
+
+#[test]
+fn test_typed_holes() {
+    // Two holes, each reported with the bindings in scope of the type expected there.
+    let true_ = Expr::from(ConstExpr::Bool(true));
+    let program = Expr::let_vars(
+        vec![
+            ("b", Mutability::Immutable, None, int(1)),
+            ("flag", Mutability::Immutable, None, true_),
+            ("a", Mutability::Immutable, None, int(2)),
+        ],
+        Expr::Many(vec![
+            Expr::let_var(
+                "goal",
+                Mutability::Immutable,
+                Some(Type::Int),
+                Expr::hole(Some("goal")),
+                Expr::NONE,
+            ),
+            Expr::let_var(
+                "letter",
+                Mutability::Immutable,
+                Some(Type::Char),
+                Expr::hole(None::<String>),
+                Expr::NONE,
+            ),
+        ]),
+    );
+    let diagnostics = program
+        .type_check(&Env::default())
+        .unwrap_err()
+        .diagnostics();
+    assert_eq!(diagnostics.len(), 2);
+
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].code, Some("E0061"));
+    assert_eq!(diagnostics[0].message, "found hole ?goal of type Int");
+    let notes = diagnostics[0]
+        .notes
+        .iter()
+        .map(|note| note.message.as_str())
+        .collect::<Vec<_>>();
+    // The bindings which fit are sorted by name.
+    assert_eq!(
+        notes,
+        [
+            "bindings in scope that fit this hole:",
+            "  a: Int",
+            "  b: Int"
+        ]
+    );
+
+    assert_eq!(diagnostics[1].code, Some("E0061"));
+    assert_eq!(diagnostics[1].message, "found hole ? of type Char");
+    assert_eq!(diagnostics[1].notes.len(), 1);
+    assert_eq!(
+        diagnostics[1].notes[0].message,
+        "no bindings in scope fit this hole"
+    );
+}
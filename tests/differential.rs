@@ -0,0 +1,131 @@
+mod support;
+
+use sage::Compiler;
+use std::fs::read_to_string;
+use support::*;
+
+const INPUT: &str = "2 4 8 16 32 64 128 256 512 1024 2048 4096";
+
+/// Examples whose output is the same on every target.
+/// Programs which print pointers or floats are left out,
+/// because targets are free to lay out memory and format floats differently.
+const EXAMPLES: &[&str] = &[
+    "hello.sg",
+    "pure.sg",
+    "rng.sg",
+    "mod_sqrt.sg",
+    "pattern.sg",
+    "sudoku.sg",
+    "division.sg",
+    "overflow.sg",
+];
+
+/// Examples which are also checked on particular targets, even if they aren't enabled.
+/// Every backend implements division itself, so it's checked on each one that shares
+/// code with another backend, and that the host can run.
+const TARGET_EXAMPLES: &[(&str, &str)] = &[("division.sg", "freestanding-c")];
+
+/// The output of the overflow example: each kind of arithmetic at the bounds of a 64-bit `Int`.
+const OVERFLOW_OUTPUT: &str = "9223372036854775807 + 1: checked = -9223372036854775808 true, wrapping = -9223372036854775808, saturating = 9223372036854775807
+9223372036854775807 - 1: checked = 9223372036854775806 false, wrapping = 9223372036854775806, saturating = 9223372036854775806
+9223372036854775807 * 1: checked = 9223372036854775807 false, wrapping = 9223372036854775807, saturating = 9223372036854775807
+-9223372036854775808 + 1: checked = -9223372036854775807 false, wrapping = -9223372036854775807, saturating = -9223372036854775807
+-9223372036854775808 - 1: checked = 9223372036854775807 true, wrapping = 9223372036854775807, saturating = -9223372036854775808
+-9223372036854775808 * 1: checked = -9223372036854775808 false, wrapping = -9223372036854775808, saturating = -9223372036854775808
+-9223372036854775808 + -1: checked = 9223372036854775807 true, wrapping = 9223372036854775807, saturating = -9223372036854775808
+-9223372036854775808 - -1: checked = -9223372036854775807 false, wrapping = -9223372036854775807, saturating = -9223372036854775807
+-9223372036854775808 * -1: checked = -9223372036854775808 true, wrapping = -9223372036854775808, saturating = 9223372036854775807
+9223372036854775807 + -1: checked = 9223372036854775806 false, wrapping = 9223372036854775806, saturating = 9223372036854775806
+9223372036854775807 - -1: checked = -9223372036854775808 true, wrapping = -9223372036854775808, saturating = 9223372036854775807
+9223372036854775807 * -1: checked = -9223372036854775807 false, wrapping = -9223372036854775807, saturating = -9223372036854775807
+9223372036854775807 + 2: checked = -9223372036854775807 true, wrapping = -9223372036854775807, saturating = 9223372036854775807
+9223372036854775807 - 2: checked = 9223372036854775805 false, wrapping = 9223372036854775805, saturating = 9223372036854775805
+9223372036854775807 * 2: checked = -2 true, wrapping = -2, saturating = 9223372036854775807
+3 + 4: checked = 7 false, wrapping = 7, saturating = 7
+3 - 4: checked = -1 false, wrapping = -1, saturating = -1
+3 * 4: checked = 12 false, wrapping = 12, saturating = 12
+";
+
+/// The division example for the 6502, whose cells are 16 bits wide, so its smallest
+/// integer is that of 16 bits. The interpreter's cells are always 64 bits wide, so
+/// the output is checked against what the virtual machine does with 16-bit cells.
+const DIVISION_16: &str = r#"
+fun show_division(a: Int, b: Int) {
+    println(a, " / ", b, " = ", a / b, ", ", a, " % ", b, " = ", a % b);
+}
+
+show_division(7, 2);
+show_division(-7, 2);
+show_division(7, -2);
+show_division(-7, -2);
+show_division(7, 0);
+show_division(-7, 0);
+show_division(-32767 - 1, -1);
+"#;
+
+const DIVISION_16_OUTPUT: &str = "7 / 2 = 3, 7 % 2 = 1
+-7 / 2 = -3, -7 % 2 = -1
+7 / -2 = -3, 7 % -2 = 1
+-7 / -2 = 3, -7 % -2 = -1
+7 / 0 = 7, 7 % 0 = 7
+-7 / 0 = -7, -7 % 0 = -7
+-32768 / -1 = -32768, -32768 % -1 = 0
+";
+
+#[test]
+fn test_differential_frontend_examples() {
+    with_large_stack(test_differential_frontend_examples_helper);
+}
+
+fn test_differential_frontend_examples_helper() {
+    for example in EXAMPLES {
+        let path = format!("examples/frontend/{example}");
+        let src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path}`"));
+        let program = compile_program(&src, Some(&path));
+        assert_same_outputs(&program, INPUT, &path);
+    }
+
+    for (example, name) in TARGET_EXAMPLES {
+        let path = format!("examples/frontend/{example}");
+        let src = read_to_string(&path)
+            .unwrap_or_else(|_| panic!("Could not read contents of file `{path}`"));
+        let program = compile_program(&src, Some(&path));
+        let expected = run_interpreter(&program, INPUT);
+        if let Some(output) = run_target(&program, name, INPUT) {
+            assert_eq!(
+                output, expected,
+                "Target `{name}` printed different output than the interpreter for {path}"
+            );
+        }
+    }
+
+    let program = compile(
+        Compiler::new(DIVISION_16)
+            .target("6502")
+            .call_stack_size(256),
+    );
+    if let Some(output) = run_target(&program, "6502", "") {
+        assert_eq!(output, DIVISION_16_OUTPUT, "The 6502 divided differently");
+    }
+}
+
+#[test]
+fn test_overflow_at_bounds() {
+    with_large_stack(test_overflow_at_bounds_helper);
+}
+
+fn test_overflow_at_bounds_helper() {
+    // The other targets are compared against the interpreter with the other examples.
+    let path = "examples/frontend/overflow.sg";
+    let src =
+        read_to_string(path).unwrap_or_else(|_| panic!("Could not read contents of file `{path}`"));
+    let program = compile_program(&src, Some(path));
+    assert_eq!(run_interpreter(&program, ""), OVERFLOW_OUTPUT);
+    if let Some(output) = run_target(&program, "c", "") {
+        assert_eq!(
+            output, OVERFLOW_OUTPUT,
+            "The C target overflowed differently"
+        );
+    }
+}
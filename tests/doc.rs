@@ -0,0 +1,73 @@
+use sage::{frontend, lir::*};
+
+const SOURCE: &str = "/// A point on the plane.
+struct Point {
+    x: Int,
+    y: Int
+}
+
+impl Point {
+    /// Make a point from its coordinates.
+    fun new(x: Int, y: Int): Point {
+        return {x=x, y=y};
+    }
+}
+
+/// Add two numbers.
+/// Overflow wraps around.
+fun add(a: Int, b: Int): Int {
+    return a + b;
+}
+
+// This isn't a documentation comment.
+fun sub(a: Int, b: Int): Int {
+    return a - b;
+}
+";
+
+fn docs() -> ModuleDocs {
+    let program = frontend::parse(SOURCE, None, false, false).unwrap();
+    ModuleDocs::from_program("point", &program, &Expr::NONE, &Env::default())
+}
+
+#[test]
+fn test_doc_comments() {
+    let docs = docs();
+    let point = docs.types.iter().find(|ty| ty.name == "Point").unwrap();
+    assert_eq!(point.doc.as_deref(), Some("A point on the plane."));
+    assert_eq!(
+        point.methods[0].doc.as_deref(),
+        Some("Make a point from its coordinates.")
+    );
+
+    let add = docs.procs.iter().find(|proc| proc.name == "add").unwrap();
+    assert_eq!(
+        add.doc.as_deref(),
+        Some("Add two numbers.\nOverflow wraps around.")
+    );
+    let sub = docs.procs.iter().find(|proc| proc.name == "sub").unwrap();
+    assert_eq!(sub.doc, None);
+}
+
+#[test]
+fn test_doc_signatures_and_layouts() {
+    let docs = docs();
+    let point = docs.types.iter().find(|ty| ty.name == "Point").unwrap();
+    assert_eq!(point.size, Some(2));
+    assert_eq!(
+        point.fields,
+        vec![
+            ("x".to_string(), Type::Int, 0),
+            ("y".to_string(), Type::Int, 1)
+        ]
+    );
+    assert_eq!(point.methods[0].signature, "fun new(x: Int, y: Int): Point");
+
+    let add = docs.procs.iter().find(|proc| proc.name == "add").unwrap();
+    assert_eq!(add.signature, "fun add(a: Int, b: Int): Int");
+
+    let markdown = docs.render(DocFormat::Markdown);
+    assert!(markdown.contains("### `fun add(a: Int, b: Int): Int`\n\nAdd two numbers."));
+    let html = docs.render(DocFormat::Html);
+    assert!(html.contains("<h3><code>fun add(a: Int, b: Int): Int</code></h3>"));
+}
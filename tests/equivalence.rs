@@ -0,0 +1,55 @@
+mod support;
+
+use sage::testing::*;
+use support::{enabled_targets, run_target};
+
+/// The number of random programs to compare on each target.
+const SEEDS: u64 = 32;
+
+#[test]
+fn test_random_programs_on_targets() {
+    for name in enabled_targets() {
+        if let Err(divergence) =
+            check_equivalence(0..SEEDS, |program| run_target(program, &name, ""))
+        {
+            panic!("Target `{name}` disagreed with the interpreter: {divergence}");
+        }
+    }
+}
+
+#[test]
+fn test_same_seed_same_program() {
+    assert_eq!(RandomProgram::generate(7), RandomProgram::generate(7));
+    assert_ne!(RandomProgram::generate(7), RandomProgram::generate(8));
+}
+
+#[test]
+fn test_random_programs_compile_and_halt() {
+    for seed in 0..SEEDS {
+        let program = RandomProgram::generate(seed);
+        let code = program
+            .compile()
+            .unwrap_or_else(|e| panic!("Could not compile {program}: {e}"));
+        run_interpreter(&code).unwrap_or_else(|e| panic!("Could not interpret {program}: {e}"));
+    }
+}
+
+#[test]
+fn test_shrink_divergent_program() {
+    // A runner which loses every minus sign, like a target that mishandles negative numbers.
+    let divergence = check_equivalence(0..SEEDS, |program| {
+        run_interpreter(program)
+            .ok()
+            .map(|output| output.replace('-', ""))
+    })
+    .expect_err("some program should print a negative number");
+
+    assert!(divergence.expected.contains('-'));
+    assert_eq!(divergence.found, divergence.expected.replace('-', ""));
+    // Nothing else in the program is needed to show the difference.
+    assert_eq!(divergence.program.body.len(), 1);
+    assert!(divergence.program.shrinks().iter().all(|program| {
+        let output = run_interpreter(&program.compile().unwrap()).unwrap();
+        !output.contains('-')
+    }));
+}
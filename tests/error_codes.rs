@@ -0,0 +1,33 @@
+use sage::{frontend, lir::*};
+use std::collections::HashSet;
+
+#[test]
+fn test_error_codes() {
+    let program = frontend::parse("let x: Int = 'a';", None, false, false).unwrap();
+    let err = program.compile_with_env(Env::default(), false).unwrap_err();
+
+    // The code is found under the error's annotations, and printed with its diagnostic.
+    let diagnostics = err.diagnostics();
+    assert!(err.errors().iter().any(|err| err.code() == Some("E0028")));
+    assert!(diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.to_string().starts_with("error[E0028]")));
+
+    // Codes can be looked up without their `E` or leading zeros.
+    let code = ErrorCode::find("e28").unwrap();
+    assert_eq!(code.code, "E0028");
+    assert_eq!(code.title, "mismatched types");
+    assert!(ErrorCode::find("E9999").is_none());
+
+    // Every code is unique, and every code an error can have is explained.
+    let codes = ERROR_CODES
+        .iter()
+        .map(|code| code.code)
+        .collect::<HashSet<_>>();
+    assert_eq!(codes.len(), ERROR_CODES.len());
+    assert!(codes.contains(err.errors()[0].code().unwrap()));
+
+    // Errors from parsing frontend code have codes too.
+    let err = frontend::parse("let x = ;", None, false, false).unwrap_err();
+    assert!(err.starts_with("[E0100]"));
+}
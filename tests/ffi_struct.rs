@@ -0,0 +1,64 @@
+use sage::{ffi_struct, lir::*, side_effects::FFIValue};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+ffi_struct!(Point { x: f64, y: f64 });
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Particle {
+    position: Point,
+    mass: f64,
+    id: i64,
+    tags: [char; 2],
+}
+ffi_struct!(Particle {
+    position: Point,
+    mass: f64,
+    id: i64,
+    tags: [char; 2],
+});
+
+#[test]
+fn test_ffi_struct() {
+    // The LIR type has the same members, and the same size.
+    let point = Type::Struct(
+        [
+            ("x".to_string(), Type::Float),
+            ("y".to_string(), Type::Float),
+        ]
+        .into_iter()
+        .collect(),
+    );
+    assert_eq!(Point::lir_type(), point);
+    let ty = Particle::lir_type();
+    let Type::Struct(members) = &ty else {
+        panic!("expected a struct, found {ty}");
+    };
+    assert_eq!(members["position"], point);
+    assert_eq!(
+        members["tags"],
+        Type::Array(Box::new(Type::Char), Box::new(ConstExpr::Int(2)))
+    );
+    assert_eq!(ty.get_size(&Env::default()).unwrap(), Particle::CELLS);
+
+    // Members are laid out in the alphabetical order of their names.
+    assert_eq!(
+        Particle::member_offsets(),
+        vec![("position", 2), ("mass", 1), ("id", 0), ("tags", 4)]
+    );
+
+    let particle = Particle {
+        position: Point { x: 1.5, y: -2.0 },
+        mass: 3.0,
+        id: 7,
+        tags: ['a', 'b'],
+    };
+    let cells = particle.encode();
+    assert_eq!(cells.len(), Particle::CELLS);
+    assert_eq!(cells[0], 7);
+    assert_eq!(cells[4], 'a' as i64);
+    assert_eq!(Particle::from_cells(&cells), particle);
+}
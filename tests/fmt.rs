@@ -0,0 +1,38 @@
+use sage::frontend::format_source;
+
+const UNFORMATTED: &str = "fun add(a:Int,b:Int): Int {
+  // Add the numbers
+        return a+b;
+}
+
+
+
+let x=add(1,2);   
+let y :Int=-x*2+add(1,-2);
+let p = &x;
+if x==3 {
+println(\"x is \", x, \" and \", *p-1);
+}
+";
+
+const FORMATTED: &str = "fun add(a: Int, b: Int): Int {
+    // Add the numbers
+    return a + b;
+}
+
+let x = add(1, 2);
+let y: Int = -x * 2 + add(1, -2);
+let p = &x;
+if x == 3 {
+    println(\"x is \", x, \" and \", *p - 1);
+}
+";
+
+#[test]
+fn test_format_source() {
+    assert_eq!(format_source(UNFORMATTED).unwrap(), FORMATTED);
+    // Formatted code stays the same.
+    assert_eq!(format_source(FORMATTED).unwrap(), FORMATTED);
+    // Code which doesn't parse can't be formatted.
+    assert!(format_source("let x = ;").is_err());
+}
@@ -0,0 +1,100 @@
+mod support;
+
+use sage::{
+    compiler::Variant,
+    lir::{Compile, ConstExpr, Expr},
+    side_effects::*,
+    Compiler,
+};
+use support::{compile, run_device, with_large_stack, CALL_STACK_SIZE};
+
+#[test]
+fn test_framebuffer() {
+    with_large_stack(test_framebuffer_helper);
+}
+
+fn test_framebuffer_helper() {
+    test_draw_builtins();
+    test_draw_exprs();
+    test_framebuffer_convention();
+}
+
+fn test_draw_builtins() {
+    let src = r#"
+clear_display(0x000080);
+for let mut x=0; x<4; x+=1; {
+    set_pixel(x + 1, 2, 0xFF0000);
+}
+present();
+set_pixel(2, 2, 0x00FF00);
+present();
+"#;
+    let program = compile(Compiler::new(src).variant(Variant::CoreOnly));
+    let device = run_device(&program, "");
+
+    let framebuffer = &device.framebuffer;
+    assert_eq!(framebuffer.frames(), 2);
+    assert_eq!(framebuffer.pixel(0, 0), Some(0x000080));
+    assert_eq!(framebuffer.pixel(0, 2), Some(0x000080));
+    assert_eq!(framebuffer.pixel(1, 2), Some(0xFF0000));
+    assert_eq!(framebuffer.pixel(2, 2), Some(0x00FF00));
+    assert_eq!(framebuffer.pixel(4, 2), Some(0xFF0000));
+    assert_eq!(framebuffer.pixel(5, 2), Some(0x000080));
+}
+
+fn test_draw_exprs() {
+    let program = Expr::Many(vec![
+        Expr::set_pixel(
+            1,
+            ConstExpr::Int(3),
+            ConstExpr::Int(4),
+            ConstExpr::Int(0xABCDEF),
+        ),
+        Expr::present(1),
+    ]);
+    let code = program
+        .compile(true)
+        .unwrap()
+        .unwrap()
+        .assemble(CALL_STACK_SIZE)
+        .unwrap();
+    let device = run_device(&Ok(code), "");
+
+    // Only channel 0 is kept in the testing device's framebuffer.
+    assert_eq!(device.framebuffer.frames(), 0);
+    assert_eq!(
+        device.other,
+        vec![
+            (3, Output::new(OutputMode::SetCursorColumn, 1)),
+            (4, Output::new(OutputMode::SetCursorRow, 1)),
+            (0xABCDEF, Output::new(OutputMode::Pixel, 1)),
+            (0, Output::new(OutputMode::UpdateDisplay, 1)),
+        ]
+    );
+}
+
+fn test_framebuffer_convention() {
+    let mut framebuffer = Framebuffer::new(3, 2);
+    let put =
+        |framebuffer: &mut Framebuffer, val, mode| framebuffer.put(val, &Output::new(mode, 0));
+
+    assert_eq!(framebuffer.pixels(), vec![0; 6]);
+    put(&mut framebuffer, 2, OutputMode::SetCursorColumn);
+    // Drawing moves the cursor right, and onto the next row from the last column.
+    assert!(!put(&mut framebuffer, 0x111111, OutputMode::Pixel));
+    assert!(!put(&mut framebuffer, 0x222222, OutputMode::Pixel));
+    assert_eq!(framebuffer.pixels(), vec![0, 0, 0x111111, 0x222222, 0, 0]);
+    // Pixels off the framebuffer are ignored, and colors are kept to 24 bits.
+    put(&mut framebuffer, 7, OutputMode::SetCursorRow);
+    put(&mut framebuffer, 0x333333, OutputMode::Pixel);
+    put(&mut framebuffer, 0, OutputMode::SetCursorRow);
+    put(&mut framebuffer, -1, OutputMode::Pixel);
+    assert_eq!(framebuffer.pixel(2, 0), Some(0xFFFFFF));
+    assert_eq!(framebuffer.pixel(3, 0), None);
+
+    assert!(put(&mut framebuffer, 0, OutputMode::UpdateDisplay));
+    put(&mut framebuffer, 0x444444, OutputMode::ClearDisplay);
+    assert_eq!(framebuffer.pixels(), vec![0x444444; 6]);
+    assert_eq!(framebuffer.frames(), 1);
+    assert!(!Framebuffer::handles(&Output::stdout_char()));
+}
@@ -0,0 +1,20 @@
+use sage::fuzz::*;
+
+#[test]
+fn test_fuzz_entry_points() {
+    // Errors in the input are ignored.
+    fuzz_parse(b"fun main() { let x = ; }");
+    fuzz_parse(&[0xff, 0xfe, b'(', 0x80]);
+    fuzz_compile("let x: Int = 'a';");
+    fuzz_compile("fun main() { println(1 + 2); }");
+
+    // Inputs nested too deeply are skipped, instead of overflowing the stack.
+    let deep = "(".repeat(MAX_NESTING * 100) + &")".repeat(MAX_NESTING * 100);
+    fuzz_parse(deep.as_bytes());
+    fuzz_compile(&deep);
+    let long = "1 + ".repeat(MAX_INPUT_LEN) + "1;";
+    fuzz_compile(&long);
+
+    // Recursive types stop at the fuzzing limits.
+    fuzz_compile("type List = (Int, &List); let x: List = (1, null);");
+}
@@ -0,0 +1,46 @@
+mod support;
+
+use sage::{
+    asm::{CoreOp, A},
+    lir::*,
+    Compiler,
+};
+use support::{assert_succeeded, compile, run_interpreter};
+
+#[test]
+fn test_host_builtins() {
+    let mut env = Env::default();
+    // Double the argument on top of the stack.
+    env.add_core_builtin(CoreBuiltin {
+        name: "host_double".to_string(),
+        args: vec![("x".to_string(), Type::Int)],
+        ret: Type::Int,
+        body: vec![
+            CoreOp::Pop(Some(A), 1),
+            CoreOp::Add { src: A, dst: A },
+            CoreOp::Push(A, 1),
+        ],
+    });
+    env.add_foreign_function(FFIProcedure::new(
+        "host_log".to_string(),
+        vec![Type::Int],
+        Type::None,
+    ));
+
+    let program = compile(Compiler::new("println(host_double(21));").env(env.clone()));
+    assert_eq!(run_interpreter(&program, ""), "42\n");
+
+    // The builtin is type checked like any other procedure.
+    let compilation = Compiler::new("host_double('a');")
+        .env(env.clone())
+        .compile();
+    assert!(!compilation.succeeded());
+
+    // Foreign functions compile to calls through the FFI.
+    let compilation = Compiler::new("host_log(5);").env(env).compile();
+    assert_succeeded(&compilation);
+    assert!(matches!(compilation.vm(), Some(Err(_))));
+
+    // Without the builtins registered, the programs don't compile.
+    assert!(!Compiler::new("host_double(21);").compile().succeeded());
+}
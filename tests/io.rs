@@ -0,0 +1,101 @@
+mod support;
+
+use sage::Compiler;
+use support::{compile, run_with, with_large_stack, RunOptions};
+
+/// Compile a program and run it in the interpreter with the given input,
+/// returning what it wrote to stdout and stderr.
+fn run(src: &str, input: &str) -> (String, String) {
+    let profile = run_with(
+        &compile(Compiler::new(src)),
+        RunOptions {
+            input,
+            ..RunOptions::default()
+        },
+    );
+    assert_eq!(profile.result, Ok(()));
+    (profile.device.output_str(), profile.device.error_str())
+}
+
+#[test]
+fn test_io() {
+    with_large_stack(test_io_helper);
+}
+
+fn test_io_helper() {
+    test_print_builtins();
+    test_writer();
+    test_reader();
+}
+
+fn test_print_builtins() {
+    let (stdout, stderr) = run(
+        r#"
+let x = 5;
+printf("{} + {} = {}\n", x, 2, x + 2);
+let pair = (x, 'a');
+printf("{:?} is one argument\n", pair);
+eprint("warning: ");
+eprintln(x);
+print("done");
+"#,
+        "",
+    );
+    assert_eq!(stdout, "5 + 2 = 7\n(5, 'a') is one argument\ndone");
+    assert_eq!(stderr, "warning: 5\n");
+}
+
+fn test_writer() {
+    let (stdout, stderr) = run(
+        r#"
+from std.io import Writer;
+let mut out = Writer.stdout();
+let mut err = Writer.stderr();
+out.write(&"count: ");
+for let mut i=0; i<300; i+=1; {
+    out.write_char('.');
+}
+out.write_char('\n');
+out.write_int(-1234);
+out.write_char(' ');
+out.write_int(0);
+out.write_line(&"");
+err.write_line(&"to stderr");
+print("|");
+out.flush();
+err.flush();
+"#,
+        "",
+    );
+    // The first 256 characters are flushed when the buffer fills up, before the `|`.
+    let written = format!("count: {}\n-1234 0\n", ".".repeat(300));
+    assert_eq!(stdout, format!("{}|{}", &written[..256], &written[256..]));
+    assert_eq!(stderr, "to stderr\n");
+}
+
+fn test_reader() {
+    let (stdout, _) = run(
+        r#"
+from std.io import Reader, read_line;
+fun show(s: &Char) {
+    for let mut i=0; s[i] != '\0'; i+=1; {
+        print(s[i]);
+    }
+    println();
+}
+let mut line = ['\0'] * 16;
+let length = read_line((&mut line) as &mut Char, 16);
+println(length);
+show((&line) as &Char);
+
+let mut reader = Reader.stdin();
+println(reader.read_int().unwrap_or(0));
+println(reader.read_int().unwrap_or(0));
+reader.skip_whitespace();
+println(reader.read_line((&mut line) as &mut Char, 4));
+show((&line) as &Char);
+"#,
+        "hello world\n  42\n-17 abcdef\n",
+    );
+    assert_eq!(stdout, "11\nhello world\n42\n-17\n3\nabc\n");
+}
@@ -0,0 +1,32 @@
+use sage::{frontend, lir::*};
+
+#[test]
+fn test_json_diagnostics() {
+    let program = frontend::parse("let x: Int = 'a';", Some("main.sg"), false, false).unwrap();
+    let err = program.compile_with_env(Env::default(), false).unwrap_err();
+    let diagnostic = err
+        .diagnostics()
+        .into_iter()
+        .find(|diagnostic| diagnostic.code == Some("E0028"))
+        .unwrap();
+
+    let json = diagnostic.to_json();
+    assert_eq!(json["severity"], "error");
+    assert_eq!(json["code"], "E0028");
+    assert_eq!(json["message"], diagnostic.message.as_str());
+    let span = &json["spans"][0];
+    assert_eq!(span["file"], "main.sg");
+    assert_eq!(span["line"], 1);
+    assert_eq!(span["column"], 14);
+    let note = &json["children"][0];
+    assert_eq!(note["severity"], "note");
+    assert_eq!(note["message"], "expected type Int, found type Char");
+    assert_eq!(note["spans"].as_array().unwrap().len(), 0);
+
+    // A diagnostic without a code or a location still has every field.
+    let json = Diagnostic::warning("unused variable `x`").to_json();
+    assert_eq!(json["severity"], "warning");
+    assert!(json["code"].is_null());
+    assert_eq!(json["spans"].as_array().unwrap().len(), 0);
+    assert_eq!(json["children"].as_array().unwrap().len(), 0);
+}
@@ -0,0 +1,54 @@
+mod support;
+
+use sage::{vm::Leak, Compiler};
+use support::{compile, run_with, with_large_stack, RunOptions};
+
+/// Compile a program, and run it in the standard interpreter, with or without the leak check.
+fn leaks(src: &str, leak_check: bool) -> Vec<Leak> {
+    let program = compile(Compiler::new(src).filename("main.sg"));
+    assert!(
+        program.is_err(),
+        "programs using the heap need the standard variant"
+    );
+    let profile = run_with(
+        &program,
+        RunOptions {
+            leak_check,
+            ..RunOptions::default()
+        },
+    );
+    assert_eq!(profile.result, Ok(()));
+    profile.leaks
+}
+
+#[test]
+fn test_leaks() {
+    with_large_stack(test_leaks_helper);
+}
+
+fn test_leaks_helper() {
+    let src = r#"let a: &mut Int = alloc(3 * sizeof<Int>());
+let b: &mut Int = alloc(sizeof<Int>());
+free(a);
+let c: &mut Int = alloc(2 * sizeof<Int>());
+"#;
+    // Leaks are only reported when the interpreter checks for them.
+    assert_eq!(leaks(src, false), vec![]);
+
+    // The block freed by `a` is reused by `c`, so `c` comes before `b`.
+    let found = leaks(src, true);
+    assert_eq!(
+        found.iter().map(|leak| leak.size).collect::<Vec<_>>(),
+        [2, 1]
+    );
+    assert!(found[0].site.contains("main.sg:4"), "{}", found[0]);
+    assert!(found[1].site.contains("main.sg:2"), "{}", found[1]);
+    assert!(found[1].to_string().starts_with("1 cell at address"));
+
+    // Nothing leaks when everything is freed.
+    let src = r#"let a: &mut Int = alloc(sizeof<Int>());
+let b: &mut Int = realloc(a, 4 * sizeof<Int>());
+free(b);
+"#;
+    assert_eq!(leaks(src, true), vec![]);
+}
@@ -0,0 +1,69 @@
+mod support;
+
+use sage::{
+    vm::{Limit, Limits, RuntimeError},
+    Compiler,
+};
+use std::time::Duration;
+use support::{compile, run_with, with_large_stack, RunOptions};
+
+/// Compile a program, and run it in the interpreter with some limits.
+fn run(src: &str, limits: Limits) -> (String, Result<(), RuntimeError>) {
+    let profile = run_with(
+        &compile(Compiler::new(src)),
+        RunOptions {
+            limits,
+            ..RunOptions::default()
+        },
+    );
+    (profile.device.output_str(), profile.result)
+}
+
+#[test]
+fn test_limits() {
+    with_large_stack(test_limits_helper);
+}
+
+fn test_limits_helper() {
+    let steps = Limits {
+        max_steps: Some(1000),
+        ..Limits::default()
+    };
+    let (_, result) = run("while true {}", steps);
+    assert_eq!(result, Err(RuntimeError::Limit(Limit::Steps(1000))));
+
+    let time = Duration::from_millis(50);
+    let timeout = Limits {
+        timeout: Some(time),
+        ..Limits::default()
+    };
+    let (_, result) = run("while true {}", timeout);
+    assert_eq!(result, Err(RuntimeError::Limit(Limit::Timeout(time))));
+
+    // The program is stopped before it writes more than its quota.
+    let io = Limits {
+        io_quota: Some(10),
+        ..Limits::default()
+    };
+    let (output, result) = run("while true { print(\"x\"); }", io);
+    assert_eq!(output, "x".repeat(10));
+    assert_eq!(result, Err(RuntimeError::Limit(Limit::Io(10))));
+
+    let tape = Limits {
+        max_tape: Some(1),
+        ..Limits::default()
+    };
+    let (_, result) = run("println(\"hi\");", tape);
+    assert_eq!(result, Err(RuntimeError::Limit(Limit::Tape(1))));
+
+    // Programs which stay within their limits run as usual.
+    let generous = Limits {
+        max_steps: Some(100_000_000),
+        max_tape: Some(10_000_000),
+        timeout: Some(Duration::from_secs(60)),
+        io_quota: Some(100),
+    };
+    let (output, result) = run("println(\"hi\");", generous);
+    assert_eq!(output, "hi\n");
+    assert_eq!(result, Ok(()));
+}
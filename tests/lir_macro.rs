@@ -0,0 +1,43 @@
+use sage::{
+    lir,
+    lir::*,
+    vm::{CoreInterpreter, StandardInterpreter, TestingDevice},
+};
+
+#[test]
+fn test_lir_macro() {
+    // The node parsed depends on what's expected.
+    let ty: Type = lir!("(ptr mut Int)");
+    assert_eq!(ty, Type::Pointer(Mutability::Mutable, Box::new(Type::Int)));
+    let pattern: Pattern = lir!("(tuple a _)");
+    assert_eq!(pattern.to_text(), "(tuple a _)");
+
+    // Nodes are built up from smaller ones, positionally or by name.
+    let n: Expr = lir!("n");
+    let decrement: Expr = lir!("(binop - {} 1)", n);
+    let program: Expr = lir!(
+        "(let (def-proc fact (proc fact ((n const {ty})) {ty}
+            (if (binop <= n 1) 1 (binop * n (call fact {arg})))))
+          (unop put (call fact 5)))",
+        ty = Type::Int,
+        arg = decrement,
+    );
+
+    let output = match program.compile_with_env(Env::default(), true).unwrap() {
+        Ok(asm) => CoreInterpreter::new(TestingDevice::new(""))
+            .run(&asm.assemble(8192).unwrap())
+            .unwrap()
+            .output_str(),
+        Err(asm) => StandardInterpreter::new(TestingDevice::new(""))
+            .run(&asm.assemble(8192).unwrap())
+            .unwrap()
+            .output_str(),
+    };
+    assert_eq!(output, "120");
+}
+
+#[test]
+#[should_panic(expected = "invalid LIR text")]
+fn test_lir_macro_invalid_text() {
+    let _: Expr = lir!("(binop + 1");
+}
@@ -0,0 +1,314 @@
+use sage::lir::*;
+
+/// Parse a node from the text format, panicking if the text is invalid.
+fn parse<T: FromText>(text: &str) -> T {
+    T::from_text(text).unwrap_or_else(|e| panic!("Could not parse {text:?}: {e}"))
+}
+
+/// Check that a node printed in the text format parses back
+/// to a node which prints the same way, and return the text.
+fn assert_stable<T: FromText + ToText>(node: &T) -> String {
+    let printed = node.to_text();
+    assert_eq!(parse::<T>(&printed).to_text(), printed);
+    printed
+}
+
+/// Check that text written in the canonical form is printed back unchanged,
+/// and that the printed text survives another round trip.
+fn assert_round_trip<T: FromText + ToText>(text: &str) {
+    assert_eq!(assert_stable(&parse::<T>(text)), text);
+}
+
+#[test]
+fn test_expr_round_trip() {
+    for text in [
+        "(do (unop put 1) (unop put 2))",
+        "(let (var x mut Int 5) (assign += x 1))",
+        "(while (binop < i 10) (assign += i 1))",
+        "(if c 1 2)",
+        "(when (cfg debug) 1 2)",
+        "(match x (1 (unop put 'a')) (_ none))",
+        "(if-let (variant Some n) opt n 0)",
+        "(while-let (variant Some n) (call next) (unop put n))",
+        "(unop - x)",
+        "(binop + 1 2)",
+        "(ternop atomic_compare_swap p 1 2)",
+        "(assign -= x 1)",
+        "(ref mut x)",
+        "(deref p)",
+        "(store p 5)",
+        "(call f 1 'c' \"str\")",
+        "(call g)",
+        "(return none)",
+        "(array 1 2 3)",
+        "(array-repeat 0 16)",
+        "(tuple 1 2.5)",
+        "(union (union (a Int) (b Float)) a 1)",
+        "(enum-union (enum-union (A None) (B Int)) B 1)",
+        "(struct (x 1) (y 2))",
+        "(as 1 Float)",
+        "(member p x)",
+        "(member t 0)",
+        "(index arr 2)",
+        "(hole)",
+        "(hole goal)",
+        "(const (tuple 1 2))",
+        "(const (array))",
+        "(const (member (struct (x 1)) x))",
+        "(const (let (def-const N 4) N))",
+    ] {
+        assert_round_trip::<Expr>(text);
+    }
+}
+
+#[test]
+fn test_const_expr_round_trip() {
+    for text in [
+        "any",
+        "none",
+        "null",
+        "true",
+        "false",
+        "x",
+        "-3",
+        "(cell -1)",
+        "1.5",
+        "-0.0",
+        "1e20",
+        "'a'",
+        "(of (enum A B) A)",
+        "(typeof x)",
+        "(sizeof Int)",
+        "(sizeof-expr (tuple 1 2))",
+        "(alignof (repr 2 (a Int)))",
+        "(offsetof Point y)",
+        "(cfg debug)",
+        "(cfg target \"c\")",
+        "(typename Int)",
+        "(fieldnames Point)",
+        "(fieldoffsets Point)",
+        "(variantnames Shape)",
+        "(type (ptr const Int))",
+        "(tuple 1 'a')",
+        "(array 1 2)",
+        "(array)",
+        "\"hello\"",
+        "(table i 0 16 (binop * i i))",
+        "(struct (x 1) (y 2))",
+        "(union (union (a Int)) a 1)",
+        "(enum-union (enum-union (A Int)) A 1)",
+        "(ffi puts ((ptr const Char)) None)",
+        "(proc ((x const Int)) Int x)",
+        "(proc id ((x mut Int)) Int x (attrs inline cold))",
+        "(poly-proc id ((T)) ((x const T)) T x)",
+        "(poly-proc sum ((T Int)) ((x const T)) T x (attrs (specialize Int)))",
+        "(monomorphize id Int)",
+        "(template ((T)) (poly-proc id ((U)) ((x const U)) U x))",
+        "(member (tuple 1 2) 0)",
+        "(as 1 Float)",
+        "(let (def-const N 4) N)",
+    ] {
+        assert_round_trip::<ConstExpr>(text);
+    }
+}
+
+#[test]
+fn test_builtin_round_trip() {
+    // Assembly is printed however the assembler prints it, so only check that it's stable.
+    for text in [
+        "(core-builtin drop ((x Int)) None \"pop\")",
+        "(std-builtin drop ((x Int)) None \"pop\")",
+    ] {
+        let printed = assert_stable(&parse::<ConstExpr>(text));
+        assert!(printed.contains("pop"), "{printed}");
+    }
+}
+
+#[test]
+fn test_type_round_trip() {
+    for text in [
+        "None",
+        "Int",
+        "Float",
+        "Cell",
+        "Char",
+        "Bool",
+        "Any",
+        "Never",
+        "Point",
+        "(let T Int (ptr const T))",
+        "(unit Meters Float)",
+        "(enum A B)",
+        "(enum-repr 2 ((A 1) (B 5)) (enum A B))",
+        "(enum-repr 1 ((A 3) (B 7)) (enum-union (A None) (B Int)))",
+        "(tuple Int Float)",
+        "(array Int 4)",
+        "(struct (x Int) (y Int))",
+        "(enum-union (Circle Int) (Square Float))",
+        "(type Int)",
+        "(union (a Int) (b Float))",
+        "(repr 2 (b Char) (a Int))",
+        "(bitfield (flag 1) (mode 3))",
+        "(proc (Int Float) Bool)",
+        "(ptr mut Int)",
+        "(ptr any Char)",
+        "(poly ((T) (U Int)) (tuple T U))",
+        "(apply List Int)",
+        "(const-param 4)",
+    ] {
+        assert_round_trip::<Type>(text);
+    }
+}
+
+#[test]
+fn test_pattern_round_trip() {
+    for text in [
+        "_",
+        "x",
+        "(bind mut x)",
+        "(bind any x)",
+        "5",
+        "-5",
+        "1.5",
+        "'a'",
+        "true",
+        "none",
+        "null",
+        "any",
+        "(const N)",
+        "(const \"abc\")",
+        "(tuple a _)",
+        "(struct (x 0) (y y))",
+        "(variant Empty)",
+        "(variant Some (tuple a b))",
+        "(alt 1 2 3)",
+        "(ptr (bind mut x))",
+    ] {
+        assert_round_trip::<Pattern>(text);
+    }
+}
+
+#[test]
+fn test_declaration_round_trip() {
+    for text in [
+        "(static counter mut Int 0)",
+        "(var x const Int 5)",
+        "(var x mut _ 5)",
+        "(var-pat (tuple a b) (tuple 1 2))",
+        "(def-proc f (proc f ((x const Int)) Int x))",
+        "(def-poly-proc id (poly-proc id ((T)) ((x const T)) T x))",
+        "(def-type Point (struct (x Int) (y Int)))",
+        "(def-type Old Int (attrs (deprecated \"use New\") (derive eq cmp)))",
+        "(def-type Doc Int (attrs (doc \"A documented type.\") deprecated))",
+        "(def-const N 4)",
+        "(extern puts (ffi puts ((ptr const Char)) None))",
+        "(impl Point (origin (struct (x 0) (y 0))))",
+        "(decls (def-const A 1) (def-const B 2))",
+        "(module math true 3 (def-const PI 3.14))",
+        "(from-import math (PI) (E e))",
+        "(from-import-all math)",
+        "(static-assert true \"always\")",
+    ] {
+        assert_round_trip::<Declaration>(text);
+    }
+
+    // Every attribute which can be written on a procedure.
+    assert_round_trip::<Declaration>(
+        "(def-proc t (proc t () None none (attrs no_mangle pure test bench)))",
+    );
+}
+
+#[test]
+fn test_escapes_round_trip() {
+    // Strings and characters are printed with their escape sequences.
+    let string = ConstExpr::Array(
+        "tab\t quote\" back\\ nul\0 bell\u{7} it's é"
+            .chars()
+            .map(ConstExpr::Char)
+            .collect(),
+    );
+    let printed = assert_stable(&string);
+    assert_eq!(printed, r#""tab\t quote\" back\\ nul\0 bell\u{7} it's é""#);
+    assert_eq!(parse::<ConstExpr>(&printed), string);
+
+    for (c, text) in [
+        ('\n', r"'\n'"),
+        ('\'', r"'\''"),
+        ('"', "'\"'"),
+        ('\\', r"'\\'"),
+        ('\0', r"'\0'"),
+        ('\u{1b}', r"'\u{1b}'"),
+    ] {
+        assert_eq!(assert_stable(&ConstExpr::Char(c)), text);
+        assert_eq!(parse::<ConstExpr>(text), ConstExpr::Char(c));
+    }
+
+    // Escapes which aren't printed are still read.
+    assert_eq!(
+        parse::<ConstExpr>(r#""\x41\u{42}\/""#),
+        parse::<ConstExpr>("\"AB/\"")
+    );
+}
+
+#[test]
+fn test_non_finite_floats_round_trip() {
+    for (n, text) in [
+        (f64::INFINITY, "(float \"inf\")"),
+        (f64::NEG_INFINITY, "(float \"-inf\")"),
+        (f64::NAN, "(float \"NaN\")"),
+    ] {
+        assert_eq!(assert_stable(&ConstExpr::Float(n)), text);
+        match parse::<ConstExpr>(text) {
+            ConstExpr::Float(m) if n.is_nan() => assert!(m.is_nan()),
+            ConstExpr::Float(m) => assert_eq!(m, n),
+            other => panic!("{text} parsed as {other:?}"),
+        }
+    }
+    // In an expression, they're constants like any other float.
+    assert_round_trip::<Expr>("(binop * x (float \"inf\"))");
+}
+
+#[test]
+fn test_symbols_round_trip() {
+    // Names which can't be written as atoms are written as `(sym "name")`.
+    for name in [
+        "true",
+        "Int",
+        "_",
+        "1st",
+        "-5",
+        "two words",
+        "a(b)",
+        "semi;colon",
+        "",
+    ] {
+        let symbol = ConstExpr::Symbol(name.to_string());
+        let printed = assert_stable(&symbol);
+        assert_eq!(printed, format!("(sym {name:?})"));
+        assert_eq!(parse::<ConstExpr>(&printed), symbol);
+
+        let ty = Type::Symbol(name.to_string());
+        assert_eq!(parse::<Type>(&assert_stable(&ty)), ty);
+        let pat = Pattern::Symbol(Mutability::Immutable, name.to_string());
+        assert_eq!(assert_stable(&pat), printed);
+    }
+    // Operators and other punctuation are bare atoms.
+    for name in ["-", "+", "<=", "x.y", "snake_case", "kebab-case"] {
+        assert_eq!(assert_stable(&ConstExpr::Symbol(name.to_string())), name);
+    }
+    assert_round_trip::<Expr>("(binop (sym \"my op\") 1 2)");
+    assert_round_trip::<Declaration>("(def-const (sym \"a b\") (sym \"true\"))");
+}
+
+#[test]
+fn test_pretty_round_trip() {
+    // Text too wide for a line is broken up, and still parses back the same.
+    let program: Expr = parse(
+        "(let (def-proc fact (proc fact ((n const Int)) Int
+            (if (binop <= n 1) 1 (binop * n (call fact (binop - n 1))))))
+          (unop put (call fact 5)))",
+    );
+    let printed = assert_stable(&program);
+    assert!(printed.contains('\n'));
+    assert!(printed.lines().all(|line| line.len() <= 80), "{printed}");
+}
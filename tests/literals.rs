@@ -0,0 +1,29 @@
+mod support;
+
+use sage::Compiler;
+use support::{compile, run_interpreter, with_large_stack};
+
+/// String and character literals with every kind of escape sequence.
+const ESCAPES: &str = r#"
+println("tab:\there quote:\" slash:\\\/ hex:\x41 unicode:\u{42} \
+         joined");
+println("before\0after");
+println('\n' as Int, " ", '\r' as Int, " ", '\0' as Int, " ", '\'' as Int);
+println('\x7a', '\u{63}', '\u{00041}', " ", '\b' as Int, " ", '\f' as Int);
+"#;
+
+#[test]
+fn test_escapes() {
+    with_large_stack(test_escapes_helper);
+}
+
+fn test_escapes_helper() {
+    let program = compile(Compiler::new(ESCAPES));
+    assert_eq!(
+        run_interpreter(&program, ""),
+        "tab:\there quote:\" slash:\\/ hex:A unicode:B joined\n\
+         before\n\
+         10 13 0 39\n\
+         zcA 8 12\n"
+    );
+}
@@ -0,0 +1,34 @@
+mod support;
+
+use sage::{compiler::Variant, side_effects::LogLevel, Compiler};
+use support::{compile, run_device, with_large_stack};
+
+#[test]
+fn test_logs() {
+    with_large_stack(test_logs_helper);
+}
+
+fn test_logs_helper() {
+    let src = r#"
+log_info("starting");
+println(1);
+let x = 5;
+log_warn("x is ", x);
+eprint("err");
+log_error("done after ", 2.5, " seconds");
+"#;
+    let program = compile(Compiler::new(src).variant(Variant::CoreOnly));
+    let device = run_device(&program, "");
+
+    // Logging doesn't change what the program prints.
+    assert_eq!(device.output_str(), "1\n");
+    assert_eq!(device.error_str(), "err");
+    assert_eq!(
+        device.logs(),
+        vec![
+            (LogLevel::Info, "starting".to_string()),
+            (LogLevel::Warn, "x is 5".to_string()),
+            (LogLevel::Error, "done after 2.5 seconds".to_string()),
+        ]
+    );
+}
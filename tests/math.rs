@@ -0,0 +1,125 @@
+mod support;
+
+use sage::{compiler::Variant, Compiler};
+use support::{compile, run_interpreter, with_large_stack};
+
+const INTS: [i64; 9] = [-12, -7, -1, 0, 1, 2, 9, 15, 36];
+const ANGLES: [f64; 9] = [-7.0, -3.0, -1.5, -0.5, 0.0, 0.25, 1.0, 2.5, 6.0];
+const FIXED_ONE: f64 = 4096.0;
+
+/// Compile a program to a variant, and run it in the interpreter, returning
+/// what it printed one value per line.
+fn run(src: &str, variant: Variant) -> Vec<String> {
+    let program = compile(Compiler::new(src).variant(variant));
+    // The integer and fixed-point functions don't need the standard instructions.
+    assert_eq!(program.is_ok(), variant == Variant::Core);
+    run_interpreter(&program, "")
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a.abs(),
+        _ => gcd(b, a % b),
+    }
+}
+
+#[test]
+fn test_math() {
+    with_large_stack(test_math_helper);
+}
+
+fn test_math_helper() {
+    test_int_math();
+    test_fixed_math();
+    test_float_math();
+}
+
+fn test_int_math() {
+    let mut src = "from std.math import gcd, lcm, isqrt, min, max;\n".to_string();
+    let mut expected = vec![];
+    for a in INTS {
+        src += &format!("println(({a}).abs());\n");
+        expected.push(a.abs());
+        src += &format!("println(isqrt({a}));\n");
+        expected.push(if a > 0 { (a as f64).sqrt() as i64 } else { 0 });
+        for b in INTS {
+            src += &format!("println(min({a}, {b}));\nprintln(max({a}, {b}));\n");
+            expected.push(a.min(b));
+            expected.push(a.max(b));
+            src += &format!("println(gcd({a}, {b}));\nprintln(lcm({a}, {b}));\n");
+            expected.push(gcd(a, b));
+            expected.push(match gcd(a, b) {
+                0 => 0,
+                d => (a / d * b).abs(),
+            });
+        }
+        src += &format!("println(({a}).clamp(-5, 5));\n");
+        expected.push(a.clamp(-5, 5));
+        for exp in 0..5 {
+            src += &format!("println(({a}).pow({exp}));\n");
+            expected.push(a.pow(exp));
+        }
+    }
+
+    let output = run(&src, Variant::Core);
+    let expected = expected.iter().map(i64::to_string).collect::<Vec<_>>();
+    assert_eq!(output, expected);
+}
+
+fn test_fixed_math() {
+    let mut src = "from std.math.fixed import sin, cos, sqrt;\n".to_string();
+    for x in ANGLES {
+        let fixed = (x * FIXED_ONE) as i64;
+        src += &format!("println(sin({fixed}));\nprintln(cos({fixed}));\n");
+        src += &format!("println(sqrt({}));\n", fixed.abs());
+    }
+
+    let output = run(&src, Variant::Core);
+    let values = output
+        .iter()
+        .map(|line| line.parse::<i64>().unwrap() as f64 / FIXED_ONE)
+        .collect::<Vec<_>>();
+    for (i, x) in ANGLES.into_iter().enumerate() {
+        let x = (x * FIXED_ONE) as i64 as f64 / FIXED_ONE;
+        let [sin, cos, sqrt] = [values[i * 3], values[i * 3 + 1], values[i * 3 + 2]];
+        assert!((sin - x.sin()).abs() < 0.003, "sin({x}) = {sin}");
+        assert!((cos - x.cos()).abs() < 0.003, "cos({x}) = {cos}");
+        assert!((sqrt - x.abs().sqrt()).abs() < 0.003, "sqrt({x}) = {sqrt}");
+    }
+}
+
+fn test_float_math() {
+    let mut src = "from std.math import sin, cos, sqrt, exp, ln;\n".to_string();
+    for x in ANGLES {
+        src += &format!("println(sin({x:?}));\nprintln(cos({x:?}));\n");
+        src += &format!("println(({x:?}).min(1.0));\nprintln(({x:?}).max(1.0));\n");
+    }
+    for x in [0.5, 1.0, 2.0, 10.0] {
+        src += &format!("println(sqrt({x:?}));\nprintln(exp({x:?}));\nprintln(ln({x:?}));\n");
+    }
+
+    let output = run(&src, Variant::Standard);
+    let mut lines = output.iter();
+    let mut check = |name: &str, x: f64, expected: f64| {
+        let line = lines.next().unwrap();
+        let found = line.parse::<f64>().unwrap();
+        assert!(
+            (found - expected).abs() < 1e-4 * expected.abs().max(1.0),
+            "{name}({x}) = {found}, not {expected}"
+        );
+    };
+    for x in ANGLES {
+        check("sin", x, x.sin());
+        check("cos", x, x.cos());
+        check("min", x, x.min(1.0));
+        check("max", x, x.max(1.0));
+    }
+    for x in [0.5, 1.0, 2.0, 10.0] {
+        check("sqrt", x, x.sqrt());
+        check("exp", x, x.exp());
+        check("ln", x, x.ln());
+    }
+}
@@ -0,0 +1,56 @@
+mod support;
+
+use sage::{lir::*, Compiler};
+use support::{compile, run_interpreter, with_large_stack};
+
+/// A procedure nested in a polymorphic procedure, which uses its type parameter.
+/// Each monomorph of the outer procedure gets its own monomorph of the nested one.
+const NESTED_PROCEDURE: &str = r#"
+fun twice<T>(x: T): (T, T) {
+    fun pair(y: T): (T, T) {
+        return (y, y);
+    }
+    return pair(x);
+}
+
+let (a, b) = twice<Int>(3);
+let (c, d) = twice<Bool>(true);
+let (e, f) = twice<(Int, Char)>((5, 'x'));
+println(a, " ", b, " ", c, " ", d, " ", e.0, e.1, " ", f.0, f.1);
+"#;
+
+#[test]
+fn test_nested_procedures() {
+    with_large_stack(test_nested_procedures_helper);
+}
+
+fn test_nested_procedures_helper() {
+    let program = compile(Compiler::new(NESTED_PROCEDURE));
+    assert_eq!(run_interpreter(&program, ""), "3 3 true true 5x 5x\n");
+}
+
+#[test]
+fn test_wrong_number_of_type_arguments() {
+    let env = Env::default();
+    let t = Type::Symbol("T".to_string());
+    let id = PolyProcedure::new(
+        "id".to_string(),
+        vec![("T".to_string(), None)],
+        vec![("x".to_string(), Mutability::Immutable, t.clone())],
+        t,
+        Expr::var("x"),
+    );
+
+    assert!(id.check_ty_args(&[Type::Int], &env).is_ok());
+    for ty_args in [vec![], vec![Type::Int, Type::Bool]] {
+        // Too few and too many type arguments are both rejected, with the monomorph as written.
+        let expected = ConstExpr::PolyProc(id.clone()).monomorphize(ty_args.clone());
+        match id.monomorphize(ty_args.clone(), &env) {
+            Err(Error::InvalidMonomorphize(found)) => {
+                assert_eq!(found.to_string(), expected.to_string())
+            }
+            Err(e) => panic!("Expected an invalid monomorphization for {ty_args:?}, found {e}"),
+            Ok(mono) => panic!("Expected an error for {ty_args:?}, found {mono}"),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+mod support;
+
+use sage::{vm::RuntimeError, BuildProfile, Compiler};
+use support::{compile, run_with, with_large_stack, RunOptions};
+
+/// A procedure which panics with its argument if it's negative.
+const PANIC: &str = r#"
+fun check(n: Int): Int {
+    if n < 0 {
+        panic(n);
+    }
+    return n;
+}
+
+println(check(5));
+println(check(0 - 7));
+println("unreachable");
+"#;
+
+/// A program which halts with the exit status returned by `main`, after its top level runs.
+const EXIT: &str = r#"
+fun main(): Int {
+    println("main");
+    return {status};
+}
+
+println("top");
+"#;
+
+/// Assertions whose conditions and messages write to stdout when they're evaluated.
+const ASSERTIONS: &str = r#"
+fun condition(b: Bool): Bool {
+    print("condition ");
+    return b;
+}
+
+fun message(n: Int): Int {
+    print("message ");
+    return n;
+}
+
+let x = 3;
+assert(condition(x > 0), message(x));
+println("ok");
+assert(condition(x > 5), message(x));
+println("unreachable");
+"#;
+
+/// Compile a program with a profile and run it in the interpreter,
+/// returning what it wrote to stdout and stderr, and how it stopped.
+fn run(src: &str, profile: BuildProfile) -> (String, String, Result<(), RuntimeError>) {
+    let program = compile(Compiler::new(src).filename("panics.sg").profile(profile));
+    let profile = run_with(&program, RunOptions::default());
+    (
+        profile.device.output_str(),
+        profile.device.error_str(),
+        profile.result,
+    )
+}
+
+#[test]
+fn test_panic_and_exit() {
+    with_large_stack(test_panic_and_exit_helper);
+}
+
+fn test_panic_and_exit_helper() {
+    // A panic writes its message with its location, and halts with the status 1.
+    let (stdout, stderr, result) = run(PANIC, BuildProfile::default());
+    assert_eq!(stdout, "5\n");
+    assert!(stderr.starts_with("panicked at panics.sg:"), "{stderr}");
+    assert!(stderr.ends_with(": -7\n"), "{stderr}");
+    assert_eq!(result, Err(RuntimeError::Exit(1)));
+
+    // Exiting with a failing status writes nothing to stderr.
+    let (stdout, stderr, result) = run(&EXIT.replace("{status}", "3"), BuildProfile::default());
+    assert_eq!(stdout, "top\nmain\n");
+    assert_eq!(stderr, "");
+    assert_eq!(result, Err(RuntimeError::Exit(3)));
+
+    // Exiting with the status 0 is success.
+    let (stdout, stderr, result) = run(&EXIT.replace("{status}", "0"), BuildProfile::default());
+    assert_eq!(stdout, "top\nmain\n");
+    assert_eq!(stderr, "");
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn test_assertions() {
+    with_large_stack(test_assertions_helper);
+}
+
+fn test_assertions_helper() {
+    // The message of an assertion is only evaluated if it fails.
+    let (stdout, stderr, result) = run(ASSERTIONS, BuildProfile::debug());
+    assert_eq!(stdout, "condition ok\ncondition message ");
+    assert!(stderr.starts_with("panicked at panics.sg:"), "{stderr}");
+    assert!(stderr.ends_with(": assertion failed: 3\n"), "{stderr}");
+    assert_eq!(result, Err(RuntimeError::Exit(1)));
+
+    // Release builds remove assertions, without evaluating their conditions.
+    let (stdout, stderr, result) = run(ASSERTIONS, BuildProfile::release());
+    assert_eq!(stdout, "ok\nunreachable\n");
+    assert_eq!(stderr, "");
+    assert_eq!(result, Ok(()));
+}
@@ -0,0 +1,61 @@
+mod support;
+
+use sage::{compiler::Variant, frontend::Prelude, Compiler};
+use support::{assert_succeeded, compile, run_interpreter, with_large_stack};
+
+const USES_STD: &str = "from std.math import gcd;\nprintln(gcd(12, 18));";
+const USES_ALLOC: &str = "let p = alloc(1) as &mut Int;\n*p = 5;\nprintln(*p);";
+
+#[test]
+fn test_prelude() {
+    with_large_stack(test_prelude_helper);
+}
+
+fn test_prelude_helper() {
+    test_prelude_contents();
+    test_prelude_override();
+    test_core_only();
+}
+
+fn test_prelude_contents() {
+    let compiles = |src, prelude| Compiler::new(src).prelude(prelude).compile().succeeded();
+    assert!(compiles(USES_STD, Prelude::Std));
+    assert!(!compiles(USES_STD, Prelude::Builtins));
+    assert!(!compiles(USES_STD, Prelude::None));
+
+    assert!(compiles(USES_ALLOC, Prelude::Builtins));
+    assert!(!compiles(USES_ALLOC, Prelude::None));
+
+    // The builtin printing and input operations don't come from the prelude.
+    assert!(compiles("println(1);", Prelude::None));
+}
+
+fn test_prelude_override() {
+    // The program's own declarations replace the builtins with the same names.
+    let program =
+        compile(Compiler::new("fun debug() { println(7); }\ndebug();").variant(Variant::Core));
+    assert!(program.is_ok());
+    assert_eq!(run_interpreter(&program, ""), "7\n");
+}
+
+fn test_core_only() {
+    // Without core-only mode, the program falls back on the standard variant.
+    let compilation = Compiler::new(USES_ALLOC).variant(Variant::Core).compile();
+    assert_succeeded(&compilation);
+    assert!(compilation.vm().unwrap().is_err());
+
+    // In core-only mode, it's an error where the standard instruction is used.
+    let compilation = Compiler::new(USES_ALLOC)
+        .variant(Variant::CoreOnly)
+        .compile();
+    assert!(!compilation.succeeded());
+    assert!(compilation.vm().is_none());
+    assert!(compilation
+        .render_diagnostics()
+        .contains("Unsupported instruction"));
+
+    // Programs which only need the core instructions are unaffected.
+    let compilation = Compiler::new(USES_STD).variant(Variant::CoreOnly).compile();
+    assert_succeeded(&compilation);
+    assert!(compilation.vm().unwrap().is_ok());
+}
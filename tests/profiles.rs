@@ -0,0 +1,66 @@
+mod support;
+
+use sage::{BuildProfile, Compiler, Manifest};
+use support::with_large_stack;
+
+const MANIFEST: &str = r#"
+[profile.release]
+debug-info = true
+
+[profile.fuzz]
+inherits = "release"
+bounds-checks = true
+
+[profile.loop]
+inherits = "cycle"
+
+[profile.cycle]
+inherits = "loop"
+"#;
+
+#[test]
+fn test_manifest_profiles() {
+    let manifest = Manifest::parse(MANIFEST).unwrap();
+    // Profiles the manifest doesn't mention are the built in ones.
+    assert_eq!(manifest.profile("debug"), Ok(BuildProfile::debug()));
+    assert_eq!(
+        manifest.profile("release"),
+        Ok(BuildProfile {
+            debug_info: true,
+            ..BuildProfile::release()
+        })
+    );
+    // Inherited profiles include the manifest's changes to them.
+    assert_eq!(
+        manifest.profile("fuzz"),
+        Ok(BuildProfile {
+            bounds_checks: true,
+            debug_info: true,
+            ..BuildProfile::release()
+        })
+    );
+    assert!(manifest.profile("loop").is_err());
+    assert!(manifest.profile("missing").is_err());
+
+    assert!(Manifest::parse("[profile.fast]\nopt-level = 3\n").is_err());
+    assert!(Manifest::parse("[profile.fast]\nspeed = 1\n").is_err());
+}
+
+#[test]
+fn test_profile_debug_info() {
+    with_large_stack(test_profile_debug_info_helper);
+}
+
+fn test_profile_debug_info_helper() {
+    let build = |profile| {
+        let compilation = Compiler::new("println(1 + 2);")
+            .filename("main.sg")
+            .target("c")
+            .profile(profile)
+            .compile();
+        compilation.code().unwrap().to_string()
+    };
+    // Debug builds mark the source lines, and release builds leave them out.
+    assert!(build(BuildProfile::debug()).contains("#line 1 \"main.sg\""));
+    assert!(!build(BuildProfile::release()).contains("#line"));
+}
@@ -0,0 +1,59 @@
+mod support;
+
+use sage::{frontend::Repl, lir::Env};
+use support::with_large_stack;
+
+#[test]
+fn test_repl() {
+    with_large_stack(test_repl_helper);
+}
+
+fn test_repl_helper() {
+    let mut repl = Repl::new(Env::default(), 8192).unwrap();
+
+    // Statements print their output once, and are kept for later inputs.
+    assert_eq!(
+        repl.eval("let x = 5;\nprintln(\"x is \", x);")
+            .unwrap()
+            .stdout,
+        "x is 5\n"
+    );
+    assert_eq!(repl.eval("x + 1").unwrap().stdout, "6: Int\n");
+    assert_eq!(
+        repl.eval("fun double(n: Int): Int { return n * 2; }")
+            .unwrap()
+            .stdout,
+        ""
+    );
+    assert_eq!(repl.eval("double(x)").unwrap().stdout, "10: Int\n");
+
+    // Inputs with errors are discarded.
+    assert!(repl.eval("let y: Int = True;").is_err());
+    assert!(repl.eval("y").is_err());
+
+    // The tape is kept between inputs, so changes to variables and side effects persist,
+    // and the code of earlier inputs isn't run again.
+    repl.eval("let mut count = 0;").unwrap();
+    for expected in ["1", "2", "3"] {
+        let printed = repl.eval("count += 1;\nprintln(count);").unwrap();
+        assert_eq!(printed.stdout, format!("{expected}\n"));
+    }
+    assert_eq!(repl.eval("count").unwrap().stdout, "3: Int\n");
+
+    // What an input writes to the standard error is returned along with its output.
+    let printed = repl.eval("print(\"out\");\neprint(\"err\");").unwrap();
+    assert_eq!(printed.stdout, "out");
+    assert_eq!(printed.stderr, "err");
+
+    // An input which fails at runtime is discarded, and the state before it is kept.
+    let error = repl
+        .eval("count += 10;\nlet z = 1;\npanic(\"boom\");")
+        .unwrap_err();
+    assert!(error.contains("boom"), "{error}");
+    assert!(repl.eval("z").is_err());
+    assert_eq!(repl.eval("count").unwrap().stdout, "3: Int\n");
+    assert_eq!(repl.eval("double(count)").unwrap().stdout, "6: Int\n");
+
+    assert!(Repl::is_incomplete("fun f() {\n    println(\"}\");"));
+    assert!(!Repl::is_incomplete("fun f() {\n    println(\"}\");\n}"));
+}
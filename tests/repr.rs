@@ -0,0 +1,31 @@
+use sage::lir::*;
+
+#[test]
+fn test_repr_casts() {
+    let env = Env::default();
+    let repr = |members: &[(&str, Type)], align| {
+        Type::Repr(
+            members
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+            align,
+        )
+    };
+    let ints = repr(&[("a", Type::Int), ("b", Type::Int)], 1);
+    let aligned = repr(&[("a", Type::Int), ("b", Type::Int)], 2);
+    let floats = repr(&[("a", Type::Int), ("b", Type::Float)], 2);
+    let structure = Type::Struct(
+        [("b".to_string(), Type::Int), ("a".to_string(), Type::Int)]
+            .into_iter()
+            .collect(),
+    );
+
+    // Members with the same types are rearranged by the cast.
+    assert!(ints.can_cast_to(&aligned, &env).unwrap());
+    assert!(ints.can_cast_to(&structure, &env).unwrap());
+    assert!(structure.can_cast_to(&aligned, &env).unwrap());
+    // Their cells aren't converted, so members with different types can't be cast.
+    assert!(!ints.can_cast_to(&floats, &env).unwrap());
+    assert!(!floats.can_cast_to(&structure, &env).unwrap());
+}
@@ -0,0 +1,65 @@
+mod support;
+
+use sage::Compiler;
+use support::{compile, run_interpreter, with_large_stack};
+
+/// Structs larger than the arguments of the procedures returning them,
+/// returned through several calls.
+const LARGE_RETURNS: &str = r#"
+struct Big {
+    cells: [Int * 8],
+    sum: Int
+}
+
+// Return a local variable.
+fun make(n: Int): Big {
+    let big = {cells=[n, n + 1, n + 2, n + 3, n + 4, n + 5, n + 6, n + 7], sum=8 * n + 28};
+    return big;
+}
+
+// Return a call from a procedure with other local variables.
+fun shifted(n: Int, by: Int): Big {
+    let m = n + by;
+    let _padding = [0; 4];
+    return make(m);
+}
+
+// Return a value built from the results of other calls.
+fun combined(n: Int): Big {
+    let a = shifted(n, 1);
+    let b = shifted(n, 2);
+    return {cells=a.cells, sum=a.sum + b.sum};
+}
+
+// Return the result of a recursive call.
+fun nested(depth: Int, n: Int): Big {
+    if depth == 0 {
+        return combined(n);
+    }
+    return nested(depth - 1, n + 10);
+}
+
+let x = make(1);
+println(x.cells, " ", x.sum);
+let y = shifted(1, 2);
+println(y.cells, " ", y.sum);
+let z = nested(3, 0);
+println(z.cells, " ", z.sum);
+println(nested(0, 5).sum, " ", x.sum);
+"#;
+
+#[test]
+fn test_large_returns() {
+    with_large_stack(test_large_returns_helper);
+}
+
+fn test_large_returns_helper() {
+    let program = compile(Compiler::new(LARGE_RETURNS));
+    assert_eq!(
+        run_interpreter(&program, ""),
+        "[1, 2, 3, 4, 5, 6, 7, 8] 36\n\
+         [3, 4, 5, 6, 7, 8, 9, 10] 52\n\
+         [31, 32, 33, 34, 35, 36, 37, 38] 560\n\
+         160 36\n"
+    );
+}
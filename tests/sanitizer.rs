@@ -0,0 +1,113 @@
+mod support;
+
+use sage::{vm::RuntimeError, Compiler};
+use support::{compile, run_with, with_large_stack, RunOptions};
+
+/// Compile a program, and run it in the standard interpreter, with or without the sanitizer.
+fn run(src: &str, sanitize: bool) -> (String, Result<(), RuntimeError>) {
+    let program = compile(Compiler::new(src).filename("main.sg"));
+    assert!(
+        program.is_err(),
+        "programs using the heap need the standard variant"
+    );
+    let profile = run_with(
+        &program,
+        RunOptions {
+            sanitize,
+            ..RunOptions::default()
+        },
+    );
+    (profile.device.output_str(), profile.result)
+}
+
+/// The message of the memory error a program stopped with.
+fn memory_error(result: Result<(), RuntimeError>) -> String {
+    match result {
+        Err(RuntimeError::Memory(msg)) => msg,
+        other => panic!("expected a memory error, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_sanitizer() {
+    with_large_stack(test_sanitizer_helper);
+}
+
+fn test_sanitizer_helper() {
+    // Printing a cell which was never written.
+    let uninit = r#"
+let p: &mut Int = alloc(4 * sizeof<Int>());
+p[0] = 5;
+println(p[0]);
+println(p[1]);
+"#;
+    // Without the sanitizer, the fresh cell reads as zero.
+    assert_eq!(run(uninit, false), ("5\n0\n".to_string(), Ok(())));
+    let (output, result) = run(uninit, true);
+    assert_eq!(output, "5\n");
+    let msg = memory_error(result);
+    assert!(
+        msg.contains("an output uses an uninitialized value"),
+        "{msg}"
+    );
+    assert!(msg.contains("main.sg:"), "{msg}");
+
+    // Copying an uninitialized cell is fine, as long as the copy isn't used.
+    let (output, result) = run(
+        r#"
+let p: &mut Int = alloc(2 * sizeof<Int>());
+let q: &mut Int = alloc(2 * sizeof<Int>());
+p[0] = 1;
+q[0] = p[0];
+q[1] = p[1];
+println(q[0]);
+"#,
+        true,
+    );
+    assert_eq!(output, "1\n");
+    assert_eq!(result, Ok(()));
+
+    // Branching on a copy of an uninitialized cell.
+    let (_, result) = run(
+        r#"
+let p: &mut Int = alloc(2 * sizeof<Int>());
+let x = p[1] + 1;
+if x > 0 {
+    println("positive");
+}
+"#,
+        true,
+    );
+    assert!(memory_error(result).contains("a branch uses an uninitialized value"));
+
+    // Reading freed memory, inside a procedure, reports the calls which reached it.
+    let (output, result) = run(
+        r#"
+fun show(p: &Int) {
+    println(*p);
+}
+
+let p: &mut Int = alloc(sizeof<Int>());
+*p = 3;
+show(p);
+free(p);
+show(p);
+"#,
+        true,
+    );
+    assert_eq!(output, "3\n");
+    let msg = memory_error(result);
+    assert!(msg.contains("read of freed memory"), "{msg}");
+    assert!(msg.contains("called from"), "{msg}");
+
+    // Freeing memory twice.
+    let (_, result) = run(
+        r#"
+let p: &mut Int = alloc(sizeof<Int>());
+free(p);
+free(p);
+"#,
+        true,
+    );
+    assert!(memory_error(result).contains("double free"));
+}
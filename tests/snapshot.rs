@@ -0,0 +1,65 @@
+mod support;
+
+use sage::{
+    side_effects::{Output, OutputMode},
+    testing::*,
+    vm::{Device, RuntimeError, TestingDevice},
+    Compiler,
+};
+use std::fs::read_to_string;
+use support::compile;
+
+#[test]
+fn test_hello_snapshot() {
+    let src = read_to_string("examples/frontend/hello.sg").unwrap();
+    let snapshot = DeviceSnapshot::run(&compile(Compiler::new(src)), "");
+    assert_snapshot("tests/snapshots/hello.snap", &snapshot);
+}
+
+#[test]
+fn test_capture_channels() {
+    let mut device = TestingDevice::new("");
+    for ch in "hi\n".chars() {
+        device.put(ch as i64, Output::stdout_char()).unwrap();
+    }
+    device.put(42, Output::stderr_int()).unwrap();
+    device
+        .put(10, Output::new(OutputMode::Brightness, 2))
+        .unwrap();
+    device
+        .put(1, Output::new(OutputMode::DigitalPin, 0))
+        .unwrap();
+    device
+        .put(20, Output::new(OutputMode::Brightness, 2))
+        .unwrap();
+
+    let snapshot = DeviceSnapshot::capture(&device, &Err(RuntimeError::Exit(3)));
+    assert_eq!(snapshot.channel("stdout"), Some("hi\n"));
+    assert_eq!(snapshot.channel("stderr"), Some("42"));
+    assert_eq!(snapshot.channel("brightness #2"), Some("10 20"));
+    assert_eq!(snapshot.channel("digitalpin #0"), Some("1"));
+    assert_eq!(
+        snapshot.to_string(),
+        "-- stdout --\nhi\n-- stderr --\n42\n-- brightness #2 --\n10 20\n\
+         -- digitalpin #0 --\n1\n-- error --\nprogram exited with status 3\n"
+    );
+}
+
+#[test]
+fn test_bless_snapshot() {
+    let path = std::env::temp_dir()
+        .join(format!("sage-snapshot-{}", std::process::id()))
+        .join("blessed.snap");
+
+    // A snapshot which doesn't exist yet fails, until it's blessed.
+    assert!(check_snapshot(&path, "one\ntwo\n", false).is_err());
+    check_snapshot(&path, "one\ntwo\n", true).unwrap();
+    assert_eq!(read_to_string(&path).unwrap(), "one\ntwo\n");
+    check_snapshot(&path, "one\ntwo\n", false).unwrap();
+
+    // A change is reported line by line.
+    let e = check_snapshot(&path, "one\nthree\n", false).unwrap_err();
+    assert!(e.contains("  one\n- two\n+ three\n"), "{e}");
+
+    let _ = std::fs::remove_dir_all(path.parent().unwrap());
+}
@@ -0,0 +1,99 @@
+mod support;
+
+use sage::{lir::Severity, Compiler};
+use support::{compile, run_interpreter, with_large_stack};
+
+/// Compile a program, and run it in the interpreter, returning everything it printed.
+fn run(src: &str) -> String {
+    run_interpreter(&compile(Compiler::new(src).filename("main.sg")), "")
+}
+
+#[test]
+fn test_stack_alloc() {
+    with_large_stack(test_stack_alloc_helper);
+}
+
+fn test_stack_alloc_helper() {
+    // Variables declared before the allocation are addressed from the saved frame pointer,
+    // and those declared after it from the new one.
+    let output = run(r#"
+fun sum(n: Int): Int {
+    let before = 100;
+    let cells: &mut Int = alloca(n);
+    let after = 1000;
+    for let mut i=0; i<n; i+=1; {
+        cells[i] = i + 1;
+    }
+    let mut total = before + after;
+    for let mut i=0; i<n; i+=1; {
+        total += cells[i];
+    }
+    return total;
+}
+
+let x = 5;
+println(sum(4), " ", sum(10), " ", x);
+"#);
+    assert_eq!(output, "1110 1155 5\n");
+
+    // An allocation in a nested scope is freed at the end of that scope,
+    // so the variables after it are back at their usual offsets.
+    let output = run(r#"
+fun nested(n: Int): Int {
+    let outer = 5;
+    let mut result = 0;
+    if n > 0 {
+        let inner: &mut Int = alloca(n);
+        for let mut i=0; i<n; i+=1; {
+            inner[i] = outer * i;
+        }
+        result = inner[n - 1];
+    }
+    let after = 7;
+    return result + outer + after;
+}
+
+// Repeating the allocations checks that the stack is restored each time.
+let mut total = 0;
+for let mut i=0; i<100; i+=1; {
+    total += nested(3);
+}
+println(nested(3), " ", nested(0), " ", total);
+"#);
+    assert_eq!(output, "22 12 2200\n");
+
+    // Returning early from inside the allocation's scope restores the stack too.
+    let output = run(r#"
+fun early(n: Int): Int {
+    let first = 3;
+    let cells: &mut Int = alloca(n);
+    cells[0] = first * 2;
+    if n > 1 {
+        return cells[0] + first;
+    }
+    let second = 4;
+    return cells[0] + second;
+}
+
+let before = 11;
+let mut total = 0;
+for let mut i=0; i<100; i+=1; {
+    total += early(2) + early(1);
+}
+let after = 13;
+println(early(2), " ", early(1), " ", total, " ", before, " ", after);
+"#);
+    assert_eq!(output, "9 10 1900 11 13\n");
+
+    // A stack allocation must be the initial value of a variable.
+    let compilation = Compiler::new("let cells = (alloca(3) as &mut Int);")
+        .filename("main.sg")
+        .compile();
+    assert!(!compilation.succeeded());
+    assert!(compilation
+        .diagnostics()
+        .iter()
+        .any(
+            |diagnostic| diagnostic.severity == Severity::Error && diagnostic.code == Some("E0057")
+        ));
+}
@@ -0,0 +1,349 @@
+//! # Test Support
+//!
+//! The helpers the integration tests share: compiling programs, running them under
+//! the interpreter with some options, and running tests on a thread with a stack
+//! large enough to compile the standard library.
+//!
+//! ## Differential Testing
+//!
+//! This module also compiles a frontend program once, and runs the same compiled
+//! program under the interpreter and under every enabled target, feeding each
+//! of them the same input. Since every target implements the same virtual
+//! machine, they should all print exactly what the interpreter prints.
+//!
+//! The C backend is always enabled. Other targets can be enabled with the
+//! `SAGE_DIFF_TARGETS` environment variable, a comma separated list of target
+//! names (like `c,x86-64,js,python`). Targets whose toolchain isn't installed
+//! on the host are skipped with a warning, instead of failing the test.
+#![allow(dead_code)]
+
+use log::warn;
+use sage::{lir::Compile, parse::*, targets, vm::*, Compilation, Compiler};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+pub const CALL_STACK_SIZE: usize = 8192;
+
+/// The targets enabled when `SAGE_DIFF_TARGETS` isn't set.
+const DEFAULT_TARGETS: &str = "c";
+
+/// A program compiled once, ready to run under the interpreter or to build for any target.
+pub type Program = Result<CoreProgram, StandardProgram>;
+
+/// Parse, compile, and assemble a frontend program.
+pub fn compile_program(src: &str, filename: Option<&str>) -> Program {
+    let frontend_code = parse_frontend(src, filename)
+        .unwrap_or_else(|e| panic!("Could not parse {filename:?}: {e}"));
+    let asm_code = frontend_code
+        .compile(false)
+        .unwrap_or_else(|e| panic!("Could not compile {filename:?}: {e}"));
+    match asm_code {
+        Ok(core_asm_code) => core_asm_code.assemble(CALL_STACK_SIZE).map(Ok),
+        Err(std_asm_code) => std_asm_code.assemble(CALL_STACK_SIZE).map(Err),
+    }
+    .unwrap_or_else(|e| panic!("Could not assemble {filename:?}: {e}"))
+}
+
+/// Run a test on a new thread, and wait for it to finish.
+/// Compiling the standard library overflows the tiny stack for tests,
+/// so the thread is given a larger stack size.
+pub fn with_large_stack(test: fn()) {
+    let child = std::thread::Builder::new()
+        .stack_size(512 * 1024 * 1024)
+        .spawn(test)
+        .unwrap();
+    child.join().unwrap();
+}
+
+/// Assert that a compilation succeeded, panicking with its diagnostics if it didn't.
+pub fn assert_succeeded(compilation: &Compilation) {
+    assert!(
+        compilation.succeeded(),
+        "{}",
+        compilation.render_diagnostics()
+    );
+}
+
+/// Compile a program with a configured compiler, panicking with the diagnostics if it fails.
+pub fn compile(compiler: Compiler) -> Program {
+    let compilation = compiler.compile();
+    assert_succeeded(&compilation);
+    compilation.vm().unwrap().clone()
+}
+
+/// How a program is run under the interpreter.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunOptions<'a> {
+    /// The input the program reads.
+    pub input: &'a str,
+    /// The limits the program is stopped at.
+    pub limits: Limits,
+    /// Check every memory access with the sanitizer. Only the standard interpreter has one.
+    pub sanitize: bool,
+    /// Report the allocations the program never freed. Only the standard interpreter checks.
+    pub leak_check: bool,
+}
+
+/// Run the program under the interpreter with some options, returning the profile
+/// of the run: the device with what the program printed, why the program stopped,
+/// and the allocations it leaked.
+pub fn run_with(program: &Program, options: RunOptions) -> Profile<TestingDevice> {
+    match program {
+        Ok(code) => CoreInterpreter::new(TestingDevice::new(options.input))
+            .with_limits(options.limits)
+            .profile(code),
+        Err(code) => StandardInterpreter::new(TestingDevice::new(options.input))
+            .with_limits(options.limits)
+            .with_sanitizer(options.sanitize)
+            .with_leak_check(options.leak_check)
+            .profile(code),
+    }
+}
+
+/// Run the program under the interpreter, returning the device it ran on.
+pub fn run_device(program: &Program, input: &str) -> TestingDevice {
+    let profile = run_with(
+        program,
+        RunOptions {
+            input,
+            ..RunOptions::default()
+        },
+    );
+    if let Err(e) = profile.result {
+        panic!("Could not interpret program: {e}");
+    }
+    profile.device
+}
+
+/// Run the program under the interpreter, returning everything it printed.
+pub fn run_interpreter(program: &Program, input: &str) -> String {
+    run_device(program, input).output_str()
+}
+
+/// The names of the targets to compare against the interpreter.
+pub fn enabled_targets() -> Vec<String> {
+    std::env::var("SAGE_DIFF_TARGETS")
+        .unwrap_or_else(|_| DEFAULT_TARGETS.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Build the program for a target and run it with the given input,
+/// returning everything it printed. If the host can't run the target,
+/// this returns `None`.
+pub fn run_target(program: &Program, name: &str, input: &str) -> Option<String> {
+    let mut target = targets::get_target(name)
+        .unwrap_or_else(|| panic!("No target is registered with the name `{name}`"));
+    let flattened = match program {
+        Ok(code) => Ok(code.flatten()),
+        Err(code) => Err(code.flatten()),
+    };
+    let code = match target.build(&flattened) {
+        Ok(code) => code,
+        // Core-only targets can't build standard programs, so there's nothing to compare.
+        Err(e) if flattened.is_err() && !target.supports_std() => {
+            warn!("Skipping target `{name}`: {e}");
+            return None;
+        }
+        Err(e) => panic!("Could not build program for target `{name}`: {e}"),
+    };
+
+    let dir = scratch_dir(name);
+    let files = target
+        .emit(dir.join("program").to_str().unwrap(), &code)
+        .unwrap_or_else(|e| panic!("Could not write program for target `{name}`: {e}"));
+    let source = PathBuf::from(&files[0]);
+
+    let output = match name {
+        "c" => {
+            compile_native(&source, &dir, &["-lm"]).and_then(|exe| run(Command::new(exe), input))
+        }
+        "freestanding-c" => {
+            // The I/O hooks and the startup code come from the host's libc.
+            let hooks = dir.join("hooks.c");
+            std::fs::write(
+                &hooks,
+                "#include <stdio.h>\n\
+                 #include <stdlib.h>\n\
+                 #include <stdint.h>\n\
+                 void sage_main(void);\n\
+                 void sage_putchar(int64_t ch) { putchar((int)ch); }\n\
+                 void sage_eputchar(int64_t ch) { fputc((int)ch, stderr); }\n\
+                 int64_t sage_getchar(void) { int ch = getchar(); return ch == EOF? -1 : ch; }\n\
+                 void sage_exit(int64_t status) { exit((int)status); }\n\
+                 int main(void) { sage_main(); return 0; }\n",
+            )
+            .unwrap();
+            compile_native(&source, &dir, &[hooks.to_str().unwrap(), "-lm"])
+                .and_then(|exe| run(Command::new(exe), input))
+        }
+        "x86-64" if cfg!(all(target_arch = "x86_64", target_os = "linux")) => {
+            compile_native(&source, &dir, &["-lm"]).and_then(|exe| run(Command::new(exe), input))
+        }
+        "6502" => {
+            // The program runs in cc65's simulator, with hooks written against its C library.
+            let hooks = dir.join("hooks.c");
+            std::fs::write(
+                &hooks,
+                "#include <stdio.h>\n\
+                 #include <stdlib.h>\n\
+                 int sage_main(void);\n\
+                 void __fastcall__ sage_putchar(unsigned char ch) { putchar(ch); }\n\
+                 void __fastcall__ sage_eputchar(unsigned char ch) { fputc(ch, stderr); }\n\
+                 unsigned char sage_getchar(void) { int ch = getchar(); return ch == EOF? 0 : ch; }\n\
+                 void __fastcall__ sage_exit(unsigned char status) { exit(status); }\n\
+                 int main(void) { return sage_main(); }\n",
+            )
+            .unwrap();
+            compile_cc65(&source, &hooks, &dir).and_then(|exe| {
+                let mut command = Command::new("sim65");
+                command.arg(exe);
+                run(command, input)
+            })
+        }
+        "js" => {
+            let runner = dir.join("runner.mjs");
+            std::fs::write(
+                &runner,
+                "import { readFileSync } from \"fs\";\n\
+                 import { run, BufferDevice } from \"./program.mjs\";\n\
+                 const device = new BufferDevice(readFileSync(0, \"utf8\"));\n\
+                 const status = run(device);\n\
+                 process.stdout.write(Buffer.from(device.output, \"latin1\"));\n\
+                 process.exitCode = status;\n",
+            )
+            .unwrap();
+            let mut command = Command::new("node");
+            command.arg(runner);
+            run(command, input)
+        }
+        "python" => {
+            let runner = dir.join("runner.py");
+            std::fs::write(
+                &runner,
+                "import sys\n\
+                 from program import run, BufferDevice\n\
+                 device = BufferDevice(sys.stdin.read())\n\
+                 status = run(device)\n\
+                 sys.stdout.buffer.write(device.output.encode(\"latin-1\"))\n\
+                 sys.exit(status)\n",
+            )
+            .unwrap();
+            let mut command = Command::new("python3");
+            command.arg(runner);
+            run(command, input)
+        }
+        _ => {
+            warn!("Don't know how to run target `{name}` on this host, skipping it");
+            None
+        }
+    };
+
+    let _ = std::fs::remove_dir_all(&dir);
+    output
+}
+
+/// Run the program under the interpreter and every enabled target,
+/// and assert that they all print the same output.
+pub fn assert_same_outputs(program: &Program, input: &str, description: &str) {
+    let expected = run_interpreter(program, input);
+    for name in enabled_targets() {
+        if let Some(output) = run_target(program, &name, input) {
+            assert_eq!(
+                output, expected,
+                "Target `{name}` printed different output than the interpreter for {description}"
+            );
+        }
+    }
+}
+
+/// A fresh directory for building a target's output in.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "sage-diff-{}-{}-{name}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Compile C or assembly source with the host's C compiler (`$CC`, or `cc`).
+fn compile_native(source: &Path, dir: &Path, flags: &[&str]) -> Option<PathBuf> {
+    let exe = dir.join("program.exe");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = match Command::new(&cc)
+        .arg(source)
+        .arg("-o")
+        .arg(&exe)
+        .args(flags)
+        .output()
+    {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Could not run C compiler `{cc}`, skipping: {e}");
+            return None;
+        }
+    };
+    if !result.status.success() {
+        panic!(
+            "Could not compile {source:?}:\n{}",
+            String::from_utf8_lossy(&result.stderr)
+        );
+    }
+    Some(exe)
+}
+
+/// Compile 6502 assembly and C hooks for cc65's simulator, with `cl65`.
+fn compile_cc65(source: &Path, hooks: &Path, dir: &Path) -> Option<PathBuf> {
+    let exe = dir.join("program.sim");
+    let result = match Command::new("cl65")
+        .args(["-t", "sim6502", "-D", "SAGE_TAPE_CELLS=2048"])
+        .arg(source)
+        .arg(hooks)
+        .arg("-o")
+        .arg(&exe)
+        .output()
+    {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Could not run `cl65`, skipping: {e}");
+            return None;
+        }
+    };
+    if !result.status.success() {
+        panic!(
+            "Could not compile {source:?}:\n{}",
+            String::from_utf8_lossy(&result.stderr)
+        );
+    }
+    Some(exe)
+}
+
+/// Run a command with the input on stdin, returning its stdout.
+/// Each byte is decoded as its own character, just like the testing device does.
+fn run(mut command: Command, input: &str) -> Option<String> {
+    let mut child = match command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Could not run {command:?}, skipping: {e}");
+            return None;
+        }
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap_or_else(|e| panic!("Could not write to stdin of {command:?}: {e}"));
+    let output = child.wait_with_output().unwrap();
+    Some(output.stdout.iter().map(|byte| *byte as char).collect())
+}
@@ -0,0 +1,36 @@
+mod support;
+
+use sage::{compiler::Variant, Compiler};
+use support::{compile, run_device, with_large_stack};
+
+#[test]
+fn test_terminal() {
+    with_large_stack(test_terminal_helper);
+}
+
+fn test_terminal_helper() {
+    // A game loop which handles every key that's waiting, and then stops.
+    let src = r#"
+from std.term import NO_KEY, KEY_UP, KEY_ENTER, is_printable;
+
+println(terminal_width(), "x", terminal_height());
+let mut count = 0;
+let mut key = poll_key();
+while key != NO_KEY {
+    if is_printable(key) {
+        print(key as Char);
+    } else if key == KEY_UP {
+        print("<up>");
+    } else if key == KEY_ENTER {
+        print("<enter>");
+    }
+    count += 1;
+    key = poll_key();
+}
+println();
+println(count);
+"#;
+    let program = compile(Compiler::new(src).variant(Variant::CoreOnly));
+    let device = run_device(&program, "hi\u{100}!\n");
+    assert_eq!(device.output_str(), "80x24\nhi<up>!<enter>\n5\n");
+}
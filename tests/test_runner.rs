@@ -0,0 +1,74 @@
+mod support;
+
+use sage::{frontend::TestRunner, lir::Env};
+use support::with_large_stack;
+
+const SOURCE: &str = r#"fun add(a: Int, b: Int): Int {
+    return a + b;
+}
+
+mod math {
+    fun double(n: Int): Int {
+        return n * 2;
+    }
+
+    #[test]
+    fun test_double() {
+        assert(double(4) == 8, "4 doubled should be 8");
+    }
+}
+
+#[test]
+fun test_add() {
+    println("adding");
+    assert(add(1, 2) == 3, "1 + 2 should be 3");
+}
+
+#[test]
+fun test_add_fails() {
+    println("adding badly");
+    assert(add(1, 2) == 4, "1 + 2 should be 4");
+}
+
+fun main() {
+    println("main shouldn't run");
+}
+
+println("neither should this");
+"#;
+
+#[test]
+fn test_test_runner() {
+    with_large_stack(test_test_runner_helper);
+}
+
+fn test_test_runner_helper() {
+    let runner = TestRunner::new(Env::default(), 8192);
+
+    // Every test runs in its own program, and only the test's output is captured.
+    let results = runner.run(SOURCE, None, None).unwrap();
+    let names = results
+        .iter()
+        .map(|result| result.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["math.test_double", "test_add", "test_add_fails"]);
+    assert!(results[0].passed());
+    assert_eq!(results[0].output, "");
+    assert!(results[1].passed());
+    assert_eq!(results[1].output, "adding\n");
+    assert!(!results[2].passed());
+    assert_eq!(results[2].output, "adding badly\n");
+    assert!(results[2]
+        .failure
+        .as_ref()
+        .unwrap()
+        .contains("1 + 2 should be 4"));
+
+    // Only the tests whose names contain the filter are run.
+    let results = runner.run(SOURCE, None, Some("add")).unwrap();
+    let names = results
+        .iter()
+        .map(|result| result.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["test_add", "test_add_fails"]);
+}
@@ -0,0 +1,43 @@
+use sage::{frontend, lir::*};
+
+const SOURCE: &str = "fun id<T>(x: T): T {
+    return x;
+}
+
+println(id(1), id(2.5));
+";
+
+#[test]
+fn test_pass_timings() {
+    let program = frontend::parse(SOURCE, None, false, false).unwrap();
+    let mut env = Env::default();
+    env.enable_pass_timings();
+    env.time_pass("parse", || {});
+    program.compile_with_env(env.clone(), false).unwrap();
+
+    // The passes run by the compiler are recorded on the environment it was given.
+    let passes = env.get_pass_timings().unwrap().passes();
+    let names = passes
+        .iter()
+        .map(|pass| pass.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names[0], "parse");
+    assert!(names.contains(&"typecheck"));
+    assert!(names.contains(&"lir-to-asm"));
+    // `id` is monomorphized for `Int` and `Float`.
+    let monomorphize = passes
+        .iter()
+        .find(|pass| pass.name == "monomorphize")
+        .unwrap();
+    assert!(monomorphize.runs >= 2);
+    // Monomorphization only runs inside other passes, so its memory isn't measured.
+    assert_eq!(monomorphize.peak_memory, None);
+
+    let timings = env.get_pass_timings().unwrap();
+    assert!(timings.report().starts_with("pass"));
+    assert!(timings.report().contains("monomorphize"));
+    assert!(timings.to_json().contains("\"name\":\"typecheck\""));
+
+    // Without timing enabled, nothing is recorded.
+    assert!(Env::default().get_pass_timings().is_none());
+}
@@ -0,0 +1,58 @@
+use sage::lir::*;
+
+#[test]
+fn test_interned_type_equality() {
+    let structure = |members: &[(&str, Type)]| {
+        Type::Struct(
+            members
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+        )
+    };
+    let pointer = Type::Pointer(Mutability::Immutable, Box::new(Type::Int));
+    let definitions = vec![
+        ("A", structure(&[("x", Type::Int), ("y", Type::Int)])),
+        ("B", structure(&[("x", Type::Int), ("y", Type::Int)])),
+        ("C", structure(&[("x", Type::Int), ("y", Type::Float)])),
+        ("D", Type::Symbol("A".to_string())),
+        ("E", pointer.clone()),
+        ("F", pointer),
+    ];
+    let mut env = Env::default();
+    env.define_types(
+        definitions
+            .iter()
+            .map(|(name, ty)| (name.to_string(), ty.clone()))
+            .collect(),
+    );
+
+    // Structurally identical definitions share the same handle.
+    assert!(env
+        .intern_type(definitions[0].1.clone())
+        .ptr_eq(&env.intern_type(definitions[1].1.clone())));
+    assert!(!env
+        .intern_type(definitions[0].1.clone())
+        .ptr_eq(&env.intern_type(definitions[2].1.clone())));
+
+    // Comparing the names, which uses the interned handles when they're shared,
+    // agrees with comparing the definitions they're bound to.
+    for (a, a_ty) in &definitions {
+        for (b, b_ty) in &definitions {
+            let by_name = Type::Symbol(a.to_string())
+                .equals(&Type::Symbol(b.to_string()), &env)
+                .unwrap();
+            let by_definition = a_ty.equals(b_ty, &env).unwrap();
+            assert_eq!(by_name, by_definition, "comparing {a} and {b}");
+        }
+    }
+    assert!(Type::Symbol("A".to_string())
+        .equals(&Type::Symbol("B".to_string()), &env)
+        .unwrap());
+    assert!(Type::Symbol("D".to_string())
+        .equals(&Type::Symbol("B".to_string()), &env)
+        .unwrap());
+    assert!(!Type::Symbol("A".to_string())
+        .equals(&Type::Symbol("C".to_string()), &env)
+        .unwrap());
+}